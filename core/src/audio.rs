@@ -12,15 +12,36 @@
 // You should have received a copy of the GNU General Public License along with Millenium Player.
 // If not, see <https://www.gnu.org/licenses/>.
 
+/// Cache of per-track analysis that is kept between runs.
+pub mod analysis_cache;
+
+/// Routing of stereo audio to the channels of an output device.
+pub mod channel_map;
+
 /// Audio hardware device abstraction.
 pub mod device;
 
+/// Dynamic range (loudness war) measurement.
+pub mod dynamic_range;
+
+/// Checks of whether consecutive tracks will play without gaps between them.
+pub mod gapless;
+
+/// Buffered HTTP media source for internet files and radio streams.
+pub mod http;
+
+/// Peak scanning for normalizing a whole playlist at once.
+pub mod peak;
+
 /// A sink for audio data that sends that data to the audio device.
 pub mod sink;
 
 /// Source buffer and audio decoder stream.
 pub mod source;
 
+/// Generated signals for checking speakers and channel routing.
+pub mod test_signal;
+
 /// Type alias for sample rates to help with consistency.
 pub type SampleRate = u32;
 
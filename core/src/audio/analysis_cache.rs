@@ -0,0 +1,233 @@
+// This file is part of Millenium Player.
+// Copyright (C) 2023 John DiSanti.
+//
+// Millenium Player is free software: you can redistribute it and/or modify it under the terms of
+// the GNU General Public License as published by the Free Software Foundation, either version 3 of
+// the License, or (at your option) any later version.
+//
+// Millenium Player is distributed in the hope that it will be useful, but WITHOUT ANY WARRANTY;
+// without even the implied warranty of MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE. See
+// the GNU General Public License for more details.
+//
+// You should have received a copy of the GNU General Public License along with Millenium Player.
+// If not, see <https://www.gnu.org/licenses/>.
+
+use crate::{audio::dynamic_range::DynamicRange, location::Location};
+use camino::Utf8Path;
+use std::{
+    collections::HashMap,
+    fs, io,
+    path::PathBuf,
+    sync::{Arc, Mutex},
+    time::UNIX_EPOCH,
+};
+
+#[derive(Debug, thiserror::Error)]
+pub enum AnalysisCacheError {
+    #[error("failed to save analysis cache {path:?}: {source}")]
+    Write { path: PathBuf, source: io::Error },
+}
+
+/// Results of the analyses that take decoding a whole track to work out.
+#[derive(Copy, Clone, Debug, Default, PartialEq, serde::Serialize, serde::Deserialize)]
+pub struct TrackAnalysis {
+    /// Largest absolute sample value, where 1.0 is full scale.
+    pub peak: Option<f32>,
+    pub dynamic_range: Option<DynamicRange>,
+}
+
+/// Size and modification time of a file, so that its analysis is redone when it changes.
+#[derive(Copy, Clone, Debug, Eq, PartialEq, serde::Serialize, serde::Deserialize)]
+struct Fingerprint {
+    size: u64,
+    modified_secs: u64,
+    modified_nanos: u32,
+}
+
+impl Fingerprint {
+    fn of(path: &Utf8Path) -> Option<Self> {
+        let metadata = fs::metadata(path).ok()?;
+        let modified = metadata.modified().ok()?.duration_since(UNIX_EPOCH).ok()?;
+        Some(Self {
+            size: metadata.len(),
+            modified_secs: modified.as_secs(),
+            modified_nanos: modified.subsec_nanos(),
+        })
+    }
+}
+
+#[derive(Debug, serde::Serialize, serde::Deserialize)]
+struct CachedAnalysis {
+    fingerprint: Fingerprint,
+    #[serde(flatten)]
+    analysis: TrackAnalysis,
+}
+
+#[derive(Debug, Default)]
+struct Inner {
+    path: Option<PathBuf>,
+    /// Keyed by file path.
+    entries: HashMap<String, CachedAnalysis>,
+    dirty: bool,
+}
+
+/// Analysis of local files that is kept between runs so that re-opening the same files
+/// doesn't decode them all over again. Streams aren't cached.
+///
+/// Clones share the same cache.
+#[derive(Clone, Debug, Default)]
+pub struct AnalysisCache {
+    inner: Arc<Mutex<Inner>>,
+}
+
+impl AnalysisCache {
+    /// Cache that is only kept in memory.
+    pub fn in_memory() -> Self {
+        Self::default()
+    }
+
+    /// Loads the cache file at the given path. The cache starts out empty if the file
+    /// doesn't exist or can't be read.
+    pub fn load(path: impl Into<PathBuf>) -> Self {
+        let path = path.into();
+        let entries = match fs::read_to_string(&path) {
+            Ok(contents) => serde_json::from_str(&contents).unwrap_or_else(|err| {
+                log::warn!("discarding unreadable analysis cache {path:?}: {err}");
+                HashMap::new()
+            }),
+            Err(err) => {
+                if err.kind() != io::ErrorKind::NotFound {
+                    log::warn!("failed to read analysis cache {path:?}: {err}");
+                }
+                HashMap::new()
+            }
+        };
+        Self {
+            inner: Arc::new(Mutex::new(Inner {
+                path: Some(path),
+                entries,
+                dirty: false,
+            })),
+        }
+    }
+
+    /// Cached analysis of a file, as long as the file hasn't changed since it was analyzed.
+    pub fn get(&self, location: &Location) -> Option<TrackAnalysis> {
+        let path = location.as_path()?;
+        let fingerprint = Fingerprint::of(path)?;
+        let inner = self.inner.lock().unwrap();
+        inner
+            .entries
+            .get(path.as_str())
+            .filter(|cached| cached.fingerprint == fingerprint)
+            .map(|cached| cached.analysis)
+    }
+
+    /// Records more analysis of a file. Anything cached from an older version of
+    /// the file is dropped.
+    pub fn update(&self, location: &Location, update: impl FnOnce(&mut TrackAnalysis)) {
+        let Some((path, fingerprint)) = location
+            .as_path()
+            .and_then(|path| Some((path, Fingerprint::of(path)?)))
+        else {
+            return;
+        };
+        let mut inner = self.inner.lock().unwrap();
+        let cached = inner
+            .entries
+            .entry(path.to_string())
+            .or_insert_with(|| CachedAnalysis {
+                fingerprint,
+                analysis: TrackAnalysis::default(),
+            });
+        if cached.fingerprint != fingerprint {
+            cached.fingerprint = fingerprint;
+            cached.analysis = TrackAnalysis::default();
+        }
+        update(&mut cached.analysis);
+        inner.dirty = true;
+    }
+
+    /// Writes the cache file if anything changed since it was last written.
+    pub fn save(&self) -> Result<(), AnalysisCacheError> {
+        let mut inner = self.inner.lock().unwrap();
+        let Some(path) = inner.path.clone().filter(|_| inner.dirty) else {
+            return Ok(());
+        };
+        let contents = serde_json::to_string(&inner.entries).expect("serializable");
+        let write = || {
+            if let Some(dir) = path.parent() {
+                fs::create_dir_all(dir)?;
+            }
+            fs::write(&path, contents)
+        };
+        write().map_err(|source| AnalysisCacheError::Write {
+            path: path.clone(),
+            source,
+        })?;
+        inner.dirty = false;
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const TEST_FILE: &str = "../test-data/melodic_a_minor/melodic_a_minor_2chan_44100hz_11s.ogg";
+
+    #[test]
+    fn round_trip() {
+        let dir = std::env::temp_dir().join(format!("analysis-cache-{}", std::process::id()));
+        let path = dir.join("analysis.json");
+        let location = Location::path(TEST_FILE);
+
+        let cache = AnalysisCache::load(&path);
+        assert_eq!(None, cache.get(&location));
+        cache.update(&location, |analysis| analysis.peak = Some(0.5));
+        cache.update(&location, |analysis| {
+            analysis.dynamic_range = Some(DynamicRange(11))
+        });
+        cache.save().unwrap();
+
+        let expected = TrackAnalysis {
+            peak: Some(0.5),
+            dynamic_range: Some(DynamicRange(11)),
+        };
+        assert_eq!(Some(expected), AnalysisCache::load(&path).get(&location));
+        fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn changed_file_is_analyzed_again() {
+        let location = Location::path(TEST_FILE);
+        let cache = AnalysisCache::in_memory();
+        cache.update(&location, |analysis| analysis.peak = Some(0.5));
+        cache
+            .inner
+            .lock()
+            .unwrap()
+            .entries
+            .get_mut(TEST_FILE)
+            .unwrap()
+            .fingerprint
+            .size += 1;
+        assert_eq!(None, cache.get(&location));
+
+        cache.update(&location, |analysis| {
+            analysis.dynamic_range = Some(DynamicRange(9))
+        });
+        let analysis = cache.get(&location).unwrap();
+        assert_eq!(None, analysis.peak, "it should drop the stale peak");
+        assert_eq!(Some(DynamicRange(9)), analysis.dynamic_range);
+    }
+
+    #[test]
+    fn streams_are_not_cached() {
+        let location = Location::url(url::Url::parse("https://example.com/stream").unwrap());
+        let cache = AnalysisCache::in_memory();
+        cache.update(&location, |analysis| analysis.peak = Some(0.5));
+        assert_eq!(None, cache.get(&location));
+        assert!(cache.inner.lock().unwrap().entries.is_empty());
+    }
+}
@@ -0,0 +1,146 @@
+// This file is part of Millenium Player.
+// Copyright (C) 2023 John DiSanti.
+//
+// Millenium Player is free software: you can redistribute it and/or modify it under the terms of
+// the GNU General Public License as published by the Free Software Foundation, either version 3 of
+// the License, or (at your option) any later version.
+//
+// Millenium Player is distributed in the hope that it will be useful, but WITHOUT ANY WARRANTY;
+// without even the implied warranty of MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE. See
+// the GNU General Public License for more details.
+//
+// You should have received a copy of the GNU General Public License along with Millenium Player.
+// If not, see <https://www.gnu.org/licenses/>.
+
+use super::ChannelCount;
+use std::{collections::BTreeMap, fmt, str::FromStr};
+
+/// Channel maps keyed by the name of the output device they apply to.
+pub type ChannelMaps = BTreeMap<String, ChannelMap>;
+
+#[derive(Debug, thiserror::Error)]
+pub enum ChannelMapError {
+    #[error("a channel map needs at least one output channel")]
+    Empty,
+    #[error("unknown channel \"{0}\" in channel map. Expected L, R, or - for silence")]
+    UnknownChannel(String),
+    #[error("a channel map can have at most {} output channels", ChannelCount::MAX)]
+    TooManyChannels,
+}
+
+/// Which channel of stereo audio plays on each channel of an output device.
+///
+/// This is written with one entry per device channel, where `L` and `R` are the left and right
+/// channels, and `-` is silence. For example, `"R L"` swaps the left and right channels, and
+/// `"- - L R"` plays stereo on the third and fourth channels of a multichannel interface.
+#[derive(Clone, Debug, Eq, PartialEq, serde::Serialize, serde::Deserialize)]
+#[serde(try_from = "String", into = "String")]
+pub struct ChannelMap {
+    /// Index of the stereo channel for each device channel, or `None` for silence.
+    sources: Vec<Option<usize>>,
+}
+
+impl ChannelMap {
+    /// Number of channels the output device needs to have for this map.
+    pub fn device_channels(&self) -> ChannelCount {
+        self.sources.len() as ChannelCount
+    }
+
+    /// Index of the stereo channel for each device channel, or `None` for silence.
+    pub fn sources(&self) -> &[Option<usize>] {
+        &self.sources
+    }
+}
+
+impl FromStr for ChannelMap {
+    type Err = ChannelMapError;
+
+    fn from_str(value: &str) -> Result<Self, Self::Err> {
+        let sources = value
+            .split_whitespace()
+            .map(|channel| match channel {
+                "L" | "l" => Ok(Some(0)),
+                "R" | "r" => Ok(Some(1)),
+                "-" => Ok(None),
+                _ => Err(ChannelMapError::UnknownChannel(channel.into())),
+            })
+            .collect::<Result<Vec<_>, _>>()?;
+        if sources.is_empty() {
+            return Err(ChannelMapError::Empty);
+        }
+        if sources.len() > ChannelCount::MAX as usize {
+            return Err(ChannelMapError::TooManyChannels);
+        }
+        Ok(Self { sources })
+    }
+}
+
+impl TryFrom<String> for ChannelMap {
+    type Error = ChannelMapError;
+
+    fn try_from(value: String) -> Result<Self, Self::Error> {
+        value.parse()
+    }
+}
+
+impl fmt::Display for ChannelMap {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        for (index, source) in self.sources.iter().enumerate() {
+            if index > 0 {
+                f.write_str(" ")?;
+            }
+            f.write_str(match source {
+                Some(0) => "L",
+                Some(_) => "R",
+                None => "-",
+            })?;
+        }
+        Ok(())
+    }
+}
+
+impl From<ChannelMap> for String {
+    fn from(value: ChannelMap) -> Self {
+        value.to_string()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::audio::source::SourceBuffer;
+
+    #[test]
+    fn parse_and_display() {
+        let map: ChannelMap = "- - L R".parse().unwrap();
+        assert_eq!(&[None, None, Some(0), Some(1)], map.sources());
+        assert_eq!(4, map.device_channels());
+        assert_eq!("- - L R", map.to_string());
+        assert_eq!("R L", "r  l".parse::<ChannelMap>().unwrap().to_string());
+
+        assert!(matches!(
+            "".parse::<ChannelMap>(),
+            Err(ChannelMapError::Empty)
+        ));
+        assert!(matches!(
+            "L C R".parse::<ChannelMap>(),
+            Err(ChannelMapError::UnknownChannel(channel)) if channel == "C"
+        ));
+    }
+
+    #[test]
+    fn route_channels() {
+        let mut buffer = SourceBuffer::from_channels(44100, vec![vec![0.1, 0.2], vec![0.3, 0.4]]);
+        buffer.route_in_place(&"R L".parse().unwrap());
+        assert_eq!(2, buffer.channel_count());
+        assert_eq!(&[0.3, 0.4], buffer.channel(0));
+        assert_eq!(&[0.1, 0.2], buffer.channel(1));
+
+        buffer.route_in_place(&"- R L -".parse().unwrap());
+        assert_eq!(4, buffer.channel_count());
+        assert_eq!(&[0.0, 0.0], buffer.channel(0));
+        assert_eq!(&[0.1, 0.2], buffer.channel(1));
+        assert_eq!(&[0.3, 0.4], buffer.channel(2));
+        assert_eq!(&[0.0, 0.0], buffer.channel(3));
+    }
+}
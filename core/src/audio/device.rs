@@ -15,10 +15,12 @@
 use self::sealed::BroadcastingAudioDevice;
 
 use super::{
+    channel_map::{ChannelMap, ChannelMaps},
     sink::{AudioBuffer, BoxAudioBuffer, Sink},
     ChannelCount,
 };
 use crate::audio::SampleRate;
+use crate::clock::{SharedClock, SystemClock};
 use cpal::{
     traits::{DeviceTrait, HostTrait, StreamTrait},
     BuildStreamError, Device, DeviceNameError, Host, OutputCallbackInfo, PauseStreamError,
@@ -41,6 +43,42 @@ use std::{
 
 const PREFERRED_SAMPLE_RATES: &[u32] = &[48000, 44100, 88200, 96000];
 const DESIRED_BUFFER_LENGTH: Duration = Duration::from_millis(500);
+/// Extra time to wait for a fade out to be written before pausing anyway, since the
+/// write callback may only run every so often.
+const FADE_OUT_GRACE: Duration = Duration::from_millis(100);
+/// A playing stream calls back every few milliseconds, so going this long without a callback
+/// means that the stream stopped, such as after its device went away.
+const STALLED_STREAM_TIMEOUT: Duration = Duration::from_secs(2);
+
+/// How the audio device behaves around the end of playback.
+#[derive(Clone, Debug, PartialEq)]
+pub struct DeviceOptions {
+    /// How long the device keeps playing silence after playback finishes before it's paused.
+    pub idle_timeout: Duration,
+    /// Pad the final partial chunk of audio with silence. It's always padded when resampling
+    /// since the resampler only works on whole chunks.
+    pub pad_final_chunk: bool,
+    /// Close the output stream once the device has been paused this long, so that other apps
+    /// can take exclusive control of it. It's reopened when playback resumes.
+    pub release_after: Option<Duration>,
+    /// Length of the fade out when pausing or stopping, and the fade in when resuming.
+    /// Zero cuts the audio off abruptly.
+    pub fade: Duration,
+    /// Length of the ramp when the volume is ducked under other audio, and when it's restored.
+    pub duck_ramp: Duration,
+}
+
+impl Default for DeviceOptions {
+    fn default() -> Self {
+        Self {
+            idle_timeout: Duration::from_secs(5),
+            pad_final_chunk: true,
+            release_after: None,
+            fade: Duration::from_millis(20),
+            duck_ramp: Duration::from_millis(300),
+        }
+    }
+}
 
 #[derive(Debug, thiserror::Error)]
 pub enum AudioDeviceError {
@@ -156,12 +194,20 @@ pub trait AudioDevice: BroadcastingAudioDevice {
     /// Pauses playback on the device.
     fn pause(&self) -> Result<(), AudioDeviceError>;
 
+    /// Closes the output stream if the device has been paused longer than it's configured to
+    /// stay open. It's reopened the next time playback starts.
+    fn release_if_idle(&self);
+
     /// Set the output volume on this device.
     fn set_volume(&self, volume: Volume);
 
     /// Returns the current output volume.
     fn volume(&self) -> Volume;
 
+    /// Ramps the output down to a fraction of the volume, such as while someone is talking
+    /// over a call, or back up to the full volume if `None`.
+    fn duck(&self, level: Option<Volume>);
+
     /// Subscribe to this device's events.
     fn subscribe(
         &self,
@@ -198,10 +244,14 @@ impl fmt::Debug for CreateDeviceError {
 }
 
 /// Create an audio device for this platform.
+///
+/// If there's a channel map for the device, it's opened with the channels the map needs.
 pub fn create_device(
     preferred_output_device_name: Option<&str>,
+    channel_maps: &ChannelMaps,
+    options: &DeviceOptions,
 ) -> Result<Box<dyn AudioDevice>, CreateDeviceError> {
-    match CpalAudioDevice::new(preferred_output_device_name) {
+    match CpalAudioDevice::new(preferred_output_device_name, channel_maps, options) {
         Ok(device) => Ok(Box::new(device)),
         Err(err) => {
             log::error!("failed to create cpal audio device: {}", err);
@@ -213,7 +263,7 @@ pub fn create_device(
     }
 }
 
-struct NullAudioDevice {
+pub(crate) struct NullAudioDevice {
     config: SupportedStreamConfig,
     output_buffer: Arc<Mutex<BoxAudioBuffer>>,
     frames_consumed: AtomicU64,
@@ -221,7 +271,7 @@ struct NullAudioDevice {
 }
 
 impl NullAudioDevice {
-    pub(super) fn new() -> Self {
+    pub(crate) fn new() -> Self {
         Self {
             config: SupportedStreamConfig::new(
                 2,
@@ -285,12 +335,16 @@ impl AudioDevice for NullAudioDevice {
         Ok(())
     }
 
+    fn release_if_idle(&self) {}
+
     fn set_volume(&self, _volume: Volume) {}
 
     fn volume(&self) -> Volume {
         Volume::default()
     }
 
+    fn duck(&self, _level: Option<Volume>) {}
+
     fn subscribe(
         &self,
         name: &'static str,
@@ -300,6 +354,96 @@ impl AudioDevice for NullAudioDevice {
     }
 }
 
+/// Device that fails to start playing while it's unplugged, like a device that went away.
+#[cfg(test)]
+pub(crate) struct UnpluggableAudioDevice {
+    inner: NullAudioDevice,
+    unplugged: Arc<AtomicBool>,
+}
+
+#[cfg(test)]
+impl UnpluggableAudioDevice {
+    /// Creates the device along with the flag that unplugs it.
+    pub(crate) fn new() -> (Self, Arc<AtomicBool>) {
+        let unplugged = Arc::new(AtomicBool::new(false));
+        let device = Self {
+            inner: NullAudioDevice::new(),
+            unplugged: unplugged.clone(),
+        };
+        (device, unplugged)
+    }
+}
+
+#[cfg(test)]
+impl BroadcastingAudioDevice for UnpluggableAudioDevice {
+    fn broadcaster(&self) -> Broadcaster<AudioDeviceMessage> {
+        self.inner.broadcaster()
+    }
+}
+
+#[cfg(test)]
+impl AudioDevice for UnpluggableAudioDevice {
+    fn create_sink(&self, input_sample_rate: SampleRate, input_channels: ChannelCount) -> Sink {
+        self.inner.create_sink(input_sample_rate, input_channels)
+    }
+
+    fn playback_sample_rate(&self) -> SampleRate {
+        self.inner.playback_sample_rate()
+    }
+
+    fn playback_channels(&self) -> ChannelCount {
+        self.inner.playback_channels()
+    }
+
+    fn frames_consumed(&self) -> u64 {
+        self.inner.frames_consumed()
+    }
+
+    fn reset_frames_consumed(&self) {
+        self.inner.reset_frames_consumed()
+    }
+
+    fn play(&self) -> Result<(), AudioDeviceError> {
+        if self.unplugged.load(atomic::Ordering::SeqCst) {
+            // Like opening the stream again after it was lost or released
+            return Err(BuildStreamError::DeviceNotAvailable.into());
+        }
+        self.inner.play()
+    }
+
+    fn stop(&self) -> Result<(), AudioDeviceError> {
+        self.inner.stop()
+    }
+
+    fn pause(&self) -> Result<(), AudioDeviceError> {
+        self.inner.pause()
+    }
+
+    fn release_if_idle(&self) {
+        self.inner.release_if_idle()
+    }
+
+    fn set_volume(&self, volume: Volume) {
+        self.inner.set_volume(volume)
+    }
+
+    fn volume(&self) -> Volume {
+        self.inner.volume()
+    }
+
+    fn duck(&self, level: Option<Volume>) {
+        self.inner.duck(level)
+    }
+
+    fn subscribe(
+        &self,
+        name: &'static str,
+        channel: AudioDeviceMessageChannel,
+    ) -> BroadcastSubscription<AudioDeviceMessage> {
+        self.inner.subscribe(name, channel)
+    }
+}
+
 #[derive(Default)]
 struct StreamBuilder<'a> {
     config: Option<&'a SupportedStreamConfig>,
@@ -308,6 +452,11 @@ struct StreamBuilder<'a> {
     device: Option<&'a Device>,
     broadcaster: Option<Broadcaster<AudioDeviceMessage>>,
     volume: Option<Arc<AtomicU8>>,
+    fade: Option<Arc<Mutex<Fade>>>,
+    duck: Option<Arc<Mutex<Fade>>>,
+    liveness: Option<Arc<StreamLiveness>>,
+    clock: Option<SharedClock>,
+    idle_timeout: Duration,
 }
 
 impl<'a> StreamBuilder<'a> {
@@ -345,6 +494,31 @@ impl<'a> StreamBuilder<'a> {
         self
     }
 
+    fn fade(mut self, fade: Arc<Mutex<Fade>>) -> Self {
+        self.fade = Some(fade);
+        self
+    }
+
+    fn duck(mut self, duck: Arc<Mutex<Fade>>) -> Self {
+        self.duck = Some(duck);
+        self
+    }
+
+    fn liveness(mut self, liveness: Arc<StreamLiveness>) -> Self {
+        self.liveness = Some(liveness);
+        self
+    }
+
+    fn clock(mut self, clock: SharedClock) -> Self {
+        self.clock = Some(clock);
+        self
+    }
+
+    fn idle_timeout(mut self, idle_timeout: Duration) -> Self {
+        self.idle_timeout = idle_timeout;
+        self
+    }
+
     fn output_stream<S>(&self) -> Result<Stream, BuildStreamError>
     where
         S: Sample + SizedSample + 'static,
@@ -377,10 +551,18 @@ impl<'a> StreamBuilder<'a> {
             broadcaster: broadcaster.clone(),
             frames_consumed,
             volume: self.volume.clone().expect("volume is required"),
+            fade: self.fade.clone().expect("fade is required"),
+            duck: self.duck.clone().expect("duck is required"),
+            clock: self.clock.clone().expect("clock is required"),
+            idle_timeout: self.idle_timeout,
             state: DeviceState::Idle,
         };
+        let liveness = self.liveness.clone().expect("liveness is required");
+        liveness.reset();
         let write_data = {
+            let liveness = liveness.clone();
             move |data: &mut [S], _info: &OutputCallbackInfo| {
+                liveness.record_callback();
                 let mut output_buffer = output_buffer.lock().unwrap();
                 write_audio_data(&mut write_data_context, &mut output_buffer, data);
             }
@@ -388,6 +570,9 @@ impl<'a> StreamBuilder<'a> {
 
         let error_callback = move |err: StreamError| {
             log::error!("stream error: {}", err);
+            if matches!(err, StreamError::DeviceNotAvailable) {
+                liveness.lost.store(true, atomic::Ordering::SeqCst);
+            }
             broadcaster.broadcast(AudioDeviceMessage::Error(
                 AudioDeviceError::from(err).into(),
             ));
@@ -416,14 +601,29 @@ impl<'a> StreamBuilder<'a> {
 
 struct CpalAudioDevice {
     // Cpal audio structs
-    _device: Device,
+    /// Device that the stream plays on. Replaced if it goes away while playing.
+    device: Mutex<Device>,
+    preferred_output_device_name: Option<String>,
     config: SupportedStreamConfig,
-    stream: Stream,
+    /// Output stream, which is `None` while the device is released.
+    stream: Mutex<Option<Stream>>,
+    /// Routes audio to the device's channels when one is configured for the device.
+    channel_map: Option<ChannelMap>,
+    pad_final_chunk: bool,
+    idle_timeout: Duration,
+    release_after: Option<Duration>,
+    fade_length: Duration,
+    duck_ramp: Duration,
 
     // Information about the current state of playback
     frames_consumed: Arc<AtomicU64>,
     playing: AtomicBool,
+    paused_since: Mutex<Option<Instant>>,
     volume: Arc<AtomicU8>,
+    fade: Arc<Mutex<Fade>>,
+    duck: Arc<Mutex<Fade>>,
+    liveness: Arc<StreamLiveness>,
+    clock: SharedClock,
 
     // Audio data and message passing
     output_buffer: Arc<Mutex<BoxAudioBuffer>>,
@@ -431,13 +631,20 @@ struct CpalAudioDevice {
 }
 
 impl CpalAudioDevice {
-    fn new(preferred_output_device_name: Option<&str>) -> Result<Self, AudioDeviceError> {
+    fn new(
+        preferred_output_device_name: Option<&str>,
+        channel_maps: &ChannelMaps,
+        options: &DeviceOptions,
+    ) -> Result<Self, AudioDeviceError> {
         let host = cpal::default_host();
         let device = select_device(&host, preferred_output_device_name)?;
-        log::info!("selected audio output device: {}", device.name()?);
+        let name = device.name()?;
+        log::info!("selected audio output device: {name}");
+        let channel_map = channel_maps.get(&name);
 
         let supported_output_configs = device.supported_output_configs()?;
-        let config = select_config(supported_output_configs)?
+        let required_channels = channel_map.map(ChannelMap::device_channels);
+        let config = select_config(supported_output_configs, required_channels)?
             .ok_or(AudioDeviceError::FailedToSelectConfig)?;
         log::info!(
             "selected audio output configuration: channels={}, sample_rate={}, sample_format={:?}",
@@ -445,35 +652,87 @@ impl CpalAudioDevice {
             config.sample_rate().0,
             config.sample_format()
         );
+        let channel_map = channel_map
+            .filter(|map| {
+                let fits = map.device_channels() == config.channels() as ChannelCount;
+                if !fits {
+                    log::warn!(
+                        "ignoring channel map \"{map}\" since {name} doesn't support {} channels",
+                        map.device_channels()
+                    );
+                }
+                fits
+            })
+            .cloned();
+        if let Some(map) = &channel_map {
+            log::info!("routing audio to the device's channels with channel map \"{map}\"");
+        }
 
         let frames_consumed = Arc::new(AtomicU64::new(0));
         let output_buffer = Arc::new(Mutex::new(BoxAudioBuffer::empty(config.sample_format())));
 
-        let broadcaster = Broadcaster::new();
-        let volume = Arc::new(AtomicU8::new(Volume::default().into()));
-        let stream = StreamBuilder::new()
-            .config(&config)
-            .device(&device)
-            .broadcaster(broadcaster.clone())
-            .frames_consumed(frames_consumed.clone())
-            .output_buffer(output_buffer.clone())
-            .volume(volume.clone())
-            .build()?;
-
-        stream.pause()?;
-
-        Ok(Self {
-            _device: device,
+        let clock = SystemClock::shared();
+        let audio_device = Self {
+            device: Mutex::new(device),
+            preferred_output_device_name: preferred_output_device_name.map(str::to_string),
             config,
-            stream,
+            stream: Mutex::new(None),
+            channel_map,
+            pad_final_chunk: options.pad_final_chunk,
+            idle_timeout: options.idle_timeout,
+            release_after: options.release_after,
+            fade_length: options.fade,
+            duck_ramp: options.duck_ramp,
 
             frames_consumed,
             playing: AtomicBool::new(false),
-            volume,
+            paused_since: Mutex::new(Some(clock.now())),
+            volume: Arc::new(AtomicU8::new(Volume::default().into())),
+            fade: Arc::new(Mutex::new(Fade::default())),
+            duck: Arc::new(Mutex::new(Fade::default())),
+            liveness: Arc::new(StreamLiveness::new(clock.clone())),
+            clock,
 
             output_buffer,
-            broadcaster,
-        })
+            broadcaster: Broadcaster::new(),
+        };
+        let stream = audio_device.open_stream()?;
+        stream.pause()?;
+        *audio_device.stream.lock().unwrap() = Some(stream);
+        Ok(audio_device)
+    }
+
+    fn open_stream(&self) -> Result<Stream, BuildStreamError> {
+        let device = self.device.lock().unwrap();
+        StreamBuilder::new()
+            .config(&self.config)
+            .device(&device)
+            .broadcaster(self.broadcaster.clone())
+            .frames_consumed(self.frames_consumed.clone())
+            .output_buffer(self.output_buffer.clone())
+            .volume(self.volume.clone())
+            .fade(self.fade.clone())
+            .duck(self.duck.clone())
+            .liveness(self.liveness.clone())
+            .clock(self.clock.clone())
+            .idle_timeout(self.idle_timeout)
+            .build()
+    }
+
+    fn fade_frames(&self) -> usize {
+        (self.fade_length.as_secs_f32() * self.config.sample_rate().0 as f32) as usize
+    }
+
+    /// Fades out the audio that's still playing, and waits for the write callback to finish it.
+    fn fade_out(&self) {
+        if self.fade_length.is_zero() || !self.playing.load(atomic::Ordering::SeqCst) {
+            return;
+        }
+        self.fade.lock().unwrap().ramp_to(0.0, self.fade_frames());
+        let deadline = self.clock.now() + self.fade_length + FADE_OUT_GRACE;
+        while !self.fade.lock().unwrap().finished() && self.clock.now() < deadline {
+            std::thread::sleep(Duration::from_millis(1));
+        }
     }
 }
 
@@ -493,6 +752,8 @@ impl AudioDevice for CpalAudioDevice {
             self.output_buffer.clone(),
             self.broadcaster.clone(),
         )
+        .with_channel_map(self.channel_map.clone())
+        .with_final_chunk_padding(self.pad_final_chunk)
     }
 
     fn playback_sample_rate(&self) -> SampleRate {
@@ -512,24 +773,83 @@ impl AudioDevice for CpalAudioDevice {
     }
 
     fn stop(&self) -> Result<(), AudioDeviceError> {
+        self.fade_out();
         self.output_buffer.lock().unwrap().clear();
         self.pause()
     }
 
     fn play(&self) -> Result<(), AudioDeviceError> {
-        self.stream.play()?;
+        let mut stream = self.stream.lock().unwrap();
+        // The device can disappear without notice while paused, such as when undocking
+        if stream.is_some()
+            && !self
+                .liveness
+                .alive(self.playing.load(atomic::Ordering::SeqCst))
+        {
+            log::warn!("the audio output stream was lost, so it's being rebuilt");
+            *stream = None;
+            self.playing.store(false, atomic::Ordering::SeqCst);
+            self.output_buffer.lock().unwrap().clear();
+            // The lost device may not come back, so pick again, which falls back to the
+            // system's default device if the preferred one is gone
+            let host = cpal::default_host();
+            match select_device(&host, self.preferred_output_device_name.as_deref()) {
+                Ok(device) => {
+                    if let Ok(name) = device.name() {
+                        log::info!("rebuilding the audio output stream on {name}");
+                    }
+                    *self.device.lock().unwrap() = device;
+                }
+                Err(err) => log::warn!("failed to select an audio output device: {err}"),
+            }
+        }
+        if stream.is_none() {
+            *stream = Some(self.open_stream()?);
+            log::info!("reopened audio device");
+        }
+        if !self.playing.load(atomic::Ordering::SeqCst) {
+            let mut fade = self.fade.lock().unwrap();
+            if self.fade_length.is_zero() {
+                *fade = Fade::default();
+            } else {
+                fade.gain = 0.0;
+                fade.ramp_to(1.0, self.fade_frames());
+            }
+        }
+        stream.as_ref().expect("opened above").play()?;
         self.playing.store(true, atomic::Ordering::SeqCst);
+        *self.paused_since.lock().unwrap() = None;
         log::info!("resumed audio device");
         Ok(())
     }
 
     fn pause(&self) -> Result<(), AudioDeviceError> {
-        self.stream.pause()?;
+        self.fade_out();
+        if let Some(stream) = self.stream.lock().unwrap().as_ref() {
+            stream.pause()?;
+        }
         self.playing.store(false, atomic::Ordering::SeqCst);
+        self.paused_since
+            .lock()
+            .unwrap()
+            .get_or_insert_with(|| self.clock.now());
         log::info!("paused audio device");
         Ok(())
     }
 
+    fn release_if_idle(&self) {
+        let (Some(release_after), Some(paused_since)) =
+            (self.release_after, *self.paused_since.lock().unwrap())
+        else {
+            return;
+        };
+        let mut stream = self.stream.lock().unwrap();
+        if stream.is_some() && self.clock.elapsed(paused_since) >= release_after {
+            *stream = None;
+            log::info!("released audio device after {release_after:?} paused");
+        }
+    }
+
     fn set_volume(&self, volume: Volume) {
         self.volume.store(volume.into(), atomic::Ordering::Relaxed);
     }
@@ -538,6 +858,12 @@ impl AudioDevice for CpalAudioDevice {
         self.volume.load(atomic::Ordering::Relaxed).into()
     }
 
+    fn duck(&self, level: Option<Volume>) {
+        let gain = level.map(|level| level.as_percentage()).unwrap_or(1.0);
+        let frames = self.duck_ramp.as_secs_f32() * self.config.sample_rate().0 as f32;
+        self.duck.lock().unwrap().ramp_to(gain, frames as usize);
+    }
+
     fn subscribe(
         &self,
         name: &'static str,
@@ -547,6 +873,93 @@ impl AudioDevice for CpalAudioDevice {
     }
 }
 
+/// Whether the output stream is still running, as seen from its callbacks.
+struct StreamLiveness {
+    /// Set when the stream reports that its device went away.
+    lost: AtomicBool,
+    /// When the write callback last ran.
+    last_callback: Mutex<Instant>,
+    clock: SharedClock,
+}
+
+impl StreamLiveness {
+    fn new(clock: SharedClock) -> Self {
+        Self {
+            lost: AtomicBool::new(false),
+            last_callback: Mutex::new(clock.now()),
+            clock,
+        }
+    }
+
+    /// Starts over for a newly opened stream.
+    fn reset(&self) {
+        self.lost.store(false, atomic::Ordering::SeqCst);
+        self.record_callback();
+    }
+
+    /// Called from the write callback.
+    fn record_callback(&self) {
+        *self.last_callback.lock().unwrap() = self.clock.now();
+    }
+
+    /// Returns false if the stream was lost. Callbacks are only expected while it's playing.
+    fn alive(&self, playing: bool) -> bool {
+        if self.lost.load(atomic::Ordering::SeqCst) {
+            return false;
+        }
+        let last_callback = *self.last_callback.lock().unwrap();
+        !playing || self.clock.elapsed(last_callback) < STALLED_STREAM_TIMEOUT
+    }
+}
+
+/// Gain ramp applied by the write callback so that starting and stopping playback doesn't click.
+#[derive(Debug)]
+struct Fade {
+    gain: f32,
+    target: f32,
+    /// Frames until the gain reaches the target.
+    frames_left: usize,
+}
+
+impl Default for Fade {
+    fn default() -> Self {
+        Self {
+            gain: 1.0,
+            target: 1.0,
+            frames_left: 0,
+        }
+    }
+}
+
+impl Fade {
+    /// Ramps from the current gain to the target over the given number of frames.
+    fn ramp_to(&mut self, target: f32, frames: usize) {
+        self.target = target;
+        self.frames_left = frames.max(1);
+    }
+
+    fn finished(&self) -> bool {
+        self.frames_left == 0
+    }
+
+    /// Returns the gain for the next frame, and moves along the ramp.
+    fn next_gain(&mut self) -> f32 {
+        let gain = self.gain;
+        self.advance(1);
+        gain
+    }
+
+    fn advance(&mut self, frames: usize) {
+        if frames >= self.frames_left {
+            self.gain = self.target;
+            self.frames_left = 0;
+        } else {
+            self.gain += (self.target - self.gain) * frames as f32 / self.frames_left as f32;
+            self.frames_left -= frames;
+        }
+    }
+}
+
 #[cfg_attr(test, derive(Debug, Eq, PartialEq))]
 enum DeviceState {
     Idle,
@@ -560,6 +973,13 @@ struct WriteAudioDataContext {
     broadcaster: Broadcaster<AudioDeviceMessage>,
     frames_consumed: Arc<AtomicU64>,
     volume: Arc<AtomicU8>,
+    fade: Arc<Mutex<Fade>>,
+    /// Ramps to and from the ducked volume, separately from the fade so that pausing while
+    /// ducked resumes at the ducked volume.
+    duck: Arc<Mutex<Fade>>,
+    clock: SharedClock,
+    /// How long to play silence before broadcasting that the device is idle.
+    idle_timeout: Duration,
     state: DeviceState,
 }
 
@@ -570,6 +990,10 @@ fn write_audio_data<S>(
         broadcaster,
         frames_consumed,
         volume,
+        fade,
+        duck,
+        clock,
+        idle_timeout,
         state,
     }: &mut WriteAudioDataContext,
     box_output_buffer: &mut BoxAudioBuffer,
@@ -588,13 +1012,20 @@ fn write_audio_data<S>(
         len_to_consume as u64 / *channels as u64,
         atomic::Ordering::SeqCst,
     );
-    let volume: <S as Sample>::Float = Volume::from(volume.load(atomic::Ordering::Relaxed))
-        .as_percentage()
-        .into();
+    let volume = Volume::from(volume.load(atomic::Ordering::Relaxed)).as_percentage();
+    let mut fade = fade.lock().unwrap();
+    let mut duck = duck.lock().unwrap();
     let source = output_buffer.drain(0..len_to_consume);
-    for (from, into) in source.zip(data.iter_mut()) {
-        *into = from.mul_amp(volume);
+    let mut amp: <S as Sample>::Float = volume.into();
+    for (index, (from, into)) in source.zip(data.iter_mut()).enumerate() {
+        if index % *channels == 0 {
+            amp = (volume * fade.next_gain() * duck.next_gain()).into();
+        }
+        *into = from.mul_amp(amp);
     }
+    // Keep the fade moving through silence so that pausing doesn't wait on it
+    fade.advance((data.len() - len_to_consume) / *channels);
+    duck.advance((data.len() - len_to_consume) / *channels);
     let mut filled_in_silence = false;
     for into in data.iter_mut().skip(len_to_consume) {
         *into = S::EQUILIBRIUM;
@@ -609,11 +1040,11 @@ fn write_audio_data<S>(
         DeviceState::Playing => {
             if filled_in_silence {
                 broadcaster.broadcast(AudioDeviceMessage::EventPlaybackFinished);
-                *state = DeviceState::SilenceSince(Instant::now());
+                *state = DeviceState::SilenceSince(clock.now());
             }
         }
         DeviceState::SilenceSince(start) => {
-            if Instant::now() - *start >= Duration::from_secs(5) {
+            if clock.elapsed(*start) >= *idle_timeout {
                 broadcaster.broadcast(AudioDeviceMessage::EventAudioDeviceIdle);
                 *state = DeviceState::Idle;
             }
@@ -621,6 +1052,15 @@ fn write_audio_data<S>(
     }
 }
 
+/// Names of the audio output devices, for choosing which one to play on.
+pub fn output_device_names() -> Result<Vec<String>, AudioDeviceError> {
+    cpal::default_host()
+        .output_devices()
+        .map_err(AudioDeviceError::FailedToQueryDevices)?
+        .map(|device| Ok(device.name()?))
+        .collect()
+}
+
 fn select_device(host: &Host, preferred: Option<&str>) -> Result<Device, AudioDeviceError> {
     if let Some(preferred) = preferred {
         log::info!("looking for preferred audio device named \"{preferred}\"...");
@@ -643,6 +1083,7 @@ fn select_device(host: &Host, preferred: Option<&str>) -> Result<Device, AudioDe
 
 fn select_config(
     supported_output_configs: impl Iterator<Item = SupportedStreamConfigRange>,
+    required_channels: Option<ChannelCount>,
 ) -> Result<Option<SupportedStreamConfig>, AudioDeviceError> {
     let mut supported_output_configs = supported_output_configs.collect::<Vec<_>>();
     if supported_output_configs.is_empty() {
@@ -650,7 +1091,8 @@ fn select_config(
     }
 
     supported_output_configs.sort_by(|a, b| {
-        by_preferred_channels(a, b)
+        by_required_channels(required_channels, a, b)
+            .then_with(|| by_preferred_channels(a, b))
             .then_with(|| by_preferred_sample_rate(a, b))
             .then_with(|| by_preferred_sample_format(a, b))
     });
@@ -686,6 +1128,19 @@ fn select_sample_rate(range: SupportedStreamConfigRange) -> SupportedStreamConfi
     range.with_max_sample_rate()
 }
 
+/// Sorts configs with the required number of channels to the front.
+fn by_required_channels(
+    required: Option<ChannelCount>,
+    left: &SupportedStreamConfigRange,
+    right: &SupportedStreamConfigRange,
+) -> Ordering {
+    let Some(required) = required else {
+        return Ordering::Equal;
+    };
+    let fits = |config: &SupportedStreamConfigRange| config.channels() == required as u16;
+    fits(right).cmp(&fits(left))
+}
+
 fn by_preferred_channels(
     left: &SupportedStreamConfigRange,
     right: &SupportedStreamConfigRange,
@@ -753,6 +1208,7 @@ fn by_preferred_sample_format(
 #[cfg(test)]
 mod tests {
     use super::*;
+    use crate::clock::{Clock, FakeClock};
     use cpal::{SampleFormat, SupportedBufferSize};
 
     #[test]
@@ -874,24 +1330,43 @@ mod tests {
             )
         }
 
-        assert_eq!(None, select_config([].into_iter()).unwrap());
+        assert_eq!(None, select_config([].into_iter(), None).unwrap());
 
         assert_eq!(
             Some(cfg(2, 44100, F32).with_sample_rate(cpal::SampleRate(44100))),
-            select_config([cfg(5, 44100, F32), cfg(2, 44100, F32), cfg(1, 44100, F32)].into_iter())
-                .unwrap()
+            select_config(
+                [cfg(5, 44100, F32), cfg(2, 44100, F32), cfg(1, 44100, F32)].into_iter(),
+                None
+            )
+            .unwrap()
         );
 
         assert_eq!(
             Some(cfg(2, 48000, F32).with_sample_rate(cpal::SampleRate(48000))),
-            select_config([cfg(2, 8000, F32), cfg(2, 96000, F32), cfg(2, 48000, F32)].into_iter())
-                .unwrap()
+            select_config(
+                [cfg(2, 8000, F32), cfg(2, 96000, F32), cfg(2, 48000, F32)].into_iter(),
+                None
+            )
+            .unwrap()
         );
 
         assert_eq!(
             Some(cfg(2, 48000, I16).with_sample_rate(cpal::SampleRate(48000))),
-            select_config([cfg(2, 48000, I8), cfg(2, 48000, U32), cfg(2, 48000, I16)].into_iter())
-                .unwrap()
+            select_config(
+                [cfg(2, 48000, I8), cfg(2, 48000, U32), cfg(2, 48000, I16)].into_iter(),
+                None
+            )
+            .unwrap()
+        );
+
+        // Channel maps need the device opened with a channel for every entry
+        assert_eq!(
+            Some(cfg(4, 44100, F32).with_sample_rate(cpal::SampleRate(44100))),
+            select_config(
+                [cfg(2, 44100, F32), cfg(4, 44100, F32)].into_iter(),
+                Some(4)
+            )
+            .unwrap()
         );
     }
 
@@ -910,6 +1385,10 @@ mod tests {
             broadcaster: broadcaster.clone(),
             frames_consumed,
             volume: Arc::new(AtomicU8::new(Volume::default().into())),
+            fade: Arc::new(Mutex::new(Fade::default())),
+            duck: Arc::new(Mutex::new(Fade::default())),
+            clock: SystemClock::shared(),
+            idle_timeout: Duration::from_secs(5),
             state: DeviceState::Playing,
         };
 
@@ -950,6 +1429,10 @@ mod tests {
             broadcaster: broadcaster.clone(),
             frames_consumed,
             volume: Arc::new(AtomicU8::new(Volume::from_percentage(0.5).into())),
+            fade: Arc::new(Mutex::new(Fade::default())),
+            duck: Arc::new(Mutex::new(Fade::default())),
+            clock: SystemClock::shared(),
+            idle_timeout: Duration::from_secs(5),
             state: DeviceState::Playing,
         };
 
@@ -975,6 +1458,118 @@ mod tests {
         );
     }
 
+    #[test]
+    fn write_audio_data_fade_in() {
+        let mut output_buffer =
+            BoxAudioBuffer::new(SampleFormat::F32, AudioBuffer::new(vec![1f32; 2000]));
+        let mut fade = Fade {
+            gain: 0.0,
+            ..Default::default()
+        };
+        fade.ramp_to(1.0, 100);
+
+        let mut output = vec![0f32; 1000];
+        let mut context = WriteAudioDataContext {
+            channels: 2,
+            desired_output_buffer_size: 1000,
+            broadcaster: Broadcaster::new(),
+            frames_consumed: Arc::new(AtomicU64::new(0)),
+            volume: Arc::new(AtomicU8::new(Volume::default().into())),
+            fade: Arc::new(Mutex::new(fade)),
+            duck: Arc::new(Mutex::new(Fade::default())),
+            clock: SystemClock::shared(),
+            idle_timeout: Duration::from_secs(5),
+            state: DeviceState::Playing,
+        };
+
+        write_audio_data(&mut context, &mut output_buffer, &mut output);
+
+        assert_eq!(0.0, output[0], "it should start the fade in silent");
+        assert_eq!(
+            output[100], output[101],
+            "it should fade both channels together"
+        );
+        assert!(
+            output[..200].windows(2).all(|w| w[0] <= w[1]),
+            "it should ramp up the gain"
+        );
+        assert!(
+            output[200..].iter().all(|&s| s == 1.0),
+            "it should play at full volume once the fade finishes"
+        );
+        assert!(context.fade.lock().unwrap().finished());
+    }
+
+    #[test]
+    fn write_audio_data_duck() {
+        let mut output_buffer =
+            BoxAudioBuffer::new(SampleFormat::F32, AudioBuffer::new(vec![1f32; 2000]));
+        let mut duck = Fade::default();
+        duck.ramp_to(0.25, 100);
+
+        let mut output = vec![0f32; 1000];
+        let mut context = WriteAudioDataContext {
+            channels: 1,
+            desired_output_buffer_size: 1000,
+            broadcaster: Broadcaster::new(),
+            frames_consumed: Arc::new(AtomicU64::new(0)),
+            volume: Arc::new(AtomicU8::new(Volume::default().into())),
+            fade: Arc::new(Mutex::new(Fade::default())),
+            duck: Arc::new(Mutex::new(duck)),
+            clock: SystemClock::shared(),
+            idle_timeout: Duration::from_secs(5),
+            state: DeviceState::Playing,
+        };
+
+        write_audio_data(&mut context, &mut output_buffer, &mut output);
+
+        assert_eq!(1.0, output[0], "it should start the duck at full volume");
+        assert!(
+            output[..100].windows(2).all(|w| w[0] >= w[1]),
+            "it should ramp down the gain"
+        );
+        assert!(
+            output[100..].iter().all(|&s| s == 0.25),
+            "it should stay ducked once the ramp finishes"
+        );
+
+        context.duck.lock().unwrap().ramp_to(1.0, 100);
+        write_audio_data(&mut context, &mut output_buffer, &mut output);
+        assert!(
+            output[100..].iter().all(|&s| s == 1.0),
+            "it should restore the full volume"
+        );
+    }
+
+    #[test]
+    fn stream_liveness() {
+        let clock = FakeClock::new();
+        let liveness = StreamLiveness::new(clock.shared());
+        assert!(liveness.alive(true));
+
+        clock.advance(STALLED_STREAM_TIMEOUT);
+        assert!(!liveness.alive(true), "a playing stream should call back");
+        assert!(liveness.alive(false), "a paused stream doesn't call back");
+
+        liveness.reset();
+        liveness.lost.store(true, atomic::Ordering::SeqCst);
+        assert!(!liveness.alive(false));
+        liveness.reset();
+        assert!(liveness.alive(true));
+    }
+
+    #[test]
+    fn fade_out_through_silence() {
+        let mut fade = Fade::default();
+        fade.ramp_to(0.0, 100);
+        fade.advance(50);
+        assert_eq!(0.5, fade.gain);
+        assert!(!fade.finished());
+        fade.advance(500);
+        assert_eq!(0.0, fade.gain);
+        assert!(fade.finished());
+    }
+
     #[test]
     fn write_audio_data_request_more_audio() {
         let mut output_buffer =
@@ -990,6 +1585,10 @@ mod tests {
             broadcaster: broadcaster.clone(),
             frames_consumed,
             volume: Arc::new(AtomicU8::new(Volume::default().into())),
+            fade: Arc::new(Mutex::new(Fade::default())),
+            duck: Arc::new(Mutex::new(Fade::default())),
+            clock: SystemClock::shared(),
+            idle_timeout: Duration::from_secs(5),
             state: DeviceState::Playing,
         };
 
@@ -1033,6 +1632,10 @@ mod tests {
             broadcaster: broadcaster.clone(),
             frames_consumed,
             volume: Arc::new(AtomicU8::new(Volume::default().into())),
+            fade: Arc::new(Mutex::new(Fade::default())),
+            duck: Arc::new(Mutex::new(Fade::default())),
+            clock: SystemClock::shared(),
+            idle_timeout: Duration::from_secs(5),
             state: DeviceState::Playing,
         };
 
@@ -1079,6 +1682,7 @@ mod tests {
         let broadcaster = Broadcaster::new();
         let test_sub = broadcaster.subscribe("test", AudioDeviceMessageChannel::All);
 
+        let clock = FakeClock::new();
         let mut output = vec![123f32; 1000];
         let mut context = WriteAudioDataContext {
             channels: 1,
@@ -1086,9 +1690,26 @@ mod tests {
             broadcaster: broadcaster.clone(),
             frames_consumed,
             volume: Arc::new(AtomicU8::new(Volume::default().into())),
-            state: DeviceState::SilenceSince(Instant::now() - Duration::from_secs(10)),
+            fade: Arc::new(Mutex::new(Fade::default())),
+            duck: Arc::new(Mutex::new(Fade::default())),
+            clock: clock.shared(),
+            idle_timeout: Duration::from_secs(5),
+            state: DeviceState::SilenceSince(clock.now()),
         };
 
+        clock.advance(Duration::from_secs(4));
+        write_audio_data(&mut context, &mut output_buffer, &mut output);
+        assert!(
+            matches!(context.state, DeviceState::SilenceSince(_)),
+            "it shouldn't be idle before the timeout"
+        );
+        assert!(matches!(
+            test_sub.try_recv().unwrap(),
+            AudioDeviceMessage::RequestAudioData
+        ));
+        assert!(test_sub.try_recv().is_none());
+
+        clock.advance(Duration::from_secs(1));
         write_audio_data(&mut context, &mut output_buffer, &mut output);
 
         assert!(
@@ -1115,6 +1736,45 @@ mod tests {
         );
     }
 
+    #[test]
+    fn write_audio_data_release_immediately() {
+        let mut output_buffer =
+            BoxAudioBuffer::new(SampleFormat::F32, AudioBuffer::new(Vec::<f32>::new()));
+        let broadcaster = Broadcaster::new();
+        let test_sub = broadcaster.subscribe("test", AudioDeviceMessageChannel::Events);
+
+        let mut output = vec![123f32; 1000];
+        let mut context = WriteAudioDataContext {
+            channels: 1,
+            desired_output_buffer_size: 3000,
+            broadcaster: broadcaster.clone(),
+            frames_consumed: Arc::new(AtomicU64::new(0)),
+            volume: Arc::new(AtomicU8::new(Volume::default().into())),
+            fade: Arc::new(Mutex::new(Fade::default())),
+            duck: Arc::new(Mutex::new(Fade::default())),
+            clock: SystemClock::shared(),
+            idle_timeout: Duration::ZERO,
+            state: DeviceState::Playing,
+        };
+
+        // The first silent callback finishes playback, and the next one releases the device
+        write_audio_data(&mut context, &mut output_buffer, &mut output);
+        write_audio_data(&mut context, &mut output_buffer, &mut output);
+
+        assert!(
+            matches!(context.state, DeviceState::Idle),
+            "it should switch to the Idle state without waiting"
+        );
+        assert!(matches!(
+            test_sub.try_recv().unwrap(),
+            AudioDeviceMessage::EventPlaybackFinished
+        ));
+        assert!(matches!(
+            test_sub.try_recv().unwrap(),
+            AudioDeviceMessage::EventAudioDeviceIdle
+        ));
+    }
+
     #[test]
     fn write_audio_data_idle_back_to_playing() {
         let mut output_buffer =
@@ -1130,6 +1790,10 @@ mod tests {
             broadcaster: broadcaster.clone(),
             frames_consumed,
             volume: Arc::new(AtomicU8::new(Volume::default().into())),
+            fade: Arc::new(Mutex::new(Fade::default())),
+            duck: Arc::new(Mutex::new(Fade::default())),
+            clock: SystemClock::shared(),
+            idle_timeout: Duration::from_secs(5),
             state: DeviceState::Idle,
         };
 
@@ -0,0 +1,238 @@
+// This file is part of Millenium Player.
+// Copyright (C) 2023 John DiSanti.
+//
+// Millenium Player is free software: you can redistribute it and/or modify it under the terms of
+// the GNU General Public License as published by the Free Software Foundation, either version 3 of
+// the License, or (at your option) any later version.
+//
+// Millenium Player is distributed in the hope that it will be useful, but WITHOUT ANY WARRANTY;
+// without even the implied warranty of MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE. See
+// the GNU General Public License for more details.
+//
+// You should have received a copy of the GNU General Public License along with Millenium Player.
+// If not, see <https://www.gnu.org/licenses/>.
+
+use crate::{
+    audio::source::{AudioDecoderSource, AudioSourceError, PreferredFormat, SourceBuffer},
+    audio::{ChannelCount, SampleRate},
+    location::Location,
+};
+use std::{
+    sync::atomic::{AtomicBool, Ordering},
+    time::Duration,
+};
+
+/// Length of the blocks that peak and RMS values are measured over.
+const BLOCK_LENGTH: Duration = Duration::from_secs(3);
+
+/// Portion of the loudest blocks that are used for the RMS measurement.
+const LOUDEST_BLOCK_PORTION: f64 = 0.2;
+
+/// Dynamic range estimate in decibels, rounded to the nearest whole number.
+///
+/// This follows the commonly used "DR" measurement, where the difference between the second
+/// highest peak and the RMS of the loudest 20% of three second blocks is averaged across channels.
+/// Heavily compressed masters tend to measure below DR8, while dynamic masters measure DR12+.
+#[derive(
+    Copy, Clone, Debug, Eq, PartialEq, Ord, PartialOrd, serde::Serialize, serde::Deserialize,
+)]
+pub struct DynamicRange(pub i32);
+
+#[derive(Clone, Default)]
+struct ChannelBlocks {
+    current_sum_squares: f64,
+    current_peak: f32,
+    rms: Vec<f64>,
+    peaks: Vec<f32>,
+}
+
+impl ChannelBlocks {
+    fn finish_block(&mut self, frames: usize) {
+        if frames > 0 {
+            self.rms
+                .push((2.0 * self.current_sum_squares / frames as f64).sqrt());
+            self.peaks.push(self.current_peak);
+        }
+        self.current_sum_squares = 0.0;
+        self.current_peak = 0.0;
+    }
+
+    fn dynamic_range(&self) -> Option<f64> {
+        if self.rms.is_empty() {
+            return None;
+        }
+        let mut rms = self.rms.clone();
+        rms.sort_by(|a, b| b.total_cmp(a));
+        let loudest_count = usize::max(1, (rms.len() as f64 * LOUDEST_BLOCK_PORTION) as usize);
+        let loudest_rms = (rms[0..loudest_count].iter().map(|r| r * r).sum::<f64>()
+            / loudest_count as f64)
+            .sqrt();
+
+        let mut peaks = self.peaks.clone();
+        peaks.sort_by(|a, b| b.total_cmp(a));
+        let peak = peaks.get(1).or(peaks.first()).copied().unwrap_or(0.0) as f64;
+
+        if loudest_rms <= 0.0 || peak <= 0.0 {
+            None
+        } else {
+            Some(20.0 * (peak / loudest_rms).log10())
+        }
+    }
+}
+
+/// Measures the dynamic range of audio as it is fed in.
+pub struct DynamicRangeMeter {
+    block_frames: usize,
+    frames_in_block: usize,
+    channels: Vec<ChannelBlocks>,
+}
+
+impl DynamicRangeMeter {
+    /// Creates a new meter for audio with the given sample rate and channel count.
+    pub fn new(sample_rate: SampleRate, channels: ChannelCount) -> Self {
+        Self {
+            block_frames: (BLOCK_LENGTH.as_secs_f64() * sample_rate as f64) as usize,
+            frames_in_block: 0,
+            channels: vec![ChannelBlocks::default(); channels as usize],
+        }
+    }
+
+    /// The number of channels this meter was created for.
+    pub fn channel_count(&self) -> ChannelCount {
+        self.channels.len() as ChannelCount
+    }
+
+    /// Adds audio to the measurement.
+    ///
+    /// The buffer must have the same channel count the meter was created with.
+    pub fn push(&mut self, buffer: &SourceBuffer) {
+        debug_assert_eq!(self.channel_count(), buffer.channel_count());
+        let frame_count = buffer.frame_count();
+        let mut offset = 0;
+        while offset < frame_count {
+            let take = usize::min(
+                frame_count - offset,
+                self.block_frames - self.frames_in_block,
+            );
+            for (index, channel) in self.channels.iter_mut().enumerate() {
+                for &sample in &buffer.channel(index)[offset..offset + take] {
+                    channel.current_sum_squares += sample as f64 * sample as f64;
+                    channel.current_peak = f32::max(channel.current_peak, sample.abs());
+                }
+            }
+            offset += take;
+            self.frames_in_block += take;
+            if self.frames_in_block == self.block_frames {
+                for channel in &mut self.channels {
+                    channel.finish_block(self.block_frames);
+                }
+                self.frames_in_block = 0;
+            }
+        }
+    }
+
+    /// Finishes the measurement and returns the dynamic range.
+    ///
+    /// Returns `None` if no audio was measured, or if the audio was entirely silent.
+    pub fn finish(mut self) -> Option<DynamicRange> {
+        for channel in &mut self.channels {
+            channel.finish_block(self.frames_in_block);
+        }
+        let per_channel: Vec<f64> = self
+            .channels
+            .iter()
+            .filter_map(ChannelBlocks::dynamic_range)
+            .collect();
+        if per_channel.is_empty() {
+            None
+        } else {
+            let average = per_channel.iter().sum::<f64>() / per_channel.len() as f64;
+            Some(DynamicRange(average.round() as i32))
+        }
+    }
+}
+
+/// Decodes an entire location to measure its dynamic range. Decoding stops early if `cancel`
+/// gets set.
+pub fn measure_dynamic_range(
+    location: Location,
+    cancel: &AtomicBool,
+) -> Result<Option<DynamicRange>, AudioSourceError> {
+    let mut source = AudioDecoderSource::new(location, PreferredFormat::new(44100, 2))?;
+    let mut meter: Option<DynamicRangeMeter> = None;
+    while let Some(chunk) = source.next_chunk()? {
+        if cancel.load(Ordering::Relaxed) {
+            break;
+        }
+        if chunk.frame_count() == 0 {
+            continue;
+        }
+        let meter = meter.get_or_insert_with(|| {
+            DynamicRangeMeter::new(chunk.sample_rate(), chunk.channel_count())
+        });
+        if meter.channel_count() == chunk.channel_count() {
+            meter.push(chunk);
+        }
+    }
+    Ok(meter.and_then(DynamicRangeMeter::finish))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::f32::consts::PI;
+
+    fn sine(sample_rate: SampleRate, seconds: f32, amplitude: f32) -> Vec<f32> {
+        (0..(sample_rate as f32 * seconds) as usize)
+            .map(|i| amplitude * (2.0 * PI * 441.0 * i as f32 / sample_rate as f32).sin())
+            .collect()
+    }
+
+    #[test]
+    fn steady_sine_has_no_dynamic_range() {
+        let mut meter = DynamicRangeMeter::new(44100, 1);
+        meter.push(&SourceBuffer::from_channels(
+            44100,
+            vec![sine(44100, 12.0, 0.5)],
+        ));
+        assert_eq!(Some(DynamicRange(0)), meter.finish());
+    }
+
+    #[test]
+    fn quiet_passages_with_loud_peaks() {
+        let mut samples = sine(44100, 30.0, 0.1);
+        // A couple of short loud transients in separate blocks
+        for block in [1, 5] {
+            let start = block * 3 * 44100;
+            samples[start..start + 100].fill(1.0);
+        }
+
+        let mut meter = DynamicRangeMeter::new(44100, 2);
+        meter.push(&SourceBuffer::from_channels(
+            44100,
+            vec![samples.clone(), samples],
+        ));
+        // 20 * log10(1.0 / 0.1) = 20, minus a little since the transients
+        // also raise the RMS of the blocks they're in
+        assert_eq!(Some(DynamicRange(19)), meter.finish());
+    }
+
+    #[test]
+    fn silence() {
+        let mut meter = DynamicRangeMeter::new(44100, 1);
+        meter.push(&SourceBuffer::from_channels(44100, vec![vec![0.0; 44100]]));
+        assert_eq!(None, meter.finish());
+        assert_eq!(None, DynamicRangeMeter::new(44100, 1).finish());
+    }
+
+    #[test]
+    fn measure_test_file() {
+        let dr = measure_dynamic_range(
+            Location::path("../test-data/melodic_a_minor/melodic_a_minor_2chan_44100hz_11s.ogg"),
+            &AtomicBool::new(false),
+        )
+        .unwrap()
+        .unwrap();
+        assert!(dr.0 > 0 && dr.0 < 30, "unexpected dynamic range: {dr:?}");
+    }
+}
@@ -0,0 +1,310 @@
+// This file is part of Millenium Player.
+// Copyright (C) 2023 John DiSanti.
+//
+// Millenium Player is free software: you can redistribute it and/or modify it under the terms of
+// the GNU General Public License as published by the Free Software Foundation, either version 3 of
+// the License, or (at your option) any later version.
+//
+// Millenium Player is distributed in the hope that it will be useful, but WITHOUT ANY WARRANTY;
+// without even the implied warranty of MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE. See
+// the GNU General Public License for more details.
+//
+// You should have received a copy of the GNU General Public License along with Millenium Player.
+// If not, see <https://www.gnu.org/licenses/>.
+
+use crate::{
+    audio::{
+        source::{AudioDecoderSource, AudioSourceError, PreferredFormat, SourceBuffer},
+        ChannelCount, SampleRate,
+    },
+    location::Location,
+};
+use std::{fmt, time::Duration};
+
+/// Samples quieter than this (about -80 dBFS) count as silence.
+const SILENCE_THRESHOLD: f32 = 1e-4;
+
+/// How much of each end of a track is looked at for silence.
+const EDGE_LENGTH: Duration = Duration::from_secs(2);
+
+/// Shortest silence between two tracks that is heard as a gap.
+const AUDIBLE_GAP: Duration = Duration::from_millis(50);
+
+/// What decides whether a track joins up with the tracks around it.
+#[derive(Clone, Debug, PartialEq)]
+pub struct TrackEdges {
+    pub sample_rate: SampleRate,
+    pub channel_count: ChannelCount,
+    /// Whether the encoder's delay and padding are known, so that they're trimmed off.
+    pub padding_known: bool,
+    /// Silence at the start of the track, looking no further than a couple of seconds in.
+    pub leading_silence: Duration,
+    /// Silence at the end of the track, looking no further than a couple of seconds back.
+    pub trailing_silence: Duration,
+}
+
+impl TrackEdges {
+    /// Decodes the start and end of a track.
+    pub fn read(location: &Location) -> Result<Self, AudioSourceError> {
+        let mut source = AudioDecoderSource::new(location.clone(), PreferredFormat::new(44100, 2))?;
+        let padding_known = source.encoder_padding_known();
+
+        let mut format = None;
+        let mut leading_frames = 0;
+        while let Some(chunk) = source.next_chunk()? {
+            let (sample_rate, _) =
+                *format.get_or_insert((chunk.sample_rate(), chunk.channel_count()));
+            match first_sound(chunk) {
+                Some(frame) => {
+                    leading_frames += frame;
+                    break;
+                }
+                None => leading_frames += chunk.frame_count(),
+            }
+            if leading_frames as f64 >= EDGE_LENGTH.as_secs_f64() * sample_rate as f64 {
+                break;
+            }
+        }
+        let (sample_rate, channel_count) =
+            format.ok_or(AudioSourceError::SourceHadNoAudioTracks)?;
+
+        // Skip ahead to the end rather than decoding the whole track when its length is known
+        let edge_frames = (EDGE_LENGTH.as_secs_f64() * sample_rate as f64) as u64;
+        if let Some(tail_start) = source
+            .frame_count()
+            .map(|frames| frames.saturating_sub(edge_frames))
+            .filter(|&start| start > 0)
+        {
+            source.seek(Duration::from_secs_f64(
+                tail_start as f64 / sample_rate as f64,
+            ))?;
+        }
+        let mut trailing_frames = 0;
+        while let Some(chunk) = source.next_chunk()? {
+            trailing_frames = match last_sound(chunk) {
+                Some(frame) => chunk.frame_count() - frame - 1,
+                None => trailing_frames + chunk.frame_count(),
+            };
+        }
+
+        let duration = |frames: usize| Duration::from_secs_f64(frames as f64 / sample_rate as f64);
+        Ok(Self {
+            sample_rate,
+            channel_count,
+            padding_known,
+            leading_silence: duration(leading_frames).min(EDGE_LENGTH),
+            trailing_silence: duration(trailing_frames).min(EDGE_LENGTH),
+        })
+    }
+}
+
+fn is_sound(buffer: &SourceBuffer, frame: usize) -> bool {
+    (0..buffer.channel_count() as usize)
+        .any(|channel| buffer.channel(channel)[frame].abs() > SILENCE_THRESHOLD)
+}
+
+fn first_sound(buffer: &SourceBuffer) -> Option<usize> {
+    (0..buffer.frame_count()).find(|&frame| is_sound(buffer, frame))
+}
+
+fn last_sound(buffer: &SourceBuffer) -> Option<usize> {
+    (0..buffer.frame_count())
+        .rev()
+        .find(|&frame| is_sound(buffer, frame))
+}
+
+/// Something that keeps one track from flowing straight into the next.
+#[derive(Clone, Debug, PartialEq)]
+pub enum GaplessIssue {
+    /// One of the tracks couldn't be decoded.
+    Unreadable(String),
+    /// The player restarts its output when the sample rate changes.
+    SampleRateChanges { from: SampleRate, to: SampleRate },
+    /// The player restarts its output when the channel count changes.
+    ChannelCountChanges {
+        from: ChannelCount,
+        to: ChannelCount,
+    },
+    /// The first track ends with encoder padding that can't be trimmed off.
+    UntrimmedEnd,
+    /// The second track starts with encoder delay that can't be trimmed off.
+    UntrimmedStart,
+    /// The tracks themselves have silence between them, such as from a rip with gaps.
+    Silence(Duration),
+}
+
+impl fmt::Display for GaplessIssue {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Unreadable(err) => write!(f, "{err}"),
+            Self::SampleRateChanges { from, to } => {
+                write!(f, "the sample rate changes from {from} Hz to {to} Hz")
+            }
+            Self::ChannelCountChanges { from, to } => {
+                write!(f, "the channel count changes from {from} to {to}")
+            }
+            Self::UntrimmedEnd => write!(
+                f,
+                "the first track's encoder padding isn't known, so it plays as silence"
+            ),
+            Self::UntrimmedStart => write!(
+                f,
+                "the second track's encoder delay isn't known, so it plays as silence"
+            ),
+            Self::Silence(silence) => write!(
+                f,
+                "the tracks have {} ms of silence between them",
+                silence.as_millis()
+            ),
+        }
+    }
+}
+
+/// How one track leads into the next.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Transition {
+    pub from: Location,
+    pub to: Location,
+    pub issues: Vec<GaplessIssue>,
+}
+
+impl Transition {
+    pub fn is_gapless(&self) -> bool {
+        self.issues.is_empty()
+    }
+}
+
+/// Checks whether each track will flow into the next without a gap, such as to verify an
+/// album rip before listening to it. Each track is decoded around its start and end.
+pub fn check_transitions(locations: &[Location]) -> Vec<Transition> {
+    let edges: Vec<Result<TrackEdges, String>> = locations
+        .iter()
+        .map(|location| {
+            TrackEdges::read(location).map_err(|err| format!("{location} couldn't be read: {err}"))
+        })
+        .collect();
+    locations
+        .windows(2)
+        .zip(edges.windows(2))
+        .map(|(locations, edges)| Transition {
+            from: locations[0].clone(),
+            to: locations[1].clone(),
+            issues: transition_issues(&edges[0], &edges[1]),
+        })
+        .collect()
+}
+
+fn transition_issues(
+    from: &Result<TrackEdges, String>,
+    to: &Result<TrackEdges, String>,
+) -> Vec<GaplessIssue> {
+    let (from, to) = match (from, to) {
+        (Ok(from), Ok(to)) => (from, to),
+        (from, to) => {
+            return [from, to]
+                .into_iter()
+                .filter_map(|edges| edges.as_ref().err())
+                .map(|err| GaplessIssue::Unreadable(err.clone()))
+                .collect()
+        }
+    };
+    let mut issues = Vec::new();
+    if from.sample_rate != to.sample_rate {
+        issues.push(GaplessIssue::SampleRateChanges {
+            from: from.sample_rate,
+            to: to.sample_rate,
+        });
+    }
+    if from.channel_count != to.channel_count {
+        issues.push(GaplessIssue::ChannelCountChanges {
+            from: from.channel_count,
+            to: to.channel_count,
+        });
+    }
+    if !from.padding_known {
+        issues.push(GaplessIssue::UntrimmedEnd);
+    }
+    if !to.padding_known {
+        issues.push(GaplessIssue::UntrimmedStart);
+    }
+    let silence = from.trailing_silence + to.leading_silence;
+    if silence >= AUDIBLE_GAP {
+        issues.push(GaplessIssue::Silence(silence));
+    }
+    issues
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn edges() -> TrackEdges {
+        TrackEdges {
+            sample_rate: 44100,
+            channel_count: 2,
+            padding_known: true,
+            leading_silence: Duration::ZERO,
+            trailing_silence: Duration::from_millis(10),
+        }
+    }
+
+    #[test]
+    fn issues_between_tracks() {
+        assert!(transition_issues(&Ok(edges()), &Ok(edges())).is_empty());
+
+        let to = TrackEdges {
+            sample_rate: 48000,
+            channel_count: 1,
+            padding_known: false,
+            leading_silence: Duration::from_millis(90),
+            ..edges()
+        };
+        assert_eq!(
+            vec![
+                GaplessIssue::SampleRateChanges {
+                    from: 44100,
+                    to: 48000
+                },
+                GaplessIssue::ChannelCountChanges { from: 2, to: 1 },
+                GaplessIssue::UntrimmedStart,
+                GaplessIssue::Silence(Duration::from_millis(100)),
+            ],
+            transition_issues(&Ok(edges()), &Ok(to))
+        );
+
+        assert_eq!(
+            vec![GaplessIssue::Unreadable("gone".into())],
+            transition_issues(&Ok(edges()), &Err("gone".into()))
+        );
+    }
+
+    #[test]
+    fn check_test_files() {
+        let path = |name: &str| Location::path(format!("../test-data/melodic_a_minor/{name}"));
+        let locations = [
+            path("melodic_a_minor_1chan_44100hz_6s.ogg"),
+            path("melodic_a_minor_2chan_44100hz_11s.ogg"),
+            path("melodic_a_minor_1chan_16000hz_6s.ogg"),
+            path("does-not-exist.ogg"),
+        ];
+        let transitions = check_transitions(&locations);
+        assert_eq!(3, transitions.len());
+        assert!(transitions[0]
+            .issues
+            .contains(&GaplessIssue::ChannelCountChanges { from: 1, to: 2 }));
+        assert!(transitions[1]
+            .issues
+            .contains(&GaplessIssue::SampleRateChanges {
+                from: 44100,
+                to: 16000
+            }));
+        assert!(matches!(
+            transitions[2].issues.as_slice(),
+            [GaplessIssue::Unreadable(_)]
+        ));
+
+        let edges = TrackEdges::read(&locations[0]).unwrap();
+        assert!(edges.padding_known);
+        assert!(edges.leading_silence < EDGE_LENGTH);
+    }
+}
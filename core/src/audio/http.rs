@@ -0,0 +1,1055 @@
+// This file is part of Millenium Player.
+// Copyright (C) 2023 John DiSanti.
+//
+// Millenium Player is free software: you can redistribute it and/or modify it under the terms of
+// the GNU General Public License as published by the Free Software Foundation, either version 3 of
+// the License, or (at your option) any later version.
+//
+// Millenium Player is distributed in the hope that it will be useful, but WITHOUT ANY WARRANTY;
+// without even the implied warranty of MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE. See
+// the GNU General Public License for more details.
+//
+// You should have received a copy of the GNU General Public License along with Millenium Player.
+// If not, see <https://www.gnu.org/licenses/>.
+
+use millenium_post_office::types::StreamHealth;
+use std::{
+    collections::VecDeque,
+    io::{self, BufRead, BufReader, Read, Seek, SeekFrom, Write},
+    net::{Shutdown, TcpStream, ToSocketAddrs},
+    sync::{Arc, Condvar, Mutex},
+    thread,
+    time::{Duration, Instant},
+};
+use symphonia::core::io::MediaSource;
+use url::Url;
+
+/// Maximum number of redirects to follow before giving up.
+const MAX_REDIRECTS: usize = 5;
+/// How long to wait on the network before failing.
+const TIMEOUT: Duration = Duration::from_secs(15);
+/// How much audio to read ahead of the decoder. The buffer only grows this large if the
+/// network is faster than playback, which keeps memory low on endless radio streams.
+const MAX_READ_AHEAD: usize = 4 * 1024 * 1024;
+/// Size of each read from the network.
+const READ_CHUNK_SIZE: usize = 64 * 1024;
+/// How much of a live stream to buffer before playing, both when it starts and after it
+/// runs dry. At 128 kbps, this covers about four seconds of network trouble.
+const LIVE_PREBUFFER: usize = 64 * 1024;
+/// How many times to try reconnecting a dropped connection before giving up.
+const RECONNECT_ATTEMPTS: u32 = 6;
+/// Delay before the first reconnection attempt, which doubles with each failed attempt.
+const RECONNECT_BACKOFF: Duration = Duration::from_millis(500);
+/// Longest delay between reconnection attempts.
+const MAX_RECONNECT_BACKOFF: Duration = Duration::from_secs(10);
+
+type HealthListener = Box<dyn Fn(StreamHealth) + Send>;
+type ThroughputListener = Box<dyn Fn(u64) + Send>;
+type TitleListener = Box<dyn Fn(String) + Send>;
+
+#[derive(Default)]
+struct HealthInner {
+    health: StreamHealth,
+    listener: Option<HealthListener>,
+    /// Most recent throughput measurement in bits per second.
+    throughput: Option<u64>,
+    throughput_listener: Option<ThroughputListener>,
+    /// Most recent song title announced by the station.
+    title: Option<String>,
+    title_listener: Option<TitleListener>,
+}
+
+/// Connection health of an [`HttpMediaSource`], which can be watched from other threads.
+#[derive(Clone, Default)]
+pub struct StreamHealthHandle {
+    inner: Arc<Mutex<HealthInner>>,
+}
+
+impl StreamHealthHandle {
+    /// The current connection health.
+    pub fn get(&self) -> StreamHealth {
+        self.inner.lock().unwrap().health
+    }
+
+    /// Calls the given listener whenever the connection health changes.
+    ///
+    /// The listener is called from the thread reading the stream, so it shouldn't block.
+    pub fn on_change(&self, listener: impl Fn(StreamHealth) + Send + 'static) {
+        self.inner.lock().unwrap().listener = Some(Box::new(listener));
+    }
+
+    /// Calls the given listener with the network throughput in bits per second each time
+    /// the stream finishes buffering.
+    ///
+    /// Audio is downloaded as fast as possible while buffering, so this is a rough measure
+    /// of how fast the connection is rather than how fast the stream is. The listener is
+    /// called right away if the stream has already been measured.
+    pub fn on_throughput(&self, listener: impl Fn(u64) + Send + 'static) {
+        let mut inner = self.inner.lock().unwrap();
+        if let Some(throughput) = inner.throughput {
+            listener(throughput);
+        }
+        inner.throughput_listener = Some(Box::new(listener));
+    }
+
+    /// Calls the given listener with the song title each time the station announces one.
+    ///
+    /// The listener is called right away if the station has already announced a title.
+    pub fn on_title(&self, listener: impl Fn(String) + Send + 'static) {
+        let mut inner = self.inner.lock().unwrap();
+        if let Some(title) = &inner.title {
+            listener(title.clone());
+        }
+        inner.title_listener = Some(Box::new(listener));
+    }
+
+    fn report_title(&self, title: String) {
+        let mut inner = self.inner.lock().unwrap();
+        if inner.title.as_ref() == Some(&title) {
+            return;
+        }
+        log::info!("stream title changed to {title:?}");
+        inner.title = Some(title.clone());
+        if let Some(listener) = &inner.title_listener {
+            listener(title);
+        }
+    }
+
+    fn report_throughput(&self, bytes: u64, elapsed: Duration) {
+        if elapsed.is_zero() {
+            return;
+        }
+        let bits_per_second = (bytes as f64 * 8.0 / elapsed.as_secs_f64()) as u64;
+        log::info!("measured stream throughput of {bits_per_second} bits per second");
+        let mut inner = self.inner.lock().unwrap();
+        inner.throughput = Some(bits_per_second);
+        if let Some(listener) = &inner.throughput_listener {
+            listener(bits_per_second);
+        }
+    }
+
+    fn set(&self, health: StreamHealth) {
+        let mut inner = self.inner.lock().unwrap();
+        if inner.health != health {
+            log::info!("stream health changed to {health:?}");
+            inner.health = health;
+            if let Some(listener) = &inner.listener {
+                listener(health);
+            }
+        }
+    }
+}
+
+/// An audio file or radio stream served over HTTP.
+///
+/// The response body is read ahead on a background thread so that network hiccups don't
+/// stall decoding. Seeking reconnects with a range request, so it's only supported if
+/// the server advertises byte range support and the length is known.
+///
+/// Dropped connections are retried with exponential backoff. Live streams (ones without
+/// a length) are reconnected from wherever the station is now, and files are resumed with
+/// a range request if the server supports it.
+pub struct HttpMediaSource {
+    url: Url,
+    content_type: Option<String>,
+    length: Option<u64>,
+    seekable: bool,
+    position: u64,
+    health: StreamHealthHandle,
+    reader: ReadAhead,
+}
+
+impl HttpMediaSource {
+    /// Connects to the given URL and starts reading the response.
+    pub fn open(url: &Url) -> io::Result<Self> {
+        let response = Response::request(url, 0)?;
+        let seekable = response.accepts_ranges && response.length.is_some();
+        let health = StreamHealthHandle::default();
+        Ok(Self {
+            url: response.url.clone(),
+            content_type: response.content_type.clone(),
+            length: response.length,
+            seekable,
+            position: 0,
+            reader: ReadAhead::start(response, 0, health.clone()),
+            health,
+        })
+    }
+
+    /// The media type given by the server, such as "audio/mpeg".
+    pub fn content_type(&self) -> Option<&str> {
+        self.content_type.as_deref()
+    }
+
+    /// Handle for watching the connection health.
+    pub fn health(&self) -> StreamHealthHandle {
+        self.health.clone()
+    }
+}
+
+impl Read for HttpMediaSource {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        let read = self.reader.read(buf)?;
+        self.position += read as u64;
+        Ok(read)
+    }
+}
+
+impl Seek for HttpMediaSource {
+    fn seek(&mut self, pos: SeekFrom) -> io::Result<u64> {
+        let target = match pos {
+            SeekFrom::Start(offset) => Some(offset),
+            SeekFrom::Current(offset) => self.position.checked_add_signed(offset),
+            SeekFrom::End(offset) => self
+                .length
+                .and_then(|length| length.checked_add_signed(offset)),
+        };
+        let target = target
+            .ok_or_else(|| io::Error::new(io::ErrorKind::InvalidInput, "invalid seek position"))?;
+        if target == self.position {
+            return Ok(target);
+        }
+        if !self.seekable {
+            return Err(io::Error::new(
+                io::ErrorKind::Unsupported,
+                "the server doesn't support seeking in this stream",
+            ));
+        }
+        log::info!("seeking HTTP stream to byte {target}");
+        let response = Response::request(&self.url, target)?;
+        if !response.partial {
+            return Err(io::Error::new(
+                io::ErrorKind::Unsupported,
+                "the server ignored the range request",
+            ));
+        }
+        self.reader = ReadAhead::start(response, target, self.health.clone());
+        self.position = target;
+        Ok(target)
+    }
+}
+
+impl MediaSource for HttpMediaSource {
+    fn is_seekable(&self) -> bool {
+        self.seekable
+    }
+
+    fn byte_len(&self) -> Option<u64> {
+        self.length
+    }
+}
+
+/// Headers and body of an HTTP response.
+struct Response {
+    /// URL after following redirects.
+    url: Url,
+    content_type: Option<String>,
+    /// Length of the whole resource, which differs from the body length for range requests.
+    length: Option<u64>,
+    accepts_ranges: bool,
+    /// True if the server responded to a range request with partial content.
+    partial: bool,
+    /// Number of audio bytes between each block of ICY metadata, if the server sends it.
+    metadata_interval: Option<usize>,
+    stream: TcpStream,
+    body: Body,
+}
+
+impl Response {
+    /// Requests the given URL, starting at the given byte offset, and follows redirects.
+    fn request(url: &Url, offset: u64) -> io::Result<Response> {
+        let mut url = url.clone();
+        for _ in 0..=MAX_REDIRECTS {
+            match Self::request_once(&url, offset)? {
+                Ok(response) => return Ok(response),
+                Err(location) => {
+                    log::info!("following redirect from {url} to {location}");
+                    url = url.join(&location).map_err(invalid_data)?;
+                }
+            }
+        }
+        Err(invalid_data(format!("too many redirects for {url}")))
+    }
+
+    /// Sends a single request, returning the redirect location if there was one.
+    fn request_once(url: &Url, offset: u64) -> io::Result<Result<Response, String>> {
+        if url.scheme() != "http" {
+            return Err(io::Error::new(
+                io::ErrorKind::Unsupported,
+                format!("unsupported URL scheme \"{}\"", url.scheme()),
+            ));
+        }
+        let host = url
+            .host_str()
+            .ok_or_else(|| invalid_data(format!("URL has no host: {url}")))?;
+        let port = url.port_or_known_default().unwrap_or(80);
+        let address = (host, port)
+            .to_socket_addrs()?
+            .next()
+            .ok_or_else(|| io::Error::new(io::ErrorKind::NotFound, "host not found"))?;
+        let mut stream = TcpStream::connect_timeout(&address, TIMEOUT)?;
+        stream.set_read_timeout(Some(TIMEOUT))?;
+        stream.set_write_timeout(Some(TIMEOUT))?;
+
+        let mut path = url.path().to_string();
+        if let Some(query) = url.query() {
+            path.push('?');
+            path.push_str(query);
+        }
+        let host_header = match url.port() {
+            Some(port) => format!("{host}:{port}"),
+            None => host.to_string(),
+        };
+        let mut request = format!(
+            "GET {path} HTTP/1.1\r\n\
+             Host: {host_header}\r\n\
+             User-Agent: millenium-player\r\n\
+             Accept: */*\r\n\
+             Connection: close\r\n"
+        );
+        if offset > 0 {
+            request.push_str(&format!("Range: bytes={offset}-\r\n"));
+        } else {
+            // Ask radio stations to send the song titles along with the audio
+            request.push_str("Icy-MetaData: 1\r\n");
+        }
+        request.push_str("\r\n");
+        stream.write_all(request.as_bytes())?;
+
+        let mut reader = BufReader::new(stream.try_clone()?);
+        let status = read_status(&mut reader)?;
+        let headers = read_headers(&mut reader)?;
+        let header = |name: &str| {
+            headers
+                .iter()
+                .find(|(key, _)| key.eq_ignore_ascii_case(name))
+                .map(|(_, value)| value.as_str())
+        };
+
+        if matches!(status, 301 | 302 | 303 | 307 | 308) {
+            let location =
+                header("location").ok_or_else(|| invalid_data("redirect without a location"))?;
+            return Ok(Err(location.to_string()));
+        }
+        if !(200..300).contains(&status) {
+            return Err(io::Error::new(
+                io::ErrorKind::Other,
+                format!("server responded with HTTP status {status}"),
+            ));
+        }
+
+        let partial = status == 206;
+        let content_length = header("content-length").and_then(|value| value.parse().ok());
+        let length = if partial {
+            // Content-Range: bytes 100-199/200
+            header("content-range")
+                .and_then(|value| value.rsplit('/').next())
+                .and_then(|total| total.parse().ok())
+        } else {
+            content_length
+        };
+        let chunked = header("transfer-encoding")
+            .map(|value| value.to_ascii_lowercase().contains("chunked"))
+            .unwrap_or(false);
+        let body = if chunked {
+            Body::Chunked {
+                reader,
+                remaining_in_chunk: 0,
+                done: false,
+            }
+        } else {
+            Body::Plain {
+                reader,
+                remaining: content_length,
+            }
+        };
+        Ok(Ok(Response {
+            url: url.clone(),
+            content_type: header("content-type")
+                .map(|value| value.split(';').next().unwrap_or(value).trim().to_string()),
+            length,
+            accepts_ranges: header("accept-ranges")
+                .map(|value| value.eq_ignore_ascii_case("bytes"))
+                .unwrap_or(false),
+            partial,
+            metadata_interval: header("icy-metaint")
+                .and_then(|value| value.parse().ok())
+                .filter(|&interval| interval > 0),
+            stream,
+            body,
+        }))
+    }
+}
+
+fn invalid_data(message: impl ToString) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, message.to_string())
+}
+
+fn read_line(reader: &mut impl BufRead) -> io::Result<String> {
+    let mut line = String::new();
+    if reader.read_line(&mut line)? == 0 {
+        return Err(io::ErrorKind::UnexpectedEof.into());
+    }
+    Ok(line.trim_end_matches(['\r', '\n']).to_string())
+}
+
+/// Reads the status line. SHOUTcast servers respond with "ICY 200 OK" rather than HTTP.
+fn read_status(reader: &mut impl BufRead) -> io::Result<u16> {
+    let line = read_line(reader)?;
+    let mut parts = line.split_whitespace();
+    match (parts.next(), parts.next()) {
+        (Some(protocol), Some(status)) if protocol.starts_with("HTTP/") || protocol == "ICY" => {
+            status.parse().map_err(invalid_data)
+        }
+        _ => Err(invalid_data(format!("invalid HTTP status line: {line}"))),
+    }
+}
+
+fn read_headers(reader: &mut impl BufRead) -> io::Result<Vec<(String, String)>> {
+    let mut headers = Vec::new();
+    loop {
+        let line = read_line(reader)?;
+        if line.is_empty() {
+            return Ok(headers);
+        }
+        if let Some((key, value)) = line.split_once(':') {
+            headers.push((key.trim().to_string(), value.trim().to_string()));
+        }
+    }
+}
+
+enum Body {
+    Plain {
+        reader: BufReader<TcpStream>,
+        remaining: Option<u64>,
+    },
+    Chunked {
+        reader: BufReader<TcpStream>,
+        remaining_in_chunk: u64,
+        done: bool,
+    },
+}
+
+impl Read for Body {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        match self {
+            Body::Plain { reader, remaining } => {
+                let limit = match *remaining {
+                    Some(0) => return Ok(0),
+                    Some(remaining) => buf.len().min(remaining as usize),
+                    None => buf.len(),
+                };
+                let read = reader.read(&mut buf[..limit])?;
+                if let Some(remaining) = remaining {
+                    if read == 0 && limit > 0 {
+                        // The connection closed before the whole body was sent
+                        return Err(io::ErrorKind::UnexpectedEof.into());
+                    }
+                    *remaining -= read as u64;
+                }
+                Ok(read)
+            }
+            Body::Chunked {
+                reader,
+                remaining_in_chunk,
+                done,
+            } => {
+                if *done {
+                    return Ok(0);
+                }
+                if *remaining_in_chunk == 0 {
+                    let size_line = read_line(reader)?;
+                    let size = size_line.split(';').next().unwrap_or_default().trim();
+                    *remaining_in_chunk = u64::from_str_radix(size, 16).map_err(invalid_data)?;
+                    if *remaining_in_chunk == 0 {
+                        *done = true;
+                        return Ok(0);
+                    }
+                }
+                let limit = buf.len().min(*remaining_in_chunk as usize);
+                let read = reader.read(&mut buf[..limit])?;
+                if read == 0 {
+                    return Err(io::ErrorKind::UnexpectedEof.into());
+                }
+                *remaining_in_chunk -= read as u64;
+                if *remaining_in_chunk == 0 {
+                    // Each chunk is followed by a line break
+                    read_line(reader)?;
+                }
+                Ok(read)
+            }
+        }
+    }
+}
+
+/// Removes the metadata that SHOUTcast and Icecast servers interleave with the audio.
+///
+/// Every `interval` bytes of audio are followed by a length byte, and then that many
+/// 16 byte blocks of metadata, such as `StreamTitle='Artist - Song';`.
+struct IcyMetadata {
+    interval: usize,
+    /// Audio bytes left before the next length byte.
+    audio_remaining: usize,
+    /// Metadata bytes left in the current block, once its length has been read.
+    metadata_remaining: Option<usize>,
+    metadata: Vec<u8>,
+}
+
+impl IcyMetadata {
+    fn new(interval: usize) -> Self {
+        Self {
+            interval,
+            audio_remaining: interval,
+            metadata_remaining: None,
+            metadata: Vec::new(),
+        }
+    }
+
+    /// Removes the metadata from the given data in place. Returns how many bytes of audio
+    /// are left at the start of the data, and the last song title that was found.
+    fn strip(&mut self, data: &mut [u8]) -> (usize, Option<String>) {
+        let (mut audio, mut read, mut title) = (0, 0, None);
+        while read < data.len() {
+            let available = data.len() - read;
+            if self.audio_remaining > 0 {
+                let length = self.audio_remaining.min(available);
+                data.copy_within(read..read + length, audio);
+                audio += length;
+                read += length;
+                self.audio_remaining -= length;
+            } else if let Some(remaining) = self.metadata_remaining {
+                let length = remaining.min(available);
+                self.metadata.extend(&data[read..read + length]);
+                read += length;
+                if length == remaining {
+                    title = parse_stream_title(&self.metadata).or(title);
+                    self.metadata.clear();
+                    self.metadata_remaining = None;
+                    self.audio_remaining = self.interval;
+                } else {
+                    self.metadata_remaining = Some(remaining - length);
+                }
+            } else {
+                let length = data[read] as usize * 16;
+                read += 1;
+                if length == 0 {
+                    self.audio_remaining = self.interval;
+                } else {
+                    self.metadata_remaining = Some(length);
+                }
+            }
+        }
+        (audio, title)
+    }
+}
+
+/// Finds the song title in a block of ICY metadata. Most stations send UTF-8,
+/// but older ones send Latin-1, which is decoded as Windows-1252.
+fn parse_stream_title(metadata: &[u8]) -> Option<String> {
+    let metadata = match std::str::from_utf8(metadata) {
+        Ok(text) => text.to_string(),
+        Err(_) => encoding_rs::WINDOWS_1252.decode(metadata).0.into_owned(),
+    };
+    let start = metadata.find("StreamTitle='")? + "StreamTitle='".len();
+    let rest = &metadata[start..];
+    let end = rest.find("';").unwrap_or(rest.trim_end_matches('\0').len());
+    let title = rest[..end].trim();
+    (!title.is_empty()).then(|| title.to_string())
+}
+
+#[derive(Default)]
+struct ReadAheadState {
+    buffer: VecDeque<u8>,
+    finished: bool,
+    error: Option<io::Error>,
+    cancelled: bool,
+    /// Reads wait until the buffer holds [`LIVE_PREBUFFER`] bytes while this is set.
+    buffering: bool,
+    /// When buffering started, and how much has been received since, to measure throughput.
+    buffering_since: Option<(Instant, u64)>,
+}
+
+struct Shared {
+    state: Mutex<ReadAheadState>,
+    changed: Condvar,
+    /// The current connection, so that it can be shut down to cancel a blocked read.
+    stream: Mutex<TcpStream>,
+}
+
+/// How to reconnect to the stream if the connection drops.
+struct Reconnect {
+    url: Url,
+    /// Live streams have no length, and are resumed from wherever they are now.
+    live: bool,
+    /// Byte offset of the next byte to read from the resource.
+    offset: u64,
+}
+
+impl Reconnect {
+    /// Reconnects with exponential backoff, returning `None` if cancelled.
+    fn run(&self, shared: &Shared, health: &StreamHealthHandle) -> Option<io::Result<Response>> {
+        let mut delay = RECONNECT_BACKOFF;
+        let mut last_error = None;
+        for attempt in 1..=RECONNECT_ATTEMPTS {
+            health.set(StreamHealth::Reconnecting { attempt });
+            let state = shared.state.lock().unwrap();
+            let (state, _) = shared
+                .changed
+                .wait_timeout_while(state, delay, |state| !state.cancelled)
+                .unwrap();
+            if state.cancelled {
+                return None;
+            }
+            drop(state);
+            delay = (delay * 2).min(MAX_RECONNECT_BACKOFF);
+
+            let offset = if self.live { 0 } else { self.offset };
+            log::info!(
+                "reconnecting to {} at byte {offset} (attempt {attempt})",
+                self.url
+            );
+            match Response::request(&self.url, offset) {
+                Ok(response) if offset > 0 && !response.partial => {
+                    last_error = Some(io::Error::new(
+                        io::ErrorKind::Unsupported,
+                        "the server ignored the range request",
+                    ));
+                    break;
+                }
+                Ok(response) => return Some(Ok(response)),
+                Err(err) => {
+                    log::warn!("failed to reconnect to {}: {err}", self.url);
+                    last_error = Some(err);
+                }
+            }
+        }
+        Some(Err(last_error.expect("at least one attempt was made")))
+    }
+}
+
+/// Reads a response body on a background thread into a buffer that grows up to
+/// [`MAX_READ_AHEAD`] bytes.
+struct ReadAhead {
+    shared: Arc<Shared>,
+    health: StreamHealthHandle,
+    prebuffer: usize,
+}
+
+impl ReadAhead {
+    /// Starts reading the given response, which starts at `offset` bytes into the resource.
+    fn start(response: Response, offset: u64, health: StreamHealthHandle) -> Self {
+        let live = response.length.is_none();
+        let prebuffer = if live { LIVE_PREBUFFER } else { 0 };
+        health.set(if live {
+            StreamHealth::Buffering
+        } else {
+            StreamHealth::Connected
+        });
+        let Response {
+            url,
+            accepts_ranges,
+            metadata_interval,
+            stream,
+            mut body,
+            ..
+        } = response;
+        let mut icy = metadata_interval.map(IcyMetadata::new);
+        let shared = Arc::new(Shared {
+            state: Mutex::new(ReadAheadState {
+                buffering: live,
+                buffering_since: live.then(|| (Instant::now(), 0)),
+                ..Default::default()
+            }),
+            changed: Condvar::new(),
+            stream: Mutex::new(stream),
+        });
+        let mut reconnect = Reconnect { url, live, offset };
+        let (thread_shared, thread_health) = (shared.clone(), health.clone());
+        let spawned = thread::Builder::new()
+            .name("http-read-ahead".into())
+            .spawn(move || {
+                let (shared, health) = (thread_shared, thread_health);
+                let mut chunk = vec![0; READ_CHUNK_SIZE];
+                loop {
+                    let result = match body.read(&mut chunk) {
+                        Err(err) if err.kind() == io::ErrorKind::Interrupted => continue,
+                        // Live streams never end on their own, so the connection dropped
+                        Ok(0) if live => Err(io::ErrorKind::UnexpectedEof.into()),
+                        Ok(read) if read > 0 => match icy.as_mut() {
+                            Some(icy) => {
+                                let (audio, title) = icy.strip(&mut chunk[..read]);
+                                if let Some(title) = title {
+                                    health.report_title(title);
+                                }
+                                if audio == 0 {
+                                    continue;
+                                }
+                                Ok(audio)
+                            }
+                            None => Ok(read),
+                        },
+                        result => result,
+                    };
+                    let result = match result {
+                        Err(err) if live || accepts_ranges => {
+                            if shared.state.lock().unwrap().cancelled {
+                                break;
+                            }
+                            log::warn!("lost connection to {}: {err}", reconnect.url);
+                            match reconnect.run(&shared, &health) {
+                                None => break,
+                                Some(Ok(response)) => {
+                                    let state = shared.state.lock().unwrap();
+                                    if state.cancelled {
+                                        let _ = response.stream.shutdown(Shutdown::Both);
+                                        break;
+                                    }
+                                    *shared.stream.lock().unwrap() = response.stream;
+                                    icy = response.metadata_interval.map(IcyMetadata::new);
+                                    body = response.body;
+                                    let buffering = state.buffering;
+                                    drop(state);
+                                    health.set(if buffering {
+                                        StreamHealth::Buffering
+                                    } else {
+                                        StreamHealth::Connected
+                                    });
+                                    continue;
+                                }
+                                Some(Err(err)) => Err(err),
+                            }
+                        }
+                        result => result,
+                    };
+                    let mut state = shared.state.lock().unwrap();
+                    match result {
+                        Ok(0) => state.finished = true,
+                        Ok(read) => {
+                            while state.buffer.len() >= MAX_READ_AHEAD && !state.cancelled {
+                                state = shared.changed.wait(state).unwrap();
+                            }
+                            state.buffer.extend(&chunk[..read]);
+                            reconnect.offset += read as u64;
+                            if let Some((_, received)) = state.buffering_since.as_mut() {
+                                *received += read as u64;
+                            }
+                            if state.buffering && state.buffer.len() >= prebuffer {
+                                state.buffering = false;
+                                if let Some((since, received)) = state.buffering_since.take() {
+                                    health.report_throughput(received, since.elapsed());
+                                }
+                                health.set(StreamHealth::Connected);
+                            }
+                        }
+                        Err(err) => {
+                            health.set(StreamHealth::Failed);
+                            state.error = Some(err);
+                        }
+                    }
+                    let stop = state.finished || state.error.is_some() || state.cancelled;
+                    if stop {
+                        state.buffering = false;
+                    }
+                    drop(state);
+                    shared.changed.notify_all();
+                    if stop {
+                        break;
+                    }
+                }
+            });
+        if let Err(err) = spawned {
+            shared.state.lock().unwrap().error = Some(err);
+        }
+        Self {
+            shared,
+            health,
+            prebuffer,
+        }
+    }
+
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        let mut state = self.shared.state.lock().unwrap();
+        let ended = |state: &ReadAheadState| state.finished || state.error.is_some();
+        // Running dry means the network can't keep up, so build up a cushion again
+        if self.prebuffer > 0 && state.buffer.is_empty() && !state.buffering && !ended(&state) {
+            log::info!("stream ran dry, buffering");
+            state.buffering = true;
+            state.buffering_since = Some((Instant::now(), 0));
+            if self.health.get() == StreamHealth::Connected {
+                self.health.set(StreamHealth::Buffering);
+            }
+        }
+        while (state.buffer.is_empty() || state.buffering) && !ended(&state) {
+            state = self.shared.changed.wait(state).unwrap();
+        }
+        if state.buffer.is_empty() {
+            if let Some(err) = state.error.take() {
+                // Later reads see the end of the stream rather than the same error again
+                state.finished = true;
+                return Err(err);
+            }
+            return Ok(0);
+        }
+        let read = buf.len().min(state.buffer.len());
+        for (into, from) in buf.iter_mut().zip(state.buffer.drain(..read)) {
+            *into = from;
+        }
+        drop(state);
+        self.shared.changed.notify_all();
+        Ok(read)
+    }
+}
+
+impl Drop for ReadAhead {
+    fn drop(&mut self) {
+        self.shared.state.lock().unwrap().cancelled = true;
+        self.shared.changed.notify_all();
+        // Unblock the read-ahead thread if it's waiting on the network
+        let _ = self.shared.stream.lock().unwrap().shutdown(Shutdown::Both);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::net::TcpListener;
+
+    /// Serves `data` to every connection, honoring range requests if `ranges` is set.
+    fn serve(data: Vec<u8>, ranges: bool) -> Url {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let port = listener.local_addr().unwrap().port();
+        thread::spawn(move || {
+            for stream in listener.incoming() {
+                let mut stream = stream.unwrap();
+                let mut reader = BufReader::new(stream.try_clone().unwrap());
+                let request = read_line(&mut reader).unwrap();
+                let headers = read_headers(&mut reader).unwrap();
+                if request.starts_with("GET /redirect ") {
+                    write!(
+                        stream,
+                        "HTTP/1.1 302 Found\r\nLocation: /audio.mp3\r\nContent-Length: 0\r\n\r\n"
+                    )
+                    .unwrap();
+                    continue;
+                }
+                let offset: usize = headers
+                    .iter()
+                    .find(|(key, _)| key == "Range")
+                    .and_then(|(_, value)| value.strip_prefix("bytes=")?.strip_suffix('-'))
+                    .and_then(|offset| offset.parse().ok())
+                    .filter(|_| ranges)
+                    .unwrap_or(0);
+                let body = &data[offset..];
+                let status = if offset > 0 {
+                    format!(
+                        "206 Partial Content\r\nContent-Range: bytes {offset}-{}/{}",
+                        data.len() - 1,
+                        data.len()
+                    )
+                } else {
+                    "200 OK".into()
+                };
+                let accept_ranges = if ranges { "bytes" } else { "none" };
+                write!(
+                    stream,
+                    "HTTP/1.1 {status}\r\nContent-Type: audio/mpeg; charset=binary\r\n\
+                     Accept-Ranges: {accept_ranges}\r\nContent-Length: {}\r\n\r\n",
+                    body.len()
+                )
+                .unwrap();
+                let _ = stream.write_all(body);
+            }
+        });
+        Url::parse(&format!("http://127.0.0.1:{port}/redirect")).unwrap()
+    }
+
+    fn test_data() -> Vec<u8> {
+        (0..200_000u32).map(|i| i as u8).collect()
+    }
+
+    #[test]
+    fn read_and_seek() {
+        let data = test_data();
+        let mut source = HttpMediaSource::open(&serve(data.clone(), true)).unwrap();
+        assert_eq!(Some("audio/mpeg"), source.content_type());
+        assert_eq!(Some(data.len() as u64), source.byte_len());
+        assert!(source.is_seekable());
+
+        let mut start = [0; 10];
+        source.read_exact(&mut start).unwrap();
+        assert_eq!(&data[..10], &start);
+
+        assert_eq!(150_000, source.seek(SeekFrom::Start(150_000)).unwrap());
+        let mut rest = Vec::new();
+        source.read_to_end(&mut rest).unwrap();
+        assert_eq!(&data[150_000..], &rest[..]);
+    }
+
+    #[test]
+    fn seek_unsupported() {
+        let data = test_data();
+        let mut source = HttpMediaSource::open(&serve(data.clone(), false)).unwrap();
+        assert!(!source.is_seekable());
+        assert_eq!(
+            io::ErrorKind::Unsupported,
+            source.seek(SeekFrom::Start(10)).unwrap_err().kind()
+        );
+
+        let mut all = Vec::new();
+        source.read_to_end(&mut all).unwrap();
+        assert_eq!(data, all);
+    }
+
+    #[test]
+    fn chunked_body() {
+        let bytes = b"5\r\nhello\r\n7; ext=1\r\n, world\r\n0\r\n\r\n";
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let address = listener.local_addr().unwrap();
+        let client = TcpStream::connect(address).unwrap();
+        let (mut server, _) = listener.accept().unwrap();
+        server.write_all(bytes).unwrap();
+        drop(server);
+
+        let mut body = Body::Chunked {
+            reader: BufReader::new(client),
+            remaining_in_chunk: 0,
+            done: false,
+        };
+        let mut text = String::new();
+        body.read_to_string(&mut text).unwrap();
+        assert_eq!("hello, world", text);
+    }
+
+    /// Serves `data`, but closes the first connection after `cut_at` bytes of the body.
+    ///
+    /// Live streams are served without a length, and files honor range requests.
+    fn serve_flaky(data: Vec<u8>, live: bool, cut_at: usize) -> Url {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let port = listener.local_addr().unwrap().port();
+        thread::spawn(move || {
+            for (connection, stream) in listener.incoming().enumerate() {
+                let mut stream = stream.unwrap();
+                let mut reader = BufReader::new(stream.try_clone().unwrap());
+                read_line(&mut reader).unwrap();
+                let headers = read_headers(&mut reader).unwrap();
+                let offset: usize = headers
+                    .iter()
+                    .find(|(key, _)| key == "Range")
+                    .and_then(|(_, value)| value.strip_prefix("bytes=")?.strip_suffix('-'))
+                    .and_then(|offset| offset.parse().ok())
+                    .unwrap_or(0);
+                let head = match (live, offset) {
+                    (true, _) => "HTTP/1.1 200 OK\r\n".to_string(),
+                    (false, 0) => format!(
+                        "HTTP/1.1 200 OK\r\nAccept-Ranges: bytes\r\nContent-Length: {}\r\n",
+                        data.len()
+                    ),
+                    (false, _) => format!(
+                        "HTTP/1.1 206 Partial Content\r\nAccept-Ranges: bytes\r\n\
+                         Content-Range: bytes {offset}-{}/{}\r\nContent-Length: {}\r\n",
+                        data.len() - 1,
+                        data.len(),
+                        data.len() - offset
+                    ),
+                };
+                write!(stream, "{head}\r\n").unwrap();
+                let end = if connection == 0 { cut_at } else { data.len() };
+                let _ = stream.write_all(&data[offset..end]);
+            }
+        });
+        Url::parse(&format!("http://127.0.0.1:{port}/stream")).unwrap()
+    }
+
+    #[test]
+    fn reconnect_live_stream() {
+        let data = test_data();
+        let mut source = HttpMediaSource::open(&serve_flaky(data.clone(), true, 100_000)).unwrap();
+        let changes = Arc::new(Mutex::new(Vec::new()));
+        let recorded = changes.clone();
+        source
+            .health()
+            .on_change(move |health| recorded.lock().unwrap().push(health));
+        let throughput = Arc::new(Mutex::new(None));
+        let measured = throughput.clone();
+        source
+            .health()
+            .on_throughput(move |bits| *measured.lock().unwrap() = Some(bits));
+
+        // The station is picked up from wherever it is after reconnecting
+        let mut received = vec![0; 150_000];
+        source.read_exact(&mut received).unwrap();
+        assert_eq!(&data[..100_000], &received[..100_000]);
+        assert_eq!(&data[..50_000], &received[100_000..]);
+        // The stream is played again once enough of it has been buffered
+        let changes = changes.lock().unwrap();
+        let reconnecting = changes
+            .iter()
+            .position(|&health| health == StreamHealth::Reconnecting { attempt: 1 })
+            .unwrap();
+        assert!(changes[reconnecting..].contains(&StreamHealth::Connected));
+        assert!(throughput.lock().unwrap().is_some());
+    }
+
+    #[test]
+    fn resume_file_after_dropped_connection() {
+        let data = test_data();
+        let mut source = HttpMediaSource::open(&serve_flaky(data.clone(), false, 70_000)).unwrap();
+        let mut all = Vec::new();
+        source.read_to_end(&mut all).unwrap();
+        assert_eq!(data, all);
+        assert_eq!(StreamHealth::Connected, source.health().get());
+    }
+
+    #[test]
+    fn strip_icy_metadata() {
+        let title = b"StreamTitle='Artist - Song';StreamUrl='';";
+        let mut block = vec![(title.len() / 16 + 1) as u8];
+        block.extend(title);
+        block.resize(1 + (title.len() / 16 + 1) * 16, 0);
+
+        let audio: Vec<u8> = (0..25u8).collect();
+        let mut stream = Vec::new();
+        for (index, part) in audio.chunks(10).enumerate() {
+            stream.extend(part);
+            if part.len() == 10 {
+                // Only the first block has a title, and the others are empty
+                stream.extend(if index == 0 { &block[..] } else { &[0][..] });
+            }
+        }
+
+        // Split the stream at awkward places, including in the middle of the metadata
+        let mut icy = IcyMetadata::new(10);
+        let (mut stripped, mut titles) = (Vec::<u8>::new(), Vec::new());
+        for chunk in stream.chunks(7) {
+            let mut chunk = chunk.to_vec();
+            let (length, title) = icy.strip(&mut chunk);
+            stripped.extend(&chunk[..length]);
+            titles.extend(title);
+        }
+        assert_eq!(audio, stripped);
+        assert_eq!(vec!["Artist - Song".to_string()], titles);
+    }
+
+    #[test]
+    fn stream_title() {
+        assert_eq!(
+            Some("Café - Olé".into()),
+            parse_stream_title(b"StreamTitle='Caf\xE9 - Ol\xE9';\0\0")
+        );
+        assert_eq!(
+            Some("It's".into()),
+            parse_stream_title(b"StreamTitle='It's';StreamUrl='';")
+        );
+        assert_eq!(None, parse_stream_title(b"StreamTitle='';\0"));
+        assert_eq!(None, parse_stream_title(b"StreamUrl='x';"));
+    }
+
+    #[test]
+    fn https_not_supported() {
+        let url = Url::parse("https://example.com/audio.mp3").unwrap();
+        assert_eq!(
+            io::ErrorKind::Unsupported,
+            HttpMediaSource::open(&url).err().unwrap().kind()
+        );
+    }
+}
@@ -0,0 +1,193 @@
+// This file is part of Millenium Player.
+// Copyright (C) 2023 John DiSanti.
+//
+// Millenium Player is free software: you can redistribute it and/or modify it under the terms of
+// the GNU General Public License as published by the Free Software Foundation, either version 3 of
+// the License, or (at your option) any later version.
+//
+// Millenium Player is distributed in the hope that it will be useful, but WITHOUT ANY WARRANTY;
+// without even the implied warranty of MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE. See
+// the GNU General Public License for more details.
+//
+// You should have received a copy of the GNU General Public License along with Millenium Player.
+// If not, see <https://www.gnu.org/licenses/>.
+
+use crate::{
+    audio::{
+        analysis_cache::AnalysisCache,
+        source::{
+            read_metadata, AudioDecoderSource, AudioSourceError, PreferredFormat, SourceBuffer,
+        },
+    },
+    location::Location,
+};
+use std::{
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        mpsc::{self, Receiver},
+        Arc,
+    },
+    thread::{self, JoinHandle},
+};
+
+/// Largest absolute sample value in a buffer, where 1.0 is full scale.
+fn buffer_peak(buffer: &SourceBuffer) -> f32 {
+    (0..buffer.channel_count() as usize)
+        .flat_map(|channel| buffer.channel(channel))
+        .fold(0.0, |peak, sample| peak.max(sample.abs()))
+}
+
+/// Finds the largest absolute sample value in a location, where 1.0 is full scale.
+///
+/// The ReplayGain peak tag is used when there is one, since that avoids decoding the whole
+/// file. Decoding stops early if `cancel` gets set.
+pub fn scan_peak(location: &Location, cancel: &AtomicBool) -> Result<f32, AudioSourceError> {
+    let tagged = read_metadata(location)?.and_then(|metadata| metadata.replay_gain.track_peak);
+    if let Some(peak) = tagged.filter(|peak| *peak > 0.0) {
+        return Ok(peak);
+    }
+    let mut source = AudioDecoderSource::new(location.clone(), PreferredFormat::new(44100, 2))?;
+    let mut peak = 0f32;
+    while let Some(chunk) = source.next_chunk()? {
+        if cancel.load(Ordering::Relaxed) {
+            break;
+        }
+        peak = peak.max(buffer_peak(chunk));
+    }
+    Ok(peak)
+}
+
+/// Linear gain that brings the loudest of the given peaks to `target_db` (in dBFS).
+///
+/// Silence and empty playlists are left alone.
+pub fn playlist_peak_gain(peaks: impl IntoIterator<Item = f32>, target_db: f32) -> f32 {
+    let loudest = peaks.into_iter().fold(0f32, f32::max);
+    if loudest > 0.0 {
+        10f32.powf(target_db / 20.0) / loudest
+    } else {
+        1.0
+    }
+}
+
+/// Scans the peaks of a list of locations on a background thread.
+///
+/// Dropping the scan cancels it.
+pub struct PeakScan {
+    peak: Receiver<f32>,
+    cancel: Arc<AtomicBool>,
+    thread: Option<JoinHandle<()>>,
+}
+
+impl PeakScan {
+    /// Starts scanning. Locations that fail to scan are left out of the result.
+    ///
+    /// Peaks found in the cache aren't scanned again, and new ones are added to it.
+    pub fn start(locations: Vec<Location>, cache: AnalysisCache) -> Self {
+        let (peak_tx, peak) = mpsc::channel();
+        let cancel = Arc::new(AtomicBool::new(false));
+        let spawned = thread::Builder::new().name("peak-scan".into()).spawn({
+            let cancel = cancel.clone();
+            move || {
+                log::info!("scanning the peaks of {} locations", locations.len());
+                let mut loudest = 0f32;
+                for location in &locations {
+                    if cancel.load(Ordering::Relaxed) {
+                        return;
+                    }
+                    if let Some(peak) = cache.get(location).and_then(|analysis| analysis.peak) {
+                        loudest = loudest.max(peak);
+                        continue;
+                    }
+                    match scan_peak(location, &cancel) {
+                        // A cancelled scan stops partway through the file
+                        Ok(_) if cancel.load(Ordering::Relaxed) => return,
+                        Ok(peak) => {
+                            cache.update(location, |analysis| analysis.peak = Some(peak));
+                            loudest = loudest.max(peak);
+                        }
+                        Err(err) => log::warn!("failed to scan the peak of {location}: {err}"),
+                    }
+                }
+                if let Err(err) = cache.save() {
+                    log::warn!("{err}");
+                }
+                let _ = peak_tx.send(loudest);
+            }
+        });
+        let thread = spawned
+            .map_err(|err| log::error!("failed to start the peak scan thread: {err}"))
+            .ok();
+        Self {
+            peak,
+            cancel,
+            thread,
+        }
+    }
+
+    /// Returns the loudest peak once the scan has finished.
+    pub fn try_finish(&self) -> Option<f32> {
+        self.peak.try_recv().ok()
+    }
+
+    /// Cancels the scan and waits for its thread to exit.
+    pub fn stop(mut self) {
+        self.cancel.store(true, Ordering::Relaxed);
+        if let Some(thread) = self.thread.take() {
+            let _ = thread.join();
+        }
+    }
+}
+
+impl Drop for PeakScan {
+    fn drop(&mut self) {
+        self.cancel.store(true, Ordering::Relaxed);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::time::{Duration, Instant};
+
+    #[test]
+    fn gain_brings_loudest_peak_to_target() {
+        let gain = playlist_peak_gain([0.25, 0.5, 0.1], 0.0);
+        assert!((gain - 2.0).abs() < 0.001, "unexpected gain: {gain}");
+
+        let gain = playlist_peak_gain([0.5], -6.0);
+        assert!((gain - 1.0).abs() < 0.01, "unexpected gain: {gain}");
+
+        assert_eq!(1.0, playlist_peak_gain([], -1.0));
+        assert_eq!(1.0, playlist_peak_gain([0.0], -1.0));
+    }
+
+    #[test]
+    fn scan_test_files() {
+        let cache = AnalysisCache::in_memory();
+        let scan = PeakScan::start(
+            vec![
+                Location::path(
+                    "../test-data/melodic_a_minor/melodic_a_minor_2chan_44100hz_11s.ogg",
+                ),
+                Location::path("../test-data/does-not-exist.ogg"),
+            ],
+            cache.clone(),
+        );
+        let started = Instant::now();
+        let peak = loop {
+            if let Some(peak) = scan.try_finish() {
+                break peak;
+            }
+            assert!(
+                started.elapsed() < Duration::from_secs(30),
+                "scan took too long"
+            );
+            thread::sleep(Duration::from_millis(10));
+        };
+        assert!(peak > 0.0 && peak <= 1.5, "unexpected peak: {peak}");
+        let cached = cache.get(&Location::path(
+            "../test-data/melodic_a_minor/melodic_a_minor_2chan_44100hz_11s.ogg",
+        ));
+        assert_eq!(Some(peak), cached.and_then(|analysis| analysis.peak));
+    }
+}
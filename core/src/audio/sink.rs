@@ -13,6 +13,7 @@
 // If not, see <https://www.gnu.org/licenses/>.
 
 use super::{
+    channel_map::ChannelMap,
     device::{AudioDeviceMessage, AudioDeviceMessageChannel},
     source::SourceBuffer,
     ChannelCount, SampleRate,
@@ -22,7 +23,7 @@ use millenium_post_office::broadcast::{BroadcastSubscription, Broadcaster};
 use rubato::{FftFixedInOut, Resampler};
 use std::{
     any::Any,
-    cell::RefCell,
+    cell::{Cell, RefCell},
     ops::RangeBounds,
     sync::{Arc, Mutex},
     time::Duration,
@@ -30,6 +31,8 @@ use std::{
 
 const DESIRED_CHUNK_SIZE_FRAMES: usize = 2048;
 const DESIRED_QUEUE_LENGTH: Duration = Duration::from_millis(500);
+/// How long it takes to crossfade between processed and unprocessed audio when bypassing.
+const BYPASS_RAMP: Duration = Duration::from_millis(50);
 
 struct ResampleBuffers {
     input: SourceBuffer,
@@ -49,6 +52,15 @@ pub struct Sink {
     input_buffer: Arc<Mutex<SourceBuffer>>,
     output_buffer: Arc<Mutex<BoxAudioBuffer>>,
     subscription: BroadcastSubscription<AudioDeviceMessage>,
+    gain: Cell<f32>,
+    /// When set, processing such as the normalization gain is skipped.
+    bypass: Cell<bool>,
+    /// How far the crossfade to bypassed audio has gone, from 0 (processed) to 1 (bypassed).
+    bypass_mix: Cell<f32>,
+    /// Routes the remixed stereo audio to the output device's channels when set.
+    channel_map: Option<ChannelMap>,
+    /// Pad the final partial chunk with silence when flushing, even when not resampling.
+    pad_final_chunk: bool,
 }
 
 impl Sink {
@@ -93,9 +105,34 @@ impl Sink {
             ))),
             output_buffer,
             subscription,
+            gain: Cell::new(1.0),
+            bypass: Cell::new(false),
+            bypass_mix: Cell::new(0.0),
+            channel_map: None,
+            pad_final_chunk: true,
         }
     }
 
+    /// Routes the audio to the output device's channels with the given map after remixing.
+    ///
+    /// The map must have an entry for every output channel.
+    pub fn with_channel_map(mut self, channel_map: Option<ChannelMap>) -> Self {
+        debug_assert!(channel_map
+            .as_ref()
+            .map(|map| map.device_channels() == self.output_channels)
+            .unwrap_or(true));
+        self.channel_map = channel_map;
+        self
+    }
+
+    /// Sets whether the final partial chunk is padded with silence when flushing.
+    ///
+    /// The resampler only works on whole chunks, so it's always padded when resampling.
+    pub fn with_final_chunk_padding(mut self, pad_final_chunk: bool) -> Self {
+        self.pad_final_chunk = pad_final_chunk;
+        self
+    }
+
     /// The expected sample rate of the input.
     pub fn input_sample_rate(&self) -> SampleRate {
         self.input_sample_rate
@@ -106,6 +143,32 @@ impl Sink {
         self.input_channels
     }
 
+    /// Number of frames waiting to be sent to the audio device.
+    pub fn buffered_frames(&self) -> usize {
+        self.input_buffer.lock().unwrap().frame_count()
+    }
+
+    /// Number of samples in the audio device's output buffer.
+    pub fn output_samples(&self) -> usize {
+        self.output_buffer.lock().unwrap().len()
+    }
+
+    /// Sets the gain applied to audio as it's queued.
+    ///
+    /// This is applied at queue time rather than output time so that a change in gain
+    /// lines up exactly with the start of the next track during gapless playback.
+    pub fn set_gain(&self, gain: f32) {
+        self.gain.set(gain);
+    }
+
+    /// Bypasses all processing so that processed and unprocessed audio can be compared.
+    ///
+    /// Like the gain, this is applied at queue time, and the switch is crossfaded
+    /// so that it doesn't click.
+    pub fn set_bypass(&self, bypass: bool) {
+        self.bypass.set(bypass);
+    }
+
     /// True if more audio data is needed to feed the audio device.
     pub fn needs_more_chunks(&self) -> bool {
         self.input_buffer.lock().unwrap().frame_count() < self.desired_input_frames
@@ -125,13 +188,25 @@ impl Sink {
         let resampler_borrow = self.resampler.as_ref().map(|r| r.borrow_mut());
 
         input.make_empty_with_channels(original.channel_count());
-        original.drain_into(self.chunk_size_frames, input);
-
-        input.remix_in_place(self.output_channels);
-        let mut final_buffer = &input;
-        if let Some(mut resampler) = resampler_borrow {
-            input.resample_into(output, self.output_sample_rate, &mut *resampler);
-            final_buffer = &output;
+        // The final chunk can be short if it wasn't padded
+        let frames = original.frame_count().min(self.chunk_size_frames);
+        original.drain_into(frames, input);
+
+        // Channel maps route stereo, so the audio is mixed down to stereo before it's routed
+        let mix_channels = match self.channel_map {
+            Some(_) => 2,
+            None => self.output_channels,
+        };
+        input.remix_in_place(mix_channels);
+        let final_buffer = match resampler_borrow {
+            Some(mut resampler) => {
+                input.resample_into(output, self.output_sample_rate, &mut *resampler);
+                output
+            }
+            None => input,
+        };
+        if let Some(channel_map) = &self.channel_map {
+            final_buffer.route_in_place(channel_map);
         }
 
         final_output.extend(final_buffer);
@@ -161,7 +236,38 @@ impl Sink {
         debug_assert!(source.channel_count() == self.input_channels);
 
         let mut input_buffer = self.input_buffer.lock().unwrap();
-        input_buffer.extend(source);
+        let processed_gain = self.gain.get();
+        let gain_with_mix = |mix: f32| processed_gain + (1.0 - processed_gain) * mix;
+        let mix = self.bypass_mix.get();
+        let target_mix = if self.bypass.get() { 1.0 } else { 0.0 };
+        if mix != target_mix {
+            let frames_per_ramp = BYPASS_RAMP.as_secs_f32() * self.input_sample_rate as f32;
+            let ramp_frames = ((target_mix - mix).abs() * frames_per_ramp).ceil() as usize;
+            input_buffer.extend_with_gain_ramp(
+                source,
+                gain_with_mix(mix),
+                gain_with_mix(target_mix),
+                ramp_frames,
+            );
+            let progress = (source.frame_count() as f32 / frames_per_ramp).min(1.0);
+            self.bypass_mix.set(if target_mix > mix {
+                (mix + progress).min(target_mix)
+            } else {
+                (mix - progress).max(target_mix)
+            });
+            return;
+        }
+        match gain_with_mix(mix) {
+            gain if gain == 1.0 => input_buffer.extend(source),
+            gain => input_buffer.extend_amplified(source, gain),
+        }
+    }
+
+    /// Throws away any audio that was queued but not yet sent to the audio device.
+    ///
+    /// This is used when seeking so that audio from before the seek isn't played.
+    pub fn clear(&self) {
+        self.input_buffer.lock().unwrap().clear();
     }
 
     /// Flushes any remaining audio data to the audio device.
@@ -170,7 +276,8 @@ impl Sink {
         if input_buffer.frame_count() == 0 {
             return;
         }
-        if input_buffer.frame_count() < self.chunk_size_frames {
+        let pad = self.pad_final_chunk || self.resampler.is_some();
+        if pad && input_buffer.frame_count() < self.chunk_size_frames {
             input_buffer.extend_with_silence(self.chunk_size_frames);
         }
 
@@ -305,6 +412,27 @@ impl BoxAudioBuffer {
         }
     }
 
+    /// The number of samples in this buffer.
+    pub fn len(&self) -> usize {
+        match self.format {
+            SampleFormat::F32 => self.expect_ref::<f32>().len(),
+            SampleFormat::F64 => self.expect_ref::<f64>().len(),
+            SampleFormat::I16 => self.expect_ref::<i16>().len(),
+            SampleFormat::I32 => self.expect_ref::<i32>().len(),
+            SampleFormat::I8 => self.expect_ref::<i8>().len(),
+            SampleFormat::U16 => self.expect_ref::<u16>().len(),
+            SampleFormat::U32 => self.expect_ref::<u32>().len(),
+            SampleFormat::U8 => self.expect_ref::<u8>().len(),
+            SampleFormat::I64 | SampleFormat::U64 => unreachable!("unsupported: {}", self.format),
+            _ => unreachable!("{}", self.format),
+        }
+    }
+
+    /// Whether or not this buffer is empty.
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
     /// Clears this buffer.
     pub fn clear(&mut self) {
         match self.format {
@@ -321,6 +449,22 @@ impl BoxAudioBuffer {
         }
     }
 
+    /// Returns a reference to the underlying typed audio buffer.
+    ///
+    /// Panics if the underlying type is not the expected type.
+    #[inline]
+    fn expect_ref<S: Sample + 'static>(&self) -> &AudioBuffer<S> {
+        self.inner
+            .downcast_ref::<AudioBuffer<S>>()
+            .unwrap_or_else(|| {
+                panic!(
+                    "failed to downcast {} audio buffer to {}",
+                    self.inner_format,
+                    std::any::type_name::<S>()
+                )
+            })
+    }
+
     /// Returns a mutable reference to the underlying typed audio buffer.
     #[inline]
     pub fn get_mut<S: Sample + 'static>(&mut self) -> Option<&mut AudioBuffer<S>> {
@@ -13,23 +13,32 @@
 // If not, see <https://www.gnu.org/licenses/>.
 
 use crate::{
-    audio::{ChannelCount, SampleRate},
+    audio::{
+        channel_map::ChannelMap,
+        http::{HttpMediaSource, StreamHealthHandle},
+        ChannelCount, SampleRate,
+    },
     location::Location,
-    metadata::{Metadata, MetadataConversionError},
+    metadata::{read_nero_chapters, Chapter, Metadata, MetadataConversionError},
 };
 use camino::Utf8PathBuf;
+use millenium_post_office::types::{PlaybackError, PlaybackErrorKind};
 use rubato::ResampleResult;
-use std::{cmp::Ordering, error::Error as StdError};
-use std::{fs::File, time::Duration};
+use std::{cmp::Ordering, error::Error as StdError, mem};
+use std::{fs::File, io, time::Duration};
 use symphonia::core::{
     audio::{AudioBuffer, AudioBufferRef, Signal},
-    codecs::{Decoder, DecoderOptions},
+    codecs::{
+        CodecParameters, Decoder, DecoderOptions, CODEC_TYPE_AAC, CODEC_TYPE_MP1, CODEC_TYPE_MP2,
+        CODEC_TYPE_MP3, CODEC_TYPE_NULL,
+    },
     conv::{FromSample, IntoSample},
-    formats::{FormatReader, SeekMode, SeekTo, Track},
+    errors::Error as SymphoniaError,
+    formats::{Cue, FormatOptions, FormatReader, SeekMode, SeekTo, Track},
     io::MediaSourceStream,
-    probe::Hint,
+    probe::{Hint, ProbeResult},
     sample::Sample,
-    units::Time,
+    units::{Time, TimeBase, TimeStamp},
 };
 
 #[derive(Debug, thiserror::Error)]
@@ -63,6 +72,8 @@ pub enum AudioSourceError {
     },
     #[error("source contained no audio tracks")]
     SourceHadNoAudioTracks,
+    #[error("stream was served as a playlist rather than audio")]
+    StreamIsPlaylist,
     #[error("failed to create audio decoder: {source}")]
     FailedToCreateAudioDecoder {
         #[source]
@@ -70,6 +81,41 @@ pub enum AudioSourceError {
     },
 }
 
+impl AudioSourceError {
+    /// Converts this error into a [`PlaybackError`] that can be broadcast to the UI.
+    pub fn to_playback_error(&self, location: &Location) -> PlaybackError {
+        use PlaybackErrorKind::*;
+        let (kind, retryable) = match self {
+            Self::FailedToLoadFile { source, .. } => match source.kind() {
+                io::ErrorKind::NotFound => (NotFound, false),
+                io::ErrorKind::PermissionDenied => (Unreadable, false),
+                _ => (Unreadable, true),
+            },
+            Self::FailedToLoadStream { source }
+            | Self::FailedToReadStream { source }
+            | Self::FailedToDecodeStream { source } => {
+                match source.downcast_ref::<SymphoniaError>() {
+                    Some(SymphoniaError::IoError(_)) => (Unreadable, true),
+                    Some(SymphoniaError::Unsupported(_)) => (UnsupportedFormat, false),
+                    // Network errors from streaming
+                    None if source.is::<io::Error>() => (Unreadable, true),
+                    _ => (CorruptData, false),
+                }
+            }
+            Self::FailedToReadMetadata { .. } => (CorruptData, false),
+            Self::SourceHadNoAudioTracks
+            | Self::StreamIsPlaylist
+            | Self::FailedToCreateAudioDecoder { .. } => (UnsupportedFormat, false),
+        };
+        PlaybackError {
+            kind,
+            location: location.to_string(),
+            message: self.to_string(),
+            retryable,
+        }
+    }
+}
+
 /// Specialized object-safe adapter for Rubato's [`Resampler`](rubato::Resampler) trait.
 pub trait Resampler {
     /// Resample the given channels into a new set of channels.
@@ -133,6 +179,18 @@ impl SourceBuffer {
         }
     }
 
+    /// Creates a source buffer from non-interleaved channel data.
+    ///
+    /// All channels must have the same number of frames.
+    pub fn from_channels(sample_rate: SampleRate, channels: Vec<Vec<f32>>) -> Self {
+        debug_assert!(channels.windows(2).all(|w| w[0].len() == w[1].len()));
+        Self {
+            sample_rate,
+            channel_count: channels.len(),
+            channels,
+        }
+    }
+
     /// Clears this buffer.
     pub fn clear(&mut self) {
         for channel in &mut self.channels {
@@ -158,6 +216,72 @@ impl SourceBuffer {
         }
     }
 
+    /// Extend this buffer with another buffer's data, multiplying each sample by `gain`.
+    pub fn extend_amplified(&mut self, other: &SourceBuffer, gain: f32) {
+        debug_assert!(other.sample_rate() == self.sample_rate);
+        debug_assert!(other.channel_count() == self.channel_count());
+        for (into, from) in self.channels.iter_mut().zip(other.channels.iter()) {
+            into.extend(from.iter().map(|sample| sample * gain));
+        }
+    }
+
+    /// Extend this buffer with another buffer's data, moving the gain linearly from `from_gain`
+    /// to `to_gain` over the first `ramp_frames` frames and holding `to_gain` after that.
+    pub fn extend_with_gain_ramp(
+        &mut self,
+        other: &SourceBuffer,
+        from_gain: f32,
+        to_gain: f32,
+        ramp_frames: usize,
+    ) {
+        debug_assert!(other.sample_rate() == self.sample_rate);
+        debug_assert!(other.channel_count() == self.channel_count());
+        let gain_at = |frame: usize| {
+            if frame < ramp_frames {
+                from_gain + (to_gain - from_gain) * frame as f32 / ramp_frames as f32
+            } else {
+                to_gain
+            }
+        };
+        for (into, from) in self.channels.iter_mut().zip(other.channels.iter()) {
+            into.extend(
+                from.iter()
+                    .enumerate()
+                    .map(|(frame, sample)| sample * gain_at(frame)),
+            );
+        }
+    }
+
+    /// Extend this buffer with the sum of the first `frames` frames of two buffers, multiplying
+    /// each of their samples by the pair of gains that `gains` returns for the frame.
+    pub fn extend_mixed(
+        &mut self,
+        a: &SourceBuffer,
+        b: &SourceBuffer,
+        frames: usize,
+        gains: impl Fn(usize) -> (f32, f32),
+    ) {
+        debug_assert!(a.sample_rate() == self.sample_rate && b.sample_rate() == self.sample_rate);
+        debug_assert!(a.channel_count() == self.channel_count());
+        debug_assert!(b.channel_count() == self.channel_count());
+        let channels = self
+            .channels
+            .iter_mut()
+            .zip(a.channels.iter().zip(&b.channels));
+        for (into, (a, b)) in channels {
+            into.extend(
+                a[..frames]
+                    .iter()
+                    .zip(&b[..frames])
+                    .enumerate()
+                    .map(|(frame, (a, b))| {
+                        let (a_gain, b_gain) = gains(frame);
+                        a * a_gain + b * b_gain
+                    }),
+            );
+        }
+    }
+
     /// Extend this buffer to the given frame count with silence.
     pub fn extend_with_silence(&mut self, desired_frames: usize) {
         debug_assert!(self.frame_count() < desired_frames);
@@ -182,6 +306,14 @@ impl SourceBuffer {
         }
     }
 
+    /// Removes the first N frames from the buffer.
+    pub fn discard_front(&mut self, n: usize) {
+        debug_assert!(self.frame_count() >= n);
+        for channel in &mut self.channels[0..self.channel_count] {
+            channel.drain(0..n);
+        }
+    }
+
     /// The sample rate of the source buffer.
     pub fn sample_rate(&self) -> SampleRate {
         self.sample_rate
@@ -298,6 +430,21 @@ impl SourceBuffer {
         )
     }
 
+    /// Routes stereo channels to the channels of an output device in place.
+    pub fn route_in_place(&mut self, map: &ChannelMap) {
+        let frame_count = self.frame_count();
+        let sources = mem::take(&mut self.channels);
+        self.channels = map
+            .sources()
+            .iter()
+            .map(|source| match source {
+                Some(index) => sources[*index].clone(),
+                None => vec![0.0; frame_count],
+            })
+            .collect();
+        self.channel_count = self.channels.len();
+    }
+
     /// Interleave into the given vec in the required sample format.
     ///
     /// This extends the given vec rather than overwrite it.
@@ -322,18 +469,17 @@ impl SourceBuffer {
         }
     }
 
-    // TODO: Do this conversion in place to reduce allocations
-    fn from_symphonia(from: AudioBufferRef) -> Self {
+    /// Replaces the contents of this buffer with the decoded audio from Symphonia.
+    ///
+    /// The existing channel allocations are reused, so converting into the same buffer
+    /// for every packet doesn't allocate once it has grown to the largest packet size.
+    fn copy_from_symphonia(&mut self, from: AudioBufferRef) {
         fn convert_and_copy<S>(channel: usize, from: &AudioBuffer<S>, into: &mut Vec<f32>)
         where
             S: Sample,
             S: IntoSample<f32>,
         {
-            let from_iter = from.chan(channel).iter().map(|&s| s.into_sample());
-            let to_iter = into.as_mut_slice().iter_mut();
-            for (to, from) in to_iter.zip(from_iter) {
-                *to = from;
-            }
+            into.extend(from.chan(channel).iter().map(|&s| s.into_sample()));
         }
         macro_rules! convert_and_copy {
             ($channel:ident, $from:expr => $into:expr, $($format:ident,)+) => {
@@ -343,23 +489,14 @@ impl SourceBuffer {
             };
         }
 
-        let sample_rate = from.spec().rate;
-        let channel_count = from.spec().channels.count();
-        let frame_count = from.frames();
-        let mut channels = Vec::with_capacity(channel_count);
-        for channel in 0..channel_count {
-            let mut data = vec![0.0; frame_count];
+        self.sample_rate = from.spec().rate;
+        self.make_empty_with_channels(from.spec().channels.count() as ChannelCount);
+        for channel in 0..self.channel_count {
             convert_and_copy!(
                 channel,
-                &from => &mut data,
+                &from => &mut self.channels[channel],
                 U8, U16, U24, U32, S8, S16, S24, S32, F32, F64,
             );
-            channels.push(data);
-        }
-        Self {
-            sample_rate,
-            channels,
-            channel_count,
         }
     }
 }
@@ -382,14 +519,25 @@ impl PreferredFormat {
     }
 }
 
+/// How far before the position to start decoding when seeking. Decoders like Vorbis and MP3
+/// need the packets before a position to reproduce it, so these are decoded and thrown away.
+const SEEK_PREROLL: Duration = Duration::from_millis(100);
+
 /// An audio decoder source.
 pub struct AudioDecoderSource {
-    _location: Location,
+    location: Location,
     reader: Box<dyn FormatReader>,
     decoder: Box<dyn Decoder>,
     metadata: Option<Metadata>,
     frame_count: Option<u64>,
     selected_track_id: u32,
+    stream_health: Option<StreamHealthHandle>,
+    /// Timestamp that the last seek asked for, until decoding has reached it.
+    seek_target: Option<TimeStamp>,
+    /// Buffer that every packet is decoded into, so that playback doesn't allocate per chunk.
+    chunk: SourceBuffer,
+    /// Set when `chunk` was decoded ahead of time and hasn't been returned yet.
+    primed: bool,
 }
 
 impl AudioDecoderSource {
@@ -406,17 +554,27 @@ impl AudioDecoderSource {
             metadata,
             frame_count,
             selected_track_id,
+            stream_health,
         } = load_stream(&location, None, preferred_format)?;
         Ok(Self {
-            _location: location,
+            location,
             reader,
             decoder,
             metadata,
             frame_count,
             selected_track_id,
+            stream_health,
+            seek_target: None,
+            chunk: SourceBuffer::empty(preferred_format.sample_rate, 0),
+            primed: false,
         })
     }
 
+    /// The location this source is decoding.
+    pub fn location(&self) -> &Location {
+        &self.location
+    }
+
     /// The metadata from the tags on this source.
     pub fn metadata(&self) -> Option<&Metadata> {
         self.metadata.as_ref()
@@ -427,49 +585,132 @@ impl AudioDecoderSource {
         self.frame_count
     }
 
+    /// Sample rate and channel count of the decoded audio, once a chunk has been decoded.
+    pub fn decoded_format(&self) -> Option<(SampleRate, ChannelCount)> {
+        (self.chunk.frame_count() > 0)
+            .then(|| (self.chunk.sample_rate(), self.chunk.channel_count()))
+    }
+
+    /// Connection health of the source if it's streaming from the network.
+    pub fn stream_health(&self) -> Option<&StreamHealthHandle> {
+        self.stream_health.as_ref()
+    }
+
     /// Seek to the given position in the audio source.
+    ///
+    /// The seek is sample accurate: the next chunk starts at the frame closest to the position.
     pub fn seek(&mut self, position: Duration) -> Result<(), AudioSourceError> {
+        fn time(duration: Duration) -> Time {
+            Time::new(duration.as_secs(), duration.subsec_nanos() as f64 / 1e9)
+        }
         self.reader
             .seek(
-                SeekMode::Coarse,
+                SeekMode::Accurate,
                 SeekTo::Time {
-                    time: Time::new(position.as_secs(), 0.0),
+                    time: time(position.saturating_sub(SEEK_PREROLL)),
                     track_id: Some(self.selected_track_id),
                 },
             )
             .map_err(|err| AudioSourceError::FailedToReadStream {
                 source: Box::new(err),
             })?;
+        self.decoder.reset();
+        self.primed = false;
+        // The reader lands before the position, so the frames leading up to it
+        // need to be decoded and thrown away.
+        self.seek_target = self
+            .time_base_and_rate()
+            .map(|(time_base, _)| time_base.calc_timestamp(time(position)));
+        Ok(())
+    }
+
+    /// Whether the silence that the encoder added to the start and end of the track is known,
+    /// so that it's trimmed off for gapless playback. Lossless and Vorbis audio is always
+    /// sample accurate, while MPEG audio and AAC need a LAME, Xing, or iTunes header saying how
+    /// much was added.
+    pub fn encoder_padding_known(&self) -> bool {
+        match self.selected_codec_params() {
+            Some(params) => match params.codec {
+                CODEC_TYPE_MP1 | CODEC_TYPE_MP2 | CODEC_TYPE_MP3 | CODEC_TYPE_AAC => {
+                    params.delay.is_some() || params.padding.is_some()
+                }
+                _ => true,
+            },
+            None => false,
+        }
+    }
+
+    fn selected_codec_params(&self) -> Option<&CodecParameters> {
+        self.reader
+            .tracks()
+            .iter()
+            .find(|track| track.id == self.selected_track_id)
+            .map(|track| &track.codec_params)
+    }
+
+    fn time_base_and_rate(&self) -> Option<(TimeBase, u32)> {
+        let params = self.selected_codec_params()?;
+        Some((params.time_base?, params.sample_rate?))
+    }
+
+    /// Converts the span between two timestamps of the selected track into a frame count.
+    fn frames_between(&self, from: TimeStamp, to: TimeStamp) -> u64 {
+        let Some((time_base, sample_rate)) = self.time_base_and_rate() else {
+            return 0;
+        };
+        let time = time_base.calc_time(to.saturating_sub(from));
+        ((time.seconds as f64 + time.frac) * sample_rate as f64).round() as u64
+    }
+
+    /// Decodes the first chunk ahead of time so that the decoder is warmed up, and the next
+    /// call to [`AudioDecoderSource::next_chunk`] returns right away.
+    pub fn prime(&mut self) -> Result<(), AudioSourceError> {
+        self.primed = self.next_chunk()?.is_some();
         Ok(())
     }
 
     /// Retrieve and decode the next chunk of audio data.
     ///
-    /// Returns `Ok(None)` if the stream has ended.
-    pub fn next_chunk(&mut self) -> Result<Option<SourceBuffer>, AudioSourceError> {
-        let packet = loop {
-            match self.reader.next_packet() {
-                Ok(packet) => {
-                    if packet.track_id() == self.selected_track_id {
-                        break packet;
+    /// Returns `Ok(None)` if the stream has ended. The returned chunk is owned by the source
+    /// and gets overwritten by the next call, so copy out anything that needs to outlive it.
+    pub fn next_chunk(&mut self) -> Result<Option<&SourceBuffer>, AudioSourceError> {
+        if mem::take(&mut self.primed) {
+            return Ok(Some(&self.chunk));
+        }
+        loop {
+            let packet = loop {
+                match self.reader.next_packet() {
+                    Ok(packet) => {
+                        if packet.track_id() == self.selected_track_id {
+                            break packet;
+                        }
                     }
-                }
-                // Symphonia's end of stream is an IO error with unexpected EOF
-                Err(symphonia::core::errors::Error::IoError(err))
-                    if err.kind() == std::io::ErrorKind::UnexpectedEof =>
-                {
-                    return Ok(None)
-                }
-                Err(err) => {
-                    return Err(AudioSourceError::FailedToReadStream { source: err.into() })
-                }
+                    // Symphonia's end of stream is an IO error with unexpected EOF
+                    Err(symphonia::core::errors::Error::IoError(err))
+                        if err.kind() == std::io::ErrorKind::UnexpectedEof =>
+                    {
+                        return Ok(None)
+                    }
+                    Err(err) => {
+                        return Err(AudioSourceError::FailedToReadStream { source: err.into() })
+                    }
+                };
             };
-        };
-        self.decoder
-            .decode(&packet)
-            .map(SourceBuffer::from_symphonia)
-            .map(Some)
-            .map_err(|err| AudioSourceError::FailedToDecodeStream { source: err.into() })
+            let decoded = self
+                .decoder
+                .decode(&packet)
+                .map_err(|err| AudioSourceError::FailedToDecodeStream { source: err.into() })?;
+            self.chunk.copy_from_symphonia(decoded);
+            if let Some(target) = self.seek_target {
+                let discard = self.frames_between(packet.ts(), target) as usize;
+                if discard >= self.chunk.frame_count() {
+                    continue;
+                }
+                self.chunk.discard_front(discard);
+                self.seek_target = None;
+            }
+            return Ok(Some(&self.chunk));
+        }
     }
 }
 
@@ -479,16 +720,100 @@ struct Stream {
     metadata: Option<Metadata>,
     frame_count: Option<u64>,
     selected_track_id: u32,
+    stream_health: Option<StreamHealthHandle>,
 }
 
-fn load_stream(
+/// Reads the metadata tags for the given location without creating an audio decoder.
+pub fn read_metadata(location: &Location) -> Result<Option<Metadata>, AudioSourceError> {
+    let (mut format, _) = probe_location(location)?;
+    read_probed_metadata(&mut format)
+}
+
+/// What [`probe`] found out about a location.
+#[derive(Debug, Default)]
+pub struct ProbedAudio {
+    pub metadata: Option<Metadata>,
+    /// `None` when the container doesn't say how many frames the track has.
+    pub duration: Option<Duration>,
+    /// Chapters from the file's embedded cuesheet, chapter tags, or MP4 chapter list.
+    pub chapters: Vec<Chapter>,
+}
+
+/// Checks that a location contains audio that can be decoded, and reads its tags, length,
+/// and chapters.
+///
+/// The format is detected from the file contents rather than its extension, and the audio
+/// itself isn't decoded, but this still reads the file, so it belongs on a background thread.
+pub fn probe(location: &Location) -> Result<ProbedAudio, AudioSourceError> {
+    let (mut format, _) = probe_location(location)?;
+    let metadata = read_probed_metadata(&mut format).unwrap_or_else(|err| {
+        log::warn!("failed to read tags from {location}: {err}");
+        None
+    });
+    let track = format
+        .format
+        .tracks()
+        .iter()
+        .find(|track| track.codec_params.codec != CODEC_TYPE_NULL)
+        .ok_or(AudioSourceError::SourceHadNoAudioTracks)?;
+    symphonia::default::get_codecs()
+        .make(&track.codec_params, &DecoderOptions::default())
+        .map_err(|err| AudioSourceError::FailedToCreateAudioDecoder { source: err.into() })?;
+    let duration = track.codec_params.n_frames.and_then(|frames| {
+        let sample_rate = track.codec_params.sample_rate?;
+        Some(Duration::from_secs_f64(frames as f64 / sample_rate as f64))
+    });
+    let mut chapters = metadata
+        .as_ref()
+        .map(|metadata| metadata.chapters.clone())
+        .unwrap_or_default();
+    if chapters.is_empty() {
+        chapters = mp4_chapters(location);
+    }
+    Ok(ProbedAudio {
+        metadata,
+        duration,
+        chapters,
+    })
+}
+
+/// Reads the chapter list of local MP4 files, which symphonia doesn't give as metadata.
+fn mp4_chapters(location: &Location) -> Vec<Chapter> {
+    let extension = location.extension().map(str::to_ascii_lowercase);
+    let (Some(path), Some("m4a" | "m4b" | "mp4")) = (location.as_path(), extension.as_deref())
+    else {
+        return Vec::new();
+    };
+    File::open(path)
+        .and_then(|mut file| read_nero_chapters(&mut file))
+        .unwrap_or_else(|err| {
+            log::warn!("failed to read MP4 chapters from {location}: {err}");
+            Vec::new()
+        })
+}
+
+fn probe_location(
     location: &Location,
-    existing_metadata: Option<Metadata>,
-    preferred_format: PreferredFormat,
-) -> Result<Stream, AudioSourceError> {
+) -> Result<(ProbeResult, Option<StreamHealthHandle>), AudioSourceError> {
+    let mut hint = Hint::new();
+    let mut stream_health = None;
     let media_stream = match location {
-        Location::Url(_url) => {
-            unimplemented!("streaming from URLs is not yet supported")
+        Location::Url(url) => {
+            let source =
+                HttpMediaSource::open(url).map_err(|err| AudioSourceError::FailedToLoadStream {
+                    source: Box::new(err),
+                })?;
+            if let Some(content_type) = source.content_type() {
+                if location
+                    .inferred_type_with_content_type(content_type)
+                    .is_playlist()
+                {
+                    return Err(AudioSourceError::StreamIsPlaylist);
+                }
+                hint.mime_type(content_type);
+            }
+            stream_health = Some(source.health());
+            MediaSourceStream::new(Box::new(source), Default::default())
         }
         Location::Path(path) => MediaSourceStream::new(
             Box::new(
@@ -501,34 +826,78 @@ fn load_stream(
         ),
     };
     let probe = symphonia::default::get_probe();
-    let mut hint = Hint::new();
-    // TODO: Add mime hint for streaming URLs
     if let Some(extension) = location.extension() {
         hint.with_extension(extension);
     }
 
-    let mut format = probe
-        .format(
-            &hint,
-            media_stream,
-            &Default::default(),
-            &Default::default(),
-        )
+    // Gapless mode trims encoder delay and padding so that consecutive tracks line up
+    let format_options = FormatOptions {
+        enable_gapless: true,
+        ..Default::default()
+    };
+    let format = probe
+        .format(&hint, media_stream, &format_options, &Default::default())
         .map_err(|err| AudioSourceError::FailedToLoadStream {
             source: Box::new(err),
         })?;
+    Ok((format, stream_health))
+}
+
+fn read_probed_metadata(format: &mut ProbeResult) -> Result<Option<Metadata>, AudioSourceError> {
+    let mut metadata = format
+        .metadata
+        .get()
+        .or_else(|| Some(format.format.metadata()))
+        .map(|mut meta| {
+            meta.skip_to_latest();
+            Metadata::try_from(&meta)
+        })
+        .transpose()?;
+    // Chapter tags take precedence over an embedded cuesheet since they can carry titles
+    if let Some(metadata) = metadata.as_mut().filter(|meta| meta.chapters.is_empty()) {
+        let sample_rate = format
+            .format
+            .default_track()
+            .and_then(|track| track.codec_params.sample_rate);
+        if let Some(sample_rate) = sample_rate {
+            metadata.chapters = chapters_from_cues(format.format.cues(), sample_rate);
+        }
+    }
+    Ok(metadata)
+}
+
+/// Converts the cues of an embedded cuesheet (such as FLAC's CUESHEET block) into chapters.
+fn chapters_from_cues(cues: &[Cue], sample_rate: SampleRate) -> Vec<Chapter> {
+    // CD-DA cuesheets end with a lead-out track numbered 170, and others use 255
+    const LEAD_OUT_TRACKS: [u32; 2] = [170, 255];
+    cues.iter()
+        .filter(|cue| !LEAD_OUT_TRACKS.contains(&cue.index))
+        .map(|cue| {
+            // The last index point is the start of the audio, after any pre-gap
+            let offset = cue
+                .points
+                .last()
+                .map(|point| point.start_offset_ts)
+                .unwrap_or_default();
+            let frames = cue.start_ts + offset;
+            Chapter {
+                title: None,
+                start: Duration::from_secs_f64(frames as f64 / sample_rate as f64),
+            }
+        })
+        .collect()
+}
+
+fn load_stream(
+    location: &Location,
+    existing_metadata: Option<Metadata>,
+    preferred_format: PreferredFormat,
+) -> Result<Stream, AudioSourceError> {
+    let (mut format, stream_health) = probe_location(location)?;
     let metadata = if let Some(existing_metadata) = existing_metadata {
         Some(existing_metadata)
     } else {
-        format
-            .metadata
-            .get()
-            .or_else(|| Some(format.format.metadata()))
-            .map(|mut meta| {
-                meta.skip_to_latest();
-                Metadata::try_from(&meta)
-            })
-            .transpose()?
+        read_probed_metadata(&mut format)?
     };
 
     let codecs = symphonia::default::get_codecs();
@@ -549,6 +918,7 @@ fn load_stream(
         metadata,
         frame_count,
         selected_track_id,
+        stream_health,
     })
 }
 
@@ -598,3 +968,187 @@ fn select_track(
         Ok(selected_track)
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use millenium_post_office::types::PlaybackErrorKind;
+
+    fn remaining_frames(source: &mut AudioDecoderSource) -> u64 {
+        let mut frames = 0;
+        while let Some(chunk) = source.next_chunk().unwrap() {
+            frames += chunk.frame_count() as u64;
+        }
+        frames
+    }
+
+    #[test]
+    fn gain_ramp() {
+        let source = SourceBuffer::from_channels(44100, vec![vec![1.0; 6], vec![-1.0; 6]]);
+        let mut buffer = SourceBuffer::empty(44100, 2);
+        buffer.extend_with_gain_ramp(&source, 0.0, 1.0, 4);
+        assert_eq!(&[0.0, 0.25, 0.5, 0.75, 1.0, 1.0], buffer.channel(0));
+        assert_eq!(&[0.0, -0.25, -0.5, -0.75, -1.0, -1.0], buffer.channel(1));
+    }
+
+    #[test]
+    fn mixed() {
+        let a = SourceBuffer::from_channels(44100, vec![vec![1.0; 4]]);
+        let b = SourceBuffer::from_channels(44100, vec![vec![2.0; 4]]);
+        let mut buffer = SourceBuffer::from_channels(44100, vec![vec![9.0]]);
+        buffer.extend_mixed(&a, &b, 3, |frame| {
+            (1.0 - frame as f32 / 2.0, frame as f32 / 2.0)
+        });
+        assert_eq!(&[9.0, 1.0, 1.5, 2.0], buffer.channel(0));
+    }
+
+    #[test]
+    fn decoding_reuses_the_chunk_buffer() {
+        let mut source = AudioDecoderSource::new(
+            Location::path("../test-data/melodic_a_minor/melodic_a_minor_1chan_48000hz_6s.mp3"),
+            PreferredFormat::new(44100, 2),
+        )
+        .unwrap();
+        // The first MP3 packet is shorter, so the buffer grows once after it
+        source.next_chunk().unwrap();
+        let first = source.next_chunk().unwrap().unwrap().channel(0).as_ptr();
+        for _ in 0..10 {
+            let chunk = source.next_chunk().unwrap().unwrap();
+            assert_eq!(1152, chunk.frame_count());
+            assert_eq!(first, chunk.channel(0).as_ptr());
+        }
+    }
+
+    #[test]
+    fn seek_is_sample_accurate() {
+        for path in [
+            "../test-data/melodic_a_minor/melodic_a_minor_2chan_44100hz_11s.ogg",
+            "../test-data/melodic_a_minor/melodic_a_minor_1chan_48000hz_6s.mp3",
+        ] {
+            let mut source =
+                AudioDecoderSource::new(Location::path(path), PreferredFormat::new(44100, 2))
+                    .unwrap();
+            let total = remaining_frames(&mut source);
+            let sample_rate = source.decoder.codec_params().sample_rate.unwrap() as u64;
+
+            for position in [Duration::from_millis(1250), Duration::from_millis(3007)] {
+                source.seek(position).unwrap();
+                let expected = total - position.as_millis() as u64 * sample_rate / 1000;
+                assert_eq!(
+                    expected,
+                    remaining_frames(&mut source),
+                    "{path} at {position:?}"
+                );
+            }
+        }
+    }
+
+    #[test]
+    fn playback_error_kinds() {
+        let location = Location::path("test.ogg");
+        let error = |err: AudioSourceError| err.to_playback_error(&location);
+
+        let not_found = error(AudioSourceError::FailedToLoadFile {
+            path: "test.ogg".into(),
+            source: io::ErrorKind::NotFound.into(),
+        });
+        assert_eq!(PlaybackErrorKind::NotFound, not_found.kind);
+        assert!(!not_found.retryable);
+        assert_eq!("test.ogg", not_found.location);
+
+        let interrupted = error(AudioSourceError::FailedToLoadFile {
+            path: "test.ogg".into(),
+            source: io::ErrorKind::Interrupted.into(),
+        });
+        assert_eq!(PlaybackErrorKind::Unreadable, interrupted.kind);
+        assert!(interrupted.retryable);
+
+        let unsupported = error(AudioSourceError::FailedToLoadStream {
+            source: Box::new(SymphoniaError::Unsupported(
+                "core (probe): no suitable reader",
+            )),
+        });
+        assert_eq!(PlaybackErrorKind::UnsupportedFormat, unsupported.kind);
+        assert!(!unsupported.retryable);
+
+        let corrupt = error(AudioSourceError::FailedToDecodeStream {
+            source: Box::new(SymphoniaError::DecodeError("invalid frame")),
+        });
+        assert_eq!(PlaybackErrorKind::CorruptData, corrupt.kind);
+        assert!(!corrupt.retryable);
+
+        let read_failure = error(AudioSourceError::FailedToReadStream {
+            source: Box::new(SymphoniaError::IoError(io::ErrorKind::TimedOut.into())),
+        });
+        assert_eq!(PlaybackErrorKind::Unreadable, read_failure.kind);
+        assert!(read_failure.retryable);
+    }
+
+    #[test]
+    fn streams_served_as_playlists() {
+        use std::{
+            io::{BufRead, BufReader, Write},
+            net::TcpListener,
+            thread,
+        };
+
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let port = listener.local_addr().unwrap().port();
+        thread::spawn(move || {
+            let (mut stream, _) = listener.accept().unwrap();
+            let mut reader = BufReader::new(stream.try_clone().unwrap());
+            let mut line = String::new();
+            while reader.read_line(&mut line).unwrap() > 2 {
+                line.clear();
+            }
+            let body = "#EXTM3U\nhttp://127.0.0.1/station.mp3\n";
+            write!(
+                stream,
+                "HTTP/1.1 200 OK\r\nContent-Type: audio/x-mpegurl\r\n\
+                 Content-Length: {}\r\n\r\n{body}",
+                body.len()
+            )
+            .unwrap();
+        });
+
+        // The URL doesn't say that it's a playlist, but the content type does
+        let location: Location = format!("http://127.0.0.1:{port}/listen").parse().unwrap();
+        let err = probe(&location).unwrap_err();
+        assert!(
+            matches!(err, AudioSourceError::StreamIsPlaylist),
+            "unexpected error: {err}"
+        );
+        assert_eq!(
+            PlaybackErrorKind::UnsupportedFormat,
+            err.to_playback_error(&location).kind
+        );
+    }
+
+    #[test]
+    fn cuesheet_chapters() {
+        use symphonia::core::formats::CuePoint;
+        let cue = |index, start_ts, points: &[u64]| Cue {
+            index,
+            start_ts,
+            tags: Vec::new(),
+            points: points
+                .iter()
+                .map(|&start_offset_ts| CuePoint {
+                    start_offset_ts,
+                    tags: Vec::new(),
+                })
+                .collect(),
+        };
+        let cues = [
+            cue(1, 0, &[0]),
+            // Track with a one second pre-gap
+            cue(2, 441_000, &[0, 44_100]),
+            cue(170, 882_000, &[]),
+        ];
+        let chapters = chapters_from_cues(&cues, 44_100);
+        assert_eq!(
+            vec![Duration::ZERO, Duration::from_secs(11)],
+            chapters.iter().map(|c| c.start).collect::<Vec<_>>()
+        );
+    }
+}
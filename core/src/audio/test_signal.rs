@@ -0,0 +1,207 @@
+// This file is part of Millenium Player.
+// Copyright (C) 2023 John DiSanti.
+//
+// Millenium Player is free software: you can redistribute it and/or modify it under the terms of
+// the GNU General Public License as published by the Free Software Foundation, either version 3 of
+// the License, or (at your option) any later version.
+//
+// Millenium Player is distributed in the hope that it will be useful, but WITHOUT ANY WARRANTY;
+// without even the implied warranty of MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE. See
+// the GNU General Public License for more details.
+//
+// You should have received a copy of the GNU General Public License along with Millenium Player.
+// If not, see <https://www.gnu.org/licenses/>.
+
+use millenium_post_office::types::TestSignal;
+use std::{f64::consts::TAU, fs, io, path::Path, time::Duration};
+
+/// Sample rate that test signals are generated at.
+pub const SAMPLE_RATE: u32 = 48_000;
+
+/// Test signals are quiet enough to not be startling, at about -12 dBFS.
+const AMPLITUDE: f64 = 0.25;
+
+const SWEEP_LENGTH: Duration = Duration::from_secs(10);
+const SWEEP_START_HZ: f64 = 20.0;
+const SWEEP_END_HZ: f64 = 20_000.0;
+const NOISE_LENGTH: Duration = Duration::from_secs(10);
+const BEEP_HZ: f64 = 1_000.0;
+const BEEP_LENGTH: Duration = Duration::from_millis(200);
+const BEEP_GAP: Duration = Duration::from_millis(200);
+const CHANNEL_GAP: Duration = Duration::from_millis(800);
+/// Fade in and out so that the signals don't start or stop with a click.
+const FADE_LENGTH: Duration = Duration::from_millis(10);
+
+/// Generates the given test signal as stereo channels.
+pub fn generate(signal: TestSignal) -> [Vec<f32>; 2] {
+    match signal {
+        TestSignal::SineSweep => {
+            let sweep = sine_sweep(frames(SWEEP_LENGTH));
+            [sweep.clone(), sweep]
+        }
+        TestSignal::PinkNoise => {
+            let noise = pink_noise(frames(NOISE_LENGTH));
+            [noise.clone(), noise]
+        }
+        TestSignal::ChannelCheck => channel_check(),
+    }
+}
+
+/// Writes the given test signal to a 16-bit stereo WAV file so that it can be played
+/// like any other file.
+pub fn write_wav(signal: TestSignal, path: &Path) -> io::Result<()> {
+    let [left, right] = generate(signal);
+    let data_len = (left.len() * 2 * 2) as u32;
+    let mut wav = Vec::with_capacity(44 + data_len as usize);
+    wav.extend_from_slice(b"RIFF");
+    wav.extend_from_slice(&(36 + data_len).to_le_bytes());
+    wav.extend_from_slice(b"WAVEfmt ");
+    wav.extend_from_slice(&16u32.to_le_bytes());
+    wav.extend_from_slice(&1u16.to_le_bytes()); // PCM
+    wav.extend_from_slice(&2u16.to_le_bytes()); // stereo
+    wav.extend_from_slice(&SAMPLE_RATE.to_le_bytes());
+    wav.extend_from_slice(&(SAMPLE_RATE * 2 * 2).to_le_bytes());
+    wav.extend_from_slice(&4u16.to_le_bytes());
+    wav.extend_from_slice(&16u16.to_le_bytes());
+    wav.extend_from_slice(b"data");
+    wav.extend_from_slice(&data_len.to_le_bytes());
+    for (l, r) in left.iter().zip(right.iter()) {
+        for sample in [l, r] {
+            let sample = (sample.clamp(-1.0, 1.0) * i16::MAX as f32) as i16;
+            wav.extend_from_slice(&sample.to_le_bytes());
+        }
+    }
+    fs::write(path, wav)
+}
+
+fn frames(length: Duration) -> usize {
+    (length.as_secs_f64() * SAMPLE_RATE as f64) as usize
+}
+
+/// Exponential sweep, which spends the same time in each octave.
+fn sine_sweep(frame_count: usize) -> Vec<f32> {
+    let length = frame_count as f64 / SAMPLE_RATE as f64;
+    let rate = (SWEEP_END_HZ / SWEEP_START_HZ).ln();
+    let mut sweep: Vec<f32> = (0..frame_count)
+        .map(|frame| {
+            let t = frame as f64 / SAMPLE_RATE as f64;
+            let phase = TAU * SWEEP_START_HZ * length / rate * ((t / length * rate).exp() - 1.0);
+            (AMPLITUDE * phase.sin()) as f32
+        })
+        .collect();
+    fade(&mut sweep);
+    sweep
+}
+
+/// White noise filtered to pink with Paul Kellet's economy filter.
+fn pink_noise(frame_count: usize) -> Vec<f32> {
+    // Xorshift is plenty random for noise, and keeps the signal the same every time
+    let mut state = 0x2545_f491_4f6c_dd1d_u64;
+    let mut white = move || {
+        state ^= state << 13;
+        state ^= state >> 7;
+        state ^= state << 17;
+        (state >> 11) as f64 / (1u64 << 53) as f64 * 2.0 - 1.0
+    };
+    let (mut b0, mut b1, mut b2) = (0.0, 0.0, 0.0);
+    let mut noise: Vec<f32> = (0..frame_count)
+        .map(|_| {
+            let white = white();
+            b0 = 0.99765 * b0 + white * 0.0990460;
+            b1 = 0.96300 * b1 + white * 0.2965164;
+            b2 = 0.57000 * b2 + white * 1.0526913;
+            let pink = b0 + b1 + b2 + white * 0.1848;
+            // The filter has a gain of about 3, but peaks can go higher
+            (AMPLITUDE * pink / 4.0) as f32
+        })
+        .collect();
+    fade(&mut noise);
+    noise
+}
+
+/// Beeps once on the left channel, then twice on the right, so that each channel
+/// can be identified by ear.
+fn channel_check() -> [Vec<f32>; 2] {
+    let mut channels = [Vec::new(), Vec::new()];
+    for (index, beeps) in [1, 2].into_iter().enumerate() {
+        for beep in 0..beeps {
+            if beep > 0 {
+                silence(&mut channels, frames(BEEP_GAP));
+            }
+            let mut tone: Vec<f32> = (0..frames(BEEP_LENGTH))
+                .map(|frame| {
+                    let t = frame as f64 / SAMPLE_RATE as f64;
+                    (AMPLITUDE * (TAU * BEEP_HZ * t).sin()) as f32
+                })
+                .collect();
+            fade(&mut tone);
+            channels[1 - index].resize(channels[1 - index].len() + tone.len(), 0.0);
+            channels[index].extend(tone);
+        }
+        silence(&mut channels, frames(CHANNEL_GAP));
+    }
+    channels
+}
+
+fn silence(channels: &mut [Vec<f32>; 2], frame_count: usize) {
+    for channel in channels {
+        channel.resize(channel.len() + frame_count, 0.0);
+    }
+}
+
+fn fade(samples: &mut [f32]) {
+    let fade_frames = frames(FADE_LENGTH).min(samples.len() / 2);
+    let len = samples.len();
+    for frame in 0..fade_frames {
+        let gain = frame as f32 / fade_frames as f32;
+        samples[frame] *= gain;
+        samples[len - 1 - frame] *= gain;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{audio::source, location::Location};
+
+    fn peak(samples: &[f32]) -> f32 {
+        samples
+            .iter()
+            .fold(0.0, |peak, sample| peak.max(sample.abs()))
+    }
+
+    #[test]
+    fn signals_stay_below_full_scale() {
+        for signal in [TestSignal::SineSweep, TestSignal::PinkNoise] {
+            let [left, right] = generate(signal);
+            assert_eq!(frames(Duration::from_secs(10)), left.len());
+            assert_eq!(left, right);
+            let peak = peak(&left);
+            assert!(peak > 0.1 && peak <= 0.5, "{signal:?} peaks at {peak}");
+            // Faded in so that it doesn't click
+            assert_eq!(0.0, left[0]);
+        }
+    }
+
+    #[test]
+    fn channel_check_beeps_one_channel_at_a_time() {
+        let [left, right] = generate(TestSignal::ChannelCheck);
+        assert_eq!(left.len(), right.len());
+        let beep = frames(BEEP_LENGTH);
+        // The single beep on the left comes first
+        assert!(peak(&left[..beep]) > 0.2);
+        assert_eq!(0.0, peak(&right[..beep]));
+        let right_start = beep + frames(CHANNEL_GAP);
+        assert_eq!(0.0, peak(&left[right_start..]));
+        assert!(peak(&right[right_start..right_start + beep]) > 0.2);
+    }
+
+    #[test]
+    fn write_playable_wav() {
+        let path = std::env::temp_dir().join("millenium-test-signal-write.wav");
+        write_wav(TestSignal::ChannelCheck, &path).unwrap();
+        let location = Location::path(camino::Utf8PathBuf::try_from(path.clone()).unwrap());
+        assert!(source::probe(&location).is_ok());
+        fs::remove_file(path).unwrap();
+    }
+}
@@ -15,6 +15,9 @@
 /// Audio support logic.
 pub mod audio;
 
+/// Source of the current time.
+pub mod clock;
+
 /// Location struct that represents file system or network locations.
 pub mod location;
 
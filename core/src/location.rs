@@ -13,7 +13,13 @@
 // If not, see <https://www.gnu.org/licenses/>.
 
 use camino::{Utf8Path, Utf8PathBuf};
-use std::{error::Error as StdError, fmt, str::FromStr};
+use std::{
+    error::Error as StdError,
+    fmt,
+    fs::File,
+    io::{self, Read},
+    str::FromStr,
+};
 use thiserror::Error;
 use url::Url;
 
@@ -35,6 +41,51 @@ impl InferredLocationType {
     pub fn is_unknown(&self) -> bool {
         matches!(self, Self::Unknown)
     }
+
+    /// Infers the type from a MIME type, such as the `Content-Type` header of an HTTP response.
+    pub fn from_content_type(content_type: &str) -> Self {
+        let mime = content_type
+            .split(';')
+            .next()
+            .unwrap_or_default()
+            .trim()
+            .to_ascii_lowercase();
+        match mime.as_str() {
+            "audio/mpegurl"
+            | "audio/x-mpegurl"
+            | "application/mpegurl"
+            | "application/x-mpegurl"
+            | "application/vnd.apple.mpegurl"
+            | "audio/x-scpls" => Self::Playlist,
+            "application/ogg" => Self::Audio,
+            _ if mime.starts_with("audio/") => Self::Audio,
+            _ => Self::Unknown,
+        }
+    }
+
+    /// Infers the type from the first few bytes of a file.
+    pub fn from_magic_bytes(bytes: &[u8]) -> Self {
+        const AUDIO_MAGIC: &[&[u8]] = &[
+            b"fLaC",
+            b"OggS",
+            b"ID3",
+            // Matroska/WebM
+            &[0x1A, 0x45, 0xDF, 0xA3],
+        ];
+        if bytes.starts_with(b"#EXTM3U") || bytes.starts_with(b"[playlist]") {
+            Self::Playlist
+        } else if AUDIO_MAGIC.iter().any(|magic| bytes.starts_with(magic))
+            || (bytes.starts_with(b"RIFF") && bytes.get(8..12) == Some(b"WAVE"))
+            // MP4/M4A
+            || bytes.get(4..8) == Some(b"ftyp")
+            // MPEG audio and ADTS AAC frame sync
+            || (bytes.len() >= 2 && bytes[0] == 0xFF && bytes[1] & 0xE0 == 0xE0)
+        {
+            Self::Audio
+        } else {
+            Self::Unknown
+        }
+    }
 }
 
 /// Resource location that can either be a URL or file path.
@@ -84,8 +135,39 @@ impl Location {
         }
     }
 
-    /// Infers the type of the location.
+    /// Infers the type of the location from its extension.
     pub fn inferred_type(&self) -> InferredLocationType {
+        self.inferred_type_from_extension()
+    }
+
+    /// Infers the type of the location, identifying local files by their contents when
+    /// possible so that misnamed and extensionless files are classified correctly. Otherwise,
+    /// the extension is used.
+    ///
+    /// This reads the start of the file, so the result should be kept rather than sniffing
+    /// the same location again.
+    pub fn sniff_type(&self) -> InferredLocationType {
+        if let Some(path) = self.as_path() {
+            match sniff_file(path) {
+                Ok(InferredLocationType::Unknown) => {}
+                Ok(inferred) => return inferred,
+                Err(err) if err.kind() == io::ErrorKind::NotFound => {}
+                Err(err) => log::debug!("failed to sniff {path}: {err}"),
+            }
+        }
+        self.inferred_type_from_extension()
+    }
+
+    /// Infers the type of the location using the `Content-Type` it was served with,
+    /// falling back to the extension if the content type doesn't identify it.
+    pub fn inferred_type_with_content_type(&self, content_type: &str) -> InferredLocationType {
+        match InferredLocationType::from_content_type(content_type) {
+            InferredLocationType::Unknown => self.inferred_type_from_extension(),
+            inferred => inferred,
+        }
+    }
+
+    fn inferred_type_from_extension(&self) -> InferredLocationType {
         let lower_ext: Option<String> = match self {
             Self::Url(url) => Utf8Path::new(&url.path())
                 .extension()
@@ -96,7 +178,7 @@ impl Location {
             match lower_ext.as_str() {
                 "m3u" | "m3u8" | "pls" => InferredLocationType::Playlist,
                 "aac" => InferredLocationType::Audio,
-                "mp1" | "mp2" | "mp3" | "mp4" | "m4a" => InferredLocationType::Audio,
+                "mp1" | "mp2" | "mp3" | "mp4" | "m4a" | "m4b" => InferredLocationType::Audio,
                 "ogg" | "oga" | "opus" | "flac" => InferredLocationType::Audio,
                 "wav" => InferredLocationType::Audio,
                 "webm" => InferredLocationType::Audio,
@@ -108,6 +190,15 @@ impl Location {
     }
 }
 
+fn sniff_file(path: &Utf8Path) -> io::Result<InferredLocationType> {
+    if !path.is_file() {
+        return Ok(InferredLocationType::Unknown);
+    }
+    let mut header = Vec::with_capacity(16);
+    File::open(path)?.take(16).read_to_end(&mut header)?;
+    Ok(InferredLocationType::from_magic_bytes(&header))
+}
+
 impl fmt::Display for Location {
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
         write!(f, "{}", self.as_str())
@@ -213,8 +304,8 @@ mod tests {
     fn infer_type() {
         let playlist_extensions = &[".m3u", ".m3u8", ".pls"];
         let audio_extensions = &[
-            ".aac", ".mp1", ".mp2", ".mp3", ".mp4", ".m4a", ".ogg", ".oga", ".opus", ".flac",
-            ".wav", ".webm",
+            ".aac", ".mp1", ".mp2", ".mp3", ".mp4", ".m4a", ".m4b", ".ogg", ".oga", ".opus",
+            ".flac", ".wav", ".webm",
         ];
         for ext in playlist_extensions {
             assert_eq!(
@@ -285,4 +376,78 @@ mod tests {
             serde_json::from_str("\"/path/to/something\"").unwrap(),
         );
     }
+
+    #[test]
+    fn infer_type_from_content_type() {
+        let from = InferredLocationType::from_content_type;
+        assert_eq!(InferredLocationType::Audio, from("audio/mpeg"));
+        assert_eq!(
+            InferredLocationType::Audio,
+            from("audio/flac; charset=binary")
+        );
+        assert_eq!(InferredLocationType::Audio, from("Application/Ogg"));
+        assert_eq!(InferredLocationType::Playlist, from("audio/x-mpegurl"));
+        assert_eq!(InferredLocationType::Playlist, from("audio/x-scpls"));
+        assert_eq!(InferredLocationType::Unknown, from("text/html"));
+        assert_eq!(InferredLocationType::Unknown, from(""));
+
+        let url = Location::from_str("https://example.com/download").unwrap();
+        assert_eq!(
+            InferredLocationType::Audio,
+            url.inferred_type_with_content_type("audio/ogg")
+        );
+        let url = Location::from_str("https://example.com/foo.mp3").unwrap();
+        assert_eq!(
+            InferredLocationType::Audio,
+            url.inferred_type_with_content_type("application/octet-stream")
+        );
+    }
+
+    #[test]
+    fn infer_type_from_magic_bytes() {
+        let from = InferredLocationType::from_magic_bytes;
+        assert_eq!(InferredLocationType::Audio, from(b"fLaC\0\0\0\x22"));
+        assert_eq!(InferredLocationType::Audio, from(b"OggS\0\x02"));
+        assert_eq!(InferredLocationType::Audio, from(b"ID3\x04\0"));
+        assert_eq!(InferredLocationType::Audio, from(b"RIFF\0\0\0\0WAVEfmt "));
+        assert_eq!(InferredLocationType::Audio, from(b"\0\0\0\x20ftypM4A "));
+        assert_eq!(InferredLocationType::Audio, from(&[0xFF, 0xFB, 0x90, 0x64]));
+        assert_eq!(InferredLocationType::Playlist, from(b"#EXTM3U\n#EXTINF"));
+        assert_eq!(InferredLocationType::Playlist, from(b"[playlist]\nFile1="));
+        assert_eq!(InferredLocationType::Unknown, from(b"RIFF\0\0\0\0AVI LIST"));
+        assert_eq!(InferredLocationType::Unknown, from(b"\x89PNG\r\n"));
+        assert_eq!(InferredLocationType::Unknown, from(b""));
+    }
+
+    #[test]
+    fn infer_type_of_local_files_from_contents() {
+        let dir = std::env::temp_dir().join(format!("millenium-sniff-{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let (download, playlist, text) = (
+            dir.join("download"),
+            dir.join("playlist.mp3"),
+            dir.join("notes.flac"),
+        );
+        std::fs::write(&download, b"fLaC\0\0\0\x22").unwrap();
+        std::fs::write(&playlist, b"#EXTM3U\n").unwrap();
+        std::fs::write(&text, b"just some notes").unwrap();
+        let location = |path: std::path::PathBuf| Location::path(path.to_str().unwrap());
+        let (download, playlist, text) = (location(download), location(playlist), location(text));
+
+        let actual = (
+            download.sniff_type(),
+            playlist.sniff_type(),
+            text.sniff_type(),
+        );
+        std::fs::remove_dir_all(&dir).unwrap();
+        assert_eq!(
+            (
+                InferredLocationType::Audio,
+                InferredLocationType::Playlist,
+                // Falls back to the extension when the contents aren't recognized
+                InferredLocationType::Audio,
+            ),
+            actual
+        );
+    }
 }
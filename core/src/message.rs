@@ -12,13 +12,13 @@
 // You should have received a copy of the GNU General Public License along with Millenium Player.
 // If not, see <https://www.gnu.org/licenses/>.
 
-use crate::audio::{device::AudioDeviceError, source::AudioSourceError};
+use crate::audio::{device::AudioDeviceError, dynamic_range::DynamicRange};
 use crate::player::waveform::Waveform;
 use crate::{location::Location, metadata::Metadata};
 use millenium_post_office::{
     broadcast::{BroadcastMessage, Channel},
     frontend::state::PlaybackStatus,
-    types::Volume,
+    types::{AbLoop, BufferStats, NormalizationMode, PlaybackError, StreamHealth, Volume},
 };
 use std::{
     sync::{Arc, Mutex},
@@ -41,12 +41,22 @@ impl Channel for PlayerMessageChannel {
     }
 }
 
-#[derive(Clone, Debug)]
+/// Player messages are serializable so that they can be recorded for debugging. Variants that
+/// carry errors or shared buffers are skipped, and are left out of recordings.
+#[derive(Clone, Debug, serde::Serialize, serde::Deserialize)]
 pub enum PlayerMessage {
     /// The application is shutting down. Exit the player thread.
     CommandQuit,
     /// Load and play a location.
     CommandLoadAndPlayLocation(Location),
+    /// Load a location and start playing it from the given position.
+    CommandLoadAndPlayLocationFrom(Location, Duration),
+    /// Set the location to play after the current track finishes. `None` clears it.
+    ///
+    /// The next location is decoded ahead of time and played without a gap. The end of the
+    /// current track is crossfaded into it over the given duration, unless it's zero.
+    /// Loading a location or stopping playback clears it.
+    CommandSetNextLocation(Option<Location>, Duration),
     /// Pause playback.
     CommandPause,
     /// Resume playback.
@@ -54,9 +64,44 @@ pub enum PlayerMessage {
     /// Stop playback.
     CommandStop,
     /// Seek to a position in the currently playing track.
+    ///
+    /// Live streams can only be sought within their timeshift, and seeking past it goes
+    /// back to the live stream.
     CommandSeek(Duration),
+    /// Catch a live stream that was paused or rewound back up to what's being broadcast.
+    CommandJumpToLive,
+    /// Mark the current position as the start of the region to repeat.
+    CommandMarkLoopStart,
+    /// Mark the current position as the end of the region to repeat, and start repeating it.
+    CommandMarkLoopEnd,
+    /// Stop repeating a region of the current track.
+    CommandClearLoop,
     /// Change the playback volume.
     CommandSetVolume(Volume),
+    /// Lower the volume to a fraction of itself while other audio plays, or restore it if `None`.
+    CommandDuck(Option<Volume>),
+    /// Change which ReplayGain values are applied. Takes effect within the sink's queue length.
+    CommandSetNormalizationMode(NormalizationMode),
+    /// Change the linear gain applied to every track in peak normalization mode.
+    CommandSetPlaylistGain(f32),
+    /// Bypass all processing, such as normalization, to compare against the unprocessed audio.
+    /// Takes effect within the sink's queue length.
+    CommandSetDspBypass(bool),
+    /// Open the cue output on the named audio device, or the default device if no name is given.
+    CommandOpenCueDevice(Option<String>),
+    /// Close the cue output device.
+    CommandCloseCueDevice,
+    /// Load and play a location on the cue output device.
+    CommandCueLocation(Location),
+    /// Stop playback on the cue output device.
+    CommandStopCue,
+    /// Change the cue output device's volume.
+    CommandSetCueVolume(Volume),
+    /// Play the start of a location quietly on its own short-lived output, mixed over
+    /// whatever the main output is playing.
+    CommandPreviewLocation(Location),
+    /// Stop the preview, if one is playing.
+    CommandStopPreview,
 
     /// This is the loaded track metadata.
     EventMetadataLoaded(Metadata),
@@ -64,19 +109,54 @@ pub enum PlayerMessage {
     EventStartedTrack,
     /// The currently playing track finished.
     EventFinishedTrack,
+    /// The current track finished, and the location given by
+    /// [`PlayerMessage::CommandSetNextLocation`] started playing without a gap.
+    ///
+    /// This is sent instead of [`PlayerMessage::EventFinishedTrack`] and
+    /// [`PlayerMessage::EventStartedTrack`] once the last sample of the previous track is played,
+    /// or once the crossfade into the next location starts.
+    EventStartedNextTrack(Location),
     /// Failed to load location.
-    EventFailedToLoadLocation(Arc<AudioSourceError>),
+    EventFailedToLoadLocation(PlaybackError),
     /// Failed to decode audio.
-    EventFailedToDecodeAudio(Arc<AudioSourceError>),
+    EventFailedToDecodeAudio(PlaybackError),
+    /// The dynamic range of the track that just finished was measured.
+    ///
+    /// This is only sent if the whole track was played without seeking, and it is sent
+    /// before [`PlayerMessage::EventFinishedTrack`] or [`PlayerMessage::EventStartedNextTrack`].
+    EventDynamicRangeMeasured(DynamicRange),
+    /// The track playing on the cue output device finished.
+    EventCueFinished,
+    /// The preview reached its length limit or the end of the location.
+    EventPreviewFinished,
     /// The audio device failed.
     EventAudioDeviceFailed(String),
     /// Failed to create an audio device.
+    #[serde(skip)]
     EventAudioDeviceCreationFailed(Arc<AudioDeviceError>),
+    /// The connection health of the current location changed.
+    ///
+    /// This is `None` when the location isn't streamed from the network.
+    EventStreamHealthChanged(Option<StreamHealth>),
+    /// The network delivered the current stream at this many bits per second while buffering.
+    EventStreamThroughputMeasured(u64),
+    /// The station playing the current stream announced a new song title.
+    EventStreamTitleChanged(String),
+    /// The current track played past its halfway point, so it counts as having been played.
+    /// This is sent at most once each time a track plays.
+    EventTrackPlayed(Location),
+    /// Points of the region being repeated changed. Starting a track clears them.
+    EventAbLoopChanged(AbLoop),
 
     /// The playback status changed.
     UpdatePlaybackStatus(PlaybackStatus),
     /// Updated waveform data.
+    #[serde(skip)]
     UpdateWaveform(Arc<Mutex<Waveform>>),
+    /// Playback paused or stopped, so there is no waveform to show until it resumes.
+    UpdateWaveformCleared,
+    /// Current sizes of the player's buffers.
+    UpdateBufferStats(BufferStats),
 }
 
 impl BroadcastMessage for PlayerMessage {
@@ -86,23 +166,50 @@ impl BroadcastMessage for PlayerMessage {
         match self {
             Self::CommandQuit
             | Self::CommandLoadAndPlayLocation(_)
+            | Self::CommandLoadAndPlayLocationFrom(..)
+            | Self::CommandSetNextLocation(..)
             | Self::CommandPause
             | Self::CommandResume
             | Self::CommandStop
             | Self::CommandSeek(_)
-            | Self::CommandSetVolume(_) => Self::Channel::Commands,
+            | Self::CommandJumpToLive
+            | Self::CommandMarkLoopStart
+            | Self::CommandMarkLoopEnd
+            | Self::CommandClearLoop
+            | Self::CommandSetVolume(_)
+            | Self::CommandDuck(_)
+            | Self::CommandSetNormalizationMode(_)
+            | Self::CommandSetPlaylistGain(_)
+            | Self::CommandSetDspBypass(_)
+            | Self::CommandOpenCueDevice(_)
+            | Self::CommandCloseCueDevice
+            | Self::CommandCueLocation(_)
+            | Self::CommandStopCue
+            | Self::CommandSetCueVolume(_)
+            | Self::CommandPreviewLocation(_)
+            | Self::CommandStopPreview => Self::Channel::Commands,
 
             Self::EventMetadataLoaded(_)
             | Self::EventStartedTrack
             | Self::EventFinishedTrack
+            | Self::EventStartedNextTrack(_)
             | Self::EventFailedToLoadLocation(_)
             | Self::EventFailedToDecodeAudio(_)
+            | Self::EventDynamicRangeMeasured(_)
+            | Self::EventCueFinished
+            | Self::EventPreviewFinished
             | Self::EventAudioDeviceFailed(_)
-            | Self::EventAudioDeviceCreationFailed(_) => Self::Channel::Events,
+            | Self::EventAudioDeviceCreationFailed(_)
+            | Self::EventStreamHealthChanged(_)
+            | Self::EventStreamThroughputMeasured(_)
+            | Self::EventStreamTitleChanged(_)
+            | Self::EventTrackPlayed(_)
+            | Self::EventAbLoopChanged(_) => Self::Channel::Events,
 
-            Self::UpdatePlaybackStatus(_) | Self::UpdateWaveform(_) => {
-                Self::Channel::FrequentUpdates
-            }
+            Self::UpdatePlaybackStatus(_)
+            | Self::UpdateWaveform(_)
+            | Self::UpdateWaveformCleared
+            | Self::UpdateBufferStats(_) => Self::Channel::FrequentUpdates,
         }
     }
 
@@ -118,22 +225,51 @@ impl PartialEq for PlayerMessage {
         match (self, other) {
             (CommandQuit, CommandQuit) => true,
             (CommandLoadAndPlayLocation(l), CommandLoadAndPlayLocation(r)) => l == r,
+            (CommandLoadAndPlayLocationFrom(l, a), CommandLoadAndPlayLocationFrom(r, b)) => {
+                l == r && a == b
+            }
+            (CommandSetNextLocation(l, a), CommandSetNextLocation(r, b)) => l == r && a == b,
             (CommandPause, CommandPause) => true,
             (CommandResume, CommandResume) => true,
             (CommandStop, CommandStop) => true,
             (CommandSeek(a), CommandSeek(b)) => a == b,
+            (CommandMarkLoopStart, CommandMarkLoopStart) => true,
+            (CommandMarkLoopEnd, CommandMarkLoopEnd) => true,
+            (CommandClearLoop, CommandClearLoop) => true,
             (CommandSetVolume(a), CommandSetVolume(b)) => a == b,
+            (CommandDuck(a), CommandDuck(b)) => a == b,
+            (CommandSetNormalizationMode(a), CommandSetNormalizationMode(b)) => a == b,
+            (CommandSetPlaylistGain(a), CommandSetPlaylistGain(b)) => a == b,
+            (CommandSetDspBypass(a), CommandSetDspBypass(b)) => a == b,
+            (CommandOpenCueDevice(a), CommandOpenCueDevice(b)) => a == b,
+            (CommandCloseCueDevice, CommandCloseCueDevice) => true,
+            (CommandCueLocation(l), CommandCueLocation(r)) => l == r,
+            (CommandStopCue, CommandStopCue) => true,
+            (CommandSetCueVolume(a), CommandSetCueVolume(b)) => a == b,
+            (CommandPreviewLocation(l), CommandPreviewLocation(r)) => l == r,
+            (CommandStopPreview, CommandStopPreview) => true,
 
             (EventMetadataLoaded(l), EventMetadataLoaded(r)) => l == r,
             (EventStartedTrack, EventStartedTrack) => true,
             (EventFinishedTrack, EventFinishedTrack) => true,
+            (EventStartedNextTrack(l), EventStartedNextTrack(r)) => l == r,
+            (EventDynamicRangeMeasured(l), EventDynamicRangeMeasured(r)) => l == r,
+            (EventCueFinished, EventCueFinished) => true,
+            (EventPreviewFinished, EventPreviewFinished) => true,
+            (EventStreamHealthChanged(l), EventStreamHealthChanged(r)) => l == r,
+            (EventStreamThroughputMeasured(l), EventStreamThroughputMeasured(r)) => l == r,
+            (EventStreamTitleChanged(l), EventStreamTitleChanged(r)) => l == r,
+            (EventTrackPlayed(l), EventTrackPlayed(r)) => l == r,
+            (EventAbLoopChanged(l), EventAbLoopChanged(r)) => l == r,
+            (EventFailedToLoadLocation(l), EventFailedToLoadLocation(r)) => l == r,
+            (EventFailedToDecodeAudio(l), EventFailedToDecodeAudio(r)) => l == r,
 
             (UpdatePlaybackStatus(l), UpdatePlaybackStatus(r)) => l == r,
+            (UpdateWaveformCleared, UpdateWaveformCleared) => true,
+            (UpdateBufferStats(l), UpdateBufferStats(r)) => l == r,
 
             (UpdateWaveform(_), UpdateWaveform(_))
             | (EventAudioDeviceCreationFailed(_), EventAudioDeviceCreationFailed(_))
-            | (EventFailedToLoadLocation(_), EventFailedToLoadLocation(_))
-            | (EventFailedToDecodeAudio(_), EventFailedToDecodeAudio(_))
             | (EventAudioDeviceFailed(_), EventAudioDeviceFailed(_)) => {
                 core::mem::discriminant(self) == core::mem::discriminant(other)
             }
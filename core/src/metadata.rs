@@ -12,28 +12,79 @@
 // You should have received a copy of the GNU General Public License along with Millenium Player.
 // If not, see <https://www.gnu.org/licenses/>.
 
-use std::{borrow::Cow, cmp::Ordering, collections::BTreeSet, fmt, sync::Arc};
+use encoding_rs::Encoding;
+use millenium_post_office::types::NormalizationMode;
+use std::{
+    borrow::Cow,
+    cmp::Ordering,
+    collections::{BTreeMap, BTreeSet},
+    fmt,
+    sync::Arc,
+    time::Duration,
+};
 use symphonia::core::meta::{StandardTagKey, StandardVisualKey};
 
+mod mp4;
+mod provider;
+
+pub use mp4::read_nero_chapters;
+pub use provider::{
+    EmbeddedTags, MetadataChain, MetadataProvider, MetadataProviderError, SidecarFile,
+    UnknownProviderError,
+};
+
 #[derive(Debug, thiserror::Error)]
 #[error("{}", self.0)]
 pub struct MetadataConversionError(&'static str);
 
-#[derive(Clone, Debug, Default)]
-#[cfg_attr(any(test, feature = "test-util"), derive(PartialEq, Eq))]
+#[derive(Debug, thiserror::Error)]
+#[error("unknown text encoding \"{0}\"")]
+pub struct UnknownEncodingError(String);
+
+#[derive(Clone, Debug, Default, serde::Serialize, serde::Deserialize)]
+#[cfg_attr(any(test, feature = "test-util"), derive(PartialEq))]
 pub struct Metadata {
     pub album: Option<String>,
     pub album_artist: Option<String>,
+    /// Name to sort the album artist by (such as "Beatles, The"), from the TSO2 tag.
+    pub album_artist_sort: Option<String>,
+    /// Name to sort the album by, from the TSOA tag.
+    pub album_sort: Option<String>,
+    /// Artist for display. Multiple artist tags are joined with semicolons.
     pub artist: Option<String>,
+    /// Name to sort the artist by, from the TSOP tag.
+    pub artist_sort: Option<String>,
+    /// Individual artists, split out of multi-value tags and "feat." style credits.
+    pub artists: Vec<String>,
     pub composer: Option<String>,
+    // Cover images are too large to serialize into message recordings
+    #[serde(skip)]
     pub cover: Option<EmbeddedImage>,
+    pub disc_number: Option<String>,
+    pub disc_total: Option<String>,
+    /// Genre for display. Multiple genre tags are joined with semicolons.
     pub genre: Option<String>,
+    /// Individual genres, split out of multi-value tags.
+    pub genres: Vec<String>,
     pub track_number: Option<String>,
     pub track_total: Option<String>,
     pub track_title: Option<String>,
+    /// Name to sort the track by, from the TSOT tag.
+    pub track_title_sort: Option<String>,
+    pub replay_gain: ReplayGain,
+    /// Chapters from an embedded cuesheet or Vorbis `CHAPTERxxx` comments, in order.
+    pub chapters: Vec<Chapter>,
     pub other: BTreeSet<Tag>,
 }
 
+/// A chapter (or CD track) within a single audio file.
+#[derive(Clone, Debug, Eq, PartialEq, serde::Serialize, serde::Deserialize)]
+pub struct Chapter {
+    pub title: Option<String>,
+    /// Position of the chapter from the start of the file.
+    pub start: Duration,
+}
+
 impl TryFrom<&symphonia::core::meta::Metadata<'_>> for Metadata {
     type Error = MetadataConversionError;
 
@@ -52,13 +103,19 @@ impl TryFrom<&symphonia::core::meta::Metadata<'_>> for Metadata {
                     meta.album_artist = Some(tag.value.into());
                 }
                 Some(StandardTagKey::Artist) => {
-                    meta.artist = Some(tag.value.into());
+                    meta.artists.push(tag.value.into());
                 }
                 Some(StandardTagKey::Composer) => {
                     meta.composer = Some(tag.value.into());
                 }
+                Some(StandardTagKey::DiscNumber) => {
+                    meta.disc_number = Some(tag.value.into());
+                }
+                Some(StandardTagKey::DiscTotal) => {
+                    meta.disc_total = Some(tag.value.into());
+                }
                 Some(StandardTagKey::Genre) => {
-                    meta.genre = Some(tag.value.into());
+                    meta.genres.push(tag.value.into());
                 }
                 Some(StandardTagKey::TrackNumber) => {
                     meta.track_number = Some(tag.value.into());
@@ -69,11 +126,45 @@ impl TryFrom<&symphonia::core::meta::Metadata<'_>> for Metadata {
                 Some(StandardTagKey::TrackTitle) => {
                     meta.track_title = Some(tag.value.into());
                 }
+                Some(StandardTagKey::SortAlbum) => {
+                    meta.album_sort = Some(tag.value.into());
+                }
+                Some(StandardTagKey::SortAlbumArtist) => {
+                    meta.album_artist_sort = Some(tag.value.into());
+                }
+                Some(StandardTagKey::SortArtist) => {
+                    meta.artist_sort = Some(tag.value.into());
+                }
+                Some(StandardTagKey::SortTrackTitle) => {
+                    meta.track_title_sort = Some(tag.value.into());
+                }
+                Some(StandardTagKey::ReplayGainTrackGain) => {
+                    meta.replay_gain.track_gain = parse_gain(&tag.value);
+                }
+                Some(StandardTagKey::ReplayGainTrackPeak) => {
+                    meta.replay_gain.track_peak = parse_peak(&tag.value);
+                }
+                Some(StandardTagKey::ReplayGainAlbumGain) => {
+                    meta.replay_gain.album_gain = parse_gain(&tag.value);
+                }
+                Some(StandardTagKey::ReplayGainAlbumPeak) => {
+                    meta.replay_gain.album_peak = parse_peak(&tag.value);
+                }
+                _ if tag.key.eq_ignore_ascii_case("R128_TRACK_GAIN") => {
+                    meta.replay_gain.track_gain = parse_r128_gain(&tag.value);
+                }
+                _ if tag.key.eq_ignore_ascii_case("R128_ALBUM_GAIN") => {
+                    meta.replay_gain.album_gain = parse_r128_gain(&tag.value);
+                }
                 _ => {
                     meta.other.insert(tag);
                 }
             }
         }
+        meta.chapters = take_vorbis_chapters(&mut meta.other);
+        meta.artist = join_values(&meta.artists);
+        meta.genre = join_values(&meta.genres);
+        meta.split_multi_values(&TagSeparators::default());
         for visual in latest.visuals() {
             if let Some(StandardVisualKey::FrontCover) = visual.usage {
                 meta.cover = Some(visual.into());
@@ -83,7 +174,366 @@ impl TryFrom<&symphonia::core::meta::Metadata<'_>> for Metadata {
     }
 }
 
-#[derive(Clone, Debug, Eq, PartialEq)]
+impl Metadata {
+    /// The track number parsed from the track number tag.
+    ///
+    /// Handles both plain numbers and the "number/total" form.
+    pub fn parsed_track_number(&self) -> Option<u32> {
+        self.track_number.as_deref().and_then(parse_position)
+    }
+
+    /// The disc number parsed from the disc number tag.
+    ///
+    /// Handles both plain numbers and the "number/total" form.
+    pub fn parsed_disc_number(&self) -> Option<u32> {
+        self.disc_number.as_deref().and_then(parse_position)
+    }
+
+    /// Splits the artists and genres on the given separators.
+    ///
+    /// Splitting is idempotent, so this can be called again with different separators
+    /// as long as they're a superset of the ones used before.
+    pub fn split_multi_values(&mut self, separators: &TagSeparators) {
+        self.artists = separators.split_all(&self.artists);
+        self.genres = separators.split_all(&self.genres);
+    }
+
+    /// Re-decodes all text in this metadata with the given decoder.
+    pub fn decode_text(&mut self, decoder: &TagDecoder) {
+        for field in [
+            &mut self.album,
+            &mut self.album_artist,
+            &mut self.album_artist_sort,
+            &mut self.album_sort,
+            &mut self.artist,
+            &mut self.artist_sort,
+            &mut self.composer,
+            &mut self.disc_number,
+            &mut self.disc_total,
+            &mut self.genre,
+            &mut self.track_number,
+            &mut self.track_total,
+            &mut self.track_title,
+            &mut self.track_title_sort,
+        ] {
+            if let Some(Cow::Owned(decoded)) = field.as_deref().map(|value| decoder.decode(value)) {
+                *field = Some(decoded);
+            }
+        }
+        for value in self.artists.iter_mut().chain(self.genres.iter_mut()) {
+            if let Cow::Owned(decoded) = decoder.decode(value) {
+                *value = decoded;
+            }
+        }
+        self.other = std::mem::take(&mut self.other)
+            .into_iter()
+            .map(|tag| Tag {
+                value: Cow::Owned(decoder.decode(&tag.value).into_owned()),
+                key: tag.key,
+            })
+            .collect();
+    }
+}
+
+/// Removes Vorbis chapter comments from the tags and converts them into chapters.
+///
+/// Chapters are given as `CHAPTER001=00:01:30.000` pairs with optional `CHAPTER001NAME=Title`
+/// comments. Chapters with an invalid start time are dropped.
+fn take_vorbis_chapters(tags: &mut BTreeSet<Tag>) -> Vec<Chapter> {
+    let mut chapters: BTreeMap<u32, (Option<Duration>, Option<String>)> = BTreeMap::new();
+    tags.retain(|tag| {
+        let key = tag.key.to_ascii_uppercase();
+        let Some(rest) = key.strip_prefix("CHAPTER") else {
+            return true;
+        };
+        let (number, is_name) = match rest.strip_suffix("NAME") {
+            Some(number) => (number, true),
+            None => (rest, false),
+        };
+        let Ok(number) = number.parse::<u32>() else {
+            return true;
+        };
+        let chapter = chapters.entry(number).or_default();
+        if is_name {
+            chapter.1 = Some(tag.value.to_string());
+        } else {
+            chapter.0 = parse_timestamp(&tag.value);
+        }
+        false
+    });
+    let mut chapters: Vec<Chapter> = chapters
+        .into_values()
+        .filter_map(|(start, title)| {
+            Some(Chapter {
+                title,
+                start: start?,
+            })
+        })
+        .collect();
+    chapters.sort_by_key(|chapter| chapter.start);
+    chapters
+}
+
+/// Parses a "HH:MM:SS.mmm" timestamp.
+fn parse_timestamp(value: &str) -> Option<Duration> {
+    let mut parts = value.trim().splitn(3, ':');
+    let hours: u64 = parts.next()?.parse().ok()?;
+    let minutes: u64 = parts.next()?.parse().ok()?;
+    let seconds: f64 = parts.next()?.parse().ok()?;
+    if !seconds.is_finite() || seconds < 0.0 {
+        return None;
+    }
+    Some(Duration::from_secs(hours * 3600 + minutes * 60) + Duration::from_secs_f64(seconds))
+}
+
+fn join_values(values: &[String]) -> Option<String> {
+    (!values.is_empty()).then(|| values.join("; "))
+}
+
+/// Separators that multi-value tags are split on.
+///
+/// The ID3v2.4 null separator is already turned into a semicolon when the tag text is decoded,
+/// so the semicolon separator also covers native multi-value frames.
+#[derive(Clone, Debug, PartialEq)]
+pub struct TagSeparators {
+    separators: Vec<String>,
+}
+
+impl Default for TagSeparators {
+    fn default() -> Self {
+        // Separators like "/" and "&" are left out since they appear in plenty of
+        // single artist names, such as "AC/DC" and "Simon & Garfunkel".
+        Self::new([";", " feat. ", " ft. ", " featuring "])
+    }
+}
+
+impl TagSeparators {
+    /// Creates a set of separators. ASCII letters in separators are matched case insensitively.
+    pub fn new<S: Into<String>>(separators: impl IntoIterator<Item = S>) -> Self {
+        Self {
+            separators: separators
+                .into_iter()
+                .map(Into::into)
+                .filter(|separator: &String| !separator.is_empty())
+                .map(|separator| separator.to_ascii_lowercase())
+                .collect(),
+        }
+    }
+
+    /// Splits a single tag value into its trimmed, non-empty parts.
+    pub fn split(&self, value: &str) -> Vec<String> {
+        let mut parts = vec![value.to_string()];
+        for separator in &self.separators {
+            parts = parts
+                .iter()
+                .flat_map(|part| split_case_insensitive(part, separator))
+                .collect();
+        }
+        parts
+            .into_iter()
+            .map(|part| part.trim().to_string())
+            .filter(|part| !part.is_empty())
+            .collect()
+    }
+
+    /// Splits each value, removing duplicates while keeping the first occurrence's position.
+    fn split_all(&self, values: &[String]) -> Vec<String> {
+        let mut result: Vec<String> = Vec::with_capacity(values.len());
+        for part in values.iter().flat_map(|value| self.split(value)) {
+            if !result.contains(&part) {
+                result.push(part);
+            }
+        }
+        result
+    }
+}
+
+/// Splits on a lowercase separator without changing the case of the parts.
+fn split_case_insensitive(value: &str, separator: &str) -> Vec<String> {
+    // Lowercasing can change byte lengths outside of ASCII, so only fold ASCII
+    let lower = value.to_ascii_lowercase();
+    let mut parts = Vec::new();
+    let mut start = 0;
+    while let Some(found) = lower[start..].find(separator) {
+        parts.push(value[start..start + found].to_string());
+        start += found + separator.len();
+    }
+    parts.push(value[start..].to_string());
+    parts
+}
+
+/// Decodes tag text that was written in a legacy encoding.
+///
+/// ID3v2.3 only allows latin-1 and UTF-16 text, but plenty of taggers wrote UTF-8 or the
+/// system's codepage into latin-1 frames, and some wrote UTF-16 with the wrong byte order.
+/// Symphonia faithfully decodes those as declared, which results in mojibake.
+///
+/// Latin-1 maps every byte to the code point of the same value, so the original bytes can
+/// be recovered and decoded again: first as UTF-8, and then with each of the fallback
+/// encodings in order. The fallback encodings are opt-in since they will misinterpret text
+/// that really was latin-1.
+#[derive(Clone, Debug, Default)]
+pub struct TagDecoder {
+    fallback_encodings: Vec<&'static Encoding>,
+}
+
+impl TagDecoder {
+    /// Creates a decoder with the given fallback encoding labels, such as "windows-1251"
+    /// or "shift_jis". Labels are matched the same way web browsers match them.
+    pub fn new<S: AsRef<str>>(
+        labels: impl IntoIterator<Item = S>,
+    ) -> Result<Self, UnknownEncodingError> {
+        let fallback_encodings = labels
+            .into_iter()
+            .map(|label| {
+                let label = label.as_ref();
+                Encoding::for_label(label.as_bytes())
+                    .ok_or_else(|| UnknownEncodingError(label.into()))
+            })
+            .collect::<Result<_, _>>()?;
+        Ok(Self { fallback_encodings })
+    }
+
+    /// Decodes a tag value, returning it unchanged if it doesn't look mis-encoded.
+    pub fn decode<'a>(&self, value: &'a str) -> Cow<'a, str> {
+        let mut value = strip_encoding_artifacts(value);
+        if let Some(swapped) = swap_utf16_byte_order(&value) {
+            value = Cow::Owned(swapped);
+        }
+        match self.decode_latin1_bytes(&value) {
+            Some(decoded) => Cow::Owned(decoded),
+            None => value,
+        }
+    }
+
+    fn decode_latin1_bytes(&self, value: &str) -> Option<String> {
+        // Plain ASCII decodes the same in every supported encoding
+        if value.is_ascii() || value.chars().any(|c| c as u32 > 0xFF) {
+            return None;
+        }
+        let bytes: Vec<u8> = value.chars().map(|c| c as u8).collect();
+        if let Ok(utf8) = std::str::from_utf8(&bytes) {
+            return Some(utf8.into());
+        }
+        self.fallback_encodings.iter().find_map(|encoding| {
+            encoding
+                .decode_without_bom_handling_and_without_replacement(&bytes)
+                .map(Cow::into_owned)
+        })
+    }
+}
+
+/// Removes stray byte order marks and null terminators that some taggers leave in the text.
+///
+/// Null characters between values are multi-value separators, so those become semicolons.
+fn strip_encoding_artifacts(value: &str) -> Cow<'_, str> {
+    const BOM: char = '\u{FEFF}';
+    const SWAPPED_BOM: char = '\u{FFFE}';
+    if !value.contains([BOM, SWAPPED_BOM, '\0']) {
+        return Cow::Borrowed(value);
+    }
+    let values: Vec<String> = value
+        .split('\0')
+        .map(|part| part.replace([BOM, SWAPPED_BOM], ""))
+        .filter(|part| !part.is_empty())
+        .collect();
+    Cow::Owned(values.join("; "))
+}
+
+/// Detects UTF-16 text that was decoded with the wrong byte order, which shows up as
+/// characters whose low byte is zero (e.g. U+4100 instead of "A").
+fn swap_utf16_byte_order(value: &str) -> Option<String> {
+    let swappable = |c: char| {
+        let unit = c as u32;
+        unit <= 0xFFFF && unit & 0xFF == 0 && (0x20..0x7F).contains(&(unit >> 8))
+    };
+    if value.chars().count() < 2 || !value.chars().all(swappable) {
+        return None;
+    }
+    Some(
+        value
+            .chars()
+            .map(|c| char::from((c as u32 >> 8) as u8))
+            .collect(),
+    )
+}
+
+/// Loudness adjustments from ReplayGain (or Opus R128) tags.
+#[derive(Copy, Clone, Debug, Default, PartialEq, serde::Serialize, serde::Deserialize)]
+pub struct ReplayGain {
+    /// Gain in dB that brings the track to the reference loudness.
+    pub track_gain: Option<f32>,
+    /// Largest absolute sample value in the track, where 1.0 is full scale.
+    pub track_peak: Option<f32>,
+    /// Gain in dB that brings the album to the reference loudness.
+    pub album_gain: Option<f32>,
+    /// Largest absolute sample value in the album, where 1.0 is full scale.
+    pub album_peak: Option<f32>,
+}
+
+impl ReplayGain {
+    /// Linear gain factor to apply for the given normalization mode.
+    ///
+    /// Falls back to the other mode's values if the preferred ones aren't tagged, and
+    /// limits the gain so that the peak doesn't clip.
+    pub fn linear_gain(&self, mode: NormalizationMode) -> f32 {
+        let track = (self.track_gain, self.track_peak);
+        let album = (self.album_gain, self.album_peak);
+        let (gain, peak) = match mode {
+            // The playlist gain is worked out from a scan rather than from tags
+            NormalizationMode::Off | NormalizationMode::PlaylistPeak => return 1.0,
+            NormalizationMode::Track if track.0.is_some() => track,
+            NormalizationMode::Track => album,
+            NormalizationMode::Album if album.0.is_some() => album,
+            NormalizationMode::Album => track,
+        };
+        let Some(gain) = gain else {
+            return 1.0;
+        };
+        let linear = 10f32.powf(gain / 20.0);
+        match peak {
+            Some(peak) if peak > 0.0 => linear.min(1.0 / peak),
+            _ => linear,
+        }
+    }
+}
+
+/// Parses a ReplayGain gain tag, such as "-6.48 dB".
+fn parse_gain(value: &str) -> Option<f32> {
+    let value = value.trim();
+    let value = value
+        .strip_suffix("dB")
+        .or_else(|| value.strip_suffix("db"))
+        .unwrap_or(value);
+    value
+        .trim()
+        .parse()
+        .ok()
+        .filter(|gain: &f32| gain.is_finite())
+}
+
+/// Parses a ReplayGain peak tag, such as "0.988556".
+fn parse_peak(value: &str) -> Option<f32> {
+    value
+        .trim()
+        .parse()
+        .ok()
+        .filter(|peak: &f32| peak.is_finite() && *peak >= 0.0)
+}
+
+/// Parses an Opus R128 gain tag, which is a Q7.8 fixed point number of dB relative to
+/// -23 LUFS. ReplayGain's reference is 5 dB louder, so it's adjusted to match.
+fn parse_r128_gain(value: &str) -> Option<f32> {
+    let fixed: i16 = value.trim().parse().ok()?;
+    Some(fixed as f32 / 256.0 + 5.0)
+}
+
+/// Parses a track or disc position tag, which can either be "N" or "N/TOTAL".
+fn parse_position(value: &str) -> Option<u32> {
+    value.split('/').next()?.trim().parse().ok()
+}
+
+#[derive(Clone, Debug, Eq, PartialEq, serde::Serialize, serde::Deserialize)]
 pub struct Tag {
     pub key: String,
     pub value: Cow<'static, str>,
@@ -117,7 +567,7 @@ impl From<&symphonia::core::meta::Tag> for Tag {
                 Value::Flag => Cow::Borrowed(""),
                 Value::Float(f) => Cow::Owned(f.to_string()),
                 Value::SignedInt(i) => Cow::Owned(i.to_string()),
-                Value::String(s) => Cow::Owned(s.clone()),
+                Value::String(s) => Cow::Owned(TagDecoder::default().decode(s).into_owned()),
                 Value::UnsignedInt(u) => Cow::Owned(u.to_string()),
             },
         }
@@ -186,13 +636,23 @@ mod test {
             Metadata {
                 album: None,
                 album_artist: None,
+                album_artist_sort: None,
+                album_sort: None,
                 artist: Some("kenny beltrey".into()),
+                artist_sort: None,
+                artists: vec!["kenny beltrey".into()],
                 composer: None,
                 cover: None,
+                disc_number: None,
+                disc_total: None,
                 genre: Some("Electronic".into()),
+                genres: vec!["Electronic".into()],
                 track_number: None,
                 track_total: None,
                 track_title: Some("hydrate (the beach)".into()),
+                track_title_sort: None,
+                replay_gain: ReplayGain::default(),
+                chapters: Vec::new(),
                 other: [("COMM!eng", "kahvi #011 - kahvi.stc.cx"), ("TYER", "2000")]
                     .iter()
                     .map(|&(k, v)| Tag {
@@ -207,4 +667,154 @@ mod test {
         assert_eq!("image/jpeg", cover.mime_type);
         assert_eq!(226833, cover.data.len());
     }
+
+    #[test]
+    fn decode_mis_encoded_text() {
+        let decoder = TagDecoder::default();
+        // UTF-8 bytes that were decoded as latin-1
+        assert_eq!("Sigur Rós", decoder.decode("Sigur RÃ³s"));
+        // Real latin-1 is left alone
+        assert_eq!("Café", decoder.decode("Café"));
+        assert!(matches!(decoder.decode("plain"), Cow::Borrowed("plain")));
+        // Byte order marks and null terminators
+        assert_eq!("Title", decoder.decode("\u{FEFF}Title\0"));
+        assert_eq!("One; Two", decoder.decode("One\0\u{FEFF}Two\0"));
+        // UTF-16 decoded with the wrong byte order
+        assert_eq!("Abc", decoder.decode("\u{4100}\u{6200}\u{6300}"));
+    }
+
+    #[test]
+    fn decode_fallback_encodings() {
+        // "Кино" in windows-1251, decoded as latin-1
+        let mojibake: String = [0xCA_u8, 0xE8, 0xED, 0xEE]
+            .iter()
+            .map(|&b| char::from(b))
+            .collect();
+        assert_eq!(mojibake, TagDecoder::default().decode(&mojibake));
+
+        let decoder = TagDecoder::new(["windows-1251"]).unwrap();
+        assert_eq!("Кино", decoder.decode(&mojibake));
+
+        let mut meta = Metadata {
+            artist: Some(mojibake.clone()),
+            other: [Tag {
+                key: "TALB".into(),
+                value: mojibake.into(),
+            }]
+            .into(),
+            ..Default::default()
+        };
+        meta.decode_text(&decoder);
+        assert_eq!(Some("Кино"), meta.artist.as_deref());
+        assert_eq!("Кино", meta.other.first().unwrap().value);
+
+        assert!(TagDecoder::new(["not-an-encoding"]).is_err());
+    }
+
+    #[test]
+    fn split_multi_value_tags() {
+        let separators = TagSeparators::default();
+        assert_eq!(
+            vec!["Artist A", "B", "C"],
+            separators.split("Artist A Feat. B; C")
+        );
+        assert_eq!(vec!["AC/DC"], separators.split("AC/DC"));
+
+        let mut meta = Metadata {
+            artists: vec!["A; B".into(), "B ft. C".into()],
+            genres: vec!["Rock/Pop".into()],
+            ..Default::default()
+        };
+        meta.split_multi_values(&separators);
+        assert_eq!(vec!["A", "B", "C"], meta.artists);
+        assert_eq!(vec!["Rock/Pop"], meta.genres);
+
+        meta.split_multi_values(&TagSeparators::new([";", "/"]));
+        assert_eq!(vec!["Rock", "Pop"], meta.genres);
+    }
+
+    #[test]
+    fn vorbis_chapters() {
+        let mut tags: BTreeSet<Tag> = [
+            ("CHAPTER002", "00:02:03.500"),
+            ("CHAPTER002NAME", "Second"),
+            ("chapter001", "00:00:00.000"),
+            ("CHAPTER003NAME", "No start time"),
+            ("CHAPTERS", "not a chapter"),
+        ]
+        .iter()
+        .map(|&(key, value)| Tag {
+            key: key.into(),
+            value: value.into(),
+        })
+        .collect();
+        let chapters = take_vorbis_chapters(&mut tags);
+        assert_eq!(
+            vec![
+                Chapter {
+                    title: None,
+                    start: Duration::ZERO,
+                },
+                Chapter {
+                    title: Some("Second".into()),
+                    start: Duration::from_millis(123_500),
+                },
+            ],
+            chapters
+        );
+        assert_eq!(1, tags.len());
+    }
+
+    #[test]
+    fn replay_gain() {
+        assert_eq!(Some(-6.48), parse_gain(" -6.48 dB"));
+        assert_eq!(Some(2.0), parse_gain("+2.00"));
+        assert_eq!(None, parse_gain("loud"));
+        assert_eq!(Some(0.988), parse_peak("0.988"));
+        assert_eq!(None, parse_peak("-1"));
+        // -3 dB relative to -23 LUFS is +2 dB relative to ReplayGain's reference
+        assert_eq!(Some(2.0), parse_r128_gain("-768"));
+
+        let gain = ReplayGain {
+            track_gain: Some(-6.0),
+            track_peak: Some(0.5),
+            album_gain: Some(6.0),
+            album_peak: Some(0.9),
+        };
+        assert_eq!(1.0, gain.linear_gain(NormalizationMode::Off));
+        assert!((gain.linear_gain(NormalizationMode::Track) - 0.501).abs() < 0.001);
+        // +6 dB would clip the peak, so it's limited
+        assert!((gain.linear_gain(NormalizationMode::Album) - 1.0 / 0.9).abs() < 0.001);
+
+        let track_only = ReplayGain {
+            track_gain: Some(-6.0),
+            ..Default::default()
+        };
+        assert_eq!(
+            track_only.linear_gain(NormalizationMode::Track),
+            track_only.linear_gain(NormalizationMode::Album)
+        );
+        assert_eq!(
+            1.0,
+            ReplayGain::default().linear_gain(NormalizationMode::Album)
+        );
+    }
+
+    #[test]
+    fn parse_track_and_disc_numbers() {
+        let meta = Metadata {
+            track_number: Some("3/12".into()),
+            disc_number: Some(" 2 ".into()),
+            ..Default::default()
+        };
+        assert_eq!(Some(3), meta.parsed_track_number());
+        assert_eq!(Some(2), meta.parsed_disc_number());
+
+        let meta = Metadata {
+            track_number: Some("A1".into()),
+            ..Default::default()
+        };
+        assert_eq!(None, meta.parsed_track_number());
+        assert_eq!(None, meta.parsed_disc_number());
+    }
 }
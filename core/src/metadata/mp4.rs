@@ -0,0 +1,205 @@
+// This file is part of Millenium Player.
+// Copyright (C) 2023 John DiSanti.
+//
+// Millenium Player is free software: you can redistribute it and/or modify it under the terms of
+// the GNU General Public License as published by the Free Software Foundation, either version 3 of
+// the License, or (at your option) any later version.
+//
+// Millenium Player is distributed in the hope that it will be useful, but WITHOUT ANY WARRANTY;
+// without even the implied warranty of MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE. See
+// the GNU General Public License for more details.
+//
+// You should have received a copy of the GNU General Public License along with Millenium Player.
+// If not, see <https://www.gnu.org/licenses/>.
+
+use super::Chapter;
+use std::{
+    io::{self, Read, Seek, SeekFrom},
+    time::Duration,
+};
+
+/// Largest `moov` box that's read looking for chapters. It's mostly sample tables, which
+/// are a few megabytes even for very long audiobooks.
+const MAX_MOOV_SIZE: u64 = 64 * 1024 * 1024;
+
+/// Chapter start times are in units of this many nanoseconds.
+const CHAPTER_TIME_UNIT_NANOS: u64 = 100;
+
+/// Reads the chapters from an MP4 file's Nero chapter list (`moov/udta/chpl`), which is
+/// where M4B audiobooks keep them rather than in tags.
+pub fn read_nero_chapters(reader: &mut (impl Read + Seek)) -> io::Result<Vec<Chapter>> {
+    let file_len = reader.seek(SeekFrom::End(0))?;
+    reader.seek(SeekFrom::Start(0))?;
+    let Some(moov_len) = find_top_level_box(reader, file_len, b"moov")? else {
+        return Ok(Vec::new());
+    };
+    if moov_len > MAX_MOOV_SIZE {
+        log::warn!("not reading chapters from a {moov_len} byte moov box");
+        return Ok(Vec::new());
+    }
+    let mut moov = vec![0; moov_len as usize];
+    reader.read_exact(&mut moov)?;
+    Ok(child_box(&moov, b"udta")
+        .and_then(|udta| child_box(udta, b"chpl"))
+        .map(parse_chpl)
+        .unwrap_or_default())
+}
+
+/// Seeks past top-level boxes until the one with the given type, and returns the length of
+/// its contents, leaving the reader at the start of them.
+fn find_top_level_box(
+    reader: &mut (impl Read + Seek),
+    file_len: u64,
+    kind: &[u8; 4],
+) -> io::Result<Option<u64>> {
+    let mut position = 0;
+    while position + 8 <= file_len {
+        let mut header = [0; 8];
+        reader.read_exact(&mut header)?;
+        let mut header_len = 8;
+        let size = match u32::from_be_bytes(header[0..4].try_into().unwrap()) {
+            // The box runs to the end of the file
+            0 => file_len - position,
+            // The real size follows the type as a 64-bit number
+            1 => {
+                let mut large_size = [0; 8];
+                reader.read_exact(&mut large_size)?;
+                header_len += 8;
+                u64::from_be_bytes(large_size)
+            }
+            size => size as u64,
+        };
+        if size < header_len || position + size > file_len {
+            return Ok(None);
+        }
+        if &header[4..8] == kind {
+            return Ok(Some(size - header_len));
+        }
+        position += size;
+        reader.seek(SeekFrom::Start(position))?;
+    }
+    Ok(None)
+}
+
+/// Finds the contents of the first child box with the given type.
+fn child_box<'a>(mut data: &'a [u8], kind: &[u8; 4]) -> Option<&'a [u8]> {
+    while data.len() >= 8 {
+        let size = u32::from_be_bytes(data[0..4].try_into().unwrap()) as usize;
+        let size = match size {
+            0 => data.len(),
+            size if size < 8 || size > data.len() => return None,
+            size => size,
+        };
+        if &data[4..8] == kind {
+            return Some(&data[8..size]);
+        }
+        data = &data[size..];
+    }
+    None
+}
+
+/// Parses the contents of a `chpl` box, keeping whatever chapters are complete if it's
+/// truncated.
+fn parse_chpl(data: &[u8]) -> Vec<Chapter> {
+    let mut chapters = Vec::new();
+    let Some(&version) = data.first() else {
+        return chapters;
+    };
+    // Version and flags, then an unknown field in version 1
+    let header_len = if version == 0 { 4 } else { 8 };
+    let Some((&count, mut rest)) = data.get(header_len..).and_then(<[u8]>::split_first) else {
+        return chapters;
+    };
+    for _ in 0..count {
+        let Some((start, title_len)) = rest.get(..9).map(|header| {
+            let start = u64::from_be_bytes(header[0..8].try_into().unwrap());
+            (start, header[8] as usize)
+        }) else {
+            break;
+        };
+        let Some(title) = rest.get(9..9 + title_len) else {
+            break;
+        };
+        chapters.push(Chapter {
+            title: Some(String::from_utf8_lossy(title).into_owned()).filter(|t| !t.is_empty()),
+            start: Duration::from_nanos(start.saturating_mul(CHAPTER_TIME_UNIT_NANOS)),
+        });
+        rest = &rest[9 + title_len..];
+    }
+    chapters.sort_by_key(|chapter| chapter.start);
+    chapters
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::io::Cursor;
+
+    fn mp4_box(kind: &[u8; 4], contents: &[u8]) -> Vec<u8> {
+        let mut data = ((contents.len() + 8) as u32).to_be_bytes().to_vec();
+        data.extend_from_slice(kind);
+        data.extend_from_slice(contents);
+        data
+    }
+
+    fn chpl(version: u8, chapters: &[(u64, &str)]) -> Vec<u8> {
+        let mut data = vec![version, 0, 0, 0];
+        if version == 1 {
+            data.extend_from_slice(&[0; 4]);
+        }
+        data.push(chapters.len() as u8);
+        for (start, title) in chapters {
+            data.extend_from_slice(&start.to_be_bytes());
+            data.push(title.len() as u8);
+            data.extend_from_slice(title.as_bytes());
+        }
+        mp4_box(b"chpl", &data)
+    }
+
+    #[test]
+    fn nero_chapters() {
+        for version in [0, 1] {
+            let chapters = chpl(version, &[(0, "Opening"), (905_000_000, "Chapter 1")]);
+            let mut file = mp4_box(b"ftyp", b"M4B isom");
+            file.extend(mp4_box(
+                b"moov",
+                &[mp4_box(b"mvhd", &[0; 100]), mp4_box(b"udta", &chapters)].concat(),
+            ));
+            file.extend(mp4_box(b"mdat", &[0; 1000]));
+
+            let chapters = read_nero_chapters(&mut Cursor::new(file)).unwrap();
+            assert_eq!(
+                vec![
+                    Chapter {
+                        title: Some("Opening".into()),
+                        start: Duration::ZERO,
+                    },
+                    Chapter {
+                        title: Some("Chapter 1".into()),
+                        start: Duration::from_millis(90_500),
+                    },
+                ],
+                chapters
+            );
+        }
+    }
+
+    #[test]
+    fn no_or_truncated_chapters() {
+        let mut file = mp4_box(b"ftyp", b"M4A isom");
+        file.extend(mp4_box(b"moov", &mp4_box(b"mvhd", &[0; 100])));
+        assert!(read_nero_chapters(&mut Cursor::new(&file))
+            .unwrap()
+            .is_empty());
+
+        let mut truncated = chpl(0, &[(0, "Whole"), (10_000_000, "Cut off")]);
+        truncated.truncate(truncated.len() - 3);
+        let chapters = parse_chpl(&truncated[8..]);
+        assert_eq!(1, chapters.len());
+        assert_eq!(Some("Whole"), chapters[0].title.as_deref());
+
+        assert!(read_nero_chapters(&mut Cursor::new(b"not an mp4 file"))
+            .unwrap()
+            .is_empty());
+    }
+}
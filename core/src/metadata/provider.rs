@@ -0,0 +1,347 @@
+// This file is part of Millenium Player.
+// Copyright (C) 2023 John DiSanti.
+//
+// Millenium Player is free software: you can redistribute it and/or modify it under the terms of
+// the GNU General Public License as published by the Free Software Foundation, either version 3 of
+// the License, or (at your option) any later version.
+//
+// Millenium Player is distributed in the hope that it will be useful, but WITHOUT ANY WARRANTY;
+// without even the implied warranty of MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE. See
+// the GNU General Public License for more details.
+//
+// You should have received a copy of the GNU General Public License along with Millenium Player.
+// If not, see <https://www.gnu.org/licenses/>.
+
+use super::Metadata;
+use crate::{
+    audio::source::{self, AudioSourceError},
+    location::Location,
+};
+use camino::Utf8PathBuf;
+use std::{fmt, fs, io, sync::Arc};
+
+#[derive(Debug, thiserror::Error)]
+pub enum MetadataProviderError {
+    #[error("failed to read tags: {0}")]
+    Tags(#[from] AudioSourceError),
+    #[error("failed to read sidecar file {path}: {source}")]
+    ReadSidecar {
+        path: Utf8PathBuf,
+        source: io::Error,
+    },
+    #[error("failed to parse sidecar file {path}: {source}")]
+    ParseSidecar {
+        path: Utf8PathBuf,
+        source: toml::de::Error,
+    },
+}
+
+#[derive(Debug, thiserror::Error)]
+#[error("unknown metadata provider \"{0}\"")]
+pub struct UnknownProviderError(String);
+
+/// A source of tags for audio files, such as the files themselves or an online database.
+pub trait MetadataProvider: Send + Sync {
+    /// Name the provider is configured by.
+    fn name(&self) -> &'static str;
+
+    /// Reads the tags for a location. Returns `None` if the provider has nothing for it.
+    fn read(&self, location: &Location) -> Result<Option<Metadata>, MetadataProviderError>;
+}
+
+/// Tags embedded in the audio file itself.
+pub struct EmbeddedTags;
+
+impl EmbeddedTags {
+    pub const NAME: &'static str = "embedded";
+}
+
+impl MetadataProvider for EmbeddedTags {
+    fn name(&self) -> &'static str {
+        Self::NAME
+    }
+
+    fn read(&self, location: &Location) -> Result<Option<Metadata>, MetadataProviderError> {
+        Ok(source::read_metadata(location)?)
+    }
+}
+
+/// Tags in a TOML file next to a local audio file, named after it with `.toml` appended
+/// (such as `01 Intro.flac.toml`), for correcting tags without rewriting the audio file.
+pub struct SidecarFile;
+
+impl SidecarFile {
+    pub const NAME: &'static str = "sidecar";
+}
+
+#[derive(Debug, Default, serde::Deserialize)]
+#[serde(default, deny_unknown_fields, rename_all = "kebab-case")]
+struct SidecarTags {
+    title: Option<String>,
+    artist: Option<String>,
+    album: Option<String>,
+    album_artist: Option<String>,
+    composer: Option<String>,
+    genre: Option<String>,
+    track_number: Option<u32>,
+    disc_number: Option<u32>,
+}
+
+impl From<SidecarTags> for Metadata {
+    fn from(tags: SidecarTags) -> Self {
+        Metadata {
+            track_title: tags.title,
+            artist: tags.artist,
+            album: tags.album,
+            album_artist: tags.album_artist,
+            composer: tags.composer,
+            genre: tags.genre,
+            track_number: tags.track_number.map(|number| number.to_string()),
+            disc_number: tags.disc_number.map(|number| number.to_string()),
+            ..Default::default()
+        }
+    }
+}
+
+impl MetadataProvider for SidecarFile {
+    fn name(&self) -> &'static str {
+        Self::NAME
+    }
+
+    fn read(&self, location: &Location) -> Result<Option<Metadata>, MetadataProviderError> {
+        let Some(path) = location.as_path() else {
+            return Ok(None);
+        };
+        let path = Utf8PathBuf::from(format!("{path}.toml"));
+        let contents = match fs::read_to_string(&path) {
+            Ok(contents) => contents,
+            Err(err) if err.kind() == io::ErrorKind::NotFound => return Ok(None),
+            Err(source) => return Err(MetadataProviderError::ReadSidecar { path, source }),
+        };
+        let tags: SidecarTags = toml::from_str(&contents)
+            .map_err(|source| MetadataProviderError::ParseSidecar { path, source })?;
+        Ok(Some(tags.into()))
+    }
+}
+
+/// Metadata providers that are consulted in order for each location. Earlier providers take
+/// precedence, and later ones fill in the tags that they didn't have.
+#[derive(Clone)]
+pub struct MetadataChain {
+    providers: Vec<Arc<dyn MetadataProvider>>,
+}
+
+impl Default for MetadataChain {
+    /// Sidecar files, and then the embedded tags.
+    fn default() -> Self {
+        Self::new(vec![Arc::new(SidecarFile), Arc::new(EmbeddedTags)])
+    }
+}
+
+impl fmt::Debug for MetadataChain {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_list()
+            .entries(self.providers.iter().map(|provider| provider.name()))
+            .finish()
+    }
+}
+
+impl MetadataChain {
+    pub fn new(providers: Vec<Arc<dyn MetadataProvider>>) -> Self {
+        Self { providers }
+    }
+
+    /// Builds a chain out of the built-in providers with the given names, in order.
+    pub fn from_names<S: AsRef<str>>(
+        names: impl IntoIterator<Item = S>,
+    ) -> Result<Self, UnknownProviderError> {
+        let providers = names
+            .into_iter()
+            .map(|name| match name.as_ref() {
+                EmbeddedTags::NAME => Ok(Arc::new(EmbeddedTags) as Arc<dyn MetadataProvider>),
+                SidecarFile::NAME => Ok(Arc::new(SidecarFile) as Arc<dyn MetadataProvider>),
+                name => Err(UnknownProviderError(name.into())),
+            })
+            .collect::<Result<_, _>>()?;
+        Ok(Self::new(providers))
+    }
+
+    /// Reads the tags for a location from every provider. Providers that fail are logged
+    /// and passed over.
+    pub fn read(&self, location: &Location) -> Option<Metadata> {
+        self.resolve(location, None)
+    }
+
+    /// Like `read`, but uses embedded tags that were already read rather than reading the
+    /// file's tags again.
+    pub fn read_with_embedded(
+        &self,
+        location: &Location,
+        embedded: Option<Metadata>,
+    ) -> Option<Metadata> {
+        self.resolve(location, Some(embedded))
+    }
+
+    fn resolve(
+        &self,
+        location: &Location,
+        mut embedded: Option<Option<Metadata>>,
+    ) -> Option<Metadata> {
+        let mut resolved: Option<Metadata> = None;
+        for provider in &self.providers {
+            let read = match embedded.take() {
+                Some(embedded) if provider.name() == EmbeddedTags::NAME => embedded,
+                taken => {
+                    embedded = taken;
+                    provider.read(location).unwrap_or_else(|err| {
+                        log::warn!("{} metadata for {location}: {err}", provider.name());
+                        None
+                    })
+                }
+            };
+            resolved = match (resolved, read) {
+                (Some(mut resolved), Some(read)) => {
+                    resolved.fill_from(read);
+                    Some(resolved)
+                }
+                (resolved, read) => resolved.or(read),
+            };
+        }
+        resolved
+    }
+}
+
+impl Metadata {
+    /// Fills in the tags that are missing with the ones from `other`.
+    fn fill_from(&mut self, other: Metadata) {
+        fn fill<T>(value: &mut Option<T>, other: Option<T>) {
+            if value.is_none() {
+                *value = other;
+            }
+        }
+        fill(&mut self.album, other.album);
+        fill(&mut self.album_artist, other.album_artist);
+        fill(&mut self.album_artist_sort, other.album_artist_sort);
+        fill(&mut self.album_sort, other.album_sort);
+        fill(&mut self.artist, other.artist);
+        fill(&mut self.artist_sort, other.artist_sort);
+        fill(&mut self.composer, other.composer);
+        fill(&mut self.cover, other.cover);
+        fill(&mut self.disc_number, other.disc_number);
+        fill(&mut self.disc_total, other.disc_total);
+        fill(&mut self.genre, other.genre);
+        fill(&mut self.track_number, other.track_number);
+        fill(&mut self.track_total, other.track_total);
+        fill(&mut self.track_title, other.track_title);
+        fill(&mut self.track_title_sort, other.track_title_sort);
+        fill(
+            &mut self.replay_gain.track_gain,
+            other.replay_gain.track_gain,
+        );
+        fill(
+            &mut self.replay_gain.track_peak,
+            other.replay_gain.track_peak,
+        );
+        fill(
+            &mut self.replay_gain.album_gain,
+            other.replay_gain.album_gain,
+        );
+        fill(
+            &mut self.replay_gain.album_peak,
+            other.replay_gain.album_peak,
+        );
+        // Lists come from a single provider rather than being mixed together
+        if self.artists.is_empty() {
+            self.artists = other.artists;
+        }
+        if self.genres.is_empty() {
+            self.genres = other.genres;
+        }
+        if self.chapters.is_empty() {
+            self.chapters = other.chapters;
+        }
+        self.other.extend(other.other);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const TEST_FILE: &str = "../test-data/melodic_a_minor/melodic_a_minor_2chan_44100hz_11s.ogg";
+
+    struct FixedTags(Metadata);
+
+    impl MetadataProvider for FixedTags {
+        fn name(&self) -> &'static str {
+            "fixed"
+        }
+
+        fn read(&self, _location: &Location) -> Result<Option<Metadata>, MetadataProviderError> {
+            Ok(Some(self.0.clone()))
+        }
+    }
+
+    #[test]
+    fn earlier_providers_take_precedence() {
+        let corrected = Metadata {
+            track_title: Some("Corrected".into()),
+            ..Default::default()
+        };
+        let location = Location::path(TEST_FILE);
+        let embedded = EmbeddedTags.read(&location).unwrap().expect("tags");
+        assert_ne!(embedded.track_title, corrected.track_title);
+        let chain =
+            MetadataChain::new(vec![Arc::new(FixedTags(corrected)), Arc::new(EmbeddedTags)]);
+
+        let resolved = chain.read(&location).expect("tags");
+        assert_eq!(Some("Corrected"), resolved.track_title.as_deref());
+        assert_eq!(embedded.artist, resolved.artist);
+        assert!(resolved.artist.is_some());
+
+        let prefetched = Metadata {
+            album: Some("Prefetched".into()),
+            ..Default::default()
+        };
+        let resolved = chain
+            .read_with_embedded(&location, Some(prefetched))
+            .expect("tags");
+        assert_eq!(Some("Corrected"), resolved.track_title.as_deref());
+        assert_eq!(Some("Prefetched"), resolved.album.as_deref());
+        assert_eq!(None, resolved.artist);
+    }
+
+    #[test]
+    fn sidecar_files() {
+        let dir = std::env::temp_dir().join(format!("metadata-sidecar-{}", std::process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        let audio = Utf8PathBuf::from_path_buf(dir.join("intro.flac")).unwrap();
+        let location = Location::Path(audio.clone());
+        assert!(SidecarFile.read(&location).unwrap().is_none());
+
+        fs::write(
+            format!("{audio}.toml"),
+            "title = \"Intro\"\nalbum-artist = \"Various\"\ntrack-number = 3\n",
+        )
+        .unwrap();
+        let tags = SidecarFile.read(&location).unwrap().expect("tags");
+        fs::write(format!("{audio}.toml"), "titel = \"Typo\"\n").unwrap();
+        let typo = SidecarFile.read(&location);
+        let _ = fs::remove_dir_all(&dir);
+
+        assert_eq!(Some("Intro"), tags.track_title.as_deref());
+        assert_eq!(Some("Various"), tags.album_artist.as_deref());
+        assert_eq!(Some(3), tags.parsed_track_number());
+        assert!(matches!(
+            typo,
+            Err(MetadataProviderError::ParseSidecar { .. })
+        ));
+    }
+
+    #[test]
+    fn chain_from_names() {
+        let chain = MetadataChain::from_names(["embedded", "sidecar"]).unwrap();
+        assert_eq!("[\"embedded\", \"sidecar\"]", format!("{chain:?}"));
+        assert!(MetadataChain::from_names(["discogs"]).is_err());
+    }
+}
@@ -12,12 +12,19 @@
 // You should have received a copy of the GNU General Public License along with Millenium Player.
 // If not, see <https://www.gnu.org/licenses/>.
 
+mod crossfade;
+mod cue;
+mod decode_ahead;
+/// Scripted fake player for testing code that drives the player without audio devices.
+#[cfg(any(test, feature = "test-util"))]
+pub mod fake;
 mod handle;
 mod state;
 mod thread;
+mod timeshift;
 pub mod waveform;
 
-pub use handle::PlayerThreadHandle;
+pub use handle::{PlayerHandle, PlayerThreadHandle};
 pub use thread::PlayerThread;
 
 #[derive(Debug, thiserror::Error)]
@@ -0,0 +1,274 @@
+// This file is part of Millenium Player.
+// Copyright (C) 2023 John DiSanti.
+//
+// Millenium Player is free software: you can redistribute it and/or modify it under the terms of
+// the GNU General Public License as published by the Free Software Foundation, either version 3 of
+// the License, or (at your option) any later version.
+//
+// Millenium Player is distributed in the hope that it will be useful, but WITHOUT ANY WARRANTY;
+// without even the implied warranty of MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE. See
+// the GNU General Public License for more details.
+//
+// You should have received a copy of the GNU General Public License along with Millenium Player.
+// If not, see <https://www.gnu.org/licenses/>.
+
+use crate::audio::{source::SourceBuffer, ChannelCount, SampleRate};
+use std::{f32::consts::FRAC_PI_2, time::Duration};
+
+/// Progress through a crossfade that has started.
+struct Mix {
+    /// Length of the crossfade in frames.
+    frames: usize,
+    /// Frames that have been mixed so far.
+    mixed: usize,
+}
+
+/// Mixes the end of one track into the start of the next.
+///
+/// While a crossfade is wanted, the end of the current track is held back instead of being
+/// queued, so that once the track runs out, it can be faded out under the start of the next one.
+/// Tracks with different sample rates or channel counts can't be mixed, so they're played back
+/// to back instead.
+pub(super) struct Crossfade {
+    length: Duration,
+    /// Audio from the end of the current track that hasn't been queued yet.
+    tail: SourceBuffer,
+    /// Gain of the track that the tail is from.
+    tail_gain: f32,
+    mix: Option<Mix>,
+    /// Audio that's ready to be queued.
+    output: SourceBuffer,
+}
+
+impl Crossfade {
+    pub(super) fn new() -> Self {
+        Self {
+            length: Duration::ZERO,
+            tail: SourceBuffer::empty(0, 0),
+            tail_gain: 1.0,
+            mix: None,
+            output: SourceBuffer::empty(0, 0),
+        }
+    }
+
+    /// Length of the crossfade into the next track. Zero plays them back to back.
+    pub(super) fn length(&self) -> Duration {
+        self.length
+    }
+
+    /// Changes the length of the next crossfade. One that already started isn't affected.
+    pub(super) fn set_length(&mut self, length: Duration) {
+        self.length = length;
+    }
+
+    /// Takes a decoded chunk, and returns the audio that's ready to be queued along with the
+    /// gain to queue it with. The end of the track is held back if `hold` is set.
+    ///
+    /// The gains are already applied to mixed audio, so it's returned with a gain of one.
+    pub(super) fn process<'a>(
+        &'a mut self,
+        chunk: &'a SourceBuffer,
+        gain: f32,
+        hold: bool,
+    ) -> (&'a SourceBuffer, f32) {
+        if self.mix.is_some() {
+            self.mix_into_output(chunk, gain);
+            return (&self.output, 1.0);
+        }
+        if hold {
+            if self.tail.frame_count() == 0 {
+                self.tail = SourceBuffer::empty(chunk.sample_rate(), chunk.channel_count());
+            }
+            self.tail.extend(chunk);
+            self.tail_gain = gain;
+            let length = frames(self.length, chunk.sample_rate());
+            let ready = self.tail.frame_count().saturating_sub(length);
+            self.tail.drain_into(ready, &mut self.output);
+            return (&self.output, gain);
+        }
+        if self.tail.frame_count() == 0 {
+            return (chunk, gain);
+        }
+        // The crossfade is no longer wanted, so the held back audio goes out first
+        let held = self.tail.frame_count();
+        self.tail.drain_into(held, &mut self.output);
+        self.output.extend(chunk);
+        (&self.output, gain)
+    }
+
+    /// Starts mixing the held back audio into the next track, which has the given format.
+    ///
+    /// Returns false if there's nothing to mix, or if the formats don't match, in which case
+    /// the held back audio should be released instead.
+    pub(super) fn start(&mut self, next_format: Option<(SampleRate, ChannelCount)>) -> bool {
+        let frames = self.tail.frame_count();
+        let format = (self.tail.sample_rate(), self.tail.channel_count());
+        if frames == 0 || next_format != Some(format) {
+            return false;
+        }
+        log::info!("crossfading over {frames} frames");
+        self.mix = Some(Mix { frames, mixed: 0 });
+        true
+    }
+
+    /// Returns the audio that's still held back along with the gain to queue it with, and
+    /// finishes fading it out if a crossfade is in progress.
+    pub(super) fn release(&mut self) -> Option<(&SourceBuffer, f32)> {
+        if self.tail.frame_count() == 0 {
+            self.mix = None;
+            return None;
+        }
+        if self.mix.is_some() {
+            let mut silence =
+                SourceBuffer::empty(self.tail.sample_rate(), self.tail.channel_count());
+            silence.extend_with_silence(self.tail.frame_count());
+            self.mix_into_output(&silence, 1.0);
+            return Some((&self.output, 1.0));
+        }
+        let held = self.tail.frame_count();
+        self.tail.drain_into(held, &mut self.output);
+        Some((&self.output, self.tail_gain))
+    }
+
+    /// Throws away the held back audio, such as after seeking.
+    pub(super) fn clear(&mut self) {
+        self.tail.clear();
+        self.mix = None;
+    }
+
+    /// Mixes the next track's chunk with the held back audio using equal power fades.
+    fn mix_into_output(&mut self, chunk: &SourceBuffer, gain: f32) {
+        let Mix {
+            frames: length,
+            mixed,
+        } = *self.mix.as_ref().expect("mixing");
+        let frames = chunk.frame_count();
+        if self.tail.frame_count() < frames {
+            // The tail runs out partway through the chunk
+            self.tail.extend_with_silence(frames);
+        }
+        let tail_gain = self.tail_gain;
+        if self.output.sample_rate() == chunk.sample_rate() {
+            self.output.make_empty_with_channels(chunk.channel_count());
+        } else {
+            self.output = SourceBuffer::empty(chunk.sample_rate(), chunk.channel_count());
+        }
+        self.output
+            .extend_mixed(&self.tail, chunk, frames, |frame| {
+                let progress = (mixed + frame) as f32 / length as f32;
+                if progress >= 1.0 {
+                    (0.0, gain)
+                } else {
+                    let angle = progress * FRAC_PI_2;
+                    (angle.cos() * tail_gain, angle.sin() * gain)
+                }
+            });
+        self.tail.discard_front(frames);
+        if mixed + frames >= length {
+            self.clear();
+        } else {
+            self.mix = Some(Mix {
+                frames: length,
+                mixed: mixed + frames,
+            });
+        }
+    }
+}
+
+fn frames(duration: Duration, sample_rate: SampleRate) -> usize {
+    (duration.as_secs_f64() * sample_rate as f64).round() as usize
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn constant(value: f32, frames: usize) -> SourceBuffer {
+        SourceBuffer::from_channels(4, vec![vec![value; frames]])
+    }
+
+    fn crossfade_holding(frames: usize) -> Crossfade {
+        let mut crossfade = Crossfade::new();
+        crossfade.set_length(Duration::from_secs(1));
+        let chunk = constant(1.0, frames);
+        let (output, gain) = crossfade.process(&chunk, 0.5, true);
+        assert_eq!(frames.saturating_sub(4), output.frame_count());
+        assert_eq!(0.5, gain);
+        crossfade
+    }
+
+    #[test]
+    fn holds_back_the_end() {
+        let mut crossfade = crossfade_holding(6);
+        let chunk = constant(1.0, 3);
+        let (output, gain) = crossfade.process(&chunk, 0.5, true);
+        assert_eq!(3, output.frame_count());
+        assert_eq!(0.5, gain);
+        assert_eq!(4, crossfade.tail.frame_count());
+
+        // Once the crossfade is no longer wanted, the held audio goes out ahead of the chunk
+        let chunk = constant(2.0, 1);
+        let (output, gain) = crossfade.process(&chunk, 0.5, false);
+        assert_eq!(&[1.0, 1.0, 1.0, 1.0, 2.0], output.channel(0));
+        assert_eq!(0.5, gain);
+        assert_eq!(0, crossfade.tail.frame_count());
+    }
+
+    #[test]
+    fn mixes_into_the_next_track() {
+        let mut crossfade = crossfade_holding(4);
+        assert!(crossfade.start(Some((4, 1))));
+
+        let chunk = constant(1.0, 2);
+        let (output, gain) = crossfade.process(&chunk, 1.0, false);
+        assert_eq!(1.0, gain);
+        let expected = [0.5, (FRAC_PI_2 / 4.0).cos() * 0.5 + (FRAC_PI_2 / 4.0).sin()];
+        assert_eq!(expected, output.channel(0));
+
+        // The fade finishes partway through the chunk, and the rest is only the next track
+        let chunk = constant(1.0, 4);
+        let (output, gain) = crossfade.process(&chunk, 1.0, false);
+        assert_eq!(1.0, gain);
+        assert_eq!(4, output.frame_count());
+        assert_eq!(&[1.0, 1.0], &output.channel(0)[2..]);
+        assert!(crossfade.mix.is_none());
+
+        let chunk = constant(1.0, 2);
+        let (output, gain) = crossfade.process(&chunk, 0.25, false);
+        assert_eq!(&[1.0, 1.0], output.channel(0));
+        assert_eq!(0.25, gain);
+    }
+
+    #[test]
+    fn fades_out_over_silence_when_the_next_track_is_short() {
+        let mut crossfade = crossfade_holding(4);
+        assert!(crossfade.start(Some((4, 1))));
+        crossfade.process(&constant(1.0, 1), 1.0, false);
+
+        let (output, gain) = crossfade.release().unwrap();
+        assert_eq!(1.0, gain);
+        assert_eq!(3, output.frame_count());
+        assert!(output.channel(0).iter().all(|&sample| sample < 0.5));
+        assert!(crossfade.release().is_none());
+    }
+
+    #[test]
+    fn mismatched_formats_play_back_to_back() {
+        let mut crossfade = crossfade_holding(4);
+        assert!(!crossfade.start(Some((8, 1))));
+        assert!(!crossfade.start(Some((4, 2))));
+        assert!(!crossfade.start(None));
+
+        let (output, gain) = crossfade.release().unwrap();
+        assert_eq!(&[1.0; 4], output.channel(0));
+        assert_eq!(0.5, gain);
+        assert!(crossfade.release().is_none());
+    }
+
+    #[test]
+    fn nothing_to_mix() {
+        let mut crossfade = Crossfade::new();
+        assert!(!crossfade.start(Some((4, 1))));
+        assert!(crossfade.release().is_none());
+    }
+}
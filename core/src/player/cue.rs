@@ -0,0 +1,206 @@
+// This file is part of Millenium Player.
+// Copyright (C) 2023 John DiSanti.
+//
+// Millenium Player is free software: you can redistribute it and/or modify it under the terms of
+// the GNU General Public License as published by the Free Software Foundation, either version 3 of
+// the License, or (at your option) any later version.
+//
+// Millenium Player is distributed in the hope that it will be useful, but WITHOUT ANY WARRANTY;
+// without even the implied warranty of MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE. See
+// the GNU General Public License for more details.
+//
+// You should have received a copy of the GNU General Public License along with Millenium Player.
+// If not, see <https://www.gnu.org/licenses/>.
+
+use crate::{
+    audio::{
+        device::AudioDevice,
+        sink::Sink,
+        source::{AudioDecoderSource, PreferredFormat},
+    },
+    location::Location,
+    message::PlayerMessage,
+};
+use millenium_post_office::{broadcast::Broadcaster, types::Volume};
+use std::time::Duration;
+
+/// Secondary output for pre-listening to a track on a separate audio device.
+///
+/// The cue output has its own device, sink, and volume so that it never interferes
+/// with what's playing on the main output device.
+pub(super) struct CueOutput {
+    device: Box<dyn AudioDevice>,
+    sink: Option<Sink>,
+    source: Option<AudioDecoderSource>,
+    /// Stops after this much audio, such as for previews.
+    limit: Option<Duration>,
+    queued: Duration,
+    finished: fn() -> PlayerMessage,
+}
+
+impl CueOutput {
+    pub(super) fn new(device: Box<dyn AudioDevice>) -> Self {
+        Self {
+            device,
+            sink: None,
+            source: None,
+            limit: None,
+            queued: Duration::ZERO,
+            finished: || PlayerMessage::EventCueFinished,
+        }
+    }
+
+    /// Output that plays only the start of each location, for previewing it alongside
+    /// what's playing on the main output.
+    pub(super) fn preview(device: Box<dyn AudioDevice>, length: Duration) -> Self {
+        Self {
+            limit: Some(length),
+            finished: || PlayerMessage::EventPreviewFinished,
+            ..Self::new(device)
+        }
+    }
+
+    /// True if the cue output is currently playing something.
+    pub(super) fn is_active(&self) -> bool {
+        self.source.is_some()
+    }
+
+    /// Loads a location and starts playing it on the cue device.
+    pub(super) fn load(&mut self, location: Location, broadcaster: &Broadcaster<PlayerMessage>) {
+        log::info!("cueing location: {location:?}");
+        self.stop();
+        self.queued = Duration::ZERO;
+        let preferred_format = PreferredFormat::new(
+            self.device.playback_sample_rate(),
+            self.device.playback_channels(),
+        );
+        match AudioDecoderSource::new(location.clone(), preferred_format) {
+            Ok(source) => {
+                self.source = Some(source);
+                if let Err(err) = self.device.play() {
+                    log::error!("failed to start cue device: {err}");
+                    broadcaster.broadcast(PlayerMessage::EventAudioDeviceFailed(err.to_string()));
+                }
+            }
+            Err(err) => {
+                log::error!("failed to load cue location: {err}");
+                let error = err.to_playback_error(&location);
+                broadcaster.broadcast(PlayerMessage::EventFailedToLoadLocation(error));
+            }
+        }
+    }
+
+    /// Stops playback on the cue device.
+    pub(super) fn stop(&mut self) {
+        self.source = None;
+        self.sink = None;
+        if let Err(err) = self.device.stop() {
+            log::error!("failed to stop cue device: {err}");
+        }
+    }
+
+    /// Sets the volume of the cue device independently of the main device.
+    pub(super) fn set_volume(&self, volume: Volume) {
+        self.device.set_volume(volume);
+    }
+
+    /// Decodes more audio for the cue device and sends it when requested.
+    ///
+    /// This never blocks so that it doesn't hold up the main output.
+    pub(super) fn update(&mut self, broadcaster: &Broadcaster<PlayerMessage>) {
+        self.device.release_if_idle();
+        self.queue_chunks(broadcaster);
+        if let Some(sink) = self.sink.as_ref() {
+            sink.send_audio_with_timeout(Duration::ZERO);
+        }
+    }
+
+    fn queue_chunks(&mut self, broadcaster: &Broadcaster<PlayerMessage>) {
+        let Some(source) = self.source.as_mut() else {
+            return;
+        };
+        while self
+            .sink
+            .as_ref()
+            .map(Sink::needs_more_chunks)
+            .unwrap_or(true)
+        {
+            match source.next_chunk() {
+                Ok(Some(chunk)) => {
+                    if chunk.frame_count() == 0 {
+                        continue;
+                    }
+                    let recreate_sink = match &self.sink {
+                        Some(sink) => {
+                            sink.input_channels() != chunk.channel_count()
+                                || sink.input_sample_rate() != chunk.sample_rate()
+                        }
+                        None => true,
+                    };
+                    if recreate_sink {
+                        if let Some(sink) = self.sink.as_ref() {
+                            sink.flush();
+                        }
+                        self.sink = Some(
+                            self.device
+                                .create_sink(chunk.sample_rate(), chunk.channel_count()),
+                        );
+                    }
+                    self.queued += Duration::from_secs_f64(
+                        chunk.frame_count() as f64 / chunk.sample_rate() as f64,
+                    );
+                    self.sink.as_ref().unwrap().queue(chunk);
+                    if self.limit.map_or(false, |limit| self.queued >= limit) {
+                        self.finish(broadcaster);
+                        break;
+                    }
+                }
+                Ok(None) => {
+                    self.finish(broadcaster);
+                    break;
+                }
+                Err(err) => {
+                    log::error!("error occurred while decoding cued audio: {err}");
+                    let error = err.to_playback_error(source.location());
+                    self.source = None;
+                    broadcaster.broadcast(PlayerMessage::EventFailedToDecodeAudio(error));
+                    break;
+                }
+            }
+        }
+    }
+
+    /// Lets the queued audio play out without decoding any more.
+    fn finish(&mut self, broadcaster: &Broadcaster<PlayerMessage>) {
+        log::info!("finished playing cued track");
+        if let Some(sink) = self.sink.as_ref() {
+            sink.flush();
+        }
+        self.source = None;
+        broadcaster.broadcast((self.finished)());
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{audio::device::NullAudioDevice, message::PlayerMessageChannel};
+
+    #[test]
+    fn previews_stop_after_their_length() {
+        let broadcaster = Broadcaster::new();
+        let sub = broadcaster.subscribe("test", PlayerMessageChannel::All);
+        let mut preview =
+            CueOutput::preview(Box::new(NullAudioDevice::new()), Duration::from_millis(200));
+
+        preview.load(
+            Location::path("../test-data/melodic_a_minor/melodic_a_minor_1chan_44100hz_6s.ogg"),
+            &broadcaster,
+        );
+        assert!(preview.is_active());
+        preview.update(&broadcaster);
+        assert!(!preview.is_active(), "it should stop decoding at the limit");
+        assert!(preview.queued < Duration::from_secs(1));
+        assert_eq!(Some(PlayerMessage::EventPreviewFinished), sub.try_recv());
+    }
+}
@@ -0,0 +1,135 @@
+// This file is part of Millenium Player.
+// Copyright (C) 2023 John DiSanti.
+//
+// Millenium Player is free software: you can redistribute it and/or modify it under the terms of
+// the GNU General Public License as published by the Free Software Foundation, either version 3 of
+// the License, or (at your option) any later version.
+//
+// Millenium Player is distributed in the hope that it will be useful, but WITHOUT ANY WARRANTY;
+// without even the implied warranty of MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE. See
+// the GNU General Public License for more details.
+//
+// You should have received a copy of the GNU General Public License along with Millenium Player.
+// If not, see <https://www.gnu.org/licenses/>.
+
+use crate::{
+    audio::source::{AudioDecoderSource, AudioSourceError, PreferredFormat},
+    location::Location,
+};
+use std::{
+    sync::mpsc::{self, Receiver, TryRecvError},
+    thread,
+};
+
+enum Worker {
+    Pending(Receiver<Result<AudioDecoderSource, AudioSourceError>>),
+    Ready(AudioDecoderSource),
+    Failed,
+}
+
+/// Opens and primes the next track's source on a worker thread, so that opening and
+/// probing the file doesn't hold up the player thread at the track transition.
+pub(super) struct DecodeAhead {
+    location: Location,
+    worker: Worker,
+}
+
+impl DecodeAhead {
+    /// Starts opening the location on a worker thread.
+    pub(super) fn start(location: Location, preferred_format: PreferredFormat) -> Self {
+        let (source_tx, source) = mpsc::channel();
+        let spawned = thread::Builder::new().name("decode-ahead".into()).spawn({
+            let location = location.clone();
+            move || {
+                let source =
+                    AudioDecoderSource::new(location, preferred_format).and_then(|mut source| {
+                        source.prime()?;
+                        Ok(source)
+                    });
+                let _ = source_tx.send(source);
+            }
+        });
+        if let Err(err) = spawned {
+            log::error!("failed to start the decode ahead thread: {err}");
+        }
+        log::info!("decoding ahead: {location:?}");
+        Self {
+            location,
+            worker: Worker::Pending(source),
+        }
+    }
+
+    /// Checks on the worker, and returns true once it's done, whether or not it succeeded.
+    pub(super) fn poll(&mut self) -> bool {
+        let Worker::Pending(source) = &self.worker else {
+            return true;
+        };
+        self.worker = match source.try_recv() {
+            Ok(Ok(source)) => Worker::Ready(source),
+            Ok(Err(err)) => {
+                // Leave it to the normal load to report the error after this track finishes
+                log::warn!("failed to decode {:?} ahead: {err}", self.location);
+                Worker::Failed
+            }
+            Err(TryRecvError::Disconnected) => Worker::Failed,
+            Err(TryRecvError::Empty) => return false,
+        };
+        true
+    }
+
+    /// The opened source, once the worker has finished with it.
+    pub(super) fn source(&self) -> Option<&AudioDecoderSource> {
+        match &self.worker {
+            Worker::Ready(source) => Some(source),
+            _ => None,
+        }
+    }
+
+    /// Takes the opened source. This is `None` if the worker failed or isn't done yet.
+    pub(super) fn into_source(self) -> Option<AudioDecoderSource> {
+        match self.worker {
+            Worker::Ready(source) => Some(source),
+            _ => None,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::time::Duration;
+
+    const PATH: &str = "../test-data/melodic_a_minor/melodic_a_minor_1chan_48000hz_6s.mp3";
+
+    fn finish(mut decode_ahead: DecodeAhead) -> Option<AudioDecoderSource> {
+        while !decode_ahead.poll() {
+            thread::sleep(Duration::from_millis(10));
+        }
+        decode_ahead.into_source()
+    }
+
+    #[test]
+    #[ntest::timeout(5000)]
+    fn primes_the_next_source() {
+        let format = PreferredFormat::new(44100, 2);
+        let decode_ahead = DecodeAhead::start(Location::path(PATH), format);
+        let mut primed = finish(decode_ahead).expect("loaded");
+
+        // The primed chunk comes out first, followed by the rest of the track as usual
+        let mut unprimed = AudioDecoderSource::new(Location::path(PATH), format).unwrap();
+        for _ in 0..3 {
+            assert_eq!(
+                unprimed.next_chunk().unwrap().unwrap().channel(0),
+                primed.next_chunk().unwrap().unwrap().channel(0),
+            );
+        }
+    }
+
+    #[test]
+    #[ntest::timeout(5000)]
+    fn failure() {
+        let format = PreferredFormat::new(44100, 2);
+        let decode_ahead = DecodeAhead::start(Location::path("does-not-exist.mp3"), format);
+        assert!(finish(decode_ahead).is_none());
+    }
+}
@@ -0,0 +1,222 @@
+// This file is part of Millenium Player.
+// Copyright (C) 2023 John DiSanti.
+//
+// Millenium Player is free software: you can redistribute it and/or modify it under the terms of
+// the GNU General Public License as published by the Free Software Foundation, either version 3 of
+// the License, or (at your option) any later version.
+//
+// Millenium Player is distributed in the hope that it will be useful, but WITHOUT ANY WARRANTY;
+// without even the implied warranty of MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE. See
+// the GNU General Public License for more details.
+//
+// You should have received a copy of the GNU General Public License along with Millenium Player.
+// If not, see <https://www.gnu.org/licenses/>.
+
+use crate::{
+    message::{PlayerMessage, PlayerMessageChannel},
+    player::{PlayerHandle, PlayerThreadError},
+};
+use millenium_post_office::broadcast::{BroadcastSubscription, Broadcaster};
+use std::{
+    collections::VecDeque,
+    sync::{Arc, Mutex},
+};
+
+/// How a fake player should respond to a command.
+type Response = Box<dyn FnMut(&PlayerMessage) -> Vec<PlayerMessage> + Send>;
+
+#[derive(Default)]
+struct Shared {
+    failure: Option<PlayerThreadError>,
+    exited: bool,
+}
+
+/// Fake player that records the commands it receives and replies with scripted events.
+///
+/// Commands are only processed when [`FakePlayer::process`] is called, which keeps tests
+/// deterministic. Responses are consumed in the order they were scripted, and commands
+/// received after the script runs out are recorded without a response.
+pub struct FakePlayer {
+    broadcaster: Broadcaster<PlayerMessage>,
+    subscription: BroadcastSubscription<PlayerMessage>,
+    shared: Arc<Mutex<Shared>>,
+    script: VecDeque<Response>,
+    received: Vec<PlayerMessage>,
+}
+
+impl Default for FakePlayer {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl FakePlayer {
+    /// Creates a new fake player with an empty script.
+    pub fn new() -> Self {
+        let broadcaster = Broadcaster::new();
+        let subscription = broadcaster.subscribe("fake-player", PlayerMessageChannel::Commands);
+        Self {
+            broadcaster,
+            subscription,
+            shared: Default::default(),
+            script: VecDeque::new(),
+            received: Vec::new(),
+        }
+    }
+
+    /// Creates a handle to this fake player that can be given to the code under test.
+    pub fn handle(&self) -> Box<dyn PlayerHandle> {
+        Box::new(FakePlayerHandle {
+            broadcaster: self.broadcaster.clone(),
+            shared: self.shared.clone(),
+        })
+    }
+
+    /// The broadcaster the fake player communicates over.
+    pub fn broadcaster(&self) -> &Broadcaster<PlayerMessage> {
+        &self.broadcaster
+    }
+
+    /// Adds a response to the script that replies to the next command with the given events.
+    pub fn respond_with(mut self, events: impl IntoIterator<Item = PlayerMessage>) -> Self {
+        let events: Vec<PlayerMessage> = events.into_iter().collect();
+        self.script.push_back(Box::new(move |_| events.clone()));
+        self
+    }
+
+    /// Adds a response to the script that computes the events to reply with from the command.
+    pub fn respond(
+        mut self,
+        response: impl FnMut(&PlayerMessage) -> Vec<PlayerMessage> + Send + 'static,
+    ) -> Self {
+        self.script.push_back(Box::new(response));
+        self
+    }
+
+    /// Broadcasts an event as if the player produced it.
+    pub fn send(&self, message: PlayerMessage) {
+        self.subscription.broadcast(message);
+    }
+
+    /// Processes all pending commands, replying to them with the scripted responses.
+    ///
+    /// Returns the commands that were processed.
+    pub fn process(&mut self) -> Vec<PlayerMessage> {
+        let mut processed = Vec::new();
+        while let Some(command) = self.subscription.try_recv() {
+            if let Some(mut response) = self.script.pop_front() {
+                for event in response(&command) {
+                    self.subscription.broadcast(event);
+                }
+            }
+            processed.push(command);
+        }
+        self.received.extend(processed.iter().cloned());
+        processed
+    }
+
+    /// All commands received so far.
+    pub fn received(&self) -> &[PlayerMessage] {
+        &self.received
+    }
+
+    /// Makes the fake player appear to have crashed with the given panic reason.
+    pub fn crash(&self, panic_reason: impl Into<String>) {
+        self.shared.lock().unwrap().failure = Some(PlayerThreadError::FailedToJoin {
+            panic_reason: panic_reason.into(),
+        });
+    }
+
+    /// Makes the fake player appear to have exited without an error.
+    pub fn exit(&self) {
+        self.shared.lock().unwrap().failure = Some(PlayerThreadError::EarlyExit);
+    }
+
+    /// True if the handle was joined.
+    pub fn joined(&self) -> bool {
+        self.shared.lock().unwrap().exited
+    }
+}
+
+struct FakePlayerHandle {
+    broadcaster: Broadcaster<PlayerMessage>,
+    shared: Arc<Mutex<Shared>>,
+}
+
+impl PlayerHandle for FakePlayerHandle {
+    fn healthcheck(self: Box<Self>) -> Result<Box<dyn PlayerHandle>, PlayerThreadError> {
+        let failure = self.shared.lock().unwrap().failure.take();
+        match failure {
+            Some(err) => Err(err),
+            None => Ok(self),
+        }
+    }
+
+    fn broadcaster(&self) -> &Broadcaster<PlayerMessage> {
+        &self.broadcaster
+    }
+
+    fn join(self: Box<Self>) -> Result<(), PlayerThreadError> {
+        let mut shared = self.shared.lock().unwrap();
+        shared.exited = true;
+        match shared.failure.take() {
+            Some(PlayerThreadError::EarlyExit) | None => Ok(()),
+            Some(err) => Err(err),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{location::Location, playlist::PlaylistManager};
+    use millenium_post_office::frontend::message::FrontendMessage;
+
+    #[test]
+    fn drive_playlist_manager() {
+        let mut player = FakePlayer::new()
+            .respond_with([
+                PlayerMessage::EventStartedTrack,
+                PlayerMessage::EventFinishedTrack,
+            ])
+            .respond_with([PlayerMessage::EventStartedTrack]);
+        let handle = player.handle();
+        let ui = Broadcaster::new();
+        let mut manager = PlaylistManager::new(handle.broadcaster().clone(), ui.clone());
+
+        ui.broadcast(FrontendMessage::LoadLocations {
+            locations: vec!["one.ogg".into(), "two.ogg".into()],
+        });
+        manager.update();
+        assert_eq!(
+            vec![PlayerMessage::CommandLoadAndPlayLocation(Location::path(
+                "one.ogg"
+            ))],
+            player.process()
+        );
+
+        // The scripted player finished the first track, so the manager should advance
+        manager.update();
+        assert_eq!(
+            vec![PlayerMessage::CommandLoadAndPlayLocation(Location::path(
+                "two.ogg"
+            ))],
+            player.process()
+        );
+        assert_eq!(2, player.received().len());
+
+        let handle = handle.healthcheck().expect("healthy");
+        player.crash("oops");
+        let err = handle.healthcheck().err().expect("crashed");
+        assert_eq!("failed to join player thread: oops", err.to_string());
+    }
+
+    #[test]
+    fn join() {
+        let player = FakePlayer::new();
+        let handle = player.handle();
+        handle.broadcaster().broadcast(PlayerMessage::CommandQuit);
+        handle.join().expect("success");
+        assert!(player.joined());
+    }
+}
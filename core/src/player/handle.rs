@@ -17,6 +17,23 @@ use millenium_post_office::broadcast::Broadcaster;
 use std::any::Any;
 use std::thread;
 
+/// Handle to a running player.
+///
+/// This is implemented by [`PlayerThreadHandle`] for the real player thread, and it allows
+/// a scripted fake player to be substituted in tests.
+pub trait PlayerHandle {
+    /// Checks that the player is still running.
+    ///
+    /// If the player has exited, then the reason it exited is returned as an error.
+    fn healthcheck(self: Box<Self>) -> Result<Box<dyn PlayerHandle>, PlayerThreadError>;
+
+    /// The broadcaster used to communicate with the player.
+    fn broadcaster(&self) -> &Broadcaster<PlayerMessage>;
+
+    /// Waits for the player to exit.
+    fn join(self: Box<Self>) -> Result<(), PlayerThreadError>;
+}
+
 pub struct PlayerThreadHandle {
     handle: thread::JoinHandle<()>,
     broadcaster: Broadcaster<PlayerMessage>,
@@ -65,3 +82,17 @@ impl PlayerThreadHandle {
         }
     }
 }
+
+impl PlayerHandle for PlayerThreadHandle {
+    fn healthcheck(self: Box<Self>) -> Result<Box<dyn PlayerHandle>, PlayerThreadError> {
+        PlayerThreadHandle::healthcheck(*self).map(|handle| Box::new(handle) as _)
+    }
+
+    fn broadcaster(&self) -> &Broadcaster<PlayerMessage> {
+        PlayerThreadHandle::broadcaster(self)
+    }
+
+    fn join(self: Box<Self>) -> Result<(), PlayerThreadError> {
+        PlayerThreadHandle::join(*self)
+    }
+}
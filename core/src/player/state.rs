@@ -13,17 +13,32 @@
 // If not, see <https://www.gnu.org/licenses/>.
 
 use crate::{
-    audio::source::{AudioDecoderSource, PreferredFormat},
+    audio::{
+        device::AudioDeviceError,
+        dynamic_range::DynamicRangeMeter,
+        source::{AudioDecoderSource, PreferredFormat, SourceBuffer},
+    },
+    clock::SharedClock,
     location::Location,
     message::PlayerMessage,
-    player::{thread::PlayerThreadResources, waveform::WaveformCalculator},
+    metadata::Metadata,
+    player::{
+        crossfade::Crossfade, decode_ahead::DecodeAhead, thread::PlayerThreadResources,
+        timeshift::Timeshift, waveform::WaveformCalculator,
+    },
+};
+use millenium_post_office::{
+    frontend::state::{LiveStatus, PlaybackStatus},
+    types::{AbLoop, NormalizationMode, Volume},
 };
-use millenium_post_office::{frontend::state::PlaybackStatus, types::Volume};
 use std::{
     mem,
     time::{Duration, Instant},
 };
 
+/// How long before the end of the current track to start opening the next one.
+const DECODE_AHEAD_LEAD: Duration = Duration::from_secs(10);
+
 trait State {
     fn update(self, resources: &mut PlayerThreadResources) -> CurrentState;
 }
@@ -39,7 +54,7 @@ enum CurrentState {
 }
 
 impl CurrentState {
-    fn handle_message(self, resources: &PlayerThreadResources, message: PlayerMessage) -> Self {
+    fn handle_message(self, resources: &mut PlayerThreadResources, message: PlayerMessage) -> Self {
         match message {
             PlayerMessage::CommandQuit => CurrentState::Quit,
             PlayerMessage::CommandPause => {
@@ -52,62 +67,202 @@ impl CurrentState {
             PlayerMessage::CommandResume => {
                 if matches!(self, CurrentState::Paused(_)) {
                     log::info!("resuming playback");
-                    let CurrentState::Paused(state) = self else {
+                    let CurrentState::Paused(mut state) = self else {
                         unreachable!()
                     };
-                    resources.device.play().unwrap();
-                    CurrentState::Playing(state)
+                    let within_timeshift = state
+                        .timeshift
+                        .as_mut()
+                        .map(Timeshift::resume)
+                        .unwrap_or(true);
+                    if !within_timeshift {
+                        log::info!("paused for longer than the timeshift covers");
+                        return state.jump_to_live(resources);
+                    }
+                    if device_ok(resources, resources.device.play()) {
+                        CurrentState::Playing(state)
+                    } else {
+                        // Such as when a released device can't be opened again
+                        if let Some(timeshift) = state.timeshift.as_mut() {
+                            timeshift.pause();
+                        }
+                        CurrentState::Paused(state)
+                    }
                 } else {
                     self
                 }
             }
             PlayerMessage::CommandStop => {
                 log::info!("stopping playback");
-                if matches!(self, CurrentState::Playing(_)) {
-                    if let Err(err) = resources.device.stop() {
-                        log::error!("failed to stop audio stream: {}", err);
+                if let CurrentState::Playing(state) = &self {
+                    device_ok(resources, resources.device.stop());
+                    // Status updates are only sent while playing, so let listeners know it stopped
+                    resources
+                        .broadcaster
+                        .broadcast(PlayerMessage::UpdatePlaybackStatus(PlaybackStatus {
+                            playing: false,
+                            ..state.status
+                        }));
+                    if state.ab_loop != AbLoop::default() {
                         resources
                             .broadcaster
-                            .broadcast(PlayerMessage::EventAudioDeviceFailed(err.to_string()));
+                            .broadcast(PlayerMessage::EventAbLoopChanged(AbLoop::default()));
                     }
+                    clear_waveform(resources);
                     CurrentState::DoNothing
                 } else {
                     self
                 }
             }
-            PlayerMessage::CommandSeek(position) => {
-                if let CurrentState::Playing(mut state) = self {
-                    log::info!("seeking to {}s", position.as_secs());
-                    resources.device.stop().unwrap();
-                    if let Err(err) = state.source.seek(position) {
-                        log::error!("failed to seek: {}", err);
-                        resources
-                            .broadcaster
-                            .broadcast(PlayerMessage::EventFailedToDecodeAudio(err.into()));
+            PlayerMessage::CommandMarkLoopStart => self.change_loop(resources, AbLoop::mark_a),
+            PlayerMessage::CommandMarkLoopEnd => self.change_loop(resources, AbLoop::mark_b),
+            PlayerMessage::CommandClearLoop => self.change_loop(resources, |ab_loop, _| {
+                *ab_loop = AbLoop::default();
+            }),
+            PlayerMessage::CommandSeek(position) => match self {
+                CurrentState::Playing(state) | CurrentState::Paused(state)
+                    if state.pending_boundary.is_none()
+                        && state
+                            .timeshift
+                            .as_ref()
+                            .is_some_and(|timeshift| timeshift.reaches_live(position)) =>
+                {
+                    state.jump_to_live(resources)
+                }
+                CurrentState::Playing(StatePlaying {
+                    pending_boundary: Some(_),
+                    ..
+                })
+                | CurrentState::Paused(StatePlaying {
+                    pending_boundary: Some(_),
+                    ..
+                }) => {
+                    // The previous track's source was already replaced by the next one's
+                    log::info!("ignoring command to seek since the next track is starting");
+                    self
+                }
+                CurrentState::Playing(mut state) => {
+                    if !state.seek(resources, position) {
                         CurrentState::DoNothing
-                    } else {
-                        resources.device.play().unwrap();
+                    } else if device_ok(resources, resources.device.play()) {
                         CurrentState::Playing(state)
+                    } else {
+                        state.transition_to_pause_state(resources)
                     }
-                } else {
+                }
+                // Seeking while paused stays paused at the new position
+                CurrentState::Paused(mut state) => {
+                    if state.seek(resources, position) {
+                        CurrentState::Paused(state)
+                    } else {
+                        CurrentState::DoNothing
+                    }
+                }
+                _ => {
                     log::info!("ignoring command to seek since we're not playing anything");
                     self
                 }
-            }
+            },
+            PlayerMessage::CommandJumpToLive => match self {
+                CurrentState::Playing(state) | CurrentState::Paused(state)
+                    if state.timeshift.is_some() && state.pending_boundary.is_none() =>
+                {
+                    state.jump_to_live(resources)
+                }
+                _ => {
+                    log::info!("ignoring command to jump to live since no live stream is playing");
+                    self
+                }
+            },
+            PlayerMessage::CommandSetNextLocation(location, crossfade) => match self {
+                CurrentState::Playing(mut state) => {
+                    state.set_next(location, crossfade);
+                    CurrentState::Playing(state)
+                }
+                CurrentState::Paused(mut state) => {
+                    state.set_next(location, crossfade);
+                    CurrentState::Paused(state)
+                }
+                _ => {
+                    log::info!("ignoring next location since nothing is playing");
+                    self
+                }
+            },
             PlayerMessage::CommandSetVolume(volume) => {
                 log::info!("setting volume to {}", volume.as_percentage());
                 resources.device.set_volume(volume);
                 self
             }
+            PlayerMessage::CommandDuck(level) => {
+                match level {
+                    Some(level) => log::info!("ducking to {}", level.as_percentage()),
+                    None => log::info!("restoring volume after ducking"),
+                }
+                resources.device.duck(level);
+                self
+            }
+            PlayerMessage::CommandSetNormalizationMode(mode) => {
+                log::info!("setting normalization mode to {mode:?}");
+                resources.normalization = mode;
+                self
+            }
+            PlayerMessage::CommandSetPlaylistGain(gain) => {
+                log::info!("setting playlist gain to {gain}");
+                resources.playlist_gain = gain;
+                self
+            }
+            PlayerMessage::CommandSetDspBypass(bypass) => {
+                log::info!("setting DSP bypass to {bypass}");
+                resources.dsp_bypass = bypass;
+                self
+            }
             PlayerMessage::CommandLoadAndPlayLocation(location) => {
                 log::info!("loading and playing location: {:?}", location);
-                CurrentState::LoadLocation(StateLoadLocation { location })
+                CurrentState::LoadLocation(StateLoadLocation {
+                    location,
+                    start_position: Duration::ZERO,
+                })
+            }
+            PlayerMessage::CommandLoadAndPlayLocationFrom(location, start_position) => {
+                log::info!(
+                    "loading and playing location from {}s: {:?}",
+                    start_position.as_secs_f64(),
+                    location
+                );
+                CurrentState::LoadLocation(StateLoadLocation {
+                    location,
+                    start_position,
+                })
             }
             _ => self,
         }
     }
 }
 
+impl CurrentState {
+    /// Changes the repeated region with the position that has been played up to.
+    fn change_loop(
+        self,
+        resources: &PlayerThreadResources,
+        change: impl FnOnce(&mut AbLoop, Duration),
+    ) -> Self {
+        match self {
+            CurrentState::Playing(mut state) => {
+                state.change_loop(resources, change);
+                CurrentState::Playing(state)
+            }
+            CurrentState::Paused(mut state) => {
+                state.change_loop(resources, change);
+                CurrentState::Paused(state)
+            }
+            _ => {
+                log::info!("ignoring loop command since nothing is playing");
+                self
+            }
+        }
+    }
+}
+
 impl State for CurrentState {
     fn update(self, resources: &mut PlayerThreadResources) -> Self {
         match self {
@@ -157,42 +312,404 @@ impl StateManager {
         let current = mem::take(&mut self.current);
         self.current = current.update(resources);
     }
+
+    /// Number of bytes of cover art held by the loaded sources' metadata.
+    pub(super) fn artwork_bytes(&self) -> usize {
+        match &self.current {
+            CurrentState::Playing(state) | CurrentState::Paused(state) => {
+                let next = state.next.as_ref().and_then(DecodeAhead::source);
+                let sources = Some(&state.source).into_iter().chain(next);
+                let boundary = state.pending_boundary.as_ref();
+                sources
+                    .filter_map(|source| source.metadata())
+                    .chain(boundary.and_then(|boundary| boundary.metadata.as_ref()))
+                    .filter_map(|metadata| metadata.cover.as_ref())
+                    .map(|cover| cover.data.len())
+                    .sum()
+            }
+            _ => 0,
+        }
+    }
 }
 
 struct StatePlaying {
     source: AudioDecoderSource,
     status: PlaybackStatus,
-    last_refresh_sent: Instant,
+    /// When the last status refresh was sent, or `None` to send one right away.
+    last_refresh_sent: Option<Instant>,
+    /// Position in the track that the device's consumed frame count is relative to.
+    position_offset: Duration,
+    /// Device frame that the current track started at. This is non-zero after a gapless
+    /// transition since the device's consumed frame count isn't reset between the tracks.
+    start_frame: u64,
+    /// Number of device frames that have been queued since the consumed frame count was reset.
+    ///
+    /// This is fractional since resampling doesn't produce a whole number of frames per chunk.
+    frames_queued: f64,
+    /// Location to continue with once the current one runs out so that there's no gap
+    /// between tracks. It's opened ahead of time once the current track is near its end.
+    next_location: Option<Location>,
+    /// The next location being opened on a worker thread.
+    next: Option<DecodeAhead>,
+    /// Mixes the end of the current track into the next location.
+    crossfade: Crossfade,
+    /// Set once decoding moved on to the next track, but the device is still playing
+    /// the end of the previous one.
+    pending_boundary: Option<TrackBoundary>,
+    /// Region of the track to repeat.
+    ab_loop: AbLoop,
+    /// Audio that was already played, if the source is a live stream.
+    timeshift: Option<Timeshift>,
+    /// Set once the current track played past its halfway point.
+    counted_play: bool,
+}
+
+/// Where the track that's being decoded starts in the device's consumed frame count.
+struct TrackBoundary {
+    frame: u64,
+    location: Location,
+    metadata: Option<Metadata>,
+}
+
+/// Result of queueing decoded audio into the sink.
+enum Queued {
+    /// The sink has enough audio for now.
+    Enough,
+    /// The source ran out of audio.
+    EndOfStream,
+    /// Decoding failed, and the error was broadcast.
+    Failed,
 }
 
 impl StatePlaying {
-    fn new(source: AudioDecoderSource, volume: Volume) -> Self {
+    fn new(
+        source: AudioDecoderSource,
+        volume: Volume,
+        position_offset: Duration,
+        clock: SharedClock,
+    ) -> Self {
+        let timeshift = Timeshift::for_live(source.location(), source.frame_count(), clock);
         Self {
             source,
             status: PlaybackStatus {
                 playing: true,
-                current_position: Duration::from_secs(0),
+                current_position: position_offset,
                 end_position: None,
                 volume,
+                live: None,
             },
-            last_refresh_sent: Instant::now() - Duration::from_secs(2),
+            last_refresh_sent: None,
+            position_offset,
+            start_frame: 0,
+            frames_queued: 0.0,
+            next_location: None,
+            next: None,
+            crossfade: Crossfade::new(),
+            pending_boundary: None,
+            ab_loop: AbLoop::default(),
+            timeshift,
+            counted_play: false,
         }
     }
 
+    /// Where a live stream is playing relative to the broadcast, or `None` for other sources.
+    fn live_status(&self) -> Option<LiveStatus> {
+        let timeshift = self.timeshift.as_ref()?;
+        Some(timeshift.status(self.status.current_position))
+    }
+
+    /// Reopens the live stream so that it plays what's being broadcast right now.
+    fn jump_to_live(self, resources: &mut PlayerThreadResources) -> CurrentState {
+        log::info!("jumping to the live stream");
+        device_ok(resources, resources.device.stop());
+        if let Some(sink) = resources.current_sink.as_ref() {
+            sink.clear();
+        }
+        CurrentState::LoadLocation(StateLoadLocation {
+            location: self.source.location().clone(),
+            start_position: Duration::ZERO,
+        })
+    }
+
+    /// Lets listeners know that the track counts as played once it passes the halfway point.
+    fn count_play(&mut self, resources: &PlayerThreadResources) {
+        let Some(end_position) = self.status.end_position else {
+            return;
+        };
+        if self.counted_play
+            || self.pending_boundary.is_some()
+            || self.status.current_position < end_position / 2
+        {
+            return;
+        }
+        self.counted_play = true;
+        resources
+            .broadcaster
+            .broadcast(PlayerMessage::EventTrackPlayed(
+                self.source.location().clone(),
+            ));
+    }
+
     fn transition_to_pause_state(mut self, resources: &PlayerThreadResources) -> CurrentState {
         log::info!("pausing playback");
         self.status.playing = false;
+        if let Some(timeshift) = self.timeshift.as_mut() {
+            timeshift.pause();
+        }
+        self.status.live = self.live_status();
         resources
             .broadcaster
             .broadcast(PlayerMessage::UpdatePlaybackStatus(self.status));
-        resources.device.pause().unwrap();
+        device_ok(resources, resources.device.pause());
+        resources
+            .broadcaster
+            .broadcast(PlayerMessage::UpdateWaveformCleared);
         CurrentState::Paused(self)
     }
+
+    /// Seeks the source, and throws away any audio that was queued from before the new position.
+    ///
+    /// Returns false if seeking failed. Failures are reported to listeners, after which
+    /// nothing should be playing.
+    fn seek(&mut self, resources: &mut PlayerThreadResources, position: Duration) -> bool {
+        log::info!("seeking to {}s", position.as_secs());
+        let played = self.played_position(resources);
+        // Stopping the device clears its output buffer
+        device_ok(resources, resources.device.stop());
+        if let Some(sink) = resources.current_sink.as_ref() {
+            sink.clear();
+        }
+        resources.measure_dynamic_range = false;
+        resources.dynamic_range_meter = None;
+        resources.device.reset_frames_consumed();
+        self.start_frame = 0;
+        self.frames_queued = 0.0;
+        self.crossfade.clear();
+        // Live streams are replayed from the audio that was already decoded
+        let position = match self.timeshift.as_mut() {
+            Some(timeshift) => timeshift.seek(position, played).unwrap_or(played),
+            None => position,
+        };
+        self.position_offset = position;
+        let seeked = match self.timeshift {
+            Some(_) => Ok(()),
+            None => self.source.seek(position),
+        };
+        if let Err(err) = seeked {
+            log::error!("failed to seek: {}", err);
+            let error = err.to_playback_error(self.source.location());
+            resources
+                .broadcaster
+                .broadcast(PlayerMessage::EventFailedToDecodeAudio(error));
+            return false;
+        }
+        // Let listeners know about the new position right away rather than on the next refresh
+        self.status.current_position = position;
+        self.status.live = self.live_status();
+        self.last_refresh_sent = Some(resources.clock.now());
+        resources
+            .broadcaster
+            .broadcast(PlayerMessage::UpdatePlaybackStatus(self.status));
+        true
+    }
+
+    /// Position in the track that the device has played up to. This is more precise than
+    /// the position in the status, which is only refreshed once a second.
+    fn played_position(&self, resources: &PlayerThreadResources) -> Duration {
+        let (frames_consumed, sample_rate) = (
+            resources.device.frames_consumed(),
+            resources.device.playback_sample_rate() as f64,
+        );
+        let frames_played = frames_consumed.saturating_sub(self.start_frame) as f64;
+        self.position_offset + Duration::from_secs_f64(frames_played / sample_rate)
+    }
+
+    fn change_loop(
+        &mut self,
+        resources: &PlayerThreadResources,
+        change: impl FnOnce(&mut AbLoop, Duration),
+    ) {
+        if self.pending_boundary.is_some() {
+            log::info!("ignoring loop command since the next track is starting");
+            return;
+        }
+        let position = self.played_position(resources);
+        change(&mut self.ab_loop, position);
+        log::info!("repeating region: {:?}", self.ab_loop);
+        resources
+            .broadcaster
+            .broadcast(PlayerMessage::EventAbLoopChanged(self.ab_loop));
+    }
+
+    /// Seeks back to point A once playback reaches point B.
+    ///
+    /// Returns false if seeking or playing from point A failed.
+    fn repeat_loop(&mut self, resources: &mut PlayerThreadResources) -> bool {
+        let Some((a, b)) = self.ab_loop.region() else {
+            return true;
+        };
+        if self.pending_boundary.is_some() || self.played_position(resources) < b {
+            return true;
+        }
+        self.seek(resources, a) && device_ok(resources, resources.device.play())
+    }
+
+    /// Sets the location to continue with once the current track runs out, and how long to
+    /// crossfade into it.
+    fn set_next(&mut self, location: Option<Location>, crossfade: Duration) {
+        self.next_location = location;
+        self.next = None;
+        self.crossfade.set_length(crossfade);
+    }
+
+    /// True once the current track is close enough to its end that the next one should
+    /// be opened. Streams don't have a known end, so their next location is opened right away.
+    fn near_end(&self) -> bool {
+        let lead = DECODE_AHEAD_LEAD + self.crossfade.length();
+        match self.status.end_position {
+            Some(end) => end.saturating_sub(self.status.current_position) <= lead,
+            None => self.source.frame_count().is_none(),
+        }
+    }
+
+    /// Whether the end of the current track should be held back to crossfade into the next one.
+    ///
+    /// Streams don't have an end to hold back, and a repeated region has to be able to play
+    /// up to the end of the track.
+    fn holds_crossfade(&self) -> bool {
+        !self.crossfade.length().is_zero()
+            && (self.next_location.is_some() || self.next.is_some())
+            && self.pending_boundary.is_none()
+            && self.ab_loop.region().is_none()
+            && self.source.frame_count().is_some()
+    }
+
+    /// Queues the audio that was held back for a crossfade that isn't going to happen.
+    fn release_crossfade(&mut self, resources: &mut PlayerThreadResources) {
+        if let Some((held, gain)) = self.crossfade.release() {
+            queue_buffer(resources, held, gain, &mut self.frames_queued);
+        }
+    }
+
+    /// Starts opening the next location on a worker thread if it's time to.
+    fn start_decode_ahead(&mut self, resources: &PlayerThreadResources, force: bool) {
+        if self.pending_boundary.is_some() || !(force || self.near_end()) {
+            return;
+        }
+        if let Some(location) = self.next_location.take() {
+            self.next = Some(DecodeAhead::start(location, preferred_format(resources)));
+        }
+    }
+
+    /// Queues audio into the sink, moving on to the next source without flushing
+    /// the sink if the current one runs out.
+    fn queue_chunks(&mut self, resources: &mut PlayerThreadResources) -> Queued {
+        loop {
+            let hold = self.holds_crossfade();
+            let timeshift = self.timeshift.as_mut();
+            match queue_chunks(
+                resources,
+                &mut self.source,
+                timeshift,
+                &mut self.crossfade,
+                hold,
+                &mut self.frames_queued,
+            ) {
+                Queued::EndOfStream
+                    if self.pending_boundary.is_none()
+                        && (self.next.is_some() || self.next_location.is_some()) =>
+                {
+                    // A short track can run out before it was time to start decoding ahead
+                    self.start_decode_ahead(resources, true);
+                    let next = self.next.as_mut().expect("started above");
+                    if !next.poll() {
+                        // Keep playing what's already queued while the worker catches up
+                        return Queued::Enough;
+                    }
+                    match self.next.take().and_then(DecodeAhead::into_source) {
+                        Some(next) => self.start_decoding_next(resources, next),
+                        None => {
+                            self.release_crossfade(resources);
+                            return Queued::EndOfStream;
+                        }
+                    }
+                }
+                Queued::EndOfStream => {
+                    self.release_crossfade(resources);
+                    return Queued::EndOfStream;
+                }
+                queued => return queued,
+            }
+        }
+    }
+
+    fn start_decoding_next(
+        &mut self,
+        resources: &mut PlayerThreadResources,
+        next: AudioDecoderSource,
+    ) {
+        log::info!("continuing with next location: {:?}", next.location());
+        // The next track starts where the crossfade starts
+        if !self.crossfade.start(next.decoded_format()) {
+            self.release_crossfade(resources);
+        }
+        finish_dynamic_range(resources);
+        resources.measure_dynamic_range = true;
+        self.pending_boundary = Some(TrackBoundary {
+            frame: self.frames_queued.round() as u64,
+            location: next.location().clone(),
+            metadata: next.metadata().cloned(),
+        });
+        self.timeshift =
+            Timeshift::for_live(next.location(), next.frame_count(), resources.clock.clone());
+        self.source = next;
+    }
+
+    /// Switches over to the next track once the device has played the last frame of the
+    /// previous one.
+    fn finish_pending_transition(&mut self, resources: &PlayerThreadResources, force: bool) {
+        let reached = match &self.pending_boundary {
+            Some(boundary) => force || resources.device.frames_consumed() >= boundary.frame,
+            None => false,
+        };
+        if !reached {
+            return;
+        }
+        let boundary = self.pending_boundary.take().expect("checked above");
+        log::info!("started next track at frame {}", boundary.frame);
+        self.ab_loop = AbLoop::default();
+        self.counted_play = false;
+        self.start_frame = boundary.frame;
+        self.position_offset = Duration::ZERO;
+        self.status.current_position = Duration::ZERO;
+        self.status.end_position = None;
+        // Send a status update right away since the position jumped back to zero
+        self.last_refresh_sent = None;
+        resources
+            .broadcaster
+            .broadcast(PlayerMessage::EventStartedNextTrack(boundary.location));
+        if let Some(metadata) = boundary.metadata {
+            resources
+                .broadcaster
+                .broadcast(PlayerMessage::EventMetadataLoaded(metadata));
+        }
+    }
 }
 
 impl State for StatePlaying {
     fn update(mut self, resources: &mut PlayerThreadResources) -> CurrentState {
-        let maybe_next_state = queue_chunks(resources, &mut self.source);
+        self.start_decode_ahead(resources, false);
+        if let Some(next) = self.next.as_mut() {
+            next.poll();
+        }
+        let queued = match self.queue_chunks(resources) {
+            // Point B can come before the end of the audio that's already queued
+            Queued::EndOfStream if self.ab_loop.region().is_some() => Queued::Enough,
+            queued => queued,
+        };
+        if matches!(queued, Queued::Enough) && !self.repeat_loop(resources) {
+            return CurrentState::DoNothing;
+        }
 
         if let Some(waveform_calc) = resources.waveform_calculator.as_mut() {
             let mut waveform_lock = resources.waveform.lock().unwrap();
@@ -205,31 +722,43 @@ impl State for StatePlaying {
             }
         }
 
-        let next_state = if let Some(new_state) = maybe_next_state {
-            new_state
-        } else {
-            if Instant::now() - self.last_refresh_sent >= Duration::from_secs(1) {
-                self.status.playing = true;
+        let next_state = match queued {
+            Queued::EndOfStream => {
+                // A very short track can run out before the device reaches its start
+                self.finish_pending_transition(resources, true);
+                finish_track(resources)
+            }
+            Queued::Failed => CurrentState::DoNothing,
+            Queued::Enough => {
+                self.finish_pending_transition(resources, false);
+                let refresh_due = self.last_refresh_sent.map_or(true, |sent| {
+                    resources.clock.elapsed(sent) >= Duration::from_secs(1)
+                });
+                if refresh_due {
+                    self.status.playing = true;
+                    self.status.current_position = self.played_position(resources);
+                    self.status.live = self.live_status();
+                    let sample_rate = resources.device.playback_sample_rate() as f64;
 
-                let (frames_consumed, sample_rate) = (
-                    resources.device.frames_consumed() as f64,
-                    resources.device.playback_sample_rate() as f64,
-                );
-                self.status.current_position =
-                    Duration::from_secs_f64(frames_consumed / sample_rate);
+                    // The frame count is for the source being decoded, which is the next track
+                    // rather than the one playing if a transition is pending
+                    let frame_count = self.source.frame_count();
+                    if self.status.end_position.is_none()
+                        && frame_count.is_some()
+                        && self.pending_boundary.is_none()
+                    {
+                        self.status.end_position =
+                            frame_count.map(|fc| Duration::from_secs_f64(fc as f64 / sample_rate));
+                    }
 
-                let frame_count = self.source.frame_count();
-                if self.status.end_position.is_none() && frame_count.is_some() {
-                    self.status.end_position =
-                        frame_count.map(|fc| Duration::from_secs_f64(fc as f64 / sample_rate));
+                    resources
+                        .broadcaster
+                        .broadcast(PlayerMessage::UpdatePlaybackStatus(self.status));
+                    self.last_refresh_sent = Some(resources.clock.now());
+                    self.count_play(resources);
                 }
-
-                resources
-                    .broadcaster
-                    .broadcast(PlayerMessage::UpdatePlaybackStatus(self.status));
-                self.last_refresh_sent = Instant::now();
+                CurrentState::Playing(self)
             }
-            CurrentState::Playing(self)
         };
         if let Some(sink) = resources.current_sink.as_ref() {
             sink.send_audio_with_timeout(Duration::from_millis(50));
@@ -240,114 +769,417 @@ impl State for StatePlaying {
 
 struct StateLoadLocation {
     location: Location,
+    start_position: Duration,
 }
 
 impl State for StateLoadLocation {
     fn update(self, resources: &mut PlayerThreadResources) -> CurrentState {
         log::info!("loading location: {:?}", self.location);
-        let preferred_format = PreferredFormat::new(
-            resources.device.playback_sample_rate(),
-            resources.device.playback_channels(),
-        );
-        let mut source = match AudioDecoderSource::new(self.location, preferred_format) {
-            Ok(source) => source,
-            Err(err) => {
-                log::error!("failed to load location: {}", err);
-                resources
-                    .broadcaster
-                    .broadcast(PlayerMessage::EventFailedToLoadLocation(err.into()));
-                return CurrentState::DoNothing;
-            }
-        };
+        let mut source =
+            match AudioDecoderSource::new(self.location.clone(), preferred_format(resources)) {
+                Ok(source) => source,
+                Err(err) => {
+                    log::error!("failed to load location: {}", err);
+                    let error = err.to_playback_error(&self.location);
+                    resources
+                        .broadcaster
+                        .broadcast(PlayerMessage::EventFailedToLoadLocation(error));
+                    return CurrentState::DoNothing;
+                }
+            };
+        let stream_health = source.stream_health().map(|health| {
+            let broadcaster = resources.broadcaster.clone();
+            health.on_change(move |health| {
+                broadcaster.broadcast(PlayerMessage::EventStreamHealthChanged(Some(health)))
+            });
+            let broadcaster = resources.broadcaster.clone();
+            health.on_throughput(move |throughput| {
+                broadcaster.broadcast(PlayerMessage::EventStreamThroughputMeasured(throughput))
+            });
+            let broadcaster = resources.broadcaster.clone();
+            health.on_title(move |title| {
+                broadcaster.broadcast(PlayerMessage::EventStreamTitleChanged(title))
+            });
+            health.get()
+        });
+        resources
+            .broadcaster
+            .broadcast(PlayerMessage::EventStreamHealthChanged(stream_health));
         if let Some(metadata) = source.metadata() {
             log::info!("loaded metaresources: {:?}", metadata);
             resources
                 .broadcaster
                 .broadcast(PlayerMessage::EventMetadataLoaded(metadata.clone()));
         }
-        resources
-            .device
-            .pause()
-            .expect("failed to pause audio stream");
-        let state = if let Some(new_state) = queue_chunks(resources, &mut source) {
-            new_state
-        } else {
-            resources
-                .broadcaster
-                .broadcast(PlayerMessage::EventStartedTrack);
-            CurrentState::Playing(StatePlaying::new(source, resources.device.volume()))
+        device_ok(resources, resources.device.pause());
+        // Only measure dynamic range if the whole track is going to be played
+        resources.measure_dynamic_range = self.start_position.is_zero();
+        resources.dynamic_range_meter = None;
+        if !self.start_position.is_zero() {
+            if let Err(err) = source.seek(self.start_position) {
+                log::error!("failed to seek to the start position: {}", err);
+                let error = err.to_playback_error(source.location());
+                resources
+                    .broadcaster
+                    .broadcast(PlayerMessage::EventFailedToDecodeAudio(error));
+                return CurrentState::DoNothing;
+            }
+        }
+        let mut playing = StatePlaying::new(
+            source,
+            resources.device.volume(),
+            self.start_position,
+            resources.clock.clone(),
+        );
+        let state = match playing.queue_chunks(resources) {
+            Queued::Enough => {
+                resources
+                    .broadcaster
+                    .broadcast(PlayerMessage::EventStartedTrack);
+                CurrentState::Playing(playing)
+            }
+            Queued::EndOfStream => finish_track(resources),
+            Queued::Failed => CurrentState::DoNothing,
         };
-        let device = &resources.device;
-        device.reset_frames_consumed();
-        device.play().expect("failed to pause audio stream");
-        state
+        resources.device.reset_frames_consumed();
+        if device_ok(resources, resources.device.play()) {
+            return state;
+        }
+        match state {
+            CurrentState::Playing(playing) => playing.transition_to_pause_state(resources),
+            state => state,
+        }
     }
 }
 
+fn preferred_format(resources: &PlayerThreadResources) -> PreferredFormat {
+    PreferredFormat::new(
+        resources.device.playback_sample_rate(),
+        resources.device.playback_channels(),
+    )
+}
+
+/// Queues decoded audio into the sink until it has enough, and counts how many
+/// device frames were queued.
+///
+/// The audio passes through the crossfade, which holds back the end of the track if `hold`
+/// is set, and mixes it into the start of the next one.
 fn queue_chunks(
     resources: &mut PlayerThreadResources,
     source: &mut AudioDecoderSource,
-) -> Option<CurrentState> {
+    mut timeshift: Option<&mut Timeshift>,
+    crossfade: &mut Crossfade,
+    hold: bool,
+    frames_queued: &mut f64,
+) -> Queued {
     while resources
         .current_sink
         .as_ref()
         .map(|s| s.needs_more_chunks())
         .unwrap_or(true)
     {
-        match source.next_chunk() {
+        // The gain is looked up before decoding since the decoded chunk borrows the source
+        let gain = match resources.normalization {
+            NormalizationMode::PlaylistPeak => resources.playlist_gain,
+            mode => source
+                .metadata()
+                .map(|metadata| metadata.replay_gain.linear_gain(mode))
+                .unwrap_or(1.0),
+        };
+        let replaying = timeshift.as_ref().is_some_and(|t| t.replaying());
+        let next_chunk = if replaying {
+            let timeshift = timeshift.as_deref_mut().expect("checked above");
+            Ok(timeshift.next_replayed())
+        } else {
+            let next_chunk = source.next_chunk();
+            if let (Ok(Some(chunk)), Some(timeshift)) = (&next_chunk, timeshift.as_deref_mut()) {
+                timeshift.record(chunk);
+            }
+            next_chunk
+        };
+        match next_chunk {
             Ok(Some(chunk)) => {
                 if chunk.frame_count() > 0 {
-                    let sample_rate = chunk.sample_rate();
-
-                    // Note that since we're doing this during audio decode, there is a slight
-                    // delay between the audio being played and the waveform being updated.
-                    // However, this delay is small enough as to not be noticeable.
-                    if resources.waveform_calculator.is_none() {
-                        resources.waveform_calculator = Some(WaveformCalculator::new(sample_rate));
-                    }
-                    let waveform_calc = resources.waveform_calculator.as_mut().unwrap();
-                    waveform_calc.push_source(&chunk);
-                    waveform_calc.calculate();
-
-                    let channels = chunk.channel_count();
-                    let recreate_sink = match &resources.current_sink {
-                        Some(sink) => {
-                            sink.input_channels() != channels
-                                || sink.input_sample_rate() != sample_rate
-                        }
-                        None => true,
-                    };
-                    if recreate_sink {
-                        log::info!("recreating the audio sink");
-                        if let Some(s) = resources.current_sink.as_ref() {
-                            s.flush();
+                    let (sample_rate, channels) = (chunk.sample_rate(), chunk.channel_count());
+                    if resources.measure_dynamic_range {
+                        let meter = resources
+                            .dynamic_range_meter
+                            .get_or_insert_with(|| DynamicRangeMeter::new(sample_rate, channels));
+                        if meter.channel_count() == channels {
+                            meter.push(chunk);
                         }
-                        resources.current_sink =
-                            Some(resources.device.create_sink(sample_rate, channels));
                     }
-                    let sink = resources.current_sink.as_ref().unwrap();
-                    sink.queue(&chunk);
-                }
-            }
-            Ok(None) => {
-                log::info!("finished playing track");
-                if let Some(sink) = resources.current_sink.as_ref() {
-                    sink.flush();
+                    let (ready, gain) = crossfade.process(chunk, gain, hold);
+                    queue_buffer(resources, ready, gain, frames_queued);
                 }
-                resources.waveform_calculator = None;
-                resources
-                    .broadcaster
-                    .broadcast(PlayerMessage::EventFinishedTrack);
-                return Some(CurrentState::DoNothing);
             }
+            Ok(None) => return Queued::EndOfStream,
             Err(err) => {
                 log::error!("error occurred while decoding audio: {}", err);
+                let error = err.to_playback_error(source.location());
                 resources
                     .broadcaster
-                    .broadcast(PlayerMessage::EventFailedToDecodeAudio(err.into()));
-                return Some(CurrentState::DoNothing);
+                    .broadcast(PlayerMessage::EventFailedToDecodeAudio(error));
+                return Queued::Failed;
             }
         }
     }
-    None
+    Queued::Enough
+}
+
+/// Queues audio into the sink with the given gain, recreating the sink if the audio's format
+/// changed, and counts how many device frames were queued.
+fn queue_buffer(
+    resources: &mut PlayerThreadResources,
+    buffer: &SourceBuffer,
+    gain: f32,
+    frames_queued: &mut f64,
+) {
+    if buffer.frame_count() == 0 {
+        return;
+    }
+    let (sample_rate, channels) = (buffer.sample_rate(), buffer.channel_count());
+
+    // Note that since this is done while queueing, there is a slight delay between the audio
+    // being played and the waveform being updated. However, this delay is small enough as
+    // to not be noticeable.
+    if resources.waveform_calculator.is_none() {
+        resources.waveform_calculator = Some(WaveformCalculator::new(
+            sample_rate,
+            resources.clock.clone(),
+        ));
+    }
+    let waveform_calc = resources.waveform_calculator.as_mut().unwrap();
+    waveform_calc.push_source(buffer);
+    waveform_calc.calculate();
+
+    let recreate_sink = match &resources.current_sink {
+        Some(sink) => sink.input_channels() != channels || sink.input_sample_rate() != sample_rate,
+        None => true,
+    };
+    if recreate_sink {
+        log::info!("recreating the audio sink");
+        if let Some(s) = resources.current_sink.as_ref() {
+            s.flush();
+        }
+        resources.current_sink = Some(resources.device.create_sink(sample_rate, channels));
+    }
+    let sink = resources.current_sink.as_ref().unwrap();
+    sink.set_gain(gain);
+    sink.set_bypass(resources.dsp_bypass);
+    sink.queue(buffer);
+    *frames_queued += buffer.frame_count() as f64 * resources.device.playback_sample_rate() as f64
+        / sample_rate as f64;
+}
+
+/// Flushes the remaining audio out to the device and tells listeners that the track finished.
+fn finish_track(resources: &mut PlayerThreadResources) -> CurrentState {
+    log::info!("finished playing track");
+    if let Some(sink) = resources.current_sink.as_ref() {
+        sink.flush();
+    }
+    clear_waveform(resources);
+    finish_dynamic_range(resources);
+    resources
+        .broadcaster
+        .broadcast(PlayerMessage::EventFinishedTrack);
+    CurrentState::DoNothing
+}
+
+/// Tells listeners if the audio device failed to play, pause, or stop, and returns whether
+/// it succeeded.
+fn device_ok(resources: &PlayerThreadResources, result: Result<(), AudioDeviceError>) -> bool {
+    match result {
+        Ok(()) => true,
+        Err(err) => {
+            log::error!("audio device failed: {err}");
+            resources
+                .broadcaster
+                .broadcast(PlayerMessage::EventAudioDeviceFailed(err.to_string()));
+            false
+        }
+    }
+}
+
+/// Broadcasts the dynamic range of the track that finished decoding if it was measured.
+fn finish_dynamic_range(resources: &mut PlayerThreadResources) {
+    if let Some(meter) = resources.dynamic_range_meter.take() {
+        if let Some(dynamic_range) = meter.finish() {
+            log::info!("measured dynamic range: DR{}", dynamic_range.0);
+            resources
+                .broadcaster
+                .broadcast(PlayerMessage::EventDynamicRangeMeasured(dynamic_range));
+        }
+    }
+}
+
+/// Drops the waveform calculation state and tells listeners that there's no waveform to show.
+fn clear_waveform(resources: &mut PlayerThreadResources) {
+    resources.waveform_calculator = None;
+    resources
+        .broadcaster
+        .broadcast(PlayerMessage::UpdateWaveformCleared);
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        audio::device::{AudioDevice, NullAudioDevice, UnpluggableAudioDevice},
+        clock::FakeClock,
+        message::PlayerMessageChannel,
+        player::waveform::Waveform,
+    };
+    use millenium_post_office::broadcast::{BroadcastSubscription, Broadcaster};
+    use std::sync::{
+        atomic::{AtomicBool, Ordering},
+        Arc, Mutex,
+    };
+
+    const TRACK: &str = "../test-data/melodic_a_minor/melodic_a_minor_2chan_44100hz_11s.ogg";
+
+    fn resources(clock: &FakeClock) -> PlayerThreadResources {
+        resources_with_device(clock, Box::new(NullAudioDevice::new()))
+    }
+
+    fn resources_with_device(
+        clock: &FakeClock,
+        device: Box<dyn AudioDevice>,
+    ) -> PlayerThreadResources {
+        PlayerThreadResources {
+            device,
+            current_sink: None,
+            waveform_calculator: None,
+            waveform: Arc::new(Mutex::new(Waveform::empty())),
+            broadcaster: Broadcaster::new(),
+            cue: None,
+            preview: None,
+            dynamic_range_meter: None,
+            measure_dynamic_range: false,
+            normalization: NormalizationMode::default(),
+            playlist_gain: 1.0,
+            dsp_bypass: false,
+            clock: clock.shared(),
+        }
+    }
+
+    fn status_updates(sub: &BroadcastSubscription<PlayerMessage>) -> usize {
+        std::iter::from_fn(|| sub.try_recv())
+            .filter(|message| matches!(message, PlayerMessage::UpdatePlaybackStatus(_)))
+            .count()
+    }
+
+    #[test]
+    fn playback_status_refresh_is_throttled() {
+        let clock = FakeClock::new();
+        let mut resources = resources(&clock);
+        let sub = resources
+            .broadcaster
+            .subscribe("test", PlayerMessageChannel::All);
+
+        let load = StateLoadLocation {
+            location: Location::path(TRACK),
+            start_position: Duration::ZERO,
+        };
+        let mut state = load.update(&mut resources);
+        assert!(matches!(state, CurrentState::Playing(_)));
+
+        // The first update refreshes right away, and the ones after it wait for the clock
+        for _ in 0..5 {
+            state = state.update(&mut resources);
+        }
+        assert_eq!(1, status_updates(&sub));
+
+        clock.advance(Duration::from_millis(999));
+        state = state.update(&mut resources);
+        assert_eq!(0, status_updates(&sub));
+
+        clock.advance(Duration::from_millis(1));
+        for _ in 0..5 {
+            state = state.update(&mut resources);
+        }
+        assert_eq!(1, status_updates(&sub));
+        assert!(matches!(state, CurrentState::Playing(_)));
+    }
+
+    fn unpluggable_resources(clock: &FakeClock) -> (PlayerThreadResources, Arc<AtomicBool>) {
+        let (device, unplugged) = UnpluggableAudioDevice::new();
+        (resources_with_device(clock, Box::new(device)), unplugged)
+    }
+
+    fn device_failures(sub: &BroadcastSubscription<PlayerMessage>) -> usize {
+        std::iter::from_fn(|| sub.try_recv())
+            .filter(|message| matches!(message, PlayerMessage::EventAudioDeviceFailed(_)))
+            .count()
+    }
+
+    #[test]
+    fn device_failing_to_play_after_loading_pauses() {
+        let clock = FakeClock::new();
+        let (mut resources, unplugged) = unpluggable_resources(&clock);
+        let sub = resources
+            .broadcaster
+            .subscribe("test", PlayerMessageChannel::All);
+        unplugged.store(true, Ordering::SeqCst);
+
+        let load = StateLoadLocation {
+            location: Location::path(TRACK),
+            start_position: Duration::ZERO,
+        };
+        let state = load.update(&mut resources);
+        assert!(matches!(state, CurrentState::Paused(_)));
+        assert_eq!(1, device_failures(&sub));
+    }
+
+    #[test]
+    fn device_failing_to_play_after_seeking_pauses() {
+        let clock = FakeClock::new();
+        let (mut resources, unplugged) = unpluggable_resources(&clock);
+        let sub = resources
+            .broadcaster
+            .subscribe("test", PlayerMessageChannel::All);
+
+        let load = StateLoadLocation {
+            location: Location::path(TRACK),
+            start_position: Duration::ZERO,
+        };
+        let state = load.update(&mut resources);
+        assert!(matches!(state, CurrentState::Playing(_)));
+
+        unplugged.store(true, Ordering::SeqCst);
+        let seek = PlayerMessage::CommandSeek(Duration::from_secs(2));
+        let state = state.handle_message(&mut resources, seek);
+        assert!(matches!(state, CurrentState::Paused(_)));
+        assert_eq!(1, device_failures(&sub));
+    }
+
+    #[test]
+    fn resuming_on_a_device_that_cant_be_reopened_stays_paused() {
+        let clock = FakeClock::new();
+        let (mut resources, unplugged) = unpluggable_resources(&clock);
+        let sub = resources
+            .broadcaster
+            .subscribe("test", PlayerMessageChannel::All);
+
+        let load = StateLoadLocation {
+            location: Location::path(TRACK),
+            start_position: Duration::ZERO,
+        };
+        let state = load.update(&mut resources);
+        let state = state.handle_message(&mut resources, PlayerMessage::CommandPause);
+        assert!(matches!(state, CurrentState::Paused(_)));
+
+        // The device goes away while it's released for being idle
+        resources.device.release_if_idle();
+        unplugged.store(true, Ordering::SeqCst);
+        let state = state.handle_message(&mut resources, PlayerMessage::CommandResume);
+        assert!(matches!(state, CurrentState::Paused(_)));
+        assert_eq!(1, device_failures(&sub));
+
+        // Resuming tries again once it's back
+        unplugged.store(false, Ordering::SeqCst);
+        let state = state.handle_message(&mut resources, PlayerMessage::CommandResume);
+        assert!(matches!(state, CurrentState::Playing(_)));
+        assert_eq!(0, device_failures(&sub));
+    }
 }
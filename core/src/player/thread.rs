@@ -12,20 +12,45 @@
 // You should have received a copy of the GNU General Public License along with Millenium Player.
 // If not, see <https://www.gnu.org/licenses/>.
 
+use crate::audio::channel_map::ChannelMaps;
 use crate::audio::device::{
-    create_device, AudioDevice, AudioDeviceMessage, AudioDeviceMessageChannel,
+    create_device, AudioDevice, AudioDeviceMessage, AudioDeviceMessageChannel, DeviceOptions,
 };
+use crate::audio::dynamic_range::DynamicRangeMeter;
 use crate::audio::sink::Sink;
+use crate::clock::{SharedClock, SystemClock};
 use crate::message::{PlayerMessage, PlayerMessageChannel};
 use crate::player::{
+    cue::CueOutput,
     state::StateManager,
     waveform::{Waveform, WaveformCalculator},
     {PlayerThreadError, PlayerThreadHandle},
 };
 use millenium_post_office::broadcast::{BroadcastSubscription, Broadcaster};
+use millenium_post_office::types::{BufferStats, NormalizationMode, Volume};
 use std::sync::{Arc, Mutex};
 use std::thread;
-use std::time::Duration;
+use std::time::{Duration, Instant};
+
+/// How often the buffer sizes are reported.
+const BUFFER_STATS_INTERVAL: Duration = Duration::from_secs(5);
+/// How much of a location is played when previewing it.
+const PREVIEW_LENGTH: Duration = Duration::from_secs(10);
+/// Previews play quieter than the main output so that they're easy to tell apart.
+const PREVIEW_GAIN: f32 = 0.5;
+/// How long to wait for messages while only the cue or preview output is playing. It's
+/// short enough to keep their buffers fed without spinning on them.
+const CUE_POLL_INTERVAL: Duration = Duration::from_millis(5);
+
+/// Buffer sizes above which a warning is logged since they likely indicate a leak.
+const BUFFER_STATS_THRESHOLDS: BufferStats = BufferStats {
+    // Ten seconds of audio at 48 kHz
+    sink_input_frames: 480_000,
+    // Ten seconds of stereo audio at 48 kHz
+    output_samples: 960_000,
+    waveform_samples: 100_000,
+    artwork_bytes: 32 * 1024 * 1024,
+};
 
 pub(super) struct PlayerThreadResources {
     pub(super) device: Box<dyn AudioDevice>,
@@ -33,6 +58,18 @@ pub(super) struct PlayerThreadResources {
     pub(super) waveform_calculator: Option<WaveformCalculator>,
     pub(super) waveform: Arc<Mutex<Waveform>>,
     pub(super) broadcaster: Broadcaster<PlayerMessage>,
+    pub(super) cue: Option<CueOutput>,
+    /// Plays the start of a location over the main output without interrupting it.
+    pub(super) preview: Option<CueOutput>,
+    pub(super) dynamic_range_meter: Option<DynamicRangeMeter>,
+    /// The dynamic range is only meaningful if the whole track was played without seeking.
+    pub(super) measure_dynamic_range: bool,
+    pub(super) normalization: NormalizationMode,
+    /// Gain for every track while normalizing to the playlist's peak.
+    pub(super) playlist_gain: f32,
+    /// Skips all processing so that it can be compared against the unprocessed audio.
+    pub(super) dsp_bypass: bool,
+    pub(super) clock: SharedClock,
 }
 
 /// Audio playback thread.
@@ -40,6 +77,13 @@ pub struct PlayerThread {
     resources: PlayerThreadResources,
     player_sub: BroadcastSubscription<PlayerMessage>,
     device_sub: BroadcastSubscription<AudioDeviceMessage>,
+    /// Kept for opening the cue device with its channel map.
+    channel_maps: ChannelMaps,
+    device_options: DeviceOptions,
+    /// Kept for opening previews on the same device as the main output.
+    preferred_output_device_name: Option<String>,
+    last_buffer_stats: BufferStats,
+    last_buffer_stats_sent: Instant,
 }
 
 impl PlayerThread {
@@ -48,9 +92,19 @@ impl PlayerThread {
         broadcaster: Broadcaster<PlayerMessage>,
         player_sub: BroadcastSubscription<PlayerMessage>,
         preferred_output_device_name: Option<String>,
+        channel_maps: ChannelMaps,
+        device_options: DeviceOptions,
     ) -> Self {
-        let device = match create_device(preferred_output_device_name.as_deref()) {
-            Ok(device) => device,
+        let start = Instant::now();
+        let device = match create_device(
+            preferred_output_device_name.as_deref(),
+            &channel_maps,
+            &device_options,
+        ) {
+            Ok(device) => {
+                log::info!("created audio output device in {:?}", start.elapsed());
+                device
+            }
             Err(err) => {
                 player_sub.broadcast(PlayerMessage::EventAudioDeviceCreationFailed(
                     err.source.into(),
@@ -70,24 +124,61 @@ impl PlayerThread {
                 waveform_calculator: None,
                 waveform: Arc::new(Mutex::new(Waveform::empty())),
                 broadcaster: broadcaster.clone(),
+                cue: None,
+                preview: None,
+                dynamic_range_meter: None,
+                measure_dynamic_range: false,
+                normalization: NormalizationMode::default(),
+                playlist_gain: 1.0,
+                dsp_bypass: false,
+                clock: SystemClock::shared(),
             },
             player_sub,
             device_sub,
+            channel_maps,
+            device_options,
+            preferred_output_device_name,
+            last_buffer_stats: BufferStats::default(),
+            last_buffer_stats_sent: Instant::now(),
         }
     }
 
+    /// Spawns the player thread. The channel maps are keyed by output device name.
     pub fn spawn(
         preferred_output_device_name: Option<String>,
+        channel_maps: ChannelMaps,
+        device_options: DeviceOptions,
+    ) -> Result<PlayerThreadHandle, PlayerThreadError> {
+        Self::spawn_with_broadcaster(
+            Broadcaster::new(),
+            preferred_output_device_name,
+            channel_maps,
+            device_options,
+        )
+    }
+
+    /// Spawns the player thread on an existing broadcaster, so that a player thread that
+    /// exited can be replaced without its subscribers having to subscribe again.
+    pub fn spawn_with_broadcaster(
+        broadcaster: Broadcaster<PlayerMessage>,
+        preferred_output_device_name: Option<String>,
+        channel_maps: ChannelMaps,
+        device_options: DeviceOptions,
     ) -> Result<PlayerThreadHandle, PlayerThreadError> {
-        let broadcaster = Broadcaster::new();
         let subscription = broadcaster.subscribe("player-thread", PlayerMessageChannel::Commands);
         let join_handle = thread::Builder::new()
             .name("player".into())
             .spawn({
                 let broadcaster = broadcaster.clone();
                 move || {
-                    PlayerThread::new(broadcaster, subscription, preferred_output_device_name)
-                        .run();
+                    PlayerThread::new(
+                        broadcaster,
+                        subscription,
+                        preferred_output_device_name,
+                        channel_maps,
+                        device_options,
+                    )
+                    .run();
                 }
             })
             .map_err(|source| PlayerThreadError::FailedToSpawn { source })?;
@@ -108,26 +199,179 @@ impl PlayerThread {
                     }
                     AudioDeviceMessage::EventPlaybackFinished => {}
                     AudioDeviceMessage::EventAudioDeviceIdle => {
-                        self.resources.device.pause().unwrap();
+                        if let Err(err) = self.resources.device.pause() {
+                            log::error!("failed to pause the idle audio device: {err}");
+                            self.player_sub
+                                .broadcast(PlayerMessage::EventAudioDeviceFailed(err.to_string()));
+                        }
                     }
                     _ => {}
                 }
             }
 
+            self.resources.device.release_if_idle();
+
+            let cue_active = [&self.resources.cue, &self.resources.preview]
+                .into_iter()
+                .flatten()
+                .any(CueOutput::is_active);
             let next_message = if state_manager.blocked_on_messages() {
                 // Use a timeout so that audio device messages are still handled
-                self.player_sub.recv_timeout(Duration::from_millis(500))
+                let timeout = if cue_active {
+                    CUE_POLL_INTERVAL
+                } else {
+                    Duration::from_millis(500)
+                };
+                self.player_sub.recv_timeout(timeout)
             } else {
                 self.player_sub.try_recv()
             };
             if let Some(message) = next_message {
                 log::info!("player received message: {message:?}");
-                state_manager.handle_message(&mut self.resources, message);
+                match message {
+                    PlayerMessage::CommandOpenCueDevice(_)
+                    | PlayerMessage::CommandCloseCueDevice
+                    | PlayerMessage::CommandCueLocation(_)
+                    | PlayerMessage::CommandStopCue
+                    | PlayerMessage::CommandSetCueVolume(_) => self.handle_cue_message(message),
+                    PlayerMessage::CommandPreviewLocation(_)
+                    | PlayerMessage::CommandStopPreview => self.handle_preview_message(message),
+                    message => state_manager.handle_message(&mut self.resources, message),
+                }
             }
             state_manager.update(&mut self.resources);
+            for cue in [&mut self.resources.cue, &mut self.resources.preview]
+                .into_iter()
+                .flatten()
+            {
+                cue.update(&self.resources.broadcaster);
+            }
+            if self.resources.clock.elapsed(self.last_buffer_stats_sent) >= BUFFER_STATS_INTERVAL {
+                self.report_buffer_stats(&state_manager);
+            }
         }
         log::info!("player thread finished");
     }
+
+    fn report_buffer_stats(&mut self, state_manager: &StateManager) {
+        let resources = &self.resources;
+        let sink = resources.current_sink.as_ref();
+        let stats = BufferStats {
+            sink_input_frames: sink.map(Sink::buffered_frames).unwrap_or_default(),
+            output_samples: sink.map(Sink::output_samples).unwrap_or_default(),
+            waveform_samples: resources
+                .waveform_calculator
+                .as_ref()
+                .map(WaveformCalculator::buffered_samples)
+                .unwrap_or_default(),
+            artwork_bytes: state_manager.artwork_bytes(),
+        };
+        // Only warn when a buffer first goes over its threshold so that the log isn't flooded
+        let previous = self.last_buffer_stats;
+        let limit = BUFFER_STATS_THRESHOLDS;
+        for (name, current, previous, limit) in [
+            (
+                "sink input frames",
+                stats.sink_input_frames,
+                previous.sink_input_frames,
+                limit.sink_input_frames,
+            ),
+            (
+                "output samples",
+                stats.output_samples,
+                previous.output_samples,
+                limit.output_samples,
+            ),
+            (
+                "waveform samples",
+                stats.waveform_samples,
+                previous.waveform_samples,
+                limit.waveform_samples,
+            ),
+            (
+                "artwork bytes",
+                stats.artwork_bytes,
+                previous.artwork_bytes,
+                limit.artwork_bytes,
+            ),
+        ] {
+            if current > limit && previous <= limit {
+                log::warn!("buffer size for {name} is {current}, which exceeds {limit}");
+            }
+        }
+
+        resources
+            .broadcaster
+            .broadcast(PlayerMessage::UpdateBufferStats(stats));
+        self.last_buffer_stats = stats;
+        self.last_buffer_stats_sent = self.resources.clock.now();
+    }
+
+    fn handle_cue_message(&mut self, message: PlayerMessage) {
+        let broadcaster = &self.resources.broadcaster;
+        match message {
+            PlayerMessage::CommandOpenCueDevice(name) => {
+                match create_device(name.as_deref(), &self.channel_maps, &self.device_options) {
+                    Ok(device) => self.resources.cue = Some(CueOutput::new(device)),
+                    Err(err) => {
+                        // The fallback device isn't useful for cueing, so just report the error
+                        broadcaster.broadcast(PlayerMessage::EventAudioDeviceCreationFailed(
+                            err.source.into(),
+                        ));
+                    }
+                }
+            }
+            PlayerMessage::CommandCloseCueDevice => {
+                if let Some(mut cue) = self.resources.cue.take() {
+                    cue.stop();
+                }
+            }
+            PlayerMessage::CommandCueLocation(location) => match self.resources.cue.as_mut() {
+                Some(cue) => cue.load(location, broadcaster),
+                None => log::warn!("ignoring cue command since no cue device is open"),
+            },
+            PlayerMessage::CommandStopCue => {
+                if let Some(cue) = self.resources.cue.as_mut() {
+                    cue.stop();
+                }
+            }
+            PlayerMessage::CommandSetCueVolume(volume) => {
+                if let Some(cue) = self.resources.cue.as_ref() {
+                    cue.set_volume(volume);
+                }
+            }
+            _ => unreachable!("not a cue message: {message:?}"),
+        }
+    }
+
+    fn handle_preview_message(&mut self, message: PlayerMessage) {
+        // Opening a new output each time means the preview never holds on to a device
+        // that the main output has since switched away from
+        if let Some(mut preview) = self.resources.preview.take() {
+            preview.stop();
+        }
+        let PlayerMessage::CommandPreviewLocation(location) = message else {
+            return;
+        };
+        let device = match create_device(
+            self.preferred_output_device_name.as_deref(),
+            &self.channel_maps,
+            &self.device_options,
+        ) {
+            Ok(device) => device,
+            Err(err) => {
+                self.resources.broadcaster.broadcast(
+                    PlayerMessage::EventAudioDeviceCreationFailed(err.source.into()),
+                );
+                return;
+            }
+        };
+        let main_volume = self.resources.device.volume().as_percentage();
+        device.set_volume(Volume::from_percentage(main_volume * PREVIEW_GAIN));
+        let mut preview = CueOutput::preview(device, PREVIEW_LENGTH);
+        preview.load(location, &self.resources.broadcaster);
+        self.resources.preview = Some(preview);
+    }
 }
 
 #[cfg(test)]
@@ -137,7 +381,8 @@ mod tests {
     #[test]
     #[ntest::timeout(1000)]
     fn spawn_and_close() {
-        let handle = PlayerThread::spawn(None).unwrap();
+        let handle =
+            PlayerThread::spawn(None, ChannelMaps::new(), DeviceOptions::default()).unwrap();
         handle.broadcaster().broadcast(PlayerMessage::CommandQuit);
         handle.join().expect("success");
     }
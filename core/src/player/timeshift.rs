@@ -0,0 +1,248 @@
+// This file is part of Millenium Player.
+// Copyright (C) 2023 John DiSanti.
+//
+// Millenium Player is free software: you can redistribute it and/or modify it under the terms of
+// the GNU General Public License as published by the Free Software Foundation, either version 3 of
+// the License, or (at your option) any later version.
+//
+// Millenium Player is distributed in the hope that it will be useful, but WITHOUT ANY WARRANTY;
+// without even the implied warranty of MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE. See
+// the GNU General Public License for more details.
+//
+// You should have received a copy of the GNU General Public License along with Millenium Player.
+// If not, see <https://www.gnu.org/licenses/>.
+
+use crate::{audio::source::SourceBuffer, clock::SharedClock, location::Location};
+use millenium_post_office::frontend::state::LiveStatus;
+use std::{
+    collections::VecDeque,
+    time::{Duration, Instant},
+};
+
+/// How far a live stream can fall behind, both from pausing and rewinding.
+///
+/// The stream keeps downloading while it's behind, so this stays well within what the
+/// connection reads ahead at typical bitrates. The decoded audio kept for rewinding takes
+/// about 20 MB at 44.1 kHz stereo.
+pub(super) const TIMESHIFT_LENGTH: Duration = Duration::from_secs(60);
+
+/// Audio of a live stream that was already decoded, so that it can be rewound and replayed.
+///
+/// Positions are in stream time, which is how much of the stream has been played since it
+/// was opened, and doesn't include the time spent paused.
+pub(super) struct Timeshift {
+    /// Decoded chunks with their start positions, oldest first.
+    history: VecDeque<(Duration, SourceBuffer)>,
+    /// Position just after the last chunk that was decoded from the stream.
+    decoded_end: Duration,
+    /// Index of the next chunk to replay from the history, while rewound.
+    replay: Option<usize>,
+    /// How far playback is behind the live stream from earlier pauses and rewinds.
+    behind: Duration,
+    paused_at: Option<Instant>,
+    clock: SharedClock,
+}
+
+impl Timeshift {
+    /// Creates a timeshift for the location if it's a live stream, which is a URL without
+    /// a known length.
+    pub fn for_live(
+        location: &Location,
+        frame_count: Option<u64>,
+        clock: SharedClock,
+    ) -> Option<Self> {
+        (location.as_url().is_some() && frame_count.is_none()).then(|| Self {
+            history: VecDeque::new(),
+            decoded_end: Duration::ZERO,
+            replay: None,
+            behind: Duration::ZERO,
+            paused_at: None,
+            clock,
+        })
+    }
+
+    /// Whether the next chunk comes from the history rather than the stream.
+    pub fn replaying(&self) -> bool {
+        self.replay.is_some()
+    }
+
+    /// Returns the next chunk to replay, and moves on to the stream after the last one.
+    pub fn next_replayed(&mut self) -> Option<&SourceBuffer> {
+        let index = self.replay?;
+        self.replay = Some(index + 1).filter(|next| *next < self.history.len());
+        self.history.get(index).map(|(_, chunk)| chunk)
+    }
+
+    /// Keeps a chunk that was just decoded from the stream, and forgets the chunks that are
+    /// older than the timeshift length.
+    pub fn record(&mut self, chunk: &SourceBuffer) {
+        let length =
+            Duration::from_secs_f64(chunk.frame_count() as f64 / chunk.sample_rate() as f64);
+        self.history.push_back((self.decoded_end, chunk.clone()));
+        self.decoded_end += length;
+        while let Some((start, _)) = self.history.front() {
+            if self.decoded_end.saturating_sub(*start) <= TIMESHIFT_LENGTH {
+                break;
+            }
+            self.history.pop_front();
+            if let Some(replay) = self.replay.as_mut() {
+                *replay = replay.saturating_sub(1);
+            }
+        }
+    }
+
+    /// Whether the position is past all of the decoded audio, so that getting there means
+    /// going back to the live stream.
+    pub fn reaches_live(&self, position: Duration) -> bool {
+        position >= self.decoded_end
+    }
+
+    /// Replays from the chunk that contains the position, and returns where that chunk starts.
+    /// Positions from before the timeshift start at its earliest chunk.
+    ///
+    /// `played` is the position that was playing, so that moving back or forward changes how
+    /// far behind the live stream playback is. Returns `None` if nothing was decoded yet.
+    pub fn seek(&mut self, position: Duration, played: Duration) -> Option<Duration> {
+        let index = self
+            .history
+            .iter()
+            .rposition(|(start, _)| *start <= position)
+            .unwrap_or_default();
+        let start = self.history.get(index)?.0;
+        self.behind = (self.behind + played).saturating_sub(start);
+        self.replay = Some(index);
+        Some(start)
+    }
+
+    pub fn pause(&mut self) {
+        self.paused_at = Some(self.clock.now());
+    }
+
+    /// Counts the time spent paused as time behind the live stream.
+    ///
+    /// Returns false if playback fell further behind than the timeshift can cover, in which
+    /// case it should go back to the live stream.
+    pub fn resume(&mut self) -> bool {
+        if let Some(paused_at) = self.paused_at.take() {
+            self.behind += self.clock.elapsed(paused_at);
+        }
+        self.behind <= TIMESHIFT_LENGTH
+    }
+
+    /// How far back playback can be rewound, and how far behind the live stream it is.
+    pub fn status(&self, played: Duration) -> LiveStatus {
+        let paused = self
+            .paused_at
+            .map(|paused_at| self.clock.elapsed(paused_at));
+        LiveStatus {
+            earliest_position: self
+                .history
+                .front()
+                .map(|(start, _)| *start)
+                .unwrap_or(played),
+            behind_live: self.behind + paused.unwrap_or_default(),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::clock::{FakeClock, SystemClock};
+    use std::str::FromStr;
+
+    fn live() -> Timeshift {
+        live_with_clock(&FakeClock::new())
+    }
+
+    fn live_with_clock(clock: &FakeClock) -> Timeshift {
+        let location = Location::from_str("http://example.com/stream").unwrap();
+        Timeshift::for_live(&location, None, clock.shared()).unwrap()
+    }
+
+    /// One second chunk at a low sample rate to keep the test light.
+    fn chunk(value: f32) -> SourceBuffer {
+        SourceBuffer::from_channels(10, vec![vec![value; 10]])
+    }
+
+    #[test]
+    fn only_live_streams() {
+        let stream = Location::from_str("http://example.com/stream").unwrap();
+        let file = Location::path("/music/song.mp3");
+        let clock = SystemClock::shared;
+        assert!(Timeshift::for_live(&stream, None, clock()).is_some());
+        assert!(Timeshift::for_live(&stream, Some(100), clock()).is_none());
+        assert!(Timeshift::for_live(&file, None, clock()).is_none());
+    }
+
+    #[test]
+    fn rewind_and_replay() {
+        let mut timeshift = live();
+        for value in 0..5 {
+            timeshift.record(&chunk(value as f32));
+        }
+        assert!(!timeshift.replaying());
+        assert!(!timeshift.reaches_live(Duration::from_millis(4900)));
+        assert!(timeshift.reaches_live(Duration::from_secs(5)));
+
+        // Rewinding from 4.5s to 2.5s replays from the start of the third chunk
+        assert_eq!(
+            Some(Duration::from_secs(2)),
+            timeshift.seek(Duration::from_millis(2500), Duration::from_millis(4500))
+        );
+        assert_eq!(
+            Duration::from_millis(2500),
+            timeshift.status(Duration::from_secs(2)).behind_live
+        );
+        let replayed: Vec<f32> =
+            std::iter::from_fn(|| timeshift.next_replayed().map(|chunk| chunk.channel(0)[0]))
+                .collect();
+        assert_eq!(vec![2.0, 3.0, 4.0], replayed);
+        assert!(!timeshift.replaying());
+
+        // Moving forward again catches back up
+        timeshift.seek(Duration::from_secs(4), Duration::from_secs(2));
+        assert_eq!(
+            Duration::from_millis(500),
+            timeshift.status(Duration::from_secs(4)).behind_live
+        );
+        assert_eq!(None, live().seek(Duration::from_secs(1), Duration::ZERO));
+    }
+
+    #[test]
+    fn forget_old_audio() {
+        let mut timeshift = live();
+        let chunks = TIMESHIFT_LENGTH.as_secs() + 5;
+        for value in 0..chunks {
+            timeshift.record(&chunk(value as f32));
+        }
+        let status = timeshift.status(Duration::from_secs(chunks));
+        assert_eq!(Duration::from_secs(5), status.earliest_position);
+        // Rewinding further back than the timeshift starts at its earliest audio
+        assert_eq!(
+            Some(Duration::from_secs(5)),
+            timeshift.seek(Duration::from_secs(1), Duration::from_secs(chunks))
+        );
+    }
+
+    #[test]
+    fn pausing_falls_behind() {
+        let clock = FakeClock::new();
+        let mut timeshift = live_with_clock(&clock);
+        timeshift.pause();
+        clock.advance(Duration::from_secs(5));
+        assert_eq!(
+            Duration::from_secs(5),
+            timeshift.status(Duration::ZERO).behind_live
+        );
+        assert!(timeshift.resume());
+        assert_eq!(
+            Duration::from_secs(5),
+            timeshift.status(Duration::ZERO).behind_live
+        );
+
+        timeshift.pause();
+        clock.advance(TIMESHIFT_LENGTH);
+        assert!(!timeshift.resume());
+    }
+}
@@ -12,7 +12,10 @@
 // You should have received a copy of the GNU General Public License along with Millenium Player.
 // If not, see <https://www.gnu.org/licenses/>.
 
-use crate::audio::{source::SourceBuffer, SampleRate};
+use crate::{
+    audio::{source::SourceBuffer, SampleRate},
+    clock::SharedClock,
+};
 use spectrum_analyzer::{samples_fft_to_spectrum, FrequencyLimit};
 use std::{
     f32::consts::PI,
@@ -23,10 +26,25 @@ const UPDATES_PER_SECOND: u64 = 30;
 const UPDATE_INTERVAL: Duration = Duration::from_millis(1000 / UPDATES_PER_SECOND);
 const DEFAULT_BINS: usize = 31;
 
+/// Lowest frequency shown in the spectrum.
+const MIN_RANGE_HZ: f32 = 20.0;
+/// Highest frequency shown in the spectrum, if the sample rate allows for it.
+const MAX_RANGE_HZ: f32 = 20_000.0;
+
+/// Approximate frequency in Hz that a spectrum bin represents.
+///
+/// The bins are spaced logarithmically from 20 Hz to 20 kHz, which is exact at sample rates
+/// of 40 kHz and up. The exact edges depend on the FFT's resolution.
+pub fn spectrum_bin_frequency(bin: usize, bin_count: usize) -> f32 {
+    let log_max = SpectrumCalculator::<DEFAULT_BINS>::log_max(MAX_RANGE_HZ);
+    let log = bin as f32 / (bin_count - 1) as f32 * log_max;
+    10f32.powf(log + 2.0) - 100.0 + MIN_RANGE_HZ
+}
+
 #[derive(Debug)]
 pub struct Waveform<const BIN_COUNT: usize = DEFAULT_BINS> {
-    last_spectrum_update: Instant,
-    last_amplitude_update: Instant,
+    last_spectrum_update: Option<Instant>,
+    last_amplitude_update: Option<Instant>,
     pub spectrum: [f32; BIN_COUNT],
     pub amplitude: [f32; BIN_COUNT],
 }
@@ -34,8 +52,8 @@ pub struct Waveform<const BIN_COUNT: usize = DEFAULT_BINS> {
 impl<const BIN_COUNT: usize> Waveform<BIN_COUNT> {
     pub fn empty() -> Self {
         Self {
-            last_spectrum_update: Instant::now() - Duration::from_secs(1),
-            last_amplitude_update: Instant::now() - Duration::from_secs(1),
+            last_spectrum_update: None,
+            last_amplitude_update: None,
             spectrum: [0f32; BIN_COUNT],
             amplitude: [0f32; BIN_COUNT],
         }
@@ -75,13 +93,13 @@ impl<const BIN_COUNT: usize> Drop for WaveformCalculator<BIN_COUNT> {
 }
 
 impl<const BIN_COUNT: usize> WaveformCalculator<BIN_COUNT> {
-    pub fn new(sample_rate: SampleRate) -> Self {
+    pub fn new(sample_rate: SampleRate, clock: SharedClock) -> Self {
         log::info!(
             "creating waveform calculator with {BIN_COUNT} bins and a sample rate of {sample_rate}"
         );
         Self {
-            spectrum: SpectrumCalculator::new(sample_rate),
-            amplitude: AmplitudeCalculator::new(sample_rate),
+            spectrum: SpectrumCalculator::new(sample_rate, clock.clone()),
+            amplitude: AmplitudeCalculator::new(sample_rate, clock),
         }
     }
 
@@ -101,6 +119,11 @@ impl<const BIN_COUNT: usize> WaveformCalculator<BIN_COUNT> {
         self.amplitude.push_source(source);
     }
 
+    /// Number of samples held for calculating the waveform.
+    pub fn buffered_samples(&self) -> usize {
+        self.spectrum.sample_buffer.len() + self.amplitude.sample_buffer.len()
+    }
+
     pub fn copy_latest_waveform_into(&self, waveform: &mut Waveform<BIN_COUNT>) {
         self.spectrum.copy_latest_waveform_into(waveform);
         self.amplitude.copy_latest_waveform_into(waveform);
@@ -113,11 +136,12 @@ struct SpectrumCalculator<const BIN_COUNT: usize> {
     sample_buffer: Vec<f32>,
     calc_buffer: Vec<f32>,
     output_buffer: [f32; BIN_COUNT],
-    last_calculate: Instant,
+    last_calculate: Option<Instant>,
+    clock: SharedClock,
 }
 
 impl<const BIN_COUNT: usize> SpectrumCalculator<BIN_COUNT> {
-    fn new(sample_rate: SampleRate) -> Self {
+    fn new(sample_rate: SampleRate, clock: SharedClock) -> Self {
         let required_samples = 8192;
         Self {
             sample_rate,
@@ -126,7 +150,8 @@ impl<const BIN_COUNT: usize> SpectrumCalculator<BIN_COUNT> {
             // buffer at a time, and thus, could exceed the required number of samples.
             sample_buffer: Vec::with_capacity(required_samples + required_samples / 2),
             output_buffer: [0f32; BIN_COUNT],
-            last_calculate: Instant::now() - Duration::from_secs(1),
+            last_calculate: None,
+            clock,
             calc_buffer: vec![0f32; required_samples],
         }
     }
@@ -152,7 +177,9 @@ impl<const BIN_COUNT: usize> SpectrumCalculator<BIN_COUNT> {
 
     pub fn calculate(&mut self) -> bool {
         if self.sample_buffer.len() < self.required_samples
-            || Instant::now() - self.last_calculate < UPDATE_INTERVAL
+            || self
+                .last_calculate
+                .map_or(false, |last| self.clock.elapsed(last) < UPDATE_INTERVAL)
         {
             return false;
         }
@@ -166,8 +193,7 @@ impl<const BIN_COUNT: usize> SpectrumCalculator<BIN_COUNT> {
                 .copied(),
         );
 
-        const MIN_RANGE_HZ: f32 = 20.0;
-        let max_range_hz: f32 = f32::min(self.sample_rate as f32 / 2.0, 20_000.0);
+        let max_range_hz: f32 = f32::min(self.sample_rate as f32 / 2.0, MAX_RANGE_HZ);
 
         Self::apply_hamming_window(&mut self.calc_buffer);
         let spectrum = samples_fft_to_spectrum(
@@ -189,7 +215,7 @@ impl<const BIN_COUNT: usize> SpectrumCalculator<BIN_COUNT> {
             let value = (value.val() + 1.0).log10() * 0.3;
             self.output_buffer[bin] = f32::max(self.output_buffer[bin], value);
         }
-        self.last_calculate = Instant::now();
+        self.last_calculate = Some(self.clock.now());
         true
     }
 
@@ -224,11 +250,12 @@ struct AmplitudeCalculator<const BIN_COUNT: usize> {
     required_samples: usize,
     sample_buffer: Vec<f32>,
     output_buffer: [f32; BIN_COUNT],
-    last_calculate: Instant,
+    last_calculate: Option<Instant>,
+    clock: SharedClock,
 }
 
 impl<const BIN_COUNT: usize> AmplitudeCalculator<BIN_COUNT> {
-    fn new(sample_rate: SampleRate) -> Self {
+    fn new(sample_rate: SampleRate, clock: SharedClock) -> Self {
         // We want the full range of bins to represent one second of audio
         let required_samples = sample_rate as usize / BIN_COUNT;
         Self {
@@ -240,13 +267,16 @@ impl<const BIN_COUNT: usize> AmplitudeCalculator<BIN_COUNT> {
             // buffer at a time, and thus, could exceed the required number of samples.
             sample_buffer: Vec::with_capacity(required_samples + required_samples / 2),
             output_buffer: [0f32; BIN_COUNT],
-            last_calculate: Instant::now() - Duration::from_secs(1),
+            last_calculate: None,
+            clock,
         }
     }
 
     pub fn calculate(&mut self) -> bool {
         if self.sample_buffer.len() < self.required_samples
-            || Instant::now() - self.last_calculate < UPDATE_INTERVAL
+            || self
+                .last_calculate
+                .map_or(false, |last| self.clock.elapsed(last) < UPDATE_INTERVAL)
         {
             return false;
         }
@@ -254,7 +284,7 @@ impl<const BIN_COUNT: usize> AmplitudeCalculator<BIN_COUNT> {
         let sum: f32 = to_process.sum();
         let amplitude = f32::min(1.0, 2.0 * sum / self.required_samples as f32);
         self.push_calculation(amplitude);
-        self.last_calculate = Instant::now();
+        self.last_calculate = Some(self.clock.now());
         self.sample_buffer.clear();
         true
     }
@@ -293,3 +323,32 @@ impl<const BIN_COUNT: usize> AmplitudeCalculator<BIN_COUNT> {
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::clock::FakeClock;
+
+    #[test]
+    fn amplitude_is_throttled() {
+        let clock = FakeClock::new();
+        let mut calculator = AmplitudeCalculator::<10>::new(1000, clock.shared());
+        let source = SourceBuffer::from_channels(1000, vec![vec![0.25; 100]]);
+
+        calculator.push_source(&source);
+        assert!(
+            calculator.calculate(),
+            "the first calculation shouldn't wait"
+        );
+
+        calculator.push_source(&source);
+        assert!(
+            !calculator.calculate(),
+            "it shouldn't calculate again before the update interval"
+        );
+
+        clock.advance(UPDATE_INTERVAL);
+        assert!(calculator.calculate());
+        assert_eq!(0.5, calculator.output_buffer[9]);
+    }
+}
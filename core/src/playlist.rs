@@ -13,18 +13,87 @@
 // If not, see <https://www.gnu.org/licenses/>.
 
 use crate::{
+    audio::{
+        analysis_cache::AnalysisCache,
+        dynamic_range::DynamicRange,
+        peak::{playlist_peak_gain, PeakScan},
+        test_signal,
+    },
+    clock::{SharedClock, SystemClock},
     location::Location,
     message::{PlayerMessage, PlayerMessageChannel},
-    metadata::Metadata,
+    metadata::{Chapter, Metadata, MetadataChain, TagSeparators},
 };
+use camino::{Utf8Path, Utf8PathBuf};
 use millenium_post_office::{
     broadcast::{BroadcastSubscription, Broadcaster, NoChannels},
-    frontend::message::{AlertLevel, FrontendMessage, PlaylistMode},
-    frontend::state::PlaybackStatus,
+    frontend::message::{
+        AlertLevel, FrontendMessage, PlaylistMode, PlaylistSortKey, TrackTransition,
+    },
+    frontend::state::{total_duration, PlaybackStatus, PlaylistStateData},
+    types::{
+        PlaybackError, PlaylistEntryDetails, QueuedTrack, StreamQuality, TestSignal, TrackRating,
+    },
+};
+use std::{
+    collections::{HashMap, HashSet, VecDeque},
+    mem,
+    ops::Deref,
+    str::FromStr,
+    time::{Duration, Instant, SystemTime},
 };
-use std::{ops::Deref, str::FromStr, time::Duration};
 
-#[derive(Copy, Clone, Debug, Eq, PartialEq, serde::Serialize)]
+mod album;
+mod duplicate;
+mod file;
+mod folder;
+mod history;
+mod metadata_scan;
+mod quality;
+mod ratings;
+mod shuffle;
+mod sort;
+
+pub use file::{PlaylistFileError, PlaylistFormat};
+pub use folder::FolderDefaults;
+pub use history::{PlayHistory, Plays, Skip, SkipHistory};
+pub use ratings::{TrackRatings, TrackRatingsError};
+pub use sort::SortOptions;
+
+use album::DirectoryExpansion;
+use file::ListedEntry;
+use history::SongHistory;
+use metadata_scan::{MetadataScan, ScannedEntry};
+use quality::Variant;
+use shuffle::ShuffleOrder;
+
+/// Identifies the album an entry belongs to for shuffling albums. Entries without an album
+/// tag are grouped by the folder they're in.
+fn album_key(entry: &PlaylistEntry) -> Option<String> {
+    let metadata = entry.metadata.as_ref();
+    match metadata.and_then(|metadata| metadata.album.as_ref()) {
+        Some(album) => {
+            let artist = metadata.and_then(|m| m.album_artist.as_ref().or(m.artist.as_ref()));
+            Some(format!(
+                "{}\n{album}",
+                artist.map(String::as_str).unwrap_or_default()
+            ))
+        }
+        None => entry
+            .location
+            .as_path()
+            .and_then(|path| path.parent())
+            .map(|folder| folder.to_string()),
+    }
+}
+
+/// How deeply playlists that list other playlists are followed, which guards against cycles.
+const MAX_PLAYLIST_NESTING: usize = 4;
+
+/// Fraction of a track after which skipping it isn't counted, since it was nearly over.
+const SKIP_NEAR_END: f64 = 0.9;
+
+#[derive(Copy, Clone, Debug, Eq, Hash, PartialEq, serde::Serialize)]
 pub struct PlaylistEntryId(usize);
 
 impl Deref for PlaylistEntryId {
@@ -50,15 +119,45 @@ impl Deref for PlaylistIndex {
 #[cfg_attr(test, derive(Eq, PartialEq))]
 pub struct MinimalMetadata {
     artist: Option<String>,
+    artists: Vec<String>,
     album_artist: Option<String>,
+    album: Option<String>,
     title: Option<String>,
 }
 
+impl MinimalMetadata {
+    fn from_title(title: String) -> Self {
+        MinimalMetadata {
+            artist: None,
+            artists: Vec::new(),
+            album_artist: None,
+            album: None,
+            title: Some(title),
+        }
+    }
+
+    /// Metadata given for an entry by the playlist file that listed it, if any.
+    fn from_listed(listed: &ListedEntry) -> Option<Self> {
+        if listed.artist.is_none() && listed.title.is_none() {
+            return None;
+        }
+        Some(MinimalMetadata {
+            artist: listed.artist.clone(),
+            artists: listed.artist.iter().cloned().collect(),
+            album_artist: None,
+            album: None,
+            title: listed.title.clone(),
+        })
+    }
+}
+
 impl From<&Metadata> for MinimalMetadata {
     fn from(value: &Metadata) -> Self {
         MinimalMetadata {
             artist: value.artist.clone(),
+            artists: value.artists.clone(),
             album_artist: value.album_artist.clone(),
+            album: value.album.clone(),
             title: value.track_title.clone(),
         }
     }
@@ -72,6 +171,80 @@ pub struct PlaylistEntry {
     location: Location,
     metadata: Option<MinimalMetadata>,
     duration: Option<Duration>,
+    /// Dynamic range measured the last time this entry was played in full.
+    dynamic_range: Option<DynamicRange>,
+    /// Overrides the global intro skip for this entry when set.
+    intro_skip: Option<Duration>,
+    /// Skipped entries stay in the playlist, but are passed over during playback.
+    skipped: bool,
+    /// Part of the location to play when the entry is a virtual track split from a larger file.
+    range: Option<TrackRange>,
+    /// Qualities that the stream is offered in. The location is set to the one being played.
+    #[serde(skip_serializing)]
+    variants: Vec<Variant>,
+    /// Entries played from the play queue are only in the playlist while they're playing.
+    queued: bool,
+}
+
+impl PlaylistEntry {
+    fn queued_track(&self) -> QueuedTrack {
+        let metadata = self.metadata.as_ref();
+        QueuedTrack {
+            location: self.location.to_string(),
+            title: metadata.and_then(|metadata| metadata.title.clone()),
+            artist: metadata.and_then(|metadata| metadata.artist.clone()),
+        }
+    }
+
+    fn details(&self) -> PlaylistEntryDetails {
+        let metadata = self.metadata.as_ref();
+        PlaylistEntryDetails {
+            id: self.id.0,
+            location: self.location.to_string(),
+            skipped: self.skipped,
+            title: metadata.and_then(|metadata| metadata.title.clone()),
+            artist: metadata.and_then(|metadata| metadata.artist.clone()),
+            album: metadata.and_then(|metadata| metadata.album.clone()),
+            duration: self.duration,
+            dynamic_range: self.dynamic_range.map(|dynamic_range| dynamic_range.0),
+        }
+    }
+
+    /// Location that identifies the station, which stays the same whichever variant is playing.
+    fn station(&self) -> &Location {
+        self.variants
+            .first()
+            .map(|variant| &variant.location)
+            .unwrap_or(&self.location)
+    }
+}
+
+/// Part of a file that is played as its own playlist entry, such as a chapter.
+#[derive(Copy, Clone, Debug, Eq, PartialEq, serde::Serialize)]
+pub struct TrackRange {
+    pub start: Duration,
+    /// Where the next chapter starts, or `None` to play to the end of the file.
+    pub end: Option<Duration>,
+}
+
+impl TrackRange {
+    /// Splits a file into ranges at its chapters. Files with fewer than two chapters aren't split.
+    fn from_chapters(chapters: &[Chapter]) -> Vec<(TrackRange, Option<String>)> {
+        if chapters.len() < 2 {
+            return Vec::new();
+        }
+        chapters
+            .iter()
+            .enumerate()
+            .map(|(index, chapter)| {
+                let range = TrackRange {
+                    start: chapter.start,
+                    end: chapters.get(index + 1).map(|next| next.start),
+                };
+                (range, chapter.title.clone())
+            })
+            .collect()
+    }
 }
 
 #[derive(Default)]
@@ -79,6 +252,8 @@ pub struct Playlist {
     entries: Vec<PlaylistEntry>,
     current_id: Option<PlaylistEntryId>,
     current_index: Option<PlaylistIndex>,
+    /// Overrides the global track transition for this playlist when set.
+    track_transition: Option<TrackTransition>,
 }
 
 impl Playlist {
@@ -96,6 +271,139 @@ impl Playlist {
     pub fn current(&self) -> Option<(PlaylistEntryId, PlaylistIndex)> {
         self.current_id.zip(self.current_index)
     }
+
+    /// Location of the current entry, if there is one.
+    pub fn current_location(&self) -> Option<&Location> {
+        let index = self.current_index?;
+        Some(&self.entries[index.0].location)
+    }
+
+    /// Finds the current entry again after entries were rearranged or removed.
+    /// There's no current entry anymore if it was removed.
+    fn relocate_current(&mut self) {
+        let index = self
+            .current_id
+            .and_then(|id| self.entries.iter().position(|entry| entry.id == id));
+        match index {
+            Some(index) => self.current_index = Some(PlaylistIndex(index)),
+            None => self.clear_current(),
+        }
+    }
+
+    /// Returns the index of the first entry after the given index that isn't skipped.
+    pub fn next_playable(&self, after: PlaylistIndex) -> Option<PlaylistIndex> {
+        (after.0 + 1..self.entries.len())
+            .find(|&index| !self.entries[index].skipped)
+            .map(PlaylistIndex)
+    }
+
+    /// Returns the index of the first entry before the given index that isn't skipped.
+    pub fn previous_playable(&self, before: PlaylistIndex) -> Option<PlaylistIndex> {
+        (0..before.0)
+            .rev()
+            .find(|&index| !self.entries[index].skipped)
+            .map(PlaylistIndex)
+    }
+
+    /// Returns the index of the first entry that isn't skipped.
+    pub fn first_playable(&self) -> Option<PlaylistIndex> {
+        self.entries
+            .iter()
+            .position(|entry| !entry.skipped)
+            .map(PlaylistIndex)
+    }
+
+    /// Saves the playlist to a file so that it can be loaded again later.
+    pub fn save(&self, path: &Utf8Path, format: PlaylistFormat) -> Result<(), PlaylistFileError> {
+        file::write_playlist(path, format, &self.listed_entries())
+    }
+
+    /// Finds up to `limit` entries whose artist, title, or location contains every word
+    /// of the query, ignoring case. Each location is only returned once.
+    pub fn search(&self, query: &str, limit: usize) -> Vec<QueuedTrack> {
+        let words: Vec<String> = query.split_whitespace().map(str::to_lowercase).collect();
+        let mut found: Vec<QueuedTrack> = Vec::new();
+        for entry in &self.entries {
+            if found.len() == limit {
+                break;
+            }
+            if entry.queued || entry.skipped {
+                continue;
+            }
+            let track = entry.queued_track();
+            let haystack = [&track.artist, &track.title, &Some(track.location.clone())]
+                .into_iter()
+                .flatten()
+                .map(|field| field.to_lowercase())
+                .collect::<Vec<_>>()
+                .join("\n");
+            let matches = words.iter().all(|word| haystack.contains(word.as_str()));
+            if matches && !found.iter().any(|f| f.location == track.location) {
+                found.push(track);
+            }
+        }
+        found
+    }
+
+    /// What the UI shows for the playlist.
+    pub fn state_data(&self) -> PlaylistStateData {
+        let entries: Vec<_> = self.entries.iter().map(PlaylistEntry::details).collect();
+        PlaylistStateData {
+            total_duration: total_duration(&entries),
+            entries,
+            current_index: self.current_index.map(|index| index.0),
+        }
+    }
+
+    /// Local files that are played whole, in playlist order, leaving out chapters and queued entries.
+    pub fn file_locations(&self) -> Vec<Location> {
+        self.entries
+            .iter()
+            .filter(|entry| !entry.queued && entry.range.is_none())
+            .filter(|entry| entry.location.as_path().is_some())
+            .map(|entry| entry.location.clone())
+            .collect()
+    }
+
+    /// True if an entry that isn't from the play queue plays the given location.
+    pub fn contains_location(&self, location: &str) -> bool {
+        self.entries
+            .iter()
+            .any(|entry| !entry.queued && entry.location.to_string() == location)
+    }
+
+    fn listed_entries(&self) -> Vec<ListedEntry> {
+        let mut listed: Vec<ListedEntry> = Vec::new();
+        for entry in &self.entries {
+            let metadata = entry.metadata.as_ref();
+            if entry.queued {
+                continue;
+            }
+            if entry.range.is_some() {
+                // Chapters are split out again when the file is loaded, so the file is listed once
+                if listed.last().map(|last| &last.location) != Some(&entry.location) {
+                    listed.push(ListedEntry::new(entry.location.clone()));
+                }
+                continue;
+            }
+            let mut listing = ListedEntry::new(entry.location.clone());
+            listing.artist = metadata.and_then(|metadata| metadata.artist.clone());
+            listing.title = metadata.and_then(|metadata| metadata.title.clone());
+            listing.duration = entry.duration;
+            if listing.title.is_some() && !entry.variants.is_empty() {
+                // Mounts listed one after another under the same title are grouped when loaded
+                for variant in &entry.variants {
+                    listed.push(ListedEntry {
+                        location: variant.location.clone(),
+                        ..listing.clone()
+                    });
+                }
+            } else {
+                listed.push(listing);
+            }
+        }
+        listed
+    }
 }
 
 pub struct PlaylistManager {
@@ -105,6 +413,87 @@ pub struct PlaylistManager {
     ui_sub: BroadcastSubscription<FrontendMessage>,
     playlist_mode: PlaylistMode,
     playback_status: Option<PlaybackStatus>,
+    track_number_ordering: bool,
+    sort_options: SortOptions,
+    tag_separators: TagSeparators,
+    /// Where the tags of playlist entries are read from.
+    metadata_chain: MetadataChain,
+    intro_skip: Option<Duration>,
+    track_transition: TrackTransition,
+    /// Settings for the tracks under particular folders.
+    folder_defaults: Vec<FolderDefaults>,
+    /// When the next track should start if a gap is being inserted after the current one.
+    next_track_at: Option<Instant>,
+    /// Whether the current track has started, so that the player can decode the next one ahead.
+    current_started: bool,
+    /// Entry that the player was told to play next without a gap, and how long it was told
+    /// to crossfade into it.
+    queued_next: Option<(PlaylistEntryId, Duration)>,
+    /// Entry that was already retried after a temporary playback error.
+    retried_entry: Option<PlaylistEntryId>,
+    /// Errors for the entries that were skipped because they couldn't be played.
+    unplayable: Vec<PlaybackError>,
+    /// Stream variant picked by the user for an entry. Other entries choose automatically.
+    variant_choice: Option<(PlaylistEntryId, usize)>,
+    /// Variant of the current entry that is playing, if it has any.
+    active_variant: Option<usize>,
+    /// Network throughput in bits per second measured on the most recent stream.
+    throughput: Option<u64>,
+    song_history: SongHistory,
+    skip_history: SkipHistory,
+    play_history: PlayHistory,
+    /// Whether the UI is showing a station's song history that needs clearing.
+    history_shown: bool,
+    ratings: TrackRatings,
+    /// Rating of the current track the last time the UI was told.
+    reported_rating: Option<TrackRating>,
+    /// Entries to play after the current one, ahead of the rest of the playlist.
+    play_queue: VecDeque<PlaylistEntry>,
+    /// Set while party mode restricts the playlist to queueing tracks.
+    party_mode: Option<PartyMode>,
+    /// Order to play entries in while shuffling. Rebuilt when the playlist is replaced.
+    shuffle: Option<ShuffleOrder>,
+    /// Seed to shuffle with, or `None` to pick a new one each time.
+    shuffle_seed: Option<u64>,
+    /// Level in dBFS that the playlist's loudest peak is brought to, if peak normalizing.
+    peak_target_db: Option<f32>,
+    /// Scan of the playlist's peaks that is still running.
+    peak_scan: Option<PeakScan>,
+    /// Probe of the playlist's tags, lengths, and dynamic range, which carries on while the
+    /// playlist plays.
+    metadata_scan: Option<MetadataScan>,
+    /// Locations with directories that are being expanded before they're loaded or queued,
+    /// in the order they were given, and whether to alert the user if none can be played.
+    expansions: VecDeque<(LocationRequest, bool, DirectoryExpansion)>,
+    /// Set when entries were added, removed, reordered, or skipped since the UI was last told.
+    playlist_changed: bool,
+    /// Current entry the last time the UI was told about the playlist.
+    reported_current: Option<PlaylistEntryId>,
+    /// Set when entries were added, removed, or reordered since the playlist was loaded or saved.
+    unsaved_edits: bool,
+    analysis_cache: AnalysisCache,
+    /// Times the gaps between tracks.
+    clock: SharedClock,
+}
+
+struct PartyMode {
+    /// Passphrase needed to stop party mode, if one was given when starting it.
+    passphrase: Option<String>,
+}
+
+/// What to do with locations from the UI once their directories are expanded.
+enum LocationRequest {
+    Load(TrackStart),
+    Queue { allow_duplicates: bool },
+}
+
+/// How the first track starts when locations are loaded.
+#[derive(Copy, Clone, Debug, Default, Eq, PartialEq)]
+pub struct TrackStart {
+    /// Position in the file to start playing from, in place of the track's intro skip.
+    pub position: Option<Duration>,
+    /// Load the track paused rather than playing it.
+    pub paused: bool,
 }
 
 impl PlaylistManager {
@@ -124,30 +513,323 @@ impl PlaylistManager {
             ui_sub,
             playlist_mode: PlaylistMode::Normal,
             playback_status: None,
+            track_number_ordering: true,
+            sort_options: SortOptions::default(),
+            tag_separators: TagSeparators::default(),
+            metadata_chain: MetadataChain::default(),
+            intro_skip: None,
+            track_transition: TrackTransition::default(),
+            folder_defaults: Vec::new(),
+            next_track_at: None,
+            current_started: false,
+            queued_next: None,
+            retried_entry: None,
+            unplayable: Vec::new(),
+            variant_choice: None,
+            active_variant: None,
+            throughput: None,
+            song_history: SongHistory::default(),
+            skip_history: SkipHistory::default(),
+            play_history: PlayHistory::default(),
+            history_shown: false,
+            ratings: TrackRatings::in_memory(),
+            reported_rating: None,
+            play_queue: VecDeque::new(),
+            party_mode: None,
+            shuffle: None,
+            shuffle_seed: None,
+            peak_target_db: None,
+            peak_scan: None,
+            metadata_scan: None,
+            expansions: VecDeque::new(),
+            playlist_changed: false,
+            reported_current: None,
+            unsaved_edits: false,
+            analysis_cache: AnalysisCache::in_memory(),
+            clock: SystemClock::shared(),
+        }
+    }
+
+    /// Sets whether directories are ordered by their disc/track number tags when loaded.
+    ///
+    /// When disabled, the files in a directory are ordered by file name instead.
+    /// This is enabled by default.
+    pub fn set_track_number_ordering(&mut self, enabled: bool) {
+        self.track_number_ordering = enabled;
+    }
+
+    /// Loads locations like the UI's `LoadLocations`, starting the first track as given.
+    ///
+    /// Directories are expanded in the background first, so the first track isn't known until
+    /// that finishes. Telling the player to seek or pause right after this would be too early.
+    pub fn load_locations_with(&mut self, locations: Vec<Location>, start: TrackStart) {
+        self.request_locations(locations, LocationRequest::Load(start));
+    }
+
+    /// Sets where per-track analysis is cached between runs. It's only kept in memory by default.
+    pub fn set_analysis_cache(&mut self, cache: AnalysisCache) {
+        self.analysis_cache = cache;
+    }
+
+    /// Sets the clock that the gaps between tracks are timed with. It's the system clock
+    /// by default.
+    pub fn set_clock(&mut self, clock: SharedClock) {
+        self.clock = clock;
+    }
+
+    /// Sets where loved and banned tracks, and the intro skips set on tracks, are saved.
+    /// They're only kept in memory by default.
+    pub fn set_track_ratings(&mut self, ratings: TrackRatings) {
+        self.ratings = ratings;
+    }
+
+    /// Sets how names are compared when sorting the playlist.
+    pub fn set_sort_options(&mut self, options: SortOptions) {
+        self.sort_options = options;
+    }
+
+    /// Sets the separators that multi-value artist and genre tags are split on.
+    pub fn set_tag_separators(&mut self, separators: TagSeparators) {
+        self.tag_separators = separators;
+    }
+
+    /// Sets the providers that playlist entries' tags are read from, in order of precedence.
+    /// Sidecar files and then embedded tags are used by default.
+    pub fn set_metadata_chain(&mut self, chain: MetadataChain) {
+        self.metadata_chain = chain;
+    }
+
+    /// Sets how much of the start of every track to skip, which is useful for podcasts
+    /// with long intros. Individual entries can override this.
+    pub fn set_intro_skip(&mut self, skip: Option<Duration>) {
+        self.intro_skip = skip;
+    }
+
+    /// Sets the playback settings for the tracks under particular folders. These take
+    /// precedence over the global settings.
+    pub fn set_folder_defaults(&mut self, defaults: Vec<FolderDefaults>) {
+        self.folder_defaults = defaults;
+    }
+
+    /// The playlist that's loaded.
+    pub fn playlist(&self) -> &Playlist {
+        &self.playlist
+    }
+
+    /// Sets the transition used between tracks. Individual playlists can override this.
+    pub fn set_track_transition(&mut self, transition: TrackTransition) {
+        self.track_transition = transition;
+    }
+
+    /// Sets the seed to shuffle with, so that a shuffled order can be reproduced.
+    /// If `None`, a new seed is picked each time shuffling starts.
+    pub fn set_shuffle_seed(&mut self, seed: Option<u64>) {
+        if seed == self.shuffle_seed {
+            return;
+        }
+        self.shuffle_seed = seed;
+        if self.shuffle.is_some() {
+            self.shuffle = None;
+            self.ensure_shuffle_order();
+            self.report_playlist_mode();
+        }
+    }
+
+    /// Normalizes the whole playlist by its loudest peak, which is brought to `target_db`
+    /// (in dBFS). The playlist is scanned now and whenever it's replaced. `None` stops scanning.
+    pub fn set_peak_normalization(&mut self, target_db: Option<f32>) {
+        if target_db == self.peak_target_db {
+            return;
+        }
+        self.peak_target_db = target_db;
+        self.peak_scan = None;
+        self.start_peak_scan();
+    }
+
+    /// Tracks the user skipped before they finished.
+    pub fn skip_history(&self) -> &SkipHistory {
+        &self.skip_history
+    }
+
+    /// Tracks that were played at least halfway through.
+    pub fn play_history(&self) -> &PlayHistory {
+        &self.play_history
+    }
+
+    /// Tracks the user loved or banned.
+    pub fn ratings(&self) -> &TrackRatings {
+        &self.ratings
+    }
+
+    /// True if the playlist was edited since it was loaded or saved.
+    pub fn has_unsaved_edits(&self) -> bool {
+        self.unsaved_edits
+    }
+
+    /// Writes the playlist to a file.
+    pub fn save_playlist(
+        &mut self,
+        path: &Utf8Path,
+        format: PlaylistFormat,
+    ) -> Result<(), PlaylistFileError> {
+        self.playlist.save(path, format)?;
+        self.unsaved_edits = false;
+        Ok(())
+    }
+
+    /// Describes the background work that is still going, such as scanning the playlist.
+    pub fn busy_tasks(&self) -> Vec<&'static str> {
+        let mut tasks = Vec::new();
+        if !self.expansions.is_empty() {
+            tasks.push("Listing the files in folders");
         }
+        if self.peak_scan.is_some() {
+            tasks.push("Scanning the playlist's peak levels");
+        }
+        if self
+            .metadata_scan
+            .as_ref()
+            .is_some_and(|scan| !scan.is_finished())
+        {
+            tasks.push("Reading the playlist's tags and measuring its dynamic range");
+        }
+        tasks
+    }
+
+    /// Cancels the background work and waits for it to stop, so that nothing is cut off
+    /// partway through when quitting.
+    pub fn shutdown(&mut self) {
+        if let Some(scan) = self.peak_scan.take() {
+            scan.stop();
+        }
+        if let Some(scan) = self.metadata_scan.take() {
+            scan.stop();
+        }
+    }
+
+    /// Loads the current entry again from where it was last heard playing, such as after the
+    /// player thread was restarted. Streams start over since they can't be seeked.
+    pub fn reload_current(&mut self) {
+        let Some((_, index)) = self.playlist.current() else {
+            return;
+        };
+        // Anything that was queued up for the next track went away with the old player
+        self.next_track_at = None;
+        self.current_started = false;
+        self.queued_next = None;
+        let location = self.playlist.entries[index.0].location.clone();
+        let status = self.playback_status;
+        match status.filter(|status| status.end_position.is_some()) {
+            Some(status) => {
+                log::info!("reloading {location} at {:?}", status.current_position);
+                self.player_sub
+                    .broadcast(PlayerMessage::CommandLoadAndPlayLocationFrom(
+                        location,
+                        status.current_position,
+                    ));
+            }
+            None => {
+                log::info!("reloading {location}");
+                self.player_sub
+                    .broadcast(PlayerMessage::CommandLoadAndPlayLocation(location));
+            }
+        }
+        if status.map(|status| !status.playing).unwrap_or(false) {
+            self.player_sub.broadcast(PlayerMessage::CommandPause);
+        }
+    }
+
+    /// Returns true once after the playlist's entries or current entry changed,
+    /// so that the UI can refresh its copy of the playlist.
+    ///
+    /// Tags and lengths found after loading are sent separately as `PlaylistEntriesUpdated`.
+    pub fn take_playlist_changed(&mut self) -> bool {
+        let current = self.playlist.current_id;
+        let changed = mem::take(&mut self.playlist_changed) || current != self.reported_current;
+        self.reported_current = current;
+        changed
+    }
+
+    /// True while party mode restricts the playlist to queueing tracks.
+    pub fn party_mode_active(&self) -> bool {
+        self.party_mode.is_some()
     }
 
     pub fn update(&mut self) {
+        self.finish_peak_scan();
+        self.apply_scanned_metadata();
+        self.finish_expansions();
         while let Some(message) = self.player_sub.try_recv() {
-            #[allow(clippy::single_match)]
             match message {
-                PlayerMessage::EventFinishedTrack => self.start_next_track(false),
+                PlayerMessage::EventStartedTrack => {
+                    self.current_started = true;
+                    self.retried_entry = None;
+                }
+                PlayerMessage::EventStartedNextTrack(location) => {
+                    self.advance_to_queued_next(location)
+                }
+                PlayerMessage::EventFailedToLoadLocation(error)
+                | PlayerMessage::EventFailedToDecodeAudio(error) => {
+                    self.handle_playback_error(error)
+                }
+                PlayerMessage::EventFinishedTrack => match self.current_track_transition() {
+                    TrackTransition::Gap(gap) if !gap.is_zero() => {
+                        self.next_track_at = Some(self.clock.now() + gap);
+                    }
+                    _ => self.start_next_track(false),
+                },
+                PlayerMessage::EventStreamThroughputMeasured(throughput) => {
+                    self.adapt_stream_quality(throughput)
+                }
+                PlayerMessage::EventStreamTitleChanged(title) => self.record_stream_title(title),
+                PlayerMessage::EventTrackPlayed(location) => self.record_play(&location),
+                PlayerMessage::EventDynamicRangeMeasured(dynamic_range) => {
+                    self.record_dynamic_range(dynamic_range)
+                }
                 PlayerMessage::UpdatePlaybackStatus(status) => {
                     self.playback_status = Some(status);
+                    if self.reached_range_end(&status) {
+                        self.finish_range();
+                    }
                 }
                 _ => {}
             }
         }
         while let Some(message) = self.ui_sub.try_recv() {
+            let Some(message) = self.restrict_to_party_mode(message) else {
+                continue;
+            };
             match message {
-                FrontendMessage::LoadLocations { locations } => self.load_locations(
+                FrontendMessage::LoadLocations { locations } => self.request_locations(
+                    locations
+                        .into_iter()
+                        .map(|l| {
+                            Location::from_str(&l).expect("frontend is only given valid locations")
+                        })
+                        .collect(),
+                    LocationRequest::Load(TrackStart::default()),
+                ),
+                FrontendMessage::QueueLocations {
+                    locations,
+                    allow_duplicates,
+                } => self.request_locations(
                     locations
                         .into_iter()
                         .map(|l| {
                             Location::from_str(&l).expect("frontend is only given valid locations")
                         })
                         .collect(),
+                    LocationRequest::Queue { allow_duplicates },
                 ),
+                FrontendMessage::PlayTestSignal { signal } => self.play_test_signal(signal),
+                FrontendMessage::StartPartyMode { passphrase } => self.start_party_mode(passphrase),
+                FrontendMessage::StopPartyMode { passphrase } => self.stop_party_mode(passphrase),
+                FrontendMessage::ClearPlayQueue => {
+                    if !self.play_queue.is_empty() {
+                        self.play_queue.clear();
+                        self.report_play_queue();
+                    }
+                }
                 FrontendMessage::MediaControlSkipBack => self.control_skip_back(),
                 FrontendMessage::MediaControlBack => log::error!("TODO: back not implemented"),
                 FrontendMessage::MediaControlPause => {
@@ -156,351 +838,3993 @@ impl PlaylistManager {
                 FrontendMessage::MediaControlPlay => {
                     self.player_sub.broadcast(PlayerMessage::CommandResume)
                 }
-                FrontendMessage::MediaControlStop => log::error!("TODO: stop not implemented"),
+                FrontendMessage::MediaControlStop => self.stop(),
                 FrontendMessage::MediaControlForward => {
                     log::error!("TODO: forward not implemented")
                 }
-                FrontendMessage::MediaControlSkipForward => self.start_next_track(true),
-                FrontendMessage::MediaControlPlaylistMode { mode } => {
-                    self.playlist_mode = mode;
-                    // TODO: Communicate back to the UI that the playlist has changed
+                FrontendMessage::MediaControlSkipForward => {
+                    self.record_skip();
+                    self.start_next_track(true)
+                }
+                FrontendMessage::MediaControlNextChapter => self.skip_chapter(true),
+                FrontendMessage::MediaControlPreviousChapter => self.skip_chapter(false),
+                FrontendMessage::RateCurrentTrack { rating } => self.rate_current(rating),
+                FrontendMessage::PlayPlaylistEntry { id } => self.play_entry(id),
+                FrontendMessage::PreviewPlaylistEntry { id } => self.preview_entry(id),
+                FrontendMessage::StopPreview => {
+                    self.player_sub.broadcast(PlayerMessage::CommandStopPreview)
+                }
+                FrontendMessage::MovePlaylistEntry { from, to } => self.move_entry(from, to),
+                FrontendMessage::RemovePlaylistEntries { ids } => self.remove_entries(ids),
+                FrontendMessage::MediaControlPlaylistMode { mode } => self.set_playlist_mode(mode),
+                FrontendMessage::MediaControlJumpToLive => {
+                    self.player_sub.broadcast(PlayerMessage::CommandJumpToLive)
                 }
                 FrontendMessage::MediaControlSeek { position } => self
                     .player_sub
                     .broadcast(PlayerMessage::CommandSeek(position)),
+                FrontendMessage::MediaControlSeekBy { seconds } => self.seek_by(seconds),
                 FrontendMessage::MediaControlVolume { volume } => self
                     .player_sub
                     .broadcast(PlayerMessage::CommandSetVolume(volume)),
+                FrontendMessage::SetPlaylistEntrySkipped { id, skipped } => {
+                    self.set_entry_skipped(id, skipped)
+                }
+                FrontendMessage::SetIntroSkip { skip } => self.set_intro_skip(skip),
+                FrontendMessage::SortPlaylist { by } => self.sort_playlist(by),
+                FrontendMessage::SelectStreamVariant { variant } => {
+                    self.select_stream_variant(variant)
+                }
+                FrontendMessage::SetTrackTransition { transition } => {
+                    self.set_track_transition(transition)
+                }
+                FrontendMessage::SetPlaylistTrackTransition { transition } => {
+                    self.playlist.track_transition = transition;
+                }
+                FrontendMessage::SetPlaylistEntryIntroSkip { id, skip } => {
+                    self.set_entry_intro_skip(id, skip)
+                }
+                FrontendMessage::MediaControlUndoIntroSkip => self
+                    .player_sub
+                    .broadcast(PlayerMessage::CommandSeek(Duration::ZERO)),
+                FrontendMessage::MediaControlCueDevice { name } => self
+                    .player_sub
+                    .broadcast(PlayerMessage::CommandOpenCueDevice(name)),
+                FrontendMessage::MediaControlCueNext => self.cue_next_track(),
+                FrontendMessage::MediaControlCueStop => {
+                    self.player_sub.broadcast(PlayerMessage::CommandStopCue)
+                }
+                FrontendMessage::MediaControlDuck { level } => {
+                    self.player_sub.broadcast(PlayerMessage::CommandDuck(level))
+                }
+                FrontendMessage::MediaControlCueVolume { volume } => self
+                    .player_sub
+                    .broadcast(PlayerMessage::CommandSetCueVolume(volume)),
                 _ => {}
             }
         }
-    }
-
-    fn part_way_into_track(&self) -> bool {
-        self.playback_status
-            .map(|status| status.current_position >= Duration::from_secs(7))
+        if self
+            .next_track_at
+            .map(|at| self.clock.now() >= at)
             .unwrap_or(false)
+        {
+            self.next_track_at = None;
+            self.start_next_track(false);
+        }
+        self.update_queued_next();
     }
 
-    fn next_id(&mut self) -> PlaylistEntryId {
-        self.next_id += 1;
-        PlaylistEntryId(self.next_id)
+    /// Returns the entry that should play next without a gap, if any, along with how long
+    /// to crossfade into it.
+    ///
+    /// Gaps and intro skips need the next track to be started separately,
+    /// so those are left to the normal track transition.
+    fn gapless_next(&self) -> Option<(PlaylistEntryId, Duration)> {
+        let crossfade = match self.current_track_transition() {
+            TrackTransition::Immediate => Duration::ZERO,
+            TrackTransition::Overlap(crossfade) => crossfade,
+            TrackTransition::Gap(_) => return None,
+        };
+        if !self.current_started {
+            return None;
+        }
+        let (_current_id, current_index) = self.playlist.current()?;
+        // Queued entries are added to the playlist when they start, and removed when they end
+        if !self.play_queue.is_empty() || self.playlist.entries[current_index.0].queued {
+            return None;
+        }
+        let next_index = match self.playlist_mode {
+            PlaylistMode::Normal => self.playlist.next_playable(current_index)?,
+            PlaylistMode::RepeatOne => current_index,
+            PlaylistMode::Shuffle | PlaylistMode::ShuffleAlbums => self.next_shuffled()?,
+            PlaylistMode::RepeatAll => return None,
+        };
+        // Virtual tracks are started at their position in the file instead
+        let current = &self.playlist.entries[current_index.0];
+        if current.range.map(|range| range.end.is_some()) == Some(true) {
+            return None;
+        }
+        let entry = &self.playlist.entries[next_index.0];
+        let intro_skip = self.intro_skip_for(entry).unwrap_or_default();
+        (intro_skip.is_zero() && entry.range.is_none()).then_some((entry.id, crossfade))
     }
 
-    fn control_skip_back(&mut self) {
-        if self.part_way_into_track() {
-            self.restart_current_track();
-        } else {
-            self.start_previous_track();
+    /// Whether playback has passed the end of the current virtual track.
+    fn reached_range_end(&self, status: &PlaybackStatus) -> bool {
+        let Some((_, index)) = self.playlist.current() else {
+            return false;
+        };
+        // Status updates from before the current track started may be for another location
+        match self.playlist.entries[index.0]
+            .range
+            .and_then(|range| range.end)
+        {
+            Some(end) => self.current_started && status.current_position >= end,
+            None => false,
         }
     }
 
-    fn restart_current_track(&mut self) {
-        if let Some(current_index) = self.playlist.current_index {
-            self.start_track(current_index);
+    /// Moves on from a virtual track that reached its end.
+    ///
+    /// When the next entry continues where the current one ends in the same file, the player
+    /// is left playing and only the current entry changes, so that there's no gap.
+    fn finish_range(&mut self) {
+        let Some((_, current_index)) = self.playlist.current() else {
+            return;
+        };
+        if self.playlist_mode == PlaylistMode::Normal {
+            if let Some(next_index) = self.playlist.next_playable(current_index) {
+                let (current, next) = (
+                    &self.playlist.entries[current_index.0],
+                    &self.playlist.entries[next_index.0],
+                );
+                let continues = current.location == next.location
+                    && current.range.and_then(|range| range.end)
+                        == next.range.map(|range| range.start);
+                if continues {
+                    self.playlist.set_current_index(next_index);
+                    self.retried_entry = None;
+                    return;
+                }
+            }
         }
+        self.start_next_track(false);
     }
 
-    fn start_previous_track(&mut self) {
-        if self.playlist.current_index.is_none() {
-            return;
+    /// Tells the player about changes to the next entry so that it can decode it ahead of time.
+    fn update_queued_next(&mut self) {
+        let next = self.gapless_next();
+        if next != self.queued_next {
+            self.queued_next = next;
+            let location = next.and_then(|(id, _)| {
+                let entry = self.playlist.entries.iter().find(|entry| entry.id == id)?;
+                Some(entry.location.clone())
+            });
+            let crossfade = next.map(|(_, crossfade)| crossfade).unwrap_or_default();
+            self.player_sub
+                .broadcast(PlayerMessage::CommandSetNextLocation(location, crossfade));
         }
+    }
 
-        let (_current_id, current_index) = self.playlist.current().unwrap();
-        match self.playlist_mode {
-            PlaylistMode::Normal => {
-                if *current_index == 0 {
-                    self.stop();
-                } else {
-                    self.start_track(PlaylistIndex(*current_index - 1));
+    /// The player moved on to the queued next entry without a gap.
+    fn advance_to_queued_next(&mut self, location: Location) {
+        let queued_next = self.queued_next.take().map(|(id, _)| id);
+        let index = self
+            .playlist
+            .entries
+            .iter()
+            .position(|entry| Some(entry.id) == queued_next && entry.location == location)
+            .or_else(|| {
+                log::warn!("player started an unexpected next location: {location}");
+                self.playlist
+                    .entries
+                    .iter()
+                    .position(|entry| entry.location == location)
+            });
+        match index {
+            Some(index) => {
+                self.playlist.set_current_index(PlaylistIndex(index));
+                if let Some(shuffle) = self.shuffle.as_mut() {
+                    shuffle.played(self.playlist.entries[index].id);
                 }
+                self.retried_entry = None;
             }
-            PlaylistMode::Shuffle => {
-                unimplemented!()
-            }
-            PlaylistMode::RepeatOne => {
-                self.restart_current_track();
-            }
-            PlaylistMode::RepeatAll => {
-                unimplemented!()
-            }
+            None => self.playlist.clear_current(),
         }
+        self.report_song_history();
+        self.report_rating();
     }
 
-    fn stop(&mut self) {
-        self.playlist.clear_current();
-        self.player_sub.broadcast(PlayerMessage::CommandStop);
+    fn current_track_transition(&self) -> TrackTransition {
+        let folder_transition = self.playlist.current().and_then(|(_, index)| {
+            let location = &self.playlist.entries[index.0].location;
+            folder::defaults_for(&self.folder_defaults, location)?.track_transition
+        });
+        self.playlist
+            .track_transition
+            .or(folder_transition)
+            .unwrap_or(self.track_transition)
     }
 
-    fn start_track(&mut self, index: PlaylistIndex) {
-        self.playlist.set_current_index(index);
-        self.player_sub
-            .broadcast(PlayerMessage::CommandLoadAndPlayLocation(
-                self.playlist.entries[index.0].location.clone(),
-            ));
+    /// Intro skip for the entry, which can be set on the entry itself, its folder, or globally.
+    fn intro_skip_for(&self, entry: &PlaylistEntry) -> Option<Duration> {
+        entry
+            .intro_skip
+            .or_else(|| folder::defaults_for(&self.folder_defaults, &entry.location)?.intro_skip)
+            .or(self.intro_skip)
     }
 
-    fn start_next_track(&mut self, stop_immediately: bool) {
-        if self.playlist.current_index.is_none() {
+    fn handle_playback_error(&mut self, error: PlaybackError) {
+        let Some((current_id, current_index)) = self.playlist.current() else {
+            return;
+        };
+        // Errors from the cue output come through here too, so ignore ones for other locations
+        if self.playlist.entries[current_index.0].location.to_string() != error.location {
             return;
         }
-
-        let (_current_id, current_index) = self.playlist.current().unwrap();
-        match self.playlist_mode {
-            PlaylistMode::Normal => {
-                let next_index = PlaylistIndex(*current_index + 1);
-                if next_index.0 >= self.playlist.entries.len() {
-                    if stop_immediately {
-                        self.stop();
-                    } else {
-                        self.playlist.clear_current();
-                    }
-                } else {
-                    self.start_track(next_index);
-                }
-            }
-            PlaylistMode::Shuffle => {
-                unimplemented!()
-            }
-            PlaylistMode::RepeatOne => {
-                self.restart_current_track();
+        if error.retryable && self.retried_entry != Some(current_id) {
+            log::info!("retrying after temporary playback error: {error}");
+            self.retried_entry = Some(current_id);
+            // Status updates from before the current track started may be for another location,
+            // so only errors partway through pick up where the track was last heard playing
+            if self.current_started {
+                self.reload_current();
+            } else {
+                self.start_track(current_index);
             }
-            PlaylistMode::RepeatAll => {
-                unimplemented!()
+            return;
+        }
+
+        log::warn!("skipping unplayable entry: {error}");
+        self.unplayable.push(error);
+        // Move on regardless of the playlist mode so that repeat modes don't get stuck
+        if self.start_from_play_queue() {
+            return;
+        }
+        let next_index = if self.shuffle.is_some() {
+            self.next_shuffled()
+        } else {
+            self.playlist.next_playable(current_index)
+        };
+        match next_index {
+            Some(next_index) => self.start_track(next_index),
+            None => {
+                self.playlist.clear_current();
+                self.remove_queued_entry(current_index, current_index);
+                self.report_unplayable();
             }
         }
     }
 
-    fn load_locations(&mut self, locations: Vec<Location>) {
-        let filtered_locations: Vec<Location> = locations
-            .iter()
-            .cloned()
-            .filter(|location| !location.inferred_type().is_unknown())
-            // TODO: remove the following filter and load playlists
-            .filter(|location| !location.inferred_type().is_playlist())
-            .collect();
-        if filtered_locations.is_empty() && !locations.is_empty() {
-            self.ui_sub.broadcast(FrontendMessage::ShowAlert {
-                level: AlertLevel::Info,
-                message: "None of the given files are audio or playlist files.".into(),
+    /// Shows a single alert summarizing all the entries that couldn't be played.
+    fn report_unplayable(&mut self) {
+        if self.unplayable.is_empty() {
+            return;
+        }
+        let errors = mem::take(&mut self.unplayable);
+        let message = match errors.len() {
+            1 => "1 file could not be played.".to_string(),
+            count => format!("{count} files could not be played."),
+        };
+        self.ui_sub
+            .broadcast(FrontendMessage::UnplayableEntriesSkipped { errors });
+        self.ui_sub.broadcast(FrontendMessage::ShowAlert {
+            level: AlertLevel::Warn,
+            message: message.into(),
+        });
+    }
+
+    fn part_way_into_track(&self) -> bool {
+        self.playback_status
+            .map(|status| status.current_position >= Duration::from_secs(7))
+            .unwrap_or(false)
+    }
+
+    fn next_id(&mut self) -> PlaylistEntryId {
+        self.next_id += 1;
+        PlaylistEntryId(self.next_id)
+    }
+
+    fn new_entry(
+        &mut self,
+        location: Location,
+        metadata: Option<MinimalMetadata>,
+        range: Option<TrackRange>,
+    ) -> PlaylistEntry {
+        // Virtual tracks only measure part of the file, so they aren't cached
+        let dynamic_range = range
+            .is_none()
+            .then(|| self.analysis_cache.get(&location))
+            .flatten()
+            .and_then(|analysis| analysis.dynamic_range);
+        let start = range.map(|range| range.start).unwrap_or_default();
+        let intro_skip = self.ratings.intro_skip(&location, start);
+        PlaylistEntry {
+            id: self.next_id(),
+            location,
+            metadata,
+            duration: range.and_then(|range| Some(range.end? - range.start)),
+            dynamic_range,
+            intro_skip,
+            skipped: false,
+            range,
+            variants: Vec::new(),
+            queued: false,
+        }
+    }
+
+    fn record_dynamic_range(&mut self, dynamic_range: DynamicRange) {
+        let Some((_, index)) = self.playlist.current() else {
+            return;
+        };
+        let entry = &mut self.playlist.entries[index.0];
+        if entry.dynamic_range == Some(dynamic_range) {
+            return;
+        }
+        entry.dynamic_range = Some(dynamic_range);
+        if !entry.queued {
+            self.ui_sub
+                .broadcast(FrontendMessage::PlaylistEntriesUpdated {
+                    entries: vec![entry.details()],
+                });
+        }
+        if entry.range.is_none() {
+            self.analysis_cache.update(&entry.location, |analysis| {
+                analysis.dynamic_range = Some(dynamic_range)
             });
+            if let Err(err) = self.analysis_cache.save() {
+                log::warn!("{err}");
+            }
         }
-        let entries: Vec<PlaylistEntry> = filtered_locations
-            .into_iter()
-            .map(|location| {
-                PlaylistEntry {
-                    id: self.next_id(),
-                    location,
-                    // TODO: Add support for metadata loading
-                    metadata: None,
-                    duration: None,
-                }
-            })
-            .collect();
-        let (current_id, current_index) = if let Some(first) = entries.first() {
-            (Some(first.id), Some(PlaylistIndex(0)))
+    }
+
+    /// Remembers that the current track was skipped, unless it was nearly over anyway.
+    fn record_skip(&mut self) {
+        let (Some((_, index)), Some(status)) = (self.playlist.current(), self.playback_status)
+        else {
+            return;
+        };
+        // Status updates from before the current track started may be for another location
+        if !self.current_started {
+            return;
+        }
+        let entry = &self.playlist.entries[index.0];
+        let start = entry.range.map(|range| range.start).unwrap_or_default();
+        let position = status.current_position.saturating_sub(start);
+        let length = entry
+            .duration
+            .or_else(|| Some(status.end_position?.saturating_sub(start)));
+        if length.map(|length| position >= length.mul_f64(SKIP_NEAR_END)) == Some(true) {
+            return;
+        }
+        self.skip_history.record(
+            &entry.location,
+            start,
+            Skip {
+                position,
+                skipped_at: SystemTime::now(),
+            },
+        );
+    }
+
+    /// Counts a play of the current track once the player reports it played halfway through.
+    fn record_play(&mut self, location: &Location) {
+        let Some((_, index)) = self.playlist.current() else {
+            return;
+        };
+        let entry = &self.playlist.entries[index.0];
+        if !self.current_started || &entry.location != location {
+            return;
+        }
+        let start = entry.range.map(|range| range.start).unwrap_or_default();
+        self.play_history
+            .record(&entry.location, start, SystemTime::now());
+    }
+
+    fn control_skip_back(&mut self) {
+        if self.part_way_into_track() {
+            self.restart_current_track();
+        } else {
+            self.start_previous_track();
+        }
+    }
+
+    /// Moves between the entries that a file with chapters was split into. Going back restarts
+    /// the current chapter unless it only just started, and going past either end of the
+    /// file's chapters does nothing.
+    fn skip_chapter(&mut self, forward: bool) {
+        let Some((_, index)) = self.playlist.current() else {
+            return;
+        };
+        let entries = &self.playlist.entries;
+        let current = &entries[index.0];
+        let Some(range) = current.range else {
+            return;
+        };
+        // Positions are from the start of the file rather than the chapter
+        let into_chapter = self
+            .playback_status
+            .map(|status| status.current_position.saturating_sub(range.start))
+            .unwrap_or_default();
+        let adjacent = if forward {
+            Some(index.0 + 1)
+        } else if into_chapter >= Duration::from_secs(7) {
+            None
         } else {
-            (None, None)
+            index.0.checked_sub(1)
         };
+        let adjacent = adjacent.filter(|&adjacent| {
+            entries.get(adjacent).map_or(false, |entry| {
+                entry.range.is_some() && entry.location == current.location
+            })
+        });
+        match adjacent {
+            Some(adjacent) => {
+                self.record_skip();
+                self.start_track(PlaylistIndex(adjacent));
+            }
+            None if !forward => self.restart_current_track(),
+            None => log::info!("no chapter after the current one"),
+        }
+    }
 
-        self.playlist = Playlist {
-            entries,
-            current_id,
-            current_index,
+    /// Seeks relative to where the current track was last heard playing, without going past
+    /// either end of it, or of a live stream's timeshift.
+    fn seek_by(&mut self, seconds: i32) {
+        let Some(status) = self.playback_status else {
+            return;
         };
+        let (start, end) = match (status.live, status.end_position) {
+            (Some(live), _) => (
+                live.earliest_position,
+                status.current_position + live.behind_live,
+            ),
+            (None, Some(end)) => (Duration::ZERO, end),
+            (None, None) => return,
+        };
+        let offset = Duration::from_secs(seconds.unsigned_abs() as u64);
+        let position = if seconds < 0 {
+            status.current_position.saturating_sub(offset)
+        } else {
+            status.current_position + offset
+        };
+        self.player_sub
+            .broadcast(PlayerMessage::CommandSeek(position.clamp(start, end)));
+    }
+
+    fn restart_current_track(&mut self) {
+        if let Some(current_index) = self.playlist.current_index {
+            self.start_track(current_index);
+        }
+    }
+
+    fn start_previous_track(&mut self) {
+        if self.playlist.current_index.is_none() {
+            return;
+        }
+
+        let (_current_id, current_index) = self.playlist.current().unwrap();
+        match self.playlist_mode {
+            PlaylistMode::Normal => match self.playlist.previous_playable(current_index) {
+                Some(previous_index) => self.start_track(previous_index),
+                None => self.stop(),
+            },
+            PlaylistMode::Shuffle | PlaylistMode::ShuffleAlbums => {
+                self.ensure_shuffle_order();
+                match self.previous_shuffled() {
+                    Some(previous_index) => self.start_track(previous_index),
+                    None => self.stop(),
+                }
+            }
+            PlaylistMode::RepeatOne => {
+                self.restart_current_track();
+            }
+            PlaylistMode::RepeatAll => {
+                unimplemented!()
+            }
+        }
+    }
+
+    fn stop(&mut self) {
+        self.next_track_at = None;
+        self.current_started = false;
+        self.queued_next = None;
+        self.playlist.clear_current();
+        self.player_sub.broadcast(PlayerMessage::CommandStop);
+    }
+
+    fn start_track(&mut self, index: PlaylistIndex) {
+        self.start_track_with(index, TrackStart::default());
+    }
 
-        if current_id.is_some() {
-            let entry = &self.playlist.entries[0];
+    fn start_track_with(&mut self, index: PlaylistIndex, start: TrackStart) {
+        let index = match self.playlist.current() {
+            Some((_, current_index)) if current_index != index => {
+                self.remove_queued_entry(current_index, index)
+            }
+            _ => index,
+        };
+        // Loading a location clears the player's next location
+        self.next_track_at = None;
+        self.current_started = false;
+        self.queued_next = None;
+        self.playlist.set_current_index(index);
+        if let Some(shuffle) = self.shuffle.as_mut() {
+            shuffle.played(self.playlist.entries[index.0].id);
+        }
+        self.choose_stream_variant(index);
+        self.report_song_history();
+        self.report_rating();
+        let entry = &self.playlist.entries[index.0];
+        let intro_skip = self
+            .intro_skip_for(entry)
+            .filter(|skip| !skip.is_zero() && start.position.is_none());
+        let range_start = entry
+            .range
+            .map(|range| range.start)
+            .filter(|start| !start.is_zero());
+        if let Some(position) = start.position {
+            self.player_sub
+                .broadcast(PlayerMessage::CommandLoadAndPlayLocationFrom(
+                    entry.location.clone(),
+                    position,
+                ));
+        } else if intro_skip.is_some() || range_start.is_some() {
+            self.player_sub
+                .broadcast(PlayerMessage::CommandLoadAndPlayLocationFrom(
+                    entry.location.clone(),
+                    range_start.unwrap_or_default() + intro_skip.unwrap_or_default(),
+                ));
+            if let Some(intro_skip) = intro_skip {
+                self.ui_sub.broadcast(FrontendMessage::IntroSkipped {
+                    position: intro_skip,
+                });
+            }
+        } else {
             self.player_sub
                 .broadcast(PlayerMessage::CommandLoadAndPlayLocation(
                     entry.location.clone(),
                 ));
         }
+        if start.paused {
+            self.player_sub.broadcast(PlayerMessage::CommandPause);
+        }
     }
-}
 
-#[cfg(test)]
-mod playlist_manager_tests {
-    use super::*;
+    /// Points the entry at the variant of its stream that should be played.
+    fn choose_stream_variant(&mut self, index: PlaylistIndex) {
+        let entry = &mut self.playlist.entries[index.0];
+        if entry.variants.is_empty() {
+            if self.active_variant.take().is_some() {
+                self.ui_sub
+                    .broadcast(FrontendMessage::StreamQualityChanged { quality: None });
+            }
+            return;
+        }
+        let active = match self.variant_choice {
+            Some((id, choice)) if id == entry.id => choice,
+            _ => quality::choose(&entry.variants, self.throughput),
+        };
+        entry.location = entry.variants[active].location.clone();
+        self.active_variant = Some(active);
+        self.report_stream_quality();
+    }
+
+    fn report_stream_quality(&self) {
+        let quality =
+            self.playlist
+                .current()
+                .zip(self.active_variant)
+                .map(|((id, index), active)| StreamQuality {
+                    variants: quality::describe(&self.playlist.entries[index.0].variants),
+                    selected: self
+                        .variant_choice
+                        .filter(|(choice_id, _)| *choice_id == id)
+                        .map(|(_, choice)| choice),
+                    active,
+                });
+        self.ui_sub
+            .broadcast(FrontendMessage::StreamQualityChanged { quality });
+    }
+
+    fn record_stream_title(&mut self, title: String) {
+        let Some((_, index)) = self.playlist.current() else {
+            return;
+        };
+        let station = self.playlist.entries[index.0].station().clone();
+        if self.song_history.record(&station, title, SystemTime::now()) {
+            self.report_song_history();
+        }
+    }
+
+    /// Tells the UI which songs the current station has played.
+    fn report_song_history(&mut self) {
+        let songs = self
+            .playlist
+            .current()
+            .map(|(_, index)| {
+                self.song_history
+                    .songs(self.playlist.entries[index.0].station())
+            })
+            .unwrap_or_default();
+        if songs.is_empty() && !self.history_shown {
+            return;
+        }
+        self.history_shown = !songs.is_empty();
+        self.ui_sub
+            .broadcast(FrontendMessage::SongHistoryChanged { songs });
+    }
+
+    /// Sets or clears the intro skip of an entry, which is remembered for its track.
+    fn set_entry_intro_skip(&mut self, id: usize, skip: Option<Duration>) {
+        let Some(entry) = self.playlist.entries.iter_mut().find(|e| *e.id == id) else {
+            log::warn!("no playlist entry with ID {id} to set intro skip on");
+            return;
+        };
+        entry.intro_skip = skip;
+        let start = entry.range.map(|range| range.start).unwrap_or_default();
+        if self.ratings.set_intro_skip(&entry.location, start, skip) {
+            if let Err(err) = self.ratings.save() {
+                log::warn!("{err}");
+            }
+        }
+    }
+
+    /// Loves or bans the current track, or clears its rating.
+    fn rate_current(&mut self, rating: Option<TrackRating>) {
+        let Some((_, index)) = self.playlist.current() else {
+            return;
+        };
+        let entry = &self.playlist.entries[index.0];
+        let start = entry.range.map(|range| range.start).unwrap_or_default();
+        if self.ratings.set(&entry.location, start, rating) {
+            if let Err(err) = self.ratings.save() {
+                log::warn!("{err}");
+            }
+            self.report_rating();
+        }
+    }
+
+    /// Tells the UI how the current track is rated, if that changed since it was last told.
+    fn report_rating(&mut self) {
+        let rating = self.playlist.current().and_then(|(_, index)| {
+            let entry = &self.playlist.entries[index.0];
+            let start = entry.range.map(|range| range.start).unwrap_or_default();
+            self.ratings.get(&entry.location, start)
+        });
+        if rating != self.reported_rating {
+            self.reported_rating = rating;
+            self.ui_sub
+                .broadcast(FrontendMessage::TrackRatingChanged { rating });
+        }
+    }
+
+    /// Switches the current stream to the variant the user picked, or back to automatic.
+    fn select_stream_variant(&mut self, variant: Option<usize>) {
+        let Some((current_id, current_index)) = self.playlist.current() else {
+            return;
+        };
+        let variants = &self.playlist.entries[current_index.0].variants;
+        if variant.map(|variant| variant >= variants.len()) == Some(true) {
+            log::warn!("no stream variant {variant:?} to select");
+            return;
+        }
+        self.variant_choice = variant.map(|variant| (current_id, variant));
+        let wanted = variant.unwrap_or_else(|| quality::choose(variants, self.throughput));
+        if self.active_variant.is_some() && self.active_variant != Some(wanted) {
+            self.start_track(current_index);
+        } else {
+            self.report_stream_quality();
+        }
+    }
+
+    /// Drops to a lower quality if the network can't keep up with the current stream.
+    fn adapt_stream_quality(&mut self, throughput: u64) {
+        self.throughput = Some(throughput);
+        let (Some((current_id, current_index)), Some(active)) =
+            (self.playlist.current(), self.active_variant)
+        else {
+            return;
+        };
+        if self.variant_choice.map(|(id, _)| id) == Some(current_id) {
+            return;
+        }
+        let adapted = quality::adapt(
+            &self.playlist.entries[current_index.0].variants,
+            active,
+            throughput,
+        );
+        if adapted != active {
+            log::info!("switching to stream variant {adapted} since the network can't keep up");
+            self.start_track(current_index);
+        }
+    }
+
+    fn start_next_track(&mut self, stop_immediately: bool) {
+        if self.start_from_play_queue() {
+            return;
+        }
+        if self.playlist.current_index.is_none() {
+            return;
+        }
+
+        let (_current_id, current_index) = self.playlist.current().unwrap();
+        let next_index = match self.playlist_mode {
+            PlaylistMode::Normal => self.playlist.next_playable(current_index),
+            PlaylistMode::Shuffle | PlaylistMode::ShuffleAlbums => {
+                self.ensure_shuffle_order();
+                self.next_shuffled()
+            }
+            PlaylistMode::RepeatOne => {
+                self.restart_current_track();
+                return;
+            }
+            PlaylistMode::RepeatAll => {
+                unimplemented!()
+            }
+        };
+        match next_index {
+            Some(next_index) => self.start_track(next_index),
+            None => {
+                if stop_immediately {
+                    self.stop();
+                } else {
+                    self.playlist.clear_current();
+                }
+                self.remove_queued_entry(current_index, current_index);
+                self.report_unplayable();
+            }
+        }
+    }
+
+    /// Starts the next entry in the play queue, if there is one.
+    fn start_from_play_queue(&mut self) -> bool {
+        let Some(entry) = self.play_queue.pop_front() else {
+            return false;
+        };
+        self.start_queued_entry(entry);
+        self.report_play_queue();
+        true
+    }
+
+    /// Plays an entry from the play queue by placing it in the playlist after the current entry,
+    /// so that the playlist carries on from where it was once the entry is done.
+    fn start_queued_entry(&mut self, mut entry: PlaylistEntry) {
+        entry.queued = true;
+        let index = match self.playlist.current() {
+            Some((_, current_index)) => current_index.0 + 1,
+            None => self.playlist.entries.len(),
+        };
+        self.playlist.entries.insert(index, entry);
+        self.playlist_changed = true;
+        self.start_track(PlaylistIndex(index));
+    }
+
+    /// Removes the entry at `index` from the playlist if it was played from the play queue.
+    ///
+    /// Returns `target` adjusted for the removal so that it still refers to the same entry.
+    fn remove_queued_entry(
+        &mut self,
+        index: PlaylistIndex,
+        target: PlaylistIndex,
+    ) -> PlaylistIndex {
+        if !self.playlist.entries[index.0].queued {
+            return target;
+        }
+        self.playlist.entries.remove(index.0);
+        self.playlist_changed = true;
+        if self.playlist.current_index == Some(index) {
+            self.playlist.clear_current();
+        }
+        if target.0 > index.0 {
+            PlaylistIndex(target.0 - 1)
+        } else {
+            target
+        }
+    }
+
+    /// Loads or queues locations from the UI. Playlist files among them are read right away,
+    /// and the directories among them or listed in those playlists are expanded in the
+    /// background. Later requests wait for that so that they're handled in order.
+    fn request_locations(&mut self, locations: Vec<Location>, request: LocationRequest) {
+        let mut rejected = Vec::new();
+        let listed: Vec<ListedEntry> = locations
+            .iter()
+            .flat_map(|location| expand_location(location, 0, &mut rejected))
+            .collect();
+        if !rejected.is_empty() {
+            self.alert_unplayable_files(&rejected);
+        }
+        // The user already heard about the playlists that couldn't be read
+        let alert_if_unplayable = rejected.is_empty() && !locations.is_empty();
+        let has_directory = listed
+            .iter()
+            .any(|listed| listed.location.as_path().is_some_and(|path| path.is_dir()));
+        if has_directory || !self.expansions.is_empty() {
+            let track_number_ordering = self
+                .track_number_ordering
+                .then(|| self.metadata_chain.clone());
+            let expansion = DirectoryExpansion::start(listed, track_number_ordering);
+            self.expansions
+                .push_back((request, alert_if_unplayable, expansion));
+        } else {
+            self.handle_location_request(listed, request, alert_if_unplayable);
+        }
+    }
+
+    fn finish_expansions(&mut self) {
+        while let Some(expanded) = self
+            .expansions
+            .front()
+            .and_then(|(_, _, expansion)| expansion.try_finish())
+        {
+            let (request, alert_if_unplayable, _) =
+                self.expansions.pop_front().expect("checked above");
+            self.handle_location_request(expanded, request, alert_if_unplayable);
+        }
+    }
+
+    fn handle_location_request(
+        &mut self,
+        listed: Vec<ListedEntry>,
+        request: LocationRequest,
+        alert_if_unplayable: bool,
+    ) {
+        let entries = self.create_entries(listed, alert_if_unplayable);
+        match request {
+            LocationRequest::Load(start) => self.load_entries(entries, start),
+            LocationRequest::Queue { allow_duplicates } => {
+                self.queue_entries(entries, allow_duplicates)
+            }
+        }
+    }
+
+    /// Adds entries to the play queue. Unless duplicates are allowed, tracks that are already
+    /// in the playlist or the play queue are left out, and the UI is told about them.
+    fn queue_entries(&mut self, mut entries: Vec<PlaylistEntry>, allow_duplicates: bool) {
+        if !allow_duplicates {
+            let (duplicates, unique): (Vec<_>, Vec<_>) = entries.into_iter().partition(|entry| {
+                self.playlist
+                    .entries
+                    .iter()
+                    .chain(&self.play_queue)
+                    .any(|existing| duplicate::same_recording(existing, entry))
+            });
+            if !duplicates.is_empty() {
+                log::info!("not queueing {} duplicate tracks", duplicates.len());
+                self.ui_sub.broadcast(FrontendMessage::DuplicatesSkipped {
+                    duplicates: duplicates.iter().map(PlaylistEntry::queued_track).collect(),
+                });
+            }
+            entries = unique;
+        }
+        if entries.is_empty() {
+            return;
+        }
+        self.scan_new_entries(&entries);
+        self.play_queue.extend(entries);
+        if self.playlist.current().is_none() {
+            self.start_next_track(true);
+        } else {
+            self.report_play_queue();
+        }
+    }
+
+    /// Plays a test signal ahead of the play queue, so that the playlist carries on after it.
+    fn play_test_signal(&mut self, signal: TestSignal) {
+        let file_name = format!(
+            "millenium-test-signal-{}.wav",
+            signal.name().replace(' ', "-")
+        );
+        let path = match Utf8PathBuf::from_path_buf(std::env::temp_dir()) {
+            Ok(dir) => dir.join(file_name.to_lowercase()),
+            Err(dir) => {
+                log::error!("temporary directory isn't valid UTF-8: {dir:?}");
+                return;
+            }
+        };
+        if let Err(err) = test_signal::write_wav(signal, path.as_std_path()) {
+            log::error!("failed to write test signal to {path}: {err}");
+            self.ui_sub.broadcast(FrontendMessage::ShowAlert {
+                level: AlertLevel::Error,
+                message: format!("Failed to create the test signal: {err}").into(),
+            });
+            return;
+        }
+        let title = format!("Test signal: {}", signal.name());
+        let metadata = MinimalMetadata::from_title(title);
+        let entry = self.new_entry(Location::path(path), Some(metadata), None);
+        self.start_queued_entry(entry);
+    }
+
+    /// Changes how the playlist advances, and tells the player what comes next under the new
+    /// mode. Shuffling starts over with a new order.
+    fn set_playlist_mode(&mut self, mode: PlaylistMode) {
+        if mode != self.playlist_mode {
+            self.playlist_mode = mode;
+            self.shuffle = None;
+        }
+        // The current entry goes first so that it keeps playing
+        self.ensure_shuffle_order();
+        self.report_playlist_mode();
+        self.update_queued_next();
+    }
+
+    fn report_playlist_mode(&self) {
+        self.ui_sub.broadcast(FrontendMessage::PlaylistModeChanged {
+            mode: self.playlist_mode,
+            shuffle_seed: self.shuffle.as_ref().map(ShuffleOrder::seed),
+        });
+    }
+
+    /// Builds the shuffle order if shuffling and there isn't one yet.
+    fn ensure_shuffle_order(&mut self) {
+        if self.shuffle.is_some() {
+            return;
+        }
+        let entries = self.playlist.entries.iter().filter(|entry| !entry.queued);
+        let groups: Vec<Vec<PlaylistEntryId>> = match self.playlist_mode {
+            PlaylistMode::Shuffle => entries.map(|entry| vec![entry.id]).collect(),
+            PlaylistMode::ShuffleAlbums => {
+                let mut groups: Vec<(Option<String>, Vec<PlaylistEntryId>)> = Vec::new();
+                for entry in entries {
+                    let album = album_key(entry);
+                    match groups.last_mut() {
+                        Some((last, group)) if album.is_some() && *last == album => {
+                            group.push(entry.id)
+                        }
+                        _ => groups.push((album, vec![entry.id])),
+                    }
+                }
+                groups.into_iter().map(|(_, group)| group).collect()
+            }
+            _ => return,
+        };
+        let seed = self.shuffle_seed.unwrap_or_else(ShuffleOrder::random_seed);
+        log::info!("shuffling with seed {seed}");
+        let current = self.playlist.current().map(|(id, _)| id);
+        self.shuffle = Some(ShuffleOrder::new(seed, groups, current));
+    }
+
+    /// Index of the next entry in the shuffle order that isn't skipped or banned.
+    fn next_shuffled(&self) -> Option<PlaylistIndex> {
+        let mut upcoming = self.shuffle.as_ref()?.upcoming();
+        upcoming.find_map(|id| self.shuffleable_index(id))
+    }
+
+    /// Index of the previous entry in the shuffle order that isn't skipped or banned.
+    fn previous_shuffled(&self) -> Option<PlaylistIndex> {
+        let mut previous = self.shuffle.as_ref()?.previous();
+        previous.find_map(|id| self.shuffleable_index(id))
+    }
+
+    /// Index of an entry that can be picked when shuffling, which rules out banned tracks too.
+    fn shuffleable_index(&self, id: PlaylistEntryId) -> Option<PlaylistIndex> {
+        self.playable_index(id).filter(|index| {
+            let entry = &self.playlist.entries[index.0];
+            let start = entry.range.map(|range| range.start).unwrap_or_default();
+            !self.ratings.is_banned(&entry.location, start)
+        })
+    }
+
+    fn playable_index(&self, id: PlaylistEntryId) -> Option<PlaylistIndex> {
+        self.playlist
+            .entries
+            .iter()
+            .position(|entry| entry.id == id && !entry.skipped)
+            .map(PlaylistIndex)
+    }
+
+    /// Turns destructive playlist edits into harmless ones while party mode is on.
+    /// Opening locations queues them to play next instead, and other edits are dropped.
+    fn restrict_to_party_mode(&self, message: FrontendMessage) -> Option<FrontendMessage> {
+        if self.party_mode.is_none() {
+            return Some(message);
+        }
+        match message {
+            FrontendMessage::LoadLocations { locations } => Some(FrontendMessage::QueueLocations {
+                locations,
+                allow_duplicates: false,
+            }),
+            FrontendMessage::ClearPlayQueue
+            | FrontendMessage::MediaControlPlaylistMode { .. }
+            | FrontendMessage::MovePlaylistEntry { .. }
+            | FrontendMessage::PlayPlaylistEntry { .. }
+            | FrontendMessage::RemovePlaylistEntries { .. }
+            | FrontendMessage::SetPlaylistEntryIntroSkip { .. }
+            | FrontendMessage::SetPlaylistEntrySkipped { .. }
+            | FrontendMessage::SetPlaylistTrackTransition { .. }
+            | FrontendMessage::SortPlaylist { .. } => {
+                log::info!("ignoring playlist edit during party mode: {message:?}");
+                None
+            }
+            message => Some(message),
+        }
+    }
+
+    fn start_party_mode(&mut self, passphrase: Option<String>) {
+        if self.party_mode.is_some() {
+            return;
+        }
+        log::info!("starting party mode");
+        self.party_mode = Some(PartyMode {
+            passphrase: passphrase.filter(|passphrase| !passphrase.is_empty()),
+        });
+        self.ui_sub
+            .broadcast(FrontendMessage::PartyModeChanged { active: true });
+    }
+
+    fn stop_party_mode(&mut self, passphrase: Option<String>) {
+        let Some(party_mode) = &self.party_mode else {
+            return;
+        };
+        if party_mode.passphrase.is_some() && party_mode.passphrase != passphrase {
+            self.ui_sub.broadcast(FrontendMessage::ShowAlert {
+                level: AlertLevel::Warn,
+                message: "That passphrase is wrong, so party mode is still on.".into(),
+            });
+            return;
+        }
+        log::info!("stopping party mode");
+        self.party_mode = None;
+        self.ui_sub
+            .broadcast(FrontendMessage::PartyModeChanged { active: false });
+    }
+
+    /// Tells the UI which tracks are waiting in the play queue.
+    fn report_play_queue(&self) {
+        let queue = self
+            .play_queue
+            .iter()
+            .map(PlaylistEntry::queued_track)
+            .collect();
+        self.ui_sub
+            .broadcast(FrontendMessage::PlayQueueChanged { queue });
+    }
+
+    fn set_entry_skipped(&mut self, id: usize, skipped: bool) {
+        match self
+            .playlist
+            .entries
+            .iter_mut()
+            .find(|entry| *entry.id == id)
+        {
+            Some(entry) => {
+                entry.skipped = skipped;
+                self.playlist_changed = true;
+            }
+            None => log::warn!("no playlist entry with ID {id} to mark as skipped"),
+        }
+    }
+
+    /// Jumps straight to the entry with the given ID, even if it's marked as skipped.
+    fn play_entry(&mut self, id: usize) {
+        match self
+            .playlist
+            .entries
+            .iter()
+            .position(|entry| *entry.id == id)
+        {
+            Some(index) => {
+                self.record_skip();
+                self.start_track(PlaylistIndex(index));
+            }
+            None => log::warn!("no playlist entry with ID {id} to play"),
+        }
+    }
+
+    fn preview_entry(&mut self, id: usize) {
+        match self.playlist.entries.iter().find(|entry| *entry.id == id) {
+            Some(entry) => self
+                .player_sub
+                .broadcast(PlayerMessage::CommandPreviewLocation(
+                    entry.location.clone(),
+                )),
+            None => log::warn!("no playlist entry with ID {id} to preview"),
+        }
+    }
+
+    fn cue_next_track(&mut self) {
+        let next_index = match self.playlist.current_index {
+            _ if self.shuffle.is_some() => self.next_shuffled(),
+            Some(current_index) => self.playlist.next_playable(current_index),
+            None => self.playlist.first_playable(),
+        };
+        if let Some(entry) = next_index.map(|index| &self.playlist.entries[index.0]) {
+            self.player_sub
+                .broadcast(PlayerMessage::CommandCueLocation(entry.location.clone()));
+        }
+    }
+
+    /// Sorts the playlist by the given tag, keeping the current entry playing.
+    ///
+    /// Entries are stably sorted, so entries that compare equal keep their relative order.
+    fn sort_playlist(&mut self, key: PlaylistSortKey) {
+        let entries = mem::take(&mut self.playlist.entries);
+        let mut keyed: Vec<(PlaylistEntry, Option<Metadata>)> = entries
+            .into_iter()
+            .map(|mut entry| {
+                // Only local files have tags that can be read up front
+                let metadata = match entry.location.as_path() {
+                    Some(_) => self.metadata_chain.read(&entry.location),
+                    None => None,
+                };
+                let metadata = metadata.map(|mut metadata| {
+                    metadata.split_multi_values(&self.tag_separators);
+                    metadata
+                });
+                // Virtual tracks keep their chapter titles
+                if let (Some(metadata), None) = (&metadata, entry.range) {
+                    entry.metadata = Some(metadata.into());
+                }
+                (entry, metadata)
+            })
+            .collect();
+        keyed.sort_by(|(_, a), (_, b)| self.sort_options.compare(key, a.as_ref(), b.as_ref()));
+        self.playlist.entries = keyed.into_iter().map(|(entry, _)| entry).collect();
+        self.playlist_changed = true;
+        self.unsaved_edits = true;
+        self.playlist.relocate_current();
+    }
+
+    /// Moves the entry at index `from` so that it ends up at index `to`.
+    /// The current entry keeps playing wherever it ends up.
+    fn move_entry(&mut self, from: usize, to: usize) {
+        let len = self.playlist.entries.len();
+        if from >= len || to >= len {
+            log::warn!("can't move playlist entry {from} to {to} in a playlist of {len}");
+            return;
+        }
+        if from == to {
+            return;
+        }
+        let entry = self.playlist.entries.remove(from);
+        self.playlist.entries.insert(to, entry);
+        self.playlist.relocate_current();
+        self.playlist_changed = true;
+        self.unsaved_edits = true;
+    }
+
+    /// Removes the entries with the given IDs. If the current entry is removed, the entry
+    /// that takes its place starts playing instead.
+    fn remove_entries(&mut self, ids: Vec<usize>) {
+        let ids: HashSet<usize> = ids.into_iter().collect();
+        let current = self.playlist.current();
+        let len = self.playlist.entries.len();
+        self.playlist
+            .entries
+            .retain(|entry| !ids.contains(&entry.id.0));
+        if self.playlist.entries.len() == len {
+            return;
+        }
+        self.playlist_changed = true;
+        self.unsaved_edits = true;
+        self.playlist.relocate_current();
+        let Some((_, removed_index)) = current.filter(|_| self.playlist.current_id.is_none())
+        else {
+            return;
+        };
+        let next = if self.shuffle.is_some() {
+            self.next_shuffled()
+        } else {
+            (removed_index.0..self.playlist.entries.len())
+                .find(|&index| !self.playlist.entries[index].skipped)
+                .map(PlaylistIndex)
+        };
+        match next {
+            Some(next) => self.start_track(next),
+            None => self.stop(),
+        }
+    }
+
+    fn load_entries(&mut self, entries: Vec<PlaylistEntry>, start: TrackStart) {
+        self.playlist = Playlist {
+            entries,
+            current_id: None,
+            current_index: None,
+            track_transition: None,
+        };
+        self.unplayable.clear();
+        self.playlist_changed = true;
+        self.unsaved_edits = false;
+        self.start_peak_scan();
+        self.start_metadata_scan();
+        if self.shuffle.take().is_some() {
+            // The seed changes unless one was set
+            self.ensure_shuffle_order();
+            self.report_playlist_mode();
+        }
+        let first = if self.shuffle.is_some() {
+            self.next_shuffled()
+        } else {
+            (!self.playlist.entries.is_empty()).then_some(PlaylistIndex(0))
+        };
+        if let Some(first) = first {
+            self.start_track_with(first, start);
+        }
+    }
+
+    /// Scans the local files in the playlist if peak normalizing, replacing any earlier scan.
+    /// Streams are left out since they don't end.
+    fn start_peak_scan(&mut self) {
+        if self.peak_target_db.is_none() {
+            return;
+        }
+        let mut locations: Vec<Location> = Vec::new();
+        for entry in &self.playlist.entries {
+            if entry.location.as_path().is_some() && !locations.contains(&entry.location) {
+                locations.push(entry.location.clone());
+            }
+        }
+        self.peak_scan = Some(PeakScan::start(locations, self.analysis_cache.clone()));
+    }
+
+    fn finish_peak_scan(&mut self) {
+        let (Some(scan), Some(target_db)) = (&self.peak_scan, self.peak_target_db) else {
+            return;
+        };
+        if let Some(peak) = scan.try_finish() {
+            let gain = playlist_peak_gain([peak], target_db);
+            log::info!("playlist peak is {peak}, so applying a gain of {gain}");
+            self.player_sub
+                .broadcast(PlayerMessage::CommandSetPlaylistGain(gain));
+            self.peak_scan = None;
+        }
+    }
+
+    /// Probes the local files in the playlist, replacing any earlier probe.
+    fn start_metadata_scan(&mut self) {
+        let entries = scannable_entries(&self.playlist.entries);
+        self.metadata_scan = (!entries.is_empty()).then(|| {
+            MetadataScan::start(
+                entries,
+                self.metadata_chain.clone(),
+                self.analysis_cache.clone(),
+            )
+        });
+    }
+
+    /// Probes new entries, such as queued ones, after the ones that are already waiting.
+    fn scan_new_entries(&mut self, entries: &[PlaylistEntry]) {
+        let entries = scannable_entries(entries);
+        if entries.is_empty() {
+            return;
+        }
+        match self.metadata_scan.as_mut() {
+            Some(scan) => scan.extend(entries),
+            None => {
+                self.metadata_scan = Some(MetadataScan::start(
+                    entries,
+                    self.metadata_chain.clone(),
+                    self.analysis_cache.clone(),
+                ))
+            }
+        }
+    }
+
+    /// Fills in the entries probed since the last update, and tells the UI about them.
+    ///
+    /// Entries that can't be played are removed, and files with chapters are split into
+    /// virtual tracks.
+    fn apply_scanned_metadata(&mut self) {
+        let Some(scan) = &self.metadata_scan else {
+            return;
+        };
+        let (scanned, measured) = (scan.take_scanned(), scan.take_measured());
+        if scanned.is_empty() && measured.is_empty() {
+            return;
+        }
+        let indices: HashMap<PlaylistEntryId, usize> = self
+            .playlist
+            .entries
+            .iter()
+            .enumerate()
+            .map(|(index, entry)| (entry.id, index))
+            .collect();
+        let current_id = self.playlist.current().map(|(id, _)| id);
+        let mut updated = Vec::new();
+        let mut queue_updated = false;
+        let mut rejected = Vec::new();
+        let mut chaptered = Vec::new();
+        for ScannedEntry { id, probed } in scanned {
+            // The entry may have been removed since it was probed
+            let entry = match indices.get(&id) {
+                Some(&index) => &mut self.playlist.entries[index],
+                None => match self.play_queue.iter_mut().find(|entry| entry.id == id) {
+                    Some(entry) => {
+                        queue_updated = true;
+                        entry
+                    }
+                    None => continue,
+                },
+            };
+            let probed = match probed {
+                Ok(probed) => probed,
+                // The player reports the current entry itself when it fails to play it
+                Err(_) if Some(id) == current_id => continue,
+                Err(error) => {
+                    rejected.push((id, error));
+                    continue;
+                }
+            };
+            // Files with an embedded cuesheet or chapters are split into virtual tracks
+            if !TrackRange::from_chapters(&probed.chapters).is_empty() {
+                chaptered.push((id, probed.chapters));
+                continue;
+            }
+            // Tags given by a playlist file take precedence over the file's own
+            if entry.metadata.is_none() {
+                entry.metadata = probed.metadata.map(|mut metadata| {
+                    metadata.split_multi_values(&self.tag_separators);
+                    MinimalMetadata::from(&metadata)
+                });
+            }
+            entry.duration = entry.duration.or(probed.duration);
+            if !entry.queued {
+                updated.push(entry.details());
+            }
+        }
+        for (id, dynamic_range) in measured {
+            let entry = match indices.get(&id) {
+                Some(&index) => &mut self.playlist.entries[index],
+                None => match self.play_queue.iter_mut().find(|entry| entry.id == id) {
+                    Some(entry) => entry,
+                    None => continue,
+                },
+            };
+            entry.dynamic_range = Some(dynamic_range);
+            if !entry.queued {
+                updated.retain(|details| details.id != id.0);
+                updated.push(entry.details());
+            }
+        }
+        if !updated.is_empty() {
+            self.ui_sub
+                .broadcast(FrontendMessage::PlaylistEntriesUpdated { entries: updated });
+        }
+        if !rejected.is_empty() || !chaptered.is_empty() {
+            self.replace_scanned_entries(&rejected, chaptered);
+            queue_updated = true;
+        }
+        if !rejected.is_empty() {
+            let errors: Vec<PlaybackError> = rejected.into_iter().map(|(_, error)| error).collect();
+            self.alert_unplayable_files(&errors);
+        }
+        if queue_updated {
+            self.report_play_queue();
+        }
+    }
+
+    /// Keeps the entries that weren't rejected, replacing files with chapters with their
+    /// virtual tracks.
+    fn replace_entries(
+        &mut self,
+        entries: impl IntoIterator<Item = PlaylistEntry>,
+        rejected: &HashSet<PlaylistEntryId>,
+        chaptered: &mut HashMap<PlaylistEntryId, Vec<Chapter>>,
+    ) -> Vec<PlaylistEntry> {
+        let mut replaced = Vec::new();
+        for entry in entries {
+            if rejected.contains(&entry.id) {
+                continue;
+            }
+            let Some(chapters) = chaptered.remove(&entry.id) else {
+                replaced.push(entry);
+                continue;
+            };
+            let ranges = TrackRange::from_chapters(&chapters);
+            for (index, (range, title)) in ranges.into_iter().enumerate() {
+                let metadata = title.map(MinimalMetadata::from_title);
+                let mut chapter = self.new_entry(entry.location.clone(), metadata, Some(range));
+                chapter.queued = entry.queued;
+                // The player is already playing the file from the start of the first chapter
+                if index == 0 && self.playlist.current_id == Some(entry.id) {
+                    self.playlist.current_id = Some(chapter.id);
+                }
+                replaced.push(chapter);
+            }
+        }
+        replaced
+    }
+
+    /// Removes the entries that turned out to be unplayable, and replaces files with chapters
+    /// with a virtual track for each chapter.
+    fn replace_scanned_entries(
+        &mut self,
+        rejected: &[(PlaylistEntryId, PlaybackError)],
+        chaptered: Vec<(PlaylistEntryId, Vec<Chapter>)>,
+    ) {
+        let rejected: HashSet<PlaylistEntryId> = rejected.iter().map(|(id, _)| *id).collect();
+        let mut chaptered: HashMap<PlaylistEntryId, _> = chaptered.into_iter().collect();
+        let entries = mem::take(&mut self.playlist.entries);
+        self.playlist.entries = self.replace_entries(entries, &rejected, &mut chaptered);
+        let queue = mem::take(&mut self.play_queue);
+        self.play_queue = self
+            .replace_entries(queue, &rejected, &mut chaptered)
+            .into();
+        self.playlist.relocate_current();
+        self.playlist_changed = true;
+        // Shuffle again so that the virtual tracks are in the order
+        if self.shuffle.take().is_some() {
+            self.ensure_shuffle_order();
+        }
+    }
+
+    /// Creates playlist entries for the given listed entries, which have had their playlist
+    /// files and directories expanded, and alerts the user if asked to and none of them can
+    /// be played.
+    fn create_entries(
+        &mut self,
+        listed: Vec<ListedEntry>,
+        alert_if_unplayable: bool,
+    ) -> Vec<PlaylistEntry> {
+        let filtered_locations: Vec<ListedEntry> = listed
+            .into_iter()
+            .filter(|listed| match listed.location.as_path() {
+                // Local files are probed by the metadata scan, which sniffs their contents so
+                // that misnamed files still play, and rejects unsupported ones with a reason
+                Some(path) if path.is_file() => true,
+                // Radio streams often don't have an extension, so their type is only
+                // known once they're connected to
+                _ if listed.location.as_url().is_some() => true,
+                _ => !listed.location.inferred_type().is_unknown(),
+            })
+            .collect();
+        if filtered_locations.is_empty() && alert_if_unplayable {
+            self.alert_no_playable_locations();
+        }
+        let mut entries = Vec::new();
+        for listed in filtered_locations {
+            let metadata = MinimalMetadata::from_listed(&listed);
+            let mut entry = self.new_entry(listed.location, metadata, None);
+            entry.duration = listed.duration;
+            entry.variants = listed.variants;
+            entries.push(entry);
+        }
+        entries
+    }
+
+    fn alert_no_playable_locations(&self) {
+        self.ui_sub.broadcast(FrontendMessage::ShowAlert {
+            level: AlertLevel::Info,
+            message: "None of the given files are audio or playlist files.".into(),
+        });
+    }
+
+    fn alert_unplayable_files(&self, rejected: &[PlaybackError]) {
+        let listing = rejected
+            .iter()
+            .map(|error| format!("{}: {}", error.location, error.message))
+            .collect::<Vec<_>>()
+            .join("\n");
+        self.ui_sub.broadcast(FrontendMessage::ShowAlert {
+            level: AlertLevel::Warn,
+            message: format!("The following files can't be played:\n{listing}").into(),
+        });
+    }
+}
+
+/// Entries for the metadata scan to probe. Virtual tracks already have their chapter titles
+/// and lengths, and their file was probed when it was split.
+fn scannable_entries(entries: &[PlaylistEntry]) -> Vec<(PlaylistEntryId, Location)> {
+    entries
+        .iter()
+        .filter(|entry| entry.location.as_path().is_some() && entry.range.is_none())
+        .map(|entry| (entry.id, entry.location.clone()))
+        .collect()
+}
+
+/// Expands playlist files into the entries they contain. Directories are listed as they are,
+/// since they're expanded in the background.
+fn expand_location(
+    location: &Location,
+    depth: usize,
+    rejected: &mut Vec<PlaybackError>,
+) -> Vec<ListedEntry> {
+    match location.as_path() {
+        Some(path) if path.is_dir() => vec![ListedEntry::new(location.clone())],
+        _ if location.sniff_type().is_playlist() => {
+            if depth >= MAX_PLAYLIST_NESTING {
+                log::warn!("not following {location} since playlists are nested too deeply");
+                return Vec::new();
+            }
+            match file::read_playlist(location) {
+                Ok(listed) => listed
+                    .into_iter()
+                    .flat_map(|entry| {
+                        if entry.location.inferred_type().is_playlist() {
+                            expand_location(&entry.location, depth + 1, rejected)
+                        } else {
+                            vec![entry]
+                        }
+                    })
+                    .collect(),
+                Err(err) => {
+                    log::warn!("rejecting {location}: {err}");
+                    rejected.push(err.to_playback_error(location));
+                    Vec::new()
+                }
+            }
+        }
+        _ => vec![ListedEntry::new(location.clone())],
+    }
+}
+
+#[cfg(test)]
+mod playlist_manager_tests {
+    use super::*;
+    use crate::clock::FakeClock;
+    use millenium_post_office::{frontend::state::LiveStatus, types::PlaybackErrorKind};
+
+    #[test]
+    fn no_entries_after_filtering() {
+        let (player, ui) = (Broadcaster::new(), Broadcaster::new());
+        let player_sub = player.subscribe("test", PlayerMessageChannel::All);
+        let ui_sub = ui.subscribe("test", NoChannels);
+
+        let mut manager = PlaylistManager::new(player.clone(), ui.clone());
+
+        ui_sub.broadcast(FrontendMessage::LoadLocations {
+            locations: vec![
+                "not_an_audio_file1".to_string(),
+                "not_an_audio_file2".to_string(),
+            ],
+        });
+        manager.update();
+        pretty_assertions::assert_eq!(Vec::<PlaylistEntry>::new(), manager.playlist.entries);
+        assert_eq!(None, manager.playlist.current_id);
+        assert_eq!(None, manager.playlist.current_index);
+        assert_eq!(None, player_sub.try_recv());
+        assert_eq!(
+            Some(FrontendMessage::ShowAlert {
+                level: AlertLevel::Info,
+                message: "None of the given files are audio or playlist files.".into(),
+            }),
+            ui_sub.try_recv()
+        );
+    }
+
+    #[test]
+    fn normal_mode_play_all_songs_sequentially() {
+        let (player, ui) = (Broadcaster::new(), Broadcaster::new());
+        let player_sub = player.subscribe("test", PlayerMessageChannel::All);
+        let ui_sub = ui.subscribe("test", NoChannels);
+
+        let mut manager = PlaylistManager::new(player.clone(), ui.clone());
+
+        ui_sub.broadcast(FrontendMessage::LoadLocations {
+            locations: vec!["one.ogg".to_string(), "two.ogg".to_string()],
+        });
+        manager.update();
+        pretty_assertions::assert_eq!(
+            vec![
+                PlaylistEntry {
+                    id: PlaylistEntryId(1),
+                    location: Location::path("one.ogg"),
+                    metadata: None,
+                    duration: None,
+                    dynamic_range: None,
+                    intro_skip: None,
+                    skipped: false,
+                    range: None,
+                    variants: Vec::new(),
+                    queued: false,
+                },
+                PlaylistEntry {
+                    id: PlaylistEntryId(2),
+                    location: Location::path("two.ogg"),
+                    metadata: None,
+                    duration: None,
+                    dynamic_range: None,
+                    intro_skip: None,
+                    skipped: false,
+                    range: None,
+                    variants: Vec::new(),
+                    queued: false,
+                },
+            ],
+            manager.playlist.entries
+        );
+        assert_eq!(Some(PlaylistEntryId(1)), manager.playlist.current_id);
+        assert_eq!(Some(PlaylistIndex(0)), manager.playlist.current_index);
+        assert_eq!(
+            PlayerMessage::CommandLoadAndPlayLocation(Location::path("one.ogg")),
+            player_sub.try_recv().unwrap(),
+        );
+
+        player_sub.broadcast(PlayerMessage::EventFinishedTrack);
+        manager.update();
+        assert_eq!(Some(PlaylistEntryId(2)), manager.playlist.current_id);
+        assert_eq!(Some(PlaylistIndex(1)), manager.playlist.current_index);
+        assert_eq!(
+            PlayerMessage::CommandLoadAndPlayLocation(Location::path("two.ogg")),
+            player_sub.try_recv().unwrap(),
+        );
+
+        player_sub.broadcast(PlayerMessage::EventFinishedTrack);
+        manager.update();
+        assert_eq!(None, manager.playlist.current_id);
+        assert_eq!(None, manager.playlist.current_index);
+        assert_eq!(None, player_sub.try_recv());
+
+        assert_eq!(None, ui_sub.try_recv());
+    }
+
+    #[test]
+    fn normal_mode_skip_forward_to_end() {
+        let (player, ui) = (Broadcaster::new(), Broadcaster::new());
+        let player_sub = player.subscribe("test", PlayerMessageChannel::All);
+        let ui_sub = ui.subscribe("test", NoChannels);
+
+        let mut manager = PlaylistManager::new(player.clone(), ui.clone());
+
+        ui_sub.broadcast(FrontendMessage::LoadLocations {
+            locations: vec!["one.ogg".to_string(), "two.ogg".to_string()],
+        });
+        manager.update();
+        assert_eq!(2, manager.playlist.entries.len());
+        assert_eq!(Some(PlaylistEntryId(1)), manager.playlist.current_id);
+        assert_eq!(Some(PlaylistIndex(0)), manager.playlist.current_index);
+        assert_eq!(
+            PlayerMessage::CommandLoadAndPlayLocation(Location::path("one.ogg")),
+            player_sub.try_recv().unwrap(),
+        );
+
+        ui_sub.broadcast(FrontendMessage::MediaControlSkipForward);
+        manager.update();
+        assert_eq!(2, manager.playlist.entries.len());
+        assert_eq!(Some(PlaylistEntryId(2)), manager.playlist.current_id);
+        assert_eq!(Some(PlaylistIndex(1)), manager.playlist.current_index);
+        assert_eq!(
+            PlayerMessage::CommandLoadAndPlayLocation(Location::path("two.ogg")),
+            player_sub.try_recv().unwrap(),
+        );
+
+        ui_sub.broadcast(FrontendMessage::MediaControlSkipForward);
+        manager.update();
+        assert_eq!(2, manager.playlist.entries.len());
+        assert_eq!(None, manager.playlist.current_id);
+        assert_eq!(None, manager.playlist.current_index);
+        assert_eq!(PlayerMessage::CommandStop, player_sub.try_recv().unwrap(),);
+
+        assert_eq!(None, player_sub.try_recv());
+        assert_eq!(None, ui_sub.try_recv());
+    }
+
+    #[test]
+    fn stop_and_play_again() {
+        let (player, ui) = (Broadcaster::new(), Broadcaster::new());
+        let player_sub = player.subscribe("test", PlayerMessageChannel::All);
+        let ui_sub = ui.subscribe("test", NoChannels);
+
+        let mut manager = PlaylistManager::new(player.clone(), ui.clone());
+        ui_sub.broadcast(FrontendMessage::LoadLocations {
+            locations: vec!["one.ogg".into(), "two.ogg".into()],
+        });
+        manager.update();
+        assert_eq!(
+            PlayerMessage::CommandLoadAndPlayLocation(Location::path("one.ogg")),
+            player_sub.try_recv().unwrap(),
+        );
+
+        ui_sub.broadcast(FrontendMessage::MediaControlStop);
+        manager.update();
+        assert_eq!(None, manager.playlist.current_index);
+        assert_eq!(Some(PlayerMessage::CommandStop), player_sub.try_recv());
+
+        // The playlist is kept, so any entry can be played again
+        ui_sub.broadcast(FrontendMessage::PlayPlaylistEntry { id: 2 });
+        manager.update();
+        assert_eq!(Some(PlaylistIndex(1)), manager.playlist.current_index);
+        assert_eq!(
+            PlayerMessage::CommandLoadAndPlayLocation(Location::path("two.ogg")),
+            player_sub.try_recv().unwrap(),
+        );
+    }
+
+    #[test]
+    fn record_skips() {
+        let (player, ui) = (Broadcaster::new(), Broadcaster::new());
+        let player_sub = player.subscribe("test", PlayerMessageChannel::All);
+        let ui_sub = ui.subscribe("test", NoChannels);
+
+        let mut manager = PlaylistManager::new(player.clone(), ui.clone());
+        ui_sub.broadcast(FrontendMessage::LoadLocations {
+            locations: vec![
+                "one.ogg".to_string(),
+                "two.ogg".to_string(),
+                "three.ogg".to_string(),
+            ],
+        });
+        manager.update();
+        let status = |seconds| {
+            PlayerMessage::UpdatePlaybackStatus(PlaybackStatus {
+                playing: true,
+                current_position: Duration::from_secs(seconds),
+                end_position: Some(Duration::from_secs(60)),
+                volume: Default::default(),
+                live: None,
+            })
+        };
+
+        // Skipping before the track reports having started isn't counted
+        player_sub.broadcast(status(30));
+        ui_sub.broadcast(FrontendMessage::MediaControlSkipForward);
+        manager.update();
+        let one = Location::path("one.ogg");
+        assert_eq!(0, manager.skip_history().skip_count(&one, Duration::ZERO));
+
+        player_sub.broadcast(PlayerMessage::EventStartedTrack);
+        player_sub.broadcast(status(12));
+        ui_sub.broadcast(FrontendMessage::MediaControlSkipForward);
+        manager.update();
+        let two = Location::path("two.ogg");
+        let skips = manager.skip_history().skips(&two, Duration::ZERO);
+        assert_eq!(1, skips.len());
+        assert_eq!(Duration::from_secs(12), skips[0].position);
+
+        // Skipping the last part of a track doesn't count
+        player_sub.broadcast(PlayerMessage::EventStartedTrack);
+        player_sub.broadcast(status(58));
+        ui_sub.broadcast(FrontendMessage::MediaControlSkipForward);
+        manager.update();
+        let three = Location::path("three.ogg");
+        assert_eq!(0, manager.skip_history().skip_count(&three, Duration::ZERO));
+    }
+
+    #[test]
+    fn record_plays() {
+        let (player, ui) = (Broadcaster::new(), Broadcaster::new());
+        let player_sub = player.subscribe("test", PlayerMessageChannel::Commands);
+        let ui_sub = ui.subscribe("test", NoChannels);
+        let mut manager = PlaylistManager::new(player.clone(), ui.clone());
+        ui_sub.broadcast(FrontendMessage::LoadLocations {
+            locations: vec!["one.ogg".to_string(), "two.ogg".to_string()],
+        });
+        manager.update();
+        let (one, two) = (Location::path("one.ogg"), Location::path("two.ogg"));
+
+        // Plays from before the current track started are for something else
+        player_sub.broadcast(PlayerMessage::EventTrackPlayed(one.clone()));
+        manager.update();
+        assert_eq!(None, manager.play_history().plays(&one, Duration::ZERO));
+
+        player_sub.broadcast(PlayerMessage::EventStartedTrack);
+        player_sub.broadcast(PlayerMessage::EventTrackPlayed(one.clone()));
+        player_sub.broadcast(PlayerMessage::EventTrackPlayed(two.clone()));
+        manager.update();
+        assert_eq!(
+            1,
+            manager
+                .play_history()
+                .plays(&one, Duration::ZERO)
+                .unwrap()
+                .count
+        );
+        assert_eq!(None, manager.play_history().plays(&two, Duration::ZERO));
+
+        player_sub.broadcast(PlayerMessage::EventTrackPlayed(one.clone()));
+        manager.update();
+        let plays = manager.play_history().plays(&one, Duration::ZERO).unwrap();
+        assert_eq!(2, plays.count);
+        assert!(plays.last_played <= SystemTime::now());
+    }
+
+    #[test]
+    fn peak_normalization_scans_the_playlist() {
+        let (player, ui) = (Broadcaster::new(), Broadcaster::new());
+        let player_sub = player.subscribe("test", PlayerMessageChannel::All);
+        let ui_sub = ui.subscribe("test", NoChannels);
+
+        let mut manager = PlaylistManager::new(player.clone(), ui.clone());
+        ui_sub.broadcast(FrontendMessage::LoadLocations {
+            locations: vec![
+                "../test-data/melodic_a_minor/melodic_a_minor_2chan_44100hz_11s.ogg".to_string(),
+            ],
+        });
+        manager.update();
+        manager.set_peak_normalization(Some(0.0));
+
+        let started = Instant::now();
+        let gain = 'scan: loop {
+            assert!(
+                started.elapsed() < Duration::from_secs(30),
+                "scan took too long"
+            );
+            manager.update();
+            while let Some(message) = player_sub.try_recv() {
+                if let PlayerMessage::CommandSetPlaylistGain(gain) = message {
+                    break 'scan gain;
+                }
+            }
+            std::thread::sleep(Duration::from_millis(10));
+        };
+        // Brought up to full scale, since the test file doesn't peak that high
+        assert!(gain >= 1.0, "unexpected gain: {gain}");
+    }
+
+    #[test]
+    fn play_entry_by_id() {
+        let (player, ui) = (Broadcaster::new(), Broadcaster::new());
+        let player_sub = player.subscribe("test", PlayerMessageChannel::All);
+        let ui_sub = ui.subscribe("test", NoChannels);
+
+        let mut manager = PlaylistManager::new(player.clone(), ui.clone());
+        assert!(!manager.take_playlist_changed());
+        ui_sub.broadcast(FrontendMessage::LoadLocations {
+            locations: vec![
+                "one.ogg".to_string(),
+                "two.ogg".to_string(),
+                "three.ogg".to_string(),
+            ],
+        });
+        manager.update();
+        assert!(manager.take_playlist_changed());
+        assert!(!manager.take_playlist_changed());
+        while player_sub.try_recv().is_some() {}
+
+        ui_sub.broadcast(FrontendMessage::SetPlaylistEntrySkipped {
+            id: 3,
+            skipped: true,
+        });
+        ui_sub.broadcast(FrontendMessage::PlayPlaylistEntry { id: 3 });
+        manager.update();
+        assert_eq!(
+            Some(PlayerMessage::CommandLoadAndPlayLocation(Location::path(
+                "three.ogg"
+            ))),
+            player_sub.try_recv()
+        );
+        assert!(manager.take_playlist_changed());
+
+        let state = manager.playlist().state_data();
+        assert_eq!(Some(2), state.current_index);
+        assert_eq!(
+            vec![(1, false), (2, false), (3, true)],
+            state
+                .entries
+                .iter()
+                .map(|entry| (entry.id, entry.skipped))
+                .collect::<Vec<_>>()
+        );
+
+        // Unknown entries are ignored
+        ui_sub.broadcast(FrontendMessage::PlayPlaylistEntry { id: 42 });
+        manager.update();
+        assert_eq!(None, player_sub.try_recv());
+        assert!(!manager.take_playlist_changed());
+    }
+
+    #[test]
+    fn move_entries_around_the_current_one() {
+        let (player, ui) = (Broadcaster::new(), Broadcaster::new());
+        let player_sub = player.subscribe("test", PlayerMessageChannel::All);
+        let ui_sub = ui.subscribe("test", NoChannels);
+
+        let mut manager = PlaylistManager::new(player.clone(), ui.clone());
+        ui_sub.broadcast(FrontendMessage::LoadLocations {
+            locations: vec!["one.ogg".into(), "two.ogg".into(), "three.ogg".into()],
+        });
+        manager.update();
+        manager.take_playlist_changed();
+        while player_sub.try_recv().is_some() {}
+        let ids = |manager: &PlaylistManager| {
+            manager
+                .playlist
+                .entries
+                .iter()
+                .map(|entry| entry.id.0)
+                .collect::<Vec<_>>()
+        };
+
+        // Moving the current entry keeps it playing
+        ui_sub.broadcast(FrontendMessage::MovePlaylistEntry { from: 0, to: 2 });
+        manager.update();
+        assert_eq!(vec![2, 3, 1], ids(&manager));
+        assert_eq!(
+            Some((PlaylistEntryId(1), PlaylistIndex(2))),
+            manager.playlist.current()
+        );
+        assert!(manager.take_playlist_changed());
+
+        // Moving another entry past it shifts it back
+        ui_sub.broadcast(FrontendMessage::MovePlaylistEntry { from: 2, to: 0 });
+        ui_sub.broadcast(FrontendMessage::MovePlaylistEntry { from: 2, to: 1 });
+        manager.update();
+        assert_eq!(vec![1, 3, 2], ids(&manager));
+        assert_eq!(
+            Some((PlaylistEntryId(1), PlaylistIndex(0))),
+            manager.playlist.current()
+        );
+
+        // Out of range moves are ignored
+        ui_sub.broadcast(FrontendMessage::MovePlaylistEntry { from: 0, to: 3 });
+        manager.update();
+        assert_eq!(vec![1, 3, 2], ids(&manager));
+        assert_eq!(None, player_sub.try_recv());
+    }
+
+    #[test]
+    fn remove_entries() {
+        let (player, ui) = (Broadcaster::new(), Broadcaster::new());
+        let player_sub = player.subscribe("test", PlayerMessageChannel::All);
+        let ui_sub = ui.subscribe("test", NoChannels);
+
+        let mut manager = PlaylistManager::new(player.clone(), ui.clone());
+        ui_sub.broadcast(FrontendMessage::LoadLocations {
+            locations: vec![
+                "one.ogg".into(),
+                "two.ogg".into(),
+                "three.ogg".into(),
+                "four.ogg".into(),
+            ],
+        });
+        manager.update();
+        ui_sub.broadcast(FrontendMessage::PlayPlaylistEntry { id: 2 });
+        manager.update();
+        while player_sub.try_recv().is_some() {}
+
+        // Removing other entries leaves the current one playing
+        ui_sub.broadcast(FrontendMessage::RemovePlaylistEntries { ids: vec![1] });
+        manager.update();
+        assert_eq!(None, player_sub.try_recv());
+        assert_eq!(
+            Some((PlaylistEntryId(2), PlaylistIndex(0))),
+            manager.playlist.current()
+        );
+
+        // Removing the current entry plays the one that takes its place
+        ui_sub.broadcast(FrontendMessage::RemovePlaylistEntries { ids: vec![2, 3] });
+        manager.update();
+        assert_eq!(
+            Some(PlayerMessage::CommandLoadAndPlayLocation(Location::path(
+                "four.ogg"
+            ))),
+            player_sub.try_recv()
+        );
+        assert_eq!(
+            Some((PlaylistEntryId(4), PlaylistIndex(0))),
+            manager.playlist.current()
+        );
+
+        // Removing the last entry stops playback
+        ui_sub.broadcast(FrontendMessage::RemovePlaylistEntries { ids: vec![4] });
+        manager.update();
+        assert_eq!(Some(PlayerMessage::CommandStop), player_sub.try_recv());
+        assert!(manager.playlist.entries.is_empty());
+        assert_eq!(None, manager.playlist.current());
+    }
+
+    #[test]
+    fn reload_current_at_last_position() {
+        let (player, ui) = (Broadcaster::new(), Broadcaster::new());
+        let player_sub = player.subscribe("test", PlayerMessageChannel::All);
+        let ui_sub = ui.subscribe("test", NoChannels);
+
+        let mut manager = PlaylistManager::new(player.clone(), ui.clone());
+        manager.reload_current();
+        assert_eq!(None, player_sub.try_recv());
+
+        ui_sub.broadcast(FrontendMessage::LoadLocations {
+            locations: vec!["one.ogg".to_string(), "two.ogg".to_string()],
+        });
+        manager.update();
+        ui_sub.broadcast(FrontendMessage::MediaControlSkipForward);
+        manager.update();
+        while player_sub.try_recv().is_some() {}
+
+        let mut status = PlaybackStatus {
+            playing: true,
+            current_position: Duration::from_secs(42),
+            end_position: Some(Duration::from_secs(60)),
+            volume: Default::default(),
+            live: None,
+        };
+        player_sub.broadcast(PlayerMessage::UpdatePlaybackStatus(status));
+        manager.update();
+        manager.reload_current();
+        assert_eq!(
+            Some(PlayerMessage::CommandLoadAndPlayLocationFrom(
+                Location::path("two.ogg"),
+                Duration::from_secs(42)
+            )),
+            player_sub.try_recv()
+        );
+        assert_eq!(None, player_sub.try_recv());
+
+        // Stays paused if it was paused
+        status.playing = false;
+        player_sub.broadcast(PlayerMessage::UpdatePlaybackStatus(status));
+        manager.update();
+        manager.reload_current();
+        assert!(matches!(
+            player_sub.try_recv(),
+            Some(PlayerMessage::CommandLoadAndPlayLocationFrom(..))
+        ));
+        assert_eq!(Some(PlayerMessage::CommandPause), player_sub.try_recv());
+    }
+
+    #[test]
+    fn seek_by_seconds() {
+        let (player, ui) = (Broadcaster::new(), Broadcaster::new());
+        let player_sub = player.subscribe("test", PlayerMessageChannel::All);
+        let ui_sub = ui.subscribe("test", NoChannels);
+
+        let mut manager = PlaylistManager::new(player.clone(), ui.clone());
+        let mut status = PlaybackStatus {
+            playing: true,
+            current_position: Duration::from_secs(10),
+            end_position: Some(Duration::from_secs(60)),
+            volume: Default::default(),
+            live: None,
+        };
+        player_sub.broadcast(PlayerMessage::UpdatePlaybackStatus(status));
+        manager.update();
+
+        let seeks = |seconds: Vec<i32>, manager: &mut PlaylistManager| {
+            for seconds in seconds {
+                ui_sub.broadcast(FrontendMessage::MediaControlSeekBy { seconds });
+            }
+            manager.update();
+            std::iter::from_fn(|| player_sub.try_recv()).collect::<Vec<_>>()
+        };
+        assert_eq!(
+            vec![
+                PlayerMessage::CommandSeek(Duration::from_secs(40)),
+                PlayerMessage::CommandSeek(Duration::ZERO),
+                PlayerMessage::CommandSeek(Duration::from_secs(60)),
+            ],
+            seeks(vec![30, -15, 90], &mut manager)
+        );
+
+        // Live streams stay within the timeshift
+        status.end_position = None;
+        status.live = Some(LiveStatus {
+            earliest_position: Duration::from_secs(5),
+            behind_live: Duration::from_secs(20),
+        });
+        player_sub.broadcast(PlayerMessage::UpdatePlaybackStatus(status));
+        manager.update();
+        assert_eq!(
+            vec![
+                PlayerMessage::CommandSeek(Duration::from_secs(30)),
+                PlayerMessage::CommandSeek(Duration::from_secs(5)),
+            ],
+            seeks(vec![30, -15], &mut manager)
+        );
+    }
+
+    #[test]
+    fn playlist_durations() {
+        let (player, ui) = (Broadcaster::new(), Broadcaster::new());
+        let ui_sub = ui.subscribe("test", NoChannels);
+
+        let mut manager = PlaylistManager::new(player.clone(), ui.clone());
+        ui_sub.broadcast(FrontendMessage::LoadLocations {
+            locations: vec!["one.ogg".into(), "two.ogg".into(), "three.ogg".into()],
+        });
+        manager.update();
+        manager.playlist.entries[0].duration = Some(Duration::from_secs(60));
+        manager.playlist.entries[2].duration = Some(Duration::from_secs(90));
+
+        let mut state = manager.playlist().state_data();
+        assert_eq!(None, state.total_duration, "not every duration has loaded");
+        assert_eq!(
+            Some(Duration::from_secs(150)),
+            state.duration_of(|entry| entry.id != 2)
+        );
+
+        let mut two = state.entries[1].clone();
+        two.duration = Some(Duration::from_secs(30));
+        state.update_entries(&[two]);
+        assert_eq!(Some(Duration::from_secs(180)), state.total_duration);
+        assert_eq!(
+            manager.playlist.entries[0].duration,
+            state.duration_of(|entry| entry.id == 1)
+        );
+    }
+
+    #[test]
+    fn metadata_is_probed_in_the_background() {
+        let (player, ui) = (Broadcaster::new(), Broadcaster::new());
+        let ui_sub = ui.subscribe("test", NoChannels);
+
+        let mut manager = PlaylistManager::new(player.clone(), ui.clone());
+        ui_sub.broadcast(FrontendMessage::LoadLocations {
+            locations: vec![
+                "../test-data/hydrate/hydrate.mp3".to_string(),
+                "../test-data/melodic_a_minor/melodic_a_minor_1chan_44100hz_6s.ogg".to_string(),
+            ],
+        });
+        manager.update();
+
+        let started = Instant::now();
+        let mut updated = Vec::new();
+        while updated.len() < 2 {
+            assert!(
+                started.elapsed() < Duration::from_secs(30),
+                "probe took too long"
+            );
+            manager.update();
+            while let Some(message) = ui_sub.try_recv() {
+                if let FrontendMessage::PlaylistEntriesUpdated { entries } = message {
+                    updated.extend(entries);
+                }
+            }
+            std::thread::sleep(Duration::from_millis(10));
+        }
+        updated.sort_by_key(|details| details.id);
+        assert_eq!(Some("hydrate (the beach)"), updated[0].title.as_deref());
+        let duration = updated[1].duration.expect("duration").as_secs_f64();
+        assert!(
+            (duration - 6.0).abs() < 0.5,
+            "unexpected duration: {duration}"
+        );
+
+        let entries = &manager.playlist.entries;
+        assert_eq!(updated[0].title, entries[0].details().title);
+        assert_eq!(updated[1].duration, entries[1].duration);
+    }
+
+    #[test]
+    fn normal_mode_skip_back() {
+        let (player, ui) = (Broadcaster::new(), Broadcaster::new());
+        let player_sub = player.subscribe("test", PlayerMessageChannel::All);
+        let ui_sub = ui.subscribe("test", NoChannels);
+
+        let mut manager = PlaylistManager::new(player.clone(), ui.clone());
+
+        ui_sub.broadcast(FrontendMessage::LoadLocations {
+            locations: vec!["one.ogg".to_string(), "two.ogg".to_string()],
+        });
+        manager.update();
+        assert_eq!(2, manager.playlist.entries.len());
+        assert_eq!(Some(PlaylistEntryId(1)), manager.playlist.current_id);
+        assert_eq!(Some(PlaylistIndex(0)), manager.playlist.current_index);
+        assert_eq!(
+            PlayerMessage::CommandLoadAndPlayLocation(Location::path("one.ogg")),
+            player_sub.try_recv().unwrap(),
+        );
+
+        player_sub.broadcast(PlayerMessage::UpdatePlaybackStatus(PlaybackStatus {
+            playing: true,
+            current_position: Duration::from_secs(7),
+            end_position: Some(Duration::from_secs(60)),
+            volume: Default::default(),
+            live: None,
+        }));
+        manager.update();
+
+        // Since we're 7 seconds into the song, skipping back should restart the song
+        ui_sub.broadcast(FrontendMessage::MediaControlSkipBack);
+        manager.update();
+        assert_eq!(2, manager.playlist.entries.len());
+        assert_eq!(Some(PlaylistEntryId(1)), manager.playlist.current_id);
+        assert_eq!(Some(PlaylistIndex(0)), manager.playlist.current_index);
+        assert_eq!(
+            PlayerMessage::CommandLoadAndPlayLocation(Location::path("one.ogg")),
+            player_sub.try_recv().unwrap(),
+        );
+
+        // Now skipping back should go off the end of the playlist
+        player_sub.broadcast(PlayerMessage::UpdatePlaybackStatus(PlaybackStatus {
+            playing: true,
+            current_position: Duration::from_secs(1),
+            end_position: Some(Duration::from_secs(60)),
+            volume: Default::default(),
+            live: None,
+        }));
+        manager.update();
+        ui_sub.broadcast(FrontendMessage::MediaControlSkipBack);
+        manager.update();
+        assert_eq!(2, manager.playlist.entries.len());
+        assert_eq!(None, manager.playlist.current_id);
+        assert_eq!(None, manager.playlist.current_index);
+        assert_eq!(PlayerMessage::CommandStop, player_sub.try_recv().unwrap(),);
+
+        assert_eq!(None, player_sub.try_recv());
+        assert_eq!(None, ui_sub.try_recv());
+    }
+
+    #[test]
+    fn preview_entry() {
+        let (player, ui) = (Broadcaster::new(), Broadcaster::new());
+        let player_sub = player.subscribe("test", PlayerMessageChannel::All);
+        let ui_sub = ui.subscribe("test", NoChannels);
+
+        let mut manager = PlaylistManager::new(player.clone(), ui.clone());
+        ui_sub.broadcast(FrontendMessage::LoadLocations {
+            locations: vec!["one.ogg".to_string(), "two.ogg".to_string()],
+        });
+        manager.update();
+        assert_eq!(
+            PlayerMessage::CommandLoadAndPlayLocation(Location::path("one.ogg")),
+            player_sub.try_recv().unwrap(),
+        );
+        assert!(manager.take_playlist_changed());
+
+        ui_sub.broadcast(FrontendMessage::PreviewPlaylistEntry { id: 2 });
+        ui_sub.broadcast(FrontendMessage::StopPreview);
+        manager.update();
+        assert_eq!(
+            Some(PlayerMessage::CommandPreviewLocation(Location::path(
+                "two.ogg"
+            ))),
+            player_sub.try_recv()
+        );
+        assert_eq!(
+            Some(PlayerMessage::CommandStopPreview),
+            player_sub.try_recv()
+        );
+        assert_eq!(Some(PlaylistIndex(0)), manager.playlist.current_index);
+        assert!(!manager.take_playlist_changed());
+    }
+
+    #[test]
+    fn cue_next_track() {
+        let (player, ui) = (Broadcaster::new(), Broadcaster::new());
+        let player_sub = player.subscribe("test", PlayerMessageChannel::All);
+        let ui_sub = ui.subscribe("test", NoChannels);
+
+        let mut manager = PlaylistManager::new(player.clone(), ui.clone());
+
+        ui_sub.broadcast(FrontendMessage::LoadLocations {
+            locations: vec!["one.ogg".to_string(), "two.ogg".to_string()],
+        });
+        manager.update();
+        assert_eq!(
+            PlayerMessage::CommandLoadAndPlayLocation(Location::path("one.ogg")),
+            player_sub.try_recv().unwrap(),
+        );
+
+        ui_sub.broadcast(FrontendMessage::MediaControlCueNext);
+        manager.update();
+        assert_eq!(
+            PlayerMessage::CommandCueLocation(Location::path("two.ogg")),
+            player_sub.try_recv().unwrap(),
+        );
+        assert_eq!(Some(PlaylistIndex(0)), manager.playlist.current_index);
+
+        // There's nothing after the last track to cue
+        ui_sub.broadcast(FrontendMessage::MediaControlSkipForward);
+        manager.update();
+        let _ = player_sub.try_recv();
+        ui_sub.broadcast(FrontendMessage::MediaControlCueNext);
+        manager.update();
+        assert_eq!(None, player_sub.try_recv());
+    }
+
+    #[test]
+    fn normal_mode_passes_over_skipped_entries() {
+        let (player, ui) = (Broadcaster::new(), Broadcaster::new());
+        let player_sub = player.subscribe("test", PlayerMessageChannel::All);
+        let ui_sub = ui.subscribe("test", NoChannels);
+
+        let mut manager = PlaylistManager::new(player.clone(), ui.clone());
+
+        ui_sub.broadcast(FrontendMessage::LoadLocations {
+            locations: vec![
+                "one.ogg".to_string(),
+                "interlude.ogg".to_string(),
+                "three.ogg".to_string(),
+            ],
+        });
+        ui_sub.broadcast(FrontendMessage::SetPlaylistEntrySkipped {
+            id: 2,
+            skipped: true,
+        });
+        manager.update();
+        assert!(manager.playlist.entries[1].skipped);
+        assert_eq!(
+            PlayerMessage::CommandLoadAndPlayLocation(Location::path("one.ogg")),
+            player_sub.try_recv().unwrap(),
+        );
+
+        player_sub.broadcast(PlayerMessage::EventFinishedTrack);
+        manager.update();
+        assert_eq!(Some(PlaylistEntryId(3)), manager.playlist.current_id);
+        assert_eq!(Some(PlaylistIndex(2)), manager.playlist.current_index);
+        assert_eq!(
+            PlayerMessage::CommandLoadAndPlayLocation(Location::path("three.ogg")),
+            player_sub.try_recv().unwrap(),
+        );
+
+        ui_sub.broadcast(FrontendMessage::MediaControlSkipBack);
+        manager.update();
+        assert_eq!(Some(PlaylistIndex(0)), manager.playlist.current_index);
+        assert_eq!(
+            PlayerMessage::CommandLoadAndPlayLocation(Location::path("one.ogg")),
+            player_sub.try_recv().unwrap(),
+        );
+
+        // Un-skipping the entry should make it playable again
+        ui_sub.broadcast(FrontendMessage::SetPlaylistEntrySkipped {
+            id: 2,
+            skipped: false,
+        });
+        ui_sub.broadcast(FrontendMessage::MediaControlSkipForward);
+        manager.update();
+        assert_eq!(Some(PlaylistIndex(1)), manager.playlist.current_index);
+        assert_eq!(
+            PlayerMessage::CommandLoadAndPlayLocation(Location::path("interlude.ogg")),
+            player_sub.try_recv().unwrap(),
+        );
+        assert_eq!(None, ui_sub.try_recv());
+    }
+
+    #[test]
+    fn record_dynamic_range_on_current_entry() {
+        let (player, ui) = (Broadcaster::new(), Broadcaster::new());
+        let player_sub = player.subscribe("test", PlayerMessageChannel::All);
+        let ui_sub = ui.subscribe("test", NoChannels);
+
+        let mut manager = PlaylistManager::new(player.clone(), ui.clone());
+
+        ui_sub.broadcast(FrontendMessage::LoadLocations {
+            locations: vec!["one.ogg".to_string(), "two.ogg".to_string()],
+        });
+        manager.update();
+
+        player_sub.broadcast(PlayerMessage::EventDynamicRangeMeasured(DynamicRange(11)));
+        player_sub.broadcast(PlayerMessage::EventFinishedTrack);
+        manager.update();
+        assert_eq!(
+            Some(DynamicRange(11)),
+            manager.playlist.entries[0].dynamic_range
+        );
+        assert_eq!(None, manager.playlist.entries[1].dynamic_range);
+        assert_eq!(Some(PlaylistIndex(1)), manager.playlist.current_index);
+    }
+
+    #[test]
+    fn intro_skip() {
+        let (player, ui) = (Broadcaster::new(), Broadcaster::new());
+        let player_sub = player.subscribe("test", PlayerMessageChannel::All);
+        let ui_sub = ui.subscribe("test", NoChannels);
+
+        let mut manager = PlaylistManager::new(player.clone(), ui.clone());
+        manager.set_intro_skip(Some(Duration::from_secs(30)));
+
+        ui_sub.broadcast(FrontendMessage::LoadLocations {
+            locations: vec!["one.ogg".to_string(), "two.ogg".to_string()],
+        });
+        manager.update();
+        assert_eq!(
+            PlayerMessage::CommandLoadAndPlayLocationFrom(
+                Location::path("one.ogg"),
+                Duration::from_secs(30)
+            ),
+            player_sub.try_recv().unwrap(),
+        );
+        assert_eq!(
+            Some(FrontendMessage::IntroSkipped {
+                position: Duration::from_secs(30)
+            }),
+            ui_sub.try_recv()
+        );
+
+        ui_sub.broadcast(FrontendMessage::MediaControlUndoIntroSkip);
+        manager.update();
+        assert_eq!(
+            PlayerMessage::CommandSeek(Duration::ZERO),
+            player_sub.try_recv().unwrap(),
+        );
+
+        // A zero override on the entry disables the global intro skip
+        ui_sub.broadcast(FrontendMessage::SetPlaylistEntryIntroSkip {
+            id: 2,
+            skip: Some(Duration::ZERO),
+        });
+        ui_sub.broadcast(FrontendMessage::MediaControlSkipForward);
+        manager.update();
+        assert_eq!(
+            PlayerMessage::CommandLoadAndPlayLocation(Location::path("two.ogg")),
+            player_sub.try_recv().unwrap(),
+        );
+        assert_eq!(None, ui_sub.try_recv());
+    }
+
+    #[test]
+    fn entry_intro_skips_are_remembered() {
+        let path = std::env::temp_dir().join(format!("intro-skips-{}.json", std::process::id()));
+        let (player, ui) = (Broadcaster::new(), Broadcaster::new());
+        let ui_sub = ui.subscribe("test", NoChannels);
+
+        let mut manager = PlaylistManager::new(player.clone(), ui.clone());
+        manager.set_track_ratings(TrackRatings::load(&path));
+        ui_sub.broadcast(FrontendMessage::LoadLocations {
+            locations: vec!["podcast.mp3".into()],
+        });
+        manager.update();
+        ui_sub.broadcast(FrontendMessage::SetPlaylistEntryIntroSkip {
+            id: 1,
+            skip: Some(Duration::from_secs(90)),
+        });
+        manager.update();
+
+        // Loading the track again after a restart brings back its intro skip
+        let mut manager = PlaylistManager::new(player.clone(), ui.clone());
+        manager.set_track_ratings(TrackRatings::load(&path));
+        let _ = std::fs::remove_file(&path);
+        ui_sub.broadcast(FrontendMessage::LoadLocations {
+            locations: vec!["podcast.mp3".into(), "other.mp3".into()],
+        });
+        manager.update();
+        assert_eq!(
+            Some(Duration::from_secs(90)),
+            manager.playlist.entries[0].intro_skip
+        );
+        assert_eq!(None, manager.playlist.entries[1].intro_skip);
+    }
+
+    #[test]
+    fn folder_defaults() {
+        let (player, ui) = (Broadcaster::new(), Broadcaster::new());
+        let player_sub = player.subscribe("test", PlayerMessageChannel::All);
+        let ui_sub = ui.subscribe("test", NoChannels);
+
+        let mut manager = PlaylistManager::new(player.clone(), ui.clone());
+        manager.set_intro_skip(Some(Duration::from_secs(5)));
+        manager.set_track_transition(TrackTransition::Gap(Duration::from_secs(3)));
+        manager.set_folder_defaults(vec![FolderDefaults {
+            folder: "/audiobooks".into(),
+            intro_skip: Some(Duration::ZERO),
+            track_transition: Some(TrackTransition::Gap(Duration::from_secs(2))),
+        }]);
+
+        ui_sub.broadcast(FrontendMessage::LoadLocations {
+            locations: vec![
+                "/audiobooks/one.mp3".to_string(),
+                "/music/two.ogg".to_string(),
+            ],
+        });
+        manager.update();
+        assert_eq!(
+            PlayerMessage::CommandLoadAndPlayLocation(Location::path("/audiobooks/one.mp3")),
+            player_sub.try_recv().unwrap(),
+        );
+        assert_eq!(
+            TrackTransition::Gap(Duration::from_secs(2)),
+            manager.current_track_transition()
+        );
+
+        // Tracks outside of the folder use the global settings
+        ui_sub.broadcast(FrontendMessage::MediaControlSkipForward);
+        manager.update();
+        assert_eq!(
+            PlayerMessage::CommandLoadAndPlayLocationFrom(
+                Location::path("/music/two.ogg"),
+                Duration::from_secs(5)
+            ),
+            player_sub.try_recv().unwrap(),
+        );
+        assert_eq!(
+            TrackTransition::Gap(Duration::from_secs(3)),
+            manager.current_track_transition()
+        );
+    }
+
+    #[test]
+    fn gapless_next_track() {
+        let (player, ui) = (Broadcaster::new(), Broadcaster::new());
+        let player_sub = player.subscribe("test", PlayerMessageChannel::All);
+        let ui_sub = ui.subscribe("test", NoChannels);
+
+        let mut manager = PlaylistManager::new(player.clone(), ui.clone());
+        ui_sub.broadcast(FrontendMessage::LoadLocations {
+            locations: vec!["one.ogg".into(), "two.ogg".into(), "three.ogg".into()],
+        });
+        manager.update();
+        assert_eq!(
+            PlayerMessage::CommandLoadAndPlayLocation(Location::path("one.ogg")),
+            player_sub.try_recv().unwrap(),
+        );
+        assert_eq!(None, player_sub.try_recv());
+
+        // The next track is queued once the current one starts
+        player_sub.broadcast(PlayerMessage::EventStartedTrack);
+        manager.update();
+        assert_eq!(
+            PlayerMessage::CommandSetNextLocation(Some(Location::path("two.ogg")), Duration::ZERO),
+            player_sub.try_recv().unwrap(),
+        );
+        assert_eq!(None, player_sub.try_recv());
+
+        // The player moves on by itself, and the one after that is queued
+        player_sub.broadcast(PlayerMessage::EventStartedNextTrack(Location::path(
+            "two.ogg",
+        )));
+        manager.update();
+        assert_eq!(Some(PlaylistIndex(1)), manager.playlist.current_index);
+        assert_eq!(
+            PlayerMessage::CommandSetNextLocation(
+                Some(Location::path("three.ogg")),
+                Duration::ZERO
+            ),
+            player_sub.try_recv().unwrap(),
+        );
+
+        // Changes to what comes next are passed along
+        ui_sub.broadcast(FrontendMessage::SetPlaylistEntrySkipped {
+            id: 3,
+            skipped: true,
+        });
+        manager.update();
+        assert_eq!(
+            PlayerMessage::CommandSetNextLocation(None, Duration::ZERO),
+            player_sub.try_recv().unwrap(),
+        );
+        ui_sub.broadcast(FrontendMessage::MediaControlPlaylistMode {
+            mode: PlaylistMode::RepeatOne,
+        });
+        manager.update();
+        assert_eq!(
+            PlayerMessage::CommandSetNextLocation(Some(Location::path("two.ogg")), Duration::ZERO),
+            player_sub.try_recv().unwrap(),
+        );
+        assert_eq!(
+            Some(FrontendMessage::PlaylistModeChanged {
+                mode: PlaylistMode::RepeatOne,
+                shuffle_seed: None,
+            }),
+            ui_sub.try_recv()
+        );
+
+        // Transitions that need the next track started separately don't queue it
+        ui_sub.broadcast(FrontendMessage::SetTrackTransition {
+            transition: TrackTransition::Gap(Duration::from_secs(1)),
+        });
+        manager.update();
+        assert_eq!(
+            PlayerMessage::CommandSetNextLocation(None, Duration::ZERO),
+            player_sub.try_recv().unwrap(),
+        );
+        assert_eq!(None, player_sub.try_recv());
+
+        // Crossfades are left to the player
+        ui_sub.broadcast(FrontendMessage::SetTrackTransition {
+            transition: TrackTransition::Overlap(Duration::from_secs(2)),
+        });
+        manager.update();
+        assert_eq!(
+            PlayerMessage::CommandSetNextLocation(
+                Some(Location::path("two.ogg")),
+                Duration::from_secs(2)
+            ),
+            player_sub.try_recv().unwrap(),
+        );
+        assert_eq!(None, player_sub.try_recv());
+        assert_eq!(None, ui_sub.try_recv());
+    }
+
+    #[test]
+    fn gap_between_tracks() {
+        let (player, ui) = (Broadcaster::new(), Broadcaster::new());
+        let player_sub = player.subscribe("test", PlayerMessageChannel::All);
+        let ui_sub = ui.subscribe("test", NoChannels);
+
+        let clock = FakeClock::new();
+        let mut manager = PlaylistManager::new(player.clone(), ui.clone());
+        manager.set_clock(clock.shared());
+        manager.set_track_transition(TrackTransition::Gap(Duration::from_secs(2)));
+
+        ui_sub.broadcast(FrontendMessage::LoadLocations {
+            locations: vec!["one.ogg".to_string(), "two.ogg".to_string()],
+        });
+        manager.update();
+        assert_eq!(
+            PlayerMessage::CommandLoadAndPlayLocation(Location::path("one.ogg")),
+            player_sub.try_recv().unwrap(),
+        );
+
+        player_sub.broadcast(PlayerMessage::EventFinishedTrack);
+        manager.update();
+        assert_eq!(Some(PlaylistIndex(0)), manager.playlist.current_index);
+        assert_eq!(None, player_sub.try_recv());
+
+        clock.advance(Duration::from_millis(1999));
+        manager.update();
+        assert_eq!(None, player_sub.try_recv());
+
+        clock.advance(Duration::from_millis(1));
+        manager.update();
+        assert_eq!(Some(PlaylistIndex(1)), manager.playlist.current_index);
+        assert_eq!(
+            PlayerMessage::CommandLoadAndPlayLocation(Location::path("two.ogg")),
+            player_sub.try_recv().unwrap(),
+        );
+        assert_eq!(None, manager.next_track_at);
+    }
+
+    #[test]
+    fn playlist_overrides_track_transition() {
+        let (player, ui) = (Broadcaster::new(), Broadcaster::new());
+        let player_sub = player.subscribe("test", PlayerMessageChannel::All);
+        let ui_sub = ui.subscribe("test", NoChannels);
+
+        let mut manager = PlaylistManager::new(player.clone(), ui.clone());
+        manager.set_track_transition(TrackTransition::Gap(Duration::from_secs(2)));
+
+        ui_sub.broadcast(FrontendMessage::LoadLocations {
+            locations: vec!["one.ogg".to_string(), "two.ogg".to_string()],
+        });
+        ui_sub.broadcast(FrontendMessage::SetPlaylistTrackTransition {
+            transition: Some(TrackTransition::Immediate),
+        });
+        manager.update();
+        assert_eq!(
+            PlayerMessage::CommandLoadAndPlayLocation(Location::path("one.ogg")),
+            player_sub.try_recv().unwrap(),
+        );
+        assert_eq!(
+            TrackTransition::Immediate,
+            manager.current_track_transition()
+        );
+
+        // The next track starts right away instead of after the global gap
+        player_sub.broadcast(PlayerMessage::EventFinishedTrack);
+        manager.update();
+        assert_eq!(None, manager.next_track_at);
+        assert_eq!(Some(PlaylistIndex(1)), manager.playlist.current_index);
+        assert_eq!(
+            PlayerMessage::CommandLoadAndPlayLocation(Location::path("two.ogg")),
+            player_sub.try_recv().unwrap(),
+        );
+
+        // Clearing the override goes back to the global gap
+        ui_sub.broadcast(FrontendMessage::SetPlaylistTrackTransition { transition: None });
+        manager.update();
+        assert_eq!(
+            TrackTransition::Gap(Duration::from_secs(2)),
+            manager.current_track_transition()
+        );
+    }
+
+    #[test]
+    fn replay_recorded_session() {
+        use millenium_post_office::broadcast::record::{MessageRecorder, MessageReplayer};
+        use std::{fs, io::BufReader};
+
+        let recording_path = std::env::temp_dir().join(format!(
+            "millenium-replay-test-{}.jsonl",
+            std::process::id()
+        ));
+        {
+            let (player, ui) = (Broadcaster::new(), Broadcaster::new());
+            let recorder = MessageRecorder::create(&recording_path).unwrap();
+            recorder.attach("player", &player);
+            recorder.attach("frontend", &ui);
+
+            let mut manager = PlaylistManager::new(player.clone(), ui.clone());
+            ui.broadcast(FrontendMessage::LoadLocations {
+                locations: vec!["one.ogg".to_string(), "two.ogg".to_string()],
+            });
+            manager.update();
+            player.broadcast(PlayerMessage::EventFinishedTrack);
+            manager.update();
+        }
+
+        let (player, ui) = (Broadcaster::new(), Broadcaster::new());
+        let player_sub = player.subscribe("test", PlayerMessageChannel::Commands);
+        let mut manager = PlaylistManager::new(player.clone(), ui.clone());
+        let file = fs::File::open(&recording_path).unwrap();
+        let mut replayer = MessageReplayer::from_reader(BufReader::new(file))
+            .unwrap()
+            .target("player", player)
+            .target("frontend", ui);
+        fs::remove_file(&recording_path).unwrap();
+        while replayer.step() {
+            manager.update();
+        }
+
+        assert_eq!(Some(PlaylistIndex(1)), manager.playlist.current_index);
+        // The recorded commands are replayed, followed by the replaying manager's own commands
+        let mut commands = Vec::new();
+        while let Some(command) = player_sub.try_recv() {
+            commands.push(command);
+        }
+        assert!(
+            commands.contains(&PlayerMessage::CommandLoadAndPlayLocation(Location::path(
+                "two.ogg"
+            )))
+        );
+    }
+
+    #[test]
+    fn retry_temporary_playback_errors_once() {
+        let (player, ui) = (Broadcaster::new(), Broadcaster::new());
+        let player_sub = player.subscribe("test", PlayerMessageChannel::All);
+        let ui_sub = ui.subscribe("test", NoChannels);
+
+        let mut manager = PlaylistManager::new(player.clone(), ui.clone());
+
+        ui_sub.broadcast(FrontendMessage::LoadLocations {
+            locations: vec!["one.ogg".to_string(), "two.ogg".to_string()],
+        });
+        manager.update();
+        let load_one = PlayerMessage::CommandLoadAndPlayLocation(Location::path("one.ogg"));
+        assert_eq!(load_one, player_sub.try_recv().unwrap());
+
+        let error = |location: &str, retryable: bool| PlaybackError {
+            kind: PlaybackErrorKind::Unreadable,
+            location: location.into(),
+            message: "failed to load file".into(),
+            retryable,
+        };
+
+        // Errors for other locations (such as the cue output) are ignored
+        player_sub.broadcast(PlayerMessage::EventFailedToLoadLocation(error(
+            "two.ogg", true,
+        )));
+        manager.update();
+        assert_eq!(None, player_sub.try_recv());
+        assert_eq!(None, ui_sub.try_recv());
+
+        player_sub.broadcast(PlayerMessage::EventFailedToLoadLocation(error(
+            "one.ogg", true,
+        )));
+        manager.update();
+        assert_eq!(load_one, player_sub.try_recv().unwrap());
+        assert_eq!(None, ui_sub.try_recv());
+
+        // Errors partway through the track retry from where it was last heard playing
+        player_sub.broadcast(PlayerMessage::EventStartedTrack);
+        player_sub.broadcast(PlayerMessage::UpdatePlaybackStatus(PlaybackStatus {
+            playing: true,
+            current_position: Duration::from_secs(42),
+            end_position: Some(Duration::from_secs(60)),
+            volume: Default::default(),
+            live: None,
+        }));
+        player_sub.broadcast(PlayerMessage::EventFailedToDecodeAudio(error(
+            "one.ogg", true,
+        )));
+        manager.update();
+        assert_eq!(
+            PlayerMessage::CommandLoadAndPlayLocationFrom(
+                Location::path("one.ogg"),
+                Duration::from_secs(42)
+            ),
+            player_sub.try_recv().unwrap()
+        );
+        assert_eq!(None, ui_sub.try_recv());
+
+        // Failing again skips the entry
+        player_sub.broadcast(PlayerMessage::EventFailedToLoadLocation(error(
+            "one.ogg", true,
+        )));
+        manager.update();
+        assert_eq!(
+            PlayerMessage::CommandLoadAndPlayLocation(Location::path("two.ogg")),
+            player_sub.try_recv().unwrap()
+        );
+        assert_eq!(None, ui_sub.try_recv());
+    }
+
+    #[test]
+    fn skip_unplayable_entries_and_summarize() {
+        let (player, ui) = (Broadcaster::new(), Broadcaster::new());
+        let player_sub = player.subscribe("test", PlayerMessageChannel::All);
+        let ui_sub = ui.subscribe("test", NoChannels);
+
+        let mut manager = PlaylistManager::new(player.clone(), ui.clone());
+        manager.playlist_mode = PlaylistMode::RepeatOne;
+
+        ui_sub.broadcast(FrontendMessage::LoadLocations {
+            locations: vec![
+                "one.ogg".to_string(),
+                "two.ogg".to_string(),
+                "three.ogg".to_string(),
+            ],
+        });
+        manager.update();
+        assert_eq!(
+            PlayerMessage::CommandLoadAndPlayLocation(Location::path("one.ogg")),
+            player_sub.try_recv().unwrap()
+        );
+
+        let error = |location: &str| PlaybackError {
+            kind: PlaybackErrorKind::UnsupportedFormat,
+            location: location.into(),
+            message: "unsupported".into(),
+            retryable: false,
+        };
+
+        // Unplayable entries are skipped even when repeating one track
+        player_sub.broadcast(PlayerMessage::EventFailedToLoadLocation(error("one.ogg")));
+        manager.update();
+        assert_eq!(
+            PlayerMessage::CommandLoadAndPlayLocation(Location::path("two.ogg")),
+            player_sub.try_recv().unwrap()
+        );
+        manager.playlist_mode = PlaylistMode::Normal;
+        player_sub.broadcast(PlayerMessage::EventFinishedTrack);
+        manager.update();
+        assert_eq!(
+            PlayerMessage::CommandLoadAndPlayLocation(Location::path("three.ogg")),
+            player_sub.try_recv().unwrap()
+        );
+        assert_eq!(None, ui_sub.try_recv());
+
+        player_sub.broadcast(PlayerMessage::EventFailedToDecodeAudio(error("three.ogg")));
+        manager.update();
+        assert_eq!(None, player_sub.try_recv());
+        assert_eq!(None, manager.playlist.current_index);
+        assert_eq!(
+            Some(FrontendMessage::UnplayableEntriesSkipped {
+                errors: vec![error("one.ogg"), error("three.ogg")],
+            }),
+            ui_sub.try_recv()
+        );
+        assert_eq!(
+            Some(FrontendMessage::ShowAlert {
+                level: AlertLevel::Warn,
+                message: "2 files could not be played.".into(),
+            }),
+            ui_sub.try_recv()
+        );
+        assert_eq!(None, ui_sub.try_recv());
+    }
+
+    /// Writes a short, silent, 16-bit mono WAV file.
+    fn write_wav(path: &std::path::Path) {
+        let samples = [0u8; 2 * 4410];
+        let mut wav = Vec::new();
+        wav.extend_from_slice(b"RIFF");
+        wav.extend_from_slice(&(36 + samples.len() as u32).to_le_bytes());
+        wav.extend_from_slice(b"WAVEfmt ");
+        wav.extend_from_slice(&16u32.to_le_bytes());
+        wav.extend_from_slice(&1u16.to_le_bytes()); // PCM
+        wav.extend_from_slice(&1u16.to_le_bytes()); // mono
+        wav.extend_from_slice(&44100u32.to_le_bytes());
+        wav.extend_from_slice(&(44100u32 * 2).to_le_bytes());
+        wav.extend_from_slice(&2u16.to_le_bytes());
+        wav.extend_from_slice(&16u16.to_le_bytes());
+        wav.extend_from_slice(b"data");
+        wav.extend_from_slice(&(samples.len() as u32).to_le_bytes());
+        wav.extend_from_slice(&samples);
+        std::fs::write(path, wav).unwrap();
+    }
+
+    #[test]
+    fn preflight_probes_local_files() {
+        let dir = std::env::temp_dir().join(format!("millenium-preflight-{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let misnamed = dir.join("misnamed.dat");
+        let broken = dir.join("broken.mp3");
+        write_wav(&misnamed);
+        std::fs::write(&broken, b"this is not audio").unwrap();
+        let (misnamed, broken) = (
+            Location::path(misnamed.to_str().unwrap()),
+            Location::path(broken.to_str().unwrap()),
+        );
+
+        let (player, ui) = (Broadcaster::new(), Broadcaster::new());
+        let player_sub = player.subscribe("test", PlayerMessageChannel::All);
+        let ui_sub = ui.subscribe("test", NoChannels);
+        let mut manager = PlaylistManager::new(player.clone(), ui.clone());
+
+        ui_sub.broadcast(FrontendMessage::LoadLocations {
+            locations: vec![misnamed.to_string(), broken.to_string()],
+        });
+        manager.update();
+        // Nothing is probed until the background scan gets to it
+        assert_eq!(2, manager.playlist.entries.len());
+        assert_eq!(
+            PlayerMessage::CommandLoadAndPlayLocation(misnamed),
+            player_sub.try_recv().unwrap()
+        );
+
+        let started = Instant::now();
+        let alert = loop {
+            assert!(
+                started.elapsed() < Duration::from_secs(30),
+                "probe took too long"
+            );
+            manager.update();
+            match ui_sub.try_recv() {
+                Some(FrontendMessage::ShowAlert { level, message }) => break (level, message),
+                Some(_) => {}
+                None => std::thread::sleep(Duration::from_millis(10)),
+            }
+        };
+        manager.shutdown();
+        std::fs::remove_dir_all(&dir).unwrap();
+
+        assert_eq!(1, manager.playlist.entries.len());
+        assert_eq!(AlertLevel::Warn, alert.0);
+        assert!(
+            alert
+                .1
+                .starts_with(&format!("The following files can't be played:\n{broken}: ")),
+            "{}",
+            alert.1
+        );
+    }
+
+    #[test]
+    fn split_scanned_files_with_chapters() {
+        let (player, ui) = (Broadcaster::new(), Broadcaster::new());
+        let ui_sub = ui.subscribe("test", NoChannels);
+        let mut manager = PlaylistManager::new(player.clone(), ui.clone());
+        ui_sub.broadcast(FrontendMessage::LoadLocations {
+            locations: vec!["book.m4b".into(), "broken.ogg".into()],
+        });
+        manager.update();
+        let (book, broken) = (
+            manager.playlist.entries[0].id,
+            manager.playlist.entries[1].id,
+        );
+        assert_eq!(Some(book), manager.playlist.current_id);
+
+        let chapters = [0, 10, 20].map(|start| Chapter {
+            title: Some(format!("Chapter at {start}")),
+            start: Duration::from_secs(start),
+        });
+        let error = PlaybackError {
+            kind: PlaybackErrorKind::CorruptData,
+            location: "broken.ogg".into(),
+            message: "corrupt".into(),
+            retryable: false,
+        };
+        manager.replace_scanned_entries(&[(broken, error)], vec![(book, chapters.to_vec())]);
+
+        let entries = &manager.playlist.entries;
+        assert_eq!(3, entries.len());
+        assert!(entries
+            .iter()
+            .all(|entry| entry.location == Location::path("book.m4b")));
+        assert_eq!(
+            Some(Duration::from_secs(10)),
+            entries[1].range.map(|range| range.start)
+        );
+        // The chapter that's already playing becomes the current entry
+        assert_eq!(Some(entries[0].id), manager.playlist.current_id);
+        assert_eq!(Some(PlaylistIndex(0)), manager.playlist.current_index);
+    }
+
+    #[test]
+    fn load_playlist_files() {
+        let dir = std::env::temp_dir().join(format!("millenium-m3u-{}", std::process::id()));
+        std::fs::create_dir_all(dir.join("songs")).unwrap();
+        write_wav(&dir.join("songs").join("one.wav"));
+        std::fs::write(
+            dir.join("list.m3u8"),
+            "#EXTM3U\n#EXTINF:6,Artist - One\nsongs/one.wav\nnested.pls\n",
+        )
+        .unwrap();
+        std::fs::write(
+            dir.join("nested.pls"),
+            "[playlist]\nFile1=http://example.com/radio\nTitle1=Radio\nLength1=-1\n",
+        )
+        .unwrap();
+        let playlist = Location::path(dir.join("list.m3u8").to_str().unwrap());
+        let one = Location::path(dir.join("songs").join("one.wav").to_str().unwrap());
+
+        let (player, ui) = (Broadcaster::new(), Broadcaster::new());
+        let player_sub = player.subscribe("test", PlayerMessageChannel::All);
+        let ui_sub = ui.subscribe("test", NoChannels);
+        let mut manager = PlaylistManager::new(player.clone(), ui.clone());
+        ui_sub.broadcast(FrontendMessage::LoadLocations {
+            locations: vec![playlist.to_string()],
+        });
+        manager.update();
+        std::fs::remove_dir_all(&dir).unwrap();
+
+        let entries = &manager.playlist.entries;
+        assert_eq!(2, entries.len());
+        assert_eq!(one, entries[0].location);
+        assert_eq!(
+            Some(MinimalMetadata {
+                artist: Some("Artist".into()),
+                artists: vec!["Artist".into()],
+                album_artist: None,
+                album: None,
+                title: Some("One".into()),
+            }),
+            entries[0].metadata
+        );
+        assert_eq!(Some(Duration::from_secs(6)), entries[0].duration);
+        assert_eq!(
+            Location::from_str("http://example.com/radio").unwrap(),
+            entries[1].location
+        );
+        assert_eq!(None, entries[1].duration);
+        assert_eq!(
+            PlayerMessage::CommandLoadAndPlayLocation(one),
+            player_sub.try_recv().unwrap()
+        );
+        assert_eq!(None, ui_sub.try_recv());
+    }
+
+    #[test]
+    fn expand_directories_in_background() {
+        let dir = std::env::temp_dir().join(format!("millenium-expand-{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        write_wav(&dir.join("1.wav"));
+        write_wav(&dir.join("2.wav"));
+        let location = |name: &str| Location::path(dir.join(name).to_str().unwrap());
+
+        let (player, ui) = (Broadcaster::new(), Broadcaster::new());
+        let player_sub = player.subscribe("test", PlayerMessageChannel::All);
+        let ui_sub = ui.subscribe("test", NoChannels);
+        let mut manager = PlaylistManager::new(player.clone(), ui.clone());
+        ui_sub.broadcast(FrontendMessage::LoadLocations {
+            locations: vec![dir.to_str().unwrap().into()],
+        });
+        // Waits for the directory to be expanded, so that it's queued after it was loaded
+        ui_sub.broadcast(FrontendMessage::QueueLocations {
+            locations: vec!["queued.ogg".into()],
+            allow_duplicates: false,
+        });
+        manager.update();
+        assert!(manager.playlist.entries.is_empty());
+        assert!(manager.play_queue.is_empty());
+        assert_eq!(vec!["Listing the files in folders"], manager.busy_tasks());
+
+        let started = Instant::now();
+        while !manager.expansions.is_empty() {
+            assert!(
+                started.elapsed() < Duration::from_secs(30),
+                "expansion took too long"
+            );
+            std::thread::sleep(Duration::from_millis(10));
+            manager.update();
+        }
+        manager.shutdown();
+        std::fs::remove_dir_all(&dir).unwrap();
+
+        assert_eq!(
+            vec![location("1.wav"), location("2.wav")],
+            manager
+                .playlist
+                .entries
+                .iter()
+                .map(|entry| entry.location.clone())
+                .collect::<Vec<_>>()
+        );
+        assert_eq!(
+            PlayerMessage::CommandLoadAndPlayLocation(location("1.wav")),
+            player_sub.try_recv().unwrap()
+        );
+        assert_eq!(1, manager.play_queue.len());
+    }
+
+    #[test]
+    fn expand_directories_in_playlist_files() {
+        let dir = std::env::temp_dir().join(format!("millenium-m3u-dir-{}", std::process::id()));
+        std::fs::create_dir_all(dir.join("album")).unwrap();
+        write_wav(&dir.join("album").join("1.wav"));
+        write_wav(&dir.join("album").join("2.wav"));
+        write_wav(&dir.join("single.wav"));
+        std::fs::write(
+            dir.join("list.m3u8"),
+            "#EXTM3U
+#EXTINF:1,Artist - Single
+single.wav
+album
+",
+        )
+        .unwrap();
+        let location = |name: &str| Location::path(dir.join(name).to_str().unwrap());
+
+        let (player, ui) = (Broadcaster::new(), Broadcaster::new());
+        let ui_sub = ui.subscribe("test", NoChannels);
+        let mut manager = PlaylistManager::new(player.clone(), ui.clone());
+        ui_sub.broadcast(FrontendMessage::LoadLocations {
+            locations: vec![location("list.m3u8").to_string()],
+        });
+        manager.update();
+        // The directory is expanded in the background rather than while handling the message
+        assert!(manager.playlist.entries.is_empty());
+        assert_eq!(vec!["Listing the files in folders"], manager.busy_tasks());
+
+        let started = Instant::now();
+        while !manager.expansions.is_empty() {
+            assert!(
+                started.elapsed() < Duration::from_secs(30),
+                "expansion took too long"
+            );
+            std::thread::sleep(Duration::from_millis(10));
+            manager.update();
+        }
+        manager.shutdown();
+        std::fs::remove_dir_all(&dir).unwrap();
+
+        let entries = &manager.playlist.entries;
+        assert_eq!(
+            vec![
+                location("single.wav"),
+                location("album/1.wav"),
+                location("album/2.wav")
+            ],
+            entries
+                .iter()
+                .map(|entry| entry.location.clone())
+                .collect::<Vec<_>>()
+        );
+        // What the playlist file said about its entries is kept through the expansion
+        assert_eq!(
+            Some("Single"),
+            entries[0]
+                .metadata
+                .as_ref()
+                .and_then(|metadata| metadata.title.as_deref())
+        );
+    }
+
+    #[test]
+    fn load_directory_from_position_paused() {
+        let dir = std::env::temp_dir().join(format!("millenium-start-{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        write_wav(&dir.join("1.wav"));
+        write_wav(&dir.join("2.wav"));
+        let first = Location::path(dir.join("1.wav").to_str().unwrap());
+
+        let (player, ui) = (Broadcaster::new(), Broadcaster::new());
+        let player_sub = player.subscribe("test", PlayerMessageChannel::All);
+        let mut manager = PlaylistManager::new(player.clone(), ui.clone());
+        manager.load_locations_with(
+            vec![Location::path(dir.to_str().unwrap())],
+            TrackStart {
+                position: Some(Duration::from_millis(50)),
+                paused: true,
+            },
+        );
+        manager.update();
+        assert_eq!(None, player_sub.try_recv());
+
+        let started = Instant::now();
+        while !manager.expansions.is_empty() {
+            assert!(
+                started.elapsed() < Duration::from_secs(30),
+                "expansion took too long"
+            );
+            std::thread::sleep(Duration::from_millis(10));
+            manager.update();
+        }
+        manager.shutdown();
+        std::fs::remove_dir_all(&dir).unwrap();
+
+        // The start applies to the first track once the directory is expanded
+        assert_eq!(
+            PlayerMessage::CommandLoadAndPlayLocationFrom(first, Duration::from_millis(50)),
+            player_sub.try_recv().unwrap()
+        );
+        assert_eq!(PlayerMessage::CommandPause, player_sub.try_recv().unwrap());
+    }
+
+    #[test]
+    fn list_entries_for_saving() {
+        let (player, ui) = (Broadcaster::new(), Broadcaster::new());
+        let mut manager = PlaylistManager::new(player.clone(), ui.clone());
+        let chapters = [0, 10].map(|start| Chapter {
+            title: Some(format!("Chapter at {start}")),
+            start: Duration::from_secs(start),
+        });
+        let book = Location::path("book.flac");
+        let mut entries = Vec::new();
+        for (range, title) in TrackRange::from_chapters(&chapters) {
+            let metadata = title.map(MinimalMetadata::from_title);
+            entries.push(manager.new_entry(book.clone(), metadata, Some(range)));
+        }
+        let url = |path: &str| Location::from_str(&format!("http://example.com/{path}")).unwrap();
+        let metadata = MinimalMetadata::from_title("Station".into());
+        let mut station = manager.new_entry(url("low"), Some(metadata), None);
+        station.variants = vec![
+            Variant::new(url("high"), None),
+            Variant::new(url("low"), None),
+        ];
+        entries.push(station);
+        manager.playlist.entries = entries;
+
+        let listed_station = |location| ListedEntry {
+            location,
+            artist: None,
+            title: Some("Station".into()),
+            duration: None,
+            variants: Vec::new(),
+        };
+        assert_eq!(
+            vec![
+                ListedEntry::new(book),
+                listed_station(url("high")),
+                listed_station(url("low")),
+            ],
+            manager.playlist().listed_entries()
+        );
+    }
+
+    #[test]
+    fn song_history_per_station() {
+        let (player, ui) = (Broadcaster::new(), Broadcaster::new());
+        let player_sub = player.subscribe("test", PlayerMessageChannel::All);
+        let ui_sub = ui.subscribe("test", NoChannels);
+        let mut manager = PlaylistManager::new(player.clone(), ui.clone());
+        let url = |path: &str| Location::from_str(&format!("http://example.com/{path}")).unwrap();
+        manager.playlist.entries = vec![
+            manager.new_entry(url("one"), None, None),
+            manager.new_entry(url("two"), None, None),
+        ];
+        let titles = |message: Option<FrontendMessage>| match message {
+            Some(FrontendMessage::SongHistoryChanged { songs }) => {
+                songs.into_iter().map(|song| song.title).collect::<Vec<_>>()
+            }
+            message => panic!("expected song history, got {message:?}"),
+        };
+
+        manager.start_track(PlaylistIndex(0));
+        player_sub.broadcast(PlayerMessage::EventStreamTitleChanged("A".into()));
+        player_sub.broadcast(PlayerMessage::EventStreamTitleChanged("B".into()));
+        manager.update();
+        assert_eq!(vec!["A"], titles(ui_sub.try_recv()));
+        assert_eq!(vec!["A", "B"], titles(ui_sub.try_recv()));
+
+        // The other station hasn't played anything yet
+        manager.start_track(PlaylistIndex(1));
+        assert_eq!(Vec::<String>::new(), titles(ui_sub.try_recv()));
+        manager.start_track(PlaylistIndex(0));
+        assert_eq!(vec!["A", "B"], titles(ui_sub.try_recv()));
+        assert_eq!(None, ui_sub.try_recv());
+    }
+
+    #[test]
+    fn stream_quality_selection() {
+        let (player, ui) = (Broadcaster::new(), Broadcaster::new());
+        let player_sub = player.subscribe("test", PlayerMessageChannel::All);
+        let ui_sub = ui.subscribe("test", NoChannels);
+        let mut manager = PlaylistManager::new(player.clone(), ui.clone());
+
+        let url = |path: &str| Location::from_str(&format!("http://example.com/{path}")).unwrap();
+        let mut entry = manager.new_entry(url("high"), None, None);
+        entry.variants = vec![
+            Variant::new(url("high"), Some(256_000)),
+            Variant::new(url("low"), Some(64_000)),
+        ];
+        manager.playlist.entries = vec![entry];
+        let quality = |selected, active| FrontendMessage::StreamQualityChanged {
+            quality: Some(StreamQuality {
+                variants: quality::describe(&manager.playlist.entries[0].variants),
+                selected,
+                active,
+            }),
+        };
+        let (auto_high, auto_low, manual_high) =
+            (quality(None, 0), quality(None, 1), quality(Some(0), 0));
+
+        manager.start_track(PlaylistIndex(0));
+        assert_eq!(
+            PlayerMessage::CommandLoadAndPlayLocation(url("high")),
+            player_sub.try_recv().unwrap()
+        );
+        assert_eq!(Some(auto_high), ui_sub.try_recv());
+
+        // The network can't keep up, so it drops down to the lower quality
+        player_sub.broadcast(PlayerMessage::EventStreamThroughputMeasured(100_000));
+        manager.update();
+        assert_eq!(
+            PlayerMessage::CommandLoadAndPlayLocation(url("low")),
+            player_sub.try_recv().unwrap()
+        );
+        assert_eq!(Some(auto_low.clone()), ui_sub.try_recv());
+
+        // Picking a quality overrides the automatic choice
+        ui_sub.broadcast(FrontendMessage::SelectStreamVariant { variant: Some(0) });
+        manager.update();
+        assert_eq!(
+            PlayerMessage::CommandLoadAndPlayLocation(url("high")),
+            player_sub.try_recv().unwrap()
+        );
+        assert_eq!(Some(manual_high), ui_sub.try_recv());
+        player_sub.broadcast(PlayerMessage::EventStreamThroughputMeasured(100_000));
+        manager.update();
+        assert_eq!(None, player_sub.try_recv());
+
+        ui_sub.broadcast(FrontendMessage::SelectStreamVariant { variant: None });
+        manager.update();
+        assert_eq!(
+            PlayerMessage::CommandLoadAndPlayLocation(url("low")),
+            player_sub.try_recv().unwrap()
+        );
+        assert_eq!(Some(auto_low), ui_sub.try_recv());
+    }
+
+    #[test]
+    fn play_queued_tracks_next() {
+        let (player, ui) = (Broadcaster::new(), Broadcaster::new());
+        let player_sub = player.subscribe("test", PlayerMessageChannel::All);
+        let ui_sub = ui.subscribe("test", NoChannels);
+        let mut manager = PlaylistManager::new(player.clone(), ui.clone());
+        let locations = |entries: &[PlaylistEntry]| {
+            entries
+                .iter()
+                .map(|entry| entry.location.to_string())
+                .collect::<Vec<_>>()
+        };
+        let queued = |names: &[&str]| FrontendMessage::PlayQueueChanged {
+            queue: names
+                .iter()
+                .map(|name| QueuedTrack {
+                    location: name.to_string(),
+                    title: None,
+                    artist: None,
+                })
+                .collect(),
+        };
+
+        ui_sub.broadcast(FrontendMessage::LoadLocations {
+            locations: vec!["one.ogg".into(), "two.ogg".into()],
+        });
+        manager.update();
+        assert_eq!(
+            PlayerMessage::CommandLoadAndPlayLocation(Location::path("one.ogg")),
+            player_sub.try_recv().unwrap()
+        );
+
+        ui_sub.broadcast(FrontendMessage::QueueLocations {
+            locations: vec!["a.ogg".into(), "b.ogg".into()],
+            allow_duplicates: false,
+        });
+        manager.update();
+        assert_eq!(Some(queued(&["a.ogg", "b.ogg"])), ui_sub.try_recv());
+        assert_eq!(None, player_sub.try_recv());
+
+        player_sub.broadcast(PlayerMessage::EventFinishedTrack);
+        manager.update();
+        assert_eq!(
+            PlayerMessage::CommandLoadAndPlayLocation(Location::path("a.ogg")),
+            player_sub.try_recv().unwrap()
+        );
+        assert_eq!(Some(queued(&["b.ogg"])), ui_sub.try_recv());
+        assert_eq!(
+            vec!["one.ogg", "a.ogg", "two.ogg"],
+            locations(&manager.playlist.entries)
+        );
+
+        player_sub.broadcast(PlayerMessage::EventFinishedTrack);
+        manager.update();
+        assert_eq!(
+            PlayerMessage::CommandLoadAndPlayLocation(Location::path("b.ogg")),
+            player_sub.try_recv().unwrap()
+        );
+        assert_eq!(Some(queued(&[])), ui_sub.try_recv());
+        assert_eq!(
+            vec!["one.ogg", "b.ogg", "two.ogg"],
+            locations(&manager.playlist.entries)
+        );
+
+        // The playlist carries on after the current entry, without the queued ones
+        player_sub.broadcast(PlayerMessage::EventFinishedTrack);
+        manager.update();
+        assert_eq!(
+            PlayerMessage::CommandLoadAndPlayLocation(Location::path("two.ogg")),
+            player_sub.try_recv().unwrap()
+        );
+        assert_eq!(
+            vec!["one.ogg", "two.ogg"],
+            locations(&manager.playlist.entries)
+        );
+        assert_eq!(Some(PlaylistIndex(1)), manager.playlist.current_index);
+
+        // Queued locations play right away when nothing is playing
+        player_sub.broadcast(PlayerMessage::EventFinishedTrack);
+        manager.update();
+        assert_eq!(None, manager.playlist.current());
+        ui_sub.broadcast(FrontendMessage::QueueLocations {
+            locations: vec!["a.ogg".into()],
+            allow_duplicates: false,
+        });
+        manager.update();
+        assert_eq!(
+            PlayerMessage::CommandLoadAndPlayLocation(Location::path("a.ogg")),
+            player_sub.try_recv().unwrap()
+        );
+        assert_eq!(Some(queued(&[])), ui_sub.try_recv());
+        assert_eq!(None, ui_sub.try_recv());
+    }
+
+    #[test]
+    fn queue_skips_duplicates() {
+        let (player, ui) = (Broadcaster::new(), Broadcaster::new());
+        let player_sub = player.subscribe("test", PlayerMessageChannel::All);
+        let ui_sub = ui.subscribe("test", NoChannels);
+        let mut manager = PlaylistManager::new(player.clone(), ui.clone());
+        let tracks = |names: &[&str]| -> Vec<QueuedTrack> {
+            names
+                .iter()
+                .map(|name| QueuedTrack {
+                    location: name.to_string(),
+                    title: None,
+                    artist: None,
+                })
+                .collect()
+        };
+
+        ui_sub.broadcast(FrontendMessage::LoadLocations {
+            locations: vec!["one.ogg".into(), "two.ogg".into()],
+        });
+        manager.update();
+        player_sub.try_recv().unwrap();
+
+        ui_sub.broadcast(FrontendMessage::QueueLocations {
+            locations: vec!["two.ogg".into(), "a.ogg".into()],
+            allow_duplicates: false,
+        });
+        manager.update();
+        assert_eq!(
+            Some(FrontendMessage::DuplicatesSkipped {
+                duplicates: tracks(&["two.ogg"])
+            }),
+            ui_sub.try_recv()
+        );
+        assert_eq!(
+            Some(FrontendMessage::PlayQueueChanged {
+                queue: tracks(&["a.ogg"])
+            }),
+            ui_sub.try_recv()
+        );
+
+        // Tracks already in the play queue are duplicates too
+        ui_sub.broadcast(FrontendMessage::QueueLocations {
+            locations: vec!["a.ogg".into()],
+            allow_duplicates: false,
+        });
+        manager.update();
+        assert_eq!(
+            Some(FrontendMessage::DuplicatesSkipped {
+                duplicates: tracks(&["a.ogg"])
+            }),
+            ui_sub.try_recv()
+        );
+        assert_eq!(None, ui_sub.try_recv());
+
+        // Adding anyway
+        ui_sub.broadcast(FrontendMessage::QueueLocations {
+            locations: vec!["two.ogg".into()],
+            allow_duplicates: true,
+        });
+        manager.update();
+        assert_eq!(
+            Some(FrontendMessage::PlayQueueChanged {
+                queue: tracks(&["a.ogg", "two.ogg"])
+            }),
+            ui_sub.try_recv()
+        );
+    }
+
+    #[test]
+    fn play_test_signal_then_carry_on() {
+        let (player, ui) = (Broadcaster::new(), Broadcaster::new());
+        let player_sub = player.subscribe("test", PlayerMessageChannel::All);
+        let ui_sub = ui.subscribe("test", NoChannels);
+        let mut manager = PlaylistManager::new(player.clone(), ui.clone());
+
+        ui_sub.broadcast(FrontendMessage::LoadLocations {
+            locations: vec!["one.ogg".into(), "two.ogg".into()],
+        });
+        manager.update();
+        player_sub.try_recv().unwrap();
+
+        ui_sub.broadcast(FrontendMessage::PlayTestSignal {
+            signal: TestSignal::ChannelCheck,
+        });
+        manager.update();
+        let Some(PlayerMessage::CommandLoadAndPlayLocation(location)) = player_sub.try_recv()
+        else {
+            panic!("expected the test signal to play");
+        };
+        assert!(location.as_path().unwrap().is_file());
+        let (_, index) = manager.playlist.current().unwrap();
+        assert_eq!(
+            Some("Test signal: Channel check"),
+            manager.playlist.entries[index.0]
+                .metadata
+                .as_ref()
+                .and_then(|metadata| metadata.title.as_deref())
+        );
+
+        player_sub.broadcast(PlayerMessage::EventFinishedTrack);
+        manager.update();
+        assert_eq!(
+            PlayerMessage::CommandLoadAndPlayLocation(Location::path("two.ogg")),
+            player_sub.try_recv().unwrap()
+        );
+        assert_eq!(2, manager.playlist.entries.len());
+        assert_eq!(None, ui_sub.try_recv());
+    }
 
     #[test]
-    fn no_entries_after_filtering() {
+    fn party_mode_only_allows_queueing() {
         let (player, ui) = (Broadcaster::new(), Broadcaster::new());
         let player_sub = player.subscribe("test", PlayerMessageChannel::All);
         let ui_sub = ui.subscribe("test", NoChannels);
-
         let mut manager = PlaylistManager::new(player.clone(), ui.clone());
 
         ui_sub.broadcast(FrontendMessage::LoadLocations {
-            locations: vec![
-                "not_an_audio_file1".to_string(),
-                "not_an_audio_file2".to_string(),
-            ],
+            locations: vec!["one.ogg".into(), "two.ogg".into()],
         });
         manager.update();
-        pretty_assertions::assert_eq!(Vec::<PlaylistEntry>::new(), manager.playlist.entries);
-        assert_eq!(None, manager.playlist.current_id);
-        assert_eq!(None, manager.playlist.current_index);
-        assert_eq!(None, player_sub.try_recv());
+        player_sub.try_recv().unwrap();
+
+        ui_sub.broadcast(FrontendMessage::StartPartyMode {
+            passphrase: Some("disco".into()),
+        });
+        manager.update();
+        assert!(manager.party_mode_active());
         assert_eq!(
+            Some(FrontendMessage::PartyModeChanged { active: true }),
+            ui_sub.try_recv()
+        );
+
+        // Opening files queues them instead of replacing the playlist
+        ui_sub.broadcast(FrontendMessage::LoadLocations {
+            locations: vec!["guest.ogg".into()],
+        });
+        ui_sub.broadcast(FrontendMessage::SortPlaylist {
+            by: PlaylistSortKey::Title,
+        });
+        ui_sub.broadcast(FrontendMessage::ClearPlayQueue);
+        manager.update();
+        assert!(matches!(
+            ui_sub.try_recv(),
+            Some(FrontendMessage::PlayQueueChanged { queue }) if queue.len() == 1
+        ));
+        assert_eq!(None, ui_sub.try_recv());
+        assert_eq!(None, player_sub.try_recv());
+        assert_eq!(1, manager.play_queue.len());
+
+        ui_sub.broadcast(FrontendMessage::StopPartyMode { passphrase: None });
+        manager.update();
+        assert!(manager.party_mode_active());
+        assert!(matches!(
+            ui_sub.try_recv(),
             Some(FrontendMessage::ShowAlert {
-                level: AlertLevel::Info,
-                message: "None of the given files are audio or playlist files.".into(),
-            }),
+                level: AlertLevel::Warn,
+                ..
+            })
+        ));
+
+        ui_sub.broadcast(FrontendMessage::StopPartyMode {
+            passphrase: Some("disco".into()),
+        });
+        manager.update();
+        assert!(!manager.party_mode_active());
+        assert_eq!(
+            Some(FrontendMessage::PartyModeChanged { active: false }),
             ui_sub.try_recv()
         );
     }
 
     #[test]
-    fn normal_mode_play_all_songs_sequentially() {
+    fn search_playlist() {
+        let (player, ui) = (Broadcaster::new(), Broadcaster::new());
+        let ui_sub = ui.subscribe("test", NoChannels);
+        let mut manager = PlaylistManager::new(player.clone(), ui.clone());
+
+        let tagged = "../test-data/hydrate/hydrate.mp3";
+        ui_sub.broadcast(FrontendMessage::LoadLocations {
+            locations: vec![
+                "one.ogg".into(),
+                tagged.into(),
+                "two.ogg".into(),
+                "one.ogg".into(),
+            ],
+        });
+        manager.update();
+
+        let playlist = manager.playlist();
+        let locations = |tracks: Vec<QueuedTrack>| {
+            tracks
+                .into_iter()
+                .map(|track| track.location)
+                .collect::<Vec<_>>()
+        };
+        assert_eq!(vec!["one.ogg"], locations(playlist.search("ONE", 10)));
+        assert_eq!(vec![tagged], locations(playlist.search("hydrate mp3", 10)));
+        assert_eq!(vec!["one.ogg", tagged], locations(playlist.search("", 2)));
+        assert!(playlist.search("three", 10).is_empty());
+        assert!(playlist.contains_location("two.ogg"));
+        assert!(!playlist.contains_location("three.ogg"));
+    }
+
+    #[test]
+    fn seeded_shuffle() {
         let (player, ui) = (Broadcaster::new(), Broadcaster::new());
         let player_sub = player.subscribe("test", PlayerMessageChannel::All);
         let ui_sub = ui.subscribe("test", NoChannels);
+        let locations: Vec<String> = (0..8).map(|n| format!("{n}.ogg")).collect();
 
-        let mut manager = PlaylistManager::new(player.clone(), ui.clone());
+        let play_through = |seed| {
+            let mut manager = PlaylistManager::new(player.clone(), ui.clone());
+            manager.set_shuffle_seed(Some(seed));
+            ui_sub.broadcast(FrontendMessage::MediaControlPlaylistMode {
+                mode: PlaylistMode::Shuffle,
+            });
+            ui_sub.broadcast(FrontendMessage::LoadLocations {
+                locations: locations.clone(),
+            });
+            manager.update();
+            for _ in 0..2 {
+                assert_eq!(
+                    Some(FrontendMessage::PlaylistModeChanged {
+                        mode: PlaylistMode::Shuffle,
+                        shuffle_seed: Some(seed),
+                    }),
+                    ui_sub.try_recv()
+                );
+            }
+            let mut played = Vec::new();
+            while let Some(message) = player_sub.try_recv() {
+                if let PlayerMessage::CommandLoadAndPlayLocation(location) = message {
+                    played.push(location.to_string());
+                    player_sub.broadcast(PlayerMessage::EventFinishedTrack);
+                    manager.update();
+                }
+            }
+            played
+        };
+
+        let played = play_through(5);
+        assert_eq!(play_through(5), played);
+        assert_ne!(play_through(6), played);
+        assert_ne!(locations, played);
+        let mut sorted = played.clone();
+        sorted.sort();
+        assert_eq!(locations, sorted);
+    }
+
+    #[test]
+    fn banned_tracks_are_not_shuffled() {
+        let (player, ui) = (Broadcaster::new(), Broadcaster::new());
+        let player_sub = player.subscribe("test", PlayerMessageChannel::All);
+        let ui_sub = ui.subscribe("test", NoChannels);
+        let locations: Vec<String> = (0..8).map(|n| format!("{n}.ogg")).collect();
 
+        let mut ratings = TrackRatings::in_memory();
+        for banned in ["2.ogg", "5.ogg"] {
+            ratings.set(
+                &Location::path(banned),
+                Duration::ZERO,
+                Some(TrackRating::Banned),
+            );
+        }
+        let mut manager = PlaylistManager::new(player.clone(), ui.clone());
+        manager.set_track_ratings(ratings);
+        manager.set_shuffle_seed(Some(3));
+        ui_sub.broadcast(FrontendMessage::MediaControlPlaylistMode {
+            mode: PlaylistMode::Shuffle,
+        });
         ui_sub.broadcast(FrontendMessage::LoadLocations {
-            locations: vec!["one.ogg".to_string(), "two.ogg".to_string()],
+            locations: locations.clone(),
         });
         manager.update();
-        pretty_assertions::assert_eq!(
-            vec![
-                PlaylistEntry {
-                    id: PlaylistEntryId(1),
-                    location: Location::path("one.ogg"),
-                    metadata: None,
-                    duration: None,
-                },
-                PlaylistEntry {
-                    id: PlaylistEntryId(2),
-                    location: Location::path("two.ogg"),
-                    metadata: None,
-                    duration: None,
-                },
-            ],
-            manager.playlist.entries
-        );
-        assert_eq!(Some(PlaylistEntryId(1)), manager.playlist.current_id);
-        assert_eq!(Some(PlaylistIndex(0)), manager.playlist.current_index);
+
+        let mut played = Vec::new();
+        while let Some(message) = player_sub.try_recv() {
+            if let PlayerMessage::CommandLoadAndPlayLocation(location) = message {
+                played.push(location.to_string());
+                player_sub.broadcast(PlayerMessage::EventFinishedTrack);
+                manager.update();
+            }
+        }
+        played.sort();
         assert_eq!(
-            PlayerMessage::CommandLoadAndPlayLocation(Location::path("one.ogg")),
-            player_sub.try_recv().unwrap(),
+            vec!["0.ogg", "1.ogg", "3.ogg", "4.ogg", "6.ogg", "7.ogg"],
+            played
         );
+    }
 
-        player_sub.broadcast(PlayerMessage::EventFinishedTrack);
+    #[test]
+    fn unsaved_edits_and_shutdown() {
+        let (player, ui) = (Broadcaster::new(), Broadcaster::new());
+        let ui_sub = ui.subscribe("test", NoChannels);
+        let mut manager = PlaylistManager::new(player.clone(), ui.clone());
+        let path = Utf8PathBuf::from_path_buf(
+            std::env::temp_dir().join(format!("unsaved-edits-{}.m3u8", std::process::id())),
+        )
+        .unwrap();
+
+        ui_sub.broadcast(FrontendMessage::LoadLocations {
+            locations: vec!["one.ogg".into(), "two.ogg".into(), "three.ogg".into()],
+        });
         manager.update();
-        assert_eq!(Some(PlaylistEntryId(2)), manager.playlist.current_id);
-        assert_eq!(Some(PlaylistIndex(1)), manager.playlist.current_index);
+        assert!(!manager.has_unsaved_edits());
+        ui_sub.broadcast(FrontendMessage::MovePlaylistEntry { from: 0, to: 2 });
+        manager.update();
+        assert!(manager.has_unsaved_edits());
+
+        manager.save_playlist(&path, PlaylistFormat::M3u8).unwrap();
+        let _ = std::fs::remove_file(&path);
+        assert!(!manager.has_unsaved_edits());
+
+        let id = manager.playlist().entries[0].id.0;
+        ui_sub.broadcast(FrontendMessage::RemovePlaylistEntries { ids: vec![id] });
+        manager.update();
+        assert!(manager.has_unsaved_edits());
+
+        manager.shutdown();
+        assert!(manager.busy_tasks().is_empty());
+    }
+
+    #[test]
+    fn rate_current_track() {
+        let (player, ui) = (Broadcaster::new(), Broadcaster::new());
+        let ui_sub = ui.subscribe("test", NoChannels);
+        let mut manager = PlaylistManager::new(player.clone(), ui.clone());
+        manager.playlist.entries = vec![
+            manager.new_entry(Location::path("one.ogg"), None, None),
+            manager.new_entry(Location::path("two.ogg"), None, None),
+        ];
+        let rating_changed = |rating| Some(FrontendMessage::TrackRatingChanged { rating });
+
+        manager.start_track(PlaylistIndex(0));
+        ui_sub.broadcast(FrontendMessage::RateCurrentTrack {
+            rating: Some(TrackRating::Loved),
+        });
+        manager.update();
+        assert_eq!(rating_changed(Some(TrackRating::Loved)), ui_sub.try_recv());
         assert_eq!(
-            PlayerMessage::CommandLoadAndPlayLocation(Location::path("two.ogg")),
-            player_sub.try_recv().unwrap(),
+            Some(TrackRating::Loved),
+            manager
+                .ratings()
+                .get(&Location::path("one.ogg"), Duration::ZERO)
         );
 
-        player_sub.broadcast(PlayerMessage::EventFinishedTrack);
-        manager.update();
-        assert_eq!(None, manager.playlist.current_id);
-        assert_eq!(None, manager.playlist.current_index);
-        assert_eq!(None, player_sub.try_recv());
+        manager.start_track(PlaylistIndex(1));
+        assert_eq!(rating_changed(None), ui_sub.try_recv());
+        manager.start_track(PlaylistIndex(0));
+        assert_eq!(rating_changed(Some(TrackRating::Loved)), ui_sub.try_recv());
 
+        ui_sub.broadcast(FrontendMessage::RateCurrentTrack { rating: None });
+        manager.update();
+        assert_eq!(rating_changed(None), ui_sub.try_recv());
         assert_eq!(None, ui_sub.try_recv());
     }
 
     #[test]
-    fn normal_mode_skip_forward_to_end() {
+    fn shuffle_albums_keeps_track_order() {
         let (player, ui) = (Broadcaster::new(), Broadcaster::new());
         let player_sub = player.subscribe("test", PlayerMessageChannel::All);
         let ui_sub = ui.subscribe("test", NoChannels);
-
         let mut manager = PlaylistManager::new(player.clone(), ui.clone());
-
+        let locations: Vec<String> = ["a", "b", "c", "d"]
+            .into_iter()
+            .flat_map(|album| (1..=3).map(move |track| format!("{album}/{track}.ogg")))
+            .collect();
         ui_sub.broadcast(FrontendMessage::LoadLocations {
-            locations: vec!["one.ogg".to_string(), "two.ogg".to_string()],
+            locations: locations.clone(),
+        });
+        manager.update();
+        player_sub.try_recv().unwrap();
+
+        // The album that's playing carries on, then the others follow in a shuffled order
+        manager.set_shuffle_seed(Some(1));
+        ui_sub.broadcast(FrontendMessage::MediaControlPlaylistMode {
+            mode: PlaylistMode::ShuffleAlbums,
         });
         manager.update();
-        assert_eq!(2, manager.playlist.entries.len());
-        assert_eq!(Some(PlaylistEntryId(1)), manager.playlist.current_id);
-        assert_eq!(Some(PlaylistIndex(0)), manager.playlist.current_index);
         assert_eq!(
-            PlayerMessage::CommandLoadAndPlayLocation(Location::path("one.ogg")),
-            player_sub.try_recv().unwrap(),
+            Some(FrontendMessage::PlaylistModeChanged {
+                mode: PlaylistMode::ShuffleAlbums,
+                shuffle_seed: Some(1),
+            }),
+            ui_sub.try_recv()
         );
+        let mut played = vec![locations[0].clone()];
+        loop {
+            player_sub.broadcast(PlayerMessage::EventFinishedTrack);
+            manager.update();
+            match player_sub.try_recv() {
+                Some(PlayerMessage::CommandLoadAndPlayLocation(location)) => {
+                    played.push(location.to_string())
+                }
+                _ => break,
+            }
+        }
+        assert_eq!(12, played.len());
+        assert_eq!(locations[0..3], played[0..3]);
+        assert_ne!(locations, played);
+        for album in played.chunks(3) {
+            let folder = album[0].split('/').next().unwrap();
+            let expected: Vec<_> = (1..=3)
+                .map(|track| format!("{folder}/{track}.ogg"))
+                .collect();
+            assert_eq!(expected, album);
+        }
+    }
 
-        ui_sub.broadcast(FrontendMessage::MediaControlSkipForward);
+    #[test]
+    fn sort_playlist_keeps_current_entry() {
+        let (player, ui) = (Broadcaster::new(), Broadcaster::new());
+        let player_sub = player.subscribe("test", PlayerMessageChannel::All);
+        let ui_sub = ui.subscribe("test", NoChannels);
+
+        let untagged = "../test-data/melodic_a_minor/melodic_a_minor_1chan_44100hz_6s.ogg";
+        let tagged = "../test-data/hydrate/hydrate.mp3";
+        let mut manager = PlaylistManager::new(player.clone(), ui.clone());
+        ui_sub.broadcast(FrontendMessage::LoadLocations {
+            locations: vec![untagged.into(), tagged.into()],
+        });
         manager.update();
-        assert_eq!(2, manager.playlist.entries.len());
-        assert_eq!(Some(PlaylistEntryId(2)), manager.playlist.current_id);
-        assert_eq!(Some(PlaylistIndex(1)), manager.playlist.current_index);
         assert_eq!(
-            PlayerMessage::CommandLoadAndPlayLocation(Location::path("two.ogg")),
+            PlayerMessage::CommandLoadAndPlayLocation(Location::path(untagged)),
             player_sub.try_recv().unwrap(),
         );
 
-        ui_sub.broadcast(FrontendMessage::MediaControlSkipForward);
+        // Entries without tags are sorted after the ones with them
+        ui_sub.broadcast(FrontendMessage::SortPlaylist {
+            by: PlaylistSortKey::Title,
+        });
         manager.update();
-        assert_eq!(2, manager.playlist.entries.len());
-        assert_eq!(None, manager.playlist.current_id);
-        assert_eq!(None, manager.playlist.current_index);
-        assert_eq!(PlayerMessage::CommandStop, player_sub.try_recv().unwrap(),);
-
+        let locations: Vec<_> = manager
+            .playlist
+            .entries
+            .iter()
+            .map(|entry| entry.location.clone())
+            .collect();
+        assert_eq!(
+            vec![Location::path(tagged), Location::path(untagged)],
+            locations
+        );
+        assert_eq!(
+            Some(MinimalMetadata {
+                artist: Some("kenny beltrey".into()),
+                artists: vec!["kenny beltrey".into()],
+                album_artist: None,
+                album: None,
+                title: Some("hydrate (the beach)".into()),
+            }),
+            manager.playlist.entries[0].metadata
+        );
+        assert_eq!(Some(PlaylistIndex(1)), manager.playlist.current_index);
         assert_eq!(None, player_sub.try_recv());
-        assert_eq!(None, ui_sub.try_recv());
     }
 
     #[test]
-    fn normal_mode_skip_back() {
+    fn play_chapters_as_virtual_tracks() {
         let (player, ui) = (Broadcaster::new(), Broadcaster::new());
         let player_sub = player.subscribe("test", PlayerMessageChannel::All);
-        let ui_sub = ui.subscribe("test", NoChannels);
 
         let mut manager = PlaylistManager::new(player.clone(), ui.clone());
-
-        ui_sub.broadcast(FrontendMessage::LoadLocations {
-            locations: vec!["one.ogg".to_string(), "two.ogg".to_string()],
+        let chapters = [0, 10, 20].map(|start| Chapter {
+            title: Some(format!("Chapter at {start}")),
+            start: Duration::from_secs(start),
         });
-        manager.update();
-        assert_eq!(2, manager.playlist.entries.len());
-        assert_eq!(Some(PlaylistEntryId(1)), manager.playlist.current_id);
-        assert_eq!(Some(PlaylistIndex(0)), manager.playlist.current_index);
+        let book = Location::path("book.flac");
+        let mut entries = Vec::new();
+        for (range, title) in TrackRange::from_chapters(&chapters) {
+            let metadata = title.map(MinimalMetadata::from_title);
+            entries.push(manager.new_entry(book.clone(), metadata, Some(range)));
+        }
+        entries.push(manager.new_entry(Location::path("two.ogg"), None, None));
+        manager.playlist.entries = entries;
         assert_eq!(
-            PlayerMessage::CommandLoadAndPlayLocation(Location::path("one.ogg")),
+            Some(Duration::from_secs(10)),
+            manager.playlist.entries[1].duration
+        );
+        assert_eq!(None, manager.playlist.entries[2].duration);
+
+        let status = |seconds| {
+            PlayerMessage::UpdatePlaybackStatus(PlaybackStatus {
+                playing: true,
+                current_position: Duration::from_secs(seconds),
+                end_position: Some(Duration::from_secs(30)),
+                volume: Default::default(),
+                live: None,
+            })
+        };
+
+        // Later chapters start part way into the file
+        manager.start_track(PlaylistIndex(1));
+        assert_eq!(
+            PlayerMessage::CommandLoadAndPlayLocationFrom(book.clone(), Duration::from_secs(10)),
             player_sub.try_recv().unwrap(),
         );
 
-        player_sub.broadcast(PlayerMessage::UpdatePlaybackStatus(PlaybackStatus {
-            playing: true,
-            current_position: Duration::from_secs(7),
-            end_position: Some(Duration::from_secs(60)),
-            volume: Default::default(),
-        }));
+        // Stale status from before the chapter started is ignored
+        player_sub.broadcast(status(25));
         manager.update();
+        assert_eq!(Some(PlaylistIndex(1)), manager.playlist.current_index);
 
-        // Since we're 7 seconds into the song, skipping back should restart the song
-        ui_sub.broadcast(FrontendMessage::MediaControlSkipBack);
+        // Reaching the end of a chapter moves on to the next one without reloading
+        player_sub.broadcast(PlayerMessage::EventStartedTrack);
+        player_sub.broadcast(status(19));
+        player_sub.broadcast(status(20));
         manager.update();
-        assert_eq!(2, manager.playlist.entries.len());
-        assert_eq!(Some(PlaylistEntryId(1)), manager.playlist.current_id);
-        assert_eq!(Some(PlaylistIndex(0)), manager.playlist.current_index);
+        assert_eq!(Some(PlaylistIndex(2)), manager.playlist.current_index);
+
+        // The last chapter plays to the end of the file, so the next file can follow gaplessly
         assert_eq!(
-            PlayerMessage::CommandLoadAndPlayLocation(Location::path("one.ogg")),
+            PlayerMessage::CommandSetNextLocation(Some(Location::path("two.ogg")), Duration::ZERO),
             player_sub.try_recv().unwrap(),
         );
-
-        // Now skipping back should go off the end of the playlist
-        player_sub.broadcast(PlayerMessage::UpdatePlaybackStatus(PlaybackStatus {
-            playing: true,
-            current_position: Duration::from_secs(1),
-            end_position: Some(Duration::from_secs(60)),
-            volume: Default::default(),
-        }));
+        player_sub.broadcast(PlayerMessage::EventStartedNextTrack(Location::path(
+            "two.ogg",
+        )));
         manager.update();
-        ui_sub.broadcast(FrontendMessage::MediaControlSkipBack);
-        manager.update();
-        assert_eq!(2, manager.playlist.entries.len());
-        assert_eq!(None, manager.playlist.current_id);
-        assert_eq!(None, manager.playlist.current_index);
-        assert_eq!(PlayerMessage::CommandStop, player_sub.try_recv().unwrap(),);
+        assert_eq!(Some(PlaylistIndex(3)), manager.playlist.current_index);
+    }
 
-        assert_eq!(None, player_sub.try_recv());
-        assert_eq!(None, ui_sub.try_recv());
+    #[test]
+    fn skip_between_chapters() {
+        let (player, ui) = (Broadcaster::new(), Broadcaster::new());
+        let player_sub = player.subscribe("test", PlayerMessageChannel::All);
+        let ui_sub = ui.subscribe("test", NoChannels);
+
+        let mut manager = PlaylistManager::new(player.clone(), ui.clone());
+        let chapters = [0, 60, 120].map(|start| Chapter {
+            title: None,
+            start: Duration::from_secs(start),
+        });
+        let book = Location::path("book.m4b");
+        let mut entries = vec![manager.new_entry(Location::path("one.ogg"), None, None)];
+        for (range, _) in TrackRange::from_chapters(&chapters) {
+            entries.push(manager.new_entry(book.clone(), None, Some(range)));
+        }
+        manager.playlist.entries = entries;
+        let from = |seconds| {
+            Some(PlayerMessage::CommandLoadAndPlayLocationFrom(
+                book.clone(),
+                Duration::from_secs(seconds),
+            ))
+        };
+        let skip = |manager: &mut PlaylistManager, message: FrontendMessage, position: u64| {
+            player_sub.broadcast(PlayerMessage::UpdatePlaybackStatus(PlaybackStatus {
+                playing: true,
+                current_position: Duration::from_secs(position),
+                end_position: Some(Duration::from_secs(180)),
+                volume: Default::default(),
+                live: None,
+            }));
+            ui_sub.broadcast(message);
+            manager.update();
+            (player_sub.try_recv(), manager.playlist.current_index)
+        };
+
+        manager.start_track(PlaylistIndex(2));
+        assert_eq!(from(60), player_sub.try_recv());
+        assert_eq!(
+            (from(120), Some(PlaylistIndex(3))),
+            skip(&mut manager, FrontendMessage::MediaControlNextChapter, 70)
+        );
+        // There's no chapter after the last one
+        assert_eq!(
+            (None, Some(PlaylistIndex(3))),
+            skip(&mut manager, FrontendMessage::MediaControlNextChapter, 130)
+        );
+        // Going back part way into a chapter restarts it
+        assert_eq!(
+            (from(120), Some(PlaylistIndex(3))),
+            skip(
+                &mut manager,
+                FrontendMessage::MediaControlPreviousChapter,
+                130
+            )
+        );
+        assert_eq!(
+            (from(60), Some(PlaylistIndex(2))),
+            skip(
+                &mut manager,
+                FrontendMessage::MediaControlPreviousChapter,
+                121
+            )
+        );
+        // The first chapter restarts rather than going to another file
+        manager.start_track(PlaylistIndex(1));
+        assert_eq!(
+            Some(PlayerMessage::CommandLoadAndPlayLocation(book.clone())),
+            player_sub.try_recv()
+        );
+        assert_eq!(
+            (
+                Some(PlayerMessage::CommandLoadAndPlayLocation(book.clone())),
+                Some(PlaylistIndex(1))
+            ),
+            skip(
+                &mut manager,
+                FrontendMessage::MediaControlPreviousChapter,
+                2
+            )
+        );
     }
 }
@@ -0,0 +1,334 @@
+// This file is part of Millenium Player.
+// Copyright (C) 2023 John DiSanti.
+//
+// Millenium Player is free software: you can redistribute it and/or modify it under the terms of
+// the GNU General Public License as published by the Free Software Foundation, either version 3 of
+// the License, or (at your option) any later version.
+//
+// Millenium Player is distributed in the hope that it will be useful, but WITHOUT ANY WARRANTY;
+// without even the implied warranty of MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE. See
+// the GNU General Public License for more details.
+//
+// You should have received a copy of the GNU General Public License along with Millenium Player.
+// If not, see <https://www.gnu.org/licenses/>.
+
+use super::file::ListedEntry;
+use crate::{
+    location::Location,
+    metadata::{Metadata, MetadataChain},
+};
+use camino::Utf8Path;
+use std::{
+    cmp::Ordering,
+    iter::Peekable,
+    str::Chars,
+    sync::mpsc::{self, Receiver, TryRecvError},
+    thread,
+};
+
+/// Expands the directories among some listed entries on a background thread, since walking a
+/// large folder and reading its tags can take a while. Other entries are passed through
+/// in the order given.
+pub(crate) struct DirectoryExpansion {
+    expanded: Receiver<Vec<ListedEntry>>,
+}
+
+impl DirectoryExpansion {
+    /// Starts expanding.
+    pub fn start(entries: Vec<ListedEntry>, track_number_ordering: Option<MetadataChain>) -> Self {
+        let (expanded_tx, expanded) = mpsc::channel();
+        let expand = move || {
+            let expanded = entries
+                .into_iter()
+                .flat_map(|entry| match entry.location.as_path() {
+                    Some(path) if path.is_dir() => {
+                        expand_directory(path, track_number_ordering.as_ref())
+                            .into_iter()
+                            .map(ListedEntry::new)
+                            .collect()
+                    }
+                    _ => vec![entry],
+                })
+                .collect();
+            let _ = expanded_tx.send(expanded);
+        };
+        if let Err(err) = thread::Builder::new()
+            .name("directory-expansion".into())
+            .spawn(expand)
+        {
+            log::error!("failed to start the directory expansion thread: {err}");
+        }
+        Self { expanded }
+    }
+
+    /// Returns the expanded entries once the expansion has finished.
+    pub fn try_finish(&self) -> Option<Vec<ListedEntry>> {
+        match self.expanded.try_recv() {
+            Ok(expanded) => Some(expanded),
+            Err(TryRecvError::Empty) => None,
+            Err(TryRecvError::Disconnected) => {
+                log::error!("directory expansion stopped without a result");
+                Some(Vec::new())
+            }
+        }
+    }
+}
+
+/// Expands a directory into the audio files it contains, descending into subdirectories.
+///
+/// Files within each directory are ordered by their disc and track number tags when
+/// `track_number_ordering` gives the chain to read the tags with and every file has a track
+/// number. Otherwise, they are ordered by file name using natural ordering (so that "2.mp3"
+/// comes before "10.mp3").
+/// Subdirectories are always placed after the files in natural order, which keeps
+/// "CD1" and "CD2" style album layouts in order. Symlinked subdirectories are skipped, since
+/// they can link back to a directory that contains them.
+pub(crate) fn expand_directory(
+    path: &Utf8Path,
+    track_number_ordering: Option<&MetadataChain>,
+) -> Vec<Location> {
+    let read_dir = match path.read_dir_utf8() {
+        Ok(read_dir) => read_dir,
+        Err(err) => {
+            log::warn!("failed to read directory {path}: {err}");
+            return Vec::new();
+        }
+    };
+
+    let (mut files, mut directories) = (Vec::new(), Vec::new());
+    for entry in read_dir {
+        let entry = match entry {
+            Ok(entry) => entry,
+            Err(err) => {
+                log::warn!("failed to read directory entry in {path}: {err}");
+                continue;
+            }
+        };
+        let is_symlink = entry
+            .file_type()
+            .is_ok_and(|file_type| file_type.is_symlink());
+        let entry_path = entry.into_path();
+        if entry_path.is_dir() {
+            if is_symlink {
+                log::debug!("not following symlinked directory {entry_path}");
+            } else {
+                directories.push(entry_path);
+            }
+        } else {
+            let location = Location::Path(entry_path);
+            let inferred = location.sniff_type();
+            if !inferred.is_unknown() && !inferred.is_playlist() {
+                files.push(location);
+            }
+        }
+    }
+
+    let files: Vec<(Location, Option<Metadata>)> = files
+        .into_iter()
+        .map(|location| {
+            let metadata = track_number_ordering.and_then(|chain| chain.read(&location));
+            (location, metadata)
+        })
+        .collect();
+    let mut locations = sort_album(files);
+
+    directories.sort_by(|a, b| natural_cmp(a.as_str(), b.as_str()));
+    for directory in directories {
+        locations.extend(expand_directory(&directory, track_number_ordering));
+    }
+    locations
+}
+
+/// Sorts the files of an album.
+///
+/// If every file has a track number, then they're sorted by disc number and then track number.
+/// Otherwise, natural file name ordering is used for all of them, since mixing the two orderings
+/// would produce something nonsensical.
+pub(crate) fn sort_album(mut files: Vec<(Location, Option<Metadata>)>) -> Vec<Location> {
+    let all_numbered = files.iter().all(|(_, metadata)| {
+        metadata
+            .as_ref()
+            .and_then(Metadata::parsed_track_number)
+            .is_some()
+    });
+    if all_numbered {
+        files.sort_by(|(a_loc, a_meta), (b_loc, b_meta)| {
+            let (a_meta, b_meta) = (a_meta.as_ref().unwrap(), b_meta.as_ref().unwrap());
+            let a_disc = a_meta.parsed_disc_number().unwrap_or(1);
+            let b_disc = b_meta.parsed_disc_number().unwrap_or(1);
+            a_disc
+                .cmp(&b_disc)
+                .then_with(|| {
+                    a_meta
+                        .parsed_track_number()
+                        .cmp(&b_meta.parsed_track_number())
+                })
+                .then_with(|| natural_cmp(a_loc.as_str(), b_loc.as_str()))
+        });
+    } else {
+        files.sort_by(|(a, _), (b, _)| natural_cmp(a.as_str(), b.as_str()));
+    }
+    files.into_iter().map(|(location, _)| location).collect()
+}
+
+/// Compares two strings so that runs of digits are compared by their numeric value.
+///
+/// Letters are compared case insensitively, with the case sensitive comparison used
+/// as a tie breaker so that the ordering is total.
+pub(crate) fn natural_cmp(a: &str, b: &str) -> Ordering {
+    fn take_number(chars: &mut Peekable<Chars>) -> String {
+        let mut digits = String::new();
+        while let Some(&c) = chars.peek() {
+            if !c.is_ascii_digit() {
+                break;
+            }
+            digits.push(c);
+            chars.next();
+        }
+        digits
+    }
+
+    let (mut a_chars, mut b_chars) = (a.chars().peekable(), b.chars().peekable());
+    loop {
+        match (a_chars.peek().copied(), b_chars.peek().copied()) {
+            (None, None) => return a.cmp(b),
+            (None, Some(_)) => return Ordering::Less,
+            (Some(_), None) => return Ordering::Greater,
+            (Some(a_c), Some(b_c)) if a_c.is_ascii_digit() && b_c.is_ascii_digit() => {
+                let (a_num, b_num) = (take_number(&mut a_chars), take_number(&mut b_chars));
+                let (a_trimmed, b_trimmed) =
+                    (a_num.trim_start_matches('0'), b_num.trim_start_matches('0'));
+                let ordering = a_trimmed
+                    .len()
+                    .cmp(&b_trimmed.len())
+                    .then_with(|| a_trimmed.cmp(b_trimmed));
+                if ordering != Ordering::Equal {
+                    return ordering;
+                }
+            }
+            (Some(a_c), Some(b_c)) => {
+                let ordering = a_c.to_lowercase().cmp(b_c.to_lowercase());
+                if ordering != Ordering::Equal {
+                    return ordering;
+                }
+                a_chars.next();
+                b_chars.next();
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn natural_ordering() {
+        let mut names = vec![
+            "10 - ten.mp3",
+            "2 - two.mp3",
+            "01 - one.mp3",
+            "b.mp3",
+            "A.mp3",
+            "track 3.mp3",
+            "track 20.mp3",
+        ];
+        names.sort_by(|a, b| natural_cmp(a, b));
+        pretty_assertions::assert_eq!(
+            vec![
+                "01 - one.mp3",
+                "2 - two.mp3",
+                "10 - ten.mp3",
+                "A.mp3",
+                "b.mp3",
+                "track 3.mp3",
+                "track 20.mp3",
+            ],
+            names
+        );
+    }
+
+    fn numbered(disc: Option<&str>, track: Option<&str>) -> Option<Metadata> {
+        Some(Metadata {
+            disc_number: disc.map(Into::into),
+            track_number: track.map(Into::into),
+            ..Default::default()
+        })
+    }
+
+    #[test]
+    fn sort_album_by_track_number() {
+        let sorted = sort_album(vec![
+            (Location::path("b.mp3"), numbered(Some("2"), Some("1/2"))),
+            (Location::path("c.mp3"), numbered(None, Some("2/12"))),
+            (Location::path("a.mp3"), numbered(Some("2/2"), Some("2"))),
+            (Location::path("d.mp3"), numbered(Some("1"), Some("1"))),
+        ]);
+        pretty_assertions::assert_eq!(
+            vec![
+                Location::path("d.mp3"),
+                Location::path("c.mp3"),
+                Location::path("b.mp3"),
+                Location::path("a.mp3"),
+            ],
+            sorted
+        );
+    }
+
+    #[test]
+    fn sort_album_falls_back_to_file_names() {
+        let sorted = sort_album(vec![
+            (Location::path("10.mp3"), numbered(None, Some("1"))),
+            (Location::path("9.mp3"), None),
+            (Location::path("1.mp3"), numbered(None, Some("3"))),
+        ]);
+        pretty_assertions::assert_eq!(
+            vec![
+                Location::path("1.mp3"),
+                Location::path("9.mp3"),
+                Location::path("10.mp3"),
+            ],
+            sorted
+        );
+    }
+
+    #[test]
+    fn expand_test_data_directory() {
+        let locations = expand_directory(
+            Utf8Path::new("../test-data"),
+            Some(&MetadataChain::default()),
+        );
+        pretty_assertions::assert_eq!(
+            vec![
+                Location::path("../test-data/hydrate/hydrate.mp3"),
+                Location::path("../test-data/melodic_a_minor/melodic_a_minor_1chan_16000hz_6s.ogg"),
+                Location::path("../test-data/melodic_a_minor/melodic_a_minor_1chan_44100hz_6s.ogg"),
+                Location::path("../test-data/melodic_a_minor/melodic_a_minor_1chan_48000hz_6s.mp3"),
+                Location::path(
+                    "../test-data/melodic_a_minor/melodic_a_minor_2chan_44100hz_11s.ogg"
+                ),
+                Location::path("../test-data/melodic_a_minor/melodic_a_minor_multitrack.ogg"),
+            ],
+            locations
+        );
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn skip_symlinked_directories() {
+        let dir = std::env::temp_dir().join(format!("millenium-symlinks-{}", std::process::id()));
+        std::fs::create_dir_all(dir.join("disc")).unwrap();
+        std::fs::write(dir.join("disc").join("one.mp3"), b"").unwrap();
+        // Links back to the directory that contains it
+        std::os::unix::fs::symlink(&dir, dir.join("disc").join("loop")).unwrap();
+
+        let locations = expand_directory(Utf8Path::new(dir.to_str().unwrap()), None);
+        std::fs::remove_dir_all(&dir).unwrap();
+        assert_eq!(
+            vec![Location::path(
+                dir.join("disc").join("one.mp3").to_str().unwrap()
+            )],
+            locations
+        );
+    }
+}
@@ -0,0 +1,117 @@
+// This file is part of Millenium Player.
+// Copyright (C) 2023 John DiSanti.
+//
+// Millenium Player is free software: you can redistribute it and/or modify it under the terms of
+// the GNU General Public License as published by the Free Software Foundation, either version 3 of
+// the License, or (at your option) any later version.
+//
+// Millenium Player is distributed in the hope that it will be useful, but WITHOUT ANY WARRANTY;
+// without even the implied warranty of MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE. See
+// the GNU General Public License for more details.
+//
+// You should have received a copy of the GNU General Public License along with Millenium Player.
+// If not, see <https://www.gnu.org/licenses/>.
+
+use super::{MinimalMetadata, PlaylistEntry};
+use std::time::Duration;
+
+/// How far apart the known lengths of two entries can be for them to be the same recording.
+const LENGTH_TOLERANCE: Duration = Duration::from_secs(2);
+
+/// Lowercases a tag and collapses its whitespace so that small differences in tagging
+/// don't hide a duplicate.
+fn normalized(tag: Option<&String>) -> Option<String> {
+    let words: Vec<String> = tag?.split_whitespace().map(str::to_lowercase).collect();
+    (!words.is_empty()).then(|| words.join(" "))
+}
+
+fn artist_and_title(metadata: &MinimalMetadata) -> Option<(String, String)> {
+    Some((
+        normalized(metadata.artist.as_ref())?,
+        normalized(metadata.title.as_ref())?,
+    ))
+}
+
+/// Whether two entries are the same recording: either the same part of the same location,
+/// or a copy with the same artist and title tags (and a similar length, if both are known).
+pub(super) fn same_recording(a: &PlaylistEntry, b: &PlaylistEntry) -> bool {
+    if a.location == b.location {
+        return a.range == b.range;
+    }
+    let tags = |entry: &PlaylistEntry| entry.metadata.as_ref().and_then(artist_and_title);
+    let (Some(a_tags), Some(b_tags)) = (tags(a), tags(b)) else {
+        return false;
+    };
+    let similar_length = match (a.duration, b.duration) {
+        (Some(a), Some(b)) => a.max(b) - a.min(b) <= LENGTH_TOLERANCE,
+        _ => true,
+    };
+    a_tags == b_tags && similar_length
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        location::Location,
+        playlist::{PlaylistEntryId, TrackRange},
+    };
+
+    fn entry(path: &str, tags: Option<(&str, &str)>, duration: Option<u64>) -> PlaylistEntry {
+        PlaylistEntry {
+            id: PlaylistEntryId(0),
+            location: Location::path(path),
+            metadata: tags.map(|(artist, title)| MinimalMetadata {
+                artist: Some(artist.into()),
+                artists: vec![artist.into()],
+                album_artist: None,
+                album: None,
+                title: Some(title.into()),
+            }),
+            duration: duration.map(Duration::from_secs),
+            dynamic_range: None,
+            intro_skip: None,
+            skipped: false,
+            range: None,
+            variants: Vec::new(),
+            queued: false,
+        }
+    }
+
+    #[test]
+    fn same_location() {
+        let a = entry("one.ogg", None, None);
+        assert!(same_recording(&a, &entry("one.ogg", None, None)));
+        assert!(!same_recording(&a, &entry("two.ogg", None, None)));
+
+        // Different chapters of the same file aren't duplicates
+        let mut chapter = entry("one.ogg", None, None);
+        chapter.range = Some(TrackRange {
+            start: Duration::from_secs(60),
+            end: None,
+        });
+        assert!(!same_recording(&a, &chapter));
+    }
+
+    #[test]
+    fn matching_tags() {
+        let a = entry("one.ogg", Some(("Artist", "Song  Title")), Some(200));
+        assert!(same_recording(
+            &a,
+            &entry("copy.mp3", Some(("artist", " song title")), Some(201))
+        ));
+        assert!(same_recording(
+            &a,
+            &entry("copy.mp3", Some(("Artist", "Song Title")), None)
+        ));
+        assert!(!same_recording(
+            &a,
+            &entry("live.mp3", Some(("Artist", "Song Title")), Some(260))
+        ));
+        assert!(!same_recording(
+            &a,
+            &entry("other.mp3", Some(("Artist", "Other Song")), Some(200))
+        ));
+        assert!(!same_recording(&a, &entry("untagged.mp3", None, Some(200))));
+    }
+}
@@ -0,0 +1,622 @@
+// This file is part of Millenium Player.
+// Copyright (C) 2023 John DiSanti.
+//
+// Millenium Player is free software: you can redistribute it and/or modify it under the terms of
+// the GNU General Public License as published by the Free Software Foundation, either version 3 of
+// the License, or (at your option) any later version.
+//
+// Millenium Player is distributed in the hope that it will be useful, but WITHOUT ANY WARRANTY;
+// without even the implied warranty of MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE. See
+// the GNU General Public License for more details.
+//
+// You should have received a copy of the GNU General Public License along with Millenium Player.
+// If not, see <https://www.gnu.org/licenses/>.
+
+use super::quality::Variant;
+use crate::{audio::http::HttpMediaSource, location::Location};
+use camino::{Utf8Path, Utf8PathBuf};
+use millenium_post_office::types::{PlaybackError, PlaybackErrorKind};
+use std::{
+    borrow::Cow, collections::BTreeMap, fmt::Write, fs, io::Read, str::FromStr, time::Duration,
+};
+use url::Url;
+
+/// Largest playlist file that will be read. Anything bigger is very unlikely to be a playlist.
+const MAX_PLAYLIST_SIZE: u64 = 16 * 1024 * 1024;
+
+#[derive(Debug, thiserror::Error)]
+pub enum PlaylistFileError {
+    #[error("failed to read playlist: {source}")]
+    Read {
+        #[source]
+        source: std::io::Error,
+    },
+    #[error("playlist is larger than {MAX_PLAYLIST_SIZE} bytes")]
+    TooLarge,
+    #[error("failed to save playlist: {source}")]
+    Write {
+        #[source]
+        source: std::io::Error,
+    },
+}
+
+impl PlaylistFileError {
+    /// Converts this error into a [`PlaybackError`] that can be broadcast to the UI.
+    pub fn to_playback_error(&self, location: &Location) -> PlaybackError {
+        let kind = match self {
+            Self::Read { source } if source.kind() == std::io::ErrorKind::NotFound => {
+                PlaybackErrorKind::NotFound
+            }
+            Self::Read { .. } | Self::Write { .. } => PlaybackErrorKind::Unreadable,
+            Self::TooLarge => PlaybackErrorKind::UnsupportedFormat,
+        };
+        PlaybackError {
+            kind,
+            location: location.to_string(),
+            message: self.to_string(),
+            retryable: false,
+        }
+    }
+}
+
+/// File formats that a playlist can be saved in.
+#[derive(Copy, Clone, Debug, Eq, PartialEq)]
+pub enum PlaylistFormat {
+    /// Extended M3U in UTF-8.
+    M3u8,
+    /// XML Shareable Playlist Format.
+    Xspf,
+}
+
+impl PlaylistFormat {
+    /// Picks the format from a file name's extension, defaulting to M3U8.
+    pub fn from_path(path: &Utf8Path) -> Self {
+        match path.extension() {
+            Some(extension) if extension.eq_ignore_ascii_case("xspf") => Self::Xspf,
+            _ => Self::M3u8,
+        }
+    }
+
+    pub fn extension(&self) -> &'static str {
+        match self {
+            Self::M3u8 => "m3u8",
+            Self::Xspf => "xspf",
+        }
+    }
+}
+
+/// An entry listed in a playlist file, along with any metadata the playlist gave for it.
+#[derive(Clone, Debug, PartialEq)]
+pub(crate) struct ListedEntry {
+    pub location: Location,
+    pub artist: Option<String>,
+    pub title: Option<String>,
+    pub duration: Option<Duration>,
+    /// Qualities that the stream is offered in, if there's more than one.
+    pub variants: Vec<Variant>,
+}
+
+impl ListedEntry {
+    pub fn new(location: Location) -> Self {
+        Self {
+            location,
+            artist: None,
+            title: None,
+            duration: None,
+            variants: Vec::new(),
+        }
+    }
+}
+
+/// Reads an M3U, extended M3U8, or PLS playlist.
+///
+/// Relative entries are resolved against the location of the playlist itself.
+pub(crate) fn read_playlist(location: &Location) -> Result<Vec<ListedEntry>, PlaylistFileError> {
+    let mut bytes = Vec::new();
+    let read = match location {
+        Location::Path(path) => fs::File::open(path)
+            .and_then(|file| file.take(MAX_PLAYLIST_SIZE + 1).read_to_end(&mut bytes)),
+        Location::Url(url) => HttpMediaSource::open(url)
+            .and_then(|source| source.take(MAX_PLAYLIST_SIZE + 1).read_to_end(&mut bytes)),
+    };
+    read.map_err(|source| PlaylistFileError::Read { source })?;
+    if bytes.len() as u64 > MAX_PLAYLIST_SIZE {
+        return Err(PlaylistFileError::TooLarge);
+    }
+    Ok(parse_playlist(&decode(&bytes), location))
+}
+
+/// Writes a playlist file in the given format.
+pub(crate) fn write_playlist(
+    path: &Utf8Path,
+    format: PlaylistFormat,
+    entries: &[ListedEntry],
+) -> Result<(), PlaylistFileError> {
+    let text = match format {
+        PlaylistFormat::M3u8 => format_m3u8(entries, path.parent()),
+        PlaylistFormat::Xspf => format_xspf(entries),
+    };
+    fs::write(path, text).map_err(|source| PlaylistFileError::Write { source })
+}
+
+/// Formats an extended M3U playlist. Files in or under the playlist's directory are
+/// listed relative to it so that the whole folder can be moved together.
+fn format_m3u8(entries: &[ListedEntry], directory: Option<&Utf8Path>) -> String {
+    let mut text = String::from("#EXTM3U\n");
+    for entry in entries {
+        if entry.duration.is_some() || entry.title.is_some() {
+            let seconds = entry
+                .duration
+                .map(|duration| duration.as_secs() as i64)
+                .unwrap_or(-1);
+            let title = match (&entry.artist, &entry.title) {
+                (Some(artist), Some(title)) => format!("{artist} - {title}"),
+                (None, Some(title)) => title.clone(),
+                (_, None) => String::new(),
+            };
+            let _ = writeln!(text, "#EXTINF:{seconds},{title}");
+        }
+        let location = match (&entry.location, directory) {
+            (Location::Path(path), Some(directory)) => path
+                .strip_prefix(directory)
+                .unwrap_or(path)
+                .as_str()
+                .to_string(),
+            (location, _) => location.to_string(),
+        };
+        let _ = writeln!(text, "{location}");
+    }
+    text
+}
+
+/// Formats an XSPF playlist. Locations in XSPF are URIs, so files are written as `file://` URLs.
+fn format_xspf(entries: &[ListedEntry]) -> String {
+    let mut text = String::from(
+        "<?xml version=\"1.0\" encoding=\"UTF-8\"?>\n\
+        <playlist version=\"1\" xmlns=\"http://xspf.org/ns/0/\">\n  <trackList>\n",
+    );
+    for entry in entries {
+        let location = match &entry.location {
+            Location::Path(path) => match Url::from_file_path(path) {
+                Ok(url) => url.to_string(),
+                Err(_) => path.to_string(),
+            },
+            Location::Url(url) => url.to_string(),
+        };
+        text.push_str("    <track>\n");
+        let _ = writeln!(text, "      <location>{}</location>", escape_xml(&location));
+        if let Some(title) = &entry.title {
+            let _ = writeln!(text, "      <title>{}</title>", escape_xml(title));
+        }
+        if let Some(artist) = &entry.artist {
+            let _ = writeln!(text, "      <creator>{}</creator>", escape_xml(artist));
+        }
+        if let Some(duration) = entry.duration {
+            let _ = writeln!(text, "      <duration>{}</duration>", duration.as_millis());
+        }
+        text.push_str("    </track>\n");
+    }
+    text.push_str("  </trackList>\n</playlist>\n");
+    text
+}
+
+fn escape_xml(value: &str) -> Cow<'_, str> {
+    if !value.contains(['&', '<', '>', '"', '\'']) {
+        return Cow::Borrowed(value);
+    }
+    let mut escaped = String::with_capacity(value.len() + 8);
+    for c in value.chars() {
+        match c {
+            '&' => escaped.push_str("&amp;"),
+            '<' => escaped.push_str("&lt;"),
+            '>' => escaped.push_str("&gt;"),
+            '"' => escaped.push_str("&quot;"),
+            '\'' => escaped.push_str("&apos;"),
+            _ => escaped.push(c),
+        }
+    }
+    Cow::Owned(escaped)
+}
+
+/// Decodes the playlist text. M3U8 and PLS files are UTF-8, but plain M3U files
+/// are often in the system's legacy code page, which is most likely Windows-1252.
+fn decode(bytes: &[u8]) -> String {
+    let bytes = bytes.strip_prefix(b"\xEF\xBB\xBF").unwrap_or(bytes);
+    match std::str::from_utf8(bytes) {
+        Ok(text) => text.to_string(),
+        Err(_) => encoding_rs::WINDOWS_1252.decode(bytes).0.into_owned(),
+    }
+}
+
+fn parse_playlist(text: &str, base: &Location) -> Vec<ListedEntry> {
+    let is_pls = text
+        .trim_start()
+        .get(..10)
+        .map(|start| start.eq_ignore_ascii_case("[playlist]"))
+        .unwrap_or(false);
+    let entries = if is_pls {
+        parse_pls(text, base)
+    } else {
+        parse_m3u(text, base)
+    };
+    group_mounts(entries)
+}
+
+/// Stations often list each of their mounts (such as a high and low bitrate stream) under
+/// the same title, so consecutive streams with the same title are played as one entry.
+fn group_mounts(entries: Vec<ListedEntry>) -> Vec<ListedEntry> {
+    let mut grouped: Vec<ListedEntry> = Vec::new();
+    for entry in entries {
+        if let Some(previous) = grouped.last_mut() {
+            let same_station = previous.title.is_some()
+                && previous.title == entry.title
+                && previous.artist == entry.artist
+                && previous.location.as_url().is_some()
+                && entry.location.as_url().is_some();
+            if same_station {
+                if previous.variants.is_empty() {
+                    previous
+                        .variants
+                        .push(Variant::new(previous.location.clone(), None));
+                }
+                previous.variants.push(Variant::new(entry.location, None));
+                continue;
+            }
+        }
+        grouped.push(entry);
+    }
+    grouped
+}
+
+/// Parses an M3U playlist, using the `#EXTINF` lines of extended M3U for metadata.
+///
+/// The streams listed with `#EXT-X-STREAM-INF` in an HLS master playlist are the qualities
+/// of a single stream, so they're combined into one entry.
+fn parse_m3u(text: &str, base: &Location) -> Vec<ListedEntry> {
+    let mut entries = Vec::new();
+    let mut variants = Vec::new();
+    let mut info: Option<(Option<Duration>, Option<String>)> = None;
+    let mut stream_inf: Option<Option<u32>> = None;
+    for line in text.lines().map(str::trim) {
+        if let Some(attributes) = line.strip_prefix("#EXT-X-STREAM-INF:") {
+            let bandwidth = attribute(attributes, "BANDWIDTH").and_then(|value| value.parse().ok());
+            stream_inf = Some(bandwidth);
+        } else if let Some(extinf) = line.strip_prefix("#EXTINF:") {
+            // #EXTINF:<seconds> [attributes],<display title>
+            let (head, title) = extinf.split_once(',').unwrap_or((extinf, ""));
+            let seconds = head.split_whitespace().next().unwrap_or_default();
+            info = Some((parse_seconds(seconds), Some(title.trim().to_string())));
+        } else if line.is_empty() || line.starts_with('#') {
+            continue;
+        } else if let Some(location) = resolve(line, base) {
+            if let Some(bandwidth) = stream_inf.take() {
+                variants.push(Variant::new(location, bandwidth));
+                continue;
+            }
+            let (duration, title) = info.take().unwrap_or_default();
+            let (artist, title) = split_display_title(title);
+            entries.push(ListedEntry {
+                location,
+                artist,
+                title,
+                duration,
+                variants: Vec::new(),
+            });
+        }
+    }
+    if !variants.is_empty() {
+        let mut entry = ListedEntry::new(variants[0].location.clone());
+        if variants.len() > 1 {
+            entry.variants = variants;
+        }
+        entries.insert(0, entry);
+    }
+    entries
+}
+
+/// Finds the value of an attribute in an HLS attribute list, such as
+/// `BANDWIDTH=128000,CODECS="mp4a.40.2"`.
+fn attribute<'a>(attributes: &'a str, name: &str) -> Option<&'a str> {
+    let mut rest = attributes;
+    while !rest.is_empty() {
+        let (key, after_key) = rest.split_once('=')?;
+        let (value, after_value) = match after_key.strip_prefix('"') {
+            Some(quoted) => {
+                let (value, after) = quoted.split_once('"')?;
+                (value, after.trim_start_matches(','))
+            }
+            None => after_key.split_once(',').unwrap_or((after_key, "")),
+        };
+        if key.trim().eq_ignore_ascii_case(name) {
+            return Some(value.trim());
+        }
+        rest = after_value;
+    }
+    None
+}
+
+/// Parses a PLS playlist, which lists numbered `FileN`, `TitleN`, and `LengthN` keys.
+fn parse_pls(text: &str, base: &Location) -> Vec<ListedEntry> {
+    #[derive(Default)]
+    struct PlsEntry<'a> {
+        file: Option<&'a str>,
+        title: Option<&'a str>,
+        length: Option<&'a str>,
+    }
+    let mut numbered: BTreeMap<u32, PlsEntry> = BTreeMap::new();
+    for line in text.lines() {
+        let Some((key, value)) = line.split_once('=') else {
+            continue;
+        };
+        let key = key.trim().to_ascii_lowercase();
+        let split = key.find(|c: char| c.is_ascii_digit()).unwrap_or(key.len());
+        let (name, number) = key.split_at(split);
+        let Ok(number) = number.parse::<u32>() else {
+            continue;
+        };
+        let entry = numbered.entry(number).or_default();
+        let value = Some(value.trim());
+        match name {
+            "file" => entry.file = value,
+            "title" => entry.title = value,
+            "length" => entry.length = value,
+            _ => {}
+        }
+    }
+    numbered
+        .into_values()
+        .filter_map(|entry| {
+            let location = resolve(entry.file?, base)?;
+            let (artist, title) = split_display_title(entry.title.map(str::to_string));
+            Some(ListedEntry {
+                location,
+                artist,
+                title,
+                duration: entry.length.and_then(parse_seconds),
+                variants: Vec::new(),
+            })
+        })
+        .collect()
+}
+
+/// Parses a length in seconds. Unknown lengths, such as for radio streams, are given as -1.
+fn parse_seconds(value: &str) -> Option<Duration> {
+    value
+        .parse::<f64>()
+        .ok()
+        .filter(|seconds| seconds.is_finite() && *seconds >= 0.0)
+        .map(Duration::from_secs_f64)
+}
+
+/// Splits a display title in the conventional "Artist - Title" form.
+fn split_display_title(title: Option<String>) -> (Option<String>, Option<String>) {
+    match title.as_deref().filter(|title| !title.is_empty()) {
+        None => (None, None),
+        Some(display) => match display.split_once(" - ") {
+            Some((artist, title)) => (Some(artist.into()), Some(title.into())),
+            None => (None, title),
+        },
+    }
+}
+
+/// Resolves a playlist entry against the location of the playlist.
+fn resolve(entry: &str, base: &Location) -> Option<Location> {
+    if entry.contains("://") {
+        let url = match Url::parse(entry) {
+            Ok(url) => url,
+            Err(err) => {
+                log::warn!("ignoring invalid URL in playlist {base}: {entry}: {err}");
+                return None;
+            }
+        };
+        if url.scheme() == "file" {
+            let path = url.to_file_path().ok()?;
+            return Utf8PathBuf::from_path_buf(path).ok().map(Location::Path);
+        }
+        return Some(Location::Url(url));
+    }
+    match base {
+        Location::Url(base_url) => match base_url.join(entry) {
+            Ok(url) => Some(Location::Url(url)),
+            Err(err) => {
+                log::warn!("ignoring invalid entry in playlist {base}: {entry}: {err}");
+                None
+            }
+        },
+        Location::Path(base_path) => {
+            // Playlists written on Windows use backslashes, which are only separators there
+            let entry = if cfg!(windows) {
+                entry.to_string()
+            } else {
+                entry.replace('\\', "/")
+            };
+            let path = Utf8Path::new(&entry);
+            if path.is_absolute() {
+                return Location::from_str(path.as_str()).ok();
+            }
+            let directory = base_path.parent().unwrap_or(Utf8Path::new(""));
+            Some(Location::Path(directory.join(path)))
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn extended_m3u() {
+        let text = "#EXTM3U\n\
+            #EXTINF:123,Some Artist - Some Song\n\
+            songs/one.mp3\n\
+            \n\
+            # A comment\n\
+            #EXTINF:-1 tvg-name=\"radio\",Radio Station\n\
+            http://example.com/stream\n\
+            /absolute/two.flac\n\
+            ..\\windows\\three.ogg\n";
+        let entries = parse_m3u(text, &Location::path("/music/list.m3u8"));
+        assert_eq!(
+            vec![
+                ListedEntry {
+                    location: Location::path("/music/songs/one.mp3"),
+                    artist: Some("Some Artist".into()),
+                    title: Some("Some Song".into()),
+                    duration: Some(Duration::from_secs(123)),
+                    variants: Vec::new(),
+                },
+                ListedEntry {
+                    location: Location::from_str("http://example.com/stream").unwrap(),
+                    artist: None,
+                    title: Some("Radio Station".into()),
+                    duration: None,
+                    variants: Vec::new(),
+                },
+                ListedEntry::new(Location::path("/absolute/two.flac")),
+                ListedEntry::new(Location::path("/music/../windows/three.ogg")),
+            ],
+            entries
+        );
+    }
+
+    #[test]
+    fn pls() {
+        let text = "[playlist]\n\
+            File2=two.ogg\n\
+            File1=http://example.com/live\n\
+            Title1=Live Radio\n\
+            Length1=-1\n\
+            Length2=61.5\n\
+            NumberOfEntries=2\n\
+            Version=2\n";
+        let entries = parse_playlist(
+            text,
+            &Location::from_str("http://example.com/lists/radio.pls").unwrap(),
+        );
+        assert_eq!(
+            vec![
+                ListedEntry {
+                    location: Location::from_str("http://example.com/live").unwrap(),
+                    artist: None,
+                    title: Some("Live Radio".into()),
+                    duration: None,
+                    variants: Vec::new(),
+                },
+                ListedEntry {
+                    location: Location::from_str("http://example.com/lists/two.ogg").unwrap(),
+                    artist: None,
+                    title: None,
+                    duration: Some(Duration::from_secs_f64(61.5)),
+                    variants: Vec::new(),
+                },
+            ],
+            entries
+        );
+    }
+
+    #[test]
+    fn stream_variants() {
+        let base = Location::from_str("http://example.com/radio/master.m3u8").unwrap();
+        let url = |path: &str| Location::from_str(&format!("http://example.com/{path}")).unwrap();
+        let text = "#EXTM3U\n\
+            #EXT-X-STREAM-INF:CODECS=\"mp4a.40.2,mp4a.40.5\",BANDWIDTH=128000\n\
+            high.aac\n\
+            #EXT-X-STREAM-INF:AVERAGE-BANDWIDTH=1,BANDWIDTH=64000\n\
+            low.aac\n";
+        let entries = parse_playlist(text, &base);
+        assert_eq!(1, entries.len());
+        assert_eq!(url("radio/high.aac"), entries[0].location);
+        assert_eq!(
+            vec![
+                Variant::new(url("radio/high.aac"), Some(128_000)),
+                Variant::new(url("radio/low.aac"), Some(64_000)),
+            ],
+            entries[0].variants
+        );
+
+        let text = "[playlist]\n\
+            File1=http://example.com/hi.mp3\nTitle1=Station\n\
+            File2=http://example.com/lo.mp3\nTitle2=Station\n\
+            File3=http://example.com/other.mp3\nTitle3=Other Station\n";
+        let entries = parse_playlist(text, &base);
+        assert_eq!(2, entries.len());
+        assert_eq!(
+            vec![
+                Variant::new(url("hi.mp3"), None),
+                Variant::new(url("lo.mp3"), None),
+            ],
+            entries[0].variants
+        );
+        assert!(entries[1].variants.is_empty());
+    }
+
+    #[test]
+    fn legacy_encoding() {
+        assert_eq!("Café.mp3", decode(b"Caf\xE9.mp3"));
+        assert_eq!("Café.mp3", decode(b"\xEF\xBB\xBFCaf\xC3\xA9.mp3"));
+    }
+
+    #[test]
+    fn save_m3u8() {
+        let entries = vec![
+            ListedEntry {
+                location: Location::path("/music/album/one.flac"),
+                artist: Some("Artist".into()),
+                title: Some("Song".into()),
+                duration: Some(Duration::from_secs(200)),
+                variants: Vec::new(),
+            },
+            ListedEntry::new(Location::path("/elsewhere/two.mp3")),
+            ListedEntry {
+                location: Location::from_str("http://example.com/live").unwrap(),
+                artist: None,
+                title: Some("Radio".into()),
+                duration: None,
+                variants: Vec::new(),
+            },
+        ];
+        let text = format_m3u8(&entries, Some(Utf8Path::new("/music")));
+        assert_eq!(
+            "#EXTM3U\n\
+            #EXTINF:200,Artist - Song\n\
+            album/one.flac\n\
+            /elsewhere/two.mp3\n\
+            #EXTINF:-1,Radio\n\
+            http://example.com/live\n",
+            text
+        );
+        assert_eq!(
+            entries,
+            parse_playlist(&text, &Location::path("/music/saved.m3u8"))
+        );
+    }
+
+    #[test]
+    fn save_xspf() {
+        let entries = vec![ListedEntry {
+            location: Location::path("/music/Rock & Roll.mp3"),
+            artist: Some("Artist".into()),
+            title: Some("<Song>".into()),
+            duration: Some(Duration::from_millis(61_500)),
+            variants: Vec::new(),
+        }];
+        assert_eq!(
+            "<?xml version=\"1.0\" encoding=\"UTF-8\"?>\n\
+            <playlist version=\"1\" xmlns=\"http://xspf.org/ns/0/\">\n  \
+              <trackList>\n    \
+                <track>\n      \
+                  <location>file:///music/Rock%20&amp;%20Roll.mp3</location>\n      \
+                  <title>&lt;Song&gt;</title>\n      \
+                  <creator>Artist</creator>\n      \
+                  <duration>61500</duration>\n    \
+                </track>\n  \
+              </trackList>\n\
+            </playlist>\n",
+            format_xspf(&entries)
+        );
+        assert_eq!(
+            PlaylistFormat::Xspf,
+            PlaylistFormat::from_path(Utf8Path::new("saved.XSPF"))
+        );
+    }
+}
@@ -0,0 +1,85 @@
+// This file is part of Millenium Player.
+// Copyright (C) 2023 John DiSanti.
+//
+// Millenium Player is free software: you can redistribute it and/or modify it under the terms of
+// the GNU General Public License as published by the Free Software Foundation, either version 3 of
+// the License, or (at your option) any later version.
+//
+// Millenium Player is distributed in the hope that it will be useful, but WITHOUT ANY WARRANTY;
+// without even the implied warranty of MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE. See
+// the GNU General Public License for more details.
+//
+// You should have received a copy of the GNU General Public License along with Millenium Player.
+// If not, see <https://www.gnu.org/licenses/>.
+
+use crate::location::Location;
+use camino::Utf8PathBuf;
+use millenium_post_office::frontend::message::TrackTransition;
+use std::time::Duration;
+
+/// Playback settings for every track under a folder, such as a folder of audiobooks.
+///
+/// Settings that aren't set fall back to the global ones. Entries and playlists that
+/// override a setting themselves still take precedence.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct FolderDefaults {
+    pub folder: Utf8PathBuf,
+    pub intro_skip: Option<Duration>,
+    pub track_transition: Option<TrackTransition>,
+}
+
+/// Finds the defaults for the folder that most closely contains the location.
+///
+/// Folders can be nested, so the deepest one wins. URLs never match.
+pub(super) fn defaults_for<'a>(
+    defaults: &'a [FolderDefaults],
+    location: &Location,
+) -> Option<&'a FolderDefaults> {
+    let path = location.as_path()?;
+    defaults
+        .iter()
+        .filter(|defaults| path.starts_with(&defaults.folder))
+        .max_by_key(|defaults| defaults.folder.components().count())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn deepest_folder_wins() {
+        let defaults = [
+            FolderDefaults {
+                folder: "/home/me/Audiobooks".into(),
+                intro_skip: Some(Duration::from_secs(30)),
+                ..Default::default()
+            },
+            FolderDefaults {
+                folder: "/home/me/Audiobooks/Dramas".into(),
+                ..Default::default()
+            },
+        ];
+        let folder = |location: Location| {
+            defaults_for(&defaults, &location).map(|defaults| defaults.folder.as_str())
+        };
+        assert_eq!(
+            Some("/home/me/Audiobooks"),
+            folder(Location::path("/home/me/Audiobooks/Novel/01.mp3"))
+        );
+        assert_eq!(
+            Some("/home/me/Audiobooks/Dramas"),
+            folder(Location::path("/home/me/Audiobooks/Dramas/01.mp3"))
+        );
+        // Only whole folder names match
+        assert_eq!(None, folder(Location::path("/home/me/Audiobooks2/01.mp3")));
+        assert_eq!(None, folder(Location::path("/home/me/Music/01.mp3")));
+        assert_eq!(
+            None,
+            folder(
+                "https://example.com/home/me/Audiobooks/01.mp3"
+                    .parse()
+                    .unwrap()
+            )
+        );
+    }
+}
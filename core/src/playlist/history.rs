@@ -0,0 +1,191 @@
+// This file is part of Millenium Player.
+// Copyright (C) 2023 John DiSanti.
+//
+// Millenium Player is free software: you can redistribute it and/or modify it under the terms of
+// the GNU General Public License as published by the Free Software Foundation, either version 3 of
+// the License, or (at your option) any later version.
+//
+// Millenium Player is distributed in the hope that it will be useful, but WITHOUT ANY WARRANTY;
+// without even the implied warranty of MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE. See
+// the GNU General Public License for more details.
+//
+// You should have received a copy of the GNU General Public License along with Millenium Player.
+// If not, see <https://www.gnu.org/licenses/>.
+
+use crate::location::Location;
+use millenium_post_office::types::PlayedSong;
+use std::{
+    collections::{HashMap, VecDeque},
+    time::{Duration, SystemTime},
+};
+
+/// Most songs remembered for each station.
+const MAX_SONGS_PER_STATION: usize = 200;
+
+/// Most skips remembered for each track.
+const MAX_SKIPS_PER_TRACK: usize = 50;
+
+/// Songs that radio stations announced while they were playing, kept for each station.
+#[derive(Default)]
+pub(crate) struct SongHistory {
+    stations: HashMap<Location, VecDeque<PlayedSong>>,
+}
+
+impl SongHistory {
+    /// Records a song announced by a station. Returns false if it was already playing.
+    pub fn record(&mut self, station: &Location, title: String, played_at: SystemTime) -> bool {
+        let songs = self.stations.entry(station.clone()).or_default();
+        if songs.back().map(|last| &last.title) == Some(&title) {
+            return false;
+        }
+        if songs.len() == MAX_SONGS_PER_STATION {
+            songs.pop_front();
+        }
+        songs.push_back(PlayedSong { title, played_at });
+        true
+    }
+
+    /// Songs the station has played, oldest first.
+    pub fn songs(&self, station: &Location) -> Vec<PlayedSong> {
+        self.stations
+            .get(station)
+            .map(|songs| songs.iter().cloned().collect())
+            .unwrap_or_default()
+    }
+}
+
+/// A track being skipped before it finished.
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct Skip {
+    /// How far into the track it was skipped.
+    pub position: Duration,
+    pub skipped_at: SystemTime,
+}
+
+/// Tracks the user skipped, so that frequently skipped tracks can be played less.
+///
+/// Tracks are identified by their location and where they start in it, so that virtual
+/// tracks sharing a file are kept apart.
+#[derive(Default)]
+pub struct SkipHistory {
+    tracks: HashMap<(Location, Duration), VecDeque<Skip>>,
+}
+
+impl SkipHistory {
+    pub fn record(&mut self, location: &Location, start: Duration, skip: Skip) {
+        let skips = self.tracks.entry((location.clone(), start)).or_default();
+        if skips.len() == MAX_SKIPS_PER_TRACK {
+            skips.pop_front();
+        }
+        skips.push_back(skip);
+    }
+
+    /// Times the track was skipped, oldest first.
+    pub fn skips(&self, location: &Location, start: Duration) -> Vec<Skip> {
+        self.tracks
+            .get(&(location.clone(), start))
+            .map(|skips| skips.iter().cloned().collect())
+            .unwrap_or_default()
+    }
+
+    /// How many times the track was skipped.
+    pub fn skip_count(&self, location: &Location, start: Duration) -> usize {
+        self.tracks
+            .get(&(location.clone(), start))
+            .map(VecDeque::len)
+            .unwrap_or_default()
+    }
+}
+
+/// How many times a track was played, and when it was last played.
+#[derive(Copy, Clone, Debug, Eq, PartialEq)]
+pub struct Plays {
+    pub count: usize,
+    pub last_played: SystemTime,
+}
+
+/// Tracks that were played at least halfway through.
+///
+/// Tracks are identified the same way as in [`SkipHistory`].
+#[derive(Default)]
+pub struct PlayHistory {
+    tracks: HashMap<(Location, Duration), Plays>,
+}
+
+impl PlayHistory {
+    pub fn record(&mut self, location: &Location, start: Duration, played_at: SystemTime) {
+        self.tracks
+            .entry((location.clone(), start))
+            .and_modify(|plays| {
+                plays.count += 1;
+                plays.last_played = played_at;
+            })
+            .or_insert(Plays {
+                count: 1,
+                last_played: played_at,
+            });
+    }
+
+    /// How many times the track was played and when, or `None` if it never was.
+    pub fn plays(&self, location: &Location, start: Duration) -> Option<Plays> {
+        self.tracks.get(&(location.clone(), start)).copied()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::{str::FromStr, time::Duration};
+
+    #[test]
+    fn record_per_station() {
+        let one = Location::from_str("http://example.com/one").unwrap();
+        let two = Location::from_str("http://example.com/two").unwrap();
+        let at = |seconds| SystemTime::UNIX_EPOCH + Duration::from_secs(seconds);
+        let mut history = SongHistory::default();
+        assert!(history.record(&one, "A".into(), at(1)));
+        assert!(history.record(&two, "B".into(), at(2)));
+        // Stations repeat the title when reconnecting
+        assert!(!history.record(&one, "A".into(), at(3)));
+        assert!(history.record(&one, "C".into(), at(4)));
+        let song = |title: &str, seconds| PlayedSong {
+            title: title.into(),
+            played_at: at(seconds),
+        };
+        assert_eq!(vec![song("A", 1), song("C", 4)], history.songs(&one));
+        assert_eq!(vec![song("B", 2)], history.songs(&two));
+
+        for index in 0..MAX_SONGS_PER_STATION as u64 {
+            history.record(&one, format!("Song {index}"), at(10 + index));
+        }
+        let songs = history.songs(&one);
+        assert_eq!(MAX_SONGS_PER_STATION, songs.len());
+        assert_eq!("Song 0", songs[0].title);
+    }
+
+    #[test]
+    fn record_skips_per_track() {
+        let file = Location::path("album.flac");
+        let skip = |seconds| Skip {
+            position: Duration::from_secs(seconds),
+            skipped_at: SystemTime::UNIX_EPOCH + Duration::from_secs(seconds),
+        };
+        let mut history = SkipHistory::default();
+        history.record(&file, Duration::ZERO, skip(5));
+        history.record(&file, Duration::from_secs(300), skip(10));
+        history.record(&file, Duration::from_secs(300), skip(20));
+        assert_eq!(vec![skip(5)], history.skips(&file, Duration::ZERO));
+        assert_eq!(2, history.skip_count(&file, Duration::from_secs(300)));
+        assert_eq!(
+            0,
+            history.skip_count(&Location::path("other.flac"), Duration::ZERO)
+        );
+
+        for seconds in 0..MAX_SKIPS_PER_TRACK as u64 {
+            history.record(&file, Duration::ZERO, skip(100 + seconds));
+        }
+        let skips = history.skips(&file, Duration::ZERO);
+        assert_eq!(MAX_SKIPS_PER_TRACK, skips.len());
+        assert_eq!(skip(100), skips[0]);
+    }
+}
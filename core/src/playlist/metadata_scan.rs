@@ -0,0 +1,319 @@
+// This file is part of Millenium Player.
+// Copyright (C) 2023 John DiSanti.
+//
+// Millenium Player is free software: you can redistribute it and/or modify it under the terms of
+// the GNU General Public License as published by the Free Software Foundation, either version 3 of
+// the License, or (at your option) any later version.
+//
+// Millenium Player is distributed in the hope that it will be useful, but WITHOUT ANY WARRANTY;
+// without even the implied warranty of MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE. See
+// the GNU General Public License for more details.
+//
+// You should have received a copy of the GNU General Public License along with Millenium Player.
+// If not, see <https://www.gnu.org/licenses/>.
+
+use super::PlaylistEntryId;
+use crate::{
+    audio::{
+        analysis_cache::AnalysisCache,
+        dynamic_range::{measure_dynamic_range, DynamicRange},
+        source::{self, ProbedAudio},
+    },
+    location::Location,
+    metadata::MetadataChain,
+};
+use millenium_post_office::types::PlaybackError;
+use std::{
+    collections::VecDeque,
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        mpsc::{self, Receiver, Sender},
+        Arc, Mutex,
+    },
+    thread::{self, JoinHandle},
+};
+
+/// Most threads that probe entries at the same time.
+const MAX_WORKERS: usize = 4;
+
+/// What was probed for a playlist entry, or why it can't be played.
+#[derive(Debug)]
+pub(crate) struct ScannedEntry {
+    pub id: PlaylistEntryId,
+    /// The tags are read through the metadata chain, so they may not be the file's own.
+    pub probed: Result<ProbedAudio, PlaybackError>,
+}
+
+/// Probes playlist entries on a small pool of background threads, so that loading a large
+/// playlist doesn't hold up playback or the UI. This is the only place local files are
+/// probed, which checks that they can be played and reads their tags, lengths, and chapters.
+///
+/// Once every entry has been probed, the dynamic range of the playable files is measured,
+/// which decodes them in full. Measurements are kept in the analysis cache so that files
+/// are only measured once.
+///
+/// Entries are probed in the order given. Dropping the scan cancels it.
+pub(crate) struct MetadataScan {
+    queue: Arc<Mutex<VecDeque<(PlaylistEntryId, Location)>>>,
+    /// Probed files whose dynamic range hasn't been measured yet.
+    unmeasured: Arc<Mutex<VecDeque<(PlaylistEntryId, Location)>>>,
+    chain: MetadataChain,
+    cache: AnalysisCache,
+    scanned_tx: Sender<ScannedEntry>,
+    scanned: Receiver<ScannedEntry>,
+    measured_tx: Sender<(PlaylistEntryId, DynamicRange)>,
+    measured: Receiver<(PlaylistEntryId, DynamicRange)>,
+    cancel: Arc<AtomicBool>,
+    workers: Vec<JoinHandle<()>>,
+}
+
+impl MetadataScan {
+    /// Starts probing.
+    pub fn start(
+        entries: Vec<(PlaylistEntryId, Location)>,
+        chain: MetadataChain,
+        cache: AnalysisCache,
+    ) -> Self {
+        let (scanned_tx, scanned) = mpsc::channel();
+        let (measured_tx, measured) = mpsc::channel();
+        let mut scan = Self {
+            queue: Default::default(),
+            unmeasured: Default::default(),
+            chain,
+            cache,
+            scanned_tx,
+            scanned,
+            measured_tx,
+            measured,
+            cancel: Arc::new(AtomicBool::new(false)),
+            workers: Vec::new(),
+        };
+        scan.extend(entries);
+        scan
+    }
+
+    /// Adds more entries to probe after the ones already waiting, such as queued tracks.
+    pub fn extend(&mut self, entries: Vec<(PlaylistEntryId, Location)>) {
+        let waiting = {
+            let mut queue = self.queue.lock().unwrap();
+            queue.extend(entries);
+            queue.len()
+        };
+        self.workers.retain(|worker| !worker.is_finished());
+        let wanted = thread::available_parallelism()
+            .map(|count| count.get())
+            .unwrap_or(1)
+            .min(MAX_WORKERS)
+            .min(waiting);
+        log::info!("probing {waiting} playlist entries on {wanted} threads");
+        for worker in self.workers.len()..wanted {
+            let spawned = thread::Builder::new()
+                .name(format!("metadata-scan-{worker}"))
+                .spawn({
+                    let worker = Worker {
+                        queue: self.queue.clone(),
+                        unmeasured: self.unmeasured.clone(),
+                        chain: self.chain.clone(),
+                        cache: self.cache.clone(),
+                        scanned_tx: self.scanned_tx.clone(),
+                        measured_tx: self.measured_tx.clone(),
+                        cancel: self.cancel.clone(),
+                    };
+                    move || worker.run()
+                });
+            match spawned {
+                Ok(handle) => self.workers.push(handle),
+                Err(err) => log::error!("failed to start a metadata scan thread: {err}"),
+            }
+        }
+    }
+
+    /// Returns the entries probed since the last call.
+    pub fn take_scanned(&self) -> Vec<ScannedEntry> {
+        self.scanned.try_iter().collect()
+    }
+
+    /// Returns the dynamic ranges measured since the last call.
+    pub fn take_measured(&self) -> Vec<(PlaylistEntryId, DynamicRange)> {
+        self.measured.try_iter().collect()
+    }
+
+    /// True once every entry has been probed and measured.
+    pub fn is_finished(&self) -> bool {
+        self.workers.iter().all(JoinHandle::is_finished)
+    }
+
+    /// Cancels the probe and waits for its threads to exit.
+    pub fn stop(mut self) {
+        self.cancel.store(true, Ordering::Relaxed);
+        for worker in self.workers.drain(..) {
+            let _ = worker.join();
+        }
+    }
+}
+
+impl Drop for MetadataScan {
+    fn drop(&mut self) {
+        self.cancel.store(true, Ordering::Relaxed);
+    }
+}
+
+/// What each of the scan's threads shares with the others.
+struct Worker {
+    queue: Arc<Mutex<VecDeque<(PlaylistEntryId, Location)>>>,
+    unmeasured: Arc<Mutex<VecDeque<(PlaylistEntryId, Location)>>>,
+    chain: MetadataChain,
+    cache: AnalysisCache,
+    scanned_tx: Sender<ScannedEntry>,
+    measured_tx: Sender<(PlaylistEntryId, DynamicRange)>,
+    cancel: Arc<AtomicBool>,
+}
+
+impl Worker {
+    /// Probes entries until there are none left, then measures the probed ones.
+    fn run(self) {
+        let mut measured_any = false;
+        while !self.cancel.load(Ordering::Relaxed) {
+            let next = self.queue.lock().unwrap().pop_front();
+            if let Some((id, location)) = next {
+                if !self.probe(id, location) {
+                    break;
+                }
+                continue;
+            }
+            let Some((id, location)) = self.unmeasured.lock().unwrap().pop_front() else {
+                break;
+            };
+            match measure_dynamic_range(location.clone(), &self.cancel) {
+                // A cancelled measurement stops partway through the file
+                Ok(_) if self.cancel.load(Ordering::Relaxed) => break,
+                Ok(Some(dynamic_range)) => {
+                    self.cache.update(&location, |analysis| {
+                        analysis.dynamic_range = Some(dynamic_range)
+                    });
+                    measured_any = true;
+                    if self.measured_tx.send((id, dynamic_range)).is_err() {
+                        break;
+                    }
+                }
+                Ok(None) => {}
+                Err(err) => log::warn!("failed to measure the dynamic range of {location}: {err}"),
+            }
+        }
+        if measured_any {
+            if let Err(err) = self.cache.save() {
+                log::warn!("{err}");
+            }
+        }
+    }
+
+    /// Probes an entry, and returns false once the scan has been dropped.
+    fn probe(&self, id: PlaylistEntryId, location: Location) -> bool {
+        // Missing files are reported by the player if they're played
+        if !location.as_path().is_some_and(|path| path.is_file()) {
+            return true;
+        }
+        let probed = match source::probe(&location) {
+            Ok(mut probed) => {
+                probed.metadata = self.chain.read_with_embedded(&location, probed.metadata);
+                Ok(probed)
+            }
+            Err(err) => {
+                log::warn!("failed to probe {location}: {err}");
+                Err(err.to_playback_error(&location))
+            }
+        };
+        // Files with chapters are split into virtual tracks, which aren't measured
+        let measure = probed
+            .as_ref()
+            .is_ok_and(|probed| probed.chapters.len() < 2)
+            && self
+                .cache
+                .get(&location)
+                .and_then(|analysis| analysis.dynamic_range)
+                .is_none();
+        if measure {
+            self.unmeasured.lock().unwrap().push_back((id, location));
+        }
+        self.scanned_tx.send(ScannedEntry { id, probed }).is_ok()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::time::{Duration, Instant};
+
+    #[test]
+    fn scan_test_files() {
+        let cache = AnalysisCache::in_memory();
+        let mut scan = MetadataScan::start(
+            vec![
+                (
+                    PlaylistEntryId(0),
+                    Location::path(
+                        "../test-data/melodic_a_minor/melodic_a_minor_2chan_44100hz_11s.ogg",
+                    ),
+                ),
+                (
+                    PlaylistEntryId(1),
+                    Location::path("../test-data/melodic_a_minor/ATTRIBUTION"),
+                ),
+                (
+                    PlaylistEntryId(3),
+                    Location::path("../test-data/does-not-exist.ogg"),
+                ),
+            ],
+            MetadataChain::default(),
+            cache.clone(),
+        );
+        scan.extend(vec![(
+            PlaylistEntryId(2),
+            Location::path("../test-data/melodic_a_minor/melodic_a_minor_1chan_44100hz_6s.ogg"),
+        )]);
+        let started = Instant::now();
+        let mut scanned = Vec::new();
+        while scanned.len() < 3 {
+            scanned.extend(scan.take_scanned());
+            assert!(
+                started.elapsed() < Duration::from_secs(30),
+                "scan took too long"
+            );
+            thread::sleep(Duration::from_millis(10));
+        }
+        while !scan.is_finished() {
+            assert!(
+                started.elapsed() < Duration::from_secs(30),
+                "scan took too long to finish"
+            );
+            thread::sleep(Duration::from_millis(10));
+        }
+        scanned.sort_by_key(|entry| entry.id.0);
+        let mut measured = scan.take_measured();
+        measured.sort_by_key(|(id, _)| id.0);
+        assert_eq!(
+            vec![PlaylistEntryId(0), PlaylistEntryId(2)],
+            measured.iter().map(|(id, _)| *id).collect::<Vec<_>>()
+        );
+        let cached = cache.get(&Location::path(
+            "../test-data/melodic_a_minor/melodic_a_minor_2chan_44100hz_11s.ogg",
+        ));
+        assert_eq!(
+            Some(measured[0].1),
+            cached.and_then(|analysis| analysis.dynamic_range)
+        );
+        assert_eq!(
+            vec![PlaylistEntryId(0), PlaylistEntryId(1), PlaylistEntryId(2)],
+            scanned.iter().map(|entry| entry.id).collect::<Vec<_>>()
+        );
+        assert!(scanned[1].probed.is_err());
+        for (entry, expected) in [&scanned[0], &scanned[2]].into_iter().zip([11.0, 6.0]) {
+            let probed = entry.probed.as_ref().expect("probed");
+            let duration = probed.duration.expect("duration").as_secs_f64();
+            assert!(
+                (duration - expected).abs() < 0.5,
+                "unexpected duration: {duration}"
+            );
+        }
+    }
+}
@@ -0,0 +1,122 @@
+// This file is part of Millenium Player.
+// Copyright (C) 2023 John DiSanti.
+//
+// Millenium Player is free software: you can redistribute it and/or modify it under the terms of
+// the GNU General Public License as published by the Free Software Foundation, either version 3 of
+// the License, or (at your option) any later version.
+//
+// Millenium Player is distributed in the hope that it will be useful, but WITHOUT ANY WARRANTY;
+// without even the implied warranty of MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE. See
+// the GNU General Public License for more details.
+//
+// You should have received a copy of the GNU General Public License along with Millenium Player.
+// If not, see <https://www.gnu.org/licenses/>.
+
+use crate::location::Location;
+use millenium_post_office::types::StreamVariant;
+
+/// A stream only keeps playing smoothly if the network is faster than its bitrate, so a
+/// variant is switched away from if the measured throughput falls below this fraction of it.
+const MIN_THROUGHPUT_RATIO: f64 = 0.75;
+
+/// One of the qualities that a stream is offered in.
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub(crate) struct Variant {
+    pub location: Location,
+    /// Bitrate in bits per second, if the station advertises it.
+    pub bandwidth: Option<u32>,
+}
+
+impl Variant {
+    pub fn new(location: Location, bandwidth: Option<u32>) -> Self {
+        Self {
+            location,
+            bandwidth,
+        }
+    }
+}
+
+/// Describes the variants for the UI.
+pub(super) fn describe(variants: &[Variant]) -> Vec<StreamVariant> {
+    variants
+        .iter()
+        .enumerate()
+        .map(|(index, variant)| StreamVariant {
+            label: match variant.bandwidth {
+                Some(bandwidth) => format!("{} kbps", bandwidth / 1000),
+                None => format!("Stream {}", index + 1),
+            },
+            bandwidth: variant.bandwidth,
+        })
+        .collect()
+}
+
+/// Picks the best variant that the measured throughput can keep up with.
+///
+/// Without a measurement, the first variant is used since playlists list their default first.
+/// If none of the variants fit, the one with the lowest bitrate is used.
+pub(super) fn choose(variants: &[Variant], throughput: Option<u64>) -> usize {
+    let Some(throughput) = throughput else {
+        return 0;
+    };
+    let known = || {
+        variants
+            .iter()
+            .enumerate()
+            .filter_map(|(index, variant)| Some((index, variant.bandwidth? as u64)))
+    };
+    known()
+        .filter(|&(_, bandwidth)| bandwidth <= throughput)
+        .max_by_key(|&(_, bandwidth)| bandwidth)
+        .or_else(|| known().min_by_key(|&(_, bandwidth)| bandwidth))
+        .map(|(index, _)| index)
+        .unwrap_or(0)
+}
+
+/// Picks a lower variant if the throughput measured while playing `active` can't keep up with it.
+pub(super) fn adapt(variants: &[Variant], active: usize, throughput: u64) -> usize {
+    match variants[active].bandwidth {
+        Some(bandwidth) if (throughput as f64) < bandwidth as f64 * MIN_THROUGHPUT_RATIO => {
+            let lower = choose(variants, Some(throughput));
+            match variants[lower].bandwidth {
+                Some(lower_bandwidth) if lower_bandwidth < bandwidth => lower,
+                _ => active,
+            }
+        }
+        _ => active,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn variants(bandwidths: &[Option<u32>]) -> Vec<Variant> {
+        bandwidths
+            .iter()
+            .enumerate()
+            .map(|(index, &bandwidth)| {
+                Variant::new(Location::path(format!("{index}.mp3")), bandwidth)
+            })
+            .collect()
+    }
+
+    #[test]
+    fn choose_variant() {
+        let variants = variants(&[Some(128_000), Some(320_000), Some(64_000), None]);
+        assert_eq!(0, choose(&variants, None));
+        assert_eq!(1, choose(&variants, Some(1_000_000)));
+        assert_eq!(0, choose(&variants, Some(200_000)));
+        assert_eq!(2, choose(&variants, Some(10_000)));
+    }
+
+    #[test]
+    fn adapt_to_throughput() {
+        let variants = variants(&[Some(128_000), Some(320_000), Some(64_000)]);
+        // Keeping up, even if only just
+        assert_eq!(1, adapt(&variants, 1, 300_000));
+        assert_eq!(0, adapt(&variants, 1, 200_000));
+        // Already as low as it goes
+        assert_eq!(2, adapt(&variants, 2, 10_000));
+    }
+}
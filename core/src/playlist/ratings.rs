@@ -0,0 +1,217 @@
+// This file is part of Millenium Player.
+// Copyright (C) 2023 John DiSanti.
+//
+// Millenium Player is free software: you can redistribute it and/or modify it under the terms of
+// the GNU General Public License as published by the Free Software Foundation, either version 3 of
+// the License, or (at your option) any later version.
+//
+// Millenium Player is distributed in the hope that it will be useful, but WITHOUT ANY WARRANTY;
+// without even the implied warranty of MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE. See
+// the GNU General Public License for more details.
+//
+// You should have received a copy of the GNU General Public License along with Millenium Player.
+// If not, see <https://www.gnu.org/licenses/>.
+
+use crate::location::Location;
+use millenium_post_office::types::TrackRating;
+use std::{collections::HashMap, fs, io, path::PathBuf, time::Duration};
+
+#[derive(Debug, thiserror::Error)]
+pub enum TrackRatingsError {
+    #[error("failed to save track ratings {path:?}: {source}")]
+    Write { path: PathBuf, source: io::Error },
+}
+
+#[derive(Debug, serde::Serialize, serde::Deserialize)]
+struct RatedTrack {
+    location: Location,
+    start: Duration,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    rating: Option<TrackRating>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    intro_skip: Option<Duration>,
+}
+
+/// What the user set on a track.
+#[derive(Copy, Clone, Debug, Default, PartialEq)]
+struct TrackSettings {
+    rating: Option<TrackRating>,
+    intro_skip: Option<Duration>,
+}
+
+/// Tracks the user loved or banned, and the intro skips set on them, which are kept
+/// between runs.
+///
+/// Tracks are identified by their location and where they start in it, so that virtual
+/// tracks sharing a file are rated separately.
+#[derive(Debug, Default)]
+pub struct TrackRatings {
+    path: Option<PathBuf>,
+    tracks: HashMap<(Location, Duration), TrackSettings>,
+}
+
+impl TrackRatings {
+    /// Ratings that are only kept in memory.
+    pub fn in_memory() -> Self {
+        Self::default()
+    }
+
+    /// Loads the ratings file at the given path. Nothing is rated if the file doesn't exist
+    /// or can't be read.
+    pub fn load(path: impl Into<PathBuf>) -> Self {
+        let path = path.into();
+        let rated: Vec<RatedTrack> = match fs::read_to_string(&path) {
+            Ok(contents) => serde_json::from_str(&contents).unwrap_or_else(|err| {
+                log::warn!("discarding unreadable track ratings {path:?}: {err}");
+                Vec::new()
+            }),
+            Err(err) => {
+                if err.kind() != io::ErrorKind::NotFound {
+                    log::warn!("failed to read track ratings {path:?}: {err}");
+                }
+                Vec::new()
+            }
+        };
+        Self {
+            path: Some(path),
+            tracks: rated
+                .into_iter()
+                .map(|track| {
+                    let settings = TrackSettings {
+                        rating: track.rating,
+                        intro_skip: track.intro_skip,
+                    };
+                    ((track.location, track.start), settings)
+                })
+                .collect(),
+        }
+    }
+
+    pub fn get(&self, location: &Location, start: Duration) -> Option<TrackRating> {
+        self.tracks.get(&(location.clone(), start))?.rating
+    }
+
+    /// Intro skip set on the track, which overrides the folder and global intro skips.
+    pub fn intro_skip(&self, location: &Location, start: Duration) -> Option<Duration> {
+        self.tracks.get(&(location.clone(), start))?.intro_skip
+    }
+
+    pub fn is_banned(&self, location: &Location, start: Duration) -> bool {
+        self.get(location, start) == Some(TrackRating::Banned)
+    }
+
+    /// Rates a track, or clears its rating with `None`. Returns false if the rating didn't change.
+    pub fn set(
+        &mut self,
+        location: &Location,
+        start: Duration,
+        rating: Option<TrackRating>,
+    ) -> bool {
+        self.update(location, start, |settings| settings.rating = rating)
+    }
+
+    /// Sets the intro skip of a track, or clears it with `None`. Returns false if it didn't
+    /// change.
+    pub fn set_intro_skip(
+        &mut self,
+        location: &Location,
+        start: Duration,
+        intro_skip: Option<Duration>,
+    ) -> bool {
+        self.update(location, start, |settings| settings.intro_skip = intro_skip)
+    }
+
+    fn update(
+        &mut self,
+        location: &Location,
+        start: Duration,
+        update: impl FnOnce(&mut TrackSettings),
+    ) -> bool {
+        let key = (location.clone(), start);
+        let previous = self.tracks.get(&key).copied().unwrap_or_default();
+        let mut settings = previous;
+        update(&mut settings);
+        if settings == TrackSettings::default() {
+            self.tracks.remove(&key);
+        } else {
+            self.tracks.insert(key, settings);
+        }
+        previous != settings
+    }
+
+    /// Writes the ratings file, unless the ratings are only kept in memory.
+    pub fn save(&self) -> Result<(), TrackRatingsError> {
+        let Some(path) = &self.path else {
+            return Ok(());
+        };
+        let mut rated: Vec<_> = self
+            .tracks
+            .iter()
+            .map(|((location, start), settings)| RatedTrack {
+                location: location.clone(),
+                start: *start,
+                rating: settings.rating,
+                intro_skip: settings.intro_skip,
+            })
+            .collect();
+        rated.sort_by(|a, b| (&a.location, a.start).cmp(&(&b.location, b.start)));
+        let contents = serde_json::to_string(&rated).expect("serializable");
+        let write = || {
+            if let Some(dir) = path.parent() {
+                fs::create_dir_all(dir)?;
+            }
+            fs::write(path, contents)
+        };
+        write().map_err(|source| TrackRatingsError::Write {
+            path: path.clone(),
+            source,
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::str::FromStr;
+
+    #[test]
+    fn ratings_are_saved() {
+        let path = std::env::temp_dir().join(format!("track-ratings-{}.json", std::process::id()));
+        let one = Location::path("one.ogg");
+        let station = Location::from_str("http://example.com/radio.mp3").unwrap();
+
+        let mut ratings = TrackRatings::load(&path);
+        assert!(ratings.set(&one, Duration::ZERO, Some(TrackRating::Loved)));
+        assert!(!ratings.set(&one, Duration::ZERO, Some(TrackRating::Loved)));
+        assert!(ratings.set(&one, Duration::from_secs(60), Some(TrackRating::Banned)));
+        assert!(ratings.set(&station, Duration::ZERO, Some(TrackRating::Banned)));
+        assert!(ratings.set(&station, Duration::ZERO, None));
+        let skip = Some(Duration::from_secs(45));
+        assert!(ratings.set_intro_skip(&one, Duration::ZERO, skip));
+        assert!(!ratings.set_intro_skip(&one, Duration::ZERO, skip));
+        assert!(ratings.set_intro_skip(&station, Duration::ZERO, skip));
+        ratings.save().unwrap();
+
+        let ratings = TrackRatings::load(&path);
+        let _ = fs::remove_file(&path);
+        assert_eq!(Some(TrackRating::Loved), ratings.get(&one, Duration::ZERO));
+        assert!(!ratings.is_banned(&one, Duration::ZERO));
+        assert!(ratings.is_banned(&one, Duration::from_secs(60)));
+        assert_eq!(None, ratings.get(&station, Duration::ZERO));
+        assert_eq!(skip, ratings.intro_skip(&one, Duration::ZERO));
+        assert_eq!(None, ratings.intro_skip(&one, Duration::from_secs(60)));
+        assert_eq!(skip, ratings.intro_skip(&station, Duration::ZERO));
+    }
+
+    #[test]
+    fn clearing_everything_forgets_the_track() {
+        let one = Location::path("one.ogg");
+        let mut ratings = TrackRatings::in_memory();
+        ratings.set(&one, Duration::ZERO, Some(TrackRating::Loved));
+        ratings.set_intro_skip(&one, Duration::ZERO, Some(Duration::from_secs(5)));
+        ratings.set(&one, Duration::ZERO, None);
+        assert_eq!(1, ratings.tracks.len());
+        ratings.set_intro_skip(&one, Duration::ZERO, None);
+        assert!(ratings.tracks.is_empty());
+    }
+}
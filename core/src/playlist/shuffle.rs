@@ -0,0 +1,159 @@
+// This file is part of Millenium Player.
+// Copyright (C) 2023 John DiSanti.
+//
+// Millenium Player is free software: you can redistribute it and/or modify it under the terms of
+// the GNU General Public License as published by the Free Software Foundation, either version 3 of
+// the License, or (at your option) any later version.
+//
+// Millenium Player is distributed in the hope that it will be useful, but WITHOUT ANY WARRANTY;
+// without even the implied warranty of MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE. See
+// the GNU General Public License for more details.
+//
+// You should have received a copy of the GNU General Public License along with Millenium Player.
+// If not, see <https://www.gnu.org/licenses/>.
+
+use super::PlaylistEntryId;
+use std::time::SystemTime;
+
+/// Order that playlist entries are played in while shuffling.
+///
+/// The order only depends on the seed and the groups it was built from, so sharing the seed
+/// reproduces it for the same playlist.
+pub(crate) struct ShuffleOrder {
+    seed: u64,
+    order: Vec<PlaylistEntryId>,
+    /// Where playback is in the order, or `None` if nothing from it has played yet.
+    position: Option<usize>,
+}
+
+impl ShuffleOrder {
+    /// Shuffles the order of the groups while keeping the entries within each group in order.
+    ///
+    /// If `first` is given, its group is moved to the front and playback carries on from it,
+    /// so that turning on shuffle doesn't interrupt what's playing.
+    pub fn new(
+        seed: u64,
+        mut groups: Vec<Vec<PlaylistEntryId>>,
+        first: Option<PlaylistEntryId>,
+    ) -> Self {
+        let mut rng = SplitMix64(seed);
+        // Fisher-Yates
+        for i in (1..groups.len()).rev() {
+            groups.swap(i, rng.below(i as u64 + 1) as usize);
+        }
+        if let Some(first_group) =
+            first.and_then(|first| groups.iter().position(|group| group.contains(&first)))
+        {
+            let group = groups.remove(first_group);
+            groups.insert(0, group);
+        }
+        let order: Vec<PlaylistEntryId> = groups.into_iter().flatten().collect();
+        let position = first.and_then(|first| order.iter().position(|&id| id == first));
+        Self {
+            seed,
+            order,
+            position,
+        }
+    }
+
+    /// Picks a seed that's short enough to share.
+    pub fn random_seed() -> u64 {
+        let nanos = SystemTime::now()
+            .duration_since(SystemTime::UNIX_EPOCH)
+            .map(|elapsed| elapsed.as_nanos() as u64)
+            .unwrap_or_default();
+        SplitMix64(nanos).next() % 1_000_000
+    }
+
+    pub fn seed(&self) -> u64 {
+        self.seed
+    }
+
+    /// Moves the position to the given entry if it's in the order. Entries that aren't,
+    /// like ones from the play queue, leave the position where it was.
+    pub fn played(&mut self, id: PlaylistEntryId) {
+        if let Some(position) = self.order.iter().position(|&entry| entry == id) {
+            self.position = Some(position);
+        }
+    }
+
+    /// Entries after the current position, in the order they'll play.
+    pub fn upcoming(&self) -> impl Iterator<Item = PlaylistEntryId> + '_ {
+        let start = self.position.map(|position| position + 1).unwrap_or(0);
+        self.order[start.min(self.order.len())..].iter().copied()
+    }
+
+    /// Entries before the current position, most recent first.
+    pub fn previous(&self) -> impl Iterator<Item = PlaylistEntryId> + '_ {
+        let end = self.position.unwrap_or(0);
+        self.order[..end].iter().rev().copied()
+    }
+}
+
+/// Small, fast generator that gives the same numbers for a seed on every platform.
+struct SplitMix64(u64);
+
+impl SplitMix64 {
+    fn next(&mut self) -> u64 {
+        self.0 = self.0.wrapping_add(0x9E37_79B9_7F4A_7C15);
+        let mut z = self.0;
+        z = (z ^ (z >> 30)).wrapping_mul(0xBF58_476D_1CE4_E5B9);
+        z = (z ^ (z >> 27)).wrapping_mul(0x94D0_49BB_1331_11EB);
+        z ^ (z >> 31)
+    }
+
+    /// Returns a number below `bound`. The slight bias doesn't matter for shuffling.
+    fn below(&mut self, bound: u64) -> u64 {
+        self.next() % bound
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn ids(ids: &[usize]) -> Vec<PlaylistEntryId> {
+        ids.iter().map(|&id| PlaylistEntryId(id)).collect()
+    }
+
+    fn singles(count: usize) -> Vec<Vec<PlaylistEntryId>> {
+        (0..count).map(|id| ids(&[id])).collect()
+    }
+
+    #[test]
+    fn same_seed_same_order() {
+        let order = |seed| ShuffleOrder::new(seed, singles(20), None).order;
+        assert_eq!(order(42), order(42));
+        assert_ne!(order(42), order(43));
+
+        let mut sorted = order(42);
+        sorted.sort_by_key(|id| id.0);
+        assert_eq!(ids(&(0..20).collect::<Vec<_>>()), sorted);
+    }
+
+    #[test]
+    fn groups_stay_in_order() {
+        let groups = vec![ids(&[0, 1, 2]), ids(&[3, 4]), ids(&[5, 6, 7, 8])];
+        for seed in 0..10 {
+            let order = ShuffleOrder::new(seed, groups.clone(), None).order;
+            for group in &groups {
+                let start = order.iter().position(|id| *id == group[0]).unwrap();
+                assert_eq!(group[..], order[start..start + group.len()]);
+            }
+        }
+    }
+
+    #[test]
+    fn carry_on_from_first() {
+        let groups = vec![ids(&[0, 1, 2]), ids(&[3, 4]), ids(&[5, 6])];
+        let mut shuffle = ShuffleOrder::new(7, groups, Some(PlaylistEntryId(1)));
+        assert_eq!(ids(&[0, 1, 2]), shuffle.order[0..3]);
+        assert_eq!(ids(&[0]), shuffle.previous().collect::<Vec<_>>());
+        assert_eq!(Some(PlaylistEntryId(2)), shuffle.upcoming().next());
+
+        shuffle.played(PlaylistEntryId(100));
+        assert_eq!(Some(PlaylistEntryId(2)), shuffle.upcoming().next());
+        shuffle.played(shuffle.order[6]);
+        assert_eq!(None, shuffle.upcoming().next());
+    }
+}
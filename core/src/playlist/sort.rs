@@ -0,0 +1,215 @@
+// This file is part of Millenium Player.
+// Copyright (C) 2023 John DiSanti.
+//
+// Millenium Player is free software: you can redistribute it and/or modify it under the terms of
+// the GNU General Public License as published by the Free Software Foundation, either version 3 of
+// the License, or (at your option) any later version.
+//
+// Millenium Player is distributed in the hope that it will be useful, but WITHOUT ANY WARRANTY;
+// without even the implied warranty of MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE. See
+// the GNU General Public License for more details.
+//
+// You should have received a copy of the GNU General Public License along with Millenium Player.
+// If not, see <https://www.gnu.org/licenses/>.
+
+use super::album::natural_cmp;
+use crate::metadata::Metadata;
+use millenium_post_office::frontend::{message::PlaylistSortKey, settings::Sorting};
+use std::cmp::Ordering;
+
+/// Leading articles for the languages that sorting knows about.
+const ARTICLES: &[(&str, &[&str])] = &[
+    ("de", &["der", "die", "das", "ein", "eine"]),
+    ("en", &["the", "a", "an"]),
+    ("es", &["el", "la", "los", "las", "un", "una"]),
+    ("fr", &["le", "la", "les", "l'", "un", "une"]),
+    (
+        "it",
+        &["il", "lo", "la", "i", "gli", "le", "l'", "un", "una"],
+    ),
+    ("nl", &["de", "het", "een"]),
+    ("pt", &["o", "a", "os", "as", "um", "uma"]),
+];
+
+/// Options for comparing names when sorting.
+#[derive(Clone, Debug)]
+pub struct SortOptions {
+    articles: &'static [&'static str],
+}
+
+impl Default for SortOptions {
+    fn default() -> Self {
+        Self::from(&Sorting::default())
+    }
+}
+
+impl From<&Sorting> for SortOptions {
+    fn from(sorting: &Sorting) -> Self {
+        // Match "en-US" and "en_GB" to "en"
+        let language = sorting
+            .locale
+            .split(['-', '_'])
+            .next()
+            .unwrap_or_default()
+            .to_ascii_lowercase();
+        let articles = match ARTICLES.iter().find(|(lang, _)| *lang == language) {
+            _ if !sorting.ignore_articles => &[][..],
+            Some((_, articles)) => articles,
+            None => {
+                log::warn!(
+                    "no leading articles known for locale \"{}\"",
+                    sorting.locale
+                );
+                &[][..]
+            }
+        };
+        Self { articles }
+    }
+}
+
+impl SortOptions {
+    /// Returns the name to sort by, preferring the sort tag when there is one.
+    pub fn sort_name<'a>(&self, name: &'a str, sort_tag: Option<&'a str>) -> &'a str {
+        if let Some(sort_tag) = sort_tag.filter(|tag| !tag.trim().is_empty()) {
+            return sort_tag.trim();
+        }
+        let name = name.trim();
+        for article in self.articles {
+            // Elided articles such as "L'" are attached to the next word
+            let separator_len = if article.ends_with('\'') { 0 } else { 1 };
+            let prefix_len = article.len() + separator_len;
+            if name.len() > prefix_len
+                && name.is_char_boundary(article.len())
+                && name[..article.len()].eq_ignore_ascii_case(article)
+                && (separator_len == 0 || name[article.len()..].starts_with(' '))
+            {
+                return name[prefix_len..].trim_start();
+            }
+        }
+        name
+    }
+
+    /// Compares two entries' metadata by the given key. Entries without the tag sort last.
+    pub(super) fn compare(
+        &self,
+        key: PlaylistSortKey,
+        a: Option<&Metadata>,
+        b: Option<&Metadata>,
+    ) -> Ordering {
+        let (a, b) = match (a, b) {
+            (Some(a), Some(b)) => (a, b),
+            (Some(_), None) => return Ordering::Less,
+            (None, Some(_)) => return Ordering::Greater,
+            (None, None) => return Ordering::Equal,
+        };
+        let artist = |meta: &'_ Metadata| {
+            meta.album_artist
+                .as_deref()
+                .map(|name| self.sort_name(name, meta.album_artist_sort.as_deref()))
+                .or_else(|| {
+                    let name = meta.artist.as_deref()?;
+                    Some(self.sort_name(name, meta.artist_sort.as_deref()))
+                })
+                .map(str::to_owned)
+        };
+        let album = |meta: &'_ Metadata| {
+            let name = meta.album.as_deref()?;
+            Some(self.sort_name(name, meta.album_sort.as_deref()).to_owned())
+        };
+        let title = |meta: &'_ Metadata| {
+            let name = meta.track_title.as_deref()?;
+            Some(
+                self.sort_name(name, meta.track_title_sort.as_deref())
+                    .to_owned(),
+            )
+        };
+        let position = |meta: &'_ Metadata| {
+            (
+                meta.parsed_disc_number().unwrap_or(1),
+                meta.parsed_track_number(),
+            )
+        };
+        match key {
+            PlaylistSortKey::Artist => compare_names(artist(a), artist(b))
+                .then_with(|| compare_names(album(a), album(b)))
+                .then_with(|| position(a).cmp(&position(b))),
+            PlaylistSortKey::Album => {
+                compare_names(album(a), album(b)).then_with(|| position(a).cmp(&position(b)))
+            }
+            PlaylistSortKey::Title => compare_names(title(a), title(b)),
+        }
+    }
+}
+
+/// Compares names naturally, placing missing names last.
+fn compare_names(a: Option<String>, b: Option<String>) -> Ordering {
+    match (a, b) {
+        (Some(a), Some(b)) => natural_cmp(&a, &b),
+        (Some(_), None) => Ordering::Less,
+        (None, Some(_)) => Ordering::Greater,
+        (None, None) => Ordering::Equal,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn options(ignore_articles: bool, locale: &str) -> SortOptions {
+        SortOptions::from(&Sorting {
+            ignore_articles,
+            locale: locale.into(),
+        })
+    }
+
+    #[test]
+    fn ignore_leading_articles() {
+        let english = options(true, "en-US");
+        assert_eq!("Beatles", english.sort_name("The Beatles", None));
+        assert_eq!(
+            "Theory of a Deadman",
+            english.sort_name("Theory of a Deadman", None)
+        );
+        assert_eq!("The", english.sort_name("The", None));
+        assert_eq!("Le Tigre", english.sort_name("Le Tigre", None));
+
+        let french = options(true, "fr");
+        assert_eq!("Tigre", french.sort_name("Le Tigre", None));
+        assert_eq!("Impératrice", french.sort_name("L'Impératrice", None));
+
+        let disabled = options(false, "en");
+        assert_eq!("The Beatles", disabled.sort_name("The Beatles", None));
+    }
+
+    #[test]
+    fn prefer_sort_tags() {
+        let english = options(true, "en");
+        assert_eq!(
+            "Beatles, The",
+            english.sort_name("The Beatles", Some("Beatles, The"))
+        );
+        assert_eq!("Beatles", english.sort_name("The Beatles", Some(" ")));
+    }
+
+    #[test]
+    fn compare_by_key() {
+        let english = SortOptions::default();
+        let meta = |artist: &str, album: &str, track: &str| Metadata {
+            artist: Some(artist.into()),
+            album: Some(album.into()),
+            track_number: Some(track.into()),
+            ..Default::default()
+        };
+        let beatles = meta("The Beatles", "Abbey Road", "2");
+        let beatles_first = meta("The Beatles", "Abbey Road", "1");
+        let cure = meta("Cure", "Disintegration", "1");
+
+        let by_artist = |a, b| english.compare(PlaylistSortKey::Artist, Some(a), Some(b));
+        assert_eq!(Ordering::Less, by_artist(&beatles, &cure));
+        assert_eq!(Ordering::Greater, by_artist(&beatles, &beatles_first));
+        assert_eq!(
+            Ordering::Less,
+            english.compare(PlaylistSortKey::Album, Some(&cure), None)
+        );
+    }
+}
@@ -0,0 +1,161 @@
+// This file is part of Millenium Player.
+// Copyright (C) 2023 John DiSanti.
+//
+// Millenium Player is free software: you can redistribute it and/or modify it under the terms of
+// the GNU General Public License as published by the Free Software Foundation, either version 3 of
+// the License, or (at your option) any later version.
+//
+// Millenium Player is distributed in the hope that it will be useful, but WITHOUT ANY WARRANTY;
+// without even the implied warranty of MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE. See
+// the GNU General Public License for more details.
+//
+// You should have received a copy of the GNU General Public License along with Millenium Player.
+// If not, see <https://www.gnu.org/licenses/>.
+
+use millenium_core::metadata::EmbeddedImage;
+use millenium_post_office::frontend::state::AlbumArt;
+use std::{
+    collections::VecDeque,
+    fs,
+    path::{Path, PathBuf},
+    sync::Arc,
+};
+
+/// Most albums whose art is kept in memory.
+const CACHE_CAPACITY: usize = 16;
+/// Image names that are looked for next to a track, after the album name, in order.
+const SIDECAR_NAMES: &[&str] = &["cover", "folder", "front"];
+const SIDECAR_EXTENSIONS: &[(&str, &str)] = &[
+    ("jpg", "image/jpeg"),
+    ("jpeg", "image/jpeg"),
+    ("png", "image/png"),
+];
+
+/// Folder and album name that an image file's art is remembered for.
+type AlbumKey = (PathBuf, Option<String>);
+
+/// Finds the album art for tracks, falling back to image files next to tracks without
+/// embedded art.
+///
+/// Image files are remembered for the most recently played albums so that they aren't read
+/// from disk for every track.
+#[derive(Default)]
+pub struct AlbumArtCache {
+    /// Least recently used first. Albums without an image file are remembered as `None`.
+    albums: VecDeque<(AlbumKey, Option<AlbumArt>)>,
+    next_id: u64,
+}
+
+impl AlbumArtCache {
+    /// Returns the embedded art if there is any, or else an image file in the track's folder
+    /// if it's a file.
+    pub fn art_for(
+        &mut self,
+        track: Option<&Path>,
+        album: Option<&str>,
+        embedded: Option<&EmbeddedImage>,
+    ) -> Option<AlbumArt> {
+        if let Some(image) = embedded {
+            return Some(AlbumArt {
+                id: self.next_id(),
+                mime_type: image.mime_type.clone(),
+                data: image.data.clone(),
+            });
+        }
+        let folder = track?.parent()?.to_path_buf();
+        let key = (folder, album.map(String::from));
+        if let Some(index) = self.albums.iter().position(|(cached, _)| *cached == key) {
+            let entry = self.albums.remove(index).expect("found above");
+            let art = entry.1.clone();
+            self.albums.push_back(entry);
+            return art;
+        }
+        let art = find_sidecar(&key.0, album).map(|(mime_type, data)| AlbumArt {
+            id: self.next_id(),
+            mime_type: mime_type.into(),
+            data: Arc::new(data),
+        });
+        if self.albums.len() == CACHE_CAPACITY {
+            self.albums.pop_front();
+        }
+        self.albums.push_back((key, art.clone()));
+        art
+    }
+
+    fn next_id(&mut self) -> u64 {
+        self.next_id += 1;
+        self.next_id
+    }
+}
+
+/// Reads the first image file in the folder that's named after the album, or like a cover.
+fn find_sidecar(folder: &Path, album: Option<&str>) -> Option<(&'static str, Vec<u8>)> {
+    let file_names: Vec<String> = fs::read_dir(folder)
+        .ok()?
+        .filter_map(|entry| entry.ok()?.file_name().into_string().ok())
+        .collect();
+    // Album names with separators can't be file names
+    let album = album.filter(|album| !album.contains(['/', '\\']));
+    let stems = album.into_iter().chain(SIDECAR_NAMES.iter().copied());
+    for stem in stems {
+        for (extension, mime_type) in SIDECAR_EXTENSIONS {
+            let wanted = format!("{stem}.{extension}");
+            let found = file_names
+                .iter()
+                .find(|name| name.eq_ignore_ascii_case(&wanted));
+            if let Some(name) = found {
+                match fs::read(folder.join(name)) {
+                    Ok(data) => return Some((mime_type, data)),
+                    Err(err) => log::warn!("failed to read album art {name:?}: {err}"),
+                }
+            }
+        }
+    }
+    None
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::APP_NAME;
+
+    #[test]
+    fn sidecar_images() {
+        let dir = std::env::temp_dir().join(format!("{APP_NAME}-album-art-{}", std::process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        fs::write(dir.join("Folder.PNG"), b"folder").unwrap();
+        fs::write(dir.join("Greatest Hits.jpg"), b"album").unwrap();
+        let track = dir.join("01.flac");
+        let mut cache = AlbumArtCache::default();
+
+        let art = cache
+            .art_for(Some(&track), Some("Greatest Hits"), None)
+            .unwrap();
+        assert_eq!(
+            ("image/jpeg", &b"album"[..]),
+            (&*art.mime_type, &art.data[..])
+        );
+        let art = cache.art_for(Some(&track), Some("B-Sides"), None).unwrap();
+        assert_eq!(
+            ("image/png", &b"folder"[..]),
+            (&*art.mime_type, &art.data[..])
+        );
+        assert_eq!(
+            None,
+            cache.art_for(Some(&dir.join("nested").join("01.flac")), None, None)
+        );
+
+        // The album's art is remembered rather than read again
+        fs::remove_dir_all(&dir).unwrap();
+        let cached = cache.art_for(Some(&track), Some("B-Sides"), None).unwrap();
+        assert_eq!(art, cached);
+
+        let embedded = EmbeddedImage {
+            mime_type: "image/png".into(),
+            tags: Default::default(),
+            data: Arc::new(b"embedded".to_vec()),
+        };
+        let art = cache.art_for(Some(&track), None, Some(&embedded)).unwrap();
+        assert_eq!(&b"embedded"[..], &art.data[..]);
+    }
+}
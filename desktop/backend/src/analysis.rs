@@ -0,0 +1,201 @@
+// This file is part of Millenium Player.
+// Copyright (C) 2023 John DiSanti.
+//
+// Millenium Player is free software: you can redistribute it and/or modify it under the terms of
+// the GNU General Public License as published by the Free Software Foundation, either version 3 of
+// the License, or (at your option) any later version.
+//
+// Millenium Player is distributed in the hope that it will be useful, but WITHOUT ANY WARRANTY;
+// without even the implied warranty of MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE. See
+// the GNU General Public License for more details.
+//
+// You should have received a copy of the GNU General Public License along with Millenium Player.
+// If not, see <https://www.gnu.org/licenses/>.
+
+use millenium_core::player::waveform::spectrum_bin_frequency;
+use millenium_post_office::frontend::state::Waveform;
+use std::{
+    fs::File,
+    io::{self, BufWriter, Write},
+    path::{Path, PathBuf},
+};
+
+/// Width of each bar in exported images.
+const BAR_WIDTH: usize = 16;
+/// Height of each of the spectrum and amplitude graphs in exported images.
+const GRAPH_HEIGHT: usize = 128;
+const BACKGROUND: [u8; 3] = [0x20, 0x21, 0x24];
+const SPECTRUM_COLOR: [u8; 3] = [0x88, 0xcc, 0xff];
+const AMPLITUDE_COLOR: [u8; 3] = [0xff, 0xcc, 0x88];
+
+#[derive(Debug, thiserror::Error)]
+pub enum AnalysisExportError {
+    #[error("failed to write {path:?}: {source}")]
+    Write { path: PathBuf, source: io::Error },
+    #[error("failed to encode {path:?}: {source}")]
+    Encode {
+        path: PathBuf,
+        source: png::EncodingError,
+    },
+}
+
+/// File formats that the audio analysis can be exported to.
+#[derive(Copy, Clone, Debug, Eq, PartialEq)]
+pub enum AnalysisFormat {
+    Csv,
+    Png,
+}
+
+impl AnalysisFormat {
+    /// Picks the format from the file extension, falling back to CSV.
+    pub fn from_path(path: &Path) -> Self {
+        match path.extension().and_then(|ext| ext.to_str()) {
+            Some(ext) if ext.eq_ignore_ascii_case("png") => Self::Png,
+            _ => Self::Csv,
+        }
+    }
+
+    pub fn extension(self) -> &'static str {
+        match self {
+            Self::Csv => "csv",
+            Self::Png => "png",
+        }
+    }
+}
+
+/// Saves a snapshot of the spectrum and amplitude analysis to a file, for documenting
+/// masters or hearing tests.
+pub fn export_analysis(
+    waveform: &Waveform,
+    path: &Path,
+    format: AnalysisFormat,
+) -> Result<(), AnalysisExportError> {
+    let write_err = |source| AnalysisExportError::Write {
+        path: path.into(),
+        source,
+    };
+    let mut file = BufWriter::new(File::create(path).map_err(write_err)?);
+    match format {
+        AnalysisFormat::Csv => write_csv(waveform, &mut file).map_err(write_err)?,
+        AnalysisFormat::Png => {
+            write_png(waveform, &mut file).map_err(|source| AnalysisExportError::Encode {
+                path: path.into(),
+                source,
+            })?
+        }
+    }
+    file.flush().map_err(write_err)
+}
+
+/// Writes one row per bin. The spectrum levels go with the bin's frequency, while the
+/// amplitude bins cover the last second of audio, oldest first.
+fn write_csv(waveform: &Waveform, out: &mut impl Write) -> io::Result<()> {
+    writeln!(out, "bin,frequency_hz,spectrum,amplitude")?;
+    let bin_count = waveform.bin_count();
+    for (bin, (spectrum, amplitude)) in waveform
+        .spectrum
+        .iter()
+        .zip(waveform.amplitude.iter())
+        .enumerate()
+    {
+        let frequency = spectrum_bin_frequency(bin, bin_count);
+        writeln!(out, "{bin},{frequency:.0},{spectrum},{amplitude}")?;
+    }
+    Ok(())
+}
+
+/// Draws the spectrum as bars on top of the amplitude, like the player's visualizer.
+fn write_png(waveform: &Waveform, out: &mut impl Write) -> Result<(), png::EncodingError> {
+    let width = waveform.bin_count() * BAR_WIDTH;
+    let height = GRAPH_HEIGHT * 2;
+    let mut pixels = BACKGROUND.repeat(width * height);
+    let graphs = [
+        (&waveform.spectrum, SPECTRUM_COLOR, 0),
+        (&waveform.amplitude, AMPLITUDE_COLOR, GRAPH_HEIGHT),
+    ];
+    for (levels, color, top) in graphs {
+        for (bin, level) in levels.iter().enumerate() {
+            let bar_height = (level.clamp(0.0, 1.0) * GRAPH_HEIGHT as f32).round() as usize;
+            // Leave a pixel of space between bars so that they can be told apart
+            for x in (bin * BAR_WIDTH)..((bin + 1) * BAR_WIDTH - 1) {
+                for y in (top + GRAPH_HEIGHT - bar_height)..(top + GRAPH_HEIGHT) {
+                    let offset = (y * width + x) * 3;
+                    pixels[offset..offset + 3].copy_from_slice(&color);
+                }
+            }
+        }
+    }
+
+    let mut encoder = png::Encoder::new(out, width as u32, height as u32);
+    encoder.set_color(png::ColorType::Rgb);
+    encoder.set_depth(png::BitDepth::Eight);
+    encoder.write_header()?.write_image_data(&pixels)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn waveform() -> Waveform {
+        Waveform {
+            spectrum: vec![1.0, 0.0, 0.5].into(),
+            amplitude: vec![0.0, 0.25, 2.0].into(),
+        }
+    }
+
+    #[test]
+    fn format_from_path() {
+        assert_eq!(
+            AnalysisFormat::Png,
+            AnalysisFormat::from_path(Path::new("a.PNG"))
+        );
+        assert_eq!(
+            AnalysisFormat::Csv,
+            AnalysisFormat::from_path(Path::new("a.csv"))
+        );
+        assert_eq!(
+            AnalysisFormat::Csv,
+            AnalysisFormat::from_path(Path::new("a"))
+        );
+    }
+
+    #[test]
+    fn csv() {
+        let mut csv = Vec::new();
+        write_csv(&waveform(), &mut csv).unwrap();
+        let csv = String::from_utf8(csv).unwrap();
+        let rows: Vec<_> = csv.lines().collect();
+        assert_eq!("bin,frequency_hz,spectrum,amplitude", rows[0]);
+        assert_eq!("0,20,1,0", rows[1]);
+        assert!(rows[2].ends_with(",0,0.25"));
+        assert!(rows[3].ends_with(",0.5,2"));
+        assert_eq!(4, rows.len());
+    }
+
+    #[test]
+    fn png() {
+        let mut png = Vec::new();
+        write_png(&waveform(), &mut png).unwrap();
+
+        let decoder = png::Decoder::new(png.as_slice());
+        let mut reader = decoder.read_info().unwrap();
+        let mut pixels = vec![0; reader.output_buffer_size()];
+        let info = reader.next_frame(&mut pixels).unwrap();
+        assert_eq!(
+            (3 * BAR_WIDTH as u32, 2 * GRAPH_HEIGHT as u32),
+            (info.width, info.height)
+        );
+
+        let pixel = |x: usize, y: usize| {
+            let offset = (y * info.width as usize + x) * 3;
+            [pixels[offset], pixels[offset + 1], pixels[offset + 2]]
+        };
+        // Full spectrum bar, empty spectrum bar, and the gap between bars
+        assert_eq!(SPECTRUM_COLOR, pixel(0, 0));
+        assert_eq!(BACKGROUND, pixel(BAR_WIDTH, GRAPH_HEIGHT - 1));
+        assert_eq!(BACKGROUND, pixel(BAR_WIDTH - 1, GRAPH_HEIGHT - 1));
+        // Amplitude is clamped to the height of its graph
+        assert_eq!(AMPLITUDE_COLOR, pixel(2 * BAR_WIDTH, GRAPH_HEIGHT));
+        assert_eq!(BACKGROUND, pixel(0, 2 * GRAPH_HEIGHT - 1));
+    }
+}
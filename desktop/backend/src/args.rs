@@ -12,33 +12,70 @@
 // You should have received a copy of the GNU General Public License along with Millenium Player.
 // If not, see <https://www.gnu.org/licenses/>.
 
+use crate::single_instance::ControlCommand;
 use clap::ArgAction;
 use clap::{error::ErrorKind, ArgMatches};
 use millenium_core::location::{Location, ParseLocationError};
-use std::{ffi, str::FromStr};
+use std::{ffi, str::FromStr, time::Duration};
 
 #[derive(Debug)]
 #[cfg_attr(test, derive(Eq, PartialEq))]
 pub enum Mode {
     Simple {
         locations: Vec<Location>,
+        /// Whether directories should be ordered by their disc/track number tags.
+        track_number_ordering: bool,
+        /// Load the locations without starting playback.
+        start_paused: bool,
+        /// Where to start in the first track.
+        start_position: Option<Duration>,
     },
     Library {
         storage_path: Option<Location>,
         audio_path: Option<Location>,
     },
+    /// Send a playback command to the instance that's already running.
+    Control { command: ControlCommand },
+}
+
+/// Parsed command line arguments.
+#[derive(Debug)]
+pub struct Args {
+    pub mode: Mode,
+    /// Start with audio processing bypassed, the default output device, and default settings,
+    /// to recover from a configuration that crashes or doesn't play any audio.
+    pub safe_mode: bool,
+    /// Queue the locations to play next when handing them to an instance that's already
+    /// running, rather than replacing its playlist.
+    pub enqueue: bool,
+    /// Start a separate instance even if one is already running.
+    pub new_instance: bool,
 }
 
 fn invalid_location(err: ParseLocationError) -> clap::Error {
     cli_config().error(ErrorKind::InvalidValue, err.to_string())
 }
 
-pub fn parse<Arg, Itr>(args: Itr) -> Result<Mode, clap::Error>
+pub fn parse<Arg, Itr>(args: Itr) -> Result<Args, clap::Error>
 where
     Arg: Into<ffi::OsString> + Clone,
     Itr: IntoIterator<Item = Arg>,
 {
     let matches = cli_config().try_get_matches_from(args)?;
+    // Global flags are only propagated down to the subcommand
+    let global_flag = |name| match matches.subcommand() {
+        Some((_, sub)) => sub.get_flag(name),
+        None => matches.get_flag(name),
+    };
+    Ok(Args {
+        mode: parse_mode(&matches)?,
+        safe_mode: global_flag("safe-mode"),
+        enqueue: global_flag("enqueue"),
+        new_instance: global_flag("new-instance"),
+    })
+}
+
+fn parse_mode(matches: &ArgMatches) -> Result<Mode, clap::Error> {
     match matches.subcommand() {
         Some(("library", sub)) => {
             let storage_path = sub
@@ -55,7 +92,23 @@ where
             })
         }
         Some(("simple", sub)) => parse_simple(sub),
-        _ => parse_simple(&matches),
+        Some(("control", sub)) => Ok(Mode::Control {
+            command: parse_control(sub),
+        }),
+        _ => parse_simple(matches),
+    }
+}
+
+fn parse_control(matches: &ArgMatches) -> ControlCommand {
+    match matches.subcommand() {
+        Some(("play-pause", _)) => ControlCommand::PlayPause,
+        Some(("next", _)) => ControlCommand::Next,
+        Some(("prev", _)) => ControlCommand::Previous,
+        Some(("seek", sub)) => *sub.get_one("POSITION").expect("required"),
+        Some(("volume", sub)) => ControlCommand::Volume {
+            percent: *sub.get_one("PERCENT").expect("required"),
+        },
+        _ => unreachable!("a control subcommand is required"),
     }
 }
 
@@ -65,12 +118,108 @@ fn parse_simple(matches: &ArgMatches) -> Result<Mode, clap::Error> {
         .unwrap_or_default()
         .map(|s| Location::from_str(s))
         .collect();
+    let track_number_ordering = !matches.get_flag("no-track-order");
     match locations {
-        Ok(locations) => Ok(Mode::Simple { locations }),
+        Ok(locations) => Ok(Mode::Simple {
+            locations,
+            track_number_ordering,
+            start_paused: matches.get_flag("start-paused"),
+            start_position: matches.get_one::<Duration>("seek").copied(),
+        }),
         Err(err) => Err(invalid_location(err)),
     }
 }
 
+fn no_track_order_arg() -> clap::Arg {
+    clap::Arg::new("no-track-order")
+        .help("Order the files in loaded directories by file name rather than by track number tags")
+        .long("no-track-order")
+        .action(ArgAction::SetTrue)
+}
+
+fn start_paused_arg() -> clap::Arg {
+    clap::Arg::new("start-paused")
+        .help("Load the given files without starting playback")
+        .long("start-paused")
+        .action(ArgAction::SetTrue)
+}
+
+fn seek_arg() -> clap::Arg {
+    clap::Arg::new("seek")
+        .help("Start the first track at the given position, such as 1:23:45, 83:45, or 90")
+        .long("seek")
+        .value_name("POSITION")
+        .value_parser(parse_position)
+        .action(ArgAction::Set)
+}
+
+/// Parses a position given as seconds, "MM:SS", or "HH:MM:SS". The seconds can have a fraction.
+fn parse_position(value: &str) -> Result<Duration, String> {
+    let invalid = || format!("`{value}` isn't a position like 1:23:45, 83:45, or 90");
+    let mut parts = value.trim().rsplit(':');
+    let seconds: f64 = parts
+        .next()
+        .and_then(|part| part.parse().ok())
+        .filter(|seconds: &f64| seconds.is_finite() && *seconds >= 0.0)
+        .ok_or_else(invalid)?;
+    let mut whole = 0;
+    for unit in [60, 3600] {
+        if let Some(part) = parts.next() {
+            whole += unit * part.parse::<u64>().map_err(|_| invalid())?;
+        }
+    }
+    if parts.next().is_some() {
+        return Err(invalid());
+    }
+    Ok(Duration::from_secs(whole) + Duration::from_secs_f64(seconds))
+}
+
+/// Parses a seek given as "+30" or "-1:00" relative to the current position, or as a position.
+fn parse_seek(value: &str) -> Result<ControlCommand, String> {
+    let value = value.trim();
+    let relative =
+        |offset: &str| parse_position(offset).map(|offset| offset.as_secs_f64().round() as i32);
+    if let Some(offset) = value.strip_prefix('+') {
+        Ok(ControlCommand::SeekBy {
+            seconds: relative(offset)?,
+        })
+    } else if let Some(offset) = value.strip_prefix('-') {
+        Ok(ControlCommand::SeekBy {
+            seconds: -relative(offset)?,
+        })
+    } else {
+        parse_position(value).map(|position| ControlCommand::SeekTo { position })
+    }
+}
+
+fn control_command() -> clap::Command {
+    clap::Command::new("control")
+        .about("Control playback in the player that's already running, such as from key bindings")
+        .subcommand_required(true)
+        .subcommand(clap::Command::new("play-pause").about("Play or pause"))
+        .subcommand(clap::Command::new("next").about("Skip to the next track"))
+        .subcommand(clap::Command::new("prev").about("Go back to the previous track"))
+        .subcommand(
+            clap::Command::new("seek")
+                .about("Seek to a position, or by an offset such as +30 or -1:00")
+                .arg(
+                    clap::Arg::new("POSITION")
+                        .value_parser(parse_seek)
+                        .allow_hyphen_values(true)
+                        .required(true),
+                ),
+        )
+        .subcommand(
+            clap::Command::new("volume")
+                .about("Set the volume as a percentage")
+                .arg(
+                    clap::Arg::new("PERCENT")
+                        .value_parser(clap::value_parser!(u8).range(0..=100))
+                        .required(true),
+                ),
+        )
+}
+
 fn cli_config() -> clap::Command {
     clap::Command::new("Millenium Player")
         .version(env!("CARGO_PKG_VERSION"))
@@ -82,6 +231,30 @@ fn cli_config() -> clap::Command {
                 .action(clap::ArgAction::Append)
                 .required(false),
         )
+        .arg(no_track_order_arg())
+        .arg(start_paused_arg())
+        .arg(seek_arg())
+        .arg(
+            clap::Arg::new("safe-mode")
+                .help("Start with audio processing off, the default output device, and default settings")
+                .long("safe-mode")
+                .global(true)
+                .action(ArgAction::SetTrue),
+        )
+        .arg(
+            clap::Arg::new("enqueue")
+                .help("Queue the files to play next in the player that's already running, rather than replacing its playlist")
+                .long("enqueue")
+                .global(true)
+                .action(ArgAction::SetTrue),
+        )
+        .arg(
+            clap::Arg::new("new-instance")
+                .help("Open a new window even if the player is already running")
+                .long("new-instance")
+                .global(true)
+                .action(ArgAction::SetTrue),
+        )
         .subcommand(
             clap::Command::new("simple")
                 .about("Run in a simple audio player mode with no library management features")
@@ -93,7 +266,10 @@ fn cli_config() -> clap::Command {
                         .action(clap::ArgAction::Append)
                         .required(false)
                         .index(1),
-                ),
+                )
+                .arg(no_track_order_arg())
+                .arg(start_paused_arg())
+                .arg(seek_arg()),
         )
         .subcommand(
             clap::Command::new("library")
@@ -113,6 +289,7 @@ fn cli_config() -> clap::Command {
                         .required(false),
                 ),
         )
+        .subcommand(control_command())
 }
 
 #[cfg(test)]
@@ -123,15 +300,21 @@ mod cli_tests {
     fn no_args_runs_simple_mode() {
         pretty_assertions::assert_eq!(
             Mode::Simple {
-                locations: Vec::new()
+                locations: Vec::new(),
+                track_number_ordering: true,
+                start_paused: false,
+                start_position: None,
             },
-            parse(["millenium-player"]).expect("success"),
+            parse(["millenium-player"]).expect("success").mode,
         );
         pretty_assertions::assert_eq!(
             Mode::Simple {
-                locations: Vec::new()
+                locations: Vec::new(),
+                track_number_ordering: true,
+                start_paused: false,
+                start_position: None,
             },
-            parse(["ungabunga"]).expect("success"),
+            parse(["ungabunga"]).expect("success").mode,
         );
     }
 
@@ -140,26 +323,46 @@ mod cli_tests {
         pretty_assertions::assert_eq!(
             Mode::Simple {
                 locations: vec![Location::path("foo.mp3")],
+                track_number_ordering: true,
+                start_paused: false,
+                start_position: None,
             },
-            parse(["millenium-player", "foo.mp3"]).expect("success"),
+            parse(["millenium-player", "foo.mp3"])
+                .expect("success")
+                .mode,
         );
         pretty_assertions::assert_eq!(
             Mode::Simple {
                 locations: vec![Location::from_str("https://example.com/test.mp3").unwrap()],
+                track_number_ordering: true,
+                start_paused: false,
+                start_position: None,
             },
-            parse(["millenium-player", "https://example.com/test.mp3"]).expect("success"),
+            parse(["millenium-player", "https://example.com/test.mp3"])
+                .expect("success")
+                .mode,
         );
         pretty_assertions::assert_eq!(
             Mode::Simple {
                 locations: vec![Location::path("foo.mp3")],
+                track_number_ordering: true,
+                start_paused: false,
+                start_position: None,
             },
-            parse(["millenium-player", "--", "foo.mp3"]).expect("success"),
+            parse(["millenium-player", "--", "foo.mp3"])
+                .expect("success")
+                .mode,
         );
         pretty_assertions::assert_eq!(
             Mode::Simple {
                 locations: vec![Location::path("simple")],
+                track_number_ordering: true,
+                start_paused: false,
+                start_position: None,
             },
-            parse(["millenium-player", "--", "simple"]).expect("success"),
+            parse(["millenium-player", "--", "simple"])
+                .expect("success")
+                .mode,
         );
     }
 
@@ -167,15 +370,21 @@ mod cli_tests {
     fn simple_mode() {
         pretty_assertions::assert_eq!(
             Mode::Simple {
-                locations: Vec::new()
+                locations: Vec::new(),
+                track_number_ordering: true,
+                start_paused: false,
+                start_position: None,
             },
-            parse(["millenium-player", "simple"]).expect("success"),
+            parse(["millenium-player", "simple"]).expect("success").mode,
         );
         pretty_assertions::assert_eq!(
             Mode::Simple {
-                locations: Vec::new()
+                locations: Vec::new(),
+                track_number_ordering: true,
+                start_paused: false,
+                start_position: None,
             },
-            parse(["ungabunga", "simple"]).expect("success"),
+            parse(["ungabunga", "simple"]).expect("success").mode,
         );
 
         let args = parse([
@@ -185,19 +394,158 @@ mod cli_tests {
             "https://example.com/bar.mp3",
             "path/to/playlist.m3u8",
         ])
-        .expect("success");
+        .expect("success")
+        .mode;
         pretty_assertions::assert_eq!(
             Mode::Simple {
                 locations: vec![
                     Location::from_str("path/to/foo.ogg").unwrap(),
                     Location::from_str("https://example.com/bar.mp3").unwrap(),
                     Location::from_str("path/to/playlist.m3u8").unwrap()
-                ]
+                ],
+                track_number_ordering: true,
+                start_paused: false,
+                start_position: None,
             },
             args
         );
     }
 
+    #[test]
+    fn no_track_order() {
+        pretty_assertions::assert_eq!(
+            Mode::Simple {
+                locations: vec![Location::path("some/album")],
+                track_number_ordering: false,
+                start_paused: false,
+                start_position: None,
+            },
+            parse(["millenium-player", "--no-track-order", "some/album"])
+                .expect("success")
+                .mode,
+        );
+        pretty_assertions::assert_eq!(
+            Mode::Simple {
+                locations: vec![Location::path("some/album")],
+                track_number_ordering: false,
+                start_paused: false,
+                start_position: None,
+            },
+            parse([
+                "millenium-player",
+                "simple",
+                "--no-track-order",
+                "some/album"
+            ])
+            .expect("success")
+            .mode,
+        );
+    }
+
+    #[test]
+    fn start_paused_at_position() {
+        pretty_assertions::assert_eq!(
+            Mode::Simple {
+                locations: vec![Location::path("recording.flac")],
+                track_number_ordering: true,
+                start_paused: true,
+                start_position: Some(Duration::from_secs(5025)),
+            },
+            parse([
+                "millenium-player",
+                "--start-paused",
+                "--seek",
+                "1:23:45",
+                "recording.flac"
+            ])
+            .expect("success")
+            .mode,
+        );
+        pretty_assertions::assert_eq!(
+            Mode::Simple {
+                locations: vec![Location::path("recording.flac")],
+                track_number_ordering: true,
+                start_paused: false,
+                start_position: Some(Duration::from_millis(90_500)),
+            },
+            parse([
+                "millenium-player",
+                "simple",
+                "--seek",
+                "1:30.5",
+                "recording.flac"
+            ])
+            .expect("success")
+            .mode,
+        );
+        assert_eq!(Ok(Duration::from_secs(90)), parse_position("90"));
+        assert!(parse_position("1:2:3:4").is_err());
+        assert!(parse_position("1:xx").is_err());
+        assert!(parse_position("-5").is_err());
+        assert!(parse(["millenium-player", "--seek", "soon", "recording.flac"]).is_err());
+    }
+
+    #[test]
+    fn safe_mode() {
+        assert!(!parse(["millenium-player"]).expect("success").safe_mode);
+        assert!(
+            parse(["millenium-player", "--safe-mode", "foo.mp3"])
+                .expect("success")
+                .safe_mode
+        );
+        assert!(
+            parse(["millenium-player", "library", "--safe-mode"])
+                .expect("success")
+                .safe_mode
+        );
+    }
+
+    #[test]
+    fn instance_flags() {
+        let args = parse(["millenium-player", "foo.mp3"]).expect("success");
+        assert!(!args.enqueue && !args.new_instance);
+        let args = parse(["millenium-player", "--enqueue", "foo.mp3"]).expect("success");
+        assert!(args.enqueue && !args.new_instance);
+        let args = parse(["millenium-player", "simple", "--new-instance"]).expect("success");
+        assert!(!args.enqueue && args.new_instance);
+    }
+
+    #[test]
+    fn control_commands() {
+        let command =
+            |args: &[&str]| match parse(["millenium-player", "control"].iter().chain(args))
+                .map(|args| args.mode)
+            {
+                Ok(Mode::Control { command }) => Ok(command),
+                Ok(mode) => panic!("unexpected mode: {mode:?}"),
+                Err(err) => Err(err),
+            };
+        assert_eq!(ControlCommand::PlayPause, command(&["play-pause"]).unwrap());
+        assert_eq!(ControlCommand::Next, command(&["next"]).unwrap());
+        assert_eq!(ControlCommand::Previous, command(&["prev"]).unwrap());
+        assert_eq!(
+            ControlCommand::SeekBy { seconds: 30 },
+            command(&["seek", "+30"]).unwrap()
+        );
+        assert_eq!(
+            ControlCommand::SeekBy { seconds: -90 },
+            command(&["seek", "-1:30"]).unwrap()
+        );
+        assert_eq!(
+            ControlCommand::SeekTo {
+                position: Duration::from_secs(83)
+            },
+            command(&["seek", "1:23"]).unwrap()
+        );
+        assert_eq!(
+            ControlCommand::Volume { percent: 50 },
+            command(&["volume", "50"]).unwrap()
+        );
+        assert!(command(&["volume", "150"]).is_err());
+        assert!(command(&["seek", "+soon"]).is_err());
+        assert!(command(&[]).is_err());
+    }
+
     #[test]
     fn library_mode() {
         pretty_assertions::assert_eq!(
@@ -205,7 +553,9 @@ mod cli_tests {
                 storage_path: None,
                 audio_path: None,
             },
-            parse(["millenium-player", "library"]).expect("success"),
+            parse(["millenium-player", "library"])
+                .expect("success")
+                .mode,
         );
 
         pretty_assertions::assert_eq!(
@@ -213,7 +563,9 @@ mod cli_tests {
                 storage_path: Some(Location::from_str("some/path").unwrap()),
                 audio_path: None,
             },
-            parse(["millenium-player", "library", "--storage-path", "some/path"]).expect("success"),
+            parse(["millenium-player", "library", "--storage-path", "some/path"])
+                .expect("success")
+                .mode,
         );
 
         pretty_assertions::assert_eq!(
@@ -229,7 +581,8 @@ mod cli_tests {
                 "--audio-path",
                 "some/audio/path"
             ])
-            .expect("success"),
+            .expect("success")
+            .mode,
         );
 
         pretty_assertions::assert_eq!(
@@ -243,7 +596,8 @@ mod cli_tests {
                 "--audio-path",
                 "some/audio/path"
             ])
-            .expect("success"),
+            .expect("success")
+            .mode,
         );
     }
 }
@@ -0,0 +1,294 @@
+// This file is part of Millenium Player.
+// Copyright (C) 2023 John DiSanti.
+//
+// Millenium Player is free software: you can redistribute it and/or modify it under the terms of
+// the GNU General Public License as published by the Free Software Foundation, either version 3 of
+// the License, or (at your option) any later version.
+//
+// Millenium Player is distributed in the hope that it will be useful, but WITHOUT ANY WARRANTY;
+// without even the implied warranty of MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE. See
+// the GNU General Public License for more details.
+//
+// You should have received a copy of the GNU General Public License along with Millenium Player.
+// If not, see <https://www.gnu.org/licenses/>.
+
+use crate::{config::Config, favorites, APP_TITLE};
+use millenium_core::location::Location;
+use millenium_post_office::types::{Favorite, FavoriteKind};
+use serde::{Deserialize, Serialize};
+use std::{
+    fs, io,
+    path::{Path, PathBuf},
+    str::FromStr,
+};
+
+/// Version of the backup file format, which is increased when older players can't read it.
+const BACKUP_VERSION: u32 = 1;
+
+#[derive(Debug, thiserror::Error)]
+pub enum BackupError {
+    #[error("failed to read {path:?}: {source}")]
+    Read { path: PathBuf, source: io::Error },
+    #[error("failed to write {path:?}: {source}")]
+    Write { path: PathBuf, source: io::Error },
+    #[error("{path:?} isn't a {APP_TITLE} backup: {source}")]
+    Parse {
+        path: PathBuf,
+        source: toml::de::Error,
+    },
+    #[error("{path:?} was made by a newer version of {APP_TITLE}")]
+    UnsupportedVersion { path: PathBuf },
+    #[error("the backup has an invalid {what}: {source}")]
+    Invalid {
+        what: &'static str,
+        source: toml::de::Error,
+    },
+}
+
+/// Where the state that gets backed up is kept.
+#[derive(Clone, Debug)]
+pub struct StatePaths {
+    pub config: PathBuf,
+    pub favorites: PathBuf,
+}
+
+impl StatePaths {
+    pub fn default_paths() -> Option<Self> {
+        Some(Self {
+            config: Config::default_path()?,
+            favorites: favorites::Favorites::default_path()?,
+        })
+    }
+}
+
+/// A playlist file that one of the favorites refers to.
+#[derive(Clone, Debug, Eq, PartialEq, Deserialize, Serialize)]
+pub struct BackedUpPlaylist {
+    pub path: PathBuf,
+    pub contents: String,
+}
+
+/// Settings, favorites, and favorite playlists bundled into a single file, for moving
+/// them to another computer.
+///
+/// The config and favorites files are kept as they were written so that comments in the
+/// config file survive the trip.
+#[derive(Clone, Debug, Default, Eq, PartialEq, Deserialize, Serialize)]
+#[serde(default, rename_all = "kebab-case")]
+pub struct Backup {
+    pub version: u32,
+    pub config: Option<String>,
+    pub favorites: Option<String>,
+    #[serde(rename = "playlist")]
+    pub playlists: Vec<BackedUpPlaylist>,
+}
+
+impl Backup {
+    /// Gathers the current state. Playlists that can't be read are left out.
+    pub fn collect(paths: &StatePaths, favorites: &[Favorite]) -> Result<Self, BackupError> {
+        let playlists = favorites
+            .iter()
+            .filter(|favorite| favorite.kind == FavoriteKind::Playlist)
+            .filter_map(|favorite| match Location::from_str(&favorite.location) {
+                Ok(Location::Path(path)) => Some(path.into_std_path_buf()),
+                _ => None,
+            })
+            .filter_map(|path| match fs::read_to_string(&path) {
+                Ok(contents) => Some(BackedUpPlaylist { path, contents }),
+                Err(err) => {
+                    log::warn!("leaving playlist {path:?} out of the backup: {err}");
+                    None
+                }
+            })
+            .collect();
+        Ok(Self {
+            version: BACKUP_VERSION,
+            config: read_if_exists(&paths.config)?,
+            favorites: read_if_exists(&paths.favorites)?,
+            playlists,
+        })
+    }
+
+    pub fn save(&self, path: &Path) -> Result<(), BackupError> {
+        let contents = toml::to_string(self).expect("serializable");
+        fs::write(path, contents).map_err(|source| BackupError::Write {
+            path: path.into(),
+            source,
+        })
+    }
+
+    pub fn load(path: &Path) -> Result<Self, BackupError> {
+        let contents = fs::read_to_string(path).map_err(|source| BackupError::Read {
+            path: path.into(),
+            source,
+        })?;
+        let backup: Backup = toml::from_str(&contents).map_err(|source| BackupError::Parse {
+            path: path.into(),
+            source,
+        })?;
+        if backup.version > BACKUP_VERSION {
+            return Err(BackupError::UnsupportedVersion { path: path.into() });
+        }
+        Ok(backup)
+    }
+
+    /// Replaces the current config and favorites with the ones in the backup.
+    ///
+    /// Everything is checked before anything is written, so a bad backup changes nothing.
+    /// Playlists are put back where they were, but existing files are never overwritten.
+    pub fn restore(&self, paths: &StatePaths) -> Result<(), BackupError> {
+        if let Some(config) = &self.config {
+            toml::from_str::<Config>(config).map_err(|source| BackupError::Invalid {
+                what: "config",
+                source,
+            })?;
+        }
+        if let Some(favorites) = &self.favorites {
+            favorites::validate(favorites).map_err(|source| BackupError::Invalid {
+                what: "favorites list",
+                source,
+            })?;
+        }
+
+        if let Some(config) = &self.config {
+            write_creating_dir(&paths.config, config)?;
+        }
+        if let Some(favorites) = &self.favorites {
+            write_creating_dir(&paths.favorites, favorites)?;
+        }
+        for playlist in &self.playlists {
+            if playlist.path.exists() {
+                log::info!(
+                    "not restoring playlist {:?} over an existing file",
+                    playlist.path
+                );
+                continue;
+            }
+            write_creating_dir(&playlist.path, &playlist.contents)?;
+        }
+        Ok(())
+    }
+}
+
+fn read_if_exists(path: &Path) -> Result<Option<String>, BackupError> {
+    match fs::read_to_string(path) {
+        Ok(contents) => Ok(Some(contents)),
+        Err(err) if err.kind() == io::ErrorKind::NotFound => Ok(None),
+        Err(source) => Err(BackupError::Read {
+            path: path.into(),
+            source,
+        }),
+    }
+}
+
+fn write_creating_dir(path: &Path, contents: &str) -> Result<(), BackupError> {
+    let write = || {
+        if let Some(dir) = path.parent() {
+            fs::create_dir_all(dir)?;
+        }
+        fs::write(path, contents)
+    };
+    write().map_err(|source| BackupError::Write {
+        path: path.into(),
+        source,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::APP_NAME;
+    use pretty_assertions::assert_eq;
+
+    struct TestDir(PathBuf);
+    impl TestDir {
+        fn new(name: &str) -> Self {
+            let path =
+                std::env::temp_dir().join(format!("{APP_NAME}-{name}-{}", std::process::id()));
+            fs::create_dir_all(&path).unwrap();
+            Self(path)
+        }
+
+        fn paths(&self, machine: &str) -> StatePaths {
+            StatePaths {
+                config: self.0.join(machine).join("config.toml"),
+                favorites: self.0.join(machine).join("favorites.toml"),
+            }
+        }
+    }
+    impl Drop for TestDir {
+        fn drop(&mut self) {
+            let _ = fs::remove_dir_all(&self.0);
+        }
+    }
+
+    #[test]
+    fn export_and_import() {
+        let dir = TestDir::new("backup");
+        let (old, new) = (dir.paths("old"), dir.paths("new"));
+        let playlist = dir.0.join("music").join("Mix.m3u8");
+        let favorites = format!(
+            "[[favorite]]\nname = \"Mix\"\nlocation = {:?}\nkind = \"playlist\"\n",
+            playlist.to_str().unwrap()
+        );
+        fs::create_dir_all(old.config.parent().unwrap()).unwrap();
+        fs::write(&old.config, "# Quiet at night\nprevent-sleep = false\n").unwrap();
+        fs::write(&old.favorites, &favorites).unwrap();
+        fs::create_dir_all(playlist.parent().unwrap()).unwrap();
+        fs::write(&playlist, "#EXTM3U\none.flac\n").unwrap();
+
+        let favorite_list = crate::favorites::Favorites::load(&old.favorites).unwrap();
+        let backup = Backup::collect(&old, favorite_list.list()).unwrap();
+        let backup_path = dir.0.join("backup.toml");
+        backup.save(&backup_path).unwrap();
+
+        fs::remove_file(&playlist).unwrap();
+        let loaded = Backup::load(&backup_path).unwrap();
+        assert_eq!(backup, loaded);
+        loaded.restore(&new).unwrap();
+        assert_eq!(
+            "# Quiet at night\nprevent-sleep = false\n",
+            fs::read_to_string(&new.config).unwrap()
+        );
+        assert_eq!(favorites, fs::read_to_string(&new.favorites).unwrap());
+        assert_eq!(
+            "#EXTM3U\none.flac\n",
+            fs::read_to_string(&playlist).unwrap()
+        );
+
+        // Playlists that already exist are left alone
+        fs::write(&playlist, "#EXTM3U\ntwo.flac\n").unwrap();
+        loaded.restore(&new).unwrap();
+        assert_eq!(
+            "#EXTM3U\ntwo.flac\n",
+            fs::read_to_string(&playlist).unwrap()
+        );
+    }
+
+    #[test]
+    fn invalid_backup_changes_nothing() {
+        let dir = TestDir::new("backup-invalid");
+        let paths = dir.paths("machine");
+        let backup = Backup {
+            version: BACKUP_VERSION,
+            config: Some("prevent-sleep = true\n".into()),
+            favorites: Some("favorite = 5".into()),
+            playlists: Vec::new(),
+        };
+        assert!(matches!(
+            backup.restore(&paths),
+            Err(BackupError::Invalid {
+                what: "favorites list",
+                ..
+            })
+        ));
+        assert!(!paths.config.exists());
+
+        let path = dir.0.join("future.toml");
+        fs::write(&path, "version = 99\n").unwrap();
+        assert!(matches!(
+            Backup::load(&path),
+            Err(BackupError::UnsupportedVersion { .. })
+        ));
+    }
+}
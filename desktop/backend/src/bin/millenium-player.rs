@@ -14,12 +14,31 @@
 
 #![cfg_attr(not(debug_assertions), windows_subsystem = "windows")]
 
-use millenium_desktop_backend::{args, error::FatalError, ui, APP_NAME};
+use millenium_desktop_backend::{
+    args::{self, Mode},
+    error::FatalError,
+    log_file::{self, RotatingLogFile},
+    single_instance, ui, APP_NAME,
+};
 use std::{env, path::PathBuf};
 
 fn do_main() -> Result<(), FatalError> {
-    let mode = args::parse(env::args_os())?;
-    ui::Ui::new(mode)?.run();
+    let args = args::parse(env::args_os())?;
+    if let Mode::Control { command } = args.mode {
+        let path = single_instance::default_path()
+            .ok_or_else(|| FatalError::msg("No directory to find the running player in."))?;
+        return single_instance::send_control(&path, command)
+            .map_err(|err| FatalError::new("Couldn't reach the running player", err));
+    }
+    if let (Mode::Simple { locations, .. }, false) = (&args.mode, args.new_instance) {
+        let forwarded = single_instance::default_path().map_or(false, |path| {
+            single_instance::forward_to_running_instance(&path, locations, args.enqueue)
+        });
+        if forwarded {
+            return Ok(());
+        }
+    }
+    ui::Ui::new(args)?.run();
 }
 
 fn main() {
@@ -85,11 +104,10 @@ fn initialize_logging() {
 type PathedLogger = (Box<dyn simplelog::SharedLogger>, PathBuf);
 fn create_file_logger() -> Result<PathedLogger, (String, Option<PathBuf>)> {
     use simplelog::{LevelFilter, WriteLogger};
-    use std::fs::{create_dir_all, File};
+    use std::fs::create_dir_all;
 
-    let parent_path = dirs::cache_dir()
-        .ok_or_else(|| ("failed to locate cache dir".to_string(), None))?
-        .join(APP_NAME);
+    let parent_path =
+        log_file::log_dir().ok_or_else(|| ("failed to locate cache dir".to_string(), None))?;
     let path = parent_path.join(format!("{APP_NAME}.log"));
     create_dir_all(&parent_path).map_err(|err| {
         (
@@ -97,7 +115,12 @@ fn create_file_logger() -> Result<PathedLogger, (String, Option<PathBuf>)> {
             Some(path.clone()),
         )
     })?;
-    let file = File::create(&path).map_err(|err| (format!("{err}"), Some(path.clone())))?;
+    let file = RotatingLogFile::open(
+        &path,
+        log_file::MAX_LOG_FILE_SIZE,
+        log_file::LOG_FILE_RETENTION,
+    )
+    .map_err(|err| (format!("{err}"), Some(path.clone())))?;
     Ok((
         WriteLogger::new(LevelFilter::Info, logger_config(), file) as _,
         path,
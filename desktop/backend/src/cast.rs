@@ -0,0 +1,310 @@
+// This file is part of Millenium Player.
+// Copyright (C) 2023 John DiSanti.
+//
+// Millenium Player is free software: you can redistribute it and/or modify it under the terms of
+// the GNU General Public License as published by the Free Software Foundation, either version 3 of
+// the License, or (at your option) any later version.
+//
+// Millenium Player is distributed in the hope that it will be useful, but WITHOUT ANY WARRANTY;
+// without even the implied warranty of MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE. See
+// the GNU General Public License for more details.
+//
+// You should have received a copy of the GNU General Public License along with Millenium Player.
+// If not, see <https://www.gnu.org/licenses/>.
+
+use crate::{
+    config::CastConfig,
+    guest_queue::{
+        local_address, parse_request_line, query_param, read_request, ConnectionSlots, Response,
+    },
+};
+use millenium_core::{
+    audio::{
+        source::{AudioDecoderSource, PreferredFormat},
+        ChannelCount, SampleRate,
+    },
+    location::Location,
+    playlist::Playlist,
+};
+use std::{
+    fs::File,
+    io::{self, Write},
+    net::{Ipv4Addr, TcpListener, TcpStream},
+    sync::mpsc,
+    thread,
+    time::Duration,
+};
+
+/// How long a listener waits for the player to say which track to send.
+const TIMEOUT: Duration = Duration::from_secs(5);
+/// Most listeners that are sent a track at once, since each of them has its own thread.
+const MAX_LISTENERS: usize = 8;
+
+/// Asks the UI thread which track to send, since the playlist lives there.
+struct TrackRequest {
+    /// A location from the playlist to send rather than the current track.
+    location: Option<String>,
+    reply: mpsc::Sender<Option<Location>>,
+}
+
+/// How the track is sent to listeners.
+#[derive(Copy, Clone, Debug, Eq, PartialEq)]
+enum StreamFormat {
+    /// The file as it is, for players that can decode it themselves.
+    Original,
+    /// Decoded on the fly to 16-bit PCM, which any browser or network speaker can play.
+    Wav,
+}
+
+/// Serves the current track over HTTP so that browsers and network speakers on the local
+/// network can play along.
+///
+/// `/stream` sends the track that's playing, or the playlist entry given by a `location`
+/// parameter, which has to be in the playlist so that arbitrary files can't be read.
+/// Adding `format=wav` decodes it on the fly for players that can't decode the original.
+///
+/// It listens on every interface without any authentication, so anyone who can reach the port
+/// can listen. Listeners past [`MAX_LISTENERS`] are turned away.
+pub struct CastServer {
+    requests: mpsc::Receiver<TrackRequest>,
+    port: u16,
+}
+
+impl CastServer {
+    /// Starts serving on the configured port.
+    pub fn start(config: &CastConfig) -> io::Result<Self> {
+        let listener = TcpListener::bind((Ipv4Addr::UNSPECIFIED, config.port))?;
+        let port = listener.local_addr()?.port();
+        let (sender, requests) = mpsc::channel();
+        let slots = ConnectionSlots::new(MAX_LISTENERS);
+        thread::Builder::new().name("cast".into()).spawn(move || {
+            for stream in listener.incoming() {
+                let stream = match stream {
+                    Ok(stream) => stream,
+                    Err(err) => {
+                        log::warn!("failed to accept a cast listener: {err}");
+                        continue;
+                    }
+                };
+                let Some(slot) = slots.take() else {
+                    log::warn!("turned away a cast listener since {MAX_LISTENERS} are listening");
+                    let _ = Response::text("503 Service Unavailable", "Too many listeners")
+                        .write_to(&stream);
+                    continue;
+                };
+                // Each listener is sent a whole track, so they each get their own thread
+                let sender = sender.clone();
+                let spawned = thread::Builder::new()
+                    .name("cast-stream".into())
+                    .spawn(move || {
+                        let _slot = slot;
+                        if let Err(err) = handle_connection(stream, &sender) {
+                            log::info!("stopped casting to a listener: {err}");
+                        }
+                    });
+                if let Err(err) = spawned {
+                    log::warn!("failed to start casting to a listener: {err}");
+                }
+            }
+        })?;
+        log::info!("casting on port {port}");
+        Ok(Self { requests, port })
+    }
+
+    /// Address of the current track's stream, for opening in a browser or network speaker.
+    pub fn url(&self) -> String {
+        format!("http://{}:{}/stream", local_address(), self.port)
+    }
+
+    /// Answers which track listeners that connected since the last poll are sent.
+    pub fn poll(&self, playlist: &Playlist) {
+        while let Ok(TrackRequest { location, reply }) = self.requests.try_recv() {
+            let location = match location {
+                Some(location) if playlist.contains_location(&location) => location.parse().ok(),
+                Some(_) => None,
+                None => playlist.current_location().cloned(),
+            };
+            let _ = reply.send(location);
+        }
+    }
+}
+
+fn handle_connection(stream: TcpStream, requests: &mpsc::Sender<TrackRequest>) -> io::Result<()> {
+    let request_line = read_request(&stream)?;
+    let Some((method, url)) = parse_request_line(&request_line) else {
+        return Response::text("400 Bad Request", "Bad request").write_to(&stream);
+    };
+    if (method, url.path()) != ("GET", "/stream") {
+        return Response::text("404 Not Found", "Not found").write_to(&stream);
+    }
+    let format = match query_param(&url, "format").as_deref() {
+        None | Some("original") => StreamFormat::Original,
+        Some("wav") => StreamFormat::Wav,
+        Some(_) => {
+            return Response::text("400 Bad Request", "The format can be `original` or `wav`")
+                .write_to(&stream)
+        }
+    };
+
+    let (reply, answer) = mpsc::channel();
+    let request = TrackRequest {
+        location: query_param(&url, "location"),
+        reply,
+    };
+    let location = match requests.send(request).map(|_| answer.recv_timeout(TIMEOUT)) {
+        Ok(Ok(Some(location))) => location,
+        Ok(Ok(None)) => {
+            return Response::text("404 Not Found", "Nothing is playing").write_to(&stream)
+        }
+        _ => {
+            return Response::text("503 Service Unavailable", "The player isn't answering")
+                .write_to(&stream)
+        }
+    };
+    log::info!("casting {location} to {}", stream.peer_addr()?);
+    match (format, &location) {
+        (StreamFormat::Original, Location::Path(path)) => {
+            let mut file = File::open(path)?;
+            let length = file.metadata()?.len();
+            let content_type = content_type(location.extension());
+            write!(
+                &stream,
+                "HTTP/1.1 200 OK\r\nContent-Type: {content_type}\r\nContent-Length: {length}\r\nConnection: close\r\n\r\n"
+            )?;
+            io::copy(&mut file, &mut &stream)?;
+            Ok(())
+        }
+        // Listeners can fetch streams themselves
+        (StreamFormat::Original, Location::Url(url)) => write!(
+            &stream,
+            "HTTP/1.1 302 Found\r\nLocation: {url}\r\nContent-Length: 0\r\nConnection: close\r\n\r\n"
+        ),
+        (StreamFormat::Wav, _) => {
+            write!(
+                &stream,
+                "HTTP/1.1 200 OK\r\nContent-Type: audio/wav\r\nConnection: close\r\n\r\n"
+            )?;
+            write_wav(location, io::BufWriter::new(&stream))
+        }
+    }
+}
+
+fn content_type(extension: Option<&str>) -> &'static str {
+    match extension.map(str::to_ascii_lowercase).as_deref() {
+        Some("mp3") => "audio/mpeg",
+        Some("ogg" | "oga" | "opus") => "audio/ogg",
+        Some("flac") => "audio/flac",
+        Some("wav") => "audio/wav",
+        Some("m4a" | "mp4" | "aac") => "audio/mp4",
+        _ => "application/octet-stream",
+    }
+}
+
+/// Header for a WAV stream whose length isn't known up front, which players read until the
+/// connection closes.
+fn wav_header(sample_rate: SampleRate, channel_count: ChannelCount) -> Vec<u8> {
+    let block_align = channel_count as u16 * 2;
+    let mut header = Vec::with_capacity(44);
+    header.extend_from_slice(b"RIFF");
+    header.extend_from_slice(&u32::MAX.to_le_bytes());
+    header.extend_from_slice(b"WAVEfmt ");
+    header.extend_from_slice(&16u32.to_le_bytes());
+    header.extend_from_slice(&1u16.to_le_bytes()); // PCM
+    header.extend_from_slice(&(channel_count as u16).to_le_bytes());
+    header.extend_from_slice(&sample_rate.to_le_bytes());
+    header.extend_from_slice(&(sample_rate * block_align as u32).to_le_bytes());
+    header.extend_from_slice(&block_align.to_le_bytes());
+    header.extend_from_slice(&16u16.to_le_bytes()); // bits per sample
+    header.extend_from_slice(b"data");
+    header.extend_from_slice(&u32::MAX.to_le_bytes());
+    header
+}
+
+/// Decodes the track and writes it as 16-bit PCM WAV as it's decoded.
+fn write_wav(location: Location, mut output: impl Write) -> io::Result<()> {
+    let to_io = |err| io::Error::new(io::ErrorKind::Other, err);
+    let mut source =
+        AudioDecoderSource::new(location, PreferredFormat::new(44100, 2)).map_err(to_io)?;
+    let mut format = None;
+    while let Some(chunk) = source.next_chunk().map_err(to_io)? {
+        let chunk_format = (chunk.sample_rate(), chunk.channel_count());
+        match format {
+            None => {
+                output.write_all(&wav_header(chunk_format.0, chunk_format.1))?;
+                format = Some(chunk_format);
+            }
+            // A WAV stream can't change format partway, such as between chained Ogg streams
+            Some(format) if format != chunk_format => break,
+            Some(_) => {}
+        }
+        let mut samples = Vec::with_capacity(chunk.frame_count() * chunk_format.1 as usize * 2);
+        for frame in 0..chunk.frame_count() {
+            for channel in 0..chunk_format.1 as usize {
+                let sample = chunk.channel(channel)[frame].clamp(-1.0, 1.0);
+                samples.extend_from_slice(&((sample * i16::MAX as f32) as i16).to_le_bytes());
+            }
+        }
+        output.write_all(&samples)?;
+    }
+    output.flush()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::{io::Read, thread::JoinHandle};
+
+    fn request(port: u16, target: &str) -> JoinHandle<Vec<u8>> {
+        let request = format!("GET {target} HTTP/1.1\r\nHost: localhost\r\n\r\n");
+        thread::spawn(move || {
+            let mut stream = TcpStream::connect((Ipv4Addr::LOCALHOST, port)).unwrap();
+            stream.write_all(request.as_bytes()).unwrap();
+            let mut response = Vec::new();
+            stream.read_to_end(&mut response).unwrap();
+            response
+        })
+    }
+
+    #[test]
+    fn nothing_to_cast() {
+        let server = CastServer::start(&CastConfig {
+            enabled: true,
+            port: 0,
+        })
+        .unwrap();
+        let playlist = Playlist::default();
+        let send = |target: &str| {
+            let pending = request(server.port, target);
+            while !pending.is_finished() {
+                server.poll(&playlist);
+                thread::sleep(Duration::from_millis(1));
+            }
+            String::from_utf8(pending.join().unwrap()).unwrap()
+        };
+
+        let nothing = send("/stream");
+        assert!(nothing.starts_with("HTTP/1.1 404"), "{nothing}");
+        let not_listed = send("/stream?location=%2Fetc%2Fpasswd");
+        assert!(not_listed.starts_with("HTTP/1.1 404"), "{not_listed}");
+        let bad_format = send("/stream?format=mp3");
+        assert!(bad_format.starts_with("HTTP/1.1 400"), "{bad_format}");
+        assert!(send("/elsewhere").starts_with("HTTP/1.1 404"));
+    }
+
+    #[test]
+    fn transcode_to_wav() {
+        let mut wav = Vec::new();
+        write_wav(
+            Location::path("../../test-data/melodic_a_minor/melodic_a_minor_2chan_44100hz_11s.ogg"),
+            &mut wav,
+        )
+        .unwrap();
+        assert_eq!(wav_header(44100, 2), wav[..44]);
+        // About 11 seconds of 16-bit stereo
+        let seconds = (wav.len() - 44) as f32 / (44100.0 * 4.0);
+        assert!((10.5..11.5).contains(&seconds), "{seconds}");
+
+        assert_eq!("audio/mpeg", content_type(Some("MP3")));
+        assert_eq!("application/octet-stream", content_type(None));
+    }
+}
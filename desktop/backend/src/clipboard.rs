@@ -0,0 +1,107 @@
+// This file is part of Millenium Player.
+// Copyright (C) 2023 John DiSanti.
+//
+// Millenium Player is free software: you can redistribute it and/or modify it under the terms of
+// the GNU General Public License as published by the Free Software Foundation, either version 3 of
+// the License, or (at your option) any later version.
+//
+// Millenium Player is distributed in the hope that it will be useful, but WITHOUT ANY WARRANTY;
+// without even the implied warranty of MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE. See
+// the GNU General Public License for more details.
+//
+// You should have received a copy of the GNU General Public License along with Millenium Player.
+// If not, see <https://www.gnu.org/licenses/>.
+
+use camino::Utf8PathBuf;
+use url::Url;
+
+/// Locations found in text pasted into the window.
+#[derive(Debug, Default, PartialEq)]
+pub struct PastedLocations {
+    /// Absolute file paths and stream URLs, in the order they were pasted.
+    pub locations: Vec<String>,
+    /// Lines that aren't a path or URL that can be played.
+    pub invalid: Vec<String>,
+}
+
+/// Finds the paths and URLs in pasted text, one per line.
+///
+/// File managers paste `file://` URLs, so those are turned back into paths. Blank lines and
+/// playlist comments are ignored, and quotes around a line are removed.
+pub fn parse_pasted_locations(text: &str) -> PastedLocations {
+    let mut pasted = PastedLocations::default();
+    for line in text.lines() {
+        let line = line.trim();
+        let line = line
+            .strip_prefix('"')
+            .and_then(|line| line.strip_suffix('"'))
+            .unwrap_or(line);
+        if line.is_empty() || line.starts_with('#') {
+            continue;
+        }
+        match pasted_location(line) {
+            Some(location) => pasted.locations.push(location),
+            None => pasted.invalid.push(line.to_string()),
+        }
+    }
+    pasted
+}
+
+fn pasted_location(line: &str) -> Option<String> {
+    if !line.contains("://") {
+        let path = Utf8PathBuf::from(line);
+        return path.is_absolute().then(|| path.into_string());
+    }
+    let url = Url::parse(line).ok()?;
+    match url.scheme() {
+        "file" => {
+            let path = url.to_file_path().ok()?;
+            Utf8PathBuf::from_path_buf(path)
+                .ok()
+                .map(Utf8PathBuf::into_string)
+        }
+        "http" | "https" => Some(url.into()),
+        _ => None,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[cfg(not(target_os = "windows"))]
+    #[test]
+    fn parse_paths_and_urls() {
+        let pasted = parse_pasted_locations(
+            "/music/one.ogg\n\
+             \n\
+             #EXTINF:123,Artist - Title\n\
+             \"/music/with spaces.mp3\"\r\n\
+             file:///music/percent%20encoded.flac\n\
+             https://radio.example.com/stream\n\
+             relative/path.ogg\n\
+             ftp://example.com/song.mp3\n",
+        );
+        assert_eq!(
+            PastedLocations {
+                locations: vec![
+                    "/music/one.ogg".into(),
+                    "/music/with spaces.mp3".into(),
+                    "/music/percent encoded.flac".into(),
+                    "https://radio.example.com/stream".into(),
+                ],
+                invalid: vec![
+                    "relative/path.ogg".into(),
+                    "ftp://example.com/song.mp3".into()
+                ],
+            },
+            pasted
+        );
+    }
+
+    #[test]
+    fn parse_nothing() {
+        assert_eq!(PastedLocations::default(), parse_pasted_locations(""));
+        assert_eq!(PastedLocations::default(), parse_pasted_locations("  \n\n"));
+    }
+}
@@ -0,0 +1,783 @@
+// This file is part of Millenium Player.
+// Copyright (C) 2023 John DiSanti.
+//
+// Millenium Player is free software: you can redistribute it and/or modify it under the terms of
+// the GNU General Public License as published by the Free Software Foundation, either version 3 of
+// the License, or (at your option) any later version.
+//
+// Millenium Player is distributed in the hope that it will be useful, but WITHOUT ANY WARRANTY;
+// without even the implied warranty of MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE. See
+// the GNU General Public License for more details.
+//
+// You should have received a copy of the GNU General Public License along with Millenium Player.
+// If not, see <https://www.gnu.org/licenses/>.
+
+use crate::APP_NAME;
+use camino::Utf8PathBuf;
+use millenium_core::{
+    audio::{channel_map::ChannelMaps, device::DeviceOptions},
+    playlist::FolderDefaults,
+};
+use millenium_post_office::frontend::{message::TrackTransition, settings::Settings};
+use serde::{Deserialize, Serialize};
+use std::{
+    fs, io,
+    path::{Path, PathBuf},
+    time::{Duration, Instant},
+};
+use toml_edit::Document;
+
+/// How often the config file is checked for changes.
+const POLL_INTERVAL: Duration = Duration::from_secs(1);
+
+/// Tables in the config file that aren't part of the settings.
+const NON_SETTINGS_TABLES: [&str; 8] = [
+    "audio",
+    "metadata",
+    "guest-queue",
+    "osc",
+    "ducking",
+    "now-playing",
+    "cast",
+    "folders",
+];
+
+#[derive(Debug, thiserror::Error)]
+pub enum ConfigError {
+    #[error("failed to read config file {path:?}: {source}")]
+    Read { path: PathBuf, source: io::Error },
+    #[error("failed to parse config file {path:?}: {source}")]
+    Parse {
+        path: PathBuf,
+        source: toml::de::Error,
+    },
+    #[error("failed to edit config file {path:?}: {source}")]
+    Edit {
+        path: PathBuf,
+        source: toml_edit::TomlError,
+    },
+    #[error("failed to save config file {path:?}: {source}")]
+    Write { path: PathBuf, source: io::Error },
+}
+
+/// Contents of the config file.
+#[derive(Clone, Debug, Default, PartialEq, Deserialize, Serialize)]
+#[serde(default)]
+pub struct Config {
+    /// Settings that are applied live when the config file changes.
+    #[serde(flatten)]
+    pub settings: Settings,
+    /// Settings that only take effect after a restart.
+    pub audio: AudioConfig,
+    /// Tag decoding options, which also only take effect after a restart.
+    pub metadata: MetadataConfig,
+    /// Web page for guests to queue tracks from, which also only takes effect after a restart.
+    #[serde(rename = "guest-queue")]
+    pub guest_queue: GuestQueueConfig,
+    /// Open Sound Control output of the audio analysis, which also only takes effect after
+    /// a restart.
+    pub osc: OscConfig,
+    /// Local endpoint for lowering the volume while other audio plays, which also only takes
+    /// effect after a restart.
+    pub ducking: DuckingConfig,
+    /// File or named pipe to write what's playing to, which also only takes effect after
+    /// a restart.
+    #[serde(rename = "now-playing")]
+    pub now_playing: NowPlayingConfig,
+    /// HTTP stream of the current track for browsers and network speakers, which also only
+    /// takes effect after a restart.
+    pub cast: CastConfig,
+    /// Playback settings for the tracks under particular folders, which also only take effect
+    /// after a restart.
+    pub folders: Vec<FolderConfig>,
+}
+
+#[derive(Clone, Debug, PartialEq, Deserialize, Serialize)]
+#[serde(default, rename_all = "kebab-case")]
+pub struct AudioConfig {
+    /// Name of the preferred output device. Uses the system default if not set.
+    pub output_device: Option<String>,
+    /// Channel maps keyed by output device name, such as `"R L"` to swap left and right.
+    pub channel_maps: ChannelMaps,
+    /// Seconds to keep playing silence after playback stops before pausing the output device.
+    pub idle_timeout_secs: f32,
+    /// Pad the end of each track with silence up to a whole chunk of audio.
+    pub pad_final_chunk: bool,
+    /// Seconds the output device can stay paused before it's closed so that other apps can use
+    /// it in exclusive mode. It's kept open if not set.
+    pub release_device_after_secs: Option<f32>,
+    /// Milliseconds to fade the audio out when pausing or stopping, and back in when resuming.
+    pub fade_millis: u64,
+    /// Milliseconds to ramp the volume down when ducking, and back up when it's restored.
+    pub duck_ramp_millis: u64,
+}
+
+impl Default for AudioConfig {
+    fn default() -> Self {
+        let device_options = DeviceOptions::default();
+        Self {
+            output_device: None,
+            channel_maps: ChannelMaps::default(),
+            idle_timeout_secs: device_options.idle_timeout.as_secs_f32(),
+            pad_final_chunk: device_options.pad_final_chunk,
+            release_device_after_secs: None,
+            fade_millis: device_options.fade.as_millis() as u64,
+            duck_ramp_millis: device_options.duck_ramp.as_millis() as u64,
+        }
+    }
+}
+
+impl AudioConfig {
+    pub fn device_options(&self) -> DeviceOptions {
+        DeviceOptions {
+            idle_timeout: Duration::try_from_secs_f32(self.idle_timeout_secs)
+                .unwrap_or_else(|_| DeviceOptions::default().idle_timeout),
+            pad_final_chunk: self.pad_final_chunk,
+            release_after: self
+                .release_device_after_secs
+                .and_then(|secs| Duration::try_from_secs_f32(secs).ok()),
+            fade: Duration::from_millis(self.fade_millis),
+            duck_ramp: Duration::from_millis(self.duck_ramp_millis),
+        }
+    }
+}
+
+#[derive(Clone, Debug, Default, PartialEq, Deserialize, Serialize)]
+#[serde(default, rename_all = "kebab-case")]
+pub struct MetadataConfig {
+    /// Encodings to try for tag text that isn't valid latin-1 or UTF-8, such as "windows-1251".
+    pub fallback_encodings: Vec<String>,
+    /// Separators that multi-value artist and genre tags are split on. Uses semicolons and
+    /// "feat." style credits if not set.
+    pub multi_value_separators: Option<Vec<String>>,
+    /// Where tags are read from, in order of precedence, such as `["sidecar", "embedded"]`.
+    /// Later providers only fill in tags that earlier ones didn't have. Uses sidecar files and
+    /// then embedded tags if not set.
+    pub providers: Option<Vec<String>>,
+}
+
+#[derive(Clone, Debug, PartialEq, Deserialize, Serialize)]
+#[serde(default, rename_all = "kebab-case")]
+pub struct GuestQueueConfig {
+    /// Serve the guest page to devices on the local network.
+    pub enabled: bool,
+    pub port: u16,
+    /// How many tracks each guest can have waiting in the play queue at once.
+    pub max_queued_per_guest: usize,
+}
+
+impl Default for GuestQueueConfig {
+    fn default() -> Self {
+        Self {
+            enabled: false,
+            port: 8573,
+            max_queued_per_guest: 2,
+        }
+    }
+}
+
+#[derive(Clone, Debug, PartialEq, Deserialize, Serialize)]
+#[serde(default, rename_all = "kebab-case")]
+pub struct OscConfig {
+    /// Where to send the messages, such as `"127.0.0.1:9000"`. Nothing is sent if not set.
+    pub target: Option<String>,
+    /// Prepended to the address of every message.
+    pub address_prefix: String,
+}
+
+impl Default for OscConfig {
+    fn default() -> Self {
+        Self {
+            target: None,
+            address_prefix: "/millenium".into(),
+        }
+    }
+}
+
+#[derive(Clone, Debug, PartialEq, Deserialize, Serialize)]
+#[serde(default, rename_all = "kebab-case")]
+pub struct NowPlayingConfig {
+    /// File or named pipe to write to. Nothing is written if not set.
+    pub path: Option<PathBuf>,
+    /// Text to write, with placeholders like `{artist}` and `{title}` filled in.
+    pub template: String,
+}
+
+impl Default for NowPlayingConfig {
+    fn default() -> Self {
+        Self {
+            path: None,
+            template: "{artist} - {title}".into(),
+        }
+    }
+}
+
+#[derive(Clone, Debug, PartialEq, Deserialize, Serialize)]
+#[serde(default, rename_all = "kebab-case")]
+pub struct DuckingConfig {
+    /// Listen on localhost for requests to duck, such as from a VoIP app's hooks.
+    pub enabled: bool,
+    pub port: u16,
+    /// Percentage of the volume to play at while ducked.
+    pub level_percent: u8,
+}
+
+impl Default for DuckingConfig {
+    fn default() -> Self {
+        Self {
+            enabled: false,
+            port: 8574,
+            level_percent: 30,
+        }
+    }
+}
+
+#[derive(Clone, Debug, PartialEq, Deserialize, Serialize)]
+#[serde(default, rename_all = "kebab-case")]
+pub struct CastConfig {
+    /// Serve the current track to devices on the local network. Anyone who can reach the port
+    /// can listen to the playlist's tracks.
+    pub enabled: bool,
+    pub port: u16,
+}
+
+impl Default for CastConfig {
+    fn default() -> Self {
+        Self {
+            enabled: false,
+            port: 8575,
+        }
+    }
+}
+
+#[derive(Clone, Debug, Default, PartialEq, Deserialize, Serialize)]
+#[serde(default, rename_all = "kebab-case")]
+pub struct FolderConfig {
+    /// Folder that the settings apply to. A leading `~` is the home directory.
+    pub path: String,
+    /// Seconds to skip at the start of each track. Uses the global intro skip if not set.
+    pub intro_skip_secs: Option<f32>,
+    /// Seconds the end of each track is crossfaded into the start of the next, which takes
+    /// priority over the gap. Uses the global track transition if neither is set.
+    pub crossfade_secs: Option<f32>,
+    /// Seconds of silence between tracks. Uses the global track transition if neither this
+    /// nor the crossfade is set.
+    pub gap_secs: Option<f32>,
+}
+
+impl FolderConfig {
+    /// Converts to the playlist's folder defaults, or `None` if the path isn't valid UTF-8
+    /// after expanding `~`.
+    pub fn folder_defaults(&self) -> Option<FolderDefaults> {
+        let home_relative = self
+            .path
+            .strip_prefix('~')
+            .filter(|rest| rest.is_empty() || rest.starts_with(['/', '\\']));
+        let folder = match home_relative {
+            Some(rest) => {
+                let home = Utf8PathBuf::from_path_buf(dirs::home_dir()?).ok()?;
+                home.join(rest.trim_start_matches(['/', '\\']))
+            }
+            None => Utf8PathBuf::from(&self.path),
+        };
+        let seconds =
+            |secs: Option<f32>| secs.and_then(|secs| Duration::try_from_secs_f32(secs).ok());
+        Some(FolderDefaults {
+            folder,
+            intro_skip: seconds(self.intro_skip_secs),
+            track_transition: match (seconds(self.crossfade_secs), seconds(self.gap_secs)) {
+                (None, None) => None,
+                (Some(crossfade), _) if !crossfade.is_zero() => {
+                    Some(TrackTransition::Overlap(crossfade))
+                }
+                (_, Some(gap)) if !gap.is_zero() => Some(TrackTransition::Gap(gap)),
+                _ => Some(TrackTransition::Immediate),
+            },
+        })
+    }
+}
+
+impl Config {
+    /// Default location of the config file.
+    pub fn default_path() -> Option<PathBuf> {
+        dirs::config_dir().map(|dir| dir.join(APP_NAME).join("config.toml"))
+    }
+
+    /// Loads the config file at the given path, using the defaults if it doesn't exist.
+    pub fn load(path: &Path) -> Result<Config, ConfigError> {
+        Self::parse(path, read_contents(path)?.as_deref())
+    }
+
+    fn parse(path: &Path, contents: Option<&str>) -> Result<Config, ConfigError> {
+        match contents {
+            Some(contents) => toml::from_str(contents).map_err(|source| ConfigError::Parse {
+                path: path.into(),
+                source,
+            }),
+            None => Ok(Config::default()),
+        }
+    }
+}
+
+/// Result of reloading a changed config file.
+#[derive(Debug, PartialEq)]
+pub struct ConfigReload {
+    /// The new settings, if they changed.
+    pub settings: Option<Settings>,
+    /// True if something changed that can't be applied without restarting.
+    pub restart_required: bool,
+}
+
+/// Watches the config file for external edits.
+///
+/// The file is polled rather than relying on platform file notifications since editors
+/// replace files in a variety of ways, and polling handles all of them. The contents are
+/// compared rather than the modification time since the config file is tiny, and some
+/// file systems have a coarse modification time resolution.
+pub struct ConfigWatcher {
+    path: PathBuf,
+    config: Config,
+    contents: Option<String>,
+    last_poll: Instant,
+}
+
+impl ConfigWatcher {
+    /// Loads the config at the given path and starts watching it.
+    ///
+    /// A config file that fails to load is logged and replaced with the defaults so that
+    /// a typo in the config file doesn't prevent the player from starting.
+    pub fn new(path: impl Into<PathBuf>) -> Self {
+        let path = path.into();
+        let contents = read_contents(&path).unwrap_or_else(|err| {
+            log::error!("{err}");
+            None
+        });
+        let config = Config::parse(&path, contents.as_deref()).unwrap_or_else(|err| {
+            log::error!("{err}");
+            Config::default()
+        });
+        Self {
+            path,
+            config,
+            contents,
+            last_poll: Instant::now(),
+        }
+    }
+
+    pub fn config(&self) -> &Config {
+        &self.config
+    }
+
+    /// Reloads the config file if it changed since the last poll.
+    ///
+    /// If the new config fails to load, the previous config remains in effect.
+    pub fn poll(&mut self) -> Option<Result<ConfigReload, ConfigError>> {
+        if self.last_poll.elapsed() < POLL_INTERVAL {
+            return None;
+        }
+        self.reload_if_modified()
+    }
+
+    fn reload_if_modified(&mut self) -> Option<Result<ConfigReload, ConfigError>> {
+        self.last_poll = Instant::now();
+        let contents = match read_contents(&self.path) {
+            Ok(contents) if contents == self.contents => return None,
+            Ok(contents) => contents,
+            Err(err) => return Some(Err(err)),
+        };
+        self.contents = contents;
+
+        log::info!("config file {:?} changed, reloading", self.path);
+        let config = match Config::parse(&self.path, self.contents.as_deref()) {
+            Ok(config) => config,
+            Err(err) => return Some(Err(err)),
+        };
+        let reload = ConfigReload {
+            settings: (config.settings != self.config.settings).then(|| config.settings.clone()),
+            restart_required: config.audio != self.config.audio
+                || config.metadata != self.config.metadata
+                || config.guest_queue != self.config.guest_queue
+                || config.osc != self.config.osc
+                || config.cast != self.config.cast,
+        };
+        self.config = config;
+        Some(Ok(reload))
+    }
+
+    /// Saves settings changed in the UI to the config file.
+    ///
+    /// The file is edited rather than rewritten so that its comments, and the tables
+    /// that aren't settings, are kept.
+    pub fn save_settings(&mut self, settings: &Settings) -> Result<(), ConfigError> {
+        let saved: Document = toml::to_string(settings)
+            .expect("serializable")
+            .parse()
+            .expect("serialized settings are valid TOML");
+        self.edit(|document| {
+            // Settings that are no longer set, such as optional ones, need removing
+            let unset: Vec<String> = document
+                .iter()
+                .map(|(key, _)| key.to_string())
+                .filter(|key| !NON_SETTINGS_TABLES.contains(&key.as_str()))
+                .filter(|key| !saved.contains_key(key))
+                .collect();
+            for key in unset {
+                document.remove(&key);
+            }
+            for (key, item) in saved.iter() {
+                document[key] = item.clone();
+            }
+        })
+    }
+
+    /// Saves the audio output device to the config file. `None` uses the system default.
+    pub fn save_output_device(&mut self, name: Option<&str>) -> Result<(), ConfigError> {
+        self.edit(|document| match name {
+            Some(name) => document["audio"]["output-device"] = toml_edit::value(name),
+            None => {
+                if let Some(audio) = document
+                    .get_mut("audio")
+                    .and_then(|audio| audio.as_table_like_mut())
+                {
+                    audio.remove("output-device");
+                }
+            }
+        })
+    }
+
+    /// Edits the config file, and takes the result as the current config so that the next
+    /// poll doesn't treat the change as an external edit.
+    fn edit(&mut self, edit: impl FnOnce(&mut Document)) -> Result<(), ConfigError> {
+        let contents = read_contents(&self.path)?;
+        let mut document: Document =
+            contents
+                .as_deref()
+                .unwrap_or_default()
+                .parse()
+                .map_err(|source| ConfigError::Edit {
+                    path: self.path.clone(),
+                    source,
+                })?;
+        edit(&mut document);
+        let contents = document.to_string();
+        let config = Config::parse(&self.path, Some(&contents))?;
+        let write = || {
+            if let Some(dir) = self.path.parent() {
+                fs::create_dir_all(dir)?;
+            }
+            fs::write(&self.path, &contents)
+        };
+        write().map_err(|source| ConfigError::Write {
+            path: self.path.clone(),
+            source,
+        })?;
+        self.contents = Some(contents);
+        self.config = config;
+        Ok(())
+    }
+}
+
+/// Reads the config file, returning `None` if it doesn't exist.
+fn read_contents(path: &Path) -> Result<Option<String>, ConfigError> {
+    match fs::read_to_string(path) {
+        Ok(contents) => Ok(Some(contents)),
+        Err(err) if err.kind() == io::ErrorKind::NotFound => Ok(None),
+        Err(source) => Err(ConfigError::Read {
+            path: path.into(),
+            source,
+        }),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use millenium_post_office::{
+        frontend::settings::{KeyAction, Theme, Visualizer},
+        types::NormalizationMode,
+    };
+    use pretty_assertions::assert_eq;
+
+    struct TestDir(PathBuf);
+    impl TestDir {
+        fn new(name: &str) -> Self {
+            let path =
+                std::env::temp_dir().join(format!("{APP_NAME}-{name}-{}", std::process::id()));
+            fs::create_dir_all(&path).unwrap();
+            Self(path)
+        }
+    }
+    impl Drop for TestDir {
+        fn drop(&mut self) {
+            let _ = fs::remove_dir_all(&self.0);
+        }
+    }
+
+    fn write(path: &Path, contents: &str) {
+        fs::write(path, contents).unwrap();
+    }
+
+    #[test]
+    fn missing_config_uses_defaults() {
+        let dir = TestDir::new("config-missing");
+        let mut watcher = ConfigWatcher::new(dir.0.join("config.toml"));
+        assert_eq!(&Config::default(), watcher.config());
+        assert!(watcher.reload_if_modified().is_none());
+    }
+
+    #[test]
+    fn load_folder_defaults() {
+        let dir = TestDir::new("config-folders");
+        let path = dir.0.join("config.toml");
+        write(
+            &path,
+            "[[folders]]\n\
+             path = \"/audiobooks\"\n\
+             intro-skip-secs = 15.0\n\
+             crossfade-secs = 0.0\n\
+             [[folders]]\n\
+             path = \"~/Podcasts\"\n\
+             [[folders]]\n\
+             path = \"/mixes\"\n\
+             crossfade-secs = 4.0\n\
+             gap-secs = 1.0\n\
+             [[folders]]\n\
+             path = \"/live\"\n\
+             crossfade-secs = 0.0\n\
+             gap-secs = 2.0\n",
+        );
+        let config = Config::load(&path).unwrap();
+        assert_eq!(4, config.folders.len());
+        assert_eq!(
+            Some(FolderDefaults {
+                folder: "/audiobooks".into(),
+                intro_skip: Some(Duration::from_secs(15)),
+                track_transition: Some(TrackTransition::Immediate),
+            }),
+            config.folders[0].folder_defaults()
+        );
+        let home = dirs::home_dir().unwrap();
+        assert_eq!(
+            Some(home.join("Podcasts").to_str().unwrap()),
+            config.folders[1]
+                .folder_defaults()
+                .as_ref()
+                .map(|defaults| defaults.folder.as_str())
+        );
+        assert_eq!(
+            None,
+            config.folders[1]
+                .folder_defaults()
+                .and_then(|defaults| defaults.track_transition)
+        );
+        assert_eq!(
+            Some(TrackTransition::Overlap(Duration::from_secs(4))),
+            config.folders[2]
+                .folder_defaults()
+                .and_then(|defaults| defaults.track_transition)
+        );
+        assert_eq!(
+            Some(TrackTransition::Gap(Duration::from_secs(2))),
+            config.folders[3]
+                .folder_defaults()
+                .and_then(|defaults| defaults.track_transition)
+        );
+    }
+
+    #[test]
+    fn load_partial_config() {
+        let dir = TestDir::new("config-partial");
+        let path = dir.0.join("config.toml");
+        write(
+            &path,
+            "visualizer = \"off\"\n\
+             locale = \"fi-FI\"\n\
+             ui-scale = 1.5\n\
+             normalization = \"playlist-peak\"\n\
+             peak-target-db = -3.0\n\
+             prevent-sleep = false\n\
+             media-keys = false\n\
+             shuffle-seed = 42\n\
+             [keybindings]\n\
+             p = \"play-pause\"\n\
+             [scrobbling]\n\
+             listen-brainz = true\n\
+             [metadata]\n\
+             fallback-encodings = [\"windows-1251\"]\n\
+             providers = [\"embedded\"]\n\
+             [guest-queue]\n\
+             enabled = true\n\
+             [osc]\n\
+             target = \"127.0.0.1:9000\"\n\
+             [cast]\n\
+             enabled = true\n",
+        );
+        let config = Config::load(&path).unwrap();
+        assert_eq!(Theme::Default, config.settings.theme);
+        assert_eq!(Visualizer::Off, config.settings.visualizer);
+        assert_eq!(Some("fi-FI"), config.settings.locale.as_deref());
+        assert_eq!(1.5, config.settings.ui_scale);
+        assert_eq!(Some(-3.0), config.settings.peak_normalization());
+        assert_eq!(
+            Some(&KeyAction::PlayPause),
+            config.settings.keybindings.get("p")
+        );
+        assert_eq!(1, config.settings.keybindings.len());
+        assert!(config.settings.scrobbling.listen_brainz);
+        assert!(!config.settings.scrobbling.last_fm);
+        assert!(!config.settings.prevent_sleep);
+        assert!(!config.settings.media_keys);
+        assert_eq!(Some(42), config.settings.shuffle_seed);
+        assert_eq!(AudioConfig::default(), config.audio);
+        assert_eq!(
+            vec!["windows-1251".to_string()],
+            config.metadata.fallback_encodings
+        );
+        assert_eq!(
+            Some(vec!["embedded".to_string()]),
+            config.metadata.providers
+        );
+        assert!(config.guest_queue.enabled);
+        assert!(config.cast.enabled);
+        assert_eq!(CastConfig::default().port, config.cast.port);
+        assert_eq!(GuestQueueConfig::default().port, config.guest_queue.port);
+    }
+
+    #[test]
+    fn reload_changed_config() {
+        let dir = TestDir::new("config-reload");
+        let path = dir.0.join("config.toml");
+        write(&path, "visualizer = \"waveform\"\n");
+        let mut watcher = ConfigWatcher::new(&path);
+        assert!(watcher.reload_if_modified().is_none());
+
+        write(&path, "visualizer = \"off\"\n");
+        let reload = watcher.reload_if_modified().unwrap().unwrap();
+        assert_eq!(
+            Visualizer::Off,
+            reload.settings.expect("settings changed").visualizer
+        );
+        assert!(!reload.restart_required);
+        assert!(watcher.reload_if_modified().is_none());
+
+        write(
+            &path,
+            "visualizer = \"off\"\n[audio]\noutput-device = \"Speakers\"\n",
+        );
+        let reload = watcher.reload_if_modified().unwrap().unwrap();
+        assert_eq!(None, reload.settings);
+        assert!(reload.restart_required);
+    }
+
+    #[test]
+    fn save_settings_keeps_comments() {
+        let dir = TestDir::new("config-save");
+        let path = dir.0.join("config.toml");
+        write(
+            &path,
+            "# Night listening\n\
+             visualizer = \"off\"\n\
+             shuffle-seed = 4\n\
+             \n\
+             # Desk speakers\n\
+             [audio]\n\
+             output-device = \"Speakers\"\n",
+        );
+        let mut watcher = ConfigWatcher::new(&path);
+        let mut settings = watcher.config().settings.clone();
+        settings.normalization = NormalizationMode::Album;
+        settings.shuffle_seed = None;
+        watcher.save_settings(&settings).unwrap();
+
+        let contents = fs::read_to_string(&path).unwrap();
+        assert!(contents.contains("# Night listening"), "{contents}");
+        assert!(contents.contains("# Desk speakers"), "{contents}");
+        assert!(!contents.contains("shuffle-seed"), "{contents}");
+        let config = Config::load(&path).unwrap();
+        assert_eq!(settings, config.settings);
+        assert_eq!(Some("Speakers"), config.audio.output_device.as_deref());
+        // Saving isn't mistaken for an external edit
+        assert!(watcher.reload_if_modified().is_none());
+
+        watcher.save_output_device(Some("USB DAC")).unwrap();
+        let config = Config::load(&path).unwrap();
+        assert_eq!(Some("USB DAC"), config.audio.output_device.as_deref());
+        watcher.save_output_device(None).unwrap();
+        assert_eq!(None, Config::load(&path).unwrap().audio.output_device);
+        assert_eq!(settings, Config::load(&path).unwrap().settings);
+    }
+
+    #[test]
+    fn load_channel_maps() {
+        let dir = TestDir::new("config-channel-maps");
+        let path = dir.0.join("config.toml");
+        write(
+            &path,
+            "[audio.channel-maps]\n\
+             \"Speakers\" = \"R L\"\n\
+             \"Audio Interface\" = \"- - L R\"\n",
+        );
+        let config = Config::load(&path).unwrap();
+        assert_eq!(
+            vec![
+                ("Audio Interface".to_string(), "- - L R".to_string()),
+                ("Speakers".to_string(), "R L".to_string()),
+            ],
+            config
+                .audio
+                .channel_maps
+                .iter()
+                .map(|(device, map)| (device.clone(), map.to_string()))
+                .collect::<Vec<_>>()
+        );
+
+        write(&path, "[audio.channel-maps]\n\"Speakers\" = \"L C R\"\n");
+        assert!(matches!(
+            Config::load(&path),
+            Err(ConfigError::Parse { .. })
+        ));
+    }
+
+    #[test]
+    fn load_device_options() {
+        let dir = TestDir::new("config-device-options");
+        let path = dir.0.join("config.toml");
+        write(
+            &path,
+            "[audio]\nidle-timeout-secs = 0\npad-final-chunk = false\n",
+        );
+        let options = Config::load(&path).unwrap().audio.device_options();
+        assert_eq!(Duration::ZERO, options.idle_timeout);
+        assert!(!options.pad_final_chunk);
+        assert_eq!(None, options.release_after);
+
+        write(
+            &path,
+            "[audio]\nrelease-device-after-secs = 30\nfade-millis = 0\n",
+        );
+        let options = Config::load(&path).unwrap().audio.device_options();
+        assert_eq!(Some(Duration::from_secs(30)), options.release_after);
+        assert_eq!(Duration::ZERO, options.fade);
+
+        write(&path, "[audio]\nidle-timeout-secs = -1\n");
+        let options = Config::load(&path).unwrap().audio.device_options();
+        assert_eq!(DeviceOptions::default(), options);
+    }
+
+    #[test]
+    fn invalid_config_keeps_previous() {
+        let dir = TestDir::new("config-invalid");
+        let path = dir.0.join("config.toml");
+        write(&path, "visualizer = \"off\"\n");
+        let mut watcher = ConfigWatcher::new(&path);
+
+        write(&path, "visualizer = \"sparkles\"\n");
+        assert!(matches!(
+            watcher.reload_if_modified(),
+            Some(Err(ConfigError::Parse { .. }))
+        ));
+        assert_eq!(Visualizer::Off, watcher.config().settings.visualizer);
+        // The error is only reported once per edit
+        assert!(watcher.reload_if_modified().is_none());
+    }
+}
@@ -0,0 +1,167 @@
+// This file is part of Millenium Player.
+// Copyright (C) 2023 John DiSanti.
+//
+// Millenium Player is free software: you can redistribute it and/or modify it under the terms of
+// the GNU General Public License as published by the Free Software Foundation, either version 3 of
+// the License, or (at your option) any later version.
+//
+// Millenium Player is distributed in the hope that it will be useful, but WITHOUT ANY WARRANTY;
+// without even the implied warranty of MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE. See
+// the GNU General Public License for more details.
+//
+// You should have received a copy of the GNU General Public License along with Millenium Player.
+// If not, see <https://www.gnu.org/licenses/>.
+
+use crate::{
+    config::DuckingConfig,
+    guest_queue::{parse_request_line, query_param, read_request, Response},
+};
+use millenium_post_office::{
+    broadcast::Broadcaster, frontend::message::FrontendMessage, types::Volume,
+};
+use std::{
+    io,
+    net::{Ipv4Addr, TcpListener, TcpStream},
+    thread,
+};
+use url::Url;
+
+/// Lowers the volume while other audio plays when asked to over HTTP on localhost, so that
+/// a VoIP app or script can duck the music for the length of a call.
+///
+/// - `POST /duck` lowers the volume to the configured level, or to `?level=` percent.
+/// - `POST /restore` brings it back up.
+///
+/// It only listens on localhost since anything that can reach it can silence the music.
+pub struct DuckingServer {
+    port: u16,
+}
+
+impl DuckingServer {
+    /// Starts listening on the configured port.
+    pub fn start(
+        config: &DuckingConfig,
+        broadcaster: Broadcaster<FrontendMessage>,
+    ) -> io::Result<Self> {
+        let listener = TcpListener::bind((Ipv4Addr::LOCALHOST, config.port))?;
+        let port = listener.local_addr()?.port();
+        let level = config.level_percent;
+        thread::Builder::new()
+            .name("ducking".into())
+            .spawn(move || {
+                for stream in listener.incoming() {
+                    let result =
+                        stream.and_then(|stream| handle_connection(stream, level, &broadcaster));
+                    if let Err(err) = result {
+                        log::warn!("failed to answer ducking request: {err}");
+                    }
+                }
+            })?;
+        log::info!("listening for ducking requests on port {port}");
+        Ok(Self { port })
+    }
+
+    pub fn port(&self) -> u16 {
+        self.port
+    }
+}
+
+fn handle_connection(
+    stream: TcpStream,
+    level: u8,
+    broadcaster: &Broadcaster<FrontendMessage>,
+) -> io::Result<()> {
+    let request_line = read_request(&stream)?;
+    let response = match parse_request_line(&request_line) {
+        Some((method, url)) => route(method, &url, level, broadcaster),
+        None => Response::text("400 Bad Request", "Bad request"),
+    };
+    response.write_to(&stream)
+}
+
+fn route(
+    method: &str,
+    url: &Url,
+    level: u8,
+    broadcaster: &Broadcaster<FrontendMessage>,
+) -> Response {
+    match (method, url.path()) {
+        ("POST", "/duck") => {
+            let level = match query_param(url, "level").map(|level| level.parse::<u8>()) {
+                None => level,
+                Some(Ok(level)) if level <= 100 => level,
+                Some(_) => return Response::text("400 Bad Request", "The level must be 0 to 100"),
+            };
+            broadcaster.broadcast(FrontendMessage::MediaControlDuck {
+                level: Some(Volume::from_percentage(level as f32 / 100.0)),
+            });
+            Response::text("200 OK", "Ducked")
+        }
+        ("POST", "/restore") => {
+            broadcaster.broadcast(FrontendMessage::MediaControlDuck { level: None });
+            Response::text("200 OK", "Restored")
+        }
+        _ => Response::text("404 Not Found", "Not found"),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use millenium_post_office::broadcast::NoChannels;
+    use std::io::{Read, Write};
+
+    fn request(port: u16, method: &str, target: &str) -> String {
+        let mut stream = TcpStream::connect((Ipv4Addr::LOCALHOST, port)).unwrap();
+        write!(
+            stream,
+            "{method} {target} HTTP/1.1\r\nHost: localhost\r\n\r\n"
+        )
+        .unwrap();
+        let mut response = String::new();
+        stream.read_to_string(&mut response).unwrap();
+        response
+    }
+
+    #[test]
+    fn duck_and_restore() {
+        let broadcaster = Broadcaster::new();
+        let sub = broadcaster.subscribe("test", NoChannels);
+        let server = DuckingServer::start(
+            &DuckingConfig {
+                enabled: true,
+                port: 0,
+                level_percent: 50,
+            },
+            broadcaster.clone(),
+        )
+        .unwrap();
+
+        let ducked = request(server.port(), "POST", "/duck");
+        assert!(ducked.starts_with("HTTP/1.1 200 OK"), "{ducked}");
+        assert!(matches!(
+            sub.try_recv(),
+            Some(FrontendMessage::MediaControlDuck { level: Some(level) })
+                if level == Volume::from_percentage(0.5)
+        ));
+
+        let ducked = request(server.port(), "POST", "/duck?level=10");
+        assert!(ducked.starts_with("HTTP/1.1 200 OK"), "{ducked}");
+        assert!(matches!(
+            sub.try_recv(),
+            Some(FrontendMessage::MediaControlDuck { level: Some(level) })
+                if level == Volume::from_percentage(0.1)
+        ));
+
+        let restored = request(server.port(), "POST", "/restore");
+        assert!(restored.starts_with("HTTP/1.1 200 OK"), "{restored}");
+        assert!(matches!(
+            sub.try_recv(),
+            Some(FrontendMessage::MediaControlDuck { level: None })
+        ));
+
+        assert!(request(server.port(), "POST", "/duck?level=200").starts_with("HTTP/1.1 400"));
+        assert!(request(server.port(), "GET", "/duck").starts_with("HTTP/1.1 404"));
+        assert!(sub.try_recv().is_none());
+    }
+}
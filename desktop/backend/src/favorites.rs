@@ -0,0 +1,253 @@
+// This file is part of Millenium Player.
+// Copyright (C) 2023 John DiSanti.
+//
+// Millenium Player is free software: you can redistribute it and/or modify it under the terms of
+// the GNU General Public License as published by the Free Software Foundation, either version 3 of
+// the License, or (at your option) any later version.
+//
+// Millenium Player is distributed in the hope that it will be useful, but WITHOUT ANY WARRANTY;
+// without even the implied warranty of MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE. See
+// the GNU General Public License for more details.
+//
+// You should have received a copy of the GNU General Public License along with Millenium Player.
+// If not, see <https://www.gnu.org/licenses/>.
+
+use crate::APP_NAME;
+use millenium_core::location::{InferredLocationType, Location};
+use millenium_post_office::types::{Favorite, FavoriteKind};
+use serde::{Deserialize, Serialize};
+use std::{
+    fs, io,
+    path::{Path, PathBuf},
+};
+
+#[derive(Debug, thiserror::Error)]
+pub enum FavoritesError {
+    #[error("failed to read favorites file {path:?}: {source}")]
+    Read { path: PathBuf, source: io::Error },
+    #[error("failed to parse favorites file {path:?}: {source}")]
+    Parse {
+        path: PathBuf,
+        source: toml::de::Error,
+    },
+    #[error("failed to save favorites file {path:?}: {source}")]
+    Write { path: PathBuf, source: io::Error },
+}
+
+/// Contents of the favorites file.
+#[derive(Debug, Default, Deserialize, Serialize)]
+struct FavoritesFile {
+    #[serde(default, rename = "favorite")]
+    favorites: Vec<Favorite>,
+}
+
+/// Favorite stations, files, folders, and playlists, which are saved whenever they change.
+///
+/// Favorites are kept apart from the library so that they work in simple mode too.
+pub struct Favorites {
+    path: PathBuf,
+    favorites: Vec<Favorite>,
+}
+
+impl Favorites {
+    /// Default location of the favorites file.
+    pub fn default_path() -> Option<PathBuf> {
+        dirs::data_dir().map(|dir| dir.join(APP_NAME).join("favorites.toml"))
+    }
+
+    /// Loads the favorites file at the given path. There are no favorites if it doesn't exist.
+    pub fn load(path: impl Into<PathBuf>) -> Result<Self, FavoritesError> {
+        let path = path.into();
+        let favorites = match fs::read_to_string(&path) {
+            Ok(contents) => {
+                let file: FavoritesFile =
+                    toml::from_str(&contents).map_err(|source| FavoritesError::Parse {
+                        path: path.clone(),
+                        source,
+                    })?;
+                file.favorites
+            }
+            Err(err) if err.kind() == io::ErrorKind::NotFound => Vec::new(),
+            Err(source) => return Err(FavoritesError::Read { path, source }),
+        };
+        Ok(Self { path, favorites })
+    }
+
+    pub fn list(&self) -> &[Favorite] {
+        &self.favorites
+    }
+
+    /// Pins a location, returning false if it was already a favorite.
+    pub fn add(&mut self, location: &Location) -> Result<bool, FavoritesError> {
+        if self.contains(location.as_str()) {
+            return Ok(false);
+        }
+        self.favorites.push(favorite_for(location));
+        self.save().map(|_| true)
+    }
+
+    /// Unpins a location, returning false if it wasn't a favorite.
+    pub fn remove(&mut self, location: &str) -> Result<bool, FavoritesError> {
+        if !self.contains(location) {
+            return Ok(false);
+        }
+        self.favorites
+            .retain(|favorite| favorite.location != location);
+        self.save().map(|_| true)
+    }
+
+    fn contains(&self, location: &str) -> bool {
+        self.favorites
+            .iter()
+            .any(|favorite| favorite.location == location)
+    }
+
+    fn save(&self) -> Result<(), FavoritesError> {
+        let file = FavoritesFile {
+            favorites: self.favorites.clone(),
+        };
+        let contents = toml::to_string(&file).expect("serializable");
+        let write = |path: &Path| {
+            if let Some(dir) = path.parent() {
+                fs::create_dir_all(dir)?;
+            }
+            fs::write(path, contents)
+        };
+        write(&self.path).map_err(|source| FavoritesError::Write {
+            path: self.path.clone(),
+            source,
+        })
+    }
+}
+
+/// Checks that the contents of a favorites file can be loaded.
+pub fn validate(contents: &str) -> Result<(), toml::de::Error> {
+    toml::from_str::<FavoritesFile>(contents).map(|_| ())
+}
+
+/// Describes a location as a favorite, named after its file, folder, or stream.
+pub fn favorite_for(location: &Location) -> Favorite {
+    let (name, kind) = match location {
+        Location::Path(path) => {
+            let kind = if path.is_dir() {
+                FavoriteKind::Folder
+            } else if location.sniff_type().is_playlist() {
+                FavoriteKind::Playlist
+            } else {
+                FavoriteKind::File
+            };
+            let name = match kind {
+                FavoriteKind::Folder => path.file_name(),
+                _ => path.file_stem(),
+            };
+            (name.map(str::to_string), kind)
+        }
+        Location::Url(url) => {
+            let kind = match location.inferred_type() {
+                InferredLocationType::Playlist => FavoriteKind::Playlist,
+                _ => FavoriteKind::Station,
+            };
+            let name = url
+                .path_segments()
+                .and_then(|segments| segments.filter(|segment| !segment.is_empty()).last())
+                .map(|segment| segment.split('.').next().unwrap_or(segment))
+                .or(url.host_str())
+                .map(str::to_string);
+            (name, kind)
+        }
+    };
+    Favorite {
+        name: name.unwrap_or_else(|| location.to_string()),
+        location: location.to_string(),
+        kind,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use pretty_assertions::assert_eq;
+    use std::str::FromStr;
+
+    struct TestDir(PathBuf);
+    impl TestDir {
+        fn new(name: &str) -> Self {
+            let path =
+                std::env::temp_dir().join(format!("{APP_NAME}-{name}-{}", std::process::id()));
+            fs::create_dir_all(&path).unwrap();
+            Self(path)
+        }
+    }
+    impl Drop for TestDir {
+        fn drop(&mut self) {
+            let _ = fs::remove_dir_all(&self.0);
+        }
+    }
+
+    #[test]
+    fn add_and_remove() {
+        let dir = TestDir::new("favorites");
+        let path = dir.0.join("data").join("favorites.toml");
+        let station = Location::from_str("http://example.com/radio/groove.mp3").unwrap();
+        let folder = Location::path(dir.0.to_str().unwrap());
+
+        let mut favorites = Favorites::load(&path).unwrap();
+        assert!(favorites.list().is_empty());
+        assert!(favorites.add(&station).unwrap());
+        assert!(favorites.add(&folder).unwrap());
+        assert!(!favorites.add(&station).unwrap());
+
+        let expected = vec![
+            Favorite {
+                name: "groove".into(),
+                location: station.to_string(),
+                kind: FavoriteKind::Station,
+            },
+            Favorite {
+                name: dir.0.file_name().unwrap().to_str().unwrap().into(),
+                location: folder.to_string(),
+                kind: FavoriteKind::Folder,
+            },
+        ];
+        assert_eq!(expected, Favorites::load(&path).unwrap().list());
+
+        assert!(favorites.remove(station.as_str()).unwrap());
+        assert!(!favorites.remove(station.as_str()).unwrap());
+        assert_eq!(&expected[1..], Favorites::load(&path).unwrap().list());
+    }
+
+    #[test]
+    fn names_and_kinds() {
+        let favorite = |location: &str| {
+            let favorite = favorite_for(&Location::from_str(location).unwrap());
+            (favorite.name, favorite.kind)
+        };
+        assert_eq!(
+            ("Mix".to_string(), FavoriteKind::Playlist),
+            favorite("/music/Mix.m3u8")
+        );
+        assert_eq!(
+            ("Song".to_string(), FavoriteKind::File),
+            favorite("/music/Song.flac")
+        );
+        assert_eq!(
+            ("example.com".to_string(), FavoriteKind::Station),
+            favorite("http://example.com/")
+        );
+        assert_eq!(
+            ("stations".to_string(), FavoriteKind::Playlist),
+            favorite("http://example.com/stations.pls")
+        );
+    }
+
+    #[test]
+    fn invalid_file() {
+        let dir = TestDir::new("favorites-invalid");
+        let path = dir.0.join("favorites.toml");
+        fs::write(&path, "favorite = 5").unwrap();
+        assert!(matches!(
+            Favorites::load(&path),
+            Err(FavoritesError::Parse { .. })
+        ));
+    }
+}
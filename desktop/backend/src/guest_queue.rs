@@ -0,0 +1,509 @@
+// This file is part of Millenium Player.
+// Copyright (C) 2023 John DiSanti.
+//
+// Millenium Player is free software: you can redistribute it and/or modify it under the terms of
+// the GNU General Public License as published by the Free Software Foundation, either version 3 of
+// the License, or (at your option) any later version.
+//
+// Millenium Player is distributed in the hope that it will be useful, but WITHOUT ANY WARRANTY;
+// without even the implied warranty of MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE. See
+// the GNU General Public License for more details.
+//
+// You should have received a copy of the GNU General Public License along with Millenium Player.
+// If not, see <https://www.gnu.org/licenses/>.
+
+use crate::config::GuestQueueConfig;
+use millenium_core::playlist::Playlist;
+use millenium_desktop_assets::asset;
+use millenium_post_office::{
+    broadcast::Broadcaster, frontend::message::FrontendMessage, types::QueuedTrack,
+};
+use std::{
+    io::{self, BufRead, BufReader, Read, Write},
+    net::{IpAddr, Ipv4Addr, TcpListener, TcpStream, UdpSocket},
+    sync::{
+        atomic::{AtomicUsize, Ordering},
+        mpsc, Arc,
+    },
+    thread,
+    time::Duration,
+};
+use url::Url;
+
+/// Most search results sent to a guest at once.
+const SEARCH_LIMIT: usize = 50;
+/// How long a guest has to send a request, and how long it waits for the player to answer.
+const TIMEOUT: Duration = Duration::from_secs(5);
+/// Largest request head that is read from a guest.
+const MAX_REQUEST_SIZE: u64 = 8 * 1024;
+/// Most guest connections that are answered at once, since each of them has its own thread.
+const MAX_GUESTS: usize = 16;
+
+/// Why a guest's track wasn't queued.
+#[derive(Debug, PartialEq, thiserror::Error)]
+pub enum Refusal {
+    #[error("The host isn't taking requests right now.")]
+    NotAccepting,
+    #[error("That track isn't in the playlist.")]
+    NotInPlaylist,
+    #[error("You already have {0} tracks waiting. Try again after one of them plays.")]
+    TooManyQueued(usize),
+}
+
+enum GuestRequest {
+    Search {
+        query: String,
+        reply: mpsc::Sender<Vec<QueuedTrack>>,
+    },
+    Queue {
+        location: String,
+        guest: IpAddr,
+        reply: mpsc::Sender<Result<(), Refusal>>,
+    },
+}
+
+/// Serves a web page where guests on the local network can search the playlist and
+/// queue tracks to play next.
+///
+/// The server runs on its own thread and hands requests over to be answered by [`poll`],
+/// since the playlist lives on the UI thread. Each connection is read on a thread of its
+/// own so that a slow guest doesn't hold up the others, and connections past
+/// [`MAX_GUESTS`] are turned away.
+///
+/// [`poll`]: GuestQueue::poll
+pub struct GuestQueue {
+    requests: mpsc::Receiver<GuestRequest>,
+    port: u16,
+    accepting: bool,
+    max_queued_per_guest: usize,
+    /// Locations that each guest has waiting in the play queue.
+    queued_by: Vec<(IpAddr, String)>,
+}
+
+impl GuestQueue {
+    /// Starts serving the guest page on the configured port.
+    pub fn start(config: &GuestQueueConfig) -> io::Result<Self> {
+        let listener = TcpListener::bind((Ipv4Addr::UNSPECIFIED, config.port))?;
+        let port = listener.local_addr()?.port();
+        let (sender, requests) = mpsc::channel();
+        let slots = ConnectionSlots::new(MAX_GUESTS);
+        thread::Builder::new()
+            .name("guest-queue".into())
+            .spawn(move || {
+                for stream in listener.incoming() {
+                    let stream = match stream {
+                        Ok(stream) => stream,
+                        Err(err) => {
+                            log::warn!("failed to accept a guest: {err}");
+                            continue;
+                        }
+                    };
+                    let Some(slot) = slots.take() else {
+                        log::warn!("turned away a guest since {MAX_GUESTS} are connected");
+                        let _ = Response::text("503 Service Unavailable", "Too many guests")
+                            .write_to(&stream);
+                        continue;
+                    };
+                    let sender = sender.clone();
+                    let spawned = thread::Builder::new()
+                        .name("guest-connection".into())
+                        .spawn(move || {
+                            let _slot = slot;
+                            if let Err(err) = handle_connection(stream, &sender) {
+                                log::warn!("failed to answer guest: {err}");
+                            }
+                        });
+                    if let Err(err) = spawned {
+                        log::warn!("failed to start answering a guest: {err}");
+                    }
+                }
+            })?;
+        log::info!("serving the guest queue on port {port}");
+        Ok(Self {
+            requests,
+            port,
+            accepting: true,
+            max_queued_per_guest: config.max_queued_per_guest,
+            queued_by: Vec::new(),
+        })
+    }
+
+    /// Address that guests can open in their browser.
+    pub fn url(&self) -> String {
+        format!("http://{}:{}/", local_address(), self.port)
+    }
+
+    pub fn accepting(&self) -> bool {
+        self.accepting
+    }
+
+    /// Sets whether guests can queue tracks. Searching is always allowed.
+    pub fn set_accepting(&mut self, accepting: bool) {
+        self.accepting = accepting;
+    }
+
+    /// Forgets the guests' tracks that are no longer waiting, such as after they've started
+    /// playing or the queue was cleared.
+    pub fn play_queue_changed(&mut self, queue: &[QueuedTrack]) {
+        self.queued_by
+            .retain(|(_, location)| queue.iter().any(|track| &track.location == location));
+    }
+
+    /// Answers the requests that guests made since the last poll.
+    pub fn poll(&mut self, playlist: &Playlist, broadcaster: &Broadcaster<FrontendMessage>) {
+        while let Ok(request) = self.requests.try_recv() {
+            match request {
+                GuestRequest::Search { query, reply } => {
+                    let _ = reply.send(playlist.search(&query, SEARCH_LIMIT));
+                }
+                GuestRequest::Queue {
+                    location,
+                    guest,
+                    reply,
+                } => {
+                    let _ = reply.send(self.queue(location, guest, playlist, broadcaster));
+                }
+            }
+        }
+    }
+
+    fn queue(
+        &mut self,
+        location: String,
+        guest: IpAddr,
+        playlist: &Playlist,
+        broadcaster: &Broadcaster<FrontendMessage>,
+    ) -> Result<(), Refusal> {
+        if !self.accepting {
+            return Err(Refusal::NotAccepting);
+        }
+        // Only what's already in the playlist can be queued, so that guests can't play
+        // arbitrary files or streams
+        if !playlist.contains_location(&location) {
+            return Err(Refusal::NotInPlaylist);
+        }
+        let waiting = self.queued_by.iter().filter(|(by, _)| *by == guest).count();
+        if waiting >= self.max_queued_per_guest {
+            return Err(Refusal::TooManyQueued(waiting));
+        }
+        log::info!("guest {guest} queued {location}");
+        // Guests pick from the playlist, so every track they queue is already in it
+        broadcaster.broadcast(FrontendMessage::QueueLocations {
+            locations: vec![location.clone()],
+            allow_duplicates: true,
+        });
+        self.queued_by.push((guest, location));
+        Ok(())
+    }
+}
+
+/// Address of this computer on the local network, or localhost if it isn't on one.
+pub(crate) fn local_address() -> IpAddr {
+    // Connecting a UDP socket doesn't send anything, but picks the interface for the route
+    UdpSocket::bind((Ipv4Addr::UNSPECIFIED, 0))
+        .and_then(|socket| {
+            socket.connect((Ipv4Addr::new(192, 0, 2, 1), 9))?;
+            socket.local_addr()
+        })
+        .map(|address| address.ip())
+        .unwrap_or(IpAddr::V4(Ipv4Addr::LOCALHOST))
+}
+
+/// Counts the connections that are being answered, each on its own thread.
+#[derive(Clone, Debug)]
+pub(crate) struct ConnectionSlots {
+    taken: Arc<AtomicUsize>,
+    max: usize,
+}
+
+impl ConnectionSlots {
+    pub(crate) fn new(max: usize) -> Self {
+        Self {
+            taken: Arc::default(),
+            max,
+        }
+    }
+
+    /// Takes a slot for a new connection, or returns `None` if they're all taken.
+    pub(crate) fn take(&self) -> Option<ConnectionSlot> {
+        self.taken
+            .fetch_update(Ordering::SeqCst, Ordering::SeqCst, |taken| {
+                (taken < self.max).then_some(taken + 1)
+            })
+            .ok()?;
+        Some(ConnectionSlot(self.taken.clone()))
+    }
+}
+
+/// Frees its slot once the connection is done.
+pub(crate) struct ConnectionSlot(Arc<AtomicUsize>);
+
+impl Drop for ConnectionSlot {
+    fn drop(&mut self) {
+        self.0.fetch_sub(1, Ordering::SeqCst);
+    }
+}
+
+pub(crate) struct Response {
+    status: &'static str,
+    content_type: &'static str,
+    body: Vec<u8>,
+}
+
+impl Response {
+    pub(crate) fn new(
+        status: &'static str,
+        content_type: &'static str,
+        body: impl Into<Vec<u8>>,
+    ) -> Self {
+        Self {
+            status,
+            content_type,
+            body: body.into(),
+        }
+    }
+
+    pub(crate) fn text(status: &'static str, body: impl Into<String>) -> Self {
+        Self::new(status, "text/plain; charset=utf-8", body.into())
+    }
+
+    pub(crate) fn write_to(&self, mut stream: impl Write) -> io::Result<()> {
+        write!(
+            stream,
+            "HTTP/1.1 {}\r\nContent-Type: {}\r\nContent-Length: {}\r\nConnection: close\r\n\r\n",
+            self.status,
+            self.content_type,
+            self.body.len()
+        )?;
+        stream.write_all(&self.body)?;
+        stream.flush()
+    }
+}
+
+fn handle_connection(stream: TcpStream, requests: &mpsc::Sender<GuestRequest>) -> io::Result<()> {
+    let guest = stream.peer_addr()?.ip();
+    let request_line = read_request(&stream)?;
+    let response = match parse_request_line(&request_line) {
+        Some((method, url)) => route(method, &url, guest, requests),
+        None => Response::text("400 Bad Request", "Bad request"),
+    };
+    response.write_to(&stream)
+}
+
+/// Reads the head of a request and returns its first line.
+pub(crate) fn read_request(stream: &TcpStream) -> io::Result<String> {
+    stream.set_read_timeout(Some(TIMEOUT))?;
+    let mut reader = BufReader::new(stream.take(MAX_REQUEST_SIZE));
+    let mut request_line = String::new();
+    reader.read_line(&mut request_line)?;
+    // None of the headers matter, but they need to be read before responding
+    let mut header = String::new();
+    while reader.read_line(&mut header)? > 0 && !header.trim().is_empty() {
+        header.clear();
+    }
+    Ok(request_line)
+}
+
+/// Splits a request line like `GET /search?q=abc HTTP/1.1` into the method and URL.
+pub(crate) fn parse_request_line(line: &str) -> Option<(&str, Url)> {
+    let mut parts = line.split_whitespace();
+    let (method, target) = (parts.next()?, parts.next()?);
+    if !target.starts_with('/') {
+        return None;
+    }
+    let url = Url::parse("http://guest/").ok()?.join(target).ok()?;
+    Some((method, url))
+}
+
+pub(crate) fn query_param(url: &Url, name: &str) -> Option<String> {
+    url.query_pairs()
+        .find(|(key, _)| key == name)
+        .map(|(_, value)| value.into_owned())
+}
+
+fn route(
+    method: &str,
+    url: &Url,
+    guest: IpAddr,
+    requests: &mpsc::Sender<GuestRequest>,
+) -> Response {
+    let unavailable = || Response::text("503 Service Unavailable", "The player isn't answering");
+    match (method, url.path()) {
+        ("GET", "/") => match asset("static/guest-queue.html") {
+            Ok(page) => Response::new("200 OK", page.mime, page.contents.into_owned()),
+            Err(err) => {
+                log::error!("failed to load the guest page: {err}");
+                Response::text("500 Internal Server Error", "The page couldn't be loaded")
+            }
+        },
+        ("GET", "/search") => {
+            let (reply, answer) = mpsc::channel();
+            let query = query_param(url, "q").unwrap_or_default();
+            if requests
+                .send(GuestRequest::Search { query, reply })
+                .is_err()
+            {
+                return unavailable();
+            }
+            match answer.recv_timeout(TIMEOUT) {
+                Ok(tracks) => Response::new(
+                    "200 OK",
+                    "application/json",
+                    serde_json::to_vec(&tracks).expect("serializable"),
+                ),
+                Err(_) => unavailable(),
+            }
+        }
+        ("POST", "/queue") => {
+            let Some(location) = query_param(url, "location") else {
+                return Response::text("400 Bad Request", "No track was given");
+            };
+            let (reply, answer) = mpsc::channel();
+            let request = GuestRequest::Queue {
+                location,
+                guest,
+                reply,
+            };
+            if requests.send(request).is_err() {
+                return unavailable();
+            }
+            match answer.recv_timeout(TIMEOUT) {
+                Ok(Ok(())) => Response::text("200 OK", "Queued"),
+                Ok(Err(refusal @ Refusal::TooManyQueued(_))) => {
+                    Response::text("429 Too Many Requests", refusal.to_string())
+                }
+                Ok(Err(refusal)) => Response::text("403 Forbidden", refusal.to_string()),
+                Err(_) => unavailable(),
+            }
+        }
+        _ => Response::text("404 Not Found", "Not found"),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use millenium_core::playlist::PlaylistManager;
+    use millenium_post_office::broadcast::NoChannels;
+    use std::{thread::JoinHandle, time::Instant};
+
+    fn request(port: u16, method: &str, target: &str) -> JoinHandle<String> {
+        let request = format!("{method} {target} HTTP/1.1\r\nHost: localhost\r\n\r\n");
+        thread::spawn(move || {
+            let mut stream = TcpStream::connect((Ipv4Addr::LOCALHOST, port)).unwrap();
+            stream.write_all(request.as_bytes()).unwrap();
+            let mut response = String::new();
+            stream.read_to_string(&mut response).unwrap();
+            response
+        })
+    }
+
+    /// Answers guest requests until the pending request has its response.
+    fn answer(
+        guest_queue: &mut GuestQueue,
+        manager: &PlaylistManager,
+        ui: &Broadcaster<FrontendMessage>,
+        pending: JoinHandle<String>,
+    ) -> String {
+        while !pending.is_finished() {
+            guest_queue.poll(manager.playlist(), ui);
+            thread::sleep(Duration::from_millis(1));
+        }
+        pending.join().unwrap()
+    }
+
+    #[test]
+    fn search_and_queue() {
+        let (player, ui) = (Broadcaster::new(), Broadcaster::new());
+        let ui_sub = ui.subscribe("test", NoChannels);
+        let mut manager = PlaylistManager::new(player.clone(), ui.clone());
+        ui_sub.broadcast(FrontendMessage::LoadLocations {
+            locations: vec!["one.ogg".into(), "two.ogg".into()],
+        });
+        manager.update();
+
+        let mut guest_queue = GuestQueue::start(&GuestQueueConfig {
+            enabled: true,
+            port: 0,
+            max_queued_per_guest: 1,
+        })
+        .unwrap();
+        let port = guest_queue.port;
+        let send = |guest_queue: &mut GuestQueue, method: &str, target: &str| {
+            let pending = request(port, method, target);
+            answer(guest_queue, &manager, &ui, pending)
+        };
+
+        let found = send(&mut guest_queue, "GET", "/search?q=TWO");
+        assert!(found.starts_with("HTTP/1.1 200 OK"), "{found}");
+        assert!(found.ends_with(r#"[{"location":"two.ogg","title":null,"artist":null}]"#));
+
+        let queued = send(&mut guest_queue, "POST", "/queue?location=two.ogg");
+        assert!(queued.starts_with("HTTP/1.1 200 OK"), "{queued}");
+        assert!(matches!(
+            ui_sub.try_recv(),
+            Some(FrontendMessage::QueueLocations { locations, allow_duplicates: true })
+                if locations == ["two.ogg"]
+        ));
+
+        let too_many = send(&mut guest_queue, "POST", "/queue?location=one.ogg");
+        assert!(too_many.starts_with("HTTP/1.1 429"), "{too_many}");
+        let not_listed = send(&mut guest_queue, "POST", "/queue?location=%2Fetc%2Fpasswd");
+        assert!(not_listed.starts_with("HTTP/1.1 403"), "{not_listed}");
+        assert!(send(&mut guest_queue, "GET", "/elsewhere").starts_with("HTTP/1.1 404"));
+        assert!(ui_sub.try_recv().is_none());
+
+        // The guest can queue again once their track has played
+        guest_queue.play_queue_changed(&[]);
+        guest_queue.set_accepting(false);
+        let refused = send(&mut guest_queue, "POST", "/queue?location=one.ogg");
+        assert!(refused.starts_with("HTTP/1.1 403"), "{refused}");
+        guest_queue.set_accepting(true);
+        let queued = send(&mut guest_queue, "POST", "/queue?location=one.ogg");
+        assert!(queued.starts_with("HTTP/1.1 200 OK"), "{queued}");
+    }
+
+    #[test]
+    fn slow_guest_doesnt_hold_up_others() {
+        let (player, ui) = (Broadcaster::new(), Broadcaster::new());
+        let manager = PlaylistManager::new(player, ui.clone());
+        let mut guest_queue = GuestQueue::start(&GuestQueueConfig {
+            enabled: true,
+            port: 0,
+            max_queued_per_guest: 1,
+        })
+        .unwrap();
+
+        // Connects without ever sending a request
+        let _slow = TcpStream::connect((Ipv4Addr::LOCALHOST, guest_queue.port)).unwrap();
+        let started = Instant::now();
+        let pending = request(guest_queue.port, "GET", "/search?q=a");
+        let found = answer(&mut guest_queue, &manager, &ui, pending);
+        assert!(found.starts_with("HTTP/1.1 200 OK"), "{found}");
+        assert!(started.elapsed() < TIMEOUT);
+    }
+
+    #[test]
+    fn connection_slots() {
+        let slots = ConnectionSlots::new(2);
+        let taken = (0..2)
+            .map(|_| slots.take().expect("free slot"))
+            .collect::<Vec<_>>();
+        assert!(slots.take().is_none());
+
+        drop(taken);
+        assert!(slots.take().is_some());
+    }
+
+    #[test]
+    fn parse_request_lines() {
+        let (method, url) = parse_request_line("GET /search?q=a%20b HTTP/1.1\r\n").unwrap();
+        assert_eq!("GET", method);
+        assert_eq!("/search", url.path());
+        assert_eq!(Some("a b".to_string()), query_param(&url, "q"));
+
+        assert!(parse_request_line("GET http://elsewhere/ HTTP/1.1").is_none());
+        assert!(parse_request_line("GET").is_none());
+        assert!(parse_request_line("").is_none());
+    }
+}
@@ -12,24 +12,52 @@
 // You should have received a copy of the GNU General Public License along with Millenium Player.
 // If not, see <https://www.gnu.org/licenses/>.
 
-use http::{Request, Response, StatusCode};
+use http::{Method, Request, Response, StatusCode};
 use millenium_desktop_assets::asset;
 use millenium_post_office::{
     bytes::copy_f32s_into_ne_bytes,
-    frontend::state::{PlaybackState, WaveformState},
+    frontend::{
+        message::FrontendMessage,
+        state::{
+            DebugState, FavoritesState, PlaybackState, PlaylistState, SettingsState, UiPrefs,
+            UiPrefsState, WaveformState, WAVEFORM_BIN_COUNT_HEADER,
+        },
+    },
+    types::InstanceHealth,
 };
-use std::{borrow::Cow, mem::size_of};
+use std::{borrow::Cow, mem::size_of, time::Instant};
 
 pub struct InternalProtocol {
     playback_state: PlaybackState,
     waveform_state: WaveformState,
+    settings_state: SettingsState,
+    favorites_state: FavoritesState,
+    debug_state: DebugState,
+    playlist_state: PlaylistState,
+    ui_prefs_state: UiPrefsState,
+    /// When the backend started, for reporting its uptime.
+    started: Instant,
 }
 
 impl InternalProtocol {
-    pub fn new(playback_state: PlaybackState, waveform_state: WaveformState) -> Self {
+    pub fn new(
+        playback_state: PlaybackState,
+        waveform_state: WaveformState,
+        settings_state: SettingsState,
+        favorites_state: FavoritesState,
+        debug_state: DebugState,
+        playlist_state: PlaylistState,
+        ui_prefs_state: UiPrefsState,
+    ) -> Self {
         Self {
             playback_state,
             waveform_state,
+            settings_state,
+            favorites_state,
+            debug_state,
+            playlist_state,
+            ui_prefs_state,
+            started: Instant::now(),
         }
     }
 
@@ -64,7 +92,14 @@ impl InternalProtocol {
     ) -> Response<Cow<'static, [u8]>> {
         match path {
             "/ipc/playback" => self.handle_ipc_playback(request),
+            "/ipc/album-art" => self.handle_ipc_album_art(request),
+            "/ipc/settings" => self.handle_ipc_settings(request),
+            "/ipc/favorites" => self.handle_ipc_favorites(request),
             "/ipc/waveform" => self.handle_ipc_waveform(request),
+            "/ipc/health" => self.handle_ipc_health(request),
+            "/ipc/playlist" => self.handle_ipc_playlist(request),
+            "/ipc/ui-prefs" => self.handle_ipc_ui_prefs(request),
+            "/ipc/debug/memory" => self.handle_ipc_debug_memory(request),
             _ => Self::error_not_found(),
         }
     }
@@ -86,6 +121,100 @@ impl InternalProtocol {
             .expect("valid response")
     }
 
+    fn handle_ipc_album_art(&self, _request: Request<Vec<u8>>) -> Response<Cow<'static, [u8]>> {
+        let state = self.playback_state.borrow();
+        match &state.album_art {
+            Some(art) => Response::builder()
+                .status(StatusCode::OK)
+                .header("Content-Type", &art.mime_type)
+                .body(art.data.to_vec().into())
+                .expect("valid response"),
+            None => Self::error_not_found(),
+        }
+    }
+
+    fn handle_ipc_settings(&self, _request: Request<Vec<u8>>) -> Response<Cow<'static, [u8]>> {
+        let settings = self.settings_state.borrow();
+        let body = serde_json::to_vec(&*settings).expect("serializable");
+        Response::builder()
+            .status(StatusCode::OK)
+            .header("Content-Type", "application/json")
+            .body(body.into())
+            .expect("valid response")
+    }
+
+    fn handle_ipc_favorites(&self, _request: Request<Vec<u8>>) -> Response<Cow<'static, [u8]>> {
+        let favorites = self.favorites_state.borrow();
+        let body = serde_json::to_vec(&*favorites).expect("serializable");
+        Response::builder()
+            .status(StatusCode::OK)
+            .header("Content-Type", "application/json")
+            .body(body.into())
+            .expect("valid response")
+    }
+
+    fn handle_ipc_playlist(&self, _request: Request<Vec<u8>>) -> Response<Cow<'static, [u8]>> {
+        let playlist = self.playlist_state.borrow();
+        let body = serde_json::to_vec(&*playlist).expect("serializable");
+        Response::builder()
+            .status(StatusCode::OK)
+            .header("Content-Type", "application/json")
+            .body(body.into())
+            .expect("valid response")
+    }
+
+    /// The frontend reads its UI preferences with a GET, and replaces them with a PUT.
+    fn handle_ipc_ui_prefs(&self, request: Request<Vec<u8>>) -> Response<Cow<'static, [u8]>> {
+        if request.method() == Method::PUT {
+            return match serde_json::from_slice::<UiPrefs>(request.body()) {
+                Ok(prefs) => {
+                    self.ui_prefs_state.mutate(|state| *state = prefs);
+                    Response::builder()
+                        .status(StatusCode::NO_CONTENT)
+                        .body(Cow::Borrowed(&b""[..]))
+                        .expect("valid response")
+                }
+                Err(err) => {
+                    log::error!("invalid UI preferences from the web view: {err}");
+                    Response::builder()
+                        .status(StatusCode::BAD_REQUEST)
+                        .body(Cow::Borrowed(&b""[..]))
+                        .expect("valid response")
+                }
+            };
+        }
+        let prefs = self.ui_prefs_state.borrow();
+        let body = serde_json::to_vec(&*prefs).expect("serializable");
+        Response::builder()
+            .status(StatusCode::OK)
+            .header("Content-Type", "application/json")
+            .body(body.into())
+            .expect("valid response")
+    }
+
+    fn handle_ipc_debug_memory(&self, _request: Request<Vec<u8>>) -> Response<Cow<'static, [u8]>> {
+        let state = self.debug_state.borrow();
+        let body = serde_json::to_vec(&state.buffer_stats).expect("serializable");
+        Response::builder()
+            .status(StatusCode::OK)
+            .header("Content-Type", "application/json")
+            .body(body.into())
+            .expect("valid response")
+    }
+
+    fn handle_ipc_health(&self, _request: Request<Vec<u8>>) -> Response<Cow<'static, [u8]>> {
+        let health = InstanceHealth {
+            uptime: self.started.elapsed(),
+            ..self.debug_state.borrow().health.clone()
+        };
+        let body = serde_json::to_vec(&health).expect("serializable");
+        Response::builder()
+            .status(StatusCode::OK)
+            .header("Content-Type", "application/json")
+            .body(body.into())
+            .expect("valid response")
+    }
+
     fn handle_ipc_waveform(&self, _request: Request<Vec<u8>>) -> Response<Cow<'static, [u8]>> {
         let state = self.waveform_state.borrow();
         if let Some(waves) = &state.waveform {
@@ -95,21 +224,40 @@ impl InternalProtocol {
             Response::builder()
                 .status(StatusCode::OK)
                 .header("Content-Type", "application/octet-stream")
+                .header(WAVEFORM_BIN_COUNT_HEADER, waves.bin_count().to_string())
                 .body(body.into())
                 .expect("valid response")
         } else {
-            Self::error_not_found()
+            // The waveform was cleared because nothing is playing
+            Response::builder()
+                .status(StatusCode::NO_CONTENT)
+                .body(Cow::Borrowed(&b""[..]))
+                .expect("valid response")
         }
     }
 }
 
+/// Parses a message posted by the web view, which is either a single message or an array of
+/// messages to apply together.
+pub fn parse_post(post: &str) -> Result<Vec<FrontendMessage>, serde_json::Error> {
+    if post.trim_start().starts_with('[') {
+        serde_json::from_str(post)
+    } else {
+        serde_json::from_str(post).map(|message| vec![message])
+    }
+}
+
 #[cfg(test)]
 mod tests {
-    use std::time::Duration;
+    use std::{sync::Arc, time::Duration};
 
     use millenium_post_office::{
         bytes::ne_bytes_to_f32s,
-        frontend::state::{PlaybackStateData, Track, Waveform},
+        frontend::{
+            settings::{Settings, Visualizer},
+            state::{AlbumArt, PlaybackStateData, PlaylistStateData, TimeDisplay, Track, Waveform},
+        },
+        types::{BufferStats, DeviceStatus, Favorite, FavoriteKind, PlaylistEntryDetails},
     };
 
     use super::*;
@@ -118,7 +266,15 @@ mod tests {
     fn asset_not_found() {
         let playback_state = PlaybackState::new();
         let waveform_state = WaveformState::new();
-        let protocol = InternalProtocol::new(playback_state, waveform_state);
+        let protocol = InternalProtocol::new(
+            playback_state,
+            waveform_state,
+            SettingsState::new(),
+            FavoritesState::new(),
+            DebugState::new(),
+            PlaylistState::new(),
+            UiPrefsState::new(),
+        );
 
         let request = Request::builder()
             .uri("/does-not-exist")
@@ -134,7 +290,15 @@ mod tests {
     fn ipc_not_found() {
         let playback_state = PlaybackState::new();
         let waveform_state = WaveformState::new();
-        let protocol = InternalProtocol::new(playback_state, waveform_state);
+        let protocol = InternalProtocol::new(
+            playback_state,
+            waveform_state,
+            SettingsState::new(),
+            FavoritesState::new(),
+            DebugState::new(),
+            PlaylistState::new(),
+            UiPrefsState::new(),
+        );
 
         let request = Request::builder()
             .uri("/ipc/does-not-exist")
@@ -150,7 +314,15 @@ mod tests {
     fn respond_with_asset() {
         let playback_state = PlaybackState::new();
         let waveform_state = WaveformState::new();
-        let protocol = InternalProtocol::new(playback_state, waveform_state);
+        let protocol = InternalProtocol::new(
+            playback_state,
+            waveform_state,
+            SettingsState::new(),
+            FavoritesState::new(),
+            DebugState::new(),
+            PlaylistState::new(),
+            UiPrefsState::new(),
+        );
 
         let request = Request::builder()
             .uri("/static/test_asset.txt")
@@ -170,7 +342,15 @@ mod tests {
     fn respond_with_playback_data() {
         let playback_state = PlaybackState::new();
         let waveform_state = WaveformState::new();
-        let protocol = InternalProtocol::new(playback_state.clone(), waveform_state);
+        let protocol = InternalProtocol::new(
+            playback_state.clone(),
+            waveform_state,
+            SettingsState::new(),
+            FavoritesState::new(),
+            DebugState::new(),
+            PlaylistState::new(),
+            UiPrefsState::new(),
+        );
 
         playback_state.mutate(|state| {
             state.current_track = Some(Track {
@@ -198,11 +378,256 @@ mod tests {
         pretty_assertions::assert_eq!(*playback_state.borrow(), actual);
     }
 
+    #[test]
+    fn respond_with_settings() {
+        let settings_state = SettingsState::new();
+        let protocol = InternalProtocol::new(
+            PlaybackState::new(),
+            WaveformState::new(),
+            settings_state.clone(),
+            FavoritesState::new(),
+            DebugState::new(),
+            PlaylistState::new(),
+            UiPrefsState::new(),
+        );
+
+        settings_state.mutate(|settings| {
+            settings.visualizer = Visualizer::Off;
+        });
+
+        let request = Request::builder()
+            .uri("/ipc/settings")
+            .method("GET")
+            .body(Vec::new())
+            .unwrap();
+        let response = protocol.handle_request(request);
+        assert_eq!(200, response.status());
+        assert_eq!(
+            "application/json",
+            response.headers().get("content-type").unwrap()
+        );
+
+        let actual: Settings = serde_json::from_slice(response.body()).unwrap();
+        pretty_assertions::assert_eq!(*settings_state.borrow(), actual);
+    }
+
+    #[test]
+    fn respond_with_favorites() {
+        let favorites_state = FavoritesState::new();
+        let protocol = InternalProtocol::new(
+            PlaybackState::new(),
+            WaveformState::new(),
+            SettingsState::new(),
+            favorites_state.clone(),
+            DebugState::new(),
+            PlaylistState::new(),
+            UiPrefsState::new(),
+        );
+
+        favorites_state.mutate(|favorites| {
+            favorites.push(Favorite {
+                name: "Radio".into(),
+                location: "http://example.com/radio".into(),
+                kind: FavoriteKind::Station,
+            });
+        });
+
+        let request = Request::builder()
+            .uri("/ipc/favorites")
+            .method("GET")
+            .body(Vec::new())
+            .unwrap();
+        let response = protocol.handle_request(request);
+        assert_eq!(200, response.status());
+
+        let actual: Vec<Favorite> = serde_json::from_slice(response.body()).unwrap();
+        pretty_assertions::assert_eq!(*favorites_state.borrow(), actual);
+    }
+
+    #[test]
+    fn respond_with_buffer_stats() {
+        let debug_state = DebugState::new();
+        let protocol = InternalProtocol::new(
+            PlaybackState::new(),
+            WaveformState::new(),
+            SettingsState::new(),
+            FavoritesState::new(),
+            debug_state.clone(),
+            PlaylistState::new(),
+            UiPrefsState::new(),
+        );
+
+        let expected = BufferStats {
+            sink_input_frames: 1,
+            output_samples: 2,
+            waveform_samples: 3,
+            artwork_bytes: 4,
+        };
+        debug_state.mutate(|state| state.buffer_stats = expected);
+
+        let request = Request::builder()
+            .uri("/ipc/debug/memory")
+            .method("GET")
+            .body(Vec::new())
+            .unwrap();
+        let response = protocol.handle_request(request);
+        assert_eq!(200, response.status());
+        assert_eq!(
+            "application/json",
+            response.headers().get("content-type").unwrap()
+        );
+
+        let actual: BufferStats = serde_json::from_slice(response.body()).unwrap();
+        assert_eq!(expected, actual);
+    }
+
+    #[test]
+    fn respond_with_health() {
+        let debug_state = DebugState::new();
+        let protocol = InternalProtocol::new(
+            PlaybackState::new(),
+            WaveformState::new(),
+            SettingsState::new(),
+            FavoritesState::new(),
+            debug_state.clone(),
+            PlaylistState::new(),
+            UiPrefsState::new(),
+        );
+
+        debug_state.mutate(|state| {
+            state.health.player_alive = false;
+            state.health.device = DeviceStatus::Failed;
+            state.health.last_error = Some("player thread panicked".into());
+        });
+
+        let request = Request::builder()
+            .uri("/ipc/health")
+            .method("GET")
+            .body(Vec::new())
+            .unwrap();
+        let response = protocol.handle_request(request);
+        assert_eq!(200, response.status());
+        assert_eq!(
+            "application/json",
+            response.headers().get("content-type").unwrap()
+        );
+
+        let actual: InstanceHealth = serde_json::from_slice(response.body()).unwrap();
+        assert!(!actual.player_alive);
+        assert_eq!(DeviceStatus::Failed, actual.device);
+        assert_eq!(Some("player thread panicked".into()), actual.last_error);
+        assert!(actual.uptime <= protocol.started.elapsed());
+    }
+
+    #[test]
+    fn respond_with_playlist() {
+        let playlist_state = PlaylistState::new();
+        let protocol = InternalProtocol::new(
+            PlaybackState::new(),
+            WaveformState::new(),
+            SettingsState::new(),
+            FavoritesState::new(),
+            DebugState::new(),
+            playlist_state.clone(),
+            UiPrefsState::new(),
+        );
+
+        let expected = PlaylistStateData {
+            entries: vec![PlaylistEntryDetails {
+                id: 1,
+                location: "/music/song.ogg".into(),
+                skipped: false,
+                title: Some("Song".into()),
+                artist: Some("Artist".into()),
+                album: None,
+                duration: Some(Duration::from_secs(180)),
+                dynamic_range: Some(11),
+            }],
+            current_index: Some(0),
+            total_duration: Some(Duration::from_secs(180)),
+        };
+        playlist_state.mutate(|state| {
+            state.entries = expected.entries.clone();
+            state.current_index = expected.current_index;
+            state.total_duration = expected.total_duration;
+        });
+
+        let request = Request::builder()
+            .uri("/ipc/playlist")
+            .method("GET")
+            .body(Vec::new())
+            .unwrap();
+        let response = protocol.handle_request(request);
+        assert_eq!(200, response.status());
+        assert_eq!(
+            "application/json",
+            response.headers().get("content-type").unwrap()
+        );
+
+        let actual: PlaylistStateData = serde_json::from_slice(response.body()).unwrap();
+        assert_eq!(expected, actual);
+    }
+
+    #[test]
+    fn save_and_respond_with_ui_prefs() {
+        let ui_prefs_state = UiPrefsState::new();
+        let protocol = InternalProtocol::new(
+            PlaybackState::new(),
+            WaveformState::new(),
+            SettingsState::new(),
+            FavoritesState::new(),
+            DebugState::new(),
+            PlaylistState::new(),
+            ui_prefs_state.clone(),
+        );
+
+        let prefs = UiPrefs {
+            playlist_visible: true,
+            visualizer_popped_out: true,
+            time_display: TimeDisplay::Remaining,
+        };
+        let request = Request::builder()
+            .uri("/ipc/ui-prefs")
+            .method("PUT")
+            .body(serde_json::to_vec(&prefs).unwrap())
+            .unwrap();
+        let response = protocol.handle_request(request);
+        assert_eq!(204, response.status());
+        assert_eq!(prefs, *ui_prefs_state.borrow());
+
+        let request = Request::builder()
+            .uri("/ipc/ui-prefs")
+            .method("GET")
+            .body(Vec::new())
+            .unwrap();
+        let response = protocol.handle_request(request);
+        assert_eq!(200, response.status());
+        let actual: UiPrefs = serde_json::from_slice(response.body()).unwrap();
+        assert_eq!(prefs, actual);
+
+        let request = Request::builder()
+            .uri("/ipc/ui-prefs")
+            .method("PUT")
+            .body(b"not json".to_vec())
+            .unwrap();
+        let response = protocol.handle_request(request);
+        assert_eq!(400, response.status());
+        assert_eq!(prefs, *ui_prefs_state.borrow());
+    }
+
     #[test]
     fn respond_with_waveform_data() {
         let playback_state = PlaybackState::new();
         let waveform_state = WaveformState::new();
-        let protocol = InternalProtocol::new(playback_state, waveform_state.clone());
+        let protocol = InternalProtocol::new(
+            playback_state,
+            waveform_state.clone(),
+            SettingsState::new(),
+            FavoritesState::new(),
+            DebugState::new(),
+            PlaylistState::new(),
+            UiPrefsState::new(),
+        );
 
         waveform_state.mutate(|state| {
             state.waveform = Some(Waveform {
@@ -222,6 +647,10 @@ mod tests {
             "application/octet-stream",
             response.headers().get("content-type").unwrap()
         );
+        assert_eq!(
+            "3",
+            response.headers().get(WAVEFORM_BIN_COUNT_HEADER).unwrap()
+        );
 
         let body = response.body();
         let spectrum_bytes = &body[0..body.len() / 2];
@@ -233,4 +662,80 @@ mod tests {
         assert_eq!(&[1.0, 2.0, 3.0], &*spectrum);
         assert_eq!(&[4.0, 5.0, 6.0], &*amplitude);
     }
+
+    #[test]
+    fn respond_with_album_art() {
+        let playback_state = PlaybackState::new();
+        let protocol = InternalProtocol::new(
+            playback_state.clone(),
+            WaveformState::new(),
+            SettingsState::new(),
+            FavoritesState::new(),
+            DebugState::new(),
+            PlaylistState::new(),
+            UiPrefsState::new(),
+        );
+        let request = || {
+            Request::builder()
+                .uri("/ipc/album-art?id=1")
+                .method("GET")
+                .body(Vec::new())
+                .unwrap()
+        };
+        assert_eq!(404, protocol.handle_request(request()).status());
+
+        playback_state.mutate(|state| {
+            state.album_art = Some(AlbumArt {
+                id: 1,
+                mime_type: "image/png".into(),
+                data: Arc::new(b"png".to_vec()),
+            })
+        });
+        let response = protocol.handle_request(request());
+        assert_eq!(200, response.status());
+        assert_eq!("image/png", response.headers()["Content-Type"]);
+        assert_eq!(b"png", &response.body()[..]);
+    }
+
+    #[test]
+    fn respond_with_cleared_waveform() {
+        let playback_state = PlaybackState::new();
+        let waveform_state = WaveformState::new();
+        let protocol = InternalProtocol::new(
+            playback_state,
+            waveform_state,
+            SettingsState::new(),
+            FavoritesState::new(),
+            DebugState::new(),
+            PlaylistState::new(),
+            UiPrefsState::new(),
+        );
+
+        let request = Request::builder()
+            .uri("/ipc/waveform")
+            .method("GET")
+            .body(Vec::new())
+            .unwrap();
+        let response = protocol.handle_request(request);
+        assert_eq!(204, response.status());
+        assert!(response.body().is_empty());
+    }
+
+    #[test]
+    fn parse_posts() {
+        assert!(matches!(
+            parse_post(r#"{"kind":"MediaControlPause"}"#).unwrap()[..],
+            [FrontendMessage::MediaControlPause]
+        ));
+        assert!(matches!(
+            parse_post(r#"[{"kind":"MediaControlSeek","position":{"secs":5,"nanos":0}},{"kind":"MediaControlPlay"}]"#)
+                .unwrap()[..],
+            [
+                FrontendMessage::MediaControlSeek { position },
+                FrontendMessage::MediaControlPlay,
+            ] if position == Duration::from_secs(5)
+        ));
+        assert!(parse_post("[]").unwrap().is_empty());
+        assert!(parse_post(r#"[{"kind":"MediaControlPause"}, {"kind":"Nonsense"}]"#).is_err());
+    }
 }
@@ -15,14 +15,65 @@
 pub const APP_TITLE: &str = "Millenium Player";
 pub const APP_NAME: &str = "millenium-player";
 
+/// Album art from tags, or from image files next to the tracks.
+pub mod album_art;
+
+/// Export of the audio analysis for documenting masters or hearing tests.
+pub mod analysis;
+
 /// Command-line argument parsing.
 pub mod args;
 
+/// Export and import of settings, favorites, and playlists.
+pub mod backup;
+
+/// Serving the current track to browsers and network speakers on the local network.
+pub mod cast;
+
+/// Paths and URLs pasted into the window.
+pub mod clipboard;
+
+/// Config file loading and hot reloading.
+pub mod config;
+
+/// Lowering the volume while other audio plays, such as during a call.
+pub mod ducking;
+
 /// Common error types.
 pub mod error;
 
+/// Favorite stations, files, folders, and playlists.
+pub mod favorites;
+
+/// Web page for guests on the local network to queue tracks from.
+pub mod guest_queue;
+
 /// Inter-process communication with the UI's web view.
 pub mod ipc;
 
+/// Size-limited, rotating log file.
+pub mod log_file;
+
+/// Media keys that work while the window isn't focused.
+pub mod media_keys;
+
+/// Text file or named pipe with what's playing, for overlays and status bars.
+pub mod now_playing;
+
+/// Open Sound Control output of the audio analysis.
+pub mod osc;
+
+/// Handing locations to the player that's already running instead of opening another window.
+pub mod single_instance;
+
+/// Keeps the computer awake while playing.
+pub mod sleep_inhibit;
+
+/// Startup phase timing.
+pub mod startup;
+
 /// Web view UI.
 pub mod ui;
+
+/// Layout choices made in the UI, such as whether the playlist is shown.
+pub mod ui_prefs;
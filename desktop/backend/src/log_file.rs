@@ -0,0 +1,180 @@
+// This file is part of Millenium Player.
+// Copyright (C) 2023 John DiSanti.
+//
+// Millenium Player is free software: you can redistribute it and/or modify it under the terms of
+// the GNU General Public License as published by the Free Software Foundation, either version 3 of
+// the License, or (at your option) any later version.
+//
+// Millenium Player is distributed in the hope that it will be useful, but WITHOUT ANY WARRANTY;
+// without even the implied warranty of MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE. See
+// the GNU General Public License for more details.
+//
+// You should have received a copy of the GNU General Public License along with Millenium Player.
+// If not, see <https://www.gnu.org/licenses/>.
+
+use crate::APP_NAME;
+use std::{
+    fs::{self, File},
+    io::{self, Write},
+    path::{Path, PathBuf},
+    process::Command,
+};
+
+/// Size at which the log file is rotated.
+pub const MAX_LOG_FILE_SIZE: u64 = 5 * 1024 * 1024;
+/// Number of rotated log files to keep in addition to the current one.
+pub const LOG_FILE_RETENTION: usize = 4;
+
+/// Returns the directory that log files are written to.
+pub fn log_dir() -> Option<PathBuf> {
+    dirs::cache_dir().map(|cache_dir| cache_dir.join(APP_NAME))
+}
+
+/// Opens the log directory in the platform's file manager.
+pub fn open_log_dir() -> io::Result<()> {
+    let dir = log_dir()
+        .ok_or_else(|| io::Error::new(io::ErrorKind::NotFound, "failed to locate cache dir"))?;
+    let program = if cfg!(target_os = "windows") {
+        "explorer"
+    } else if cfg!(target_os = "macos") {
+        "open"
+    } else {
+        "xdg-open"
+    };
+    Command::new(program).arg(dir).spawn().map(|_| ())
+}
+
+/// Log file that moves itself aside once it reaches a maximum size.
+///
+/// Rotated files get a numbered suffix (`.1` is the most recent), and only
+/// `retention` of them are kept. The previous session's log is rotated on open
+/// rather than truncated so that it's still around after a crash.
+pub struct RotatingLogFile {
+    path: PathBuf,
+    max_size: u64,
+    retention: usize,
+    file: File,
+    size: u64,
+}
+
+impl RotatingLogFile {
+    /// Opens a new log file at `path`, rotating any existing one.
+    pub fn open(path: impl Into<PathBuf>, max_size: u64, retention: usize) -> io::Result<Self> {
+        let path = path.into();
+        if path.exists() {
+            rotate(&path, retention)?;
+        }
+        let file = File::create(&path)?;
+        Ok(Self {
+            path,
+            max_size,
+            retention,
+            file,
+            size: 0,
+        })
+    }
+
+    fn rotate(&mut self) -> io::Result<()> {
+        self.file.flush()?;
+        rotate(&self.path, self.retention)?;
+        self.file = File::create(&self.path)?;
+        self.size = 0;
+        Ok(())
+    }
+}
+
+impl Write for RotatingLogFile {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        // Always write at least once to an empty file so that huge records don't rotate forever
+        if self.size > 0 && self.size + buf.len() as u64 > self.max_size {
+            self.rotate()?;
+        }
+        let written = self.file.write(buf)?;
+        self.size += written as u64;
+        Ok(written)
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        self.file.flush()
+    }
+}
+
+fn rotated_path(path: &Path, index: usize) -> PathBuf {
+    let mut name = path.file_name().unwrap_or_default().to_os_string();
+    name.push(format!(".{index}"));
+    path.with_file_name(name)
+}
+
+/// Shifts the numbered log files up by one, dropping the oldest, and moves `path` to `.1`.
+fn rotate(path: &Path, retention: usize) -> io::Result<()> {
+    if retention == 0 {
+        return fs::remove_file(path);
+    }
+    let oldest = rotated_path(path, retention);
+    if oldest.exists() {
+        fs::remove_file(&oldest)?;
+    }
+    for index in (1..retention).rev() {
+        let from = rotated_path(path, index);
+        if from.exists() {
+            fs::rename(&from, rotated_path(path, index + 1))?;
+        }
+    }
+    fs::rename(path, rotated_path(path, 1))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    struct TestDir(PathBuf);
+    impl TestDir {
+        fn new(name: &str) -> Self {
+            let path =
+                std::env::temp_dir().join(format!("{APP_NAME}-{name}-{}", std::process::id()));
+            let _ = fs::remove_dir_all(&path);
+            fs::create_dir_all(&path).unwrap();
+            Self(path)
+        }
+    }
+    impl Drop for TestDir {
+        fn drop(&mut self) {
+            let _ = fs::remove_dir_all(&self.0);
+        }
+    }
+
+    fn read(path: &Path) -> String {
+        fs::read_to_string(path).unwrap()
+    }
+
+    #[test]
+    fn rotate_previous_session_on_open() {
+        let dir = TestDir::new("log-open");
+        let path = dir.0.join("test.log");
+        fs::write(&path, "previous").unwrap();
+
+        let mut log = RotatingLogFile::open(&path, 100, 2).unwrap();
+        log.write_all(b"current").unwrap();
+        log.flush().unwrap();
+
+        assert_eq!("current", read(&path));
+        assert_eq!("previous", read(&dir.0.join("test.log.1")));
+    }
+
+    #[test]
+    fn rotate_when_full_and_keep_retention_count() {
+        let dir = TestDir::new("log-rotate");
+        let path = dir.0.join("test.log");
+
+        let mut log = RotatingLogFile::open(&path, 4, 2).unwrap();
+        for record in ["aaaa", "bbbb", "cccc", "dd", "ee"] {
+            log.write_all(record.as_bytes()).unwrap();
+        }
+        log.flush().unwrap();
+
+        assert_eq!("ddee", read(&path));
+        assert_eq!("cccc", read(&dir.0.join("test.log.1")));
+        assert_eq!("bbbb", read(&dir.0.join("test.log.2")));
+        assert!(!dir.0.join("test.log.3").exists());
+    }
+}
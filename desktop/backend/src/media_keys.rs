@@ -0,0 +1,392 @@
+// This file is part of Millenium Player.
+// Copyright (C) 2023 John DiSanti.
+//
+// Millenium Player is free software: you can redistribute it and/or modify it under the terms of
+// the GNU General Public License as published by the Free Software Foundation, either version 3 of
+// the License, or (at your option) any later version.
+//
+// Millenium Player is distributed in the hope that it will be useful, but WITHOUT ANY WARRANTY;
+// without even the implied warranty of MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE. See
+// the GNU General Public License for more details.
+//
+// You should have received a copy of the GNU General Public License along with Millenium Player.
+// If not, see <https://www.gnu.org/licenses/>.
+
+use millenium_post_office::{broadcast::Broadcaster, frontend::message::FrontendMessage};
+
+/// Media keys on the keyboard that control playback.
+#[derive(Copy, Clone, Debug, Eq, PartialEq)]
+pub enum MediaKey {
+    PlayPause,
+    Next,
+    Previous,
+    Stop,
+}
+
+impl MediaKey {
+    /// Message the key translates into, the same as the frontend sends for its key bindings.
+    pub fn message(self, playing: bool) -> FrontendMessage {
+        match self {
+            Self::PlayPause if playing => FrontendMessage::MediaControlPause,
+            Self::PlayPause => FrontendMessage::MediaControlPlay,
+            Self::Next => FrontendMessage::MediaControlSkipForward,
+            Self::Previous => FrontendMessage::MediaControlSkipBack,
+            Self::Stop => FrontendMessage::MediaControlStop,
+        }
+    }
+}
+
+/// Listens for the media keys even when the window isn't focused.
+///
+/// The keys are grabbed on the X11 root window on Linux, and registered as hot keys on
+/// Windows. Other platforms, and Wayland sessions without XWayland, aren't supported.
+pub struct MediaKeys {
+    listener: Option<platform::Listener>,
+    /// Set when listening failed, so that it isn't retried every time the settings change.
+    failed: bool,
+}
+
+impl MediaKeys {
+    pub fn new(enabled: bool) -> Self {
+        let mut media_keys = Self {
+            listener: None,
+            failed: false,
+        };
+        media_keys.set_enabled(enabled);
+        media_keys
+    }
+
+    /// Starts or stops listening. Stopping releases the keys for other applications.
+    pub fn set_enabled(&mut self, enabled: bool) {
+        if !enabled {
+            if self.listener.take().is_some() {
+                log::info!("stopped listening for media keys");
+            }
+            return;
+        }
+        if self.listener.is_some() || self.failed {
+            return;
+        }
+        match platform::Listener::new() {
+            Ok(listener) => {
+                log::info!("listening for media keys");
+                self.listener = Some(listener);
+            }
+            Err(err) => {
+                log::warn!("failed to listen for media keys: {err}");
+                self.failed = true;
+            }
+        }
+    }
+
+    /// Broadcasts the messages for any media keys that were pressed since the last poll.
+    pub fn poll(&mut self, playing: bool, broadcaster: &Broadcaster<FrontendMessage>) {
+        let Some(listener) = self.listener.as_mut() else {
+            return;
+        };
+        // Play/pause toggles, so track the state across presses that arrive in the same poll
+        let mut playing = playing;
+        while let Some(key) = listener.poll() {
+            log::info!("media key pressed: {key:?}");
+            let message = key.message(playing);
+            match message {
+                FrontendMessage::MediaControlPlay => playing = true,
+                FrontendMessage::MediaControlPause | FrontendMessage::MediaControlStop => {
+                    playing = false
+                }
+                _ => {}
+            }
+            broadcaster.broadcast(message);
+        }
+    }
+}
+
+#[cfg(target_os = "linux")]
+mod platform {
+    use super::MediaKey;
+    use std::{
+        io,
+        os::raw::{c_int, c_uint},
+        ptr,
+        sync::atomic::{AtomicBool, Ordering},
+    };
+    use x11_dl::{keysym, xlib};
+
+    const KEYS: [(c_uint, MediaKey); 5] = [
+        (keysym::XF86XK_AudioPlay, MediaKey::PlayPause),
+        (keysym::XF86XK_AudioPause, MediaKey::PlayPause),
+        (keysym::XF86XK_AudioNext, MediaKey::Next),
+        (keysym::XF86XK_AudioPrev, MediaKey::Previous),
+        (keysym::XF86XK_AudioStop, MediaKey::Stop),
+    ];
+
+    /// Set by [`grab_error_handler`] when another application already grabbed a key.
+    static GRAB_FAILED: AtomicBool = AtomicBool::new(false);
+
+    unsafe extern "C" fn grab_error_handler(
+        _display: *mut xlib::Display,
+        event: *mut xlib::XErrorEvent,
+    ) -> c_int {
+        if (*event).error_code == xlib::BadAccess {
+            GRAB_FAILED.store(true, Ordering::Relaxed);
+        }
+        0
+    }
+
+    /// Holds key grabs on a separate X connection, which is polled from the event loop.
+    pub struct Listener {
+        xlib: xlib::Xlib,
+        display: *mut xlib::Display,
+        root: xlib::Window,
+        grabbed: Vec<(c_int, MediaKey)>,
+    }
+
+    impl Listener {
+        pub fn new() -> io::Result<Self> {
+            let xlib = xlib::Xlib::open()
+                .map_err(|err| io::Error::new(io::ErrorKind::Unsupported, err.to_string()))?;
+            // SAFETY: The display is only used by this listener, and closed when it's dropped
+            let display = unsafe { (xlib.XOpenDisplay)(ptr::null()) };
+            if display.is_null() {
+                return Err(io::Error::new(
+                    io::ErrorKind::Unsupported,
+                    "couldn't connect to an X server",
+                ));
+            }
+            let mut listener = Self {
+                root: unsafe { (xlib.XDefaultRootWindow)(display) },
+                xlib,
+                display,
+                grabbed: Vec::new(),
+            };
+            listener.grab_keys();
+            if listener.grabbed.is_empty() {
+                return Err(io::Error::new(
+                    io::ErrorKind::AddrInUse,
+                    "the media keys are taken by another application",
+                ));
+            }
+            Ok(listener)
+        }
+
+        fn grab_keys(&mut self) {
+            let xlib = &self.xlib;
+            for (keysym, key) in KEYS {
+                // SAFETY: The display is open for the lifetime of the listener
+                let keycode = unsafe { (xlib.XKeysymToKeycode)(self.display, keysym.into()) };
+                if keycode == 0 {
+                    continue;
+                }
+                let keycode = c_int::from(keycode);
+                // The default error handler exits the process, and a grab fails if another
+                // application (such as a desktop environment's media key daemon) has the key.
+                // The previous handler is restored right after the grab is synced.
+                GRAB_FAILED.store(false, Ordering::Relaxed);
+                unsafe {
+                    let previous = (xlib.XSetErrorHandler)(Some(grab_error_handler));
+                    (xlib.XGrabKey)(
+                        self.display,
+                        keycode,
+                        xlib::AnyModifier,
+                        self.root,
+                        xlib::False,
+                        xlib::GrabModeAsync,
+                        xlib::GrabModeAsync,
+                    );
+                    (xlib.XSync)(self.display, xlib::False);
+                    (xlib.XSetErrorHandler)(previous);
+                }
+                if GRAB_FAILED.load(Ordering::Relaxed) {
+                    log::warn!("media key {key:?} is taken by another application");
+                } else {
+                    self.grabbed.push((keycode, key));
+                }
+            }
+        }
+
+        pub fn poll(&mut self) -> Option<MediaKey> {
+            let xlib = &self.xlib;
+            // SAFETY: The display is open for the lifetime of the listener, and only
+            // key events for the grabbed keys are delivered on this connection
+            unsafe {
+                while (xlib.XPending)(self.display) > 0 {
+                    let mut event = xlib::XEvent { pad: [0; 24] };
+                    (xlib.XNextEvent)(self.display, &mut event);
+                    if event.get_type() != xlib::KeyPress {
+                        continue;
+                    }
+                    let keycode = event.key.keycode as c_int;
+                    if let Some((_, key)) = self.grabbed.iter().find(|(code, _)| *code == keycode) {
+                        return Some(*key);
+                    }
+                }
+            }
+            None
+        }
+    }
+
+    impl Drop for Listener {
+        fn drop(&mut self) {
+            // SAFETY: The display was opened in `new` and isn't used after this
+            unsafe {
+                for (keycode, _) in &self.grabbed {
+                    (self.xlib.XUngrabKey)(self.display, *keycode, xlib::AnyModifier, self.root);
+                }
+                (self.xlib.XCloseDisplay)(self.display);
+            }
+        }
+    }
+}
+
+#[cfg(target_os = "windows")]
+mod platform {
+    use super::MediaKey;
+    use std::{
+        io,
+        sync::mpsc::{self, Receiver},
+        thread::{self, JoinHandle},
+    };
+    use windows_sys::Win32::{
+        System::Threading::GetCurrentThreadId,
+        UI::{
+            Input::KeyboardAndMouse::{
+                RegisterHotKey, UnregisterHotKey, MOD_NOREPEAT, VK_MEDIA_NEXT_TRACK,
+                VK_MEDIA_PLAY_PAUSE, VK_MEDIA_PREV_TRACK, VK_MEDIA_STOP,
+            },
+            WindowsAndMessaging::{GetMessageW, PostThreadMessageW, MSG, WM_HOTKEY, WM_QUIT},
+        },
+    };
+
+    const KEYS: [(u16, MediaKey); 4] = [
+        (VK_MEDIA_PLAY_PAUSE, MediaKey::PlayPause),
+        (VK_MEDIA_NEXT_TRACK, MediaKey::Next),
+        (VK_MEDIA_PREV_TRACK, MediaKey::Previous),
+        (VK_MEDIA_STOP, MediaKey::Stop),
+    ];
+
+    /// Registers the hot keys on a thread with its own message loop, since hot key messages
+    /// go to the thread that registered them, and the window's event loop discards them.
+    pub struct Listener {
+        thread_id: u32,
+        thread: Option<JoinHandle<()>>,
+        keys: Receiver<MediaKey>,
+    }
+
+    impl Listener {
+        pub fn new() -> io::Result<Self> {
+            let (started_tx, started_rx) = mpsc::channel();
+            let (keys_tx, keys) = mpsc::channel();
+            let thread = thread::Builder::new()
+                .name("media-keys".into())
+                .spawn(move || {
+                    // SAFETY: Hot keys are registered for, and unregistered on, this thread
+                    unsafe {
+                        let mut registered = Vec::new();
+                        for (id, (vk, key)) in KEYS.into_iter().enumerate() {
+                            let id = id as i32;
+                            if RegisterHotKey(0, id, MOD_NOREPEAT, vk.into()) == 0 {
+                                log::warn!(
+                                    "media key {key:?} is taken by another application: {}",
+                                    io::Error::last_os_error()
+                                );
+                            } else {
+                                registered.push(id);
+                            }
+                        }
+                        let _ = started_tx.send((GetCurrentThreadId(), !registered.is_empty()));
+
+                        let mut msg: MSG = std::mem::zeroed();
+                        while GetMessageW(&mut msg, 0, 0, 0) > 0 {
+                            if msg.message == WM_HOTKEY {
+                                if let Some((_, key)) = KEYS.get(msg.wParam) {
+                                    let _ = keys_tx.send(*key);
+                                }
+                            }
+                        }
+                        for id in registered {
+                            UnregisterHotKey(0, id);
+                        }
+                    }
+                })?;
+            let (thread_id, any_registered) = started_rx
+                .recv()
+                .map_err(|_| io::Error::new(io::ErrorKind::Other, "media key thread exited"))?;
+            let listener = Self {
+                thread_id,
+                thread: Some(thread),
+                keys,
+            };
+            if !any_registered {
+                return Err(io::Error::new(
+                    io::ErrorKind::AddrInUse,
+                    "the media keys are taken by another application",
+                ));
+            }
+            Ok(listener)
+        }
+
+        pub fn poll(&mut self) -> Option<MediaKey> {
+            self.keys.try_recv().ok()
+        }
+    }
+
+    impl Drop for Listener {
+        fn drop(&mut self) {
+            // SAFETY: Posting to a thread that has exited just fails
+            unsafe { PostThreadMessageW(self.thread_id, WM_QUIT, 0, 0) };
+            if let Some(thread) = self.thread.take() {
+                let _ = thread.join();
+            }
+        }
+    }
+}
+
+#[cfg(not(any(target_os = "linux", target_os = "windows")))]
+mod platform {
+    use super::MediaKey;
+    use std::io;
+
+    pub struct Listener(());
+
+    impl Listener {
+        pub fn new() -> io::Result<Self> {
+            Err(io::Error::new(
+                io::ErrorKind::Unsupported,
+                "not supported on this platform",
+            ))
+        }
+
+        pub fn poll(&mut self) -> Option<MediaKey> {
+            None
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn translate_keys() {
+        assert!(matches!(
+            MediaKey::PlayPause.message(false),
+            FrontendMessage::MediaControlPlay
+        ));
+        assert!(matches!(
+            MediaKey::PlayPause.message(true),
+            FrontendMessage::MediaControlPause
+        ));
+        assert!(matches!(
+            MediaKey::Next.message(true),
+            FrontendMessage::MediaControlSkipForward
+        ));
+        assert!(matches!(
+            MediaKey::Previous.message(true),
+            FrontendMessage::MediaControlSkipBack
+        ));
+        assert!(matches!(
+            MediaKey::Stop.message(true),
+            FrontendMessage::MediaControlStop
+        ));
+    }
+}
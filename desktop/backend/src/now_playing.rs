@@ -0,0 +1,166 @@
+// This file is part of Millenium Player.
+// Copyright (C) 2023 John DiSanti.
+//
+// Millenium Player is free software: you can redistribute it and/or modify it under the terms of
+// the GNU General Public License as published by the Free Software Foundation, either version 3 of
+// the License, or (at your option) any later version.
+//
+// Millenium Player is distributed in the hope that it will be useful, but WITHOUT ANY WARRANTY;
+// without even the implied warranty of MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE. See
+// the GNU General Public License for more details.
+//
+// You should have received a copy of the GNU General Public License along with Millenium Player.
+// If not, see <https://www.gnu.org/licenses/>.
+
+use crate::config::NowPlayingConfig;
+use millenium_post_office::frontend::state::{PlaybackStateData, Track};
+use std::{
+    fs, io,
+    path::{Path, PathBuf},
+    sync::mpsc,
+    thread,
+    time::Duration,
+};
+
+/// Writes what's playing to a file or named pipe for stream overlays and status bars.
+///
+/// The text comes from a template with these placeholders:
+/// - `{title}`, `{artist}`, and `{album}`: from the track's tags, or empty if not tagged.
+/// - `{position}` and `{length}`: as `m:ss`. The length is empty for streams.
+/// - `{status}`: `playing`, `paused`, or `stopped`.
+///
+/// Files are replaced all at once so that readers never see half of an update. Pipes block
+/// until something reads them, so writing happens on its own thread, and only the latest
+/// text is written once a reader shows up.
+pub struct NowPlayingWriter {
+    template: String,
+    last_written: Option<String>,
+    texts: mpsc::Sender<String>,
+}
+
+impl NowPlayingWriter {
+    /// Starts the writer if a path is configured.
+    pub fn new(config: &NowPlayingConfig) -> io::Result<Option<Self>> {
+        let Some(path) = config.path.clone() else {
+            return Ok(None);
+        };
+        let (texts, receiver) = mpsc::channel::<String>();
+        thread::Builder::new()
+            .name("now-playing".into())
+            .spawn(move || {
+                while let Ok(mut text) = receiver.recv() {
+                    // Skip ahead to the latest text if writing fell behind
+                    while let Ok(latest) = receiver.try_recv() {
+                        text = latest;
+                    }
+                    if let Err(err) = write(&path, &text) {
+                        log::warn!("failed to write now playing to {path:?}: {err}");
+                    }
+                }
+            })?;
+        log::info!("writing now playing to {:?}", config.path);
+        Ok(Some(Self {
+            template: config.template.clone(),
+            last_written: None,
+            texts,
+        }))
+    }
+
+    /// Writes the text for the current playback state, if it changed.
+    pub fn update(&mut self, state: &PlaybackStateData) {
+        let text = render(&self.template, state);
+        if self.last_written.as_ref() == Some(&text) {
+            return;
+        }
+        self.last_written = Some(text.clone());
+        let _ = self.texts.send(text);
+    }
+}
+
+fn write(path: &Path, text: &str) -> io::Result<()> {
+    let is_pipe = fs::metadata(path)
+        .map(|metadata| !metadata.is_file())
+        .unwrap_or(false);
+    if is_pipe {
+        return fs::write(path, format!("{text}\n"));
+    }
+    let mut temp_path = path.as_os_str().to_owned();
+    temp_path.push(".tmp");
+    let temp_path = PathBuf::from(temp_path);
+    fs::write(&temp_path, text)?;
+    fs::rename(&temp_path, path)
+}
+
+fn render(template: &str, state: &PlaybackStateData) -> String {
+    let track = state.current_track.as_ref();
+    let tag = |tag: fn(&Track) -> Option<&String>| {
+        track.and_then(tag).map(String::as_str).unwrap_or_default()
+    };
+    let status = &state.playback_status;
+    let state_name = match (track, status.playing) {
+        (None, _) => "stopped",
+        (Some(_), true) => "playing",
+        (Some(_), false) => "paused",
+    };
+    template
+        .replace("{title}", tag(|track| track.title.as_ref()))
+        .replace("{artist}", tag(|track| track.artist.as_ref()))
+        .replace("{album}", tag(|track| track.album.as_ref()))
+        .replace("{position}", &format_time(status.current_position))
+        .replace(
+            "{length}",
+            &status.end_position.map(format_time).unwrap_or_default(),
+        )
+        .replace("{status}", state_name)
+}
+
+fn format_time(duration: Duration) -> String {
+    let seconds = duration.as_secs();
+    format!("{}:{:02}", seconds / 60, seconds % 60)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::APP_NAME;
+    use millenium_post_office::frontend::state::PlaybackStatus;
+
+    #[test]
+    fn render_template() {
+        let mut state = PlaybackStateData::default();
+        let template = "{artist} - {title} [{position}/{length}] {status}";
+        assert_eq!(" -  [0:00/] stopped", render(template, &state));
+
+        state.current_track = Some(Track {
+            title: Some("Song".into()),
+            artist: Some("Band".into()),
+            album: None,
+        });
+        state.playback_status = PlaybackStatus {
+            playing: true,
+            current_position: Duration::from_secs(65),
+            end_position: Some(Duration::from_secs(600)),
+            ..Default::default()
+        };
+        assert_eq!("Band - Song [1:05/10:00] playing", render(template, &state));
+
+        state.playback_status.playing = false;
+        assert_eq!(
+            "Song () paused",
+            render("{title} ({album}) {status}", &state)
+        );
+    }
+
+    #[test]
+    fn replace_file() {
+        let dir =
+            std::env::temp_dir().join(format!("{APP_NAME}-now-playing-{}", std::process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        let path = dir.join("now-playing.txt");
+        write(&path, "one").unwrap();
+        write(&path, "two").unwrap();
+        assert_eq!("two", fs::read_to_string(&path).unwrap());
+        assert_eq!(1, fs::read_dir(&dir).unwrap().count());
+        fs::remove_dir_all(&dir).unwrap();
+    }
+}
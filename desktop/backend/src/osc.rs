@@ -0,0 +1,183 @@
+// This file is part of Millenium Player.
+// Copyright (C) 2023 John DiSanti.
+//
+// Millenium Player is free software: you can redistribute it and/or modify it under the terms of
+// the GNU General Public License as published by the Free Software Foundation, either version 3 of
+// the License, or (at your option) any later version.
+//
+// Millenium Player is distributed in the hope that it will be useful, but WITHOUT ANY WARRANTY;
+// without even the implied warranty of MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE. See
+// the GNU General Public License for more details.
+//
+// You should have received a copy of the GNU General Public License along with Millenium Player.
+// If not, see <https://www.gnu.org/licenses/>.
+
+use crate::config::OscConfig;
+use millenium_post_office::frontend::state::PlaybackStatus;
+use std::{
+    io,
+    net::{Ipv4Addr, SocketAddr, ToSocketAddrs, UdpSocket},
+};
+
+/// Sends the audio analysis and transport state as Open Sound Control messages over UDP,
+/// so that visuals and lighting can react to the music.
+///
+/// The messages are, with the configured prefix in front:
+/// - `/amplitude` and `/spectrum`: one float per bin, from 0 to 1, about 30 times a second.
+/// - `/transport`: whether it's playing as an int, then the position and length in seconds
+///   as floats. The length is -1 for streams.
+pub struct OscSender {
+    socket: UdpSocket,
+    target: SocketAddr,
+    address_prefix: String,
+    /// Set after a send fails so that the log isn't flooded at the frame rate.
+    failed: bool,
+}
+
+impl OscSender {
+    /// Creates a sender if a target is configured.
+    pub fn new(config: &OscConfig) -> io::Result<Option<Self>> {
+        let Some(target) = &config.target else {
+            return Ok(None);
+        };
+        let target = target.to_socket_addrs()?.next().ok_or_else(|| {
+            io::Error::new(
+                io::ErrorKind::InvalidInput,
+                format!("no address for {target}"),
+            )
+        })?;
+        let socket = UdpSocket::bind((Ipv4Addr::UNSPECIFIED, 0))?;
+        socket.set_nonblocking(true)?;
+        log::info!("sending OSC messages to {target}");
+        Ok(Some(Self {
+            socket,
+            target,
+            address_prefix: config.address_prefix.trim_end_matches('/').into(),
+            failed: false,
+        }))
+    }
+
+    pub fn send_levels(&mut self, amplitude: &[f32], spectrum: &[f32]) {
+        let amplitude = amplitude.iter().map(|&level| Arg::Float(level));
+        self.send("/amplitude", &amplitude.collect::<Vec<_>>());
+        let spectrum = spectrum.iter().map(|&level| Arg::Float(level));
+        self.send("/spectrum", &spectrum.collect::<Vec<_>>());
+    }
+
+    pub fn send_transport(&mut self, status: &PlaybackStatus) {
+        let length = status
+            .end_position
+            .map(|end| end.as_secs_f32())
+            .unwrap_or(-1.0);
+        self.send(
+            "/transport",
+            &[
+                Arg::Int(status.playing as i32),
+                Arg::Float(status.current_position.as_secs_f32()),
+                Arg::Float(length),
+            ],
+        );
+    }
+
+    fn send(&mut self, address: &str, args: &[Arg]) {
+        let message = encode_message(&format!("{}{address}", self.address_prefix), args);
+        match self.socket.send_to(&message, self.target) {
+            Ok(_) => self.failed = false,
+            // Dropping a frame is fine if the network is backed up
+            Err(err) if err.kind() == io::ErrorKind::WouldBlock => {}
+            Err(err) if !self.failed => {
+                log::warn!("failed to send OSC message to {}: {err}", self.target);
+                self.failed = true;
+            }
+            Err(_) => {}
+        }
+    }
+}
+
+#[derive(Copy, Clone, Debug)]
+enum Arg {
+    Int(i32),
+    Float(f32),
+}
+
+/// Encodes an OSC 1.0 message. Strings are null terminated and padded to four bytes,
+/// and numbers are big endian.
+fn encode_message(address: &str, args: &[Arg]) -> Vec<u8> {
+    fn push_string(into: &mut Vec<u8>, value: &str) {
+        into.extend_from_slice(value.as_bytes());
+        into.push(0);
+        while into.len() % 4 != 0 {
+            into.push(0);
+        }
+    }
+
+    let mut message = Vec::with_capacity(address.len() + args.len() * 5 + 8);
+    push_string(&mut message, address);
+    let type_tags: String = std::iter::once(',')
+        .chain(args.iter().map(|arg| match arg {
+            Arg::Int(_) => 'i',
+            Arg::Float(_) => 'f',
+        }))
+        .collect();
+    push_string(&mut message, &type_tags);
+    for arg in args {
+        match arg {
+            Arg::Int(value) => message.extend_from_slice(&value.to_be_bytes()),
+            Arg::Float(value) => message.extend_from_slice(&value.to_be_bytes()),
+        }
+    }
+    message
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::time::Duration;
+
+    #[test]
+    fn encode() {
+        assert_eq!(b"/a\0\0,\0\0\0".to_vec(), encode_message("/a", &[]),);
+        assert_eq!(
+            [
+                &b"/abc\0\0\0\0,if\0"[..],
+                &[0, 0, 0, 1],
+                &0.5f32.to_be_bytes(),
+            ]
+            .concat(),
+            encode_message("/abc", &[Arg::Int(1), Arg::Float(0.5)]),
+        );
+    }
+
+    #[test]
+    fn send_to_target() {
+        let receiver = UdpSocket::bind((Ipv4Addr::LOCALHOST, 0)).unwrap();
+        receiver
+            .set_read_timeout(Some(Duration::from_secs(5)))
+            .unwrap();
+        let mut sender = OscSender::new(&OscConfig {
+            target: Some(receiver.local_addr().unwrap().to_string()),
+            address_prefix: "/lights/".into(),
+        })
+        .unwrap()
+        .unwrap();
+
+        sender.send_transport(&PlaybackStatus {
+            playing: true,
+            current_position: Duration::from_secs(2),
+            end_position: None,
+            volume: Default::default(),
+            live: None,
+        });
+        let mut received = [0; 64];
+        let len = receiver.recv(&mut received).unwrap();
+        assert_eq!(
+            encode_message(
+                "/lights/transport",
+                &[Arg::Int(1), Arg::Float(2.0), Arg::Float(-1.0)]
+            ),
+            received[..len]
+        );
+
+        assert!(OscSender::new(&OscConfig::default()).unwrap().is_none());
+    }
+}
@@ -0,0 +1,418 @@
+// This file is part of Millenium Player.
+// Copyright (C) 2023 John DiSanti.
+//
+// Millenium Player is free software: you can redistribute it and/or modify it under the terms of
+// the GNU General Public License as published by the Free Software Foundation, either version 3 of
+// the License, or (at your option) any later version.
+//
+// Millenium Player is distributed in the hope that it will be useful, but WITHOUT ANY WARRANTY;
+// without even the implied warranty of MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE. See
+// the GNU General Public License for more details.
+//
+// You should have received a copy of the GNU General Public License along with Millenium Player.
+// If not, see <https://www.gnu.org/licenses/>.
+
+use crate::APP_NAME;
+use camino::Utf8PathBuf;
+use millenium_core::location::Location;
+use millenium_post_office::{
+    broadcast::Broadcaster, frontend::message::FrontendMessage, types::Volume,
+};
+use serde::{Deserialize, Serialize};
+use std::{
+    env,
+    fs::{self, OpenOptions},
+    io::{self, BufRead, BufReader, Read, Write},
+    net::{Ipv4Addr, SocketAddr, TcpListener, TcpStream},
+    path::{Path, PathBuf},
+    sync::mpsc::{self, Receiver, Sender},
+    thread,
+    time::Duration,
+};
+
+/// How long to wait for the running instance before starting a new one instead.
+const TIMEOUT: Duration = Duration::from_secs(2);
+/// Largest request that's read from a later launch, which is plenty for thousands of locations.
+const MAX_REQUEST_SIZE: u64 = 1024 * 1024;
+
+/// Playback command sent by `millenium-player control`, such as from a window manager key binding.
+#[derive(Copy, Clone, Debug, Deserialize, Serialize)]
+#[cfg_attr(test, derive(Eq, PartialEq))]
+#[serde(rename_all = "kebab-case", tag = "command")]
+pub enum ControlCommand {
+    PlayPause,
+    Next,
+    Previous,
+    /// Seek forward from the current position, or back if negative.
+    SeekBy {
+        seconds: i32,
+    },
+    SeekTo {
+        position: Duration,
+    },
+    Volume {
+        percent: u8,
+    },
+}
+
+impl ControlCommand {
+    /// Message the command translates into, the same as the frontend sends for its controls.
+    pub fn message(self, playing: bool) -> FrontendMessage {
+        match self {
+            Self::PlayPause if playing => FrontendMessage::MediaControlPause,
+            Self::PlayPause => FrontendMessage::MediaControlPlay,
+            Self::Next => FrontendMessage::MediaControlSkipForward,
+            Self::Previous => FrontendMessage::MediaControlSkipBack,
+            Self::SeekBy { seconds } => FrontendMessage::MediaControlSeekBy { seconds },
+            Self::SeekTo { position } => FrontendMessage::MediaControlSeek { position },
+            Self::Volume { percent } => FrontendMessage::MediaControlVolume {
+                volume: Volume::from_percentage(percent as f32 / 100.0),
+            },
+        }
+    }
+}
+
+/// Locations or a playback command handed over by a later launch.
+#[derive(Debug, Deserialize, Serialize)]
+#[serde(rename_all = "kebab-case")]
+struct Forwarded {
+    token: String,
+    #[serde(default)]
+    locations: Vec<String>,
+    /// Queue the locations to play next rather than replacing the playlist.
+    #[serde(default)]
+    enqueue: bool,
+    #[serde(default)]
+    control: Option<ControlCommand>,
+}
+
+/// Default location of the instance file, which tells later launches how to reach the
+/// running instance.
+pub fn default_path() -> Option<PathBuf> {
+    dirs::runtime_dir()
+        .or_else(dirs::cache_dir)
+        .map(|dir| dir.join(APP_NAME).join("instance"))
+}
+
+/// Hands the locations to the instance that is already running, if there is one.
+///
+/// Returns false if no instance answered, such as when the instance file was left behind
+/// by one that has since exited, in which case this launch should carry on by itself.
+pub fn forward_to_running_instance(
+    instance_file: &Path,
+    locations: &[Location],
+    enqueue: bool,
+) -> bool {
+    let sent = send(instance_file, |token| Forwarded {
+        token,
+        // The running instance has its own working directory
+        locations: locations
+            .iter()
+            .map(|location| absolute(location).to_string())
+            .collect(),
+        enqueue,
+        control: None,
+    });
+    match sent {
+        Ok(()) => {
+            log::info!(
+                "handed {} locations to the running instance",
+                locations.len()
+            );
+            true
+        }
+        // No instance is running
+        Err(err) if err.kind() == io::ErrorKind::NotFound => false,
+        Err(err) => {
+            log::info!("starting a new instance since the running one didn't answer: {err}");
+            false
+        }
+    }
+}
+
+/// Sends a playback command to the instance that is already running.
+pub fn send_control(instance_file: &Path, command: ControlCommand) -> io::Result<()> {
+    send(instance_file, |token| Forwarded {
+        token,
+        locations: Vec::new(),
+        enqueue: false,
+        control: Some(command),
+    })
+}
+
+fn send(instance_file: &Path, request: impl FnOnce(String) -> Forwarded) -> io::Result<()> {
+    let contents = fs::read_to_string(instance_file)?;
+    let (port, token) = contents
+        .trim()
+        .split_once(' ')
+        .and_then(|(port, token)| Some((port.parse::<u16>().ok()?, token)))
+        .ok_or_else(|| {
+            io::Error::new(
+                io::ErrorKind::InvalidData,
+                format!("unreadable instance file {instance_file:?}"),
+            )
+        })?;
+    let address = SocketAddr::from((Ipv4Addr::LOCALHOST, port));
+    let mut stream = TcpStream::connect_timeout(&address, TIMEOUT)?;
+    stream.set_read_timeout(Some(TIMEOUT))?;
+    let mut message = serde_json::to_string(&request(token.into())).expect("serializable");
+    message.push('\n');
+    stream.write_all(message.as_bytes())?;
+    let mut reply = String::new();
+    BufReader::new(stream).read_line(&mut reply)?;
+    match reply.trim() {
+        "ok" => Ok(()),
+        reply => Err(io::Error::new(
+            io::ErrorKind::Other,
+            format!("refused: {reply}"),
+        )),
+    }
+}
+
+fn absolute(location: &Location) -> Location {
+    let joined = match location {
+        Location::Path(path) if path.is_relative() => env::current_dir()
+            .ok()
+            .and_then(|dir| Utf8PathBuf::from_path_buf(dir).ok())
+            .map(|dir| dir.join(path)),
+        _ => None,
+    };
+    joined
+        .map(Location::path)
+        .unwrap_or_else(|| location.clone())
+}
+
+/// Listens on localhost for locations from later launches, so that opening files while the
+/// player is running adds them to it rather than opening a second window. Playback commands
+/// from `millenium-player control` arrive the same way.
+///
+/// Later launches find it through the instance file, which holds the port and a token that
+/// requests have to include, so that other local users can't load locations into it.
+pub struct InstanceServer {
+    port: u16,
+    controls: Receiver<ControlCommand>,
+}
+
+impl InstanceServer {
+    /// Starts listening and writes the instance file.
+    pub fn start(
+        instance_file: &Path,
+        broadcaster: Broadcaster<FrontendMessage>,
+    ) -> io::Result<Self> {
+        let listener = TcpListener::bind((Ipv4Addr::LOCALHOST, 0))?;
+        let port = listener.local_addr()?.port();
+        let token = new_token()?;
+        if let Some(dir) = instance_file.parent() {
+            fs::create_dir_all(dir)?;
+        }
+        write_instance_file(instance_file, &format!("{port} {token}\n"))?;
+        let (control_sender, controls) = mpsc::channel();
+        thread::Builder::new()
+            .name("single-instance".into())
+            .spawn(move || {
+                for stream in listener.incoming() {
+                    let result = stream.and_then(|stream| {
+                        handle_connection(stream, &token, &broadcaster, &control_sender)
+                    });
+                    if let Err(err) = result {
+                        log::warn!("failed to take a request from another launch: {err}");
+                    }
+                }
+            })?;
+        Ok(Self { port, controls })
+    }
+
+    pub fn port(&self) -> u16 {
+        self.port
+    }
+
+    /// Broadcasts the messages for any playback commands that arrived since the last poll.
+    pub fn poll(&self, playing: bool, broadcaster: &Broadcaster<FrontendMessage>) {
+        // Play/pause toggles, so track the state across commands that arrive in the same poll
+        let mut playing = playing;
+        while let Ok(command) = self.controls.try_recv() {
+            log::info!("control command received: {command:?}");
+            let message = command.message(playing);
+            match message {
+                FrontendMessage::MediaControlPlay => playing = true,
+                FrontendMessage::MediaControlPause => playing = false,
+                _ => {}
+            }
+            broadcaster.broadcast(message);
+        }
+    }
+}
+
+fn new_token() -> io::Result<String> {
+    let mut bytes = [0u8; 16];
+    getrandom::getrandom(&mut bytes)
+        .map_err(|err| io::Error::new(io::ErrorKind::Other, format!("no random token: {err}")))?;
+    Ok(bytes.iter().map(|byte| format!("{byte:02x}")).collect())
+}
+
+/// Writes the instance file so that only the current user can read the token in it.
+fn write_instance_file(instance_file: &Path, contents: &str) -> io::Result<()> {
+    // The mode only applies to new files, so one left behind by an older version is replaced
+    match fs::remove_file(instance_file) {
+        Err(err) if err.kind() != io::ErrorKind::NotFound => return Err(err),
+        _ => {}
+    }
+    let mut options = OpenOptions::new();
+    options.write(true).create_new(true);
+    #[cfg(unix)]
+    std::os::unix::fs::OpenOptionsExt::mode(&mut options, 0o600);
+    options.open(instance_file)?.write_all(contents.as_bytes())
+}
+
+fn handle_connection(
+    stream: TcpStream,
+    token: &str,
+    broadcaster: &Broadcaster<FrontendMessage>,
+    controls: &Sender<ControlCommand>,
+) -> io::Result<()> {
+    stream.set_read_timeout(Some(TIMEOUT))?;
+    let mut line = String::new();
+    BufReader::new((&stream).take(MAX_REQUEST_SIZE)).read_line(&mut line)?;
+    let reply = match serde_json::from_str::<Forwarded>(&line) {
+        Ok(forwarded) if forwarded.token == token => {
+            let locations = forwarded
+                .locations
+                .into_iter()
+                .filter(|location| location.parse::<Location>().is_ok())
+                .collect::<Vec<_>>();
+            if !locations.is_empty() {
+                broadcaster.broadcast(if forwarded.enqueue {
+                    FrontendMessage::QueueLocations {
+                        locations,
+                        allow_duplicates: false,
+                    }
+                } else {
+                    FrontendMessage::LoadLocations { locations }
+                });
+            }
+            if let Some(command) = forwarded.control {
+                // Sent to the UI thread since play/pause depends on whether it's playing
+                let _ = controls.send(command);
+            }
+            "ok"
+        }
+        Ok(_) => "bad token",
+        Err(_) => "bad request",
+    };
+    (&stream).write_all(format!("{reply}\n").as_bytes())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use millenium_post_office::broadcast::NoChannels;
+    use std::{process, str::FromStr};
+
+    #[test]
+    fn forward_locations() {
+        let dir = env::temp_dir().join(format!("{APP_NAME}-instance-{}", process::id()));
+        let instance_file = dir.join("instance");
+        let broadcaster = Broadcaster::new();
+        let sub = broadcaster.subscribe("test", NoChannels);
+        let station = Location::from_str("http://example.com/radio.mp3").unwrap();
+
+        assert!(!forward_to_running_instance(
+            &instance_file,
+            &[station.clone()],
+            false
+        ));
+        let _server = InstanceServer::start(&instance_file, broadcaster.clone()).unwrap();
+        #[cfg(unix)]
+        {
+            use std::os::unix::fs::PermissionsExt;
+            let mode = fs::metadata(&instance_file).unwrap().permissions().mode();
+            assert_eq!(0o600, mode & 0o777);
+        }
+        assert!(forward_to_running_instance(
+            &instance_file,
+            &[station.clone()],
+            false
+        ));
+        assert!(forward_to_running_instance(
+            &instance_file,
+            &[Location::path("song.ogg")],
+            true
+        ));
+        let _ = fs::remove_dir_all(&dir);
+
+        assert!(matches!(
+            sub.recv_timeout(TIMEOUT),
+            Some(FrontendMessage::LoadLocations { locations }) if locations == [station.to_string()]
+        ));
+        let expected = env::current_dir().unwrap().join("song.ogg");
+        assert!(matches!(
+            sub.recv_timeout(TIMEOUT),
+            Some(FrontendMessage::QueueLocations { locations, allow_duplicates: false })
+                if locations == [expected.to_str().unwrap()]
+        ));
+    }
+
+    #[test]
+    fn control_running_instance() {
+        let dir = env::temp_dir().join(format!("{APP_NAME}-instance-control-{}", process::id()));
+        let instance_file = dir.join("instance");
+        assert_eq!(
+            io::ErrorKind::NotFound,
+            send_control(&instance_file, ControlCommand::Next)
+                .unwrap_err()
+                .kind()
+        );
+
+        let server = InstanceServer::start(&instance_file, Broadcaster::new()).unwrap();
+        for command in [
+            ControlCommand::PlayPause,
+            ControlCommand::PlayPause,
+            ControlCommand::SeekBy { seconds: -10 },
+            ControlCommand::Volume { percent: 100 },
+        ] {
+            send_control(&instance_file, command).unwrap();
+        }
+        let _ = fs::remove_dir_all(&dir);
+
+        let broadcaster = Broadcaster::new();
+        let sub = broadcaster.subscribe("test", NoChannels);
+        server.poll(false, &broadcaster);
+        assert!(matches!(
+            sub.try_recv(),
+            Some(FrontendMessage::MediaControlPlay)
+        ));
+        assert!(matches!(
+            sub.try_recv(),
+            Some(FrontendMessage::MediaControlPause)
+        ));
+        assert!(matches!(
+            sub.try_recv(),
+            Some(FrontendMessage::MediaControlSeekBy { seconds: -10 })
+        ));
+        assert!(matches!(
+            sub.try_recv(),
+            Some(FrontendMessage::MediaControlVolume { volume }) if volume == Volume::new(255)
+        ));
+    }
+
+    #[test]
+    fn tokens_are_random() {
+        let token = new_token().unwrap();
+        assert_eq!(32, token.len());
+        assert_ne!(token, new_token().unwrap());
+    }
+
+    #[test]
+    fn wrong_token_is_refused() {
+        let dir = env::temp_dir().join(format!("{APP_NAME}-instance-token-{}", process::id()));
+        let instance_file = dir.join("instance");
+        let server = InstanceServer::start(&instance_file, Broadcaster::new()).unwrap();
+        fs::write(&instance_file, format!("{} not-the-token\n", server.port())).unwrap();
+        assert!(!forward_to_running_instance(
+            &instance_file,
+            &[Location::path("/song.ogg")],
+            false
+        ));
+        let _ = fs::remove_dir_all(&dir);
+    }
+}
@@ -0,0 +1,213 @@
+// This file is part of Millenium Player.
+// Copyright (C) 2023 John DiSanti.
+//
+// Millenium Player is free software: you can redistribute it and/or modify it under the terms of
+// the GNU General Public License as published by the Free Software Foundation, either version 3 of
+// the License, or (at your option) any later version.
+//
+// Millenium Player is distributed in the hope that it will be useful, but WITHOUT ANY WARRANTY;
+// without even the implied warranty of MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE. See
+// the GNU General Public License for more details.
+//
+// You should have received a copy of the GNU General Public License along with Millenium Player.
+// If not, see <https://www.gnu.org/licenses/>.
+
+use std::time::{Duration, Instant};
+
+/// How long to keep the computer awake after a track finishes, so that the lock isn't
+/// released and taken again between every track.
+const RELEASE_AFTER_FINISHED: Duration = Duration::from_secs(10);
+
+/// Keeps the computer from suspending while audio is playing.
+///
+/// The lock is taken with `systemd-inhibit` on Linux, a `caffeinate` power assertion on macOS,
+/// and `SetThreadExecutionState` on Windows. On Windows, it must only be used from one thread.
+pub struct SleepInhibitor {
+    enabled: bool,
+    lock: Option<platform::Lock>,
+    /// When to release the lock if playback doesn't resume, such as after the last track.
+    release_at: Option<Instant>,
+    /// Set when taking the lock failed, so that it isn't retried on every status update.
+    failed: bool,
+}
+
+impl SleepInhibitor {
+    pub fn new(enabled: bool) -> Self {
+        Self {
+            enabled,
+            lock: None,
+            release_at: None,
+            failed: false,
+        }
+    }
+
+    /// Turns the inhibitor on or off. Turning it off releases the lock right away.
+    pub fn set_enabled(&mut self, enabled: bool) {
+        self.enabled = enabled;
+        if !enabled {
+            self.release();
+        }
+    }
+
+    /// Takes the lock while playing, and releases it when paused or stopped.
+    pub fn set_playing(&mut self, playing: bool) {
+        if !playing {
+            self.release();
+            return;
+        }
+        self.release_at = None;
+        if !self.enabled || self.lock.is_some() || self.failed {
+            return;
+        }
+        match platform::Lock::acquire() {
+            Ok(lock) => {
+                log::info!("preventing sleep while playing");
+                self.lock = Some(lock);
+            }
+            Err(err) => {
+                log::warn!("failed to prevent sleep while playing: {err}");
+                self.failed = true;
+            }
+        }
+    }
+
+    /// Releases the lock shortly unless playback resumes, since a finished track
+    /// is usually followed by another.
+    pub fn track_finished(&mut self) {
+        if self.lock.is_some() {
+            self.release_at = Some(Instant::now() + RELEASE_AFTER_FINISHED);
+        }
+    }
+
+    /// Releases the lock if playback didn't resume after the last track finished.
+    pub fn poll(&mut self) {
+        if self.release_at.map(|at| Instant::now() >= at) == Some(true) {
+            self.release();
+        }
+    }
+
+    fn release(&mut self) {
+        self.release_at = None;
+        if self.lock.take().is_some() {
+            log::info!("allowing sleep again");
+        }
+    }
+}
+
+#[cfg(target_os = "linux")]
+mod platform {
+    use crate::APP_TITLE;
+    use std::{
+        io,
+        process::{Child, Command, Stdio},
+    };
+
+    /// Holds `systemd-inhibit` open for as long as the lock is held.
+    pub struct Lock(Child);
+
+    impl Lock {
+        pub fn acquire() -> io::Result<Self> {
+            // `cat` exits when its input is closed, which also happens if the player crashes,
+            // so the lock can't outlive the player
+            Command::new("systemd-inhibit")
+                .args([
+                    "--what=sleep:idle",
+                    &format!("--who={APP_TITLE}"),
+                    "--why=Playing audio",
+                    "--mode=block",
+                    "cat",
+                ])
+                .stdin(Stdio::piped())
+                .stdout(Stdio::null())
+                .stderr(Stdio::null())
+                .spawn()
+                .map(Lock)
+        }
+    }
+
+    impl Drop for Lock {
+        fn drop(&mut self) {
+            drop(self.0.stdin.take());
+            if let Err(err) = self.0.wait() {
+                log::warn!("failed to wait for systemd-inhibit to exit: {err}");
+            }
+        }
+    }
+}
+
+#[cfg(target_os = "macos")]
+mod platform {
+    use std::{
+        io,
+        process::{Child, Command, Stdio},
+    };
+
+    /// Holds a `caffeinate` process, which keeps an IOKit power assertion while it runs.
+    pub struct Lock(Child);
+
+    impl Lock {
+        pub fn acquire() -> io::Result<Self> {
+            // Waiting on the player's process means the assertion can't outlive it
+            Command::new("caffeinate")
+                .args(["-i", "-w", &std::process::id().to_string()])
+                .stdin(Stdio::null())
+                .stdout(Stdio::null())
+                .stderr(Stdio::null())
+                .spawn()
+                .map(Lock)
+        }
+    }
+
+    impl Drop for Lock {
+        fn drop(&mut self) {
+            let _ = self.0.kill();
+            if let Err(err) = self.0.wait() {
+                log::warn!("failed to wait for caffeinate to exit: {err}");
+            }
+        }
+    }
+}
+
+#[cfg(target_os = "windows")]
+mod platform {
+    use std::io;
+    use windows_sys::Win32::System::Power::{
+        SetThreadExecutionState, ES_CONTINUOUS, ES_SYSTEM_REQUIRED,
+    };
+
+    /// Keeps the system required flag set on the current thread while held.
+    pub struct Lock(());
+
+    impl Lock {
+        pub fn acquire() -> io::Result<Self> {
+            // SAFETY: Only changes the execution state flags of the calling thread
+            match unsafe { SetThreadExecutionState(ES_CONTINUOUS | ES_SYSTEM_REQUIRED) } {
+                0 => Err(io::Error::last_os_error()),
+                _ => Ok(Lock(())),
+            }
+        }
+    }
+
+    impl Drop for Lock {
+        fn drop(&mut self) {
+            // SAFETY: Only changes the execution state flags of the calling thread
+            unsafe { SetThreadExecutionState(ES_CONTINUOUS) };
+        }
+    }
+}
+
+#[cfg(not(any(target_os = "linux", target_os = "macos", target_os = "windows")))]
+mod platform {
+    use std::io;
+
+    pub struct Lock(());
+
+    impl Lock {
+        pub fn acquire() -> io::Result<Self> {
+            Err(io::Error::new(
+                io::ErrorKind::Unsupported,
+                "not supported on this platform",
+            ))
+        }
+    }
+}
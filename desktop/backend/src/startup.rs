@@ -0,0 +1,49 @@
+// This file is part of Millenium Player.
+// Copyright (C) 2023 John DiSanti.
+//
+// Millenium Player is free software: you can redistribute it and/or modify it under the terms of
+// the GNU General Public License as published by the Free Software Foundation, either version 3 of
+// the License, or (at your option) any later version.
+//
+// Millenium Player is distributed in the hope that it will be useful, but WITHOUT ANY WARRANTY;
+// without even the implied warranty of MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE. See
+// the GNU General Public License for more details.
+//
+// You should have received a copy of the GNU General Public License along with Millenium Player.
+// If not, see <https://www.gnu.org/licenses/>.
+
+use std::time::{Duration, Instant};
+
+/// Logs how long each phase of startup takes so that slow startups can be diagnosed
+/// from the log file.
+#[derive(Debug)]
+pub struct StartupTimer {
+    start: Instant,
+    last: Instant,
+}
+
+impl StartupTimer {
+    pub fn start() -> Self {
+        let now = Instant::now();
+        Self {
+            start: now,
+            last: now,
+        }
+    }
+
+    /// Time elapsed since startup began.
+    pub fn elapsed(&self) -> Duration {
+        self.start.elapsed()
+    }
+
+    /// Marks the end of a startup phase, and logs how long it took.
+    pub fn phase(&mut self, name: &str) {
+        let now = Instant::now();
+        log::info!(
+            "startup phase \"{name}\" took {:?} ({:?} since start)",
+            now - self.last,
+            now - self.start
+        );
+        self.last = now;
+    }
+}
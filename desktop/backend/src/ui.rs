@@ -12,55 +12,212 @@
 // You should have received a copy of the GNU General Public License along with Millenium Player.
 // If not, see <https://www.gnu.org/licenses/>.
 
-use crate::{args::Mode, error::FatalError, ipc::InternalProtocol, APP_TITLE};
-use camino::Utf8Path;
+use crate::{
+    album_art::AlbumArtCache,
+    analysis::{export_analysis, AnalysisFormat},
+    args::{Args, Mode},
+    backup::{Backup, StatePaths},
+    cast::CastServer,
+    clipboard::parse_pasted_locations,
+    config::{AudioConfig, Config, ConfigWatcher},
+    ducking::DuckingServer,
+    error::FatalError,
+    favorites::Favorites,
+    guest_queue::GuestQueue,
+    ipc::{self, InternalProtocol},
+    log_file,
+    media_keys::MediaKeys,
+    now_playing::NowPlayingWriter,
+    osc::OscSender,
+    single_instance::{self, InstanceServer},
+    sleep_inhibit::SleepInhibitor,
+    startup::StartupTimer,
+    ui_prefs, APP_NAME, APP_TITLE,
+};
+use camino::Utf8PathBuf;
 use millenium_core::{
+    audio::{
+        analysis_cache::AnalysisCache,
+        device::output_device_names,
+        gapless::{check_transitions, Transition},
+    },
     location::Location,
     message::{PlayerMessage, PlayerMessageChannel},
-    player::{PlayerThread, PlayerThreadHandle},
-    playlist::PlaylistManager,
+    metadata::{MetadataChain, TagDecoder, TagSeparators},
+    player::{PlayerHandle, PlayerThread},
+    playlist::{PlaylistFormat, PlaylistManager, SortOptions, TrackRatings, TrackStart},
 };
 use millenium_post_office::{
-    broadcast::{BroadcastMessage, BroadcastSubscription, Broadcaster, NoChannels},
+    broadcast::{
+        record::MessageRecorder, BroadcastMessage, BroadcastSubscription, Broadcaster, NoChannels,
+    },
     frontend::{
         message::{AlertLevel, FrontendMessage, LogLevel},
-        state::{PlaybackState, PlaybackStatus, Track, Waveform, WaveformState},
+        settings::Visualizer,
+        state::{
+            DebugState, FavoritesState, PlaybackState, PlaybackStatus, PlaylistState,
+            SettingsState, Track, UiPrefsState, Waveform, WaveformState,
+        },
     },
     state::StateChanged,
+    types::{AbLoop, DeviceStatus, Favorite, InstanceHealth, TestSignal},
 };
-use muda::{ContextMenu, Menu, MenuEvent, MenuItem, PredefinedMenuItem};
+use muda::{CheckMenuItem, ContextMenu, Menu, MenuEvent, MenuItem, PredefinedMenuItem, Submenu};
 use std::{
+    env,
+    path::PathBuf,
     rc::Rc,
+    str::FromStr,
+    thread,
     time::{Duration, Instant},
 };
 use tao::{
     dpi::{LogicalSize, Size},
-    event_loop::{ControlFlow, EventLoop, EventLoopBuilder},
+    event_loop::{ControlFlow, EventLoop, EventLoopBuilder, EventLoopWindowTarget},
     window::Window,
 };
 use wry::webview::{webview_version, FileDropEvent};
 
+/// When set to a file path, all player and frontend messages are recorded to that file
+/// so that they can be replayed later with `MessageReplayer` for debugging.
+const RECORD_MESSAGES_ENV_VAR: &str = "MILLENIUM_RECORD_MESSAGES";
+
+/// Size of the main window at a UI scale of 1.0.
+const MAIN_WINDOW_SIZE: LogicalSize<f64> = LogicalSize {
+    width: 400.0,
+    height: 200.0,
+};
+
+/// Size that the visualizer window opens at. It can be resized from there.
+const VISUALIZER_WINDOW_SIZE: LogicalSize<f64> = LogicalSize {
+    width: 800.0,
+    height: 400.0,
+};
+
+/// Page that shows only the visualizer, for when it's popped out into its own window.
+const VISUALIZER_WINDOW_URL: &str = "internal://localhost/index.html?window=visualizer";
+
 struct MediaControlsMenu {
     menu: Menu,
     item_open: MenuItem,
+    item_open_folder: MenuItem,
+    item_play_next: MenuItem,
+    item_save_playlist: MenuItem,
+    item_add_favorite: MenuItem,
+    /// Each favorite's menu item, along with the location it opens.
+    favorite_items: Vec<(MenuItem, String)>,
     item_show_hide_playlist: MenuItem,
+    /// Only shown while the guest queue is being served.
+    item_guest_requests: Option<CheckMenuItem>,
+    /// Each test signal's menu item.
+    test_signal_items: Vec<(MenuItem, TestSignal)>,
+    item_export_analysis: MenuItem,
+    item_check_gapless: MenuItem,
+    item_export_backup: MenuItem,
+    item_import_backup: MenuItem,
+    item_open_log_folder: MenuItem,
 }
 
 impl MediaControlsMenu {
-    fn new() -> Self {
+    fn new(
+        favorites: &[Favorite],
+        guest_queue: Option<&GuestQueue>,
+        cast_server: Option<&CastServer>,
+    ) -> Self {
         let menu = Menu::new();
         let item_open = MenuItem::new("Open", true, None);
+        let item_open_folder = MenuItem::new("Open folder", true, None);
+        let item_play_next = MenuItem::new("Play next", true, None);
+        let item_save_playlist = MenuItem::new("Save playlist", true, None);
+        let item_add_favorite = MenuItem::new("Add to favorites", true, None);
+        let favorites_menu = Submenu::new("Favorites", !favorites.is_empty());
+        let favorite_items: Vec<_> = favorites
+            .iter()
+            .map(|favorite| {
+                let item = MenuItem::new(&favorite.name, true, None);
+                favorites_menu.append(&item).unwrap();
+                (item, favorite.location.clone())
+            })
+            .collect();
         let item_show_hide_playlist = MenuItem::new("Show/hide playlist", true, None);
+        let guest_queue_items = guest_queue.map(|guest_queue| {
+            let url = MenuItem::new(
+                format!("Guests can queue at {}", guest_queue.url()),
+                false,
+                None,
+            );
+            let accepting =
+                CheckMenuItem::new("Take guest requests", true, guest_queue.accepting(), None);
+            (url, accepting)
+        });
+        let item_cast_url = cast_server.map(|cast_server| {
+            MenuItem::new(format!("Casting at {}", cast_server.url()), false, None)
+        });
+        let test_signals_menu = Submenu::new("Test signals", true);
+        let test_signal_items: Vec<_> = [
+            TestSignal::ChannelCheck,
+            TestSignal::SineSweep,
+            TestSignal::PinkNoise,
+        ]
+        .into_iter()
+        .map(|signal| {
+            let item = MenuItem::new(signal.name(), true, None);
+            test_signals_menu.append(&item).unwrap();
+            (item, signal)
+        })
+        .collect();
+        let item_export_analysis = MenuItem::new("Export analysis", true, None);
+        let item_check_gapless = MenuItem::new("Check gapless transitions", true, None);
+        let item_export_backup = MenuItem::new("Export backup", true, None);
+        let item_import_backup = MenuItem::new("Import backup", true, None);
+        let item_open_log_folder = MenuItem::new("Open log folder", true, None);
         menu.append_items(&[
             &item_open,
+            &item_open_folder,
+            &item_play_next,
+            &item_save_playlist,
+            &PredefinedMenuItem::separator(),
+            &item_add_favorite,
+            &favorites_menu,
             &PredefinedMenuItem::separator(),
             &item_show_hide_playlist,
+            &PredefinedMenuItem::separator(),
+        ])
+        .unwrap();
+        if let Some((url, accepting)) = &guest_queue_items {
+            menu.append_items(&[url, accepting, &PredefinedMenuItem::separator()])
+                .unwrap();
+        }
+        if let Some(url) = &item_cast_url {
+            menu.append_items(&[url, &PredefinedMenuItem::separator()])
+                .unwrap();
+        }
+        menu.append_items(&[
+            &item_export_backup,
+            &item_import_backup,
+            &PredefinedMenuItem::separator(),
+            &test_signals_menu,
+            &item_export_analysis,
+            &item_check_gapless,
+            &item_open_log_folder,
         ])
         .unwrap();
         Self {
             menu,
             item_open,
+            item_open_folder,
+            item_play_next,
+            item_save_playlist,
+            item_add_favorite,
+            favorite_items,
             item_show_hide_playlist,
+            item_guest_requests: guest_queue_items.map(|(_, accepting)| accepting),
+            test_signal_items,
+            item_export_analysis,
+            item_check_gapless,
+            item_export_backup,
+            item_import_backup,
+            item_open_log_folder,
         }
     }
 
@@ -93,11 +250,19 @@ pub struct Ui {
     _osx_app_menu: OsxAppMenu,
 
     main_web_view: wry::webview::WebView,
+    /// Set while the visualizer is popped out into its own window.
+    visualizer_web_view: Option<wry::webview::WebView>,
+    /// Shared by every web view so that they all see the same state.
+    internal_protocol: Rc<InternalProtocol>,
     event_loop: Option<tao::event_loop::EventLoop<()>>,
 
-    player: Option<PlayerThreadHandle>,
+    player: Option<Box<dyn PlayerHandle>>,
+    /// Kept so that a replacement player thread reaches the existing subscribers.
+    player_broadcaster: Broadcaster<PlayerMessage>,
+    /// Audio config that the player was spawned with, so that it can be restarted.
+    audio_config: AudioConfig,
     player_sub: BroadcastSubscription<PlayerMessage>,
-    _frontend_broadcaster: Broadcaster<FrontendMessage>,
+    frontend_broadcaster: Broadcaster<FrontendMessage>,
     frontend_sub: BroadcastSubscription<FrontendMessage>,
     playlist_manager: PlaylistManager,
 
@@ -105,67 +270,353 @@ pub struct Ui {
     playback_state_sub: BroadcastSubscription<StateChanged>,
     waveform_state: WaveformState,
     waveform_state_sub: BroadcastSubscription<StateChanged>,
+    settings_state: SettingsState,
+    debug_state: DebugState,
+    /// Copy of the playlist for the frontend, refreshed whenever the playlist changes.
+    playlist_state: PlaylistState,
+    ui_prefs_state: UiPrefsState,
+    ui_prefs_state_sub: BroadcastSubscription<StateChanged>,
+    /// Not set if the UI preferences file couldn't be loaded, so that it isn't overwritten.
+    ui_prefs_path: Option<PathBuf>,
+    tag_decoder: TagDecoder,
+    settings_state_sub: BroadcastSubscription<StateChanged>,
+    config_watcher: Option<ConfigWatcher>,
+    /// Not set if the favorites file couldn't be loaded, so that it isn't overwritten.
+    favorites: Option<Favorites>,
+    favorites_state: FavoritesState,
+    favorites_state_sub: BroadcastSubscription<StateChanged>,
+    /// Locations that were opened most recently, which are what gets added to the favorites.
+    last_opened: Vec<String>,
+    album_art_cache: AlbumArtCache,
+    /// Track that the next loaded metadata is for, after a gapless transition that the
+    /// playlist hasn't caught up with yet.
+    started_next_location: Option<Location>,
+    sleep_inhibitor: SleepInhibitor,
+    media_keys: MediaKeys,
+    /// Set if guests can queue tracks from the local network.
+    guest_queue: Option<GuestQueue>,
+    /// Set if the current track is served to browsers and network speakers.
+    cast_server: Option<CastServer>,
+    /// Set if the audio analysis is sent to an Open Sound Control target.
+    osc_sender: Option<OscSender>,
+    /// Set if what's playing is written to a file or named pipe.
+    now_playing: Option<NowPlayingWriter>,
+    /// Set if other apps can duck the volume over localhost. It's only kept to keep it running.
+    _ducking_server: Option<DuckingServer>,
+    /// Set if later launches hand their locations and playback commands to this one.
+    instance_server: Option<InstanceServer>,
 
-    media_controls_menu: MediaControlsMenu,
+    /// Created the first time it's opened since it isn't needed to start playing.
+    media_controls_menu: Option<MediaControlsMenu>,
+
+    /// Set until startup finishes. Startup is considered finished when the first track
+    /// starts playing if files were given on launch, or when the window is shown otherwise.
+    startup_timer: Option<StartupTimer>,
+    launched_with_locations: bool,
 }
 
 impl Ui {
-    pub fn new(mode: Mode) -> Result<Self, FatalError> {
+    pub fn new(args: Args) -> Result<Self, FatalError> {
+        let mut startup_timer = StartupTimer::start();
+        // Safe mode runs with the default config, and leaves the config file alone
+        let config_watcher = if args.safe_mode {
+            log::warn!("starting in safe mode");
+            None
+        } else {
+            Config::default_path().map(ConfigWatcher::new)
+        };
+        startup_timer.phase("load config");
+
+        // The audio device is created on the player thread, so this gets it going
+        // while the window and web view are created.
+        let audio_config = config_watcher
+            .as_ref()
+            .map(|watcher| watcher.config().audio.clone())
+            .unwrap_or_default();
+        let player = PlayerThread::spawn(
+            audio_config.output_device.clone(),
+            audio_config.channel_maps.clone(),
+            audio_config.device_options(),
+        )?;
+        startup_timer.phase("spawn player thread");
+
+        let mut ui = Self::create(
+            args.mode,
+            Box::new(player),
+            audio_config,
+            config_watcher,
+            startup_timer,
+            args.safe_mode,
+        )?;
+        if !args.new_instance {
+            ui.instance_server = single_instance::default_path().and_then(|path| {
+                InstanceServer::start(&path, ui.frontend_broadcaster.clone())
+                    .map_err(|err| log::error!("failed to listen for other launches: {err}"))
+                    .ok()
+            });
+        }
+        Ok(ui)
+    }
+
+    fn create(
+        mode: Mode,
+        player: Box<dyn PlayerHandle>,
+        audio_config: AudioConfig,
+        config_watcher: Option<ConfigWatcher>,
+        mut startup_timer: StartupTimer,
+        safe_mode: bool,
+    ) -> Result<Self, FatalError> {
         let playback_state = PlaybackState::new();
         let playback_state_sub = playback_state.subscribe("backend");
         let waveform_state = WaveformState::new();
         let waveform_state_sub = waveform_state.subscribe("backend");
+        let settings_state = SettingsState::new();
+        if let Some(watcher) = &config_watcher {
+            settings_state.mutate(|settings| *settings = watcher.config().settings.clone());
+        }
+        if safe_mode {
+            settings_state.mutate(|settings| settings.visualizer = Visualizer::Off);
+        }
+        let settings_state_sub = settings_state.subscribe("backend");
+        let favorites = Favorites::default_path().and_then(|path| {
+            Favorites::load(path)
+                .map_err(|err| log::error!("{err}"))
+                .ok()
+        });
+        let favorites_state = FavoritesState::new();
+        if let Some(favorites) = &favorites {
+            favorites_state.mutate(|state| *state = favorites.list().to_vec());
+        }
+        let favorites_state_sub = favorites_state.subscribe("backend");
+        let sleep_inhibitor = SleepInhibitor::new(settings_state.borrow().prevent_sleep);
+        let media_keys = MediaKeys::new(settings_state.borrow().media_keys);
+        let guest_queue = config_watcher
+            .as_ref()
+            .map(|watcher| &watcher.config().guest_queue)
+            .filter(|config| config.enabled)
+            .and_then(|config| {
+                GuestQueue::start(config)
+                    .map_err(|err| log::error!("failed to start the guest queue: {err}"))
+                    .ok()
+            });
+        let cast_server = config_watcher
+            .as_ref()
+            .map(|watcher| &watcher.config().cast)
+            .filter(|config| config.enabled)
+            .and_then(|config| {
+                CastServer::start(config)
+                    .map_err(|err| log::error!("failed to start casting: {err}"))
+                    .ok()
+            });
+        let osc_sender = config_watcher
+            .as_ref()
+            .and_then(|watcher| {
+                OscSender::new(&watcher.config().osc)
+                    .map_err(|err| log::error!("failed to set up OSC output: {err}"))
+                    .ok()
+            })
+            .flatten();
+        let now_playing = config_watcher
+            .as_ref()
+            .and_then(|watcher| {
+                NowPlayingWriter::new(&watcher.config().now_playing)
+                    .map_err(|err| log::error!("failed to start writing now playing: {err}"))
+                    .ok()
+            })
+            .flatten();
+        let debug_state = DebugState::new();
+        let playlist_state = PlaylistState::new();
+        let ui_prefs_state = UiPrefsState::new();
+        let ui_prefs_path = ui_prefs::default_path().filter(|path| match ui_prefs::load(path) {
+            Ok(prefs) => {
+                ui_prefs_state.mutate(|state| *state = prefs);
+                true
+            }
+            Err(err) => {
+                log::error!("{err}");
+                false
+            }
+        });
+        let ui_prefs_state_sub = ui_prefs_state.subscribe("backend");
+        let tag_decoder = config_watcher
+            .as_ref()
+            .map(|watcher| TagDecoder::new(&watcher.config().metadata.fallback_encodings))
+            .transpose()
+            .unwrap_or_else(|err| {
+                log::error!("{err}");
+                None
+            })
+            .unwrap_or_default();
         let protocol = Rc::new(InternalProtocol::new(
             playback_state.clone(),
             waveform_state.clone(),
+            settings_state.clone(),
+            favorites_state.clone(),
+            debug_state.clone(),
+            playlist_state.clone(),
+            ui_prefs_state.clone(),
         ));
 
         let frontend_broadcaster = Broadcaster::new();
         let frontend_sub = frontend_broadcaster.subscribe("backend", NoChannels);
 
-        let event_loop: EventLoop<()> = EventLoopBuilder::new().build();
-        let main_window = tao::window::WindowBuilder::new()
-            .with_title(APP_TITLE)
-            .with_decorations(false)
-            .with_transparent(true)
-            .with_resizable(false)
-            .with_inner_size(Size::Logical(LogicalSize::new(400.0, 200.0)))
-            .with_visible(false) // start invisible
-            .build(&event_loop)
-            .map_err(|err| FatalError::new("failed to create window", err))?;
-        let main_web_view = create_webview(main_window, frontend_broadcaster.clone(), protocol)?;
+        if let Some(path) = env::var_os(RECORD_MESSAGES_ENV_VAR) {
+            match MessageRecorder::create(&path) {
+                Ok(recorder) => {
+                    log::info!("recording broadcast messages to {path:?}");
+                    recorder.attach("player", player.broadcaster());
+                    recorder.attach("frontend", &frontend_broadcaster);
+                }
+                Err(err) => log::error!("failed to create message recording at {path:?}: {err}"),
+            }
+        }
+
+        let ducking_server = config_watcher
+            .as_ref()
+            .map(|watcher| &watcher.config().ducking)
+            .filter(|config| config.enabled)
+            .and_then(|config| {
+                DuckingServer::start(config, frontend_broadcaster.clone())
+                    .map_err(|err| log::error!("failed to start listening for ducking: {err}"))
+                    .ok()
+            });
 
-        let player = PlayerThread::spawn(None)?;
         let player_sub = player.broadcaster().subscribe(
             "ui-backend",
             PlayerMessageChannel::Events | PlayerMessageChannel::FrequentUpdates,
         );
+        player_sub.broadcast(PlayerMessage::CommandSetNormalizationMode(
+            settings_state.borrow().normalization,
+        ));
+        if safe_mode {
+            player_sub.broadcast(PlayerMessage::CommandSetDspBypass(true));
+            playback_state.mutate(|state| state.dsp_bypassed = true);
+        }
 
-        let playlist_manager =
+        let mut playlist_manager =
             PlaylistManager::new(player.broadcaster().clone(), frontend_broadcaster.clone());
+        playlist_manager.set_sort_options(SortOptions::from(&settings_state.borrow().sorting));
+        playlist_manager.set_shuffle_seed(settings_state.borrow().shuffle_seed);
+        playlist_manager.set_track_transition(settings_state.borrow().track_transition());
+        if let Some(cache_dir) = dirs::cache_dir() {
+            let path = cache_dir.join(APP_NAME).join("analysis.json");
+            playlist_manager.set_analysis_cache(AnalysisCache::load(path));
+        }
+        if let Some(data_dir) = dirs::data_dir() {
+            let path = data_dir.join(APP_NAME).join("ratings.json");
+            playlist_manager.set_track_ratings(TrackRatings::load(path));
+        }
+        playlist_manager.set_peak_normalization(settings_state.borrow().peak_normalization());
+        let tag_separators = config_watcher
+            .as_ref()
+            .and_then(|watcher| watcher.config().metadata.multi_value_separators.clone())
+            .map(TagSeparators::new)
+            .unwrap_or_default();
+        playlist_manager.set_tag_separators(tag_separators);
+        let metadata_chain = config_watcher
+            .as_ref()
+            .and_then(|watcher| watcher.config().metadata.providers.as_ref())
+            .map(MetadataChain::from_names)
+            .transpose()
+            .unwrap_or_else(|err| {
+                log::error!("{err}");
+                None
+            })
+            .unwrap_or_default();
+        playlist_manager.set_metadata_chain(metadata_chain);
+        if let Some(watcher) = config_watcher.as_ref() {
+            let folders = &watcher.config().folders;
+            let defaults = folders
+                .iter()
+                .filter_map(|folder| {
+                    let defaults = folder.folder_defaults();
+                    if defaults.is_none() {
+                        log::warn!(
+                            "folder {:?} in the config isn't a valid UTF-8 path",
+                            folder.path
+                        );
+                    }
+                    defaults
+                })
+                .collect();
+            playlist_manager.set_folder_defaults(defaults);
+        }
+        let launched_with_locations;
+        let last_opened: Vec<String>;
         match mode {
-            Mode::Simple { locations } => frontend_sub.broadcast(FrontendMessage::LoadLocations {
-                locations: locations.iter().map(Location::to_string).collect(),
-            }),
-            Mode::Library {
-                storage_path,
-                audio_path,
+            Mode::Simple {
+                locations,
+                track_number_ordering,
+                start_paused,
+                start_position,
             } => {
-                let _ = (storage_path, audio_path);
-                unimplemented!("library mode isn't implemented yet")
+                launched_with_locations = !locations.is_empty();
+                playlist_manager.set_track_number_ordering(track_number_ordering);
+                last_opened = locations.iter().map(Location::to_string).collect();
+                // Carried with the load since directories are expanded before anything plays
+                playlist_manager.load_locations_with(
+                    locations,
+                    TrackStart {
+                        position: start_position,
+                        paused: start_paused,
+                    },
+                );
+            }
+            // Report this like any other startup failure rather than panicking
+            Mode::Library { .. } => {
+                return Err(FatalError::msg(
+                    "Library mode isn't available yet. Start the player without `library` to play files and streams.",
+                ));
+            }
+            Mode::Control { .. } => {
+                return Err(FatalError::msg(
+                    "Control commands are sent to the running player rather than starting one.",
+                ));
             }
         }
+        if launched_with_locations {
+            // Start playback right away rather than waiting for the window and web view so that
+            // audio starts as quickly as it would with a command-line player. The player thread
+            // decodes and plays while the window is being created, and the frontend catches up
+            // on the playback state once it loads.
+            playlist_manager.update();
+            startup_timer.phase("start playback");
+        }
+
+        let event_loop: EventLoop<()> = EventLoopBuilder::new().build();
+        let main_window = tao::window::WindowBuilder::new()
+            .with_title(APP_TITLE)
+            .with_decorations(false)
+            .with_transparent(true)
+            .with_resizable(false)
+            .with_inner_size(Size::Logical(MAIN_WINDOW_SIZE))
+            .with_visible(false) // start invisible
+            .build(&event_loop)
+            .map_err(|err| FatalError::new("failed to create window", err))?;
+        startup_timer.phase("create window");
+        let main_web_view = create_webview(
+            main_window,
+            frontend_broadcaster.clone(),
+            protocol.clone(),
+            "internal://localhost/index.html",
+        )?;
+        apply_ui_scale(&main_web_view, settings_state.borrow().clamped_ui_scale());
+        startup_timer.phase("create web view");
 
         Ok(Self {
             #[cfg(target_os = "macos")]
             _osx_app_menu: OsxAppMenu::new()?,
 
             main_web_view,
+            visualizer_web_view: None,
+            internal_protocol: protocol,
             event_loop: Some(event_loop),
 
+            player_broadcaster: player.broadcaster().clone(),
             player: Some(player),
+            audio_config,
             player_sub,
-            _frontend_broadcaster: frontend_broadcaster,
+            frontend_broadcaster,
             frontend_sub,
             playlist_manager,
 
@@ -173,8 +624,34 @@ impl Ui {
             playback_state_sub,
             waveform_state,
             waveform_state_sub,
+            settings_state,
+            debug_state,
+            playlist_state,
+            ui_prefs_state,
+            ui_prefs_state_sub,
+            ui_prefs_path,
+            tag_decoder,
+            settings_state_sub,
+            config_watcher,
+            favorites,
+            favorites_state,
+            favorites_state_sub,
+            last_opened,
+            album_art_cache: AlbumArtCache::default(),
+            started_next_location: None,
+            sleep_inhibitor,
+            media_keys,
+            guest_queue,
+            cast_server,
+            osc_sender,
+            now_playing,
+            _ducking_server: ducking_server,
+            instance_server: None,
+
+            media_controls_menu: None,
 
-            media_controls_menu: MediaControlsMenu::new(),
+            startup_timer: Some(startup_timer),
+            launched_with_locations,
         })
     }
 
@@ -182,11 +659,14 @@ impl Ui {
         use tao::event::{Event, WindowEvent};
 
         log::info!("starting event loop");
+        if let Some(timer) = self.startup_timer.as_mut() {
+            timer.phase("create UI");
+        }
         let mut start_time = Some(Instant::now());
 
         let menu_event_receiver = MenuEvent::receiver();
         let event_loop = self.event_loop.take().expect("event loop");
-        event_loop.run(move |event, _, control_flow| {
+        event_loop.run(move |event, window_target, control_flow| {
             // Show the window after 150 milliseconds to avoid the flashing white window on startup
             if start_time.is_some()
                 && Instant::now() - start_time.unwrap() > Duration::from_millis(150)
@@ -194,17 +674,54 @@ impl Ui {
                 log::info!("showing main window");
                 self.main_web_view.window().set_visible(true);
                 start_time = None;
+                if let Some(timer) = self.startup_timer.as_mut() {
+                    timer.phase("show main window");
+                }
+                if !self.launched_with_locations {
+                    self.startup_timer = None;
+                }
+
+                // Querying the version can be slow, and it's only informational, so defer it
+                log::info!(
+                    "webview version: {}",
+                    webview_version().as_deref().unwrap_or("unknown")
+                );
             }
             *control_flow =
                 ControlFlow::WaitUntil(Instant::now() + Duration::from_millis(1000 / 60));
 
+            self.handle_config_changes();
             self.handle_player_messages();
-            if let Some(new_flow) = self.handle_frontend_messages() {
+            self.sleep_inhibitor.poll();
+            if let Some(new_flow) = self.handle_frontend_messages(window_target) {
                 *control_flow = new_flow;
             }
+            let playing = self.playback_state.borrow().playback_status.playing;
+            self.media_keys.poll(playing, &self.frontend_broadcaster);
+            if let Some(instance_server) = &self.instance_server {
+                instance_server.poll(playing, &self.frontend_broadcaster);
+            }
+            if let Some(guest_queue) = self.guest_queue.as_mut() {
+                guest_queue.poll(self.playlist_manager.playlist(), &self.frontend_broadcaster);
+            }
+            if let Some(cast_server) = &self.cast_server {
+                cast_server.poll(self.playlist_manager.playlist());
+            }
             self.playlist_manager.update();
+            if self.playlist_manager.take_playlist_changed() {
+                let playlist = self.playlist_manager.playlist().state_data();
+                self.playlist_state.mutate(|state| *state = playlist);
+                let message = serde_json::to_string(&FrontendMessage::PlaylistStateUpdated)
+                    .expect("serializable");
+                self.main_web_view
+                    .evaluate_script(&format!("handle_message({message})"))
+                    .expect("valid script");
+            }
 
             if let Some(StateChanged) = self.playback_state_sub.try_recv() {
+                if let Some(now_playing) = self.now_playing.as_mut() {
+                    now_playing.update(&self.playback_state.borrow());
+                }
                 let message = serde_json::to_string(&FrontendMessage::PlaybackStateUpdated)
                     .expect("serializable");
                 self.main_web_view
@@ -217,10 +734,49 @@ impl Ui {
                 self.main_web_view
                     .evaluate_script(&format!("handle_message({message})"))
                     .expect("valid script");
+                if let Some(web_view) = &self.visualizer_web_view {
+                    web_view
+                        .evaluate_script(&format!("handle_message({message})"))
+                        .expect("valid script");
+                }
+            }
+            if let Some(StateChanged) = self.settings_state_sub.try_recv() {
+                let message = serde_json::to_string(&FrontendMessage::SettingsChanged {
+                    settings: self.settings_state.borrow().clone(),
+                })
+                .expect("serializable");
+                self.main_web_view
+                    .evaluate_script(&format!("handle_message({message})"))
+                    .expect("valid script");
+                // The visualizer window follows the theme and visualizer settings
+                if let Some(web_view) = &self.visualizer_web_view {
+                    web_view
+                        .evaluate_script(&format!("handle_message({message})"))
+                        .expect("valid script");
+                }
+            }
+            if let Some(StateChanged) = self.favorites_state_sub.try_recv() {
+                let message = serde_json::to_string(&FrontendMessage::FavoritesChanged {
+                    favorites: self.favorites_state.borrow().clone(),
+                })
+                .expect("serializable");
+                self.main_web_view
+                    .evaluate_script(&format!("handle_message({message})"))
+                    .expect("valid script");
+                // Rebuild the menu so that it lists the new favorites
+                self.media_controls_menu = None;
+            }
+            if let Some(StateChanged) = self.ui_prefs_state_sub.try_recv() {
+                if let Some(path) = &self.ui_prefs_path {
+                    if let Err(err) = ui_prefs::save(path, &self.ui_prefs_state.borrow()) {
+                        log::error!("{err}");
+                    }
+                }
             }
 
             match event {
                 Event::LoopDestroyed => {
+                    self.playlist_manager.shutdown();
                     if let Some(player) = self.player.take() {
                         self.player_sub.broadcast(PlayerMessage::CommandQuit);
                         if let Err(err) = player.join() {
@@ -230,50 +786,134 @@ impl Ui {
                     log::info!("bye!");
                 }
                 Event::WindowEvent {
+                    window_id,
                     event: WindowEvent::CloseRequested,
                     ..
-                } => *control_flow = ControlFlow::Exit,
+                } if self.is_visualizer_window(window_id) => self.close_visualizer_window(),
+                Event::WindowEvent {
+                    event: WindowEvent::CloseRequested,
+                    ..
+                } => {
+                    if !self.quit_blocked() {
+                        *control_flow = ControlFlow::Exit;
+                    }
+                }
 
                 _ => (),
             }
 
-            if let Ok(event) = menu_event_receiver.try_recv() {
-                if event.id == self.media_controls_menu.item_open.id() {
+            let menu_event = self
+                .media_controls_menu
+                .as_ref()
+                .and_then(|menu| Some((menu, menu_event_receiver.try_recv().ok()?)));
+            if let Some((menu, event)) = menu_event {
+                if event.id == menu.item_open.id() {
+                    let picked = pick_audio_files("Open audio file(s) or playlist");
+                    if let Some(picked) = picked {
+                        load_paths(&self.frontend_broadcaster, picked);
+                    }
+                } else if event.id == menu.item_open_folder.id() {
                     let picked = rfd::FileDialog::new()
-                        .add_filter(
-                            "Audio file or playlist",
-                            &[
-                                "m3u", "m3u8", "pls", "mp3", "flac", "ogg", "wav", "aac", "m4a",
-                            ],
-                        )
-                        .set_title("Open audio file(s) or playlist")
-                        .pick_files();
+                        .set_title("Open album or folder")
+                        .pick_folder();
+                    if let Some(picked) = picked {
+                        load_paths(&self.frontend_broadcaster, vec![picked]);
+                    }
+                } else if event.id == menu.item_play_next.id() {
+                    let picked = pick_audio_files("Play audio file(s) or playlist next");
                     if let Some(picked) = picked {
-                        self.frontend_sub.broadcast(FrontendMessage::LoadLocations {
-                            locations: picked
-                                .iter()
-                                .map(|path| Utf8Path::from_path(path).unwrap().to_string())
-                                .collect(),
+                        queue_paths(&self.frontend_broadcaster, picked);
+                    }
+                } else if event.id == menu.item_save_playlist.id() {
+                    self.frontend_broadcaster
+                        .broadcast(FrontendMessage::SavePlaylist);
+                } else if event.id == menu.item_add_favorite.id() {
+                    self.frontend_broadcaster
+                        .broadcast(FrontendMessage::AddFavorite);
+                } else if let Some((_, location)) = menu
+                    .favorite_items
+                    .iter()
+                    .find(|(item, _)| event.id == item.id())
+                {
+                    self.frontend_broadcaster
+                        .broadcast(FrontendMessage::LoadLocations {
+                            locations: vec![location.clone()],
                         });
+                } else if let Some((_, signal)) = menu
+                    .test_signal_items
+                    .iter()
+                    .find(|(item, _)| event.id == item.id())
+                {
+                    self.frontend_broadcaster
+                        .broadcast(FrontendMessage::PlayTestSignal { signal: *signal });
+                } else if let Some(item) = menu
+                    .item_guest_requests
+                    .as_ref()
+                    .filter(|item| event.id == item.id())
+                {
+                    if let Some(guest_queue) = self.guest_queue.as_mut() {
+                        guest_queue.set_accepting(item.is_checked());
                     }
-                } else if event.id == self.media_controls_menu.item_show_hide_playlist.id() {
-                    log::info!("TODO: show/hide playlist");
+                } else if event.id == menu.item_show_hide_playlist.id() {
+                    let message = serde_json::to_string(&FrontendMessage::ShowHidePlaylist)
+                        .expect("serializable");
+                    self.main_web_view
+                        .evaluate_script(&format!("handle_message({message})"))
+                        .expect("valid script");
+                } else if event.id == menu.item_export_analysis.id() {
+                    self.export_analysis();
+                } else if event.id == menu.item_check_gapless.id() {
+                    self.check_gapless();
+                } else if event.id == menu.item_export_backup.id() {
+                    self.export_backup();
+                } else if event.id == menu.item_import_backup.id() {
+                    self.import_backup();
+                } else if event.id == menu.item_open_log_folder.id() {
+                    self.frontend_broadcaster
+                        .broadcast(FrontendMessage::OpenLogFolder);
                 }
             }
 
-            if let Err(err) = self.healthcheck() {
+            self.healthcheck();
+        });
+    }
+
+    /// Applies external edits to the config file.
+    fn handle_config_changes(&mut self) {
+        let Some(reload) = self.config_watcher.as_mut().and_then(ConfigWatcher::poll) else {
+            return;
+        };
+        match reload {
+            Ok(reload) => {
+                if let Some(settings) = reload.settings {
+                    log::info!("applying changed settings: {settings:?}");
+                    self.frontend_broadcaster
+                        .broadcast(FrontendMessage::SettingsChanged { settings });
+                }
+                if reload.restart_required {
+                    self.frontend_broadcaster.broadcast(FrontendMessage::ShowAlert {
+                        level: AlertLevel::Info,
+                        message: format!(
+                            "Audio output changes will take effect the next time {APP_TITLE} starts."
+                        )
+                        .into(),
+                    });
+                }
+            }
+            Err(err) => {
                 log::error!("{err}");
-                rfd::MessageDialog::new()
-                    .set_level(rfd::MessageLevel::Error)
-                    .set_title("Fatal error")
-                    .set_description(format!("{APP_TITLE} had a fatal error:\n{err}"))
-                    .show();
-                *control_flow = ControlFlow::ExitWithCode(1);
+                self.frontend_broadcaster.broadcast(FrontendMessage::ShowAlert {
+                    level: AlertLevel::Error,
+                    message: format!(
+                        "The config file has an error, so the previous settings are still in use.\n{err}"
+                    )
+                    .into(),
+                });
             }
-        });
+        }
     }
 
-    fn handle_player_messages(&self) {
+    fn handle_player_messages(&mut self) {
         while let Some(message) = self.player_sub.try_recv() {
             if !message.frequent() {
                 log::info!("ui-backend received broadcast message: {message:?}");
@@ -281,6 +921,9 @@ impl Ui {
             match message {
                 PlayerMessage::UpdateWaveform(waveform) => {
                     let waveform_lock = waveform.lock().unwrap();
+                    if let Some(osc_sender) = self.osc_sender.as_mut() {
+                        osc_sender.send_levels(&waveform_lock.amplitude, &waveform_lock.spectrum);
+                    }
                     self.waveform_state.mutate(|state| {
                         state.waveform = Some(Waveform {
                             spectrum: waveform_lock.spectrum.into(),
@@ -288,36 +931,96 @@ impl Ui {
                         });
                     });
                 }
+                PlayerMessage::UpdateWaveformCleared => {
+                    self.waveform_state.mutate(|state| {
+                        state.waveform = None;
+                    });
+                }
+                PlayerMessage::UpdateBufferStats(stats) => {
+                    self.debug_state.mutate(|state| state.buffer_stats = stats);
+                }
                 PlayerMessage::UpdatePlaybackStatus(status) => {
+                    self.sleep_inhibitor.set_playing(status.playing);
+                    if let Some(osc_sender) = self.osc_sender.as_mut() {
+                        osc_sender.send_transport(&status);
+                    }
                     self.playback_state.mutate(|state| {
                         state.playback_status = status;
                     });
                 }
 
-                PlayerMessage::EventAudioDeviceCreationFailed(_err) => {
-                    // TODO
+                PlayerMessage::EventAudioDeviceCreationFailed(err) => {
+                    self.update_health(|health| {
+                        health.device = DeviceStatus::Unavailable;
+                        health.last_error = Some(err.to_string());
+                    });
                 }
-                PlayerMessage::EventAudioDeviceFailed(_err) => {
-                    // TODO
+                PlayerMessage::EventAudioDeviceFailed(err) => {
+                    self.update_health(|health| {
+                        health.device = DeviceStatus::Failed;
+                        health.last_error = Some(err);
+                    });
                 }
-                PlayerMessage::EventFailedToDecodeAudio(_err) => {
-                    // TODO
+                PlayerMessage::EventFailedToDecodeAudio(_)
+                | PlayerMessage::EventFailedToLoadLocation(_) => {
+                    // The playlist manager decides whether to retry or alert the user
                 }
-                PlayerMessage::EventFailedToLoadLocation(_err) => {
-                    // TODO
+                PlayerMessage::EventAbLoopChanged(ab_loop) => {
+                    self.playback_state.mutate(|state| state.ab_loop = ab_loop);
                 }
-                PlayerMessage::EventStartedTrack => {}
-                PlayerMessage::EventFinishedTrack => {
-                    self.waveform_state.mutate(|state| {
-                        state.waveform = None;
+                PlayerMessage::EventStreamHealthChanged(health) => {
+                    self.playback_state
+                        .mutate(|state| state.stream_health = health);
+                }
+                PlayerMessage::EventStartedTrack => {
+                    if let Some(timer) = self.startup_timer.take() {
+                        log::info!("time to first audio: {:?}", timer.elapsed());
+                    }
+                    self.playback_state
+                        .mutate(|state| state.ab_loop = AbLoop::default());
+                    // Playing again means the device recovered
+                    if self.debug_state.borrow().health.device == DeviceStatus::Failed {
+                        self.update_health(|health| health.device = DeviceStatus::Ok);
+                    }
+                }
+                PlayerMessage::EventStartedNextTrack(location) => {
+                    // The metadata for the new track follows this event
+                    self.started_next_location = Some(location);
+                    self.playback_state.mutate(|state| {
+                        state.current_track = None;
+                        state.album_art = None;
+                        state.intro_skipped = None;
+                        state.ab_loop = AbLoop::default();
                     });
+                }
+                PlayerMessage::EventFinishedTrack => {
+                    self.sleep_inhibitor.track_finished();
                     self.playback_state.mutate(|state| {
                         state.playback_status = PlaybackStatus::default();
                         state.current_track = None;
+                        state.album_art = None;
+                        state.intro_skipped = None;
+                        state.stream_health = None;
+                        state.ab_loop = AbLoop::default();
                     });
                 }
-                PlayerMessage::EventMetadataLoaded(metadata) => {
+                PlayerMessage::EventMetadataLoaded(mut metadata) => {
+                    metadata.decode_text(&self.tag_decoder);
+                    let location = self
+                        .started_next_location
+                        .take()
+                        .or_else(|| self.playlist_manager.playlist().current_location().cloned());
+                    let track_path = match &location {
+                        Some(Location::Path(path)) => Some(path.as_std_path()),
+                        _ => None,
+                    };
+                    let album_art = self.album_art_cache.art_for(
+                        track_path,
+                        metadata.album.as_deref(),
+                        metadata.cover.as_ref(),
+                    );
                     self.playback_state.mutate(|state| {
+                        state.album_art = album_art;
                         state.current_track = Some(Track {
                             title: metadata.track_title,
                             artist: metadata.artist,
@@ -331,15 +1034,200 @@ impl Ui {
         }
     }
 
-    fn handle_frontend_messages(&self) -> Option<ControlFlow> {
+    fn handle_frontend_messages(
+        &mut self,
+        window_target: &EventLoopWindowTarget<()>,
+    ) -> Option<ControlFlow> {
         while let Some(message) = self.frontend_sub.try_recv() {
             match message {
+                FrontendMessage::Quit if self.quit_blocked() => {}
                 FrontendMessage::Quit => return Some(ControlFlow::Exit),
+                FrontendMessage::RestartPlayer => self.restart_player(),
+                FrontendMessage::OpenVisualizerWindow => self.open_visualizer_window(window_target),
                 FrontendMessage::DragWindowStart => {
                     self.main_web_view.window().drag_window().unwrap();
                 }
                 FrontendMessage::MediaControlMenu => {
-                    self.media_controls_menu.show(self.main_web_view.window());
+                    let (favorites, guest_queue, cast_server) =
+                        (&self.favorites_state, &self.guest_queue, &self.cast_server);
+                    self.media_controls_menu
+                        .get_or_insert_with(|| {
+                            MediaControlsMenu::new(
+                                &favorites.borrow(),
+                                guest_queue.as_ref(),
+                                cast_server.as_ref(),
+                            )
+                        })
+                        .show(self.main_web_view.window());
+                }
+                FrontendMessage::OpenLogFolder => {
+                    if let Err(err) = log_file::open_log_dir() {
+                        log::error!("failed to open log folder: {err}");
+                    }
+                }
+                FrontendMessage::SavePlaylist => self.save_playlist(),
+                FrontendMessage::LoadLocations { locations } => self.last_opened = locations,
+                FrontendMessage::AddFavorite => self.add_favorites(),
+                FrontendMessage::RemoveFavorite { location } => self.remove_favorite(&location),
+                FrontendMessage::IntroSkipped { position } => {
+                    self.playback_state.mutate(|state| {
+                        state.intro_skipped = Some(position);
+                    });
+                }
+                FrontendMessage::SettingsChanged { settings } => {
+                    if settings.sorting != self.settings_state.borrow().sorting {
+                        self.playlist_manager
+                            .set_sort_options(SortOptions::from(&settings.sorting));
+                    }
+                    if settings.normalization != self.settings_state.borrow().normalization {
+                        self.player_sub
+                            .broadcast(PlayerMessage::CommandSetNormalizationMode(
+                                settings.normalization,
+                            ));
+                    }
+                    self.playlist_manager
+                        .set_peak_normalization(settings.peak_normalization());
+                    self.sleep_inhibitor.set_enabled(settings.prevent_sleep);
+                    self.media_keys.set_enabled(settings.media_keys);
+                    self.playlist_manager
+                        .set_shuffle_seed(settings.shuffle_seed);
+                    if settings.clamped_ui_scale()
+                        != self.settings_state.borrow().clamped_ui_scale()
+                    {
+                        apply_ui_scale(&self.main_web_view, settings.clamped_ui_scale());
+                    }
+                    if settings.track_transition()
+                        != self.settings_state.borrow().track_transition()
+                    {
+                        self.playlist_manager
+                            .set_track_transition(settings.track_transition());
+                    }
+                    self.settings_state.mutate(|state| *state = settings);
+                }
+                FrontendMessage::UpdateSettings { .. }
+                | FrontendMessage::SetOutputDevice { .. }
+                    if self.playlist_manager.party_mode_active() =>
+                {
+                    log::info!("not changing settings during party mode");
+                }
+                FrontendMessage::UpdateSettings { settings } => {
+                    if let Some(watcher) = self.config_watcher.as_mut() {
+                        if let Err(err) = watcher.save_settings(&settings) {
+                            log::error!("{err}");
+                            self.frontend_broadcaster
+                                .broadcast(FrontendMessage::ShowAlert {
+                                    level: AlertLevel::Error,
+                                    message: format!("Failed to save the settings:\n{err}").into(),
+                                });
+                        }
+                    }
+                    self.frontend_broadcaster
+                        .broadcast(FrontendMessage::SettingsChanged { settings });
+                }
+                FrontendMessage::ListOutputDevices => self.list_output_devices(),
+                FrontendMessage::SetOutputDevice { name } => self.set_output_device(name),
+                FrontendMessage::PlaylistModeChanged { mode, shuffle_seed } => {
+                    self.playback_state.mutate(|state| {
+                        state.playlist_mode = mode;
+                        state.shuffle_seed = shuffle_seed;
+                    });
+                }
+                FrontendMessage::MediaControlDspBypass { bypass } => {
+                    self.player_sub
+                        .broadcast(PlayerMessage::CommandSetDspBypass(bypass));
+                    self.playback_state.mutate(|state| {
+                        state.dsp_bypassed = bypass;
+                    });
+                }
+                FrontendMessage::MediaControlMarkLoopA => {
+                    self.player_sub
+                        .broadcast(PlayerMessage::CommandMarkLoopStart);
+                }
+                FrontendMessage::MediaControlMarkLoopB => {
+                    self.player_sub.broadcast(PlayerMessage::CommandMarkLoopEnd);
+                }
+                FrontendMessage::MediaControlClearLoop => {
+                    self.player_sub.broadcast(PlayerMessage::CommandClearLoop);
+                }
+                FrontendMessage::PartyModeChanged { active } => {
+                    self.playback_state.mutate(|state| {
+                        state.party_mode = active;
+                    });
+                }
+                FrontendMessage::PlayQueueChanged { queue } => {
+                    if let Some(guest_queue) = self.guest_queue.as_mut() {
+                        guest_queue.play_queue_changed(&queue);
+                    }
+                    self.playback_state.mutate(|state| {
+                        state.play_queue = queue;
+                    });
+                }
+                FrontendMessage::SongHistoryChanged { songs } => {
+                    self.playback_state.mutate(|state| {
+                        state.song_history = songs;
+                    });
+                }
+                FrontendMessage::TrackRatingChanged { rating } => {
+                    self.playback_state.mutate(|state| {
+                        state.rating = rating;
+                    });
+                }
+                FrontendMessage::StreamQualityChanged { quality } => {
+                    self.playback_state.mutate(|state| {
+                        state.stream_quality = quality;
+                    });
+                }
+                FrontendMessage::DuplicatesSkipped { duplicates } => {
+                    self.playback_state.mutate(|state| {
+                        state.skipped_duplicates = duplicates;
+                    });
+                }
+                FrontendMessage::QueueSkippedDuplicates => {
+                    let mut duplicates = Vec::new();
+                    self.playback_state.mutate(|state| {
+                        duplicates = std::mem::take(&mut state.skipped_duplicates);
+                    });
+                    if !duplicates.is_empty() {
+                        self.frontend_broadcaster
+                            .broadcast(FrontendMessage::QueueLocations {
+                                locations: duplicates
+                                    .into_iter()
+                                    .map(|track| track.location)
+                                    .collect(),
+                                allow_duplicates: true,
+                            });
+                    }
+                }
+                FrontendMessage::PasteLocations { text } => {
+                    let pasted = parse_pasted_locations(&text);
+                    if !pasted.invalid.is_empty() {
+                        self.frontend_broadcaster
+                            .broadcast(FrontendMessage::ShowAlert {
+                                level: AlertLevel::Warn,
+                                message: format!(
+                                    "These pasted lines aren't absolute paths or URLs, so they were skipped:\n{}",
+                                    pasted.invalid.join("\n")
+                                )
+                                .into(),
+                            });
+                    }
+                    if !pasted.locations.is_empty() {
+                        self.frontend_broadcaster
+                            .broadcast(FrontendMessage::QueueLocations {
+                                locations: pasted.locations,
+                                allow_duplicates: false,
+                            });
+                    }
+                }
+                FrontendMessage::UnplayableEntriesSkipped { errors } => {
+                    self.playback_state.mutate(|state| {
+                        state.unplayable = errors;
+                    });
+                }
+                FrontendMessage::MediaControlUndoIntroSkip => {
+                    self.playback_state.mutate(|state| {
+                        state.intro_skipped = None;
+                    });
                 }
                 FrontendMessage::ShowAlert { level, message } => {
                     let (level, title) = match level {
@@ -363,20 +1251,407 @@ impl Ui {
                     };
                     log::log!(level, "[wasm] {message}");
                 }
+                FrontendMessage::PlaylistEntriesUpdated { entries } => {
+                    self.playlist_state
+                        .mutate(|state| state.update_entries(&entries));
+                    // Passed straight through so that the playlist can update the changed rows
+                    let message =
+                        serde_json::to_string(&FrontendMessage::PlaylistEntriesUpdated { entries })
+                            .expect("serializable");
+                    self.main_web_view
+                        .evaluate_script(&format!("handle_message({message})"))
+                        .expect("valid script");
+                }
                 _ => {}
             }
         }
         None
     }
 
-    fn healthcheck(&mut self) -> Result<(), FatalError> {
-        if let Some(player) = self.player.take() {
-            match player.healthcheck() {
-                Ok(player) => self.player = Some(player),
-                Err(err) => return Err(err.into()),
+    /// Tells the user to stop party mode first if it's on, so that guests can't quit.
+    /// Checks whether quitting should go ahead. Quitting is refused during party mode, and
+    /// needs confirming if it would interrupt background work or lose playlist edits.
+    fn quit_blocked(&self) -> bool {
+        if self.playlist_manager.party_mode_active() {
+            rfd::MessageDialog::new()
+                .set_level(rfd::MessageLevel::Info)
+                .set_title("Party mode")
+                .set_description("Stop party mode before quitting.")
+                .show();
+            return true;
+        }
+        let mut interrupted = self.playlist_manager.busy_tasks();
+        if self.playlist_manager.has_unsaved_edits() {
+            interrupted.push("Unsaved changes to the playlist");
+        }
+        if interrupted.is_empty() {
+            return false;
+        }
+        let list: String = interrupted
+            .iter()
+            .map(|item| format!("\n• {item}"))
+            .collect();
+        let confirmed = rfd::MessageDialog::new()
+            .set_level(rfd::MessageLevel::Warning)
+            .set_title("Quit")
+            .set_description(format!("Quitting now will interrupt:{list}"))
+            .set_buttons(rfd::MessageButtons::OkCancel)
+            .show();
+        !matches!(confirmed, rfd::MessageDialogResult::Ok)
+    }
+
+    fn add_favorites(&mut self) {
+        let Some(favorites) = self.favorites.as_mut() else {
+            log::warn!("not adding favorites since the favorites file couldn't be loaded");
+            return;
+        };
+        for location in &self.last_opened {
+            let added = Location::from_str(location)
+                .map_err(|err| err.to_string())
+                .and_then(|location| favorites.add(&location).map_err(|err| err.to_string()));
+            if let Err(err) = added {
+                log::error!("failed to add {location} to favorites: {err}");
+            }
+        }
+        self.favorites_state
+            .mutate(|state| *state = favorites.list().to_vec());
+    }
+
+    fn remove_favorite(&mut self, location: &str) {
+        let Some(favorites) = self.favorites.as_mut() else {
+            return;
+        };
+        if let Err(err) = favorites.remove(location) {
+            log::error!("{err}");
+        }
+        self.favorites_state
+            .mutate(|state| *state = favorites.list().to_vec());
+    }
+
+    fn save_playlist(&mut self) {
+        let picked = rfd::FileDialog::new()
+            .add_filter("M3U8 playlist", &[PlaylistFormat::M3u8.extension()])
+            .add_filter("XSPF playlist", &[PlaylistFormat::Xspf.extension()])
+            .set_title("Save playlist")
+            .set_file_name("Playlist.m3u8")
+            .save_file();
+        let Some(picked) = picked else {
+            return;
+        };
+        let mut path = match Utf8PathBuf::from_path_buf(picked) {
+            Ok(path) => path,
+            Err(path) => {
+                log::error!("playlist path isn't valid UTF-8: {path:?}");
+                return;
+            }
+        };
+        let format = PlaylistFormat::from_path(&path);
+        if path.extension().is_none() {
+            path.set_extension(format.extension());
+        }
+        if let Err(err) = self.playlist_manager.save_playlist(&path, format) {
+            log::error!("{err}");
+            self.frontend_broadcaster
+                .broadcast(FrontendMessage::ShowAlert {
+                    level: AlertLevel::Error,
+                    message: format!("Failed to save the playlist to {path}:\n{err}").into(),
+                });
+        }
+    }
+
+    fn list_output_devices(&mut self) {
+        let devices = output_device_names().unwrap_or_else(|err| {
+            log::error!("failed to list audio output devices: {err}");
+            Vec::new()
+        });
+        let selected = self
+            .config_watcher
+            .as_ref()
+            .and_then(|watcher| watcher.config().audio.output_device.clone());
+        let message =
+            serde_json::to_string(&FrontendMessage::OutputDevicesListed { devices, selected })
+                .expect("serializable");
+        self.main_web_view
+            .evaluate_script(&format!("handle_message({message})"))
+            .expect("valid script");
+    }
+
+    fn set_output_device(&mut self, name: Option<String>) {
+        let Some(watcher) = self.config_watcher.as_mut() else {
+            log::warn!("not saving the output device since there's no config directory");
+            return;
+        };
+        let (level, message) = match watcher.save_output_device(name.as_deref()) {
+            Ok(_) => (
+                AlertLevel::Info,
+                format!("The audio output device will change the next time {APP_TITLE} starts."),
+            ),
+            Err(err) => {
+                log::error!("{err}");
+                (
+                    AlertLevel::Error,
+                    format!("Failed to save the audio output device:\n{err}"),
+                )
+            }
+        };
+        self.frontend_broadcaster
+            .broadcast(FrontendMessage::ShowAlert {
+                level,
+                message: message.into(),
+            });
+    }
+
+    fn export_analysis(&mut self) {
+        // Take the snapshot before the dialog opens so that it matches what was on screen
+        let Some(waveform) = self.waveform_state.borrow().waveform.clone() else {
+            self.frontend_broadcaster
+                .broadcast(FrontendMessage::ShowAlert {
+                    level: AlertLevel::Info,
+                    message: "Start playing a track to export its analysis.".into(),
+                });
+            return;
+        };
+        let picked = rfd::FileDialog::new()
+            .add_filter("CSV", &[AnalysisFormat::Csv.extension()])
+            .add_filter("PNG image", &[AnalysisFormat::Png.extension()])
+            .set_title("Export analysis")
+            .set_file_name("Analysis.csv")
+            .save_file();
+        let Some(mut path) = picked else {
+            return;
+        };
+        let format = AnalysisFormat::from_path(&path);
+        if path.extension().is_none() {
+            path.set_extension(format.extension());
+        }
+        if let Err(err) = export_analysis(&waveform, &path, format) {
+            log::error!("{err}");
+            self.frontend_broadcaster
+                .broadcast(FrontendMessage::ShowAlert {
+                    level: AlertLevel::Error,
+                    message: format!("Failed to export the analysis:\n{err}").into(),
+                });
+        }
+    }
+
+    fn check_gapless(&mut self) {
+        let locations = self.playlist_manager.playlist().file_locations();
+        if locations.len() < 2 {
+            self.frontend_broadcaster
+                .broadcast(FrontendMessage::ShowAlert {
+                    level: AlertLevel::Info,
+                    message: "Load an album to check its gapless transitions.".into(),
+                });
+            return;
+        }
+        // Decoding every track's edges takes a while, so the report is sent when it's ready
+        let broadcaster = self.frontend_broadcaster.clone();
+        let spawned = thread::Builder::new()
+            .name("gapless-check".into())
+            .spawn(move || {
+                let message = gapless_report(&check_transitions(&locations));
+                broadcaster.broadcast(FrontendMessage::ShowAlert {
+                    level: AlertLevel::Info,
+                    message: message.into(),
+                });
+            });
+        if let Err(err) = spawned {
+            log::error!("failed to start the gapless check: {err}");
+        }
+    }
+
+    fn export_backup(&mut self) {
+        let Some(paths) = StatePaths::default_paths() else {
+            log::error!("no config directory to back up");
+            return;
+        };
+        let picked = rfd::FileDialog::new()
+            .add_filter(format!("{APP_TITLE} backup"), &["toml"])
+            .set_title("Export backup")
+            .set_file_name(format!("{APP_TITLE} backup.toml"))
+            .save_file();
+        let Some(path) = picked else {
+            return;
+        };
+        let exported = Backup::collect(&paths, &self.favorites_state.borrow())
+            .and_then(|backup| backup.save(&path));
+        if let Err(err) = exported {
+            log::error!("{err}");
+            self.frontend_broadcaster
+                .broadcast(FrontendMessage::ShowAlert {
+                    level: AlertLevel::Error,
+                    message: format!("Failed to export the backup:\n{err}").into(),
+                });
+        }
+    }
+
+    /// Replaces the settings and favorites with the ones from a backup. Settings are
+    /// picked up by the config watcher, so only the favorites need reloading here.
+    fn import_backup(&mut self) {
+        let Some(paths) = StatePaths::default_paths() else {
+            log::error!("no config directory to restore the backup to");
+            return;
+        };
+        let picked = rfd::FileDialog::new()
+            .add_filter(format!("{APP_TITLE} backup"), &["toml"])
+            .set_title("Import backup")
+            .pick_file();
+        let Some(path) = picked else {
+            return;
+        };
+        let confirmed = rfd::MessageDialog::new()
+            .set_level(rfd::MessageLevel::Warning)
+            .set_title("Import backup")
+            .set_description("Importing replaces your current settings and favorites.")
+            .set_buttons(rfd::MessageButtons::OkCancel)
+            .show();
+        if !matches!(confirmed, rfd::MessageDialogResult::Ok) {
+            return;
+        }
+        let imported = Backup::load(&path).and_then(|backup| backup.restore(&paths));
+        if let Err(err) = imported {
+            log::error!("{err}");
+            self.frontend_broadcaster
+                .broadcast(FrontendMessage::ShowAlert {
+                    level: AlertLevel::Error,
+                    message: format!("Failed to import the backup:\n{err}").into(),
+                });
+            return;
+        }
+        log::info!("imported backup from {path:?}");
+        match Favorites::load(paths.favorites) {
+            Ok(favorites) => {
+                self.favorites_state
+                    .mutate(|state| *state = favorites.list().to_vec());
+                self.favorites = Some(favorites);
+            }
+            Err(err) => log::error!("{err}"),
+        }
+    }
+
+    /// Checks that the player thread is still running. The frontend offers to restart it
+    /// if it exited.
+    fn healthcheck(&mut self) {
+        let Some(player) = self.player.take() else {
+            return;
+        };
+        match player.healthcheck() {
+            Ok(player) => self.player = Some(player),
+            Err(err) => {
+                log::error!("player thread exited: {err}");
+                self.playback_state
+                    .mutate(|state| state.playback_status.playing = false);
+                self.update_health(|health| {
+                    health.player_alive = false;
+                    health.last_error = Some(err.to_string());
+                });
+            }
+        }
+    }
+
+    /// Replaces the player thread after it exited, and picks the current track back up from
+    /// where it was.
+    fn restart_player(&mut self) {
+        if self.player.is_some() {
+            log::info!("not restarting the player since it's still running");
+            return;
+        }
+        let audio_config = &self.audio_config;
+        let spawned = PlayerThread::spawn_with_broadcaster(
+            self.player_broadcaster.clone(),
+            audio_config.output_device.clone(),
+            audio_config.channel_maps.clone(),
+            audio_config.device_options(),
+        );
+        match spawned {
+            Ok(player) => {
+                log::info!("restarted the player thread");
+                self.player = Some(Box::new(player));
+                let normalization = self.settings_state.borrow().normalization;
+                let (dsp_bypassed, volume) = {
+                    let state = self.playback_state.borrow();
+                    (state.dsp_bypassed, state.playback_status.volume)
+                };
+                self.player_sub
+                    .broadcast(PlayerMessage::CommandSetNormalizationMode(normalization));
+                self.player_sub
+                    .broadcast(PlayerMessage::CommandSetDspBypass(dsp_bypassed));
+                self.player_sub
+                    .broadcast(PlayerMessage::CommandSetVolume(volume));
+                self.playlist_manager.reload_current();
+                self.update_health(|health| {
+                    health.player_alive = true;
+                    health.device = DeviceStatus::Ok;
+                });
+            }
+            Err(err) => {
+                log::error!("failed to restart the player thread: {err}");
+                self.update_health(|health| health.last_error = Some(err.to_string()));
+            }
+        }
+    }
+
+    /// Pops the visualizer out into its own resizable window, which shows the same waveform
+    /// as the main window. Focuses the window instead if it's already open.
+    fn open_visualizer_window(&mut self, window_target: &EventLoopWindowTarget<()>) {
+        if let Some(web_view) = &self.visualizer_web_view {
+            web_view.window().set_focus();
+            return;
+        }
+        let created = tao::window::WindowBuilder::new()
+            .with_title(format!("{APP_TITLE} Visualizer"))
+            .with_resizable(true)
+            .with_inner_size(Size::Logical(VISUALIZER_WINDOW_SIZE))
+            .build(window_target)
+            .map_err(|err| FatalError::new("failed to create window", err))
+            .and_then(|window| {
+                create_webview(
+                    window,
+                    self.frontend_broadcaster.clone(),
+                    self.internal_protocol.clone(),
+                    VISUALIZER_WINDOW_URL,
+                )
+            });
+        match created {
+            Ok(web_view) => {
+                log::info!("opened the visualizer window");
+                self.visualizer_web_view = Some(web_view);
+                self.playback_state
+                    .mutate(|state| state.visualizer_detached = true);
+            }
+            Err(err) => {
+                log::error!("failed to open the visualizer window: {err}");
+                self.frontend_broadcaster
+                    .broadcast(FrontendMessage::ShowAlert {
+                        level: AlertLevel::Error,
+                        message: "The visualizer window couldn't be opened.".into(),
+                    });
             }
         }
-        Ok(())
+    }
+
+    fn is_visualizer_window(&self, window_id: tao::window::WindowId) -> bool {
+        self.visualizer_web_view
+            .as_ref()
+            .map(|web_view| web_view.window().id() == window_id)
+            .unwrap_or_default()
+    }
+
+    /// Closes the visualizer window, which puts the visualizer back in the main window.
+    fn close_visualizer_window(&mut self) {
+        if self.visualizer_web_view.take().is_some() {
+            log::info!("closed the visualizer window");
+            self.playback_state
+                .mutate(|state| state.visualizer_detached = false);
+        }
+    }
+
+    fn update_health(&mut self, update: impl FnOnce(&mut InstanceHealth)) {
+        self.debug_state.mutate(|state| update(&mut state.health));
+        let message = serde_json::to_string(&FrontendMessage::HealthUpdated).expect("serializable");
+        self.main_web_view
+            .evaluate_script(&format!("handle_message({message})"))
+            .expect("valid script");
     }
 }
 
@@ -417,15 +1692,22 @@ impl OsxAppMenu {
     }
 }
 
+/// Zooms the web view, and grows the window to match so that nothing gets cut off.
+fn apply_ui_scale(web_view: &wry::webview::WebView, scale: f32) {
+    let scale = f64::from(scale);
+    web_view.zoom(scale);
+    web_view.window().set_inner_size(LogicalSize::new(
+        MAIN_WINDOW_SIZE.width * scale,
+        MAIN_WINDOW_SIZE.height * scale,
+    ));
+}
+
 fn create_webview(
     window: tao::window::Window,
     ui_broadcaster: Broadcaster<FrontendMessage>,
     internal_protocol: Rc<InternalProtocol>,
+    url: &str,
 ) -> Result<wry::webview::WebView, FatalError> {
-    log::info!(
-        "webview version: {}",
-        webview_version().as_deref().unwrap_or("unknown")
-    );
     let webview = wry::webview::WebViewBuilder::new(window)
         .map_err(|err| FatalError::new("failed to create web view", err))?
         .with_hotkeys_zoom(false)
@@ -439,29 +1721,121 @@ fn create_webview(
         .with_ipc_handler({
             let broadcaster = ui_broadcaster.clone();
             move |_window, message| {
-                match serde_json::from_str::<FrontendMessage>(&message) {
-                    Ok(message) => {
-                        broadcaster.broadcast(message);
-                    }
+                match ipc::parse_post(&message) {
+                    // Batches go out together so that nothing else is handled in between
+                    Ok(messages) => broadcaster.broadcast_all(messages),
                     Err(err) => {
                         log::error!("failed to deserialize IPC message from the webview: {err}\nmessage: {message}")
                     }
                 }
             }
         })
-        .with_url("internal://localhost/index.html")
+        .with_url(url)
         .map_err(|err| FatalError::new("failed to set web view URL", err))?
         .with_file_drop_handler(move |_window, event| {
             if let FileDropEvent::Dropped { paths, .. } = event {
-                let locations = paths.into_iter()
-                    .map(|path| Utf8Path::from_path(&path).unwrap().to_string())
-                    .collect::<Vec<_>>();
-                ui_broadcaster.broadcast(FrontendMessage::LoadLocations { locations });
+                load_paths(&ui_broadcaster, paths);
             }
             true
         })
         .with_transparent(true)
+        // The frontend handles copy and paste events itself
+        .with_clipboard(true)
         .build()
         .map_err(|err| FatalError::new("failed to create web view", err))?;
     Ok(webview)
 }
+
+fn pick_audio_files(title: &str) -> Option<Vec<PathBuf>> {
+    rfd::FileDialog::new()
+        .add_filter(
+            "Audio file or playlist",
+            &[
+                "m3u", "m3u8", "pls", "mp3", "flac", "ogg", "opus", "wav", "aac", "m4a", "m4b",
+            ],
+        )
+        .set_title(title)
+        .pick_files()
+}
+
+/// Loads file system paths into the playlist.
+fn load_paths(broadcaster: &Broadcaster<FrontendMessage>, paths: Vec<PathBuf>) {
+    let locations = path_locations(broadcaster, paths);
+    if !locations.is_empty() {
+        broadcaster.broadcast(FrontendMessage::LoadLocations { locations });
+    }
+}
+
+/// Adds file system paths to the play queue.
+fn queue_paths(broadcaster: &Broadcaster<FrontendMessage>, paths: Vec<PathBuf>) {
+    let locations = path_locations(broadcaster, paths);
+    if !locations.is_empty() {
+        broadcaster.broadcast(FrontendMessage::QueueLocations {
+            locations,
+            allow_duplicates: false,
+        });
+    }
+}
+
+/// Converts file system paths into locations.
+///
+/// Locations must be valid UTF-8, so any paths that aren't are reported to the user
+/// in an alert rather than being silently dropped.
+fn path_locations(broadcaster: &Broadcaster<FrontendMessage>, paths: Vec<PathBuf>) -> Vec<String> {
+    let (mut locations, mut invalid) = (Vec::new(), Vec::new());
+    for path in paths {
+        match Utf8PathBuf::from_path_buf(path) {
+            Ok(path) => locations.push(path.into_string()),
+            Err(path) => invalid.push(path),
+        }
+    }
+    if !invalid.is_empty() {
+        log::warn!("ignoring paths that aren't valid UTF-8: {invalid:?}");
+        let listing = invalid
+            .iter()
+            .map(|path| format!("\n{}", path.display()))
+            .collect::<String>();
+        broadcaster.broadcast(FrontendMessage::ShowAlert {
+            level: AlertLevel::Warn,
+            message: format!(
+                "The following files can't be played since their names contain characters \
+                 that aren't valid Unicode. Renaming them will allow them to be played.\n{listing}"
+            )
+            .into(),
+        });
+    }
+    locations
+}
+
+/// Lists each transition, with the reasons for any that won't be gapless.
+fn gapless_report(transitions: &[Transition]) -> String {
+    let name = |location: &Location| {
+        location
+            .as_path()
+            .and_then(|path| path.file_name())
+            .unwrap_or(location.as_str())
+            .to_string()
+    };
+    let gaps = transitions.iter().filter(|t| !t.is_gapless()).count();
+    let mut report = if gaps == 0 {
+        "Every transition is gapless.\n".to_string()
+    } else {
+        format!("{gaps} of {} transitions have gaps.\n", transitions.len())
+    };
+    for transition in transitions {
+        let mark = if transition.is_gapless() {
+            '✓'
+        } else {
+            '✗'
+        };
+        report += &format!(
+            "\n{mark} {} → {}",
+            name(&transition.from),
+            name(&transition.to)
+        );
+        for issue in &transition.issues {
+            report += &format!("\n    {issue}");
+        }
+    }
+    report
+}
@@ -0,0 +1,92 @@
+// This file is part of Millenium Player.
+// Copyright (C) 2023 John DiSanti.
+//
+// Millenium Player is free software: you can redistribute it and/or modify it under the terms of
+// the GNU General Public License as published by the Free Software Foundation, either version 3 of
+// the License, or (at your option) any later version.
+//
+// Millenium Player is distributed in the hope that it will be useful, but WITHOUT ANY WARRANTY;
+// without even the implied warranty of MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE. See
+// the GNU General Public License for more details.
+//
+// You should have received a copy of the GNU General Public License along with Millenium Player.
+// If not, see <https://www.gnu.org/licenses/>.
+
+use crate::APP_NAME;
+use millenium_post_office::frontend::state::UiPrefs;
+use std::{
+    fs, io,
+    path::{Path, PathBuf},
+};
+
+#[derive(Debug, thiserror::Error)]
+pub enum UiPrefsError {
+    #[error("failed to read UI preferences file {path:?}: {source}")]
+    Read { path: PathBuf, source: io::Error },
+    #[error("failed to parse UI preferences file {path:?}: {source}")]
+    Parse {
+        path: PathBuf,
+        source: toml::de::Error,
+    },
+    #[error("failed to save UI preferences file {path:?}: {source}")]
+    Write { path: PathBuf, source: io::Error },
+}
+
+/// Default location of the UI preferences file.
+pub fn default_path() -> Option<PathBuf> {
+    dirs::data_dir().map(|dir| dir.join(APP_NAME).join("ui-prefs.toml"))
+}
+
+/// Loads the UI preferences file at the given path, using the defaults if it doesn't exist.
+pub fn load(path: &Path) -> Result<UiPrefs, UiPrefsError> {
+    match fs::read_to_string(path) {
+        Ok(contents) => toml::from_str(&contents).map_err(|source| UiPrefsError::Parse {
+            path: path.into(),
+            source,
+        }),
+        Err(err) if err.kind() == io::ErrorKind::NotFound => Ok(UiPrefs::default()),
+        Err(source) => Err(UiPrefsError::Read {
+            path: path.into(),
+            source,
+        }),
+    }
+}
+
+pub fn save(path: &Path, prefs: &UiPrefs) -> Result<(), UiPrefsError> {
+    let contents = toml::to_string(prefs).expect("serializable");
+    let write = || {
+        if let Some(dir) = path.parent() {
+            fs::create_dir_all(dir)?;
+        }
+        fs::write(path, contents)
+    };
+    write().map_err(|source| UiPrefsError::Write {
+        path: path.into(),
+        source,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use millenium_post_office::frontend::state::TimeDisplay;
+
+    #[test]
+    fn save_and_load() {
+        let dir = std::env::temp_dir().join(format!("{APP_NAME}-ui-prefs-{}", std::process::id()));
+        let path = dir.join("data").join("ui-prefs.toml");
+        assert_eq!(UiPrefs::default(), load(&path).unwrap());
+
+        let prefs = UiPrefs {
+            playlist_visible: true,
+            visualizer_popped_out: false,
+            time_display: TimeDisplay::Remaining,
+        };
+        save(&path, &prefs).unwrap();
+        assert_eq!(prefs, load(&path).unwrap());
+
+        fs::write(&path, "playlist-visible = \"yes\"").unwrap();
+        assert!(matches!(load(&path), Err(UiPrefsError::Parse { .. })));
+        fs::remove_dir_all(dir).unwrap();
+    }
+}
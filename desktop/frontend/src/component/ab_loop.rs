@@ -0,0 +1,64 @@
+// This file is part of Millenium Player.
+// Copyright (C) 2023 John DiSanti.
+//
+// Millenium Player is free software: you can redistribute it and/or modify it under the terms of
+// the GNU General Public License as published by the Free Software Foundation, either version 3 of
+// the License, or (at your option) any later version.
+//
+// Millenium Player is distributed in the hope that it will be useful, but WITHOUT ANY WARRANTY;
+// without even the implied warranty of MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE. See
+// the GNU General Public License for more details.
+//
+// You should have received a copy of the GNU General Public License along with Millenium Player.
+// If not, see <https://www.gnu.org/licenses/>.
+
+use crate::message::post_message;
+use millenium_post_office::{frontend::message::FrontendMessage, types::AbLoop};
+use yew::prelude::*;
+
+#[derive(Properties, PartialEq)]
+pub struct AbLoopButtonsProps {
+    pub ab_loop: AbLoop,
+}
+
+/// Marks the start and end of a region of the current track to repeat.
+#[function_component(AbLoopButtons)]
+pub fn ab_loop_buttons(props: &AbLoopButtonsProps) -> Html {
+    let mark_a = |_| post_message(&FrontendMessage::MediaControlMarkLoopA);
+    let mark_b = |_| post_message(&FrontendMessage::MediaControlMarkLoopB);
+    let clear = |_| post_message(&FrontendMessage::MediaControlClearLoop);
+    let class = |marked: bool| {
+        if marked {
+            "ab-loop-button active"
+        } else {
+            "ab-loop-button"
+        }
+    };
+    let ab_loop = props.ab_loop;
+    html! {
+        <div class="ab-loop">
+            <button type="button"
+                    class={class(ab_loop.a.is_some())}
+                    title="Start repeating here"
+                    aria-label="Mark loop start"
+                    onclick={mark_a}>
+                {"A"}
+            </button>
+            <button type="button"
+                    class={class(ab_loop.b.is_some())}
+                    title="Repeat back to the start from here"
+                    aria-label="Mark loop end"
+                    onclick={mark_b}>
+                {"B"}
+            </button>
+            <button type="button"
+                    class="ab-loop-button"
+                    title="Stop repeating"
+                    aria-label="Clear loop"
+                    disabled={ab_loop == AbLoop::default()}
+                    onclick={clear}>
+                {"×"}
+            </button>
+        </div>
+    }
+}
@@ -0,0 +1,45 @@
+// This file is part of Millenium Player.
+// Copyright (C) 2023 John DiSanti.
+//
+// Millenium Player is free software: you can redistribute it and/or modify it under the terms of
+// the GNU General Public License as published by the Free Software Foundation, either version 3 of
+// the License, or (at your option) any later version.
+//
+// Millenium Player is distributed in the hope that it will be useful, but WITHOUT ANY WARRANTY;
+// without even the implied warranty of MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE. See
+// the GNU General Public License for more details.
+//
+// You should have received a copy of the GNU General Public License along with Millenium Player.
+// If not, see <https://www.gnu.org/licenses/>.
+
+use crate::message::post_message;
+use millenium_post_office::frontend::message::FrontendMessage;
+use yew::prelude::*;
+
+#[derive(Properties, PartialEq)]
+pub struct DspBypassToggleProps {
+    pub bypassed: bool,
+}
+
+/// Switches between the processed and unprocessed audio for an A/B comparison.
+#[function_component(DspBypassToggle)]
+pub fn dsp_bypass_toggle(props: &DspBypassToggleProps) -> Html {
+    let bypassed = props.bypassed;
+    let toggle =
+        move |_| post_message(&FrontendMessage::MediaControlDspBypass { bypass: !bypassed });
+    let (class, label) = if bypassed {
+        ("dsp-bypass-toggle active", "B")
+    } else {
+        ("dsp-bypass-toggle", "A")
+    };
+    html! {
+        <button type="button"
+                class={class}
+                title="Compare with the unprocessed audio"
+                aria-label="Bypass audio processing"
+                aria-pressed={bypassed.to_string()}
+                onclick={toggle}>
+            {label}
+        </button>
+    }
+}
@@ -0,0 +1,80 @@
+// This file is part of Millenium Player.
+// Copyright (C) 2023 John DiSanti.
+//
+// Millenium Player is free software: you can redistribute it and/or modify it under the terms of
+// the GNU General Public License as published by the Free Software Foundation, either version 3 of
+// the License, or (at your option) any later version.
+//
+// Millenium Player is distributed in the hope that it will be useful, but WITHOUT ANY WARRANTY;
+// without even the implied warranty of MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE. See
+// the GNU General Public License for more details.
+//
+// You should have received a copy of the GNU General Public License along with Millenium Player.
+// If not, see <https://www.gnu.org/licenses/>.
+
+use crate::message::post_message;
+use millenium_post_office::{
+    frontend::message::FrontendMessage,
+    types::{Favorite, FavoriteKind},
+};
+use std::rc::Rc;
+use yew::prelude::*;
+
+#[derive(Properties, PartialEq)]
+pub struct FavoritesStripProps {
+    pub favorites: Rc<Vec<Favorite>>,
+}
+
+/// Quick access to the favorite stations, files, folders, and playlists.
+#[function_component(FavoritesStrip)]
+pub fn favorites_strip(props: &FavoritesStripProps) -> Html {
+    let favorites = props.favorites.iter().map(|favorite| {
+        let open = {
+            let location = favorite.location.clone();
+            move |_| {
+                post_message(&FrontendMessage::LoadLocations {
+                    locations: vec![location.clone()],
+                })
+            }
+        };
+        let remove = {
+            let location = favorite.location.clone();
+            move |_| {
+                post_message(&FrontendMessage::RemoveFavorite {
+                    location: location.clone(),
+                })
+            }
+        };
+        html! {
+            <li class="favorite" title={favorite.location.clone()}>
+                <button type="button" class="favorite-open" onclick={open}>
+                    <span class="favorite-kind">{kind_symbol(favorite.kind)}</span>
+                    {&favorite.name}
+                </button>
+                <button type="button" class="favorite-remove" title="Remove from favorites" onclick={remove}>
+                    {"×"}
+                </button>
+            </li>
+        }
+    });
+    let add = |_| post_message(&FrontendMessage::AddFavorite);
+    html! {
+        <ul class="favorites-strip">
+            {for favorites}
+            <li>
+                <button type="button" class="favorite-add" title="Add what's playing to favorites" onclick={add}>
+                    {"☆"}
+                </button>
+            </li>
+        </ul>
+    }
+}
+
+fn kind_symbol(kind: FavoriteKind) -> &'static str {
+    match kind {
+        FavoriteKind::Station => "📻",
+        FavoriteKind::File => "♪",
+        FavoriteKind::Folder => "📁",
+        FavoriteKind::Playlist => "☰",
+    }
+}
@@ -46,6 +46,7 @@ impl MediaControl {
             Self::PlaylistMode(mode) => match mode {
                 PlaylistMode::Normal => playlist_mode("normal"),
                 PlaylistMode::Shuffle => playlist_mode("shuffle"),
+                PlaylistMode::ShuffleAlbums => playlist_mode("shuffle albums"),
                 PlaylistMode::RepeatOne => playlist_mode("repeat one"),
                 PlaylistMode::RepeatAll => playlist_mode("repeat all"),
             },
@@ -64,6 +65,7 @@ impl MediaControl {
             Self::PlaylistMode(mode) => match mode {
                 PlaylistMode::Normal => "media-control-playlist-mode-normal",
                 PlaylistMode::Shuffle => "media-control-playlist-mode-shuffle",
+                PlaylistMode::ShuffleAlbums => "media-control-playlist-mode-shuffle-albums",
                 PlaylistMode::RepeatOne => "media-control-playlist-mode-repeat-one",
                 PlaylistMode::RepeatAll => "media-control-playlist-mode-repeat-all",
             },
@@ -84,6 +86,9 @@ impl MediaControl {
                     mode: PlaylistMode::Shuffle,
                 },
                 PlaylistMode::Shuffle => FrontendMessage::MediaControlPlaylistMode {
+                    mode: PlaylistMode::ShuffleAlbums,
+                },
+                PlaylistMode::ShuffleAlbums => FrontendMessage::MediaControlPlaylistMode {
                     mode: PlaylistMode::RepeatOne,
                 },
                 PlaylistMode::RepeatOne => FrontendMessage::MediaControlPlaylistMode {
@@ -110,7 +115,8 @@ pub fn media_control_button(props: &MediaControlButtonProps) -> Html {
     let click_message = props.kind.click_message();
     let onclick = move |_| post_message(&click_message);
     html! {
-        <button aria-label={aria_label}
+        <button type="button"
+                aria-label={aria_label}
                 class={class}
                 onclick={onclick}>
             <i></i>
@@ -138,13 +144,20 @@ pub fn media_control_button_pause_play(props: &MediaControlButtonPausePlayProps)
 #[derive(Properties, PartialEq)]
 pub struct MediaControlPlaylistModeProps {
     pub mode: PlaylistMode,
+    pub shuffle_seed: Option<u64>,
 }
 
 #[function_component(MediaControlPlaylistMode)]
 pub fn media_control_playlist_mode(props: &MediaControlPlaylistModeProps) -> Html {
     let kind = MediaControl::PlaylistMode(props.mode);
+    // Show the seed so that a shuffled order can be shared
+    let title = props
+        .shuffle_seed
+        .map(|seed| format!("Shuffle seed: {seed}"));
     html! {
-        <MediaControlButton kind={kind} />
+        <div title={title}>
+            <MediaControlButton kind={kind} />
+        </div>
     }
 }
 
@@ -152,6 +165,7 @@ pub fn media_control_playlist_mode(props: &MediaControlPlaylistModeProps) -> Htm
 pub struct MediaControlsProps {
     pub playing: bool,
     pub playlist_mode: PlaylistMode,
+    pub shuffle_seed: Option<u64>,
     pub volume: Volume,
 }
 
@@ -164,7 +178,7 @@ pub fn media_controls(props: &MediaControlsProps) -> Html {
             <div><MediaControlButtonPausePlay playing={props.playing} /></div>
             <div><MediaControlButton kind={MediaControl::Forward} /></div>
             <div><MediaControlButton kind={MediaControl::SkipForward} /></div>
-            <div><MediaControlPlaylistMode mode={props.playlist_mode} /></div>
+            <div><MediaControlPlaylistMode mode={props.playlist_mode} shuffle_seed={props.shuffle_seed} /></div>
             <div><VolumeSlider volume={props.volume} /></div>
             <div><MediaControlButton kind={MediaControl::Menu} /></div>
         </div>
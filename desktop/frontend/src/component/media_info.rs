@@ -27,11 +27,19 @@ pub fn media_info(props: &MediaInfoProps) -> Html {
         let artist = track.artist.as_deref().unwrap_or("Unknown artist");
         let title = track.title.as_deref().unwrap_or("Untitled");
         let album = track.album.as_deref().unwrap_or("Unknown album");
+        // The ID is part of the URL so that the image loads again when the art changes
+        let art = props.state.album_art.as_ref().map(|art| {
+            let src = format!("/ipc/album-art?id={}", art.id);
+            html! { <img class="media-info-art" src={src} alt={album.to_string()} /> }
+        });
         html! {
-            <>
-                <p>{artist}{" - "}{title}</p>
-                <p>{album}</p>
-            </>
+            <div class="media-info">
+                {art}
+                <div>
+                    <p>{artist}{" - "}{title}</p>
+                    <p>{album}</p>
+                </div>
+            </div>
         }
     } else {
         html!()
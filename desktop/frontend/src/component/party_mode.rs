@@ -0,0 +1,62 @@
+// This file is part of Millenium Player.
+// Copyright (C) 2023 John DiSanti.
+//
+// Millenium Player is free software: you can redistribute it and/or modify it under the terms of
+// the GNU General Public License as published by the Free Software Foundation, either version 3 of
+// the License, or (at your option) any later version.
+//
+// Millenium Player is distributed in the hope that it will be useful, but WITHOUT ANY WARRANTY;
+// without even the implied warranty of MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE. See
+// the GNU General Public License for more details.
+//
+// You should have received a copy of the GNU General Public License along with Millenium Player.
+// If not, see <https://www.gnu.org/licenses/>.
+
+use crate::message::post_message;
+use gloo::utils::window;
+use millenium_post_office::frontend::message::FrontendMessage;
+use yew::prelude::*;
+
+#[derive(Properties, PartialEq)]
+pub struct PartyModeToggleProps {
+    pub active: bool,
+}
+
+/// Starts or stops party mode, which lets guests queue tracks without editing the playlist.
+#[function_component(PartyModeToggle)]
+pub fn party_mode_toggle(props: &PartyModeToggleProps) -> Html {
+    let active = props.active;
+    let toggle = move |_| {
+        let message = if active {
+            let Ok(Some(passphrase)) = window().prompt_with_message(
+                "Enter the passphrase to stop party mode, or leave it blank if there isn't one.",
+            ) else {
+                return;
+            };
+            FrontendMessage::StopPartyMode {
+                passphrase: Some(passphrase).filter(|passphrase| !passphrase.is_empty()),
+            }
+        } else {
+            let Ok(Some(passphrase)) = window().prompt_with_message(
+                "Party mode only allows queueing tracks to play next. \
+                 Choose a passphrase for stopping it, or leave it blank.",
+            ) else {
+                return;
+            };
+            FrontendMessage::StartPartyMode {
+                passphrase: Some(passphrase).filter(|passphrase| !passphrase.is_empty()),
+            }
+        };
+        post_message(&message);
+    };
+    let (class, title) = if active {
+        ("party-mode-toggle active", "Stop party mode")
+    } else {
+        ("party-mode-toggle", "Start party mode")
+    };
+    html! {
+        <button type="button" class={class} title={title} aria-pressed={active.to_string()} onclick={toggle}>
+            {"🎉"}
+        </button>
+    }
+}
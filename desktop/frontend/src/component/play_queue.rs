@@ -0,0 +1,72 @@
+// This file is part of Millenium Player.
+// Copyright (C) 2023 John DiSanti.
+//
+// Millenium Player is free software: you can redistribute it and/or modify it under the terms of
+// the GNU General Public License as published by the Free Software Foundation, either version 3 of
+// the License, or (at your option) any later version.
+//
+// Millenium Player is distributed in the hope that it will be useful, but WITHOUT ANY WARRANTY;
+// without even the implied warranty of MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE. See
+// the GNU General Public License for more details.
+//
+// You should have received a copy of the GNU General Public License along with Millenium Player.
+// If not, see <https://www.gnu.org/licenses/>.
+
+use crate::{locale::format_count, message::post_message};
+use millenium_post_office::{frontend::message::FrontendMessage, types::QueuedTrack};
+use yew::prelude::*;
+
+#[derive(Properties, PartialEq)]
+pub struct PlayQueueProps {
+    /// Tracks in the order they'll be played.
+    pub queue: Vec<QueuedTrack>,
+    /// Guests can't clear the queue during party mode.
+    pub party_mode: bool,
+}
+
+/// Shows the track that will play next from the play queue, and how many follow it.
+#[function_component(PlayQueue)]
+pub fn play_queue(props: &PlayQueueProps) -> Html {
+    let Some(next) = props.queue.first() else {
+        return html!();
+    };
+    let more = match props.queue.len() - 1 {
+        0 => None,
+        count => {
+            Some(html!(<span class="play-queue-more">{format!("+{}", format_count(count))}</span>))
+        }
+    };
+    let clear = (!props.party_mode).then(|| {
+        let clear = |_| post_message(&FrontendMessage::ClearPlayQueue);
+        html! {
+            <button type="button" class="play-queue-clear" title="Clear the play queue" onclick={clear}>
+                {"×"}
+            </button>
+        }
+    });
+    html! {
+        <div class="play-queue" title={next.location.clone()}>
+            <span class="play-queue-label">{"Up next"}</span>
+            <span class="play-queue-title">{display_name(next)}</span>
+            {more}
+            {clear}
+        </div>
+    }
+}
+
+fn display_name(track: &QueuedTrack) -> String {
+    match (&track.artist, &track.title) {
+        (Some(artist), Some(title)) => format!("{artist} - {title}"),
+        (None, Some(title)) => title.clone(),
+        _ => file_name(&track.location).to_string(),
+    }
+}
+
+/// The file name of a location, or the last part of its URL, to show when it has no title.
+pub fn file_name(location: &str) -> &str {
+    location
+        .trim_end_matches('/')
+        .rsplit(['/', '\\'])
+        .next()
+        .unwrap_or(location)
+}
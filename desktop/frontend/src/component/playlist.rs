@@ -0,0 +1,295 @@
+// This file is part of Millenium Player.
+// Copyright (C) 2023 John DiSanti.
+//
+// Millenium Player is free software: you can redistribute it and/or modify it under the terms of
+// the GNU General Public License as published by the Free Software Foundation, either version 3 of
+// the License, or (at your option) any later version.
+//
+// Millenium Player is distributed in the hope that it will be useful, but WITHOUT ANY WARRANTY;
+// without even the implied warranty of MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE. See
+// the GNU General Public License for more details.
+//
+// You should have received a copy of the GNU General Public License along with Millenium Player.
+// If not, see <https://www.gnu.org/licenses/>.
+
+use crate::{
+    component::{duration::Duration, play_queue::file_name},
+    locale::{format_count, format_length},
+    message::{post_message, post_messages},
+};
+use gloo::{
+    events::{EventListener, EventListenerOptions},
+    utils::document,
+};
+use millenium_post_office::{
+    frontend::{message::FrontendMessage, state::PlaylistStateData},
+    types::PlaylistEntryDetails,
+};
+use std::{collections::HashSet, rc::Rc};
+use wasm_bindgen::JsCast;
+use web_sys::{DataTransfer, Element, Event, ScrollIntoViewOptions, ScrollLogicalPosition};
+use yew::prelude::*;
+
+#[derive(Properties, PartialEq)]
+pub struct PlaylistProps {
+    pub playlist: Rc<PlaylistStateData>,
+    /// Guests can't pick what plays during party mode.
+    pub party_mode: bool,
+}
+
+/// Lists the playlist entries with the current one highlighted. Clicking an entry plays it.
+///
+/// Ctrl-clicking selects entries, and copying puts the selected entries' paths and URLs on
+/// the clipboard. Entries can be dragged to reorder them, and removed with the Delete key or
+/// their remove button. The list scrolls to keep the current entry in view whenever it changes,
+/// and is followed by how many entries there are and how long they play for. Each entry's
+/// preview button plays its start quietly over whatever is playing. Playing a skipped entry
+/// stops skipping it.
+///
+/// Measured entries show their dynamic range, and the summary shows the average dynamic range
+/// of the current entry's album, so that heavily compressed masters stand out.
+#[function_component(Playlist)]
+pub fn playlist(props: &PlaylistProps) -> Html {
+    let current_ref = use_node_ref();
+    // Index of the entry being dragged
+    let dragging = use_state(|| None::<usize>);
+    // IDs of the selected entries
+    let selected = use_state(HashSet::<usize>::new);
+    {
+        let locations: Vec<String> = props
+            .playlist
+            .entries
+            .iter()
+            .filter(|entry| selected.contains(&entry.id))
+            .map(|entry| entry.location.clone())
+            .collect();
+        use_effect_with(locations, |locations| {
+            let locations = locations.join("\n");
+            // Copying text elsewhere in the window works as usual while nothing is selected
+            let listener = (!locations.is_empty()).then(|| {
+                EventListener::new_with_options(
+                    &document(),
+                    "copy",
+                    EventListenerOptions::enable_prevent_default(),
+                    move |event| {
+                        if let Some(data) = clipboard_data(event) {
+                            if data.set_data("text/plain", &locations).is_ok() {
+                                event.prevent_default();
+                            }
+                        }
+                    },
+                )
+            });
+            move || drop(listener)
+        });
+    }
+    {
+        let current_ref = current_ref.clone();
+        use_effect_with(props.playlist.current_index, move |_| {
+            if let Some(current) = current_ref.cast::<Element>() {
+                let mut options = ScrollIntoViewOptions::new();
+                options.block(ScrollLogicalPosition::Nearest);
+                current.scroll_into_view_with_scroll_into_view_options(&options);
+            }
+        });
+    }
+
+    if props.playlist.entries.is_empty() {
+        return html! {
+            <div class="playlist playlist-empty">{"The playlist is empty"}</div>
+        };
+    }
+    let editable = !props.party_mode;
+    let entries = props
+        .playlist
+        .entries
+        .iter()
+        .enumerate()
+        .map(|(index, entry)| {
+            let current = props.playlist.current_index == Some(index);
+            let (id, skipped) = (entry.id, entry.skipped);
+            let is_selected = selected.contains(&id);
+            // Guests can still select entries to copy them
+            let onclick = {
+                let selected = selected.clone();
+                move |event: MouseEvent| {
+                    if event.ctrl_key() || event.meta_key() || !editable {
+                        let mut changed = (*selected).clone();
+                        if !changed.remove(&id) {
+                            changed.insert(id);
+                        }
+                        selected.set(changed);
+                    } else if skipped {
+                        post_messages(&[
+                            FrontendMessage::SetPlaylistEntrySkipped { id, skipped: false },
+                            FrontendMessage::PlayPlaylistEntry { id },
+                        ]);
+                    } else {
+                        post_message(&FrontendMessage::PlayPlaylistEntry { id });
+                    }
+                }
+            };
+            // Removing a selected entry removes the whole selection
+            let remove = {
+                let selected = selected.clone();
+                move || {
+                    let ids = if selected.contains(&id) {
+                        selected.iter().copied().collect()
+                    } else {
+                        vec![id]
+                    };
+                    selected.set(HashSet::new());
+                    post_message(&FrontendMessage::RemovePlaylistEntries { ids });
+                }
+            };
+            let onkeydown = {
+                let remove = remove.clone();
+                move |event: KeyboardEvent| {
+                    if event.key() == "Delete" {
+                        event.prevent_default();
+                        remove();
+                    }
+                }
+            };
+            let ondragstart = {
+                let dragging = dragging.clone();
+                move |_| dragging.set(Some(index))
+            };
+            // Dropping is only allowed where the default is prevented
+            let ondragover = |event: DragEvent| event.prevent_default();
+            let ondrop = {
+                let dragging = dragging.clone();
+                move |event: DragEvent| {
+                    event.prevent_default();
+                    if let Some(from) = *dragging {
+                        post_message(&FrontendMessage::MovePlaylistEntry { from, to: index });
+                    }
+                    dragging.set(None);
+                }
+            };
+            let ondragend = {
+                let dragging = dragging.clone();
+                move |_| dragging.set(None)
+            };
+            // Stops when the pointer leaves so that previews don't pile up while browsing
+            let preview_button = html! {
+                <button type="button"
+                        class="playlist-preview"
+                        title="Preview the first few seconds"
+                        aria-label="Preview"
+                        onclick={move |_| post_message(&FrontendMessage::PreviewPlaylistEntry { id })}
+                        onmouseleave={|_| post_message(&FrontendMessage::StopPreview)}>
+                    {"♪"}
+                </button>
+            };
+            let remove_button = editable.then(|| {
+                html! {
+                    <button type="button"
+                            class="playlist-remove"
+                            title="Remove from the playlist"
+                            onclick={move |_| remove()}>
+                        {"×"}
+                    </button>
+                }
+            });
+            let duration = entry
+                .duration
+                .map(|duration| html!(<Duration duration={duration} />));
+            let dynamic_range = entry.dynamic_range.map(|dynamic_range| {
+                let album = entry.album.as_ref().and_then(|album| {
+                    let average = props.playlist.album_dynamic_range(album)?;
+                    Some(format!("DR{average} across the measured tracks of {album}"))
+                });
+                html! {
+                    <span class="playlist-dynamic-range"
+                          title={album.unwrap_or_else(|| "Dynamic range".into())}>
+                        {format!("DR{dynamic_range}")}
+                    </span>
+                }
+            });
+            html! {
+                <li key={entry.id}
+                    ref={current.then(|| current_ref.clone()).unwrap_or_default()}
+                    class={classes!(
+                        "playlist-entry",
+                        current.then_some("playlist-current"),
+                        entry.skipped.then_some("playlist-skipped"),
+                        is_selected.then_some("playlist-selected"),
+                        (*dragging == Some(index)).then_some("playlist-dragging"),
+                    )}
+                    draggable={editable.to_string()}
+                    ondragstart={editable.then_some(ondragstart)}
+                    ondragover={editable.then_some(ondragover)}
+                    ondrop={editable.then_some(ondrop)}
+                    ondragend={ondragend}>
+                    <button type="button"
+                            class="playlist-play"
+                            title={entry.location.clone()}
+                            onclick={onclick}
+                            onkeydown={editable.then_some(onkeydown)}>
+                        <span class="playlist-name">{display_name(entry)}</span>
+                        {dynamic_range}
+                        <span class="playlist-duration">{duration}</span>
+                    </button>
+                    {preview_button}
+                    {remove_button}
+                </li>
+            }
+        });
+    let summary = {
+        let playlist = &props.playlist;
+        let tracks = match playlist.entries.len() {
+            1 => "1 track".to_string(),
+            count => format!("{} tracks", format_count(count)),
+        };
+        let mut summary = with_length(tracks, playlist.total_duration);
+        if !selected.is_empty() {
+            let length = playlist.duration_of(|entry| selected.contains(&entry.id));
+            let selection = format!("{} selected", format_count(selected.len()));
+            summary.push_str(&format!(" ({})", with_length(selection, length)));
+        }
+        let album = playlist
+            .current_index
+            .and_then(|index| playlist.entries.get(index)?.album.as_ref());
+        if let Some(album) = album {
+            if let Some(average) = playlist.album_dynamic_range(album) {
+                summary.push_str(&format!(" · {album}: DR{average}"));
+            }
+        }
+        summary
+    };
+    html! {
+        <>
+            <ol class="playlist">{for entries}</ol>
+            <div class="playlist-summary">{summary}</div>
+        </>
+    }
+}
+
+/// Adds how long some entries play for, such as "23 tracks · 1 h 42 m". The length is left
+/// off until all of the entries' durations have loaded.
+fn with_length(count: String, length: Option<std::time::Duration>) -> String {
+    match length {
+        Some(length) => format!("{count} · {}", format_length(length)),
+        None => count,
+    }
+}
+
+/// Returns the data of a copy or paste event.
+///
+/// `ClipboardEvent` is still an unstable API in web-sys, so its `clipboardData` is read
+/// by name instead.
+pub fn clipboard_data(event: &Event) -> Option<DataTransfer> {
+    js_sys::Reflect::get(event, &"clipboardData".into())
+        .ok()?
+        .dyn_into::<DataTransfer>()
+        .ok()
+}
+
+fn display_name(entry: &PlaylistEntryDetails) -> String {
+    match (&entry.artist, &entry.title) {
+        (Some(artist), Some(title)) => format!("{artist} - {title}"),
+        (None, Some(title)) => title.clone(),
+        _ => file_name(&entry.location).to_string(),
+    }
+}
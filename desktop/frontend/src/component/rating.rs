@@ -0,0 +1,61 @@
+// This file is part of Millenium Player.
+// Copyright (C) 2023 John DiSanti.
+//
+// Millenium Player is free software: you can redistribute it and/or modify it under the terms of
+// the GNU General Public License as published by the Free Software Foundation, either version 3 of
+// the License, or (at your option) any later version.
+//
+// Millenium Player is distributed in the hope that it will be useful, but WITHOUT ANY WARRANTY;
+// without even the implied warranty of MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE. See
+// the GNU General Public License for more details.
+//
+// You should have received a copy of the GNU General Public License along with Millenium Player.
+// If not, see <https://www.gnu.org/licenses/>.
+
+use crate::message::post_message;
+use millenium_post_office::{frontend::message::FrontendMessage, types::TrackRating};
+use yew::prelude::*;
+
+#[derive(Properties, PartialEq)]
+pub struct RatingButtonsProps {
+    pub rating: Option<TrackRating>,
+}
+
+/// Loves or bans the current track. Clicking the active rating again clears it.
+#[function_component(RatingButtons)]
+pub fn rating_buttons(props: &RatingButtonsProps) -> Html {
+    let current = props.rating;
+    let rate = move |rating: TrackRating| {
+        move |_| {
+            let rating = (current != Some(rating)).then_some(rating);
+            post_message(&FrontendMessage::RateCurrentTrack { rating });
+        }
+    };
+    let class = |rating: TrackRating| {
+        if current == Some(rating) {
+            "rating-button active"
+        } else {
+            "rating-button"
+        }
+    };
+    html! {
+        <div class="rating">
+            <button type="button"
+                    class={class(TrackRating::Loved)}
+                    title="Love this track"
+                    aria-label="Love"
+                    aria-pressed={(current == Some(TrackRating::Loved)).to_string()}
+                    onclick={rate(TrackRating::Loved)}>
+                {"♥"}
+            </button>
+            <button type="button"
+                    class={class(TrackRating::Banned)}
+                    title="Ban this track so that it isn't shuffled"
+                    aria-label="Ban"
+                    aria-pressed={(current == Some(TrackRating::Banned)).to_string()}
+                    onclick={rate(TrackRating::Banned)}>
+                {"⊘"}
+            </button>
+        </div>
+    }
+}
@@ -12,20 +12,66 @@
 // You should have received a copy of the GNU General Public License along with Millenium Player.
 // If not, see <https://www.gnu.org/licenses/>.
 
-use crate::component::{
-    media_controls::MediaControls, media_info::MediaInfo, time_slider::TimeSlider,
-    title_bar::TitleBar, waveform::Waveform,
+use crate::{
+    component::{
+        ab_loop::AbLoopButtons,
+        dsp_bypass::DspBypassToggle,
+        favorites::FavoritesStrip,
+        media_controls::MediaControls,
+        media_info::MediaInfo,
+        party_mode::PartyModeToggle,
+        play_queue::PlayQueue,
+        playlist::{clipboard_data, Playlist},
+        rating::RatingButtons,
+        seek_buttons::SeekButtonsControl,
+        settings::{OutputDevices, SettingsPanel},
+        shortcuts::{ShortcutsOverlay, CHEATSHEET_KEY},
+        snackbar::{
+            DuplicatesSnackbar, IntroSkippedSnackbar, PlayerStoppedSnackbar, StreamHealthSnackbar,
+        },
+        song_history::SongHistory,
+        stream_quality::StreamQualitySelect,
+        time_slider::TimeSlider,
+        title_bar::TitleBar,
+        waveform::Waveform,
+    },
+    error, locale,
+    message::{post_message, save_ui_prefs},
+};
+use gloo::{
+    events::EventListener,
+    utils::{document, window},
+};
+use millenium_post_office::{
+    frontend::{
+        message::FrontendMessage,
+        settings::{KeyAction, Settings, Theme, Visualizer},
+        state::{PlaybackStateData, PlaylistStateData, TimeDisplay, UiPrefs, WaveformStateData},
+    },
+    types::{Favorite, InstanceHealth, PlaylistEntryDetails},
 };
-use millenium_post_office::frontend::state::{PlaybackStateData, WaveformStateData};
 use once_cell::sync::Lazy;
 use std::{cell::RefCell, rc::Rc};
-use yew::prelude::*;
+use wasm_bindgen::JsCast;
+use web_sys::{Element, HtmlButtonElement, HtmlInputElement, HtmlSelectElement, KeyboardEvent};
+use yew::{platform::spawn_local, prelude::*};
 
 static EMPTY_PLAYBACK_STATE: Lazy<PlaybackStateData> = Lazy::new(PlaybackStateData::default);
 
 pub enum RootMessage {
     UpdatePlaybackState(Rc<PlaybackStateData>),
     UpdateWaveformState(WaveformStateData),
+    UpdateSettings(Rc<Settings>),
+    UpdateFavorites(Rc<Vec<Favorite>>),
+    UpdateOutputDevices(Rc<OutputDevices>),
+    UpdateHealth(Rc<InstanceHealth>),
+    UpdatePlaylist(Rc<PlaylistStateData>),
+    UpdatePlaylistEntries(Vec<PlaylistEntryDetails>),
+    UpdateUiPrefs(UiPrefs),
+    ShowHidePlaylist,
+    ToggleTimeDisplay,
+    KeyPressed(String),
+    ShowShortcuts(bool),
 }
 
 #[derive(Default, Properties, PartialEq)]
@@ -35,19 +81,134 @@ pub struct RootProps {}
 pub struct Root {
     playback_state: Option<Rc<PlaybackStateData>>,
     waveform_state: Option<Rc<RefCell<WaveformStateData>>>,
+    settings: Rc<Settings>,
+    favorites: Rc<Vec<Favorite>>,
+    output_devices: Option<Rc<OutputDevices>>,
+    health: Option<Rc<InstanceHealth>>,
+    playlist: Rc<PlaylistStateData>,
+    ui_prefs: UiPrefs,
+    shortcuts_visible: bool,
+    /// True when this is the popped out visualizer window rather than the main window.
+    visualizer_window: bool,
+    _keydown_listener: Option<EventListener>,
+    _paste_listener: Option<EventListener>,
+}
+
+impl Root {
+    fn save_ui_prefs(&self) {
+        spawn_local(save_ui_prefs(self.ui_prefs.clone()));
+    }
+
+    fn key_action_message(&self, action: KeyAction) -> FrontendMessage {
+        let state = self
+            .playback_state
+            .as_deref()
+            .unwrap_or(&EMPTY_PLAYBACK_STATE);
+        let playing = state.playback_status.playing;
+        match action {
+            KeyAction::PlayPause if playing => FrontendMessage::MediaControlPause,
+            KeyAction::PlayPause => FrontendMessage::MediaControlPlay,
+            KeyAction::Stop => FrontendMessage::MediaControlStop,
+            KeyAction::Back => FrontendMessage::MediaControlBack,
+            KeyAction::Forward => FrontendMessage::MediaControlForward,
+            KeyAction::SkipBack => FrontendMessage::MediaControlSkipBack,
+            KeyAction::SkipForward => FrontendMessage::MediaControlSkipForward,
+            KeyAction::PreviousChapter => FrontendMessage::MediaControlPreviousChapter,
+            KeyAction::NextChapter => FrontendMessage::MediaControlNextChapter,
+            KeyAction::ToggleDspBypass => FrontendMessage::MediaControlDspBypass {
+                bypass: !state.dsp_bypassed,
+            },
+        }
+    }
+}
+
+/// Whether the page was opened as the popped out visualizer window.
+fn is_visualizer_window() -> bool {
+    window()
+        .location()
+        .search()
+        .map(|search| search.contains("window=visualizer"))
+        .unwrap_or_default()
+}
+
+/// Swaps the theme class on the root element, which lives outside of Yew's control.
+fn apply_theme(theme: Theme) {
+    let Some(root) = document().get_element_by_id("root-content") else {
+        return;
+    };
+    let classes = root.class_list();
+    let stale: Vec<String> = (0..classes.length())
+        .filter_map(|index| classes.item(index))
+        .filter(|class| class.starts_with("theme-") && class != theme.css_class())
+        .collect();
+    for class in stale {
+        let _ = classes.remove_1(&class);
+    }
+    if let Err(err) = classes.add_1(theme.css_class()) {
+        error!("failed to apply theme: {err:?}");
+    }
 }
 
 impl Component for Root {
     type Message = RootMessage;
     type Properties = RootProps;
 
-    fn create(_ctx: &Context<Self>) -> Self {
-        Default::default()
+    fn create(ctx: &Context<Self>) -> Self {
+        let on_key = ctx.link().callback(RootMessage::KeyPressed);
+        let keydown_listener = EventListener::new(&document(), "keydown", move |event| {
+            let event = event
+                .dyn_ref::<KeyboardEvent>()
+                .expect("keydown is a KeyboardEvent");
+            // Leave keys alone while typing, adjusting the sliders, or picking from a list
+            let Some(target) = event.target() else {
+                return;
+            };
+            let in_input =
+                target.has_type::<HtmlInputElement>() || target.has_type::<HtmlSelectElement>();
+            // Enter and Space belong to whichever button has focus
+            let on_button = target.has_type::<HtmlButtonElement>()
+                || target
+                    .dyn_ref::<Element>()
+                    .map(|element| element.tag_name() == "SUMMARY")
+                    .unwrap_or_default();
+            let activates_button = on_button && matches!(event.key().as_str(), "Enter" | " ");
+            if !(in_input || activates_button || event.repeat()) {
+                on_key.emit(event.key());
+            }
+        });
+        // Pasting paths or URLs anywhere outside of an input queues them
+        let paste_listener = EventListener::new(&document(), "paste", |event| {
+            let in_input = event
+                .target()
+                .map(|target| target.has_type::<HtmlInputElement>())
+                .unwrap_or_default();
+            let text = clipboard_data(event)
+                .and_then(|data| data.get_data("text/plain").ok())
+                .unwrap_or_default();
+            if !in_input && !text.trim().is_empty() {
+                post_message(&FrontendMessage::PasteLocations { text });
+            }
+        });
+        Self {
+            visualizer_window: is_visualizer_window(),
+            _keydown_listener: Some(keydown_listener),
+            _paste_listener: Some(paste_listener),
+            ..Default::default()
+        }
     }
 
     fn update(&mut self, _ctx: &Context<Self>, msg: Self::Message) -> bool {
         match msg {
             RootMessage::UpdatePlaybackState(state) => {
+                // Remember whether the visualizer was popped out when its window opens or closes
+                let was_detached = self.playback_state.as_ref().map(|s| s.visualizer_detached);
+                if !self.visualizer_window
+                    && was_detached.is_some()
+                    && was_detached != Some(state.visualizer_detached)
+                {
+                    self.ui_prefs.visualizer_popped_out = state.visualizer_detached;
+                    self.save_ui_prefs();
+                }
                 self.playback_state = Some(state);
                 true
             }
@@ -55,15 +216,93 @@ impl Component for Root {
                 if let Some(waveform_state) = self.waveform_state.as_mut() {
                     *waveform_state.borrow_mut() = state;
                     false
+                } else if state.waveform.is_none() {
+                    // Nothing has played yet, so keep showing the placeholder
+                    false
                 } else {
                     self.waveform_state = Some(Rc::new(RefCell::new(state)));
                     true
                 }
             }
+            RootMessage::UpdateSettings(settings) => {
+                apply_theme(settings.theme);
+                locale::set_locale(settings.locale.clone());
+                self.settings = settings;
+                true
+            }
+            RootMessage::UpdateFavorites(favorites) => {
+                self.favorites = favorites;
+                true
+            }
+            RootMessage::UpdateOutputDevices(output_devices) => {
+                self.output_devices = Some(output_devices);
+                true
+            }
+            RootMessage::UpdateHealth(health) => {
+                self.health = Some(health);
+                true
+            }
+            RootMessage::UpdatePlaylist(playlist) => {
+                self.playlist = playlist;
+                self.ui_prefs.playlist_visible
+            }
+            RootMessage::UpdatePlaylistEntries(entries) => {
+                Rc::make_mut(&mut self.playlist).update_entries(&entries);
+                self.ui_prefs.playlist_visible
+            }
+            RootMessage::UpdateUiPrefs(prefs) => {
+                // The popped out visualizer only shows the waveform, so it has no layout
+                if self.visualizer_window {
+                    return false;
+                }
+                let detached = self
+                    .playback_state
+                    .as_ref()
+                    .map(|state| state.visualizer_detached)
+                    .unwrap_or_default();
+                if prefs.visualizer_popped_out && !detached {
+                    post_message(&FrontendMessage::OpenVisualizerWindow);
+                }
+                self.ui_prefs = prefs;
+                true
+            }
+            RootMessage::ShowHidePlaylist => {
+                self.ui_prefs.playlist_visible = !self.ui_prefs.playlist_visible;
+                self.save_ui_prefs();
+                true
+            }
+            RootMessage::ToggleTimeDisplay => {
+                self.ui_prefs.time_display = match self.ui_prefs.time_display {
+                    TimeDisplay::Length => TimeDisplay::Remaining,
+                    TimeDisplay::Remaining => TimeDisplay::Length,
+                };
+                self.save_ui_prefs();
+                true
+            }
+            RootMessage::KeyPressed(key) => {
+                if let Some(action) = self.settings.keybindings.get(&key) {
+                    if !self.shortcuts_visible {
+                        post_message(&self.key_action_message(*action));
+                    }
+                    false
+                } else if key == CHEATSHEET_KEY {
+                    self.shortcuts_visible = !self.shortcuts_visible;
+                    true
+                } else if key == "Escape" && self.shortcuts_visible {
+                    self.shortcuts_visible = false;
+                    true
+                } else {
+                    false
+                }
+            }
+            RootMessage::ShowShortcuts(visible) => {
+                self.shortcuts_visible = visible;
+                true
+            }
         }
     }
 
-    fn view(&self, _ctx: &Context<Self>) -> Html {
+    fn view(&self, ctx: &Context<Self>) -> Html {
         let state = self
             .playback_state
             .as_deref()
@@ -73,12 +312,62 @@ impl Component for Root {
         let waveform = self
             .waveform_state
             .as_ref()
+            .filter(|_| self.settings.visualizer != Visualizer::Off)
+            .filter(|_| self.visualizer_window || !state.visualizer_detached)
             .map(|w| html!(<Waveform waveform={w} />))
             .unwrap_or_else(|| html!(<div class="waveform-placeholder" />));
+        if self.visualizer_window {
+            return html!(<div class="visualizer-window">{waveform}</div>);
+        }
         let media_info = self
             .playback_state
             .as_ref()
             .map(|s| html!(<MediaInfo state={s} />));
+        let intro_skipped = state
+            .intro_skipped
+            .map(|position| html!(<IntroSkippedSnackbar position={position} />));
+        let stream_quality = state
+            .stream_quality
+            .clone()
+            .map(|quality| html!(<StreamQualitySelect quality={quality} />));
+        let playlist = self.ui_prefs.playlist_visible.then(
+            || html!(<Playlist playlist={self.playlist.clone()} party_mode={state.party_mode} />),
+        );
+        let seek_buttons = state
+            .playback_status
+            .end_position
+            .filter(|&length| self.settings.seek_buttons.is_long_form(length))
+            .map(|_| html!(<SeekButtonsControl seek_buttons={self.settings.seek_buttons} />));
+        let rating = state
+            .current_track
+            .as_ref()
+            .map(|_| html!(<RatingButtons rating={state.rating} />));
+        let song_history = (!state.song_history.is_empty())
+            .then(|| html!(<SongHistory songs={state.song_history.clone()} />));
+        // Guests at a party shouldn't be changing the settings
+        let settings = (!state.party_mode).then(|| {
+            html! {
+                <SettingsPanel settings={self.settings.clone()}
+                               output_devices={self.output_devices.clone()}
+                               on_show_shortcuts={ctx.link().callback(|_| RootMessage::ShowShortcuts(true))} />
+            }
+        });
+        let shortcuts = self.shortcuts_visible.then(|| {
+            html! {
+                <ShortcutsOverlay keybindings={self.settings.keybindings.clone()}
+                                  on_close={ctx.link().callback(|_| RootMessage::ShowShortcuts(false))} />
+            }
+        });
+        let duplicates = (!state.skipped_duplicates.is_empty())
+            .then(|| html!(<DuplicatesSnackbar duplicates={state.skipped_duplicates.clone()} />));
+        let stream_health = state
+            .stream_health
+            .map(|health| html!(<StreamHealthSnackbar health={health} />));
+        let player_stopped = self
+            .health
+            .as_ref()
+            .filter(|health| !health.player_alive)
+            .map(|health| html!(<PlayerStoppedSnackbar error={health.last_error.clone()} />));
 
         html! {
             <>
@@ -88,11 +377,32 @@ impl Component for Root {
                     <div style="padding:10px;">
                         {media_info}
                         <TimeSlider current_position={state.playback_status.current_position}
-                                    end_position={state.playback_status.end_position} />
+                                    end_position={state.playback_status.end_position}
+                                    live={state.playback_status.live}
+                                    ab_loop={state.ab_loop}
+                                    time_display={self.ui_prefs.time_display}
+                                    on_toggle_time_display={ctx.link().callback(|_| RootMessage::ToggleTimeDisplay)} />
                         <MediaControls playing={playing}
                                        playlist_mode={state.playlist_mode}
+                                       shuffle_seed={state.shuffle_seed}
                                        volume={state.playback_status.volume} />
+                        {seek_buttons}
+                        <DspBypassToggle bypassed={state.dsp_bypassed} />
+                        <AbLoopButtons ab_loop={state.ab_loop} />
+                        {rating}
+                        {stream_quality}
+                        <PlayQueue queue={state.play_queue.clone()} party_mode={state.party_mode} />
+                        {playlist}
+                        <FavoritesStrip favorites={self.favorites.clone()} />
+                        <PartyModeToggle active={state.party_mode} />
+                        {song_history}
+                        {settings}
                     </div>
+                    {intro_skipped}
+                    {duplicates}
+                    {stream_health}
+                    {player_stopped}
+                    {shortcuts}
                 </div>
             </>
         }
@@ -0,0 +1,50 @@
+// This file is part of Millenium Player.
+// Copyright (C) 2023 John DiSanti.
+//
+// Millenium Player is free software: you can redistribute it and/or modify it under the terms of
+// the GNU General Public License as published by the Free Software Foundation, either version 3 of
+// the License, or (at your option) any later version.
+//
+// Millenium Player is distributed in the hope that it will be useful, but WITHOUT ANY WARRANTY;
+// without even the implied warranty of MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE. See
+// the GNU General Public License for more details.
+//
+// You should have received a copy of the GNU General Public License along with Millenium Player.
+// If not, see <https://www.gnu.org/licenses/>.
+
+use crate::message::post_message;
+use millenium_post_office::frontend::{message::FrontendMessage, settings::SeekButtons};
+use yew::prelude::*;
+
+#[derive(Properties, PartialEq)]
+pub struct SeekButtonsProps {
+    pub seek_buttons: SeekButtons,
+}
+
+/// Seeks back and forward by the configured number of seconds, for long-form content.
+#[function_component(SeekButtonsControl)]
+pub fn seek_buttons_control(props: &SeekButtonsProps) -> Html {
+    let SeekButtons {
+        back_seconds,
+        forward_seconds,
+        ..
+    } = props.seek_buttons;
+    let seek_by =
+        |seconds: i32| move |_| post_message(&FrontendMessage::MediaControlSeekBy { seconds });
+    html! {
+        <div class="seek-buttons">
+            <button type="button"
+                    class="seek-button"
+                    aria-label={format!("Back {back_seconds} seconds")}
+                    onclick={seek_by(-(back_seconds as i32))}>
+                {format!("−{back_seconds}s")}
+            </button>
+            <button type="button"
+                    class="seek-button"
+                    aria-label={format!("Forward {forward_seconds} seconds")}
+                    onclick={seek_by(forward_seconds as i32)}>
+                {format!("+{forward_seconds}s")}
+            </button>
+        </div>
+    }
+}
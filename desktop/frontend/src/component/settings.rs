@@ -0,0 +1,257 @@
+// This file is part of Millenium Player.
+// Copyright (C) 2023 John DiSanti.
+//
+// Millenium Player is free software: you can redistribute it and/or modify it under the terms of
+// the GNU General Public License as published by the Free Software Foundation, either version 3 of
+// the License, or (at your option) any later version.
+//
+// Millenium Player is distributed in the hope that it will be useful, but WITHOUT ANY WARRANTY;
+// without even the implied warranty of MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE. See
+// the GNU General Public License for more details.
+//
+// You should have received a copy of the GNU General Public License along with Millenium Player.
+// If not, see <https://www.gnu.org/licenses/>.
+
+use crate::message::post_message;
+use millenium_post_office::{
+    frontend::{
+        message::FrontendMessage,
+        settings::{Settings, Theme, Visualizer, MAX_UI_SCALE, MIN_UI_SCALE},
+    },
+    types::NormalizationMode,
+};
+use std::rc::Rc;
+use wasm_bindgen::JsCast;
+use web_sys::{HtmlDetailsElement, HtmlInputElement, HtmlSelectElement};
+use yew::prelude::*;
+
+/// Longest crossfade that can be picked, since longer ones run whole tracks together.
+const MAX_CROSSFADE_SECONDS: f32 = 12.0;
+/// Longest gap between tracks that can be picked.
+const MAX_GAP_SECONDS: f32 = 12.0;
+
+const THEMES: &[(Theme, &str)] = &[
+    (Theme::Default, "Default"),
+    (Theme::HighContrast, "High contrast"),
+];
+const VISUALIZERS: &[(Visualizer, &str)] =
+    &[(Visualizer::Waveform, "Waveform"), (Visualizer::Off, "Off")];
+const NORMALIZATION_MODES: &[(NormalizationMode, &str)] = &[
+    (NormalizationMode::Off, "Off"),
+    (NormalizationMode::Track, "Track"),
+    (NormalizationMode::Album, "Album"),
+    (NormalizationMode::PlaylistPeak, "Playlist peak"),
+];
+
+/// Audio output devices, and the one chosen in the config file.
+#[derive(Debug, PartialEq)]
+pub struct OutputDevices {
+    pub devices: Vec<String>,
+    /// `None` means the system default.
+    pub selected: Option<String>,
+}
+
+#[derive(Properties, PartialEq)]
+pub struct SettingsPanelProps {
+    pub settings: Rc<Settings>,
+    /// Only set once the devices have been listed, which happens when the panel is opened.
+    pub output_devices: Option<Rc<OutputDevices>>,
+    pub on_show_shortcuts: Callback<()>,
+}
+
+/// Changes the settings, which the backend saves to the config file.
+#[function_component(SettingsPanel)]
+pub fn settings_panel(props: &SettingsPanelProps) -> Html {
+    let settings = &props.settings;
+    let ontoggle = |event: Event| {
+        let opened = event
+            .target()
+            .and_then(|target| target.dyn_into::<HtmlDetailsElement>().ok())
+            .map(|details| details.open())
+            .unwrap_or_default();
+        // Devices come and go, so list them every time the panel is opened
+        if opened {
+            post_message(&FrontendMessage::ListOutputDevices);
+        }
+    };
+    let crossfade_changed = seconds_changed(settings, |settings, seconds| {
+        settings.crossfade_seconds = seconds.clamp(0.0, MAX_CROSSFADE_SECONDS)
+    });
+    let gap_changed = seconds_changed(settings, |settings, seconds| {
+        settings.gap_seconds = seconds.clamp(0.0, MAX_GAP_SECONDS)
+    });
+    let ui_scale_changed = {
+        let settings = settings.clone();
+        move |event: Event| {
+            let Some(percent) = event
+                .target()
+                .and_then(|target| target.dyn_into::<HtmlInputElement>().ok())
+                .and_then(|input| input.value().parse::<f32>().ok())
+                .filter(|percent| percent.is_finite())
+            else {
+                return;
+            };
+            update_settings(&settings, |settings| {
+                settings.ui_scale = (percent / 100.0).clamp(MIN_UI_SCALE, MAX_UI_SCALE)
+            });
+        }
+    };
+    let show_shortcuts = {
+        let on_show_shortcuts = props.on_show_shortcuts.clone();
+        move |_| on_show_shortcuts.emit(())
+    };
+    html! {
+        <details class="settings" ontoggle={ontoggle}>
+            <summary>{"Settings"}</summary>
+            <div class="settings-grid">
+                <label>{"Output device"}</label>
+                {output_device_select(props.output_devices.as_deref())}
+                <label>{"Theme"}</label>
+                {choice(THEMES, settings.theme, {
+                    let settings = settings.clone();
+                    move |theme| update_settings(&settings, |settings| settings.theme = theme)
+                })}
+                <label>{"Size"}</label>
+                <span>
+                    <input type="number"
+                           min={(MIN_UI_SCALE * 100.0).to_string()}
+                           max={(MAX_UI_SCALE * 100.0).to_string()}
+                           step="25"
+                           value={(settings.clamped_ui_scale() * 100.0).round().to_string()}
+                           onchange={ui_scale_changed} />
+                    {" %"}
+                </span>
+                <label>{"Visualizer"}</label>
+                <span>
+                    {choice(VISUALIZERS, settings.visualizer, {
+                        let settings = settings.clone();
+                        move |visualizer| {
+                            update_settings(&settings, |settings| settings.visualizer = visualizer)
+                        }
+                    })}
+                    <button type="button"
+                            class="settings-pop-out"
+                            title="Show the visualizer in its own window"
+                            onclick={|_| post_message(&FrontendMessage::OpenVisualizerWindow)}>
+                        {"Pop out"}
+                    </button>
+                </span>
+                <label>{"Crossfade"}</label>
+                <span>
+                    <input type="number"
+                           min="0"
+                           max={MAX_CROSSFADE_SECONDS.to_string()}
+                           step="0.5"
+                           value={settings.crossfade_seconds.to_string()}
+                           onchange={crossfade_changed} />
+                    {" seconds"}
+                </span>
+                <label>{"Gap between tracks"}</label>
+                <span>
+                    <input type="number"
+                           min="0"
+                           max={MAX_GAP_SECONDS.to_string()}
+                           step="0.5"
+                           value={settings.gap_seconds.to_string()}
+                           onchange={gap_changed} />
+                    {" seconds"}
+                </span>
+                <label>{"Normalization"}</label>
+                {choice(NORMALIZATION_MODES, settings.normalization, {
+                    let settings = settings.clone();
+                    move |normalization| {
+                        update_settings(&settings, |settings| settings.normalization = normalization)
+                    }
+                })}
+            </div>
+            <button type="button" class="settings-shortcuts" onclick={show_shortcuts}>
+                {"Keyboard shortcuts"}
+            </button>
+        </details>
+    }
+}
+
+/// Handles a change to one of the number inputs that take seconds.
+fn seconds_changed(
+    settings: &Rc<Settings>,
+    change: impl Fn(&mut Settings, f32) + 'static,
+) -> impl Fn(Event) {
+    let settings = settings.clone();
+    move |event: Event| {
+        let Some(seconds) = event
+            .target()
+            .and_then(|target| target.dyn_into::<HtmlInputElement>().ok())
+            .and_then(|input| input.value().parse::<f32>().ok())
+            .filter(|seconds| seconds.is_finite())
+        else {
+            return;
+        };
+        update_settings(&settings, |settings| change(settings, seconds));
+    }
+}
+
+fn update_settings(settings: &Settings, change: impl FnOnce(&mut Settings)) {
+    let mut settings = settings.clone();
+    change(&mut settings);
+    post_message(&FrontendMessage::UpdateSettings { settings });
+}
+
+fn selected_value(event: &Event) -> Option<String> {
+    event
+        .target()
+        .and_then(|target| target.dyn_into::<HtmlSelectElement>().ok())
+        .map(|select| select.value())
+}
+
+/// A select for one of a fixed set of choices. Options are identified by their index.
+fn choice<T: Copy + PartialEq + 'static>(
+    choices: &'static [(T, &'static str)],
+    current: T,
+    changed: impl Fn(T) + 'static,
+) -> Html {
+    let onchange = move |event: Event| {
+        let picked = selected_value(&event)
+            .and_then(|value| value.parse::<usize>().ok())
+            .and_then(|index| choices.get(index));
+        if let Some((value, _)) = picked {
+            changed(*value);
+        }
+    };
+    let options = choices.iter().enumerate().map(|(index, (value, label))| {
+        html! {
+            <option value={index.to_string()} selected={*value == current}>{*label}</option>
+        }
+    });
+    html! { <select onchange={onchange}>{for options}</select> }
+}
+
+fn output_device_select(output_devices: Option<&OutputDevices>) -> Html {
+    let onchange = |event: Event| {
+        let name = selected_value(&event).filter(|name| !name.is_empty());
+        post_message(&FrontendMessage::SetOutputDevice { name });
+    };
+    let (devices, selected) = match output_devices {
+        Some(output_devices) => (
+            output_devices.devices.clone(),
+            output_devices.selected.clone(),
+        ),
+        None => (Vec::new(), None),
+    };
+    // A device that's unplugged stays selected so that picking another is deliberate
+    let missing = selected
+        .clone()
+        .filter(|selected| !devices.contains(selected));
+    let options = devices.into_iter().chain(missing).map(|device| {
+        html! {
+            <option value={device.clone()} selected={selected.as_ref() == Some(&device)}>
+                {&device}
+            </option>
+        }
+    });
+    html! {
+        <select onchange={onchange} disabled={output_devices.is_none()}>
+            <option value="" selected={selected.is_none()}>{"System default"}</option>
+            {for options}
+        </select>
+    }
+}
@@ -0,0 +1,161 @@
+// This file is part of Millenium Player.
+// Copyright (C) 2023 John DiSanti.
+//
+// Millenium Player is free software: you can redistribute it and/or modify it under the terms of
+// the GNU General Public License as published by the Free Software Foundation, either version 3 of
+// the License, or (at your option) any later version.
+//
+// Millenium Player is distributed in the hope that it will be useful, but WITHOUT ANY WARRANTY;
+// without even the implied warranty of MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE. See
+// the GNU General Public License for more details.
+//
+// You should have received a copy of the GNU General Public License along with Millenium Player.
+// If not, see <https://www.gnu.org/licenses/>.
+
+use gloo::utils::document;
+use millenium_post_office::frontend::settings::KeyAction;
+use std::collections::BTreeMap;
+use wasm_bindgen::JsCast;
+use web_sys::{HtmlElement, KeyboardEvent};
+use yew::prelude::*;
+
+/// Key that shows and hides the cheatsheet, unless it's bound to something else.
+pub const CHEATSHEET_KEY: &str = "?";
+
+/// Keys that every control responds to, regardless of the keybindings.
+const FIXED_SHORTCUTS: &[(&str, &str)] = &[
+    ("Tab", "Move to the next control"),
+    ("Shift+Tab", "Move to the previous control"),
+    ("Enter / Space", "Activate the focused control"),
+    ("Escape", "Close this list"),
+];
+
+fn action_label(action: KeyAction) -> &'static str {
+    match action {
+        KeyAction::PlayPause => "Play or pause",
+        KeyAction::Stop => "Stop",
+        KeyAction::Back => "Back",
+        KeyAction::Forward => "Forward",
+        KeyAction::SkipBack => "Previous track",
+        KeyAction::SkipForward => "Next track",
+        KeyAction::PreviousChapter => "Previous chapter",
+        KeyAction::NextChapter => "Next chapter",
+        KeyAction::ToggleDspBypass => "Compare with unprocessed audio",
+    }
+}
+
+/// Names a `KeyboardEvent.key` value the way it's printed on the keyboard.
+fn key_label(key: &str) -> String {
+    match key {
+        " " => "Space".into(),
+        "ArrowLeft" => "Left".into(),
+        "ArrowRight" => "Right".into(),
+        "ArrowUp" => "Up".into(),
+        "ArrowDown" => "Down".into(),
+        "PageUp" => "Page Up".into(),
+        "PageDown" => "Page Down".into(),
+        key if key.chars().count() == 1 => key.to_uppercase(),
+        key => key.into(),
+    }
+}
+
+#[derive(Properties, PartialEq)]
+pub struct ShortcutsOverlayProps {
+    pub keybindings: BTreeMap<String, KeyAction>,
+    pub on_close: Callback<()>,
+}
+
+/// Lists the keyboard shortcuts. Focus moves into the overlay while it's open,
+/// and goes back to wherever it was when it closes.
+#[function_component(ShortcutsOverlay)]
+pub fn shortcuts_overlay(props: &ShortcutsOverlayProps) -> Html {
+    let close_ref = use_node_ref();
+    {
+        let close_ref = close_ref.clone();
+        use_effect_with((), move |_| {
+            let previous = document()
+                .active_element()
+                .and_then(|element| element.dyn_into::<HtmlElement>().ok());
+            if let Some(close) = close_ref.cast::<HtmlElement>() {
+                let _ = close.focus();
+            }
+            move || {
+                if let Some(previous) = previous {
+                    let _ = previous.focus();
+                }
+            }
+        });
+    }
+
+    let close = {
+        let on_close = props.on_close.clone();
+        move |_| on_close.emit(())
+    };
+    // The close button is the only thing to focus, so keep Tab from leaving the dialog
+    let onkeydown = |event: KeyboardEvent| {
+        if event.key() == "Tab" {
+            event.prevent_default();
+        }
+    };
+    let bound = props.keybindings.iter().map(|(key, action)| {
+        html! {
+            <tr>
+                <td><kbd>{key_label(key)}</kbd></td>
+                <td>{action_label(*action)}</td>
+            </tr>
+        }
+    });
+    let cheatsheet_row = (!props.keybindings.contains_key(CHEATSHEET_KEY)).then(|| {
+        html! {
+            <tr>
+                <td><kbd>{CHEATSHEET_KEY}</kbd></td>
+                <td>{"Show or hide this list"}</td>
+            </tr>
+        }
+    });
+    let fixed = FIXED_SHORTCUTS.iter().map(|(key, description)| {
+        html! {
+            <tr>
+                <td><kbd>{*key}</kbd></td>
+                <td>{*description}</td>
+            </tr>
+        }
+    });
+    html! {
+        <div class="shortcuts-backdrop" onclick={close.clone()}>
+            <div class="shortcuts"
+                 role="dialog"
+                 aria-modal="true"
+                 aria-labelledby="shortcuts-title"
+                 onclick={|event: MouseEvent| event.stop_propagation()}
+                 onkeydown={onkeydown}>
+                <h2 id="shortcuts-title">{"Keyboard shortcuts"}</h2>
+                <table>
+                    <tbody>
+                        {for bound}
+                        {cheatsheet_row}
+                        {for fixed}
+                    </tbody>
+                </table>
+                <button type="button" class="shortcuts-close" ref={close_ref} onclick={close}>
+                    {"Close"}
+                </button>
+            </div>
+        </div>
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn key_labels() {
+        assert_eq!("Space", key_label(" "));
+        assert_eq!("Left", key_label("ArrowLeft"));
+        assert_eq!("Page Down", key_label("PageDown"));
+        assert_eq!("S", key_label("s"));
+        assert_eq!("?", key_label("?"));
+        assert_eq!("Home", key_label("Home"));
+    }
+}
@@ -0,0 +1,142 @@
+// This file is part of Millenium Player.
+// Copyright (C) 2023 John DiSanti.
+//
+// Millenium Player is free software: you can redistribute it and/or modify it under the terms of
+// the GNU General Public License as published by the Free Software Foundation, either version 3 of
+// the License, or (at your option) any later version.
+//
+// Millenium Player is distributed in the hope that it will be useful, but WITHOUT ANY WARRANTY;
+// without even the implied warranty of MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE. See
+// the GNU General Public License for more details.
+//
+// You should have received a copy of the GNU General Public License along with Millenium Player.
+// If not, see <https://www.gnu.org/licenses/>.
+
+use crate::{component::duration::Duration, message::post_message};
+use gloo::timers::callback::Timeout;
+use millenium_post_office::{
+    frontend::message::FrontendMessage,
+    types::{QueuedTrack, StreamHealth},
+};
+use std::time::Duration as StdDuration;
+use yew::prelude::*;
+
+/// How long snackbars stay visible before hiding themselves.
+const SNACKBAR_TIMEOUT_MILLIS: u32 = 8000;
+
+#[derive(Properties, PartialEq)]
+pub struct IntroSkippedSnackbarProps {
+    pub position: StdDuration,
+}
+
+#[function_component(IntroSkippedSnackbar)]
+pub fn intro_skipped_snackbar(props: &IntroSkippedSnackbarProps) -> Html {
+    let visible = use_state(|| true);
+    {
+        let visible = visible.clone();
+        use_effect_with(props.position, move |_| {
+            visible.set(true);
+            let timeout = Timeout::new(SNACKBAR_TIMEOUT_MILLIS, move || visible.set(false));
+            move || drop(timeout)
+        });
+    }
+
+    if !*visible {
+        return html!();
+    }
+    let onclick = |_| post_message(&FrontendMessage::MediaControlUndoIntroSkip);
+    html! {
+        <div class="snackbar" role="status">
+            <span>{"Skipped intro ("}<Duration duration={props.position} />{")"}</span>
+            <button type="button" class="snackbar-action" onclick={onclick}>{"Undo"}</button>
+        </div>
+    }
+}
+
+#[derive(Properties, PartialEq)]
+pub struct StreamHealthSnackbarProps {
+    pub health: StreamHealth,
+}
+
+/// Shows network stream trouble for as long as it lasts.
+#[function_component(StreamHealthSnackbar)]
+pub fn stream_health_snackbar(props: &StreamHealthSnackbarProps) -> Html {
+    let message = match props.health {
+        StreamHealth::Connected => return html!(),
+        StreamHealth::Buffering => "Buffering stream…".to_string(),
+        StreamHealth::Reconnecting { attempt } => {
+            format!("Connection lost. Reconnecting (attempt {attempt})…")
+        }
+        StreamHealth::Failed => "Couldn't reconnect to the stream.".to_string(),
+    };
+    html! {
+        <div class="snackbar" role="status">
+            <span>{message}</span>
+        </div>
+    }
+}
+
+#[derive(Properties, PartialEq)]
+pub struct PlayerStoppedSnackbarProps {
+    pub error: Option<String>,
+}
+
+/// Offers to restart the player thread for as long as it's stopped.
+#[function_component(PlayerStoppedSnackbar)]
+pub fn player_stopped_snackbar(props: &PlayerStoppedSnackbarProps) -> Html {
+    let onclick = |_| post_message(&FrontendMessage::RestartPlayer);
+    html! {
+        <div class="snackbar" role="alert" title={props.error.clone()}>
+            <span>{"The player stopped unexpectedly."}</span>
+            <button type="button" class="snackbar-action" onclick={onclick}>{"Restart"}</button>
+        </div>
+    }
+}
+
+#[derive(Properties, PartialEq)]
+pub struct DuplicatesSnackbarProps {
+    pub duplicates: Vec<QueuedTrack>,
+}
+
+/// Says which tracks weren't queued because they're already in the playlist,
+/// and offers to queue them anyway.
+#[function_component(DuplicatesSnackbar)]
+pub fn duplicates_snackbar(props: &DuplicatesSnackbarProps) -> Html {
+    let visible = use_state(|| true);
+    {
+        let visible = visible.clone();
+        use_effect_with(props.duplicates.clone(), move |_| {
+            visible.set(true);
+            let timeout = Timeout::new(SNACKBAR_TIMEOUT_MILLIS, move || visible.set(false));
+            move || drop(timeout)
+        });
+    }
+
+    if !*visible {
+        return html!();
+    }
+    let message = match props.duplicates.as_slice() {
+        [track] => {
+            let name = match (&track.artist, &track.title) {
+                (Some(artist), Some(title)) => format!("{artist} - {title}"),
+                (None, Some(title)) => title.clone(),
+                _ => track.location.clone(),
+            };
+            format!("\"{name}\" is already in the playlist")
+        }
+        tracks => format!("{} tracks are already in the playlist", tracks.len()),
+    };
+    let onclick = {
+        let visible = visible.clone();
+        move |_| {
+            post_message(&FrontendMessage::QueueSkippedDuplicates);
+            visible.set(false);
+        }
+    };
+    html! {
+        <div class="snackbar" role="status">
+            <span>{message}</span>
+            <button type="button" class="snackbar-action" onclick={onclick}>{"Add anyway"}</button>
+        </div>
+    }
+}
@@ -0,0 +1,55 @@
+// This file is part of Millenium Player.
+// Copyright (C) 2023 John DiSanti.
+//
+// Millenium Player is free software: you can redistribute it and/or modify it under the terms of
+// the GNU General Public License as published by the Free Software Foundation, either version 3 of
+// the License, or (at your option) any later version.
+//
+// Millenium Player is distributed in the hope that it will be useful, but WITHOUT ANY WARRANTY;
+// without even the implied warranty of MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE. See
+// the GNU General Public License for more details.
+//
+// You should have received a copy of the GNU General Public License along with Millenium Player.
+// If not, see <https://www.gnu.org/licenses/>.
+
+use crate::locale::format_time_of_day;
+use millenium_post_office::types::PlayedSong;
+use std::time::UNIX_EPOCH;
+use wasm_bindgen::JsValue;
+use yew::prelude::*;
+
+#[derive(Properties, PartialEq)]
+pub struct SongHistoryProps {
+    /// Songs the station has played, oldest first.
+    pub songs: Vec<PlayedSong>,
+}
+
+/// Lists the songs the current station has played, newest first.
+#[function_component(SongHistory)]
+pub fn song_history(props: &SongHistoryProps) -> Html {
+    let songs = props.songs.iter().rev().map(|song| {
+        html! {
+            <li>
+                <time>{local_time(song)}</time>
+                <span class="song-history-title">{&song.title}</span>
+            </li>
+        }
+    });
+    html! {
+        <details class="song-history">
+            <summary>{"Recently played"}</summary>
+            <ol>{for songs}</ol>
+        </details>
+    }
+}
+
+/// Formats when the song was played as a local time.
+fn local_time(song: &PlayedSong) -> String {
+    let millis = song
+        .played_at
+        .duration_since(UNIX_EPOCH)
+        .unwrap_or_default()
+        .as_millis();
+    let date = js_sys::Date::new(&JsValue::from_f64(millis as f64));
+    format_time_of_day(&date)
+}
@@ -0,0 +1,61 @@
+// This file is part of Millenium Player.
+// Copyright (C) 2023 John DiSanti.
+//
+// Millenium Player is free software: you can redistribute it and/or modify it under the terms of
+// the GNU General Public License as published by the Free Software Foundation, either version 3 of
+// the License, or (at your option) any later version.
+//
+// Millenium Player is distributed in the hope that it will be useful, but WITHOUT ANY WARRANTY;
+// without even the implied warranty of MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE. See
+// the GNU General Public License for more details.
+//
+// You should have received a copy of the GNU General Public License along with Millenium Player.
+// If not, see <https://www.gnu.org/licenses/>.
+
+use crate::message::post_message;
+use millenium_post_office::{frontend::message::FrontendMessage, types::StreamQuality};
+use wasm_bindgen::JsCast;
+use web_sys::HtmlSelectElement;
+use yew::prelude::*;
+
+/// Value of the option for choosing the quality automatically.
+const AUTO: &str = "auto";
+
+#[derive(Properties, PartialEq)]
+pub struct StreamQualitySelectProps {
+    pub quality: StreamQuality,
+}
+
+/// Picks which of a stream's qualities to play.
+#[function_component(StreamQualitySelect)]
+pub fn stream_quality_select(props: &StreamQualitySelectProps) -> Html {
+    let quality = &props.quality;
+    let onchange = |event: Event| {
+        let value = event
+            .target()
+            .and_then(|target| target.dyn_into::<HtmlSelectElement>().ok())
+            .map(|select| select.value())
+            .unwrap_or_default();
+        let variant = value.parse::<usize>().ok();
+        post_message(&FrontendMessage::SelectStreamVariant { variant });
+    };
+    let auto_label = match quality.variants.get(quality.active) {
+        Some(active) => format!("Auto ({})", active.label),
+        None => "Auto".to_string(),
+    };
+    let options = quality.variants.iter().enumerate().map(|(index, variant)| {
+        html! {
+            <option value={index.to_string()} selected={quality.selected == Some(index)}>
+                {&variant.label}
+            </option>
+        }
+    });
+    html! {
+        <div class="stream-quality">
+            <select title="Stream quality" onchange={onchange}>
+                <option value={AUTO} selected={quality.selected.is_none()}>{auto_label}</option>
+                {for options}
+            </select>
+        </div>
+    }
+}
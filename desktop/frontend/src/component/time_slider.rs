@@ -13,7 +13,13 @@
 // If not, see <https://www.gnu.org/licenses/>.
 
 use crate::{component::duration::Duration as DurationComponent, message::post_message};
-use millenium_post_office::frontend::message::FrontendMessage;
+use millenium_post_office::{
+    frontend::{
+        message::FrontendMessage,
+        state::{LiveStatus, TimeDisplay},
+    },
+    types::AbLoop,
+};
 use std::time::Duration;
 use yew::prelude::*;
 
@@ -22,23 +28,112 @@ pub struct TimeSliderProps {
     pub current_position: Duration,
     /// End position in the audio track (length of the track). If `None`, then we are streaming audio.
     pub end_position: Option<Duration>,
+    /// Set while playing a live stream, which can be rewound within its timeshift.
+    pub live: Option<LiveStatus>,
+    /// Region of the track that is repeated, shown as markers on the slider.
+    pub ab_loop: AbLoop,
+    /// Whether the time after the slider is the track's length or the time remaining.
+    pub time_display: TimeDisplay,
+    /// Clicking the time after the slider switches between the length and the time remaining.
+    pub on_toggle_time_display: Callback<()>,
+}
+
+/// Positions a loop marker over the slider track, which is inset by half the thumb width.
+fn marker_style(position: Duration, length: Duration) -> String {
+    let fraction = (position.as_secs_f64() / length.as_secs_f64()).clamp(0.0, 1.0);
+    format!("left: calc(10px + (100% - 20px) * {fraction});")
+}
+
+/// Behind the live broadcast by less than this still counts as live, since the position is
+/// only refreshed once a second.
+const LIVE_TOLERANCE: Duration = Duration::from_secs(2);
+
+fn seek(event: Event) {
+    let value = input_value!(event);
+    let secs = value.parse::<f64>().expect("valid number");
+    let position = Duration::from_secs_f64(secs.max(0.0));
+    post_message(&FrontendMessage::MediaControlSeek { position });
 }
 
 #[function_component(TimeSlider)]
 pub fn time_slider(props: &TimeSliderProps) -> Html {
     let (prefix, input, suffix) = if let Some(length) = props.end_position {
-        let onchange = |event: Event| {
-            let value = input_value!(event);
-            let secs = value.parse::<u64>().expect("valid integer");
-            let position = Duration::from_secs(secs);
-            post_message(&FrontendMessage::MediaControlSeek { position });
+        let value = props.current_position.as_secs_f64().to_string();
+        let max = length.as_secs_f64().to_string();
+        let ab_loop = props.ab_loop;
+        let region = ab_loop.region().map(|(a, b)| {
+            let width = (b.saturating_sub(a).as_secs_f64() / length.as_secs_f64()).clamp(0.0, 1.0);
+            let style = format!(
+                "{} width: calc((100% - 20px) * {width});",
+                marker_style(a, length)
+            );
+            html! { <div class="time-slider-loop-region" style={style} /> }
+        });
+        let markers = [ab_loop.a, ab_loop.b]
+            .into_iter()
+            .flatten()
+            .map(|position| {
+                html! { <div class="time-slider-loop-marker" style={marker_style(position, length)} /> }
+            });
+        let (title, time) = match props.time_display {
+            TimeDisplay::Length => (
+                "Show the time remaining",
+                html! { <DurationComponent duration={length} /> },
+            ),
+            TimeDisplay::Remaining => {
+                let remaining = length.saturating_sub(props.current_position);
+                (
+                    "Show the track length",
+                    html! { <>{"-"}<DurationComponent duration={remaining} /></> },
+                )
+            }
         };
-        let value = props.current_position.as_secs().to_string();
-        let max = length.as_secs().to_string();
+        let on_toggle = props.on_toggle_time_display.clone();
         (
             html! { <DurationComponent duration={props.current_position} /> },
-            html! { <input type="range" step="1" min="0" max={max} value={value} onchange={onchange} /> },
-            html! { <DurationComponent duration={length} /> },
+            html! {
+                <>
+                    {region}
+                    {for markers}
+                    <input type="range" step="any" min="0" max={max} value={value} onchange={seek} />
+                </>
+            },
+            html! {
+                <button type="button"
+                        class="time-slider-time-display"
+                        title={title}
+                        onclick={move |_| on_toggle.emit(())}>
+                    {time}
+                </button>
+            },
+        )
+    } else if let Some(live) = props.live {
+        // The slider covers the timeshift, and its right end is the live broadcast
+        let min = live.earliest_position.as_secs_f64().to_string();
+        let max = (props.current_position + live.behind_live)
+            .as_secs_f64()
+            .to_string();
+        let value = props.current_position.as_secs_f64().to_string();
+        let is_live = live.behind_live < LIVE_TOLERANCE;
+        let prefix = if is_live {
+            html! { {"Live"} }
+        } else {
+            html! { <>{"-"}<DurationComponent duration={live.behind_live} /></> }
+        };
+        (
+            prefix,
+            html! {
+                <input type="range" step="any" min={min} max={max} value={value} onchange={seek} />
+            },
+            html! {
+                <button type="button"
+                        class="time-slider-time-display"
+                        title="Jump to the live broadcast"
+                        disabled={is_live}
+                        onclick={|_| post_message(&FrontendMessage::MediaControlJumpToLive)}>
+                    {"Live"}
+                </button>
+            },
         )
     } else {
         let zero = Duration::from_secs(0);
@@ -0,0 +1,97 @@
+// This file is part of Millenium Player.
+// Copyright (C) 2023 John DiSanti.
+//
+// Millenium Player is free software: you can redistribute it and/or modify it under the terms of
+// the GNU General Public License as published by the Free Software Foundation, either version 3 of
+// the License, or (at your option) any later version.
+//
+// Millenium Player is distributed in the hope that it will be useful, but WITHOUT ANY WARRANTY;
+// without even the implied warranty of MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE. See
+// the GNU General Public License for more details.
+//
+// You should have received a copy of the GNU General Public License along with Millenium Player.
+// If not, see <https://www.gnu.org/licenses/>.
+
+use super::{Renderer, BAR_COLORS, HEIGHT, WIDTH};
+use millenium_post_office::frontend::state::Waveform as WaveformData;
+use wasm_bindgen::{JsCast, JsValue};
+use web_sys::{CanvasRenderingContext2d, HtmlCanvasElement};
+
+/// Slower renderer for webviews without WebGL (remote desktops, software rendering, etc.).
+pub(super) struct Canvas2dRenderer {
+    context: CanvasRenderingContext2d,
+    colors: Vec<JsValue>,
+}
+
+impl Canvas2dRenderer {
+    /// Returns `Ok(None)` if the webview doesn't support 2D canvas rendering either.
+    pub(super) fn new(canvas: &HtmlCanvasElement) -> Result<Option<Self>, String> {
+        let context: CanvasRenderingContext2d = match canvas.get_context("2d") {
+            Ok(Some(context)) => context
+                .dyn_into()
+                .expect("failed to cast JsObject into CanvasRenderingContext2d"),
+            Ok(None) => return Ok(None),
+            Err(err) => {
+                return Err(format!(
+                    "failed to call HtmlCanvasElement::getContext: {err:?}"
+                ))
+            }
+        };
+        // Match the coordinate space used by the WebGL renderer
+        canvas.set_width(WIDTH as u32);
+        canvas.set_height(HEIGHT as u32);
+
+        let colors = BAR_COLORS
+            .iter()
+            .map(|[r, g, b, a]| {
+                JsValue::from_str(&format!(
+                    "rgba({}, {}, {}, {a})",
+                    (r * 255.0).round(),
+                    (g * 255.0).round(),
+                    (b * 255.0).round()
+                ))
+            })
+            .collect();
+        Ok(Some(Self { context, colors }))
+    }
+
+    /// Draws a bar starting at `center_y` that is split into bands of increasing brightness.
+    /// Negative scales draw the bar downwards.
+    fn draw_bar(&self, x: f64, width: f64, center_y: f64, scale_y: f64) {
+        let band_height = (HEIGHT as f64 / 4.0).round() * scale_y;
+        for (band, color) in self.colors.iter().enumerate() {
+            let start = center_y + band as f64 * band_height;
+            let end = start + band_height;
+            // Canvas coordinates go top-down, so flip the Y axis
+            let top = HEIGHT as f64 - start.max(end);
+            self.context.set_fill_style(color);
+            self.context.fill_rect(x, top, width, band_height.abs());
+        }
+    }
+}
+
+impl Renderer for Canvas2dRenderer {
+    fn render(&self, waveform: &WaveformData) {
+        self.context
+            .set_fill_style(&JsValue::from_str("rgb(0, 0, 0)"));
+        self.context
+            .fill_rect(0.0, 0.0, WIDTH as f64, HEIGHT as f64);
+
+        let bin_count = waveform.spectrum.len() as f64;
+
+        let center_y = (0.33 * HEIGHT as f64).round();
+        let top_scale = 0.8;
+        let bottom_scale = 0.4;
+        let step = (WIDTH as f64 / bin_count).round();
+        let bar_width = (WIDTH as f64 / bin_count - 1.0).floor();
+
+        for (i, &height) in waveform.spectrum.iter().enumerate() {
+            let scale_y = height as f64 * top_scale;
+            self.draw_bar(step * i as f64, bar_width, center_y, scale_y);
+        }
+        for (i, &height) in waveform.amplitude.iter().enumerate() {
+            let scale_y = -height as f64 * bottom_scale;
+            self.draw_bar(step * i as f64, bar_width, center_y, scale_y);
+        }
+    }
+}
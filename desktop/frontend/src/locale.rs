@@ -0,0 +1,238 @@
+// This file is part of Millenium Player.
+// Copyright (C) 2023 John DiSanti.
+//
+// Millenium Player is free software: you can redistribute it and/or modify it under the terms of
+// the GNU General Public License as published by the Free Software Foundation, either version 3 of
+// the License, or (at your option) any later version.
+//
+// Millenium Player is distributed in the hope that it will be useful, but WITHOUT ANY WARRANTY;
+// without even the implied warranty of MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE. See
+// the GNU General Public License for more details.
+//
+// You should have received a copy of the GNU General Public License along with Millenium Player.
+// If not, see <https://www.gnu.org/licenses/>.
+
+use crate::warn;
+use js_sys::{Array, Date, Function, Intl, Object, Reflect};
+use std::time::Duration;
+use wasm_bindgen::{prelude::*, JsValue};
+
+#[wasm_bindgen]
+extern "C" {
+    #[wasm_bindgen(catch, js_namespace = Intl, js_name = getCanonicalLocales)]
+    fn canonical_locales(locales: &JsValue) -> Result<Array, JsValue>;
+}
+
+/// Intl formatters for the current locale, created once since the time slider
+/// formats durations on every playback status update.
+struct Formatters {
+    /// Formats numbers with at least two digits and no grouping, for minutes and seconds.
+    padded: Function,
+    /// Formats numbers without grouping, for hours.
+    plain: Function,
+    /// Formats numbers with grouping, for counts.
+    count: Function,
+    time_of_day: Function,
+    /// Separates hours, minutes, and seconds, which isn't a colon in every locale.
+    time_separator: String,
+}
+
+static mut LOCALE: Option<String> = None;
+static mut FORMATTERS: Option<Formatters> = None;
+
+/// Sets the locale that durations, times, and counts are formatted for.
+/// The browser's locale is used if `None`.
+pub fn set_locale(locale: Option<String>) {
+    // Safe because there isn't any multi-threading in the frontend
+    unsafe {
+        if LOCALE != locale {
+            LOCALE = locale;
+            FORMATTERS = None;
+        }
+    }
+}
+
+fn formatters() -> &'static Formatters {
+    // Safe because there isn't any multi-threading in the frontend
+    unsafe {
+        if FORMATTERS.is_none() {
+            FORMATTERS = Some(Formatters::new(LOCALE.as_deref()));
+        }
+        FORMATTERS.as_ref().unwrap()
+    }
+}
+
+impl Formatters {
+    fn new(locale: Option<&str>) -> Self {
+        let locales = locale
+            .and_then(
+                |locale| match canonical_locales(&JsValue::from_str(locale)) {
+                    Ok(locales) => Some(locales),
+                    Err(_) => {
+                        warn!("ignoring invalid locale {locale:?}");
+                        None
+                    }
+                },
+            )
+            .unwrap_or_default();
+        let number = |options: &[(&str, JsValue)]| {
+            Intl::NumberFormat::new(&locales, &options_object(options)).format()
+        };
+        let time_parts = Intl::DateTimeFormat::new(
+            &locales,
+            &options_object(&[
+                ("hour", "numeric".into()),
+                ("minute", "2-digit".into()),
+                ("hourCycle", "h23".into()),
+                ("timeZone", "UTC".into()),
+            ]),
+        )
+        .format_to_parts(&Date::new(&0.into()));
+        let time_separator = time_parts
+            .iter()
+            .find(|part| part_field(part, "type").as_deref() == Some("literal"))
+            .and_then(|part| part_field(&part, "value"))
+            .unwrap_or_else(|| ":".into());
+        Self {
+            padded: number(&[
+                ("minimumIntegerDigits", 2.into()),
+                ("useGrouping", false.into()),
+            ]),
+            plain: number(&[("useGrouping", false.into())]),
+            count: number(&[]),
+            time_of_day: Intl::DateTimeFormat::new(
+                &locales,
+                &options_object(&[("hour", "numeric".into()), ("minute", "2-digit".into())]),
+            )
+            .format(),
+            time_separator,
+        }
+    }
+}
+
+fn options_object(options: &[(&str, JsValue)]) -> Object {
+    let object = Object::new();
+    for (key, value) in options {
+        Reflect::set(&object, &JsValue::from_str(key), value).expect("object");
+    }
+    object
+}
+
+fn part_field(part: &JsValue, field: &str) -> Option<String> {
+    Reflect::get(part, &JsValue::from_str(field))
+        .ok()
+        .and_then(|value| value.as_string())
+}
+
+fn call_format(format: &Function, value: &JsValue) -> Option<String> {
+    format
+        .call1(&JsValue::NULL, value)
+        .ok()
+        .and_then(|formatted| formatted.as_string())
+}
+
+/// Formats a duration as "h:mm:ss", or "mm:ss" if it's under an hour.
+pub fn format_duration(duration: Duration) -> String {
+    let formatters = formatters();
+    format_duration_with(duration, &formatters.time_separator, |value, padded| {
+        let format = if padded {
+            &formatters.padded
+        } else {
+            &formatters.plain
+        };
+        call_format(format, &JsValue::from_f64(value as f64)).unwrap_or_else(|| value.to_string())
+    })
+}
+
+/// Formats a long duration in hours and minutes, such as "1 h 42 m", for totals like the
+/// playlist's length.
+pub fn format_length(duration: Duration) -> String {
+    format_length_with(duration, format_count)
+}
+
+/// Formats a count with the locale's digits and grouping, such as "1,234".
+pub fn format_count(count: usize) -> String {
+    call_format(&formatters().count, &JsValue::from_f64(count as f64))
+        .unwrap_or_else(|| count.to_string())
+}
+
+/// Formats the hour and minute of a time, such as "14:05" or "2:05 PM".
+pub fn format_time_of_day(date: &Date) -> String {
+    call_format(&formatters().time_of_day, date)
+        .unwrap_or_else(|| format!("{:02}:{:02}", date.get_hours(), date.get_minutes()))
+}
+
+/// Formats a duration with the given separator, and a function that formats each number,
+/// padding it to two digits if asked to.
+fn format_duration_with(
+    duration: Duration,
+    separator: &str,
+    number: impl Fn(u64, bool) -> String,
+) -> String {
+    let total_seconds = duration.as_secs();
+    let hours = Some(total_seconds / 3600).filter(|&h| h > 0);
+    let minutes = number(total_seconds % 3600 / 60, true);
+    let seconds = number(total_seconds % 60, true);
+    if let Some(hours) = hours {
+        format!(
+            "{}{separator}{minutes}{separator}{seconds}",
+            number(hours, false)
+        )
+    } else {
+        format!("{minutes}{separator}{seconds}")
+    }
+}
+
+fn format_length_with(duration: Duration, number: impl Fn(usize) -> String) -> String {
+    let total_seconds = duration.as_secs() as usize;
+    let (hours, minutes) = (total_seconds / 3600, total_seconds % 3600 / 60);
+    match (hours, minutes) {
+        (0, 0) => format!("{} s", number(total_seconds)),
+        (0, minutes) => format!("{} m", number(minutes)),
+        (hours, minutes) => format!("{} h {} m", number(hours), number(minutes)),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn ascii(value: u64, padded: bool) -> String {
+        if padded {
+            format!("{value:02}")
+        } else {
+            value.to_string()
+        }
+    }
+
+    #[test]
+    fn durations() {
+        let format = |seconds| format_duration_with(Duration::from_secs(seconds), ":", ascii);
+        assert_eq!("00:01", format(1));
+        assert_eq!("00:10", format(10));
+        assert_eq!("01:01", format(61));
+        assert_eq!("10:01", format(601));
+        assert_eq!("59:59", format(3599));
+        assert_eq!("1:00:00", format(3600));
+        assert_eq!("1:01:01", format(3661));
+        assert_eq!("100:00:00", format(360_000));
+    }
+
+    #[test]
+    fn lengths() {
+        let format = |seconds| format_length_with(Duration::from_secs(seconds), |n| n.to_string());
+        assert_eq!("45 s", format(45));
+        assert_eq!("1 m", format(60));
+        assert_eq!("59 m", format(3599));
+        assert_eq!("1 h 0 m", format(3600));
+        assert_eq!("1 h 42 m", format(6120));
+    }
+
+    #[test]
+    fn durations_with_locale_separator() {
+        // Finnish separates the parts of a time with periods
+        let format = |seconds| format_duration_with(Duration::from_secs(seconds), ".", ascii);
+        assert_eq!("01.01", format(61));
+        assert_eq!("1.01.01", format(3661));
+    }
+}
@@ -12,30 +12,52 @@
 // You should have received a copy of the GNU General Public License along with Millenium Player.
 // If not, see <https://www.gnu.org/licenses/>.
 
-use crate::component::root::{Root, RootMessage};
-use gloo::net::http::Request;
+use crate::component::{
+    root::{Root, RootMessage},
+    settings::OutputDevices,
+};
+use gloo::{events::EventListener, net::http::Request, utils::document};
 use millenium_post_office::{
     bytes::ne_bytes_to_f32s,
     frontend::{
         message::FrontendMessage,
-        state::{PlaybackStateData, Waveform, WaveformStateData},
+        settings::Settings,
+        state::{
+            PlaybackStateData, PlaylistStateData, UiPrefs, Waveform, WaveformStateData,
+            WAVEFORM_BIN_COUNT_HEADER,
+        },
     },
+    types::{Favorite, InstanceHealth},
 };
-use std::rc::Rc;
+use std::{mem::size_of, rc::Rc};
 use yew::{platform::spawn_local, AppHandle};
 
 #[macro_use]
 mod macros;
 mod component {
+    pub mod ab_loop;
+    pub mod dsp_bypass;
     pub mod duration;
+    pub mod favorites;
     pub mod media_controls;
     pub mod media_info;
+    pub mod party_mode;
+    pub mod play_queue;
+    pub mod playlist;
+    pub mod rating;
     pub mod root;
+    pub mod seek_buttons;
+    pub mod settings;
+    pub mod shortcuts;
+    pub mod snackbar;
+    pub mod song_history;
+    pub mod stream_quality;
     pub mod time_slider;
     pub mod title_bar;
     pub mod volume_slider;
     pub mod waveform;
 }
+mod locale;
 mod log;
 mod message;
 
@@ -64,12 +86,51 @@ fn main() {
         .expect("failed to query DOM")
         .expect("failed to find the #root-content element");
     set_root_handle(yew::Renderer::<component::root::Root>::with_root(root).render());
+    spawn_local(fetch_settings());
+    spawn_local(fetch_favorites());
+    // Playback may have started before the web view finished loading
+    spawn_local(fetch_playback_data());
+    spawn_local(fetch_health());
+    spawn_local(fetch_playlist());
+    spawn_local(fetch_ui_prefs());
+
+    // Waveform updates are ignored while hidden, so catch up once the window is shown again
+    EventListener::new(&document(), "visibilitychange", |_| {
+        if !document().hidden() {
+            spawn_local(fetch_waveform_data());
+        }
+    })
+    .forget();
 }
 
 fn handle_message(message: FrontendMessage) {
     match message {
         FrontendMessage::PlaybackStateUpdated => spawn_local(fetch_playback_data()),
-        FrontendMessage::WaveformStateUpdated => spawn_local(fetch_waveform_data()),
+        FrontendMessage::SettingsChanged { settings } => {
+            root_handle_mut().send_message(RootMessage::UpdateSettings(Rc::new(settings)))
+        }
+        FrontendMessage::OutputDevicesListed { devices, selected } => {
+            root_handle_mut().send_message(RootMessage::UpdateOutputDevices(Rc::new(
+                OutputDevices { devices, selected },
+            )))
+        }
+        FrontendMessage::FavoritesChanged { favorites } => {
+            root_handle_mut().send_message(RootMessage::UpdateFavorites(Rc::new(favorites)))
+        }
+        FrontendMessage::HealthUpdated => spawn_local(fetch_health()),
+        FrontendMessage::PlaylistStateUpdated => spawn_local(fetch_playlist()),
+        FrontendMessage::PlaylistEntriesUpdated { entries } => {
+            root_handle_mut().send_message(RootMessage::UpdatePlaylistEntries(entries))
+        }
+        FrontendMessage::ShowHidePlaylist => {
+            root_handle_mut().send_message(RootMessage::ShowHidePlaylist)
+        }
+        FrontendMessage::WaveformStateUpdated => {
+            // Nothing is rendered while the window is hidden, so don't bother fetching
+            if !document().hidden() {
+                spawn_local(fetch_waveform_data())
+            }
+        }
         _ => {}
     }
 }
@@ -93,10 +154,105 @@ async fn fetch_playback_data() {
     }
 }
 
+async fn fetch_settings() {
+    let response = Request::get("/ipc/settings").send().await;
+    match response {
+        Ok(response) => match response.json::<Settings>().await {
+            Ok(settings) => {
+                root_handle_mut().send_message(RootMessage::UpdateSettings(Rc::new(settings)))
+            }
+            Err(err) => error!("failed to parse settings: {err}"),
+        },
+        Err(err) => {
+            error!("failed to fetch settings: {err}");
+        }
+    }
+}
+
+async fn fetch_favorites() {
+    let response = Request::get("/ipc/favorites").send().await;
+    match response {
+        Ok(response) => match response.json::<Vec<Favorite>>().await {
+            Ok(favorites) => {
+                root_handle_mut().send_message(RootMessage::UpdateFavorites(Rc::new(favorites)))
+            }
+            Err(err) => error!("failed to parse favorites: {err}"),
+        },
+        Err(err) => {
+            error!("failed to fetch favorites: {err}");
+        }
+    }
+}
+
+async fn fetch_health() {
+    let response = Request::get("/ipc/health").send().await;
+    match response {
+        Ok(response) => match response.json::<InstanceHealth>().await {
+            Ok(health) => {
+                root_handle_mut().send_message(RootMessage::UpdateHealth(Rc::new(health)))
+            }
+            Err(err) => error!("failed to parse health: {err}"),
+        },
+        Err(err) => {
+            error!("failed to fetch health: {err}");
+        }
+    }
+}
+
+async fn fetch_playlist() {
+    let response = Request::get("/ipc/playlist").send().await;
+    match response {
+        Ok(response) => match response.json::<PlaylistStateData>().await {
+            Ok(playlist) => {
+                root_handle_mut().send_message(RootMessage::UpdatePlaylist(Rc::new(playlist)))
+            }
+            Err(err) => error!("failed to parse playlist: {err}"),
+        },
+        Err(err) => {
+            error!("failed to fetch playlist: {err}");
+        }
+    }
+}
+
+async fn fetch_ui_prefs() {
+    let response = Request::get("/ipc/ui-prefs").send().await;
+    match response {
+        Ok(response) => match response.json::<UiPrefs>().await {
+            Ok(prefs) => root_handle_mut().send_message(RootMessage::UpdateUiPrefs(prefs)),
+            Err(err) => error!("failed to parse UI preferences: {err}"),
+        },
+        Err(err) => {
+            error!("failed to fetch UI preferences: {err}");
+        }
+    }
+}
+
+/// Splits a waveform response body into its spectrum and amplitude, which have `bin_count` values each.
+fn parse_waveform(bin_count: usize, bytes: &[u8]) -> Option<Waveform> {
+    let half = bin_count * size_of::<f32>();
+    if bin_count == 0 || bytes.len() != half * 2 {
+        return None;
+    }
+    let (spectrum_bytes, amplitude_bytes) = bytes.split_at(half);
+    Some(Waveform {
+        spectrum: ne_bytes_to_f32s(spectrum_bytes),
+        amplitude: ne_bytes_to_f32s(amplitude_bytes),
+    })
+}
+
 async fn fetch_waveform_data() {
     let response = Request::get("/ipc/waveform").send().await;
     match response {
+        Ok(response) if response.status() == 204 => {
+            root_handle_mut().send_message(RootMessage::UpdateWaveformState(WaveformStateData {
+                waveform: None,
+            }));
+        }
         Ok(response) => {
+            let bin_count = response
+                .headers()
+                .get(WAVEFORM_BIN_COUNT_HEADER)
+                .and_then(|count| count.parse::<usize>().ok());
             let bytes = match response.binary().await {
                 Ok(bytes) => bytes,
                 Err(err) => {
@@ -104,19 +260,39 @@ async fn fetch_waveform_data() {
                     return;
                 }
             };
-            let (spectrum_bytes, amplitude_bytes) = bytes.split_at(bytes.len() / 2);
-            let spectrum = ne_bytes_to_f32s(spectrum_bytes);
-            let amplitude = ne_bytes_to_f32s(amplitude_bytes);
-
-            root_handle_mut().send_message(RootMessage::UpdateWaveformState(WaveformStateData {
-                waveform: Some(Waveform {
-                    spectrum,
-                    amplitude,
-                }),
-            }));
+            match bin_count.and_then(|bin_count| parse_waveform(bin_count, &bytes)) {
+                Some(waveform) => root_handle_mut().send_message(RootMessage::UpdateWaveformState(
+                    WaveformStateData {
+                        waveform: Some(waveform),
+                    },
+                )),
+                None => error!(
+                    "waveform response doesn't match its bin count of {bin_count:?} ({} bytes)",
+                    bytes.len()
+                ),
+            }
         }
         Err(err) => {
             error!("failed to fetch waveform state: {err}");
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use millenium_post_office::bytes::copy_f32s_into_ne_bytes;
+
+    #[test]
+    fn parse_waveform_checks_bin_count() {
+        let mut bytes = Vec::new();
+        copy_f32s_into_ne_bytes(&mut bytes, &[1.0, 2.0, 3.0, 4.0]);
+
+        let waveform = parse_waveform(2, &bytes).unwrap();
+        assert_eq!(&[1.0, 2.0], &*waveform.spectrum);
+        assert_eq!(&[3.0, 4.0], &*waveform.amplitude);
+
+        assert!(parse_waveform(3, &bytes).is_none());
+        assert!(parse_waveform(0, &[]).is_none());
+    }
+}
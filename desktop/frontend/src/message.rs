@@ -13,7 +13,8 @@
 // If not, see <https://www.gnu.org/licenses/>.
 
 use crate::error;
-use millenium_post_office::frontend::message::FrontendMessage;
+use gloo::net::http::Request;
+use millenium_post_office::frontend::{message::FrontendMessage, state::UiPrefs};
 use wasm_bindgen::prelude::*;
 
 #[wasm_bindgen]
@@ -38,3 +39,31 @@ pub fn post_message(message: &FrontendMessage) {
     let value = serde_json::to_string(&message).expect("serializable");
     ffi_post_message(&value)
 }
+
+/// Posts several messages that the backend applies together, such as seeking and then
+/// resuming, without handling anything else in between.
+pub fn post_messages(messages: &[FrontendMessage]) {
+    let value = serde_json::to_string(messages).expect("serializable");
+    ffi_post_message(&value)
+}
+
+/// Saves the UI preferences so that the layout is restored the next time the UI opens.
+pub async fn save_ui_prefs(prefs: UiPrefs) {
+    let request = match Request::put("/ipc/ui-prefs").json(&prefs) {
+        Ok(request) => request,
+        Err(err) => {
+            error!("failed to serialize UI preferences: {err}");
+            return;
+        }
+    };
+    match request.send().await {
+        Ok(response) if !response.ok() => {
+            error!(
+                "failed to save UI preferences: status {}",
+                response.status()
+            )
+        }
+        Ok(_) => {}
+        Err(err) => error!("failed to save UI preferences: {err}"),
+    }
+}
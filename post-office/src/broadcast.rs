@@ -19,6 +19,10 @@ use std::sync::mpsc::{self, Receiver, Sender};
 use std::sync::{Arc, Mutex};
 use std::time::Duration;
 
+/// Recording broadcast messages to a file and replaying them later.
+#[cfg(feature = "record")]
+pub mod record;
+
 /// Broadcast channel for filtering subscriptions.
 pub trait Channel: Copy + Clone + Debug {
     /// True if broadcast should occur on the given channel.
@@ -113,8 +117,13 @@ struct Subscriber<M: BroadcastMessage> {
     sender: Sender<M>,
 }
 
+type Observer<M> = Box<dyn Fn(&M) + Send>;
+
 struct Inner<M: BroadcastMessage> {
     subscriptions: Mutex<Vec<Subscriber<M>>>,
+    observers: Mutex<Vec<Observer<M>>>,
+    /// Held while broadcasting so that a batch of messages isn't interleaved with others.
+    batch: Mutex<()>,
     next_id: AtomicUsize,
 }
 
@@ -154,6 +163,8 @@ impl<M: BroadcastMessage> Broadcaster<M> {
         Self {
             inner: Arc::new(Inner {
                 subscriptions: Mutex::new(Vec::new()),
+                observers: Mutex::new(Vec::new()),
+                batch: Mutex::new(()),
                 next_id: AtomicUsize::new(0),
             }),
         }
@@ -182,6 +193,19 @@ impl<M: BroadcastMessage> Broadcaster<M> {
         }
     }
 
+    /// Observe every message broadcast, regardless of channel or sender.
+    ///
+    /// Observers are called on the broadcasting thread before the message is delivered
+    /// to any subscribers, so they see messages in the same order that subscribers do.
+    /// Observers must not broadcast on this broadcaster, or they will deadlock.
+    pub fn observe(&self, observer: impl Fn(&M) + Send + 'static) {
+        self.inner
+            .observers
+            .lock()
+            .unwrap()
+            .push(Box::new(observer));
+    }
+
     /// Unsubscribe from the broadcaster.
     pub fn unsubscribe(&self, subscription: &BroadcastSubscription<M>) {
         self.unsubscribe_id(subscription.id);
@@ -196,6 +220,15 @@ impl<M: BroadcastMessage> Broadcaster<M> {
     }
 
     fn do_broadcast(&self, exclude_id: Option<SubscriberId>, message: M) {
+        let _batch = self.inner.batch.lock().unwrap();
+        self.deliver(exclude_id, message);
+    }
+
+    fn deliver(&self, exclude_id: Option<SubscriberId>, message: M) {
+        for observer in self.inner.observers.lock().unwrap().iter() {
+            observer(&message);
+        }
+
         let channel = message.channel();
         let mut n = 0;
         let dead_subscriber = {
@@ -245,6 +278,15 @@ impl<M: BroadcastMessage> Broadcaster<M> {
     pub fn broadcast(&self, message: M) {
         self.do_broadcast(None, message);
     }
+
+    /// Broadcast several messages to all the subscribers, in order, without any other
+    /// messages in between them.
+    pub fn broadcast_all(&self, messages: impl IntoIterator<Item = M>) {
+        let _batch = self.inner.batch.lock().unwrap();
+        for message in messages {
+            self.deliver(None, message);
+        }
+    }
 }
 
 #[cfg(test)]
@@ -364,6 +406,34 @@ mod tests {
         assert!(dbg!(sub3.try_recv()).is_none());
     }
 
+    #[test]
+    #[ntest::timeout(5000)]
+    fn batches_arent_interleaved() {
+        let broadcaster = Broadcaster::<TestMessage>::new();
+        let sub = broadcaster.subscribe("one", TestChannel::All);
+
+        let other = std::thread::spawn({
+            let broadcaster = broadcaster.clone();
+            move || {
+                for _ in 0..1000 {
+                    broadcaster.broadcast(TestMessage::C);
+                }
+            }
+        });
+        for _ in 0..1000 {
+            broadcaster.broadcast_all([TestMessage::A, TestMessage::B]);
+        }
+        other.join().unwrap();
+
+        let received: Vec<_> = std::iter::from_fn(|| sub.try_recv()).collect();
+        assert_eq!(3000, received.len());
+        for (index, message) in received.iter().enumerate() {
+            if *message == TestMessage::A {
+                assert_eq!(TestMessage::B, received[index + 1]);
+            }
+        }
+    }
+
     #[test]
     #[ntest::timeout(500)]
     fn subscriber_broadcasts_dont_circle_back() {
@@ -387,4 +457,25 @@ mod tests {
         assert_eq!(TestMessage::C, sub3.recv().unwrap());
         assert!(dbg!(sub3.try_recv()).is_none());
     }
+
+    #[test]
+    #[ntest::timeout(500)]
+    fn observers_see_every_message() {
+        let broadcaster = Broadcaster::<TestMessage>::new();
+        let observed = Arc::new(Mutex::new(Vec::new()));
+        broadcaster.observe({
+            let observed = observed.clone();
+            move |message| observed.lock().unwrap().push(*message)
+        });
+
+        let sub1 = broadcaster.subscribe("one", TestChannel::A);
+        broadcaster.broadcast(TestMessage::A);
+        broadcaster.broadcast(TestMessage::B);
+        sub1.broadcast(TestMessage::C);
+
+        assert_eq!(
+            vec![TestMessage::A, TestMessage::B, TestMessage::C],
+            *observed.lock().unwrap()
+        );
+    }
 }
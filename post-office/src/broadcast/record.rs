@@ -0,0 +1,351 @@
+// This file is part of Millenium Player.
+// Copyright (C) 2023 John DiSanti.
+//
+// Millenium Player is free software: you can redistribute it and/or modify it under the terms of
+// the GNU General Public License as published by the Free Software Foundation, either version 3 of
+// the License, or (at your option) any later version.
+//
+// Millenium Player is distributed in the hope that it will be useful, but WITHOUT ANY WARRANTY;
+// without even the implied warranty of MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE. See
+// the GNU General Public License for more details.
+//
+// You should have received a copy of the GNU General Public License along with Millenium Player.
+// If not, see <https://www.gnu.org/licenses/>.
+
+use crate::broadcast::{BroadcastMessage, Broadcaster};
+use serde::{de::DeserializeOwned, Deserialize, Serialize};
+use std::{
+    collections::HashMap,
+    fmt,
+    fs::File,
+    io::{self, BufRead, BufReader, BufWriter, Write},
+    path::Path,
+    sync::{Arc, Mutex},
+    thread,
+    time::{Duration, Instant},
+};
+
+/// A single recorded message. Recordings are stored as one of these per line in JSON.
+#[derive(Debug, Serialize, Deserialize)]
+struct RecordedMessage {
+    /// Time since the recording started.
+    elapsed: Duration,
+    /// Name of the broadcaster the message was sent on.
+    source: String,
+    message: serde_json::Value,
+}
+
+struct RecorderInner {
+    writer: Box<dyn Write + Send>,
+    started: Instant,
+}
+
+/// Records every message sent on one or more broadcasters, along with when it was sent.
+///
+/// Messages from all attached broadcasters are written in the order they were broadcast,
+/// so that sequencing issues between threads can be replayed later with [`MessageReplayer`].
+/// Messages that fail to serialize (for example, ones that carry non-serializable data)
+/// are left out of the recording.
+#[derive(Clone)]
+pub struct MessageRecorder {
+    inner: Arc<Mutex<RecorderInner>>,
+}
+
+impl MessageRecorder {
+    /// Creates a recorder that writes to the given writer.
+    pub fn new(writer: impl Write + Send + 'static) -> Self {
+        Self {
+            inner: Arc::new(Mutex::new(RecorderInner {
+                writer: Box::new(writer),
+                started: Instant::now(),
+            })),
+        }
+    }
+
+    /// Creates a recorder that writes to a new file at the given path.
+    pub fn create(path: impl AsRef<Path>) -> io::Result<Self> {
+        Ok(Self::new(BufWriter::new(File::create(path)?)))
+    }
+
+    /// Records all messages sent on the given broadcaster under the given source name.
+    pub fn attach<M>(&self, source: &'static str, broadcaster: &Broadcaster<M>)
+    where
+        M: BroadcastMessage + Serialize + 'static,
+    {
+        let recorder = self.clone();
+        broadcaster.observe(move |message| recorder.record(source, message));
+    }
+
+    fn record<M: BroadcastMessage + Serialize>(&self, source: &str, message: &M) {
+        let message = match serde_json::to_value(message) {
+            Ok(message) => message,
+            Err(err) => {
+                log::debug!("not recording message {message:?}: {err}");
+                return;
+            }
+        };
+        let mut inner = self.inner.lock().unwrap();
+        let recorded = RecordedMessage {
+            elapsed: inner.started.elapsed(),
+            source: source.into(),
+            message,
+        };
+        let line = serde_json::to_string(&recorded).expect("JSON values are serializable");
+        // Flush every message so that the recording is complete even if the app crashes
+        let result = writeln!(inner.writer, "{line}").and_then(|_| inner.writer.flush());
+        if let Err(err) = result {
+            log::error!("failed to write message recording: {err}");
+        }
+    }
+}
+
+/// Error returned when a message recording can't be read.
+#[derive(Debug)]
+pub enum ReplayError {
+    Io(io::Error),
+    InvalidLine {
+        line_number: usize,
+        source: serde_json::Error,
+    },
+}
+
+impl fmt::Display for ReplayError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Io(err) => write!(f, "failed to read message recording: {err}"),
+            Self::InvalidLine { line_number, .. } => {
+                write!(f, "invalid message recording on line {line_number}")
+            }
+        }
+    }
+}
+
+impl std::error::Error for ReplayError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            Self::Io(err) => Some(err),
+            Self::InvalidLine { source, .. } => Some(source),
+        }
+    }
+}
+
+type ReplayTarget = Box<dyn Fn(serde_json::Value) -> Result<(), serde_json::Error>>;
+
+/// Feeds the messages in a recording made by [`MessageRecorder`] back into broadcasters.
+///
+/// Messages are replayed in their original order. Stepping through them one at a time with
+/// [`MessageReplayer::step`] makes it possible to drive the code under investigation
+/// (such as calling `PlaylistManager::update`) in between messages, which makes the replay
+/// deterministic.
+pub struct MessageReplayer {
+    messages: Vec<RecordedMessage>,
+    next: usize,
+    targets: HashMap<String, ReplayTarget>,
+}
+
+impl MessageReplayer {
+    /// Reads a recording from the given reader.
+    pub fn from_reader(reader: impl BufRead) -> Result<Self, ReplayError> {
+        let mut messages = Vec::new();
+        for (index, line) in reader.lines().enumerate() {
+            let line = line.map_err(ReplayError::Io)?;
+            if line.trim().is_empty() {
+                continue;
+            }
+            let message =
+                serde_json::from_str(&line).map_err(|source| ReplayError::InvalidLine {
+                    line_number: index + 1,
+                    source,
+                })?;
+            messages.push(message);
+        }
+        Ok(Self {
+            messages,
+            next: 0,
+            targets: HashMap::new(),
+        })
+    }
+
+    /// Reads the recording at the given path.
+    pub fn open(path: impl AsRef<Path>) -> Result<Self, ReplayError> {
+        Self::from_reader(BufReader::new(File::open(path).map_err(ReplayError::Io)?))
+    }
+
+    /// Replays messages recorded under the given source name onto the given broadcaster.
+    ///
+    /// Messages from sources without a target are skipped.
+    pub fn target<M>(mut self, source: &str, broadcaster: Broadcaster<M>) -> Self
+    where
+        M: BroadcastMessage + DeserializeOwned + 'static,
+    {
+        self.targets.insert(
+            source.into(),
+            Box::new(move |value| {
+                broadcaster.broadcast(serde_json::from_value::<M>(value)?);
+                Ok(())
+            }),
+        );
+        self
+    }
+
+    /// Number of messages that haven't been replayed yet.
+    pub fn remaining(&self) -> usize {
+        self.messages.len() - self.next
+    }
+
+    /// Time since the start of the recording that the next message was sent at.
+    pub fn next_elapsed(&self) -> Option<Duration> {
+        self.messages.get(self.next).map(|message| message.elapsed)
+    }
+
+    /// Broadcasts the next recorded message. Returns false once there are no more messages.
+    pub fn step(&mut self) -> bool {
+        let Some(recorded) = self.messages.get(self.next) else {
+            return false;
+        };
+        self.next += 1;
+        match self.targets.get(&recorded.source) {
+            Some(target) => {
+                if let Err(err) = target(recorded.message.clone()) {
+                    log::warn!(
+                        "failed to replay message from \"{}\": {err}",
+                        recorded.source
+                    );
+                }
+            }
+            None => log::debug!("skipping message from \"{}\"", recorded.source),
+        }
+        true
+    }
+
+    /// Broadcasts all remaining messages as fast as possible.
+    pub fn replay_all(&mut self) {
+        while self.step() {}
+    }
+
+    /// Broadcasts all remaining messages with the same timing they were recorded with.
+    pub fn replay_realtime(&mut self) {
+        let started = Instant::now();
+        let offset = self.next_elapsed().unwrap_or_default();
+        while let Some(elapsed) = self.next_elapsed() {
+            let due = elapsed.saturating_sub(offset);
+            if let Some(wait) = due.checked_sub(started.elapsed()) {
+                thread::sleep(wait);
+            }
+            self.step();
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::broadcast::NoChannels;
+
+    #[derive(Clone, Debug, Eq, PartialEq, Serialize, Deserialize)]
+    enum TestMessage {
+        Play,
+        Seek(u64),
+        #[serde(skip)]
+        NotRecordable,
+    }
+
+    impl BroadcastMessage for TestMessage {
+        type Channel = NoChannels;
+
+        fn channel(&self) -> Self::Channel {
+            NoChannels
+        }
+
+        fn frequent(&self) -> bool {
+            false
+        }
+    }
+
+    #[derive(Clone, Default)]
+    struct SharedBuffer(Arc<Mutex<Vec<u8>>>);
+
+    impl Write for SharedBuffer {
+        fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+            self.0.lock().unwrap().write(buf)
+        }
+
+        fn flush(&mut self) -> io::Result<()> {
+            Ok(())
+        }
+    }
+
+    #[test]
+    #[ntest::timeout(500)]
+    fn record_and_replay() {
+        let buffer = SharedBuffer::default();
+        let recorder = MessageRecorder::new(buffer.clone());
+        let (one, two) = (Broadcaster::new(), Broadcaster::new());
+        recorder.attach("one", &one);
+        recorder.attach("two", &two);
+
+        one.broadcast(TestMessage::Play);
+        two.broadcast(TestMessage::NotRecordable);
+        two.broadcast(TestMessage::Seek(5));
+        one.broadcast(TestMessage::Seek(10));
+
+        let recording = buffer.0.lock().unwrap().clone();
+        assert_eq!(3, recording.split(|&b| b == b'\n').count() - 1);
+
+        let (replay_one, replay_two) = (Broadcaster::new(), Broadcaster::new());
+        let (sub_one, sub_two) = (
+            replay_one.subscribe("test", NoChannels),
+            replay_two.subscribe("test", NoChannels),
+        );
+        let mut replayer = MessageReplayer::from_reader(&recording[..])
+            .unwrap()
+            .target("one", replay_one)
+            .target("two", replay_two);
+        assert_eq!(3, replayer.remaining());
+
+        assert!(replayer.step());
+        assert_eq!(Some(TestMessage::Play), sub_one.try_recv());
+        assert_eq!(None, sub_two.try_recv());
+
+        assert!(replayer.step());
+        assert_eq!(None, sub_one.try_recv());
+        assert_eq!(Some(TestMessage::Seek(5)), sub_two.try_recv());
+
+        replayer.replay_all();
+        assert_eq!(Some(TestMessage::Seek(10)), sub_one.try_recv());
+        assert_eq!(None, sub_two.try_recv());
+        assert!(!replayer.step());
+    }
+
+    #[test]
+    fn skip_sources_without_targets() {
+        let buffer = SharedBuffer::default();
+        let recorder = MessageRecorder::new(buffer.clone());
+        let (one, two) = (Broadcaster::new(), Broadcaster::new());
+        recorder.attach("one", &one);
+        recorder.attach("two", &two);
+        one.broadcast(TestMessage::Play);
+        two.broadcast(TestMessage::Seek(5));
+
+        let recording = buffer.0.lock().unwrap().clone();
+        let replay_two = Broadcaster::new();
+        let sub_two = replay_two.subscribe("test", NoChannels);
+        MessageReplayer::from_reader(&recording[..])
+            .unwrap()
+            .target("two", replay_two)
+            .replay_all();
+        assert_eq!(Some(TestMessage::Seek(5)), sub_two.try_recv());
+        assert_eq!(None, sub_two.try_recv());
+    }
+
+    #[test]
+    fn invalid_recording() {
+        let err = MessageReplayer::from_reader(&b"\n{\"elapsed\":"[..])
+            .err()
+            .expect("should fail");
+        assert!(matches!(
+            err,
+            ReplayError::InvalidLine { line_number: 2, .. }
+        ));
+    }
+}
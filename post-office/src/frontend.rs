@@ -13,4 +13,5 @@
 // If not, see <https://www.gnu.org/licenses/>.
 
 pub mod message;
+pub mod settings;
 pub mod state;
@@ -12,7 +12,13 @@
 // You should have received a copy of the GNU General Public License along with Millenium Player.
 // If not, see <https://www.gnu.org/licenses/>.
 
-use crate::types::Volume;
+use crate::{
+    frontend::settings::Settings,
+    types::{
+        Favorite, PlaybackError, PlayedSong, PlaylistEntryDetails, QueuedTrack, StreamQuality,
+        TestSignal, TrackRating, Volume,
+    },
+};
 use std::{borrow::Cow, time::Duration};
 
 #[derive(Clone, Debug)]
@@ -24,7 +30,25 @@ use std::{borrow::Cow, time::Duration};
     serde(tag = "kind")
 )]
 pub enum FrontendMessage {
+    /// Pin the locations that were opened most recently to the favorites.
+    AddFavorite,
+    /// Remove every track from the play queue.
+    ClearPlayQueue,
     DragWindowStart,
+    /// Tracks that weren't queued because they're already in the playlist.
+    DuplicatesSkipped {
+        duplicates: Vec<QueuedTrack>,
+    },
+    /// The favorites were changed.
+    FavoritesChanged {
+        favorites: Vec<Favorite>,
+    },
+    /// The intro of the current track was automatically skipped up to the given position.
+    IntroSkipped {
+        position: Duration,
+    },
+    /// Ask for the audio output devices, which are sent back in `OutputDevicesListed`.
+    ListOutputDevices,
     LoadLocations {
         locations: Vec<String>,
     },
@@ -33,15 +57,53 @@ pub enum FrontendMessage {
         message: String,
     },
     MediaControlBack,
+    /// Stop repeating the marked region of the current track.
+    MediaControlClearLoop,
+    /// Open the cue output on the named device, or the default device if no name is given.
+    MediaControlCueDevice {
+        name: Option<String>,
+    },
+    /// Pre-listen to the next track in the playlist on the cue output.
+    MediaControlCueNext,
+    MediaControlCueStop,
+    /// Lower the volume to a fraction of itself while other audio plays, such as a call,
+    /// or restore it if `None`.
+    MediaControlDuck {
+        level: Option<Volume>,
+    },
+    MediaControlCueVolume {
+        volume: Volume,
+    },
+    /// Bypass all audio processing to compare the processed and unprocessed audio.
+    MediaControlDspBypass {
+        bypass: bool,
+    },
     MediaControlForward,
+    /// Mark the current position as the start of the region to repeat.
+    MediaControlMarkLoopA,
+    /// Mark the current position as the end of the region to repeat.
+    MediaControlMarkLoopB,
+    /// Catch a live stream that was paused or rewound back up to what's being broadcast.
+    MediaControlJumpToLive,
+    /// Play the next chapter of the current file, if it's split into chapters.
+    MediaControlNextChapter,
     MediaControlPause,
     MediaControlPlay,
+    /// Go back to the start of the current chapter, or to the previous chapter if it only
+    /// just started.
+    MediaControlPreviousChapter,
     MediaControlSeek {
         position: Duration,
     },
+    /// Seek forward from the current position, or back if negative.
+    MediaControlSeekBy {
+        seconds: i32,
+    },
     MediaControlSkipBack,
     MediaControlSkipForward,
     MediaControlStop,
+    /// Undo the automatic intro skip by seeking back to the start of the track.
+    MediaControlUndoIntroSkip,
     MediaControlPlaylistMode {
         mode: PlaylistMode,
     },
@@ -49,13 +111,167 @@ pub enum FrontendMessage {
     MediaControlVolume {
         volume: Volume,
     },
+    /// Open the folder containing the log files.
+    OpenLogFolder,
+    /// Pop the visualizer out into its own window, or bring that window to the front.
+    OpenVisualizerWindow,
+    /// The audio output devices, along with the one chosen in the config file.
+    /// `None` means the system default.
+    OutputDevicesListed {
+        devices: Vec<String>,
+        selected: Option<String>,
+    },
+    /// Party mode was turned on or off.
+    PartyModeChanged {
+        active: bool,
+    },
+    /// Play a test signal right away, and carry on with the playlist after it.
+    PlayTestSignal {
+        signal: TestSignal,
+    },
+    /// Move the playlist entry at index `from` so that it ends up at index `to`.
+    MovePlaylistEntry {
+        from: usize,
+        to: usize,
+    },
+    /// Text was pasted into the window. Any paths and URLs in it are queued.
+    PasteLocations {
+        text: String,
+    },
+    /// Play the playlist entry with the given ID right away.
+    PlayPlaylistEntry {
+        id: usize,
+    },
+    /// The playlist's entries or current entry changed.
+    PlaylistStateUpdated,
+    /// Tags and lengths were found for some playlist entries. Entries are probed in the
+    /// background after loading, so this is sent a few at a time.
+    PlaylistEntriesUpdated {
+        entries: Vec<PlaylistEntryDetails>,
+    },
+    /// The playlist mode changed. The shuffle seed is set while shuffling, so that the
+    /// order can be reproduced.
+    PlaylistModeChanged {
+        mode: PlaylistMode,
+        shuffle_seed: Option<u64>,
+    },
+    /// The play queue changed. The tracks are in the order they'll be played.
+    PlayQueueChanged {
+        queue: Vec<QueuedTrack>,
+    },
+    /// Play the first few seconds of a playlist entry quietly over what's playing, without
+    /// changing the current entry.
+    PreviewPlaylistEntry {
+        id: usize,
+    },
+    /// Play the given locations after the current track, ahead of the rest of the playlist.
+    QueueLocations {
+        locations: Vec<String>,
+        /// Queue tracks even if they're already in the playlist, rather than skipping them.
+        allow_duplicates: bool,
+    },
+    /// Queue the tracks from the last `DuplicatesSkipped` anyway.
+    QueueSkippedDuplicates,
     Quit,
+    /// Love or ban the current track, or clear its rating with `None`.
+    RateCurrentTrack {
+        rating: Option<TrackRating>,
+    },
+    /// Start a new player thread after the previous one exited.
+    RestartPlayer,
+    /// Unpin the favorite with the given location.
+    RemoveFavorite {
+        location: String,
+    },
+    /// Remove the playlist entries with the given IDs. If the current entry is removed,
+    /// the entry that takes its place starts playing.
+    RemovePlaylistEntries {
+        ids: Vec<usize>,
+    },
+    /// Set the global intro skip applied to every track. `None` disables it.
+    SetIntroSkip {
+        skip: Option<Duration>,
+    },
+    /// Override the intro skip for a single playlist entry. `None` uses the global setting.
+    SetPlaylistEntryIntroSkip {
+        id: usize,
+        skip: Option<Duration>,
+    },
+    /// Ask where to save the playlist, and save it there.
+    SavePlaylist,
+    /// Choose the quality of the current stream. `None` chooses it automatically.
+    SelectStreamVariant {
+        variant: Option<usize>,
+    },
+    /// Mark a playlist entry as skipped (or not) without removing it from the playlist.
+    SetPlaylistEntrySkipped {
+        id: usize,
+        skipped: bool,
+    },
+    /// Override the track transition for the current playlist. `None` uses the global setting.
+    SetPlaylistTrackTransition {
+        transition: Option<TrackTransition>,
+    },
+    /// Sort the playlist by the given tag.
+    SortPlaylist {
+        by: PlaylistSortKey,
+    },
+    /// Choose the audio output device, which takes effect after a restart.
+    /// `None` uses the system default.
+    SetOutputDevice {
+        name: Option<String>,
+    },
+    /// Set the global transition used between tracks.
+    SetTrackTransition {
+        transition: TrackTransition,
+    },
+    /// The settings were changed, such as by editing the config file while running.
+    SettingsChanged {
+        settings: Settings,
+    },
     ShowAlert {
         level: AlertLevel,
         message: Cow<'static, str>,
     },
+    /// Show the playlist if it's hidden, or hide it if it's shown.
+    ShowHidePlaylist,
+    /// The songs the current station has played changed, such as when a new song started
+    /// or a different station was tuned in. The songs are oldest first.
+    SongHistoryChanged {
+        songs: Vec<PlayedSong>,
+    },
+    /// Turn on party mode, which only allows queueing tracks to play next. Leaving it requires
+    /// the passphrase, if one is given.
+    StartPartyMode {
+        passphrase: Option<String>,
+    },
+    /// Turn off party mode if the passphrase matches the one it was started with.
+    StopPartyMode {
+        passphrase: Option<String>,
+    },
+    /// Stop the preview started by `PreviewPlaylistEntry`.
+    StopPreview,
+    /// The qualities offered by the current stream changed. `None` if it has no choice of quality.
+    StreamQualityChanged {
+        quality: Option<StreamQuality>,
+    },
+    /// The rating of the current track changed, either because it was rated or because a
+    /// different track started.
+    TrackRatingChanged {
+        rating: Option<TrackRating>,
+    },
+    /// Entries that couldn't be played were skipped, and playback reached the end of the playlist.
+    UnplayableEntriesSkipped {
+        errors: Vec<PlaybackError>,
+    },
+    /// Change the settings from the settings panel, and save them to the config file.
+    UpdateSettings {
+        settings: Settings,
+    },
     PlaybackStateUpdated,
     WaveformStateUpdated,
+    /// The instance health changed, and can be fetched from `/ipc/health`.
+    HealthUpdated,
 }
 
 #[cfg(feature = "broadcast")]
@@ -80,6 +296,37 @@ pub enum PlaylistMode {
     RepeatOne,
     RepeatAll,
     Shuffle,
+    /// Shuffle the order of the albums, but play the tracks of each album in order.
+    ShuffleAlbums,
+}
+
+/// Tag that the playlist can be sorted by.
+#[derive(Copy, Clone, Debug, Eq, PartialEq)]
+#[cfg_attr(feature = "serialize", derive(serde::Serialize))]
+#[cfg_attr(feature = "deserialize", derive(serde::Deserialize))]
+pub enum PlaylistSortKey {
+    /// Artist, then album, then track number.
+    Artist,
+    /// Album, then track number.
+    Album,
+    Title,
+}
+
+/// What happens between the end of one track and the start of the next.
+#[derive(Copy, Clone, Debug, Default, Eq, PartialEq)]
+#[cfg_attr(feature = "serialize", derive(serde::Serialize))]
+#[cfg_attr(feature = "deserialize", derive(serde::Deserialize))]
+pub enum TrackTransition {
+    /// Start the next track as soon as the current one finishes.
+    #[default]
+    Immediate,
+    /// Insert a fixed amount of silence between tracks.
+    Gap(Duration),
+    /// Crossfade the end of each track into the start of the next over this long.
+    ///
+    /// Tracks that can't be decoded ahead, such as ones with an intro skip, start right
+    /// after the current one instead.
+    Overlap(Duration),
 }
 
 #[derive(Copy, Clone, Debug)]
@@ -0,0 +1,283 @@
+// This file is part of Millenium Player.
+// Copyright (C) 2023 John DiSanti.
+//
+// Millenium Player is free software: you can redistribute it and/or modify it under the terms of
+// the GNU General Public License as published by the Free Software Foundation, either version 3 of
+// the License, or (at your option) any later version.
+//
+// Millenium Player is distributed in the hope that it will be useful, but WITHOUT ANY WARRANTY;
+// without even the implied warranty of MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE. See
+// the GNU General Public License for more details.
+//
+// You should have received a copy of the GNU General Public License along with Millenium Player.
+// If not, see <https://www.gnu.org/licenses/>.
+
+use crate::{frontend::message::TrackTransition, types::NormalizationMode};
+use std::{collections::BTreeMap, time::Duration};
+
+/// User settings that can be changed while the player is running.
+#[derive(Clone, Debug, PartialEq)]
+#[cfg_attr(feature = "serialize", derive(serde::Serialize))]
+#[cfg_attr(feature = "deserialize", derive(serde::Deserialize))]
+#[cfg_attr(any(feature = "serialize", feature = "deserialize"), serde(default))]
+pub struct Settings {
+    pub theme: Theme,
+    /// How much larger (or smaller) to draw the whole UI, where 1.0 is the normal size.
+    #[cfg_attr(
+        any(feature = "serialize", feature = "deserialize"),
+        serde(rename = "ui-scale")
+    )]
+    pub ui_scale: f32,
+    /// Locale that durations, times, and counts are formatted for, such as "de-DE".
+    /// The system's locale is used if not set.
+    pub locale: Option<String>,
+    pub visualizer: Visualizer,
+    /// Maps key names (as given by `KeyboardEvent.key` in the frontend) to actions.
+    pub keybindings: BTreeMap<String, KeyAction>,
+    pub scrobbling: Scrobbling,
+    pub normalization: NormalizationMode,
+    /// Level in dBFS that the loudest peak in the playlist is brought to
+    /// when normalizing by the playlist's peak.
+    #[cfg_attr(
+        any(feature = "serialize", feature = "deserialize"),
+        serde(rename = "peak-target-db")
+    )]
+    pub peak_target_db: f32,
+    /// How many seconds the end of each track is crossfaded into the start of the next.
+    /// Takes priority over the gap if not zero.
+    #[cfg_attr(
+        any(feature = "serialize", feature = "deserialize"),
+        serde(rename = "crossfade-seconds")
+    )]
+    pub crossfade_seconds: f32,
+    /// How many seconds of silence to insert between tracks.
+    /// Tracks start right after each other if zero.
+    #[cfg_attr(
+        any(feature = "serialize", feature = "deserialize"),
+        serde(rename = "gap-seconds")
+    )]
+    pub gap_seconds: f32,
+    pub sorting: Sorting,
+    /// Keep the computer from suspending while audio is playing.
+    #[cfg_attr(
+        any(feature = "serialize", feature = "deserialize"),
+        serde(rename = "prevent-sleep")
+    )]
+    pub prevent_sleep: bool,
+    /// Listen for the keyboard's media keys even when the window isn't focused.
+    #[cfg_attr(
+        any(feature = "serialize", feature = "deserialize"),
+        serde(rename = "media-keys")
+    )]
+    pub media_keys: bool,
+    /// Seed for shuffling, so that the same playlist is always shuffled the same way.
+    /// A new seed is picked each time shuffling starts if not set.
+    #[cfg_attr(
+        any(feature = "serialize", feature = "deserialize"),
+        serde(rename = "shuffle-seed")
+    )]
+    pub shuffle_seed: Option<u64>,
+    #[cfg_attr(
+        any(feature = "serialize", feature = "deserialize"),
+        serde(rename = "seek-buttons")
+    )]
+    pub seek_buttons: SeekButtons,
+}
+
+impl Default for Settings {
+    fn default() -> Self {
+        Self {
+            theme: Theme::default(),
+            ui_scale: 1.0,
+            locale: None,
+            visualizer: Visualizer::default(),
+            keybindings: [
+                (" ", KeyAction::PlayPause),
+                ("s", KeyAction::Stop),
+                ("ArrowLeft", KeyAction::Back),
+                ("ArrowRight", KeyAction::Forward),
+                ("PageUp", KeyAction::SkipBack),
+                ("PageDown", KeyAction::SkipForward),
+                ("[", KeyAction::PreviousChapter),
+                ("]", KeyAction::NextChapter),
+                ("b", KeyAction::ToggleDspBypass),
+            ]
+            .into_iter()
+            .map(|(key, action)| (key.to_string(), action))
+            .collect(),
+            scrobbling: Scrobbling::default(),
+            normalization: NormalizationMode::default(),
+            peak_target_db: -1.0,
+            crossfade_seconds: 0.0,
+            gap_seconds: 0.0,
+            sorting: Sorting::default(),
+            prevent_sleep: true,
+            media_keys: true,
+            shuffle_seed: None,
+            seek_buttons: SeekButtons::default(),
+        }
+    }
+}
+
+/// Smallest UI scale that can be set.
+pub const MIN_UI_SCALE: f32 = 0.75;
+/// Largest UI scale that can be set.
+pub const MAX_UI_SCALE: f32 = 2.0;
+
+impl Settings {
+    /// Target for the playlist's peak, if normalizing by it.
+    pub fn peak_normalization(&self) -> Option<f32> {
+        (self.normalization == NormalizationMode::PlaylistPeak).then_some(self.peak_target_db)
+    }
+
+    /// UI scale, limited to a usable range.
+    pub fn clamped_ui_scale(&self) -> f32 {
+        if self.ui_scale.is_finite() {
+            self.ui_scale.clamp(MIN_UI_SCALE, MAX_UI_SCALE)
+        } else {
+            1.0
+        }
+    }
+
+    /// Transition between tracks for the crossfade and gap settings.
+    pub fn track_transition(&self) -> TrackTransition {
+        let seconds = |seconds| Duration::try_from_secs_f32(seconds).unwrap_or_default();
+        let (crossfade, gap) = (seconds(self.crossfade_seconds), seconds(self.gap_seconds));
+        if !crossfade.is_zero() {
+            TrackTransition::Overlap(crossfade)
+        } else if !gap.is_zero() {
+            TrackTransition::Gap(gap)
+        } else {
+            TrackTransition::Immediate
+        }
+    }
+}
+
+#[derive(Copy, Clone, Debug, Default, Eq, PartialEq)]
+#[cfg_attr(feature = "serialize", derive(serde::Serialize))]
+#[cfg_attr(feature = "deserialize", derive(serde::Deserialize))]
+#[cfg_attr(
+    any(feature = "serialize", feature = "deserialize"),
+    serde(rename_all = "kebab-case")
+)]
+pub enum Theme {
+    #[default]
+    Default,
+    /// White and yellow on black with heavier outlines, for low vision.
+    HighContrast,
+}
+
+impl Theme {
+    /// CSS class that applies the theme in the frontend.
+    pub fn css_class(&self) -> &'static str {
+        match self {
+            Self::Default => "theme-default",
+            Self::HighContrast => "theme-high-contrast",
+        }
+    }
+}
+
+#[derive(Copy, Clone, Debug, Default, Eq, PartialEq)]
+#[cfg_attr(feature = "serialize", derive(serde::Serialize))]
+#[cfg_attr(feature = "deserialize", derive(serde::Deserialize))]
+#[cfg_attr(
+    any(feature = "serialize", feature = "deserialize"),
+    serde(rename_all = "kebab-case")
+)]
+pub enum Visualizer {
+    /// Spectrum and amplitude bars behind the player.
+    #[default]
+    Waveform,
+    Off,
+}
+
+/// Media control actions that can be bound to keys.
+#[derive(Copy, Clone, Debug, Eq, PartialEq)]
+#[cfg_attr(feature = "serialize", derive(serde::Serialize))]
+#[cfg_attr(feature = "deserialize", derive(serde::Deserialize))]
+#[cfg_attr(
+    any(feature = "serialize", feature = "deserialize"),
+    serde(rename_all = "kebab-case")
+)]
+pub enum KeyAction {
+    PlayPause,
+    Stop,
+    Back,
+    Forward,
+    SkipBack,
+    SkipForward,
+    PreviousChapter,
+    NextChapter,
+    /// Switch between the processed and unprocessed audio.
+    ToggleDspBypass,
+}
+
+/// Which scrobbling services played tracks are submitted to.
+#[derive(Copy, Clone, Debug, Default, Eq, PartialEq)]
+#[cfg_attr(feature = "serialize", derive(serde::Serialize))]
+#[cfg_attr(feature = "deserialize", derive(serde::Deserialize))]
+#[cfg_attr(
+    any(feature = "serialize", feature = "deserialize"),
+    serde(default, rename_all = "kebab-case")
+)]
+pub struct Scrobbling {
+    pub last_fm: bool,
+    pub listen_brainz: bool,
+}
+
+/// Buttons that seek by a number of seconds, which are shown for long-form content such as
+/// podcasts and audiobooks where skipping to the next track isn't much use.
+#[derive(Copy, Clone, Debug, Eq, PartialEq)]
+#[cfg_attr(feature = "serialize", derive(serde::Serialize))]
+#[cfg_attr(feature = "deserialize", derive(serde::Deserialize))]
+#[cfg_attr(
+    any(feature = "serialize", feature = "deserialize"),
+    serde(default, rename_all = "kebab-case")
+)]
+pub struct SeekButtons {
+    pub back_seconds: u32,
+    pub forward_seconds: u32,
+    /// Tracks at least this long are long-form content.
+    pub long_form_minutes: u32,
+}
+
+impl Default for SeekButtons {
+    fn default() -> Self {
+        Self {
+            back_seconds: 15,
+            forward_seconds: 30,
+            long_form_minutes: 20,
+        }
+    }
+}
+
+impl SeekButtons {
+    /// True if a track of the given length should show the seek buttons.
+    pub fn is_long_form(&self, length: Duration) -> bool {
+        length >= Duration::from_secs(self.long_form_minutes as u64 * 60)
+    }
+}
+
+/// How artist, album, and title names are compared when sorting.
+#[derive(Clone, Debug, Eq, PartialEq)]
+#[cfg_attr(feature = "serialize", derive(serde::Serialize))]
+#[cfg_attr(feature = "deserialize", derive(serde::Deserialize))]
+#[cfg_attr(
+    any(feature = "serialize", feature = "deserialize"),
+    serde(default, rename_all = "kebab-case")
+)]
+pub struct Sorting {
+    /// Sort "The Beatles" under "B". Sort tags (such as TSOP) take precedence over this.
+    pub ignore_articles: bool,
+    /// Language whose leading articles are ignored, such as "en" or "fr".
+    pub locale: String,
+}
+
+impl Default for Sorting {
+    fn default() -> Self {
+        Self {
+            ignore_articles: true,
+            locale: "en".into(),
+        }
+    }
+}
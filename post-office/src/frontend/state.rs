@@ -12,8 +12,11 @@
 // You should have received a copy of the GNU General Public License along with Millenium Player.
 // If not, see <https://www.gnu.org/licenses/>.
 
-use crate::types::Volume;
-use std::time::Duration;
+use crate::types::{
+    AbLoop, BufferStats, InstanceHealth, PlaybackError, PlayedSong, PlaylistEntryDetails,
+    QueuedTrack, StreamHealth, StreamQuality, TrackRating, Volume,
+};
+use std::{sync::Arc, time::Duration};
 
 pub use crate::frontend::message::PlaylistMode;
 
@@ -21,26 +24,91 @@ pub use crate::frontend::message::PlaylistMode;
 pub type PlaybackState = crate::state::State<PlaybackStateData>;
 #[cfg(feature = "broadcast")]
 pub type WaveformState = crate::state::State<WaveformStateData>;
+#[cfg(feature = "broadcast")]
+pub type DebugState = crate::state::State<DebugStateData>;
+#[cfg(feature = "broadcast")]
+pub type SettingsState = crate::state::State<crate::frontend::settings::Settings>;
+#[cfg(feature = "broadcast")]
+pub type FavoritesState = crate::state::State<Vec<crate::types::Favorite>>;
+#[cfg(feature = "broadcast")]
+pub type PlaylistState = crate::state::State<PlaylistStateData>;
+#[cfg(feature = "broadcast")]
+pub type UiPrefsState = crate::state::State<UiPrefs>;
 
 #[derive(Debug, PartialEq)]
 #[cfg_attr(feature = "serialize", derive(serde::Serialize))]
 #[cfg_attr(feature = "deserialize", derive(serde::Deserialize))]
 pub struct PlaybackStateData {
     pub current_track: Option<Track>,
+    /// Cover of the current track, from its tags or an image file next to it.
+    pub album_art: Option<AlbumArt>,
     pub playback_status: PlaybackStatus,
     pub playlist_mode: PlaylistMode,
+    /// Seed of the shuffled order while shuffling.
+    pub shuffle_seed: Option<u64>,
+    /// Set when the intro of the current track was automatically skipped, so that it can be undone.
+    pub intro_skipped: Option<Duration>,
+    /// Entries that were skipped the last time through the playlist because they couldn't be played.
+    pub unplayable: Vec<PlaybackError>,
+    /// Connection health when the current track is streamed from the network.
+    pub stream_health: Option<StreamHealth>,
+    /// Qualities to choose from when the current stream is offered in more than one.
+    pub stream_quality: Option<StreamQuality>,
+    /// Songs the current station has played, oldest first.
+    pub song_history: Vec<PlayedSong>,
+    /// Whether the current track is loved or banned.
+    pub rating: Option<TrackRating>,
+    /// Tracks queued to play after the current one, in the order they'll be played.
+    pub play_queue: Vec<QueuedTrack>,
+    /// True while party mode restricts the playlist to queueing tracks.
+    pub party_mode: bool,
+    /// Tracks that weren't queued last time because they're already in the playlist,
+    /// until they're queued anyway.
+    pub skipped_duplicates: Vec<QueuedTrack>,
+    /// True while all audio processing is bypassed for an A/B comparison.
+    pub dsp_bypassed: bool,
+    /// Region of the current track that is repeated.
+    pub ab_loop: AbLoop,
+    /// True while the visualizer is shown in its own window instead of the main one.
+    pub visualizer_detached: bool,
 }
 
 impl Default for PlaybackStateData {
     fn default() -> Self {
         Self {
             current_track: None,
+            album_art: None,
             playback_status: PlaybackStatus::default(),
             playlist_mode: PlaylistMode::Normal,
+            shuffle_seed: None,
+            intro_skipped: None,
+            unplayable: Vec::new(),
+            stream_health: None,
+            stream_quality: None,
+            song_history: Vec::new(),
+            rating: None,
+            play_queue: Vec::new(),
+            party_mode: false,
+            skipped_duplicates: Vec::new(),
+            dsp_bypassed: false,
+            ab_loop: AbLoop::default(),
+            visualizer_detached: false,
         }
     }
 }
 
+#[derive(Clone, Debug, Default, PartialEq)]
+#[cfg_attr(feature = "serialize", derive(serde::Serialize))]
+#[cfg_attr(feature = "deserialize", derive(serde::Deserialize))]
+pub struct AlbumArt {
+    /// Changes whenever the art does, so that the frontend knows when to load it again.
+    pub id: u64,
+    pub mime_type: String,
+    /// The image itself, which is served from `/ipc/album-art` rather than sent with the state.
+    #[cfg_attr(any(feature = "serialize", feature = "deserialize"), serde(skip))]
+    pub data: Arc<Vec<u8>>,
+}
+
 #[derive(Default, Debug, PartialEq)]
 #[cfg_attr(feature = "serialize", derive(serde::Serialize))]
 #[cfg_attr(feature = "deserialize", derive(serde::Deserialize))]
@@ -70,6 +138,23 @@ pub struct PlaybackStatus {
     /// End position in the audio track (length of the track). If `None`, then we are streaming audio.
     pub end_position: Option<Duration>,
     pub volume: Volume,
+    /// Set while playing a live stream, which can be paused and rewound within a limited
+    /// timeshift buffer.
+    pub live: Option<LiveStatus>,
+}
+
+/// Where a live stream is playing relative to what's being broadcast.
+///
+/// Positions are in the same terms as the current position, which counts the stream's
+/// playing time since it was opened.
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+#[cfg_attr(feature = "serialize", derive(serde::Serialize))]
+#[cfg_attr(feature = "deserialize", derive(serde::Deserialize))]
+pub struct LiveStatus {
+    /// Earliest position that can be rewound to.
+    pub earliest_position: Duration,
+    /// How far playback is behind the live broadcast. Zero when playing live.
+    pub behind_live: Duration,
 }
 
 #[derive(Debug, Default, PartialEq)]
@@ -77,8 +162,162 @@ pub struct WaveformStateData {
     pub waveform: Option<Waveform>,
 }
 
-#[derive(Debug, PartialEq)]
+/// HTTP header that gives the number of bins in a waveform IPC response.
+///
+/// The body holds that many spectrum values followed by that many amplitude values.
+pub const WAVEFORM_BIN_COUNT_HEADER: &str = "X-Waveform-Bin-Count";
+
+#[derive(Clone, Debug, PartialEq)]
 pub struct Waveform {
     pub spectrum: Box<[f32]>,
     pub amplitude: Box<[f32]>,
 }
+
+impl Waveform {
+    /// Number of bars in each of the spectrum and amplitude, which can change between updates.
+    pub fn bin_count(&self) -> usize {
+        self.spectrum.len()
+    }
+}
+
+/// Entries of the playlist in the order they're listed.
+#[derive(Clone, Debug, Default, PartialEq)]
+#[cfg_attr(feature = "serialize", derive(serde::Serialize))]
+#[cfg_attr(feature = "deserialize", derive(serde::Deserialize))]
+pub struct PlaylistStateData {
+    pub entries: Vec<PlaylistEntryDetails>,
+    /// Index of the entry that is playing, if any.
+    pub current_index: Option<usize>,
+    /// Length of the whole playlist, once every entry's duration has loaded.
+    pub total_duration: Option<Duration>,
+}
+
+impl PlaylistStateData {
+    /// Replaces entries with ones that have since loaded their tags or durations.
+    pub fn update_entries(&mut self, updated: &[PlaylistEntryDetails]) {
+        for updated in updated {
+            let entry = self.entries.iter_mut().find(|e| e.id == updated.id);
+            if let Some(entry) = entry {
+                *entry = updated.clone();
+            }
+        }
+        self.total_duration = total_duration(&self.entries);
+    }
+
+    /// Length of the entries that match, such as the selected ones, once all of their
+    /// durations have loaded.
+    pub fn duration_of(
+        &self,
+        mut include: impl FnMut(&PlaylistEntryDetails) -> bool,
+    ) -> Option<Duration> {
+        total_duration(self.entries.iter().filter(|entry| include(entry)))
+    }
+
+    /// Average dynamic range of the measured entries from an album, rounded to the nearest
+    /// whole number.
+    pub fn album_dynamic_range(&self, album: &str) -> Option<i32> {
+        average_dynamic_range(
+            self.entries
+                .iter()
+                .filter(|entry| entry.album.as_deref() == Some(album))
+                .filter_map(|entry| entry.dynamic_range),
+        )
+    }
+}
+
+fn average_dynamic_range(tracks: impl IntoIterator<Item = i32>) -> Option<i32> {
+    let (mut sum, mut count) = (0, 0);
+    for track in tracks {
+        sum += track;
+        count += 1;
+    }
+    if count == 0 {
+        None
+    } else {
+        Some((sum as f64 / count as f64).round() as i32)
+    }
+}
+
+/// Sums the durations of playlist entries, or `None` if any of them hasn't loaded yet.
+pub fn total_duration<'a>(
+    entries: impl IntoIterator<Item = &'a PlaylistEntryDetails>,
+) -> Option<Duration> {
+    entries.into_iter().map(|entry| entry.duration).sum()
+}
+
+/// Layout choices made in the UI that are restored the next time it opens.
+///
+/// These are kept apart from the settings since they change often as the UI is used,
+/// and don't belong in a config file that people edit by hand.
+#[derive(Clone, Debug, Default, PartialEq)]
+#[cfg_attr(feature = "serialize", derive(serde::Serialize))]
+#[cfg_attr(feature = "deserialize", derive(serde::Deserialize))]
+#[cfg_attr(
+    any(feature = "serialize", feature = "deserialize"),
+    serde(default, rename_all = "kebab-case")
+)]
+pub struct UiPrefs {
+    pub playlist_visible: bool,
+    /// Whether the visualizer was popped out into its own window.
+    pub visualizer_popped_out: bool,
+    pub time_display: TimeDisplay,
+}
+
+/// What the time at the end of the time slider shows.
+#[derive(Copy, Clone, Debug, Default, Eq, PartialEq)]
+#[cfg_attr(feature = "serialize", derive(serde::Serialize))]
+#[cfg_attr(feature = "deserialize", derive(serde::Deserialize))]
+#[cfg_attr(
+    any(feature = "serialize", feature = "deserialize"),
+    serde(rename_all = "kebab-case")
+)]
+pub enum TimeDisplay {
+    /// Length of the track.
+    #[default]
+    Length,
+    /// Time left until the end of the track.
+    Remaining,
+}
+
+/// Diagnostic information that isn't shown in the UI.
+#[derive(Debug, Default, PartialEq)]
+#[cfg_attr(feature = "serialize", derive(serde::Serialize))]
+#[cfg_attr(feature = "deserialize", derive(serde::Deserialize))]
+pub struct DebugStateData {
+    pub buffer_stats: BufferStats,
+    /// Uptime isn't kept up to date here, and is filled in when the health is requested.
+    pub health: InstanceHealth,
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn album_dynamic_range() {
+        let entry = |id, album: &str, dynamic_range| PlaylistEntryDetails {
+            id,
+            location: format!("/music/{id}.ogg"),
+            skipped: false,
+            title: None,
+            artist: None,
+            album: Some(album.into()),
+            duration: None,
+            dynamic_range,
+        };
+        let playlist = PlaylistStateData {
+            entries: vec![
+                entry(1, "Loud", Some(8)),
+                entry(2, "Loud", Some(9)),
+                entry(3, "Loud", Some(11)),
+                entry(4, "Loud", None),
+                entry(5, "Quiet", None),
+            ],
+            current_index: None,
+            total_duration: None,
+        };
+        assert_eq!(Some(9), playlist.album_dynamic_range("Loud"));
+        assert_eq!(None, playlist.album_dynamic_range("Quiet"));
+        assert_eq!(None, playlist.album_dynamic_range("Missing"));
+    }
+}
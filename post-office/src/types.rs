@@ -12,6 +12,11 @@
 // You should have received a copy of the GNU General Public License along with Millenium Player.
 // If not, see <https://www.gnu.org/licenses/>.
 
+use std::{
+    fmt,
+    time::{Duration, SystemTime},
+};
+
 const DEFAULT_VOLUME: f32 = 1.0;
 
 /// New-type for playback volume.
@@ -72,3 +77,326 @@ impl From<&Volume> for u8 {
         value.0
     }
 }
+
+/// Why a location couldn't be played.
+#[derive(Copy, Clone, Debug, Eq, PartialEq)]
+#[cfg_attr(feature = "serialize", derive(serde::Serialize))]
+#[cfg_attr(feature = "deserialize", derive(serde::Deserialize))]
+pub enum PlaybackErrorKind {
+    /// The location doesn't exist.
+    NotFound,
+    /// The location exists, but couldn't be read.
+    Unreadable,
+    /// The audio format or codec isn't supported.
+    UnsupportedFormat,
+    /// The audio data is corrupt and couldn't be decoded.
+    CorruptData,
+}
+
+/// Error that occurred while loading or decoding a location for playback.
+#[derive(Clone, Debug, Eq, PartialEq)]
+#[cfg_attr(feature = "serialize", derive(serde::Serialize))]
+#[cfg_attr(feature = "deserialize", derive(serde::Deserialize))]
+pub struct PlaybackError {
+    pub kind: PlaybackErrorKind,
+    /// The location that failed to play.
+    pub location: String,
+    /// Human readable description of the error.
+    pub message: String,
+    /// True if the error might be temporary, so trying again could succeed.
+    pub retryable: bool,
+}
+
+impl fmt::Display for PlaybackError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "failed to play \"{}\": {}", self.location, self.message)
+    }
+}
+
+impl std::error::Error for PlaybackError {}
+
+/// Sizes of the player's buffers, for diagnosing memory growth during long playback sessions.
+#[derive(Copy, Clone, Debug, Default, Eq, PartialEq)]
+#[cfg_attr(feature = "serialize", derive(serde::Serialize))]
+#[cfg_attr(feature = "deserialize", derive(serde::Deserialize))]
+pub struct BufferStats {
+    /// Decoded frames waiting in the sink to be resampled for the output device.
+    pub sink_input_frames: usize,
+    /// Samples waiting in the output buffer to be consumed by the output device.
+    pub output_samples: usize,
+    /// Samples held for calculating the waveform.
+    pub waveform_samples: usize,
+    /// Bytes of cover art held by the loaded tracks' metadata.
+    pub artwork_bytes: usize,
+}
+
+/// Which ReplayGain values are used to normalize the playback volume.
+#[derive(Copy, Clone, Debug, Default, Eq, PartialEq)]
+#[cfg_attr(feature = "serialize", derive(serde::Serialize))]
+#[cfg_attr(feature = "deserialize", derive(serde::Deserialize))]
+#[cfg_attr(
+    any(feature = "serialize", feature = "deserialize"),
+    serde(rename_all = "kebab-case")
+)]
+pub enum NormalizationMode {
+    /// Play tracks at their original volume.
+    #[default]
+    Off,
+    /// Make every track equally loud.
+    Track,
+    /// Make every album equally loud while keeping the volume differences between its tracks.
+    Album,
+    /// Turn the whole playlist up or down by the same amount so that its loudest peak
+    /// reaches the target level. Only needs a quick peak scan rather than loudness analysis.
+    PlaylistPeak,
+}
+
+/// Signals for checking speakers, channel routing, and equalization.
+#[derive(Copy, Clone, Debug, Eq, PartialEq)]
+#[cfg_attr(feature = "serialize", derive(serde::Serialize))]
+#[cfg_attr(feature = "deserialize", derive(serde::Deserialize))]
+#[cfg_attr(
+    any(feature = "serialize", feature = "deserialize"),
+    serde(rename_all = "kebab-case")
+)]
+pub enum TestSignal {
+    /// A sine wave that sweeps through the audible frequencies.
+    SineSweep,
+    /// Noise with equal energy per octave, which sounds even across the frequency range.
+    PinkNoise,
+    /// Beeps on one channel at a time: once on the left, then twice on the right.
+    ChannelCheck,
+}
+
+impl TestSignal {
+    pub fn name(&self) -> &'static str {
+        match self {
+            Self::SineSweep => "Sine sweep",
+            Self::PinkNoise => "Pink noise",
+            Self::ChannelCheck => "Channel check",
+        }
+    }
+}
+
+/// Connection health of a network stream, such as internet radio.
+#[derive(Copy, Clone, Debug, Default, Eq, PartialEq)]
+#[cfg_attr(feature = "serialize", derive(serde::Serialize))]
+#[cfg_attr(feature = "deserialize", derive(serde::Deserialize))]
+#[cfg_attr(
+    any(feature = "serialize", feature = "deserialize"),
+    serde(rename_all = "kebab-case")
+)]
+pub enum StreamHealth {
+    /// Audio is arriving normally.
+    #[default]
+    Connected,
+    /// Waiting for enough audio to arrive to play without interruption.
+    Buffering,
+    /// The connection dropped, and is being re-established.
+    Reconnecting { attempt: u32 },
+    /// Reconnecting gave up.
+    Failed,
+}
+
+/// Whether the audio output device is working.
+#[derive(Copy, Clone, Debug, Default, Eq, PartialEq)]
+#[cfg_attr(feature = "serialize", derive(serde::Serialize))]
+#[cfg_attr(feature = "deserialize", derive(serde::Deserialize))]
+#[cfg_attr(
+    any(feature = "serialize", feature = "deserialize"),
+    serde(rename_all = "kebab-case")
+)]
+pub enum DeviceStatus {
+    #[default]
+    Ok,
+    /// The output device couldn't be opened, so nothing can be heard.
+    Unavailable,
+    /// The output device reported an error while playing.
+    Failed,
+}
+
+/// Health of the running instance, for recovering when part of it stops working.
+#[derive(Clone, Debug, Eq, PartialEq)]
+#[cfg_attr(feature = "serialize", derive(serde::Serialize))]
+#[cfg_attr(feature = "deserialize", derive(serde::Deserialize))]
+pub struct InstanceHealth {
+    /// How long the backend has been running.
+    pub uptime: Duration,
+    /// False once the player thread has exited, until it's restarted.
+    pub player_alive: bool,
+    pub device: DeviceStatus,
+    /// Most recent error from the player or the output device.
+    pub last_error: Option<String>,
+}
+
+impl Default for InstanceHealth {
+    fn default() -> Self {
+        Self {
+            uptime: Duration::ZERO,
+            player_alive: true,
+            device: DeviceStatus::Ok,
+            last_error: None,
+        }
+    }
+}
+
+/// A quality that a stream is offered in, such as an HLS variant or one of a station's mounts.
+#[derive(Clone, Debug, Eq, PartialEq)]
+#[cfg_attr(feature = "serialize", derive(serde::Serialize))]
+#[cfg_attr(feature = "deserialize", derive(serde::Deserialize))]
+pub struct StreamVariant {
+    /// Human readable description, such as "128 kbps".
+    pub label: String,
+    /// Bitrate in bits per second, if the station advertises it.
+    pub bandwidth: Option<u32>,
+}
+
+/// The qualities the current stream is offered in, and which of them is playing.
+#[derive(Clone, Debug, Eq, PartialEq)]
+#[cfg_attr(feature = "serialize", derive(serde::Serialize))]
+#[cfg_attr(feature = "deserialize", derive(serde::Deserialize))]
+pub struct StreamQuality {
+    pub variants: Vec<StreamVariant>,
+    /// Variant picked by the user, or `None` to choose from the measured network throughput.
+    pub selected: Option<usize>,
+    /// Variant that is playing.
+    pub active: usize,
+}
+
+/// A song that a radio station announced while it was playing.
+#[derive(Clone, Debug, Eq, PartialEq)]
+#[cfg_attr(feature = "serialize", derive(serde::Serialize))]
+#[cfg_attr(feature = "deserialize", derive(serde::Deserialize))]
+pub struct PlayedSong {
+    /// Title as announced by the station, usually in the form "Artist - Title".
+    pub title: String,
+    /// When the station started playing the song.
+    pub played_at: SystemTime,
+}
+
+/// How the user feels about a track.
+#[derive(Copy, Clone, Debug, Eq, PartialEq)]
+#[cfg_attr(feature = "serialize", derive(serde::Serialize))]
+#[cfg_attr(feature = "deserialize", derive(serde::Deserialize))]
+#[cfg_attr(
+    any(feature = "serialize", feature = "deserialize"),
+    serde(rename_all = "kebab-case")
+)]
+pub enum TrackRating {
+    Loved,
+    /// Banned tracks are passed over when shuffling.
+    Banned,
+}
+
+/// A track waiting in the play queue to be played after the current one.
+#[derive(Clone, Debug, Eq, PartialEq)]
+#[cfg_attr(feature = "serialize", derive(serde::Serialize))]
+#[cfg_attr(feature = "deserialize", derive(serde::Deserialize))]
+pub struct QueuedTrack {
+    pub location: String,
+    pub title: Option<String>,
+    pub artist: Option<String>,
+}
+
+/// What the UI shows for a playlist entry.
+#[derive(Clone, Debug, Eq, PartialEq)]
+#[cfg_attr(feature = "serialize", derive(serde::Serialize))]
+#[cfg_attr(feature = "deserialize", derive(serde::Deserialize))]
+pub struct PlaylistEntryDetails {
+    pub id: usize,
+    pub location: String,
+    /// Skipped entries stay in the playlist, but are passed over during playback.
+    pub skipped: bool,
+    pub title: Option<String>,
+    pub artist: Option<String>,
+    pub album: Option<String>,
+    pub duration: Option<Duration>,
+    /// Dynamic range (DR) in decibels, once the entry has been measured.
+    pub dynamic_range: Option<i32>,
+}
+
+/// Region of the current track that repeats until it's cleared.
+#[derive(Copy, Clone, Debug, Default, Eq, PartialEq)]
+#[cfg_attr(feature = "serialize", derive(serde::Serialize))]
+#[cfg_attr(feature = "deserialize", derive(serde::Deserialize))]
+pub struct AbLoop {
+    pub a: Option<Duration>,
+    pub b: Option<Duration>,
+}
+
+impl AbLoop {
+    /// Start and end of the region to repeat, once both points are marked.
+    pub fn region(&self) -> Option<(Duration, Duration)> {
+        Some((self.a?, self.b?))
+    }
+
+    /// Marks point A, clearing point B if it no longer comes after A.
+    pub fn mark_a(&mut self, position: Duration) {
+        self.a = Some(position);
+        self.b = self.b.filter(|&b| b > position);
+    }
+
+    /// Marks point B. Point A is the start of the track if it wasn't marked, and
+    /// positions that don't come after point A are ignored.
+    pub fn mark_b(&mut self, position: Duration) {
+        let a = self.a.unwrap_or_default();
+        if position > a {
+            self.a = Some(a);
+            self.b = Some(position);
+        }
+    }
+}
+
+/// What a favorite refers to.
+#[derive(Copy, Clone, Debug, Eq, PartialEq)]
+#[cfg_attr(feature = "serialize", derive(serde::Serialize))]
+#[cfg_attr(feature = "deserialize", derive(serde::Deserialize))]
+#[cfg_attr(
+    any(feature = "serialize", feature = "deserialize"),
+    serde(rename_all = "kebab-case")
+)]
+pub enum FavoriteKind {
+    Station,
+    File,
+    Folder,
+    Playlist,
+}
+
+/// A station, file, folder, or playlist pinned for quick access.
+#[derive(Clone, Debug, Eq, PartialEq)]
+#[cfg_attr(feature = "serialize", derive(serde::Serialize))]
+#[cfg_attr(feature = "deserialize", derive(serde::Deserialize))]
+pub struct Favorite {
+    pub name: String,
+    pub location: String,
+    pub kind: FavoriteKind,
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn ab_loop() {
+        let secs = Duration::from_secs;
+        let mut ab_loop = AbLoop::default();
+        assert_eq!(None, ab_loop.region());
+
+        ab_loop.mark_b(secs(5));
+        assert_eq!(Some((secs(0), secs(5))), ab_loop.region());
+
+        ab_loop.mark_a(secs(2));
+        assert_eq!(Some((secs(2), secs(5))), ab_loop.region());
+        ab_loop.mark_b(secs(1));
+        assert_eq!(Some((secs(2), secs(5))), ab_loop.region());
+
+        ab_loop.mark_a(secs(6));
+        assert_eq!(
+            AbLoop {
+                a: Some(secs(6)),
+                b: None
+            },
+            ab_loop
+        );
+    }
+}